@@ -0,0 +1,109 @@
+// Machine-readable description of the emulator
+//
+// Printed as JSON by `--about-json`, so that frontends and launchers
+// can find out what the emulator supports without parsing the help
+// text. Fields may be added, but existing fields keep their meaning
+// as long as `format` is unchanged.
+
+use serde::Serialize;
+
+use crate::gameboy::cartridge::cartridge_type::CartridgeType;
+use crate::gameboy::quirks::QuirkRegistry;
+
+// Version of the format of the report
+const FORMAT: u32 = 1;
+
+// Cargo features that may be enabled
+const FEATURES: [(&str, bool); 5] = [
+    ("gui", cfg!(feature = "gui")),
+    ("audio", cfg!(feature = "audio")),
+    ("recording", cfg!(feature = "recording")),
+    ("scripting", cfg!(feature = "scripting")),
+    ("timing", cfg!(feature = "timing")),
+];
+
+#[derive(Serialize)]
+pub struct Mapper {
+    // Cartridge type code, at 0x147 in the header
+    pub code: u8,
+    pub name: String,
+}
+
+// Defaults of the options that trade accuracy for compatibility or
+// convenience
+#[derive(Serialize)]
+pub struct AccuracyDefaults {
+    pub strict: bool,
+    pub deterministic: bool,
+    pub bank_boundary_check: &'static str,
+
+    // Number of games with compatibility quirks in the built-in list
+    pub quirk_entries: usize,
+}
+
+#[derive(Serialize)]
+pub struct About {
+    pub format: u32,
+    pub name: &'static str,
+    pub version: &'static str,
+    pub features: Vec<&'static str>,
+    pub machines: Vec<&'static str>,
+    pub mappers: Vec<Mapper>,
+    pub defaults: AccuracyDefaults,
+}
+
+fn supported_mappers() -> Vec<Mapper> {
+    let mut header = vec![0; 0x150];
+    (0..=0xFF)
+        .filter_map(|code| {
+            header[0x147] = code;
+            CartridgeType::from_rom(&header)
+                .filter(|t| t.is_supported())
+                .map(|t| Mapper {
+                    code,
+                    name: t.to_string(),
+                })
+        })
+        .collect()
+}
+
+pub fn about() -> About {
+    About {
+        format: FORMAT,
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect(),
+        machines: vec!["dmg", "cgb"],
+        mappers: supported_mappers(),
+        defaults: AccuracyDefaults {
+            strict: false,
+            deterministic: false,
+            bank_boundary_check: "off",
+            quirk_entries: QuirkRegistry::new().len(),
+        },
+    }
+}
+
+pub fn about_json() -> String {
+    serde_json::to_string_pretty(&about()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_about_json() {
+        let about: serde_json::Value = serde_json::from_str(&about_json()).unwrap();
+        assert_eq!(about["format"], 1);
+        assert_eq!(about["machines"][0], "dmg");
+
+        let mappers = about["mappers"].as_array().unwrap();
+        assert!(mappers.iter().any(|m| m["code"] == 0x13));
+        assert!(mappers.iter().all(|m| m["code"] != 0xFC));
+    }
+}
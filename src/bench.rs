@@ -0,0 +1,111 @@
+// Headless benchmark
+//
+// Runs a number of frames as fast as possible, without UI, and reports
+// the speed. The frames are run the same way as in the UI, including
+// audio resampling, so that the result reflects the cost of all
+// subsystems. Emulation is deterministic in benchmark mode, so two
+// runs of the same ROM do the same work.
+//
+// With the "timing" feature, the time spent in each subsystem is
+// reported as well.
+
+use std::time::{Duration, Instant};
+
+use crate::core::Core;
+use crate::gameboy::emu::Emu;
+use crate::gameboy::timing::SUBSYSTEM_NAMES;
+use crate::gameboy::{CLOCK_SPEED, CYCLES_PER_FRAME};
+
+// Sample rate of the audio produced while benchmarking
+const BENCH_SAMPLE_RATE: f64 = 44100.0;
+
+pub struct BenchResult {
+    pub frames: usize,
+
+    // Host time spent, and the time the frames take on hardware
+    pub elapsed: Duration,
+    pub emulated: Duration,
+
+    // Host time per subsystem, in the order of SUBSYSTEM_NAMES. Only
+    // with the "timing" feature.
+    pub subsystems: Option<[Duration; 4]>,
+}
+
+impl BenchResult {
+    pub fn fps(&self) -> f64 {
+        self.frames as f64 / self.elapsed.as_secs_f64()
+    }
+
+    // How many times faster than real hardware
+    pub fn speed(&self) -> f64 {
+        self.emulated.as_secs_f64() / self.elapsed.as_secs_f64()
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{} frames in {:.3} s: {:.1} frames/s, {:.2}x real time",
+            self.frames,
+            self.elapsed.as_secs_f64(),
+            self.fps(),
+            self.speed()
+        );
+
+        if let Some(subsystems) = self.subsystems {
+            for (name, time) in SUBSYSTEM_NAMES.iter().zip(subsystems.iter()) {
+                let share = time.as_secs_f64() / self.elapsed.as_secs_f64() * 100.0;
+                println!("  {:<4} {:8.3} s  {:5.1}%", name, time.as_secs_f64(), share);
+            }
+        }
+    }
+}
+
+pub fn run_bench(emu: &mut Emu, frames: usize) -> BenchResult {
+    emu.set_audio_rates(CLOCK_SPEED as f64 / 4.0, BENCH_SAMPLE_RATE);
+    let mut audio = Vec::new();
+    let before = emu.mmu.timing.total();
+
+    let start = Instant::now();
+    for _ in 0..frames {
+        Emu::run_one_frame(emu);
+        emu.end_audio_frame();
+        audio.clear();
+        emu.read_audio_samples(&mut audio);
+    }
+    let elapsed = start.elapsed();
+
+    // Only the frames run by the benchmark are counted
+    let subsystems = match (before, emu.mmu.timing.total()) {
+        (Some(before), Some(after)) => {
+            let mut times = after;
+            for (t, b) in times.iter_mut().zip(before.iter()) {
+                *t = t.saturating_sub(*b);
+            }
+            Some(times)
+        }
+        _ => None,
+    };
+
+    BenchResult {
+        frames,
+        elapsed,
+        emulated: Duration::from_secs_f64((frames * CYCLES_PER_FRAME) as f64 / CLOCK_SPEED as f64),
+        subsystems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::Machine;
+
+    #[test]
+    fn test_bench() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.init();
+        let result = run_bench(&mut emu, 3);
+        assert_eq!(result.frames, 3);
+        assert_eq!(emu.mmu.timer.abs_cycle, 3 * CYCLES_PER_FRAME as u64);
+        assert_eq!(result.emulated.as_millis(), 50);
+        assert!(result.fps() > 0.0);
+    }
+}
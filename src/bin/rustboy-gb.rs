@@ -1,28 +1,83 @@
 extern crate ctrlc;
 extern crate num_traits;
 extern crate png;
+#[cfg(feature = "gui")]
 extern crate winit;
 
 use clap::Parser;
+#[cfg(feature = "gui")]
+use rustboy::config::{Config, DEFAULT_CONFIG_FILE};
+use rustboy::core::Core;
+use rustboy::debug::{
+    parse_address, BankBoundaryCheck, Breakpoint, Debug, ExecState, RegisterWatchpoint,
+};
+use rustboy::frame_hash::{compare, hash_frames, parse_expected, parse_frames};
+#[cfg(feature = "gui")]
+use rustboy::frame_sink::{BlendMode, VideoFormat};
+use rustboy::gameboy::cartridge::cartridge_header::{verify_rom, HeaderWarning};
 use rustboy::gameboy::emu::Emu;
 use rustboy::gameboy::emu::Machine;
+#[cfg(feature = "gui")]
+use rustboy::gameboy::printer::Printer;
 use rustboy::gameboy::{BOOTSTRAP_ROM, CARTRIDGE_ROM};
+use rustboy::memory_dump::{self, load_memory};
+#[cfg(feature = "gui")]
+use rustboy::palette::colorization;
+#[cfg(feature = "gui")]
+use rustboy::savestate::StateSlots;
+use rustboy::savestate::DEFAULT_STATE_DIR;
+#[cfg(feature = "gui")]
 use rustboy::ui::app::MoeApp;
+#[cfg(feature = "gui")]
 use rustboy::ui::gameboy::main_window::GameboyMainWindow;
 
-fn handle_machine_option(opt: Option<String>) -> Result<Machine, ()> {
+// Parse a --dump-memory or --load-memory option: a range and a file
+fn parse_memory_option(option: &str, emu: &Emu) -> Result<(usize, usize, String), String> {
+    let (range, filename) = memory_dump::parse_option(option)?;
+    let (start, end) = memory_dump::parse_range(range, emu)?;
+    Ok((start, end, filename.to_string()))
+}
+
+// Without a machine type, games run on a DMG, except those that only
+// run on a CGB. Games that merely support the CGB still run fine as
+// DMG games, which is better emulated.
+fn handle_machine_option(opt: Option<String>, rom: &str) -> Result<Machine, ()> {
     match opt.as_deref() {
-        None => Ok(Machine::GameBoyDMG),
+        None => match std::fs::read(rom) {
+            Ok(content) if content.get(0x143) == Some(&0xC0) => Ok(Machine::GameBoyCGB),
+            _ => Ok(Machine::GameBoyDMG),
+        },
         Some("dmg") => Ok(Machine::GameBoyDMG),
+        Some("mgb") => Ok(Machine::GameBoyMGB),
+        Some("sgb") => Ok(Machine::GameBoySGB),
         Some("cgb") => Ok(Machine::GameBoyCGB),
         Some(other) => {
             println!("Unsupported machine type: {}", other);
-            println!("Supported types: dmg, cgb");
+            println!("Supported types: dmg, mgb, sgb, cgb");
             Err(())
         }
     }
 }
 
+// Boot ROM of the machine in the configuration file, if any
+#[cfg(feature = "gui")]
+fn configured_boot_rom(args: &GuiArgs, machine: Machine) -> Option<String> {
+    let config_file = args
+        .config
+        .clone()
+        .unwrap_or(DEFAULT_CONFIG_FILE.to_string());
+    if !std::path::Path::new(&config_file).exists() {
+        return None;
+    }
+    let config = Config::load(&config_file).ok()?;
+    config.boot.path(machine).map(|path| path.to_string())
+}
+
+#[cfg(not(feature = "gui"))]
+fn configured_boot_rom(_args: &GuiArgs, _machine: Machine) -> Option<String> {
+    None
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct Args {
@@ -42,10 +97,169 @@ struct Args {
     #[clap(long, value_parser)]
     exit_at_cycle: Option<usize>,
 
-    /// Fast-forward boot sequence
+    /// Dump memory to a file on exit at --exit-at-cycle, such as VRAM=vram.bin or C000-C0FF=c000.bin (repeatable)
+    #[clap(long, value_parser)]
+    dump_memory: Vec<String>,
+
+    /// Load a file into memory before starting, such as WRAM=wram.bin or C100=c100.bin (repeatable)
+    #[clap(long, value_parser)]
+    load_memory: Vec<String>,
+
+    /// Print version, features and supported hardware as JSON, and exit
+    #[clap(long, action)]
+    about_json: bool,
+
+    /// Skip the boot sequence, starting the cartridge with the state the boot ROM leaves
     #[clap(long, action)]
     ff_bootstrap: bool,
 
+    /// Run in testing mode: capture, compare, mooneye, blargg, or sm83-json with the vectors in --test-dir
+    #[clap(short = 't', long = "test", value_parser)]
+    test_variant: Option<String>,
+
+    /// Expected serial output in test mode
+    #[clap(long, value_parser)]
+    test_expect: Option<String>,
+
+    /// Reference image for the compare test. A diff image is written to diff.png on mismatch.
+    #[clap(long, value_parser)]
+    test_reference: Option<String>,
+
+    /// Number of frames to run before comparing with --test-reference
+    #[clap(long, value_parser)]
+    test_frames: Option<usize>,
+
+    /// Run all test ROMs in this directory and its subdirectories, and exit
+    #[clap(long, value_parser)]
+    test_dir: Option<String>,
+
+    /// Write the result of --test-dir to this file, as markdown if it ends with .md and JSON otherwise
+    #[clap(long, value_parser)]
+    test_report: Option<String>,
+
+    /// Time limit per ROM for --test-dir, in emulated seconds
+    #[clap(long, value_parser)]
+    test_timeout: Option<usize>,
+
+    /// File to write debug log to
+    #[clap(long, value_parser)]
+    debug_log: Option<String>,
+
+    /// Include joypad state per frame and per P1 read in the debug log
+    #[clap(long, action)]
+    debug_log_input: bool,
+
+    /// Stop before the first instruction after the boot ROM, with the debugger open
+    #[clap(long, action)]
+    wait_debugger: bool,
+
+    /// Start paused before the first instruction of the cartridge, after
+    /// the boot ROM if one is run, with the debugger open
+    #[clap(long, action)]
+    paused: bool,
+
+    /// Break at this address, such as 4123 or 03:4123 in bank 3, or label, with the debugger open (repeatable)
+    #[clap(long, value_parser)]
+    break_at: Vec<String>,
+
+    /// Break when a register condition becomes true, such as "SP<C000" (repeatable)
+    #[clap(long, value_parser)]
+    watch_reg: Vec<String>,
+
+    /// Symbol file (.sym or .map) or source listing (.lst) to load, in addition to the ones next to the ROM (repeatable)
+    #[clap(long, value_parser)]
+    symbols: Vec<String>,
+
+    /// Warn or break when execution falls through a ROM bank boundary (off, warn or break)
+    #[clap(long, value_parser, default_value = "off")]
+    bank_boundary: String,
+
+    // Machine type
+    #[clap(short, long, value_parser)]
+    machine: Option<String>,
+
+    /// Refuse to run ROMs with a bad logo, header checksum or size
+    #[clap(long, action)]
+    reject_bad_rom: bool,
+
+    /// Panic on access to unhandled registers (for development)
+    #[clap(long, action)]
+    strict: bool,
+
+    /// Print a CRC-32 of the framebuffer at these frames (comma separated), and exit
+    #[clap(long, value_parser)]
+    hash_frames: Option<String>,
+
+    /// Compare the frame hashes with this file, with a frame and a hash per line. Exits with status 1 on mismatch.
+    #[clap(long, value_parser)]
+    hash_expect: Option<String>,
+
+    /// Run N frames headless as fast as possible, print the speed, and exit
+    #[clap(long, value_parser)]
+    bench: Option<usize>,
+
+    /// Run as a frame server, controlled over stdin/stdout
+    #[clap(long, action)]
+    server: bool,
+
+    /// Wait for a GDB connection on this port and let it control the emulator
+    #[clap(long, value_parser)]
+    gdb: Option<u16>,
+
+    /// File with additional per-game compatibility quirks
+    #[clap(long, value_parser)]
+    quirks: Option<String>,
+
+    /// Capture screenshots of all ROMs in this directory and exit
+    #[clap(long, value_parser)]
+    thumbnails: Option<String>,
+
+    /// Directory to write screenshots to in thumbnail mode
+    #[clap(long, value_parser, default_value = "thumbnails")]
+    thumbnail_dir: String,
+
+    /// Frames to run each ROM before capturing in thumbnail mode
+    #[clap(long, value_parser)]
+    thumbnail_frames: Option<usize>,
+
+    /// Make emulation independent of wall clock and random state
+    #[clap(long, action)]
+    deterministic: bool,
+
+    /// Script to load at start
+    #[clap(long, value_parser)]
+    script: Option<String>,
+
+    /// Record input to this movie file, starting from power on
+    #[clap(long, value_parser)]
+    record_movie: Option<String>,
+
+    /// Play input from this movie file, starting from power on
+    #[clap(long, value_parser, conflicts_with = "record-movie")]
+    play_movie: Option<String>,
+
+    /// Load cartridge RAM from this .sav file, with or without the
+    /// RTC state appended by VBA and SameBoy
+    #[clap(long, value_parser)]
+    import_save: Option<String>,
+
+    /// Write cartridge RAM to this .sav file when the emulator exits,
+    /// with the RTC state appended for MBC3 cartridges with a clock
+    #[clap(long, value_parser)]
+    export_save: Option<String>,
+
+    /// Log which ROM bytes are executed and read as data, and write the log to this CDL file when the emulator exits
+    #[clap(long, value_parser)]
+    cdl: Option<String>,
+
+    #[clap(flatten)]
+    gui: GuiArgs,
+}
+
+// Options of the graphical user interface
+#[derive(Parser, Debug)]
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+struct GuiArgs {
     /// Record into this directory
     #[clap(short = 'R', long = "record", value_parser)]
     record_dir: Option<String>,
@@ -58,53 +272,453 @@ struct Args {
     #[clap(short = 'C', long, value_parser)]
     capture: Option<usize>,
 
-    /// Run in testing mode
-    #[clap(short = 't', long = "test", value_parser)]
-    test_variant: Option<String>,
-
-    /// Expected serial output in test mode
+    /// Blend screenshots with the previous frame, for flickering sprites: average or or
     #[clap(long, value_parser)]
-    test_expect: Option<String>,
+    screenshot_blend: Option<String>,
 
-    /// File to write debug log to
+    /// Configuration file
     #[clap(long, value_parser)]
-    debug_log: Option<String>,
+    config: Option<String>,
 
-    // Machine type
-    #[clap(short, long, value_parser)]
-    machine: Option<String>,
+    /// Video recording format: gif or apng
+    #[clap(long, value_parser, default_value = "gif")]
+    video_format: String,
+
+    /// Frames to skip between each recorded video frame
+    #[clap(long, value_parser, default_value_t = 1)]
+    video_skip: usize,
+
+    /// Scale factor of recorded video
+    #[clap(long, value_parser, default_value_t = 2)]
+    video_scale: usize,
+
+    /// Connect a Game Boy Printer to the serial port. Printouts are saved as PNG files.
+    #[clap(long, action)]
+    printer: bool,
+
+    /// Directory of savestates. Each game gets a directory of its own within it.
+    #[clap(long, value_parser, default_value = DEFAULT_STATE_DIR)]
+    state_dir: String,
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(args: GuiArgs, rom: &str, mut emu: Emu, debug: Debug) -> Result<(), ()> {
+    let mut main_window = GameboyMainWindow::new();
+    if debug.break_at_entry
+        || debug.break_on_frame.is_some()
+        || debug.state == ExecState::STEP
+        || !debug.breakpoints.is_empty()
+    {
+        main_window.open_debugger();
+    }
+
+    if args.printer {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        emu.mmu.serial.device = Some(Box::new(Printer::new(sender)));
+        main_window.printer_window.attach(receiver);
+    }
+    // A CGB colorizes DMG games with a palette picked by its boot ROM
+    let header = emu.mmu.cartridge.header();
+    let colorization = match emu.machine {
+        Machine::GameBoyCGB if header.dmg_only() => Some(colorization(header)),
+        _ => None,
+    };
+
+    let mut app = MoeApp::new(emu, main_window);
+    app.set_colorization(colorization);
+    app.set_state_slots(StateSlots::for_rom(&args.state_dir, rom));
+
+    // A missing configuration file is only an error if it was
+    // explicitly given on the command line
+    let config_file = args
+        .config
+        .clone()
+        .unwrap_or(DEFAULT_CONFIG_FILE.to_string());
+    if args.config.is_some() || std::path::Path::new(&config_file).exists() {
+        match Config::load(&config_file) {
+            Ok(config) => app.set_config(config, &config_file),
+            Err(e) => {
+                println!("Failed to load configuration from {}: {}", config_file, e);
+                return Err(());
+            }
+        }
+    }
+
+    match VideoFormat::from_name(&args.video_format) {
+        Some(format) => app.set_video_options(format, args.video_skip, args.video_scale),
+        None => {
+            println!("Unsupported video format: {}", args.video_format);
+            println!("Supported formats: gif, apng");
+            return Err(());
+        }
+    }
+
+    if let Some(dir) = args.record_dir {
+        let skip = args.skip.unwrap_or(0);
+        if let Err(e) = app.record_frames(&dir, skip) {
+            println!("Failed to create record directory {}: {}", dir, e);
+            return Err(());
+        }
+        println!("Recording every {} frame(s) to {}", skip + 1, dir);
+    }
+
+    if let Some(name) = args.screenshot_blend {
+        match BlendMode::from_name(&name) {
+            Some(mode) => app.set_screenshot_blend(Some(mode)),
+            None => {
+                println!("Unsupported screenshot blend mode: {}", name);
+                println!("Supported modes: average, or");
+                return Err(());
+            }
+        }
+    }
+
+    if let Some(frame) = args.capture {
+        app.capture_at_frame(frame);
+    }
+    app.run_with_wgpu(debug);
+
+    println!("Clean shutdown. Bye!");
+    Ok(())
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_args: GuiArgs, _rom: &str, _emu: Emu, _debug: Debug) -> Result<(), ()> {
+    println!("This build has no user interface (built without the \"gui\" feature)");
+    println!("Use one of the headless modes: --test, --test-expect, --server, --gdb, --thumbnails, --bench or --hash-frames");
+    Err(())
+}
+
+// Print the frame hashes, or compare them with the expected hashes
+fn run_hash_frames(
+    emu: &mut Emu,
+    frames: Option<String>,
+    expect: Option<String>,
+) -> Result<(), ()> {
+    let expected = match expect {
+        Some(filename) => {
+            let content = std::fs::read_to_string(&filename).map_err(|e| {
+                println!("Failed to read {}: {}", filename, e);
+            })?;
+            parse_expected(&content).map_err(|e| {
+                println!("Invalid frame hashes in {}: {}", filename, e);
+            })?
+        }
+        None => Vec::new(),
+    };
+
+    // Without a list of frames, the frames of the expected hashes are used
+    let frames = match frames {
+        Some(list) => parse_frames(&list).map_err(|e| println!("{}", e))?,
+        None => expected.iter().map(|(frame, _)| *frame).collect(),
+    };
+
+    let hashes = hash_frames(emu, &frames);
+    for (frame, hash) in hashes.iter() {
+        println!("{} {:08x}", frame, hash);
+    }
+
+    let mismatches = compare(&hashes, &expected);
+    for mismatch in mismatches.iter() {
+        println!("Mismatch at {}", mismatch);
+    }
+    if !mismatches.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), ()> {
     let args = Args::parse();
 
-    let bootstrap_rom = args.boot_rom.unwrap_or(BOOTSTRAP_ROM.to_string());
+    if args.about_json {
+        println!("{}", rustboy::about::about_json());
+        return Ok(());
+    }
+
     let cartridge_rom = args.cartridge_rom.unwrap_or(CARTRIDGE_ROM.to_string());
-    let machine = handle_machine_option(args.machine)?;
+    let machine = handle_machine_option(args.machine, &cartridge_rom)?;
+    let boot_rom = match args.boot_rom {
+        Some(path) => Some(path),
+        None => configured_boot_rom(&args.gui, machine),
+    };
+
+    // The DMG boot ROM would leave a CGB in DMG mode, while the PPU
+    // renders in CGB mode, so the boot is skipped without a CGB boot ROM
+    let skip_boot = match boot_rom {
+        None if matches!(machine, Machine::GameBoyCGB) => {
+            println!("No CGB boot ROM given with --boot or in the configuration");
+            true
+        }
+        _ => args.ff_bootstrap,
+    };
+    let bootstrap_rom = boot_rom.unwrap_or(BOOTSTRAP_ROM.to_string());
+
+    rustboy::set_strict_mode(args.strict);
+
+    // The frame server promises deterministic results, and benchmarks
+    // should be reproducible
+    rustboy::set_deterministic(
+        args.deterministic
+            || args.server
+            || args.bench.is_some()
+            || args.hash_frames.is_some()
+            || args.hash_expect.is_some(),
+    );
+
+    if let Some(rom_dir) = args.thumbnails {
+        let frames = args
+            .thumbnail_frames
+            .unwrap_or(rustboy::thumbnails::DEFAULT_THUMBNAIL_FRAMES);
+        return match rustboy::thumbnails::capture_thumbnails(
+            &rom_dir,
+            &args.thumbnail_dir,
+            machine,
+            frames,
+        ) {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(()),
+            Err(e) => {
+                println!("Failed to capture thumbnails: {}", e);
+                Err(())
+            }
+        };
+    }
+
+    if args.test_variant.as_deref() == Some("sm83-json") {
+        let dir = match args.test_dir {
+            Some(dir) => dir,
+            None => {
+                println!(
+                    "--test sm83-json requires the directory of the test vectors in --test-dir"
+                );
+                return Err(());
+            }
+        };
+        return match rustboy::sm83_json::run_sm83_tests(&dir) {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(()),
+            Err(e) => {
+                println!("Failed to run tests in {}: {}", dir, e);
+                Err(())
+            }
+        };
+    }
+
+    if let Some(rom_dir) = args.test_dir {
+        let timeout = args
+            .test_timeout
+            .unwrap_or(rustboy::test_runner::DEFAULT_TEST_TIMEOUT);
+        let report =
+            match rustboy::test_runner::run_test_dir(&rom_dir, &bootstrap_rom, machine, timeout) {
+                Ok(report) => report,
+                Err(e) => {
+                    println!("Failed to run tests in {}: {}", rom_dir, e);
+                    return Err(());
+                }
+            };
+        if let Some(filename) = args.test_report {
+            if let Err(e) = report.save(&filename) {
+                println!("Failed to write test report to {}: {}", filename, e);
+                return Err(());
+            }
+            println!("Wrote test report to {}", filename);
+        }
+        return match report.failed + report.timeout {
+            0 => Ok(()),
+            _ => Err(()),
+        };
+    }
 
     let mut emu = Emu::new(machine);
     emu.init();
 
-    println!("Loading bootstrap ROM: {}", bootstrap_rom);
-    let sz = emu.load_bootstrap(&bootstrap_rom.to_string());
-    println!(" - {} bytes read", sz);
+    if let Some(filename) = args.quirks {
+        if let Err(e) = emu.quirk_registry.load_overrides(&filename) {
+            println!("Failed to load quirks from {}: {}", filename, e);
+            return Err(());
+        }
+    }
+
+    // Without a boot ROM, resets also skip it
+    if !skip_boot {
+        println!("Loading bootstrap ROM: {}", bootstrap_rom);
+        let sz = emu.load_bootstrap(&bootstrap_rom.to_string());
+        println!(" - {} bytes read", sz);
+    }
 
     println!("Loading cartridge ROM: {}", cartridge_rom.to_string());
-    emu.load_cartridge(&cartridge_rom.to_string());
+    let content = match std::fs::read(&cartridge_rom) {
+        Ok(content) => content,
+        Err(e) => {
+            println!("Failed to read {}: {}", cartridge_rom, e);
+            return Err(());
+        }
+    };
 
-    let mut debug = rustboy::debug::Debug::new();
+    // Checked before the cartridge is built from what may not even be
+    // a complete header
+    if args.reject_bad_rom {
+        let fatal: Vec<HeaderWarning> = verify_rom(&content)
+            .into_iter()
+            .filter(|w| w.is_fatal())
+            .collect();
+        if !fatal.is_empty() {
+            for warning in fatal {
+                println!("Error: {}", warning);
+            }
+            println!("Refusing to run what looks like a corrupt ROM");
+            return Err(());
+        }
+    }
+    if let Err(e) = emu.load_cartridge_rom(&content) {
+        println!("Failed to load cartridge: {}", e);
+        return Err(());
+    }
+
+    if let Some(filename) = args.import_save {
+        if let Err(e) = emu.import_save(&filename) {
+            println!("Failed to import save from {}: {}", filename, e);
+            return Err(());
+        }
+        println!("Imported save from {}", filename);
+    }
+    emu.export_save_on_exit = args.export_save;
+
+    if args.cdl.is_some() {
+        emu.start_cdl();
+        emu.cdl_on_exit = args.cdl;
+    }
+
+    let mut debug = Debug::new();
 
     match args.debug_log {
         Some(filename) => debug.start_debug_log(&filename),
         None => {}
     };
+    debug.log_input = args.debug_log_input;
+    debug.break_at_entry = args.wait_debugger;
+    debug.break_on_frame = args.break_frame;
+    debug.exit_at_cycle = args.exit_at_cycle.map(|cycle| cycle as u64);
+
+    for filename in debug.symbols.load_for_rom(&cartridge_rom) {
+        println!("Loaded symbols from {}", filename);
+    }
+    for filename in args.symbols {
+        if let Err(e) = debug.symbols.load(&filename) {
+            println!("Failed to load symbols from {}: {}", filename, e);
+            return Err(());
+        }
+        println!("Loaded symbols from {}", filename);
+    }
+
+    // Labels can only be used once the symbols are loaded
+    for s in args.break_at {
+        match parse_address(&s, &debug.symbols) {
+            Some((bank, adr)) => {
+                let bp = Breakpoint {
+                    enabled: true,
+                    bank,
+                };
+                debug.add_breakpoint(adr, bp);
+            }
+            None => {
+                println!(
+                    "Invalid breakpoint, expected an address such as 03:4123, or a label: {}",
+                    s
+                );
+                return Err(());
+            }
+        }
+    }
+    // With a boot ROM, pause where the boot ROM hands over to the
+    // cartridge, like --wait-debugger, rather than at 0x0000 of the
+    // boot ROM
+    if args.paused {
+        match emu.in_boot_rom() {
+            true => debug.break_at_entry = true,
+            false => debug.state = ExecState::STEP,
+        }
+    }
+
+    for condition in args.watch_reg {
+        match RegisterWatchpoint::parse(&condition) {
+            Ok(wp) if emu.register(&wp.register).is_some() => debug.register_watchpoints.push(wp),
+            Ok(wp) => {
+                println!("Unsupported register: {}", wp.register);
+                println!("Supported registers: A, F, B, C, D, E, H, L, AF, BC, DE, HL, SP, PC");
+                return Err(());
+            }
+            Err(e) => {
+                println!("Invalid register watchpoint: {}", e);
+                return Err(());
+            }
+        }
+    }
+
+    match BankBoundaryCheck::from_name(&args.bank_boundary) {
+        Some(check) => debug.bank_boundary_check = check,
+        None => {
+            println!("Unsupported bank boundary check: {}", args.bank_boundary);
+            println!("Supported checks: off, warn, break");
+            return Err(());
+        }
+    }
 
-    if args.ff_bootstrap {
-        println!("Fast forward bootstrap ...");
-        while emu.mmu.bootstrap_mode {
-            emu.mmu.exec_op();
+    if skip_boot {
+        println!("Skipping bootstrap ROM");
+        emu.skip_bootrom();
+    }
+
+    for option in args.dump_memory {
+        match parse_memory_option(&option, &emu) {
+            Ok((start, end, filename)) => debug.memory_dumps.push((start, end, filename)),
+            Err(e) => {
+                println!("Invalid memory dump: {}", e);
+                return Err(());
+            }
         }
-        println!("Bootstrap mode disabled");
+    }
+
+    for option in args.load_memory {
+        let (start, filename) = match parse_memory_option(&option, &emu) {
+            Ok((start, _, filename)) => (start, filename),
+            Err(e) => {
+                println!("Invalid memory load: {}", e);
+                return Err(());
+            }
+        };
+        match load_memory(&mut emu, start, &filename) {
+            Ok(count) => println!("Loaded {} bytes from {} to {:04X}", count, filename, start),
+            Err(e) => {
+                println!("Failed to load memory from {}: {}", filename, e);
+                return Err(());
+            }
+        }
+    }
+
+    if args.hash_frames.is_some() || args.hash_expect.is_some() {
+        return run_hash_frames(&mut emu, args.hash_frames, args.hash_expect);
+    }
+
+    if let Some(frames) = args.bench {
+        rustboy::bench::run_bench(&mut emu, frames).print();
+        return Ok(());
+    }
+
+    if args.server {
+        let stdin = std::io::stdin();
+        return rustboy::server::run_server(&mut emu, stdin.lock(), std::io::stdout()).map_err(
+            |e| {
+                println!("Server error: {}", e);
+            },
+        );
+    }
+
+    if let Some(port) = args.gdb {
+        return rustboy::gdb::run_gdb_stub(&mut emu, port).map_err(|e| {
+            println!("GDB stub error: {}", e);
+        });
     }
 
     if let Some(expect) = args.test_expect {
@@ -112,15 +726,48 @@ fn main() -> Result<(), ()> {
         rustboy::test_runner::test_runner_expect(&expect, &mut emu);
     }
 
+    if args.test_variant.as_deref() == Some("compare") {
+        let reference = match args.test_reference {
+            Some(reference) => reference,
+            None => {
+                println!("The compare test needs a reference image (--test-reference)");
+                return Err(());
+            }
+        };
+        let frames = args
+            .test_frames
+            .unwrap_or(rustboy::test_runner::DEFAULT_CAPTURE_FRAMES);
+        // This never returns
+        rustboy::test_runner::test_runner_compare(&reference, frames, &mut emu, &mut debug);
+    }
+
     if let Some(variant) = args.test_variant {
         // This never returns
         rustboy::test_runner::test_runner(&variant, &mut emu, &mut debug);
     }
 
-    let main_window = GameboyMainWindow::new();
-    let app = MoeApp::new(emu, main_window);
-    app.run_with_wgpu(debug);
+    if let Some(filename) = args.record_movie {
+        if let Err(e) = emu.record_movie(&filename) {
+            println!("Failed to record movie: {}", e);
+            return Err(());
+        }
+        println!("Recording movie to {}", filename);
+    }
 
-    println!("Clean shutdown. Bye!");
-    return Ok(());
+    if let Some(filename) = args.play_movie {
+        if let Err(e) = emu.play_movie(&filename) {
+            println!("Failed to play movie {}: {}", filename, e);
+            return Err(());
+        }
+        println!("Playing movie {}", filename);
+    }
+
+    if let Some(filename) = args.script {
+        if let Err(e) = emu.load_script(&filename) {
+            println!("Failed to load script {}: {}", filename, e);
+            return Err(());
+        }
+    }
+
+    run_gui(args.gui, &cartridge_rom, emu, debug)
 }
@@ -0,0 +1,258 @@
+// User configuration
+//
+// Read from a TOML file at startup. All settings are optional, and
+// missing settings get their default value. Settings changed in the
+// UI can be saved back to the same file.
+//
+// Example:
+//
+// [display]
+// scale-mode = "integer"
+// filter = "lcd-grid"
+// fullscreen = false
+// input-display = true
+//
+// [display.palette]
+// bg = [[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]]
+// obj0 = [[255, 255, 255], [255, 132, 132], [148, 58, 58], [0, 0, 0]]
+// obj1 = [[255, 255, 255], [255, 132, 132], [148, 58, 58], [0, 0, 0]]
+//
+// [ui]
+// theme = "light"
+// font-size = 16.0
+//
+// [audio]
+// backend = "cpal"
+// device = "Built-in Audio"
+// buffer-size = 1024
+//
+// [boot]
+// dmg = "roms/dmg_boot.bin"
+// cgb = "roms/cgb_boot.bin"
+//
+// [layout]
+// width = 2800
+// height = 1800
+// open = ["debugger", "memory"]
+//
+// [layout.windows]
+// Debugger = { pos = [20.0, 60.0], size = [400.0, 600.0] }
+//
+// The layout is saved when the emulator exits.
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboy::emu::Machine;
+use crate::palette::{DmgPalette, CLASSIC_GREEN};
+use crate::ui::screen::{ScaleMode, ScreenFilter};
+use crate::ui::theme::{Theme, DEFAULT_FONT_SIZE};
+use crate::ui::window_layout::WindowLayouts;
+
+// Configuration file used if none is given on the command line
+pub const DEFAULT_CONFIG_FILE: &str = "rustboy.toml";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DisplayConfig {
+    pub scale_mode: ScaleMode,
+    pub filter: ScreenFilter,
+    pub fullscreen: bool,
+
+    // Show the pressed buttons over the screen
+    pub input_display: bool,
+
+    // Show DMG games with the colors picked by the CGB boot ROM,
+    // when running as a CGB
+    pub colorize: bool,
+
+    // Must be last, as it's written as a separate table
+    pub palette: DmgPalette,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            scale_mode: ScaleMode::Integer,
+            filter: ScreenFilter::None,
+            fullscreen: false,
+            input_display: false,
+            colorize: true,
+            palette: CLASSIC_GREEN,
+        }
+    }
+}
+
+// Settings of the debug and tooling UI
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct UiConfig {
+    pub theme: Theme,
+    pub font_size: f32,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            theme: Theme::Dark,
+            font_size: DEFAULT_FONT_SIZE,
+        }
+    }
+}
+
+// Audio output. The default device and buffer size of the audio
+// backend are used when not set.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AudioConfig {
+    // "cpal" or "sdl2". The other backends are tried if it fails to
+    // start.
+    pub backend: Option<String>,
+
+    pub device: Option<String>,
+
+    // In frames, one sample per channel
+    pub buffer_size: Option<u32>,
+}
+
+// Boot ROM of each machine type. The --boot option takes precedence.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BootConfig {
+    pub dmg: Option<String>,
+    pub mgb: Option<String>,
+    pub sgb: Option<String>,
+    pub cgb: Option<String>,
+}
+
+impl BootConfig {
+    pub fn path(&self, machine: Machine) -> Option<&str> {
+        match machine {
+            Machine::GameBoyDMG => self.dmg.as_deref(),
+            Machine::GameBoyMGB => self.mgb.as_deref(),
+            Machine::GameBoySGB => self.sgb.as_deref(),
+            Machine::GameBoyCGB => self.cgb.as_deref(),
+        }
+    }
+}
+
+// Size of the OS window, in physical pixels, and the open windows
+// with their positions and sizes
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LayoutConfig {
+    pub width: u32,
+    pub height: u32,
+
+    // Names of the open windows, as listed by the main window
+    pub open: Vec<String>,
+
+    // Must be last, as it's written as a separate table
+    pub windows: WindowLayouts,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            width: 2800,
+            height: 1800,
+            open: Vec::new(),
+            windows: WindowLayouts::new(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub display: DisplayConfig,
+    pub ui: UiConfig,
+    pub audio: AudioConfig,
+    pub boot: BootConfig,
+    pub layout: LayoutConfig,
+}
+
+impl Config {
+    pub fn load(filename: &str) -> Result<Config, String> {
+        let content = std::fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        toml::from_str(&content).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), String> {
+        let content = toml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(filename, content).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::window_layout::WindowRect;
+
+    #[test]
+    fn test_partial_config() {
+        let config: Config = toml::from_str("[display]\nfilter = \"crt\"\n").unwrap();
+        assert_eq!(config.display.filter, ScreenFilter::Crt);
+        assert_eq!(config.display.scale_mode, ScaleMode::Integer);
+        assert_eq!(config.display.palette, CLASSIC_GREEN);
+        assert_eq!(config.ui.theme, Theme::Dark);
+        assert!(config.audio == AudioConfig::default());
+        assert!(config.boot == BootConfig::default());
+    }
+
+    #[test]
+    fn test_boot_paths() {
+        let config: Config = toml::from_str("[boot]\ncgb = \"cgb_boot.bin\"\n").unwrap();
+        assert_eq!(config.boot.path(Machine::GameBoyCGB), Some("cgb_boot.bin"));
+        assert_eq!(config.boot.path(Machine::GameBoyDMG), None);
+    }
+
+    #[test]
+    fn test_audio_round_trip() {
+        let mut config = Config::default();
+        let content = toml::to_string(&config).unwrap();
+        assert!(toml::from_str::<Config>(&content).unwrap().audio == AudioConfig::default());
+
+        config.audio.device = Some("Speakers".to_string());
+        config.audio.buffer_size = Some(512);
+        config.audio.backend = Some("sdl2".to_string());
+        let content = toml::to_string(&config).unwrap();
+        let loaded: Config = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.audio.device.as_deref(), Some("Speakers"));
+        assert_eq!(loaded.audio.buffer_size, Some(512));
+        assert_eq!(loaded.audio.backend.as_deref(), Some("sdl2"));
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let mut config = Config::default();
+        config.layout.open = vec!["debugger".to_string()];
+        config.layout.windows.insert(
+            "Memory".to_string(),
+            WindowRect {
+                pos: [10.0, 20.0],
+                size: Some([300.0, 200.0]),
+            },
+        );
+        config.layout.windows.insert(
+            "OAM".to_string(),
+            WindowRect {
+                pos: [5.0, 5.0],
+                size: None,
+            },
+        );
+        let content = toml::to_string(&config).unwrap();
+        let loaded: Config = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.layout.open, ["debugger"]);
+        assert_eq!(loaded.layout.windows, config.layout.windows);
+        assert_eq!(loaded.layout.width, 2800);
+    }
+
+    #[test]
+    fn test_palette_round_trip() {
+        let mut config = Config::default();
+        config.display.palette = crate::palette::CGB_RED;
+        let content = toml::to_string(&config).unwrap();
+        let loaded: Config = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.display.palette, crate::palette::CGB_RED);
+    }
+}
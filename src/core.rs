@@ -1,8 +1,17 @@
 use std::fs::File;
 
-use egui::InputState;
 use ringbuf::Producer;
 
+use crate::palette::DmgPalette;
+
+/// Text drawn over the screen. The position is in screen pixels.
+#[derive(Clone)]
+pub struct OverlayText {
+    pub x: f32,
+    pub y: f32,
+    pub text: String,
+}
+
 pub trait Core: Sized {
     fn screen_width(&self) -> usize;
     fn screen_height(&self) -> usize;
@@ -14,6 +23,17 @@ pub trait Core: Sized {
     /// has been updated and need a refresh.
     fn current_frame(&self) -> usize;
 
+    /// Current state as a single line of text, as written to the
+    /// debug log. None if the state should not be logged.
+    fn trace_line(&self) -> Option<String>;
+
+    /// Currently pressed buttons, as text for the debug log.
+    fn input_text(&self) -> String;
+
+    /// Number of times the CPU has read the joypad, and the last
+    /// value read.
+    fn joypad_reads(&self) -> (u64, u8);
+
     /// Log current state to file.
     /// This function is used by Debug to log state after each operation.
     fn log_state(&self, f: &mut File);
@@ -21,9 +41,29 @@ pub trait Core: Sized {
     /// Returns address of next operation to be executed (program counter).
     fn pc(&self) -> usize;
 
+    /// Value of a CPU register by name, such as "A" or "HL". None if
+    /// there's no such register.
+    fn register(&self, name: &str) -> Option<u16>;
+
+    /// Read memory without side effects.
+    fn peek(&self, addr: usize) -> u8;
+
+    /// Memory bank mapped at an address, numbered as in symbol files.
+    fn bank(&self, addr: usize) -> usize;
+
+    /// True while the boot ROM is executing.
+    fn in_boot_rom(&self) -> bool;
+
     /// Return current scanline
     fn scanline(&self) -> usize;
 
+    /// Number of pixels of the framebuffer drawn so far in the
+    /// current frame, in raster order.
+    fn beam_position(&self) -> usize;
+
+    /// Number of cycles executed since reset.
+    fn cycle(&self) -> u64;
+
     /// Some architectures have semi-standardized operations that trigger
     /// breakpoints. For example, 0x40 ("LD B,B") on Gameboy.
     fn at_source_code_breakpoint(&self) -> bool;
@@ -31,12 +71,96 @@ pub trait Core: Sized {
     // Execute next operation
     fn exec_op(&mut self);
 
-    fn update_input_state(&mut self, state: &InputState);
+    /// Run for exactly one frame worth of cycles. Breakpoints
+    /// are not checked.
+    fn run_one_frame(&mut self);
+
+    /// Current state of the machine, for savestates.
+    fn save_state(&mut self) -> Vec<u8>;
+
+    /// Restore a state from `save_state`. Fails if the state is
+    /// for another machine or game.
+    fn load_state(&mut self, data: &[u8]) -> Result<(), String>;
+
+    #[cfg(feature = "gui")]
+    fn update_input_state(&mut self, state: &egui::InputState);
 
     fn register_serial_output_buffer(&mut self, p: Producer<u8>);
     fn set_audio_rates(&mut self, clock_rate: f64, sample_rate: f64);
     fn end_audio_frame(&mut self);
-    fn push_audio_samples(&mut self, p: &mut Producer<i16>);
 
-    fn to_rgba8(&self, dst: &mut Box<[u8]>, palette: Vec<(u8, u8, u8)>);
+    /// Move all audio samples generated since the last call to `dst`.
+    fn read_audio_samples(&mut self, dst: &mut Vec<i16>);
+
+    fn to_rgba8(&self, dst: &mut Box<[u8]>, palette: &DmgPalette);
+
+    /// Current framebuffer, with one palette index (0-3) per pixel,
+    /// plus the color source in bit 6-7 (see `DmgPalette`).
+    fn framebuffer(&self) -> &[u8];
+
+    /// Save the current framebuffer as a PNG file.
+    fn capture(&self, filename: &str, palette: &DmgPalette) -> Result<(), std::io::Error>;
+
+    /// Called before the application exits.
+    fn shutdown(&mut self);
+
+    /// Text to draw over the screen.
+    fn overlay_text(&self) -> Vec<OverlayText>;
+
+    /// Names of the buttons of the controller, in the order they are
+    /// shown by the input display, and whether they are pressed.
+    fn input_state(&self) -> Vec<(&'static str, bool)>;
+}
+
+/// A named range of the address space, such as "VRAM".
+pub struct MemoryRegion {
+    pub name: &'static str,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A CPU register, as shown by the debugger.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RegisterValue {
+    pub name: &'static str,
+    pub value: u16,
+
+    /// Size of the register: 8 or 16 bits
+    pub bits: u8,
+}
+
+/// What the debugger windows need to know about a machine, so that
+/// they work regardless of the machine type. Breakpoints are handled
+/// by `Debug`, through the `Core` trait.
+pub trait Debuggable: Core {
+    /// All CPU registers, in the order they should be shown.
+    fn registers(&self) -> Vec<RegisterValue>;
+
+    /// Disassemble the instruction at `addr`. Returns the text and
+    /// the length of the instruction, or None if the length is not
+    /// known, for example for an invalid opcode.
+    fn disassemble(&self, addr: usize) -> (String, Option<usize>);
+
+    /// Write memory without side effects, as far as possible.
+    fn poke(&mut self, addr: usize, value: u8);
+
+    /// Whether `poke` changes the byte at `addr`. On the Game Boy,
+    /// writes to ROM go to the bank registers of the cartridge instead.
+    fn is_writable(&self, _addr: usize) -> bool {
+        true
+    }
+
+    /// Size of the address space.
+    fn memory_size(&self) -> usize;
+
+    /// Regions of the address space, for highlighting.
+    fn memory_regions(&self) -> &'static [MemoryRegion];
+
+    /// Position within the current scanline, in dots.
+    fn dot(&self) -> usize;
+
+    /// Cycles since the start of the current frame, and the number of
+    /// cycles in a frame.
+    fn frame_cycle(&self) -> usize;
+    fn cycles_per_frame(&self) -> usize;
 }
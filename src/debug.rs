@@ -1,5 +1,76 @@
-use crate::core::Core;
-use std::collections::HashMap;
+use crate::core::{Core, Debuggable};
+use crate::gameboy::instructions::op_length;
+use crate::memory_dump;
+use crate::symbols::{is_banked, SymbolTable};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+
+// Maximum number of lines kept by "trace to breakpoint". When
+// reached, the oldest lines are dropped.
+pub const MAX_TRACE_LINES: usize = 500_000;
+
+// Default number of instructions kept in the ring buffer trace
+pub const DEFAULT_RING_TRACE_SIZE: usize = 100_000;
+
+// Selects the instructions recorded in the ring buffer trace
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TraceFilter {
+    // Address range (inclusive) of the instructions
+    pub from: usize,
+    pub to: usize,
+
+    // Only record instructions with this opcode
+    pub opcode: Option<u8>,
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceFilter {
+    pub fn new() -> Self {
+        TraceFilter {
+            from: 0,
+            to: 0xFFFF,
+            opcode: None,
+        }
+    }
+
+    pub fn matches(&self, pc: usize, opcode: u8) -> bool {
+        pc >= self.from && pc <= self.to && self.opcode.is_none_or(|op| op == opcode)
+    }
+}
+
+// What to do when execution runs across a ROM bank boundary, by
+// falling through from bank 0 into the switchable bank at 0x4000, or
+// from the end of the switchable bank into VRAM at 0x8000. Either is
+// almost always a bug, such as a missing return or a routine placed
+// too close to the end of the bank.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BankBoundaryCheck {
+    Off,
+    Warn,
+    Break,
+}
+
+impl BankBoundaryCheck {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(BankBoundaryCheck::Off),
+            "warn" => Some(BankBoundaryCheck::Warn),
+            "break" => Some(BankBoundaryCheck::Break),
+            _ => None,
+        }
+    }
+}
+
+// Addresses where execution should never continue by falling through
+const BANK_BOUNDARIES: [(usize, &str); 2] = [
+    (0x4000, "from ROM bank 0 into the switchable ROM bank"),
+    (0x8000, "from the end of the switchable ROM bank into VRAM"),
+];
 
 #[derive(PartialEq)]
 pub enum ExecState {
@@ -16,11 +87,305 @@ pub enum ExecState {
 
 pub struct Breakpoint {
     pub enabled: bool,
+
+    // Only break while this bank is mapped at the address
+    pub bank: Option<usize>,
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s.trim().trim_start_matches("0x"), 16).ok()
+}
+
+// Parse a breakpoint address, given as a label, a hexadecimal value,
+// or a bank and a value such as "03:4123". Labels come first, as names
+// such as "Add" are valid hex values. Returns the bank, which is only
+// kept for banked memory, and the address.
+pub fn parse_address(s: &str, symbols: &SymbolTable) -> Option<(Option<usize>, usize)> {
+    let s = s.trim();
+    let (bank, adr) = match (symbols.find(s), s.split_once(':')) {
+        (Some((bank, adr)), _) => (Some(bank), adr),
+        (None, Some((bank, adr))) => (Some(parse_hex(bank)?), parse_hex(adr)?),
+        (None, None) => (None, parse_hex(s)?),
+    };
+    Some((bank.filter(|_| is_banked(adr)), adr))
+}
+
+// An address with its bank, such as "03:4123", or without
+pub fn format_address(bank: Option<usize>, adr: usize) -> String {
+    match bank {
+        Some(bank) => format!("{:02X}:{:04X}", bank, adr),
+        None => format!("{:04X}", adr),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    // Longer operators first, so that "<=" is not taken for "<"
+    const OPERATORS: [(&'static str, Comparison); 7] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    pub fn compare(&self, a: u16, b: u16) -> bool {
+        match self {
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterOrEqual => a >= b,
+        }
+    }
+}
+
+// Breaks execution when a register value satisfies a condition, such
+// as "SP < C000" or "HL == 8000". The condition is checked after every
+// instruction, and execution breaks when it becomes true, rather than
+// on every instruction for as long as it stays true.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RegisterWatchpoint {
+    pub enabled: bool,
+    pub register: String,
+    pub comparison: Comparison,
+    pub value: u16,
+
+    // Result of the last evaluation
+    satisfied: bool,
+}
+
+impl RegisterWatchpoint {
+    // Parse a condition such as "SP < C000". The value is hexadecimal.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (pos, symbol, comparison) = Comparison::OPERATORS
+            .iter()
+            .filter_map(|(symbol, comparison)| {
+                s.find(symbol).map(|pos| (pos, *symbol, *comparison))
+            })
+            .min_by_key(|(pos, _, _)| *pos)
+            .ok_or(format!("missing comparison in {}", s))?;
+
+        let register = s[..pos].trim().to_uppercase();
+        if register.is_empty() || !register.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!("invalid register in {}", s));
+        }
+
+        let value = s[pos + symbol.len()..]
+            .trim()
+            .trim_start_matches("0x")
+            .trim_start_matches('$');
+        let value =
+            u16::from_str_radix(value, 16).map_err(|_| format!("invalid value in {}", s))?;
+
+        Ok(RegisterWatchpoint {
+            enabled: true,
+            register,
+            comparison,
+            value,
+            satisfied: false,
+        })
+    }
+
+    // Returns true if the condition has become true since the last
+    // evaluation
+    pub fn evaluate(&mut self, core: &impl Core) -> bool {
+        let satisfied = match core.register(&self.register) {
+            Some(value) => self.enabled && self.comparison.compare(value, self.value),
+            None => false,
+        };
+        let triggered = satisfied && !self.satisfied;
+        self.satisfied = satisfied;
+        triggered
+    }
+}
+
+impl std::fmt::Display for RegisterWatchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {:04X}",
+            self.register,
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+// An expression watched in the debugger, such as "HL", "SP-2" or
+// "[HL+1]". Terms are added or subtracted, with 16-bit wrap around. A
+// term is a register, a number, or an expression in brackets, which
+// reads the byte at that address. Numbers are decimal, or hexadecimal
+// with a 0x or $ prefix, as names such as "BC" are valid hex values.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Number(u16),
+    Register(String),
+    Memory(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, String> {
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut pos = 0;
+        let expr = Expr::parse_sum(&chars, &mut pos)?;
+        match chars.get(pos) {
+            Some(c) => Err(format!("unexpected {} in {}", c, s)),
+            None => Ok(expr),
+        }
+    }
+
+    fn parse_sum(chars: &[char], pos: &mut usize) -> Result<Expr, String> {
+        let mut expr = Expr::parse_term(chars, pos)?;
+        while let Some(op) = chars.get(*pos).copied().filter(|c| *c == '+' || *c == '-') {
+            *pos += 1;
+            let rhs = Box::new(Expr::parse_term(chars, pos)?);
+            expr = match op {
+                '+' => Expr::Add(Box::new(expr), rhs),
+                _ => Expr::Sub(Box::new(expr), rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn parse_term(chars: &[char], pos: &mut usize) -> Result<Expr, String> {
+        let is_word = |c: &char| c.is_ascii_alphanumeric() || *c == '$';
+        match chars.get(*pos) {
+            Some('[') => {
+                *pos += 1;
+                let addr = Expr::parse_sum(chars, pos)?;
+                if chars.get(*pos) != Some(&']') {
+                    return Err("missing ]".to_string());
+                }
+                *pos += 1;
+                Ok(Expr::Memory(Box::new(addr)))
+            }
+            Some(c) if is_word(c) => {
+                let start = *pos;
+                while chars.get(*pos).is_some_and(is_word) {
+                    *pos += 1;
+                }
+                let word: String = chars[start..*pos].iter().collect();
+                Expr::parse_word(&word)
+            }
+            Some(c) => Err(format!("unexpected {}", c)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn parse_word(word: &str) -> Result<Expr, String> {
+        let value = match word.strip_prefix("0x").or_else(|| word.strip_prefix('$')) {
+            Some(digits) => u16::from_str_radix(digits, 16),
+            None if word.starts_with(|c: char| c.is_ascii_digit()) => word.parse(),
+            None => return Ok(Expr::Register(word.to_uppercase())),
+        };
+        value
+            .map(Expr::Number)
+            .map_err(|_| format!("invalid number: {}", word))
+    }
+
+    pub fn evaluate(&self, core: &impl Core) -> Result<u16, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Register(name) => core
+                .register(name)
+                .ok_or(format!("unknown register: {}", name)),
+            Expr::Memory(addr) => Ok(core.peek(addr.evaluate(core)? as usize) as u16),
+            Expr::Add(a, b) => Ok(a.evaluate(core)?.wrapping_add(b.evaluate(core)?)),
+            Expr::Sub(a, b) => Ok(a.evaluate(core)?.wrapping_sub(b.evaluate(core)?)),
+        }
+    }
+
+    // Bytes of memory and 8-bit registers are shown with 8 bits
+    pub fn bits(&self) -> u8 {
+        match self {
+            Expr::Memory(_) => 8,
+            Expr::Register(name) if name.len() == 1 => 8,
+            _ => 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchFormat {
+    Hex,
+    Decimal,
+    Binary,
+}
+
+impl WatchFormat {
+    pub fn format(&self, value: u16, bits: u8) -> String {
+        match (self, bits) {
+            (WatchFormat::Hex, 8) => format!("{:02X}", value),
+            (WatchFormat::Hex, _) => format!("{:04X}", value),
+            (WatchFormat::Decimal, _) => format!("{}", value),
+            (WatchFormat::Binary, 8) => format!("{:08b}", value),
+            (WatchFormat::Binary, _) => format!("{:016b}", value),
+        }
+    }
+}
+
+pub struct WatchExpression {
+    pub text: String,
+    expr: Expr,
+    pub format: WatchFormat,
+
+    // Result of the last evaluation, if any
+    value: Option<Result<u16, String>>,
+}
+
+impl WatchExpression {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(WatchExpression {
+            text: s.trim().to_string(),
+            expr: Expr::parse(s)?,
+            format: WatchFormat::Hex,
+            value: None,
+        })
+    }
+
+    pub fn update(&mut self, core: &impl Core) {
+        self.value = Some(self.expr.evaluate(core));
+    }
+
+    // The value of the last evaluation, or the error
+    pub fn display(&self) -> String {
+        match &self.value {
+            Some(Ok(value)) => self.format.format(*value, self.expr.bits()),
+            Some(Err(e)) => e.clone(),
+            None => "-".to_string(),
+        }
+    }
 }
 
 impl Breakpoint {
-    pub fn evaluate(&self, _core: &impl Core) -> bool {
-        self.enabled
+    pub fn evaluate(&self, core: &impl Core, adr: usize) -> bool {
+        self.enabled && self.bank.is_none_or(|bank| core.bank(adr) == bank)
     }
 }
 
@@ -29,6 +394,13 @@ pub struct Debug {
     // aka "ld b, b" instructions (0x40).
     pub source_code_breakpoints: bool,
     pub debug_log: Option<std::fs::File>,
+
+    // Include the joypad state in the debug log, at the start of each
+    // frame and whenever the joypad is read
+    pub log_input: bool,
+    logged_frame: Option<usize>,
+    logged_joypad_reads: u64,
+
     pub state: ExecState,
 
     // When single-stepping, steps holds the number of steps
@@ -36,10 +408,54 @@ pub struct Debug {
     pub steps: u32,
 
     pub breakpoints: HashMap<usize, Vec<Breakpoint>>,
+    pub register_watchpoints: Vec<RegisterWatchpoint>,
 
     // Execution will break when this scanline is reached.
     // Set to a value >153 to disable.
     pub break_on_scanline: Option<usize>,
+
+    // Execution will break when this frame is reached
+    pub break_on_frame: Option<usize>,
+
+    // The emulator exits when this cycle is reached, after flushing
+    // the debug log and writing the memory dumps
+    pub exit_at_cycle: Option<u64>,
+
+    // Address ranges, both ends included, and the files they are
+    // dumped to on exit at `exit_at_cycle`
+    pub memory_dumps: Vec<(usize, usize, String)>,
+
+    // Instructions traced since "trace to breakpoint" was started.
+    // Moved to `captured_trace` when execution breaks.
+    trace: Option<VecDeque<String>>,
+    captured_trace: Option<Vec<String>>,
+
+    // The most recent instructions that match the filter, if enabled.
+    // Unlike the debug log, this is kept in memory and never grows
+    // beyond `ring_trace_size` lines.
+    ring_trace: Option<VecDeque<String>>,
+    pub ring_trace_size: usize,
+    pub ring_trace_filter: TraceFilter,
+
+    pub bank_boundary_check: BankBoundaryCheck,
+
+    // Address of the last executed instruction, and the address that
+    // follows it. Used to detect execution running across a bank
+    // boundary.
+    last_op: Option<(usize, usize)>,
+
+    // Break before the first instruction after the boot ROM has
+    // handed over control to the cartridge, so that breakpoints can
+    // be set before any game code has been executed.
+    pub break_at_entry: bool,
+
+    // Labels and source lines of the running program, used to
+    // annotate traces and to set breakpoints by label
+    pub symbols: SymbolTable,
+
+    // Frozen addresses, and the values written to them at the end of
+    // every frame
+    pub frozen: BTreeMap<usize, u8>,
 }
 
 impl Debug {
@@ -47,10 +463,38 @@ impl Debug {
         Debug {
             source_code_breakpoints: false,
             debug_log: None,
+            log_input: false,
+            logged_frame: None,
+            logged_joypad_reads: 0,
             state: ExecState::RUN,
             steps: 0,
             breakpoints: HashMap::new(),
+            register_watchpoints: Vec::new(),
             break_on_scanline: None,
+            break_on_frame: None,
+            exit_at_cycle: None,
+            memory_dumps: Vec::new(),
+            trace: None,
+            captured_trace: None,
+            ring_trace: None,
+            ring_trace_size: DEFAULT_RING_TRACE_SIZE,
+            ring_trace_filter: TraceFilter::new(),
+            bank_boundary_check: BankBoundaryCheck::Off,
+            last_op: None,
+            break_at_entry: false,
+            symbols: SymbolTable::new(),
+            frozen: BTreeMap::new(),
+        }
+    }
+
+    // Re-write all frozen values. Called by the core thread at the end
+    // of each frame. Addresses that can't be written, such as ROM,
+    // are skipped.
+    pub fn apply_frozen(&self, core: &mut impl Debuggable) {
+        for (addr, value) in self.frozen.iter() {
+            if core.is_writable(*addr) {
+                core.poke(*addr, *value);
+            }
         }
     }
 
@@ -95,6 +539,46 @@ impl Debug {
         self.steps += 1;
     }
 
+    // Trace every instruction until execution breaks next time,
+    // and continue execution if stopped.
+    pub fn trace_to_breakpoint(&mut self) {
+        println!("Tracing until next break");
+        self.trace = Some(VecDeque::new());
+        if self.state == ExecState::STEP {
+            self.continue_execution();
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    // Returns the trace captured by `trace_to_breakpoint`, once
+    // execution has stopped
+    pub fn take_captured_trace(&mut self) -> Option<Vec<String>> {
+        self.captured_trace.take()
+    }
+
+    // Start or stop recording instructions in the ring buffer.
+    // Stopping discards the recorded instructions.
+    pub fn enable_ring_trace(&mut self, enabled: bool) {
+        if !enabled {
+            self.ring_trace = None;
+        } else if self.ring_trace.is_none() {
+            self.ring_trace = Some(VecDeque::new());
+        }
+    }
+
+    pub fn ring_trace(&self) -> Option<&VecDeque<String>> {
+        self.ring_trace.as_ref()
+    }
+
+    pub fn clear_ring_trace(&mut self) {
+        if let Some(ref mut ring) = self.ring_trace {
+            ring.clear();
+        }
+    }
+
     pub fn start_debug_log(&mut self, filename: &str) {
         self.debug_log = Some(
             std::fs::OpenOptions::new()
@@ -106,7 +590,6 @@ impl Debug {
         );
     }
 
-    #[allow(dead_code)]
     pub fn finalize(&mut self) {
         match self.debug_log {
             Some(ref mut f) => match f.sync_all() {
@@ -117,16 +600,79 @@ impl Debug {
         };
     }
 
+    fn log_input(&mut self, core: &impl Core) {
+        let f = match self.debug_log {
+            Some(ref mut f) => f,
+            None => return,
+        };
+
+        let mut lines = Vec::new();
+
+        let frame = core.current_frame();
+        if self.logged_frame != Some(frame) {
+            self.logged_frame = Some(frame);
+            lines.push(format!("-- Frame {} input: {}", frame, core.input_text()));
+        }
+
+        let (reads, value) = core.joypad_reads();
+        if reads != self.logged_joypad_reads {
+            self.logged_joypad_reads = reads;
+            lines.push(format!(
+                "-- P1 read: {:02X} input: {}",
+                value,
+                core.input_text()
+            ));
+        }
+
+        for line in lines {
+            println!("{}", line);
+            if let Err(e) = writeln!(f, "{}", line) {
+                panic!("Failed to write log: {}", e);
+            }
+        }
+    }
+
+    // Returns a description of the boundary, if the next op is reached
+    // by falling through from the last op across a bank boundary.
+    fn crossed_bank_boundary(&mut self, pc: usize) -> Option<&'static str> {
+        let (last_pc, next_pc) = self.last_op.take()?;
+        if pc != next_pc {
+            return None;
+        }
+        BANK_BOUNDARIES
+            .iter()
+            .find(|(boundary, _)| last_pc < *boundary && pc >= *boundary)
+            .map(|(_, description)| *description)
+    }
+
     // Perform debugging actions before every op.
     // Returns true if a breakpoint has been triggered.
     pub fn before_op(&mut self, core: &impl Core) -> bool {
         // FIXME: this will be executed even if next op is not executed
         // because execution is stopped.
+        if self.log_input {
+            self.log_input(core);
+        }
+
         match self.debug_log {
             Some(ref mut f) => core.log_state(f),
             None => {}
         }
 
+        if let Some(cycle) = self.exit_at_cycle {
+            if core.cycle() >= cycle {
+                println!("Reached cycle {}, exiting", core.cycle());
+                for (start, end, filename) in self.memory_dumps.iter() {
+                    match memory_dump::dump_memory(core, *start, *end, filename) {
+                        Ok(_) => println!("Dumped {:04X}-{:04X} to {}", start, end, filename),
+                        Err(e) => println!("Failed to dump memory to {}: {}", filename, e),
+                    }
+                }
+                self.finalize();
+                std::process::exit(0);
+            }
+        }
+
         // Check breakpoints, unless current state is CONTINUE
         // which means that we're continuing after a breakpoint
         // was reached.
@@ -134,16 +680,49 @@ impl Debug {
             let pc = core.pc();
             if self.breakpoints.contains_key(&pc) {
                 for bp in self.breakpoints[&pc].iter() {
-                    if bp.evaluate(core) {
+                    if bp.evaluate(core, pc) {
                         self.state = ExecState::STEP;
                     }
                 }
             }
 
+            for wp in self.register_watchpoints.iter_mut() {
+                if wp.evaluate(core) {
+                    println!("Watchpoint {} triggered at {:04X}", wp, pc);
+                    self.state = ExecState::STEP;
+                }
+            }
+
+            if self.break_at_entry && !core.in_boot_rom() {
+                println!("Stopped at entry point {:04X}", pc);
+                self.break_at_entry = false;
+                self.state = ExecState::STEP;
+            }
+
             if self.source_code_breakpoints && core.at_source_code_breakpoint() {
                 self.state = ExecState::STEP;
             }
 
+            if self.bank_boundary_check != BankBoundaryCheck::Off {
+                if let Some(description) = self.crossed_bank_boundary(pc) {
+                    println!(
+                        "Execution crossed a bank boundary at {:04X}: {}",
+                        pc, description
+                    );
+                    if self.bank_boundary_check == BankBoundaryCheck::Break {
+                        self.state = ExecState::STEP;
+                    }
+                }
+            }
+
+            if let Some(frame) = self.break_on_frame {
+                if core.current_frame() >= frame {
+                    println!("Stopped at frame {}", core.current_frame());
+                    self.break_on_frame = None;
+                    self.state = ExecState::STEP;
+                }
+            }
+
             match self.break_on_scanline {
                 Some(n) => {
                     if core.scanline() == n {
@@ -155,7 +734,45 @@ impl Debug {
             }
         }
 
-        return self.next();
+        let execute = self.next();
+
+        if execute && self.bank_boundary_check != BankBoundaryCheck::Off {
+            // Only ROM is followed by a boundary
+            let pc = core.pc();
+            self.last_op = match pc {
+                0x0000..=0x7FFF => op_length(core.peek(pc)).map(|len| (pc, pc + len)),
+                _ => None,
+            };
+        }
+
+        if let Some(ref mut ring) = self.ring_trace {
+            let pc = core.pc();
+            if execute && self.ring_trace_filter.matches(pc, core.peek(pc)) {
+                if let Some(line) = core.trace_line() {
+                    while ring.len() >= self.ring_trace_size.max(1) {
+                        ring.pop_front();
+                    }
+                    ring.push_back(self.symbols.annotate(core.bank(pc), pc, line));
+                }
+            }
+        }
+
+        if let Some(ref mut trace) = self.trace {
+            if execute {
+                if let Some(line) = core.trace_line() {
+                    if trace.len() == MAX_TRACE_LINES {
+                        trace.pop_front();
+                    }
+                    let pc = core.pc();
+                    trace.push_back(self.symbols.annotate(core.bank(pc), pc, line));
+                }
+            } else if self.state == ExecState::STEP {
+                println!("Trace stopped after {} instructions", trace.len());
+                self.captured_trace = self.trace.take().map(Vec::from);
+            }
+        }
+
+        execute
     }
 }
 
@@ -207,3 +824,305 @@ pub fn address_type(addr: u16) -> String {
 
     return "Interrupt Enable Register".to_string();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::buttons::ButtonType;
+    use crate::gameboy::cartridge::cartridge_type::CartridgeType;
+    use crate::gameboy::cartridge::no_mbc::NoMBC;
+    use crate::gameboy::emu::{Emu, Machine};
+
+    #[test]
+    fn test_trace_to_breakpoint() {
+        // NOP, NOP, JP 0xC000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        for (i, b) in [0x00, 0x00, 0xC3, 0x00, 0xC0].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut debug = Debug::new();
+        let bp = Breakpoint {
+            enabled: true,
+            bank: None,
+        };
+        debug.add_breakpoint(0xC002, bp);
+        debug.trace_to_breakpoint();
+
+        while debug.before_op(&emu) {
+            emu.exec_op();
+        }
+
+        let trace = debug.take_captured_trace().unwrap();
+        assert_eq!(trace.len(), 2);
+        assert!(trace[0].contains("PC: 00:C000"));
+        assert!(trace[1].contains("PC: 00:C001"));
+        assert!(!debug.is_tracing());
+    }
+
+    #[test]
+    fn test_break_on_frame() {
+        // JP 0xC000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        for (i, b) in [0xC3, 0x00, 0xC0].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+        emu.mmu.direct_write(0xFF40, 0x80);
+
+        let mut debug = Debug::new();
+        debug.break_on_frame = Some(2);
+        while debug.before_op(&emu) {
+            emu.exec_op();
+        }
+        assert_eq!(emu.current_frame(), 2);
+        assert_eq!(debug.break_on_frame, None);
+    }
+
+    #[test]
+    fn test_watch_expressions() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.cpu.reg.set_hl(0xC0A0);
+        emu.cpu.reg.sp = 0xDFFE;
+        emu.mmu.direct_write(0xC0A0, 0x12);
+        emu.mmu.direct_write(0xC0A1, 0x34);
+
+        let eval = |s: &str| Expr::parse(s).and_then(|expr| expr.evaluate(&emu));
+        assert_eq!(eval("[0xC0A0]"), Ok(0x12));
+        assert_eq!(eval("hl"), Ok(0xC0A0));
+        assert_eq!(eval("[HL + 1]"), Ok(0x34));
+        assert_eq!(eval("SP-2"), Ok(0xDFFC));
+        assert_eq!(eval("$10-17"), Ok(0xFFFF));
+        assert!(eval("XY").is_err());
+        assert!(eval("[HL").is_err());
+        assert!(eval("HL+").is_err());
+        assert!(eval("0xG").is_err());
+
+        let mut watch = WatchExpression::parse("[HL]").unwrap();
+        assert_eq!(watch.display(), "-");
+        watch.update(&emu);
+        assert_eq!(watch.display(), "12");
+        watch.format = WatchFormat::Binary;
+        assert_eq!(watch.display(), "00010010");
+        assert_eq!(WatchFormat::Decimal.format(0xC0A0, 16), "49312");
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_label(0, 0x0150, "Add");
+        symbols.add_label(3, 0x4123, "Banked");
+        assert_eq!(parse_address("Add", &symbols), Some((None, 0x0150)));
+        assert_eq!(parse_address(" 0x1A2b", &symbols), Some((None, 0x1A2B)));
+        assert_eq!(parse_address("Main", &symbols), None);
+
+        // Banks are only kept for banked memory
+        assert_eq!(parse_address("Banked", &symbols), Some((Some(3), 0x4123)));
+        assert_eq!(parse_address("03:4123", &symbols), Some((Some(3), 0x4123)));
+        assert_eq!(parse_address("01:C000", &symbols), Some((None, 0xC000)));
+        assert_eq!(parse_address("xx:4000", &symbols), None);
+        assert_eq!(format_address(Some(3), 0x4123), "03:4123");
+    }
+
+    #[test]
+    fn test_banked_breakpoint() {
+        // The switchable bank of a ROM without banking is bank 1
+        let emu = Emu::new(Machine::GameBoyDMG);
+        let bp = |bank| Breakpoint {
+            enabled: true,
+            bank,
+        };
+        assert!(bp(None).evaluate(&emu, 0x4000));
+        assert!(bp(Some(1)).evaluate(&emu, 0x4000));
+        assert!(!bp(Some(2)).evaluate(&emu, 0x4000));
+    }
+
+    #[test]
+    fn test_break_at_entry() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        let mut debug = Debug::new();
+        debug.break_at_entry = true;
+
+        // The boot ROM runs until it hands over control
+        for _ in 0..4 {
+            assert!(debug.before_op(&emu));
+            emu.exec_op();
+        }
+
+        emu.mmu.bootstrap_mode = false;
+        assert!(!debug.before_op(&emu));
+        assert!(debug.state == ExecState::STEP);
+        assert_eq!(emu.cpu.reg.pc, 4);
+
+        debug.continue_execution();
+        assert!(debug.before_op(&emu));
+        assert!(!debug.break_at_entry);
+    }
+
+    #[test]
+    fn test_parse_register_watchpoint() {
+        let wp = RegisterWatchpoint::parse("sp<=$C000").unwrap();
+        assert_eq!(wp.register, "SP");
+        assert_eq!(wp.comparison, Comparison::LessOrEqual);
+        assert_eq!(wp.value, 0xC000);
+        assert_eq!(wp.to_string(), "SP <= C000");
+
+        assert_eq!(
+            RegisterWatchpoint::parse("HL = 8000").unwrap().comparison,
+            Comparison::Equal
+        );
+        assert!(RegisterWatchpoint::parse("HL 8000").is_err());
+        assert!(RegisterWatchpoint::parse("== 8000").is_err());
+        assert!(RegisterWatchpoint::parse("A > XY").is_err());
+    }
+
+    #[test]
+    fn test_register_watchpoint() {
+        // INC A, JP 0xC000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        for (i, b) in [0x3C, 0xC3, 0x00, 0xC0].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut debug = Debug::new();
+        debug
+            .register_watchpoints
+            .push(RegisterWatchpoint::parse("A >= 3").unwrap());
+
+        while debug.before_op(&emu) {
+            emu.exec_op();
+        }
+        assert_eq!(emu.cpu.reg.a, 3);
+
+        // Only breaks again when the condition becomes true again
+        debug.continue_execution();
+        for _ in 0..4 {
+            assert!(debug.before_op(&emu));
+            emu.exec_op();
+        }
+        assert_eq!(emu.cpu.reg.a, 5);
+    }
+
+    #[test]
+    fn test_ring_trace_filter() {
+        // NOP, NOP, JP 0xC000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        for (i, b) in [0x00, 0x00, 0xC3, 0x00, 0xC0].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut debug = Debug::new();
+        debug.enable_ring_trace(true);
+        debug.ring_trace_size = 4;
+        debug.ring_trace_filter.opcode = Some(0x00);
+
+        for _ in 0..9 {
+            debug.before_op(&emu);
+            emu.exec_op();
+        }
+
+        // Three loops with two NOPs each, of which the last four are kept
+        let ring = debug.ring_trace().unwrap();
+        assert_eq!(ring.len(), 4);
+        assert!(ring.iter().all(|line| !line.contains("PC: 00:C002")));
+
+        debug.ring_trace_filter = TraceFilter::new();
+        debug.ring_trace_filter.from = 0xC002;
+        debug.clear_ring_trace();
+        for _ in 0..3 {
+            debug.before_op(&emu);
+            emu.exec_op();
+        }
+        assert_eq!(debug.ring_trace().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_log_input() {
+        // LDH A,(00), JP 0xC000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        for (i, b) in [0xF0, 0x00, 0xC3, 0x00, 0xC0].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+        emu.mmu.buttons.set_pressed(ButtonType::A as u8);
+
+        let filename = std::env::temp_dir().join("rustboy-test-input.log");
+        let _ = std::fs::remove_file(&filename);
+        let mut debug = Debug::new();
+        debug.start_debug_log(filename.to_str().unwrap());
+        debug.log_input = true;
+
+        for _ in 0..3 {
+            debug.before_op(&emu);
+            emu.exec_op();
+        }
+
+        let log = std::fs::read_to_string(&filename).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "-- Frame 0 input: .......A");
+        assert!(lines[1].contains("PC: 00:C000"));
+        assert!(lines[2].starts_with("-- P1 read: "));
+        assert!(lines[3].contains("PC: 00:C002"));
+        assert!(lines[4].contains("PC: 00:C000"));
+    }
+
+    #[test]
+    fn test_bank_boundary_check() {
+        // NOPs all the way through the ROM
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x147] = 0x00;
+        let cartridge_type = CartridgeType::from_rom(&rom).unwrap();
+
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.mmu.cartridge = Box::new(NoMBC::new(cartridge_type, &rom));
+        emu.cpu.reg.pc = 0x3FFE;
+
+        let mut debug = Debug::new();
+        debug.bank_boundary_check = BankBoundaryCheck::Break;
+
+        while debug.before_op(&emu) {
+            emu.exec_op();
+        }
+        assert_eq!(emu.cpu.reg.pc, 0x4000);
+
+        // Jumping to the start of a bank is fine
+        for (i, b) in [0xC3, 0x00, 0x40].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+        debug.continue_execution();
+        for _ in 0..2 {
+            assert!(debug.before_op(&emu));
+            emu.exec_op();
+        }
+        assert_eq!(emu.cpu.reg.pc, 0x4001);
+    }
+
+    #[test]
+    fn test_apply_frozen() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+
+        let mut debug = Debug::new();
+        debug.frozen.insert(0xC000, 0x42);
+
+        // Writing to ROM would select a bank, so it's skipped
+        debug.frozen.insert(0x2000, 0x05);
+
+        emu.mmu.direct_write(0xC000, 0x00);
+        debug.apply_frozen(&mut emu);
+        assert_eq!(emu.mmu.direct_read(0xC000), 0x42);
+        assert!(!emu.is_writable(0x2000));
+        assert!(emu.is_writable(0xC000));
+    }
+}
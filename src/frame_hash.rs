@@ -0,0 +1,126 @@
+// Frame hashes for graphical regression tests
+//
+// Runs a ROM headless and prints a CRC-32 of the framebuffer at the
+// given frames, or compares them against a list of expected hashes.
+// This lets tests like dmg-acid2 run in CI without reference images.
+//
+// The list of expected hashes has a frame number and a hash per line,
+// in the same format as the output:
+//
+//   # dmg-acid2
+//   60 3c5a1f0e
+//   300 9b1d22c4
+//
+// The hash covers the raw framebuffer, with the color source bits
+// included, so it doesn't depend on the palette.
+
+use crate::gameboy::emu::Emu;
+use crate::gameboy::CYCLES_PER_FRAME;
+
+// CRC-32 (IEEE 802.3), as used by zip and PNG
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// Frame numbers, as a comma separated list
+pub fn parse_frames(list: &str) -> Result<Vec<usize>, String> {
+    let mut frames = list
+        .split(',')
+        .map(|s| s.trim().parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| format!("invalid frame list: {}", list))?;
+    frames.sort_unstable();
+    frames.dedup();
+    Ok(frames)
+}
+
+// Expected hashes, one "frame hash" pair per line. Empty lines and
+// lines starting with '#' are ignored.
+pub fn parse_expected(content: &str) -> Result<Vec<(usize, u32)>, String> {
+    let mut expected = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let frame = parts.next().and_then(|s| s.parse::<usize>().ok());
+        let hash = parts.next().and_then(|s| u32::from_str_radix(s, 16).ok());
+        match (frame, hash, parts.next()) {
+            (Some(frame), Some(hash), None) => expected.push((frame, hash)),
+            _ => return Err(format!("line {}: expected frame and hash", i + 1)),
+        }
+    }
+    expected.sort_unstable();
+    Ok(expected)
+}
+
+// Run until each of the frames, which must be sorted, and hash the
+// framebuffer. If the display is off no frames are produced, so the
+// time is limited to twice the time of the last frame.
+pub fn hash_frames(emu: &mut Emu, frames: &[usize]) -> Vec<(usize, u32)> {
+    let last = frames.last().copied().unwrap_or(0);
+    let max_cycles = emu.mmu.timer.abs_cycle + 2 * ((last + 1) * CYCLES_PER_FRAME) as u64;
+
+    let mut hashes = Vec::new();
+    for frame in frames {
+        while emu.mmu.ppu.frame_number < *frame && emu.mmu.timer.abs_cycle < max_cycles {
+            emu.step();
+        }
+        hashes.push((*frame, crc32(&emu.mmu.ppu.buffer)));
+    }
+    hashes
+}
+
+// Mismatches between actual and expected hashes, as text. Empty if
+// all hashes match.
+pub fn compare(actual: &[(usize, u32)], expected: &[(usize, u32)]) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    for (frame, hash) in expected {
+        match actual.iter().find(|(f, _)| f == frame) {
+            Some((_, h)) if h == hash => {}
+            Some((_, h)) => mismatches.push(format!(
+                "frame {}: expected {:08x}, got {:08x}",
+                frame, hash, h
+            )),
+            None => mismatches.push(format!("frame {}: not hashed", frame)),
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_frames("300, 60,60").unwrap(), [60, 300]);
+        assert!(parse_frames("60,x").is_err());
+
+        let expected = parse_expected("# acid\n300 0000abcd\n\n60 ffffffff\n").unwrap();
+        assert_eq!(expected, [(60, 0xFFFFFFFF), (300, 0xABCD)]);
+        assert!(parse_expected("60\n").is_err());
+
+        let actual = [(60, 0xFFFFFFFF), (300, 0x1234)];
+        assert_eq!(
+            compare(&actual, &expected),
+            ["frame 300: expected 0000abcd, got 00001234"]
+        );
+    }
+}
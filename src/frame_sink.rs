@@ -0,0 +1,518 @@
+// Frame sinks
+//
+// A frame sink receives emulated frames, in the format of the PPU
+// display buffer, and writes them somewhere. Sinks are driven by a
+// `FrameRecorder`, which takes care of frame skipping so that every
+// sink doesn't have to.
+//
+// Available sinks:
+//
+// - PngSequenceSink: one PNG file per frame, in a directory
+// - GifSink: animated GIF, written as frames arrive. Requires the
+//   "recording" feature.
+// - ApngSink: animated PNG. The number of frames has to be known
+//   before the first frame is written, so the compressed frames are
+//   kept in memory and written when recording is finished.
+
+use std::fs::File;
+use std::io::{BufWriter, Error};
+use std::path::Path;
+
+use crate::palette::DmgPalette;
+
+pub trait FrameSink {
+    // Write a frame. `duration` is the time in seconds that the frame
+    // is shown, which depends on the frame skip of the recorder.
+    fn push_frame(
+        &mut self,
+        frame_number: usize,
+        pixels: &[u8],
+        duration: f64,
+    ) -> Result<(), Error>;
+
+    // Called once when recording stops
+    fn finish(&mut self) -> Result<(), Error>;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VideoFormat {
+    Gif,
+    Apng,
+}
+
+impl VideoFormat {
+    pub fn from_name(name: &str) -> Option<VideoFormat> {
+        match name {
+            "gif" => Some(VideoFormat::Gif),
+            "apng" | "png" => Some(VideoFormat::Apng),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Gif => "gif",
+            VideoFormat::Apng => "png",
+        }
+    }
+}
+
+// Scale up the color indices of a frame with nearest neighbour
+fn scale_pixels(pixels: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for y in 0..height {
+        let row = &pixels[y * width..(y + 1) * width];
+        for _ in 0..scale {
+            for px in row.iter() {
+                for _ in 0..scale {
+                    scaled.push(*px);
+                }
+            }
+        }
+    }
+    scaled
+}
+
+pub fn to_rgb(pixels: &[u8], palette: DmgPalette) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(pixels.len() * 3);
+    for px in pixels.iter() {
+        let (r, g, b) = palette.color(*px);
+        rgb.extend([r, g, b]);
+    }
+    rgb
+}
+
+fn png_error(e: png::EncodingError) -> Error {
+    Error::other(e.to_string())
+}
+
+pub fn write_png(filename: &str, width: usize, height: usize, rgb: &[u8]) -> Result<(), Error> {
+    use png::HasParameters;
+
+    let file = File::create(filename)?;
+    let mut w = BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(&mut w, width as u32, height as u32);
+    encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(rgb).map_err(png_error)?;
+
+    Ok(())
+}
+
+// How two consecutive frames are combined into a single image, for
+// screenshots of games that show sprites every other frame to make
+// them look transparent
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BlendMode {
+    // The average color of the two frames, as the flicker looks on
+    // a real LCD
+    Average,
+
+    // The darker color of the two frames. Dark pixels are the ones
+    // that are "on", so everything drawn in either frame is shown.
+    Or,
+}
+
+impl BlendMode {
+    pub fn from_name(name: &str) -> Option<BlendMode> {
+        match name {
+            "average" => Some(BlendMode::Average),
+            "or" => Some(BlendMode::Or),
+            _ => None,
+        }
+    }
+}
+
+fn blend_rgb(a: &[u8], b: &[u8], mode: BlendMode) -> Vec<u8> {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| match mode {
+            BlendMode::Average => (*a as u16 + *b as u16).div_ceil(2) as u8,
+            BlendMode::Or => *a.min(b),
+        })
+        .collect()
+}
+
+// Save a single frame as a PNG file
+pub fn save_png(
+    filename: &str,
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    palette: DmgPalette,
+) -> Result<(), Error> {
+    write_png(filename, width, height, &to_rgb(pixels, palette))
+}
+
+// Save two consecutive frames, blended into one image, as a PNG file
+pub fn save_blended_png(
+    filename: &str,
+    pixels: &[u8],
+    previous: &[u8],
+    width: usize,
+    height: usize,
+    palette: DmgPalette,
+    mode: BlendMode,
+) -> Result<(), Error> {
+    let rgb = blend_rgb(&to_rgb(pixels, palette), &to_rgb(previous, palette), mode);
+    write_png(filename, width, height, &rgb)
+}
+
+pub struct PngSequenceSink {
+    dir: String,
+    width: usize,
+    height: usize,
+    scale: usize,
+    palette: DmgPalette,
+}
+
+impl PngSequenceSink {
+    pub fn create(
+        dir: &str,
+        width: usize,
+        height: usize,
+        scale: usize,
+        palette: DmgPalette,
+    ) -> Result<Self, Error> {
+        std::fs::create_dir_all(dir)?;
+        Ok(PngSequenceSink {
+            dir: dir.to_string(),
+            width,
+            height,
+            scale: scale.max(1),
+            palette,
+        })
+    }
+}
+
+impl FrameSink for PngSequenceSink {
+    fn push_frame(
+        &mut self,
+        frame_number: usize,
+        pixels: &[u8],
+        _duration: f64,
+    ) -> Result<(), Error> {
+        let filename = Path::new(&self.dir).join(format!("frame-{:06}.png", frame_number));
+        let scaled = scale_pixels(pixels, self.width, self.height, self.scale);
+        write_png(
+            &filename.to_string_lossy(),
+            self.width * self.scale,
+            self.height * self.scale,
+            &to_rgb(&scaled, self.palette),
+        )
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "recording")]
+pub struct GifSink {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: usize,
+    height: usize,
+    scale: usize,
+    palette: DmgPalette,
+
+    // Total time of all frames pushed so far, and the part of
+    // it that has been written as frame delays (in 1/100 s).
+    // Used to avoid drift due to rounding of the delays.
+    elapsed: f64,
+    written_delay: u64,
+}
+
+#[cfg(feature = "recording")]
+impl GifSink {
+    pub fn create(
+        filename: &str,
+        width: usize,
+        height: usize,
+        scale: usize,
+        palette: DmgPalette,
+    ) -> Result<Self, Error> {
+        let scale = scale.max(1);
+        let file = File::create(filename)?;
+
+        let mut gif_palette: Vec<u8> = Vec::with_capacity(36);
+        for (r, g, b) in palette.colors().iter() {
+            gif_palette.extend([*r, *g, *b]);
+        }
+
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(file),
+            (width * scale) as u16,
+            (height * scale) as u16,
+            &gif_palette,
+        )
+        .map_err(Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(Error::other)?;
+
+        Ok(GifSink {
+            encoder,
+            width,
+            height,
+            scale,
+            palette,
+            elapsed: 0.0,
+            written_delay: 0,
+        })
+    }
+}
+
+#[cfg(feature = "recording")]
+impl FrameSink for GifSink {
+    fn push_frame(
+        &mut self,
+        _frame_number: usize,
+        pixels: &[u8],
+        duration: f64,
+    ) -> Result<(), Error> {
+        self.elapsed += duration;
+        let total_delay = (self.elapsed * 100.0).round() as u64;
+        let delay = total_delay - self.written_delay;
+        self.written_delay = total_delay;
+
+        let scaled: Vec<u8> = scale_pixels(pixels, self.width, self.height, self.scale)
+            .iter()
+            .map(|px| self.palette.index(*px))
+            .collect();
+        let frame = gif::Frame {
+            width: (self.width * self.scale) as u16,
+            height: (self.height * self.scale) as u16,
+            delay: delay as u16,
+            buffer: std::borrow::Cow::Borrowed(&scaled),
+            ..gif::Frame::default()
+        };
+
+        self.encoder.write_frame(&frame).map_err(Error::other)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        // The trailer is written when the encoder is dropped
+        Ok(())
+    }
+}
+
+// Encode an image as PNG and return the content of its IDAT chunks,
+// which is the compressed image data
+fn compress_frame(width: usize, height: usize, rgb: &[u8]) -> Result<Vec<u8>, Error> {
+    use png::HasParameters;
+
+    let mut png_data: Vec<u8> = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, width as u32, height as u32);
+        encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(rgb).map_err(png_error)?;
+    }
+
+    // Skip the signature, then walk the chunks: length, type, data, CRC
+    let mut data = Vec::new();
+    let mut pos = 8;
+    while pos + 8 <= png_data.len() {
+        let len = u32::from_be_bytes([
+            png_data[pos],
+            png_data[pos + 1],
+            png_data[pos + 2],
+            png_data[pos + 3],
+        ]) as usize;
+        let chunk_type = &png_data[pos + 4..pos + 8];
+        if chunk_type == b"IDAT" {
+            data.extend_from_slice(&png_data[pos + 8..pos + 8 + len]);
+        }
+        pos += 12 + len;
+    }
+
+    Ok(data)
+}
+
+pub struct ApngSink {
+    filename: String,
+    width: usize,
+    height: usize,
+    scale: usize,
+    palette: DmgPalette,
+
+    // Compressed image data and duration in milliseconds of each frame
+    frames: Vec<(Vec<u8>, u16)>,
+}
+
+impl ApngSink {
+    pub fn create(
+        filename: &str,
+        width: usize,
+        height: usize,
+        scale: usize,
+        palette: DmgPalette,
+    ) -> Result<Self, Error> {
+        // Fail early if the file can't be written
+        File::create(filename)?;
+
+        Ok(ApngSink {
+            filename: filename.to_string(),
+            width,
+            height,
+            scale: scale.max(1),
+            palette,
+            frames: Vec::new(),
+        })
+    }
+}
+
+impl FrameSink for ApngSink {
+    fn push_frame(
+        &mut self,
+        _frame_number: usize,
+        pixels: &[u8],
+        duration: f64,
+    ) -> Result<(), Error> {
+        let scaled = scale_pixels(pixels, self.width, self.height, self.scale);
+        let rgb = to_rgb(&scaled, self.palette);
+        let data = compress_frame(self.width * self.scale, self.height * self.scale, &rgb)?;
+        self.frames.push((data, (duration * 1000.0).round() as u16));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        use png::HasParameters;
+
+        if self.frames.is_empty() {
+            return Err(Error::other("no frames recorded"));
+        }
+
+        let width = (self.width * self.scale) as u32;
+        let height = (self.height * self.scale) as u32;
+
+        let file = File::create(&self.filename)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+
+        // Animation control: number of frames, and loop forever
+        let mut actl = Vec::with_capacity(8);
+        actl.extend((self.frames.len() as u32).to_be_bytes());
+        actl.extend(0u32.to_be_bytes());
+        writer.write_chunk(*b"acTL", &actl).map_err(png_error)?;
+
+        // Frame control and frame data chunks share sequence numbers
+        let mut sequence: u32 = 0;
+
+        for (n, (data, delay)) in self.frames.iter().enumerate() {
+            let mut fctl = Vec::with_capacity(26);
+            fctl.extend(sequence.to_be_bytes());
+            fctl.extend(width.to_be_bytes());
+            fctl.extend(height.to_be_bytes());
+            fctl.extend(0u32.to_be_bytes());
+            fctl.extend(0u32.to_be_bytes());
+            fctl.extend(delay.to_be_bytes());
+            fctl.extend(1000u16.to_be_bytes());
+            fctl.extend([0, 0]);
+            writer.write_chunk(*b"fcTL", &fctl).map_err(png_error)?;
+            sequence += 1;
+
+            // The first frame is also the default image, shown by
+            // viewers that don't support APNG
+            if n == 0 {
+                writer.write_chunk(*b"IDAT", data).map_err(png_error)?;
+            } else {
+                let mut fdat = Vec::with_capacity(data.len() + 4);
+                fdat.extend(sequence.to_be_bytes());
+                fdat.extend_from_slice(data);
+                writer.write_chunk(*b"fdAT", &fdat).map_err(png_error)?;
+                sequence += 1;
+            }
+        }
+
+        self.frames.clear();
+
+        // The IEND chunk is written when the writer is dropped
+        Ok(())
+    }
+}
+
+pub fn create_video_sink(
+    format: VideoFormat,
+    filename: &str,
+    width: usize,
+    height: usize,
+    scale: usize,
+    palette: DmgPalette,
+) -> Result<Box<dyn FrameSink>, Error> {
+    Ok(match format {
+        #[cfg(feature = "recording")]
+        VideoFormat::Gif => Box::new(GifSink::create(filename, width, height, scale, palette)?),
+        #[cfg(not(feature = "recording"))]
+        VideoFormat::Gif => {
+            return Err(Error::other(
+                "GIF recording requires the \"recording\" feature",
+            ))
+        }
+        VideoFormat::Apng => Box::new(ApngSink::create(filename, width, height, scale, palette)?),
+    })
+}
+
+// Feeds every (skip + 1)th frame to a sink
+pub struct FrameRecorder {
+    sink: Box<dyn FrameSink>,
+    skip: usize,
+    fps: f64,
+    frame_count: usize,
+}
+
+impl FrameRecorder {
+    pub fn new(sink: Box<dyn FrameSink>, skip: usize, fps: f64) -> Self {
+        FrameRecorder {
+            sink,
+            skip,
+            fps,
+            frame_count: 0,
+        }
+    }
+
+    pub fn push(&mut self, frame_number: usize, pixels: &[u8]) -> Result<(), Error> {
+        let result = if self.frame_count.is_multiple_of(self.skip + 1) {
+            let duration = (self.skip + 1) as f64 / self.fps;
+            self.sink.push_frame(frame_number, pixels, duration)
+        } else {
+            Ok(())
+        };
+        self.frame_count += 1;
+        result
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.sink.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_pixels() {
+        let pixels = [0, 1, 2, 3];
+        let scaled = scale_pixels(&pixels, 2, 2, 2);
+        assert_eq!(scaled, vec![0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 3, 3, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn test_blend_rgb() {
+        let a = [0, 255, 100];
+        let b = [255, 255, 51];
+        assert_eq!(blend_rgb(&a, &b, BlendMode::Average), vec![128, 255, 76]);
+        assert_eq!(blend_rgb(&a, &b, BlendMode::Or), vec![0, 255, 51]);
+    }
+
+    #[test]
+    fn test_compressed_frame_is_zlib_stream() {
+        let data = compress_frame(2, 2, &[0; 12]).unwrap();
+        // zlib header: deflate with 32K window, and header checksum
+        assert_eq!(data[0] & 0x0F, 8);
+        assert_eq!(((data[0] as u16) << 8 | data[1] as u16) % 31, 0);
+    }
+}
@@ -1,20 +1,29 @@
 use super::super::{
     emu::Machine,
-    mmu::{NR50_REG, NR51_REG, NR52_REG},
+    mmu::{ResetKind, NR50_REG, NR51_REG, NR52_REG},
     CYCLES_PER_FRAME,
 };
 
 use super::{
-    noise_gen::NoiseSoundGenerator, square_gen::SquareWaveSoundGenerator,
-    wave_gen::WaveSoundGenerator,
+    high_pass::HighPassFilter, noise_gen::NoiseSoundGenerator,
+    square_gen::SquareWaveSoundGenerator, tap::ChannelTap, wave_gen::WaveSoundGenerator,
 };
 
+use std::collections::VecDeque;
+
 use blip_buf::BlipBuf;
 
+use crate::savestate::{SaveState, Serializer};
+
 // Approx numberof samples per frame at native frame rate.
 // The actual count is a little less than this.
 pub const SAMPLES_PER_FRAME: usize = CYCLES_PER_FRAME / 59;
 
+// The bit of the DIV counter that clocks the frame sequencer, on its
+// falling edge. This is bit 4 of DIV. In CGB double speed mode, which
+// isn't emulated, it's bit 5.
+const DIV_APU_BIT: u16 = 1 << 12;
+
 pub struct AudioProcessingUnit {
     machine: Machine,
 
@@ -34,9 +43,48 @@ pub struct AudioProcessingUnit {
     pub buf_left_amp: i16,
     pub buf_right_amp: i16,
 
+    // Clock and sample rate of the blip buffers
+    clock_rate: f64,
+    sample_rate: f64,
+
+    // Applied to the left and right output, after resampling
+    hpf_left: HighPassFilter,
+    hpf_right: HighPassFilter,
+
+    // Whether any DAC was powered on, once per output sample, so that
+    // the high-pass filter follows DACs turned on or off mid-frame.
+    // Sampled at the output sample rate, counted by `sample_phase`.
+    dac_states: VecDeque<bool>,
+    sample_phase: f64,
+
+    // Separate buffers for each channel, used when recording
+    // one stem per channel. Empty when not recording stems.
+    pub stem_bufs: Vec<BlipBuf>,
+    stem_amps: [i16; 4],
+    buf_size: u32,
+
     // Current frame sequencer step. Updated at 512 Hz,
     // or every 8192'th cycle.
     pub frame_seq_step: u8,
+
+    // Last value of the DIV bit that clocks the frame sequencer
+    div_apu: bool,
+
+    // Channels muted by the user. Not part of the emulated hardware.
+    pub muted: [bool; 4],
+
+    // If set, only this channel is audible
+    pub solo: Option<usize>,
+
+    // Output of each channel, before muting and mixing
+    pub taps: [ChannelTap; 4],
+
+    // Used for the wave RAM content after power cycle. Seeded with
+    // a constant so that emulation stays deterministic.
+    rng: fastrand::Rng,
+
+    // Start the DMG wave RAM with the same pattern on power cycle
+    deterministic: bool,
 }
 
 impl AudioProcessingUnit {
@@ -54,8 +102,28 @@ impl AudioProcessingUnit {
             buf_clock: 0,
             buf_left_amp: 0,
             buf_right_amp: 0,
+            clock_rate: 0.0,
+            sample_rate: 0.0,
+            hpf_left: HighPassFilter::new(machine),
+            hpf_right: HighPassFilter::new(machine),
+            dac_states: VecDeque::new(),
+            sample_phase: 0.0,
+            stem_bufs: Vec::new(),
+            stem_amps: [0; 4],
+            buf_size,
             powered_on: false,
             frame_seq_step: 0,
+            div_apu: false,
+            muted: [false; 4],
+            solo: None,
+            taps: [
+                ChannelTap::new(),
+                ChannelTap::new(),
+                ChannelTap::new(),
+                ChannelTap::new(),
+            ],
+            rng: fastrand::Rng::with_seed(0),
+            deterministic: crate::deterministic(),
         }
     }
 
@@ -63,7 +131,14 @@ impl AudioProcessingUnit {
     // Note that the APU can't easily be recreated, as it has a ringbuf
     // producer that can't be moved to a new instance of it, so instead
     // we must reset all values.
-    pub fn reset(&mut self) {
+    //
+    // The wave RAM keeps its content on soft reset. On power cycle
+    // the DMG wave RAM gets semi-random content, while the CGB
+    // always starts with the same pattern. In deterministic mode, the
+    // DMG also starts with the same pattern.
+    pub fn reset(&mut self, kind: ResetKind) {
+        let wave = self.ch3.wave;
+
         self.s1 = SquareWaveSoundGenerator::new(true, self.machine);
         self.s2 = SquareWaveSoundGenerator::new(false, self.machine);
         self.ch3 = WaveSoundGenerator::new(self.machine);
@@ -71,6 +146,59 @@ impl AudioProcessingUnit {
         self.nr50 = 0;
         self.nr51 = 0;
         self.powered_on = false;
+        self.frame_seq_step = 0;
+        self.div_apu = false;
+
+        match (kind, self.machine) {
+            (ResetKind::Soft, _) => self.ch3.wave = wave,
+            (ResetKind::Hard, Machine::GameBoyDMG | Machine::GameBoyMGB) if !self.deterministic => {
+                for b in self.ch3.wave.iter_mut() {
+                    *b = self.rng.u8(..);
+                }
+            }
+            (ResetKind::Hard, _) => {}
+        }
+    }
+
+    // Seed the generator of the power-on wave RAM content
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
+
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.clock_rate = clock_rate;
+        self.sample_rate = sample_rate;
+        self.buf_left.set_rates(clock_rate, sample_rate);
+        self.buf_right.set_rates(clock_rate, sample_rate);
+        self.hpf_left.set_sample_rate(sample_rate);
+        self.hpf_right.set_sample_rate(sample_rate);
+        self.dac_states.clear();
+        self.sample_phase = 0.0;
+        for buf in self.stem_bufs.iter_mut() {
+            buf.set_rates(clock_rate, sample_rate);
+        }
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    // Enable or disable the per-channel stem buffers
+    pub fn enable_stems(&mut self, enabled: bool) {
+        self.stem_bufs.clear();
+        self.stem_amps = [0; 4];
+
+        if enabled {
+            for _ in 0..4 {
+                let mut buf = BlipBuf::new(self.buf_size);
+                buf.set_rates(self.clock_rate, self.sample_rate);
+                self.stem_bufs.push(buf);
+            }
+        }
     }
 
     pub fn update_4t(&mut self, div_counter: u16) {
@@ -93,23 +221,23 @@ impl AudioProcessingUnit {
         // Rate   256 Hz      64 Hz       128 Hz
         //
         // To allow for all these to be generated, the frame sequencer
-        // must tick at 512 Hz (every 8192'th cycle). Since the pattern
-        // repeats every 8'th time, we can initialize frame sequencer to
-        // 65536 (8192 * 512), decrement to zero and wrap around.
-        //
-        // The frame sequencer is based on the DIV timer. DIV is the top
-        // 8 bits of the 16-bit timer that decrements for every clock cycle.
+        // must tick at 512 Hz (every 8192'th cycle).
         //
-        // Note that for CGB, div_counter should be shifted 14 bits instead of 13
-        // as the DIV registers decrements at double speed. That means only two
-        // bits remain, so we must have another strategy for the 64 Hz clock.
+        // The frame sequencer is clocked by the falling edge of bit 4
+        // of DIV (bit 12 of the 16-bit counter), called DIV-APU. Since
+        // writing to DIV resets the counter, a write while the bit is
+        // set produces an extra step.
         let mut hz64 = false;
         let mut hz128 = false;
         let mut hz256 = false;
 
         assert!(div_counter % 2 == 0);
 
-        if div_counter % 8192 == 0 {
+        let div_apu = div_counter & DIV_APU_BIT != 0;
+        let falling_edge = self.div_apu && !div_apu;
+        self.div_apu = div_apu;
+
+        if falling_edge {
             self.frame_seq_step = (self.frame_seq_step + 1) & 7;
             hz64 = self.frame_seq_step == 7;
             hz128 = self.frame_seq_step == 2 || self.frame_seq_step == 6;
@@ -121,6 +249,30 @@ impl AudioProcessingUnit {
         let ch3_output = self.ch3.update_4t(hz256);
         let ch4_output = self.ch4.update_4t(hz64, hz256);
 
+        self.taps[0].push(ch1_output);
+        self.taps[1].push(ch2_output);
+        self.taps[2].push(ch3_output);
+        self.taps[3].push(ch4_output);
+
+        if !self.stem_bufs.is_empty() {
+            let outputs = [ch1_output, ch2_output, ch3_output, ch4_output];
+            for (n, output) in outputs.iter().enumerate() {
+                // Same scale as in the mixer below, so that
+                // the stems add up to the mixed output
+                let amp = output >> 2;
+                let delta = (amp as i32) - (self.stem_amps[n] as i32);
+                if delta != 0 {
+                    self.stem_bufs[n].add_delta(self.buf_clock, delta);
+                    self.stem_amps[n] = amp;
+                }
+            }
+        }
+
+        let ch1_output = if self.is_audible(0) { ch1_output } else { 0 };
+        let ch2_output = if self.is_audible(1) { ch2_output } else { 0 };
+        let ch3_output = if self.is_audible(2) { ch3_output } else { 0 };
+        let ch4_output = if self.is_audible(3) { ch4_output } else { 0 };
+
         // Mixer
         let mut left: i16 = 0;
         if self.nr51 & 128 != 0 {
@@ -163,6 +315,59 @@ impl AudioProcessingUnit {
         }
 
         self.buf_clock = self.buf_clock.wrapping_add(1);
+
+        // At most a buffer worth of states is kept, in case the
+        // output isn't read
+        self.sample_phase += self.sample_rate;
+        if self.sample_rate > 0.0 && self.sample_phase >= self.clock_rate {
+            self.sample_phase -= self.clock_rate;
+            if self.dac_states.len() >= self.buf_size as usize {
+                self.dac_states.pop_front();
+            }
+            self.dac_states.push_back(self.dacs_enabled());
+        }
+    }
+
+    fn dacs_enabled(&self) -> bool {
+        self.s1.dac.powered_on
+            || self.s2.dac.powered_on
+            || self.ch3.dac.powered_on
+            || self.ch4.dac.powered_on
+    }
+
+    // PCM12 (CGB only): the digital output of channel 2 in the upper
+    // bits, and of channel 1 in the lower bits
+    pub fn read_pcm12(&self) -> u8 {
+        (self.s2.pcm << 4) | self.s1.pcm
+    }
+
+    // PCM34 (CGB only): the same for channel 4 and 3
+    pub fn read_pcm34(&self) -> u8 {
+        (self.ch4.pcm << 4) | self.ch3.pcm
+    }
+
+    // Remove the DC offset from resampled output, like the capacitor
+    // on the audio output of the hardware. Both channels are filtered
+    // on every sample, so that the right channel is ready when a
+    // recording starts.
+    pub fn high_pass(&mut self, left: &mut [i16], right: &mut [i16]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let dacs_enabled = self
+                .dac_states
+                .pop_front()
+                .unwrap_or_else(|| self.dacs_enabled());
+            *l = self.hpf_left.filter(*l, dacs_enabled);
+            *r = self.hpf_right.filter(*r, dacs_enabled);
+        }
+    }
+
+    // Returns false if channel n (0-3) has been muted by the user,
+    // either directly or by soloing another channel.
+    pub fn is_audible(&self, n: usize) -> bool {
+        match self.solo {
+            Some(solo) => solo == n,
+            None => !self.muted[n],
+        }
     }
 
     pub fn read_nr52(&self) -> u8 {
@@ -270,3 +475,149 @@ impl AudioProcessingUnit {
         }
     }
 }
+
+// The audio buffers are not saved. They continue from where they
+// are, so that there's no gap or click in the audio on load.
+impl SaveState for AudioProcessingUnit {
+    fn serialize(&mut self, s: &mut Serializer) {
+        self.s1.serialize(s);
+        self.s2.serialize(s);
+        self.ch3.serialize(s);
+        self.ch4.serialize(s);
+        s.u8(&mut self.nr50);
+        s.u8(&mut self.nr51);
+        s.bool(&mut self.powered_on);
+        s.u8(&mut self.frame_seq_step);
+        s.bool(&mut self.div_apu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::CLOCK_SPEED;
+
+    // Bits that read as 1 in each sound register from 0xFF10 to
+    // 0xFF2F, from the hardware tables in Blargg's "Game Boy Sound
+    // Operation". NR52 is tested separately.
+    #[rustfmt::skip]
+    const READ_MASKS: [u8; 0x20] = [
+        // NR10  NR11  NR12  NR13  NR14  -     NR21  NR22  NR23  NR24  NR30  NR31  NR32  NR33  NR34  -
+        0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+        // NR41  NR42  NR43  NR44  NR50  NR51  NR52  -     -     -     -     -     -     -     -     -
+        0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ];
+
+    #[test]
+    fn test_register_read_masks() {
+        for machine in [Machine::GameBoyDMG, Machine::GameBoyCGB] {
+            for value in [0x00, 0xFF] {
+                let mut apu = AudioProcessingUnit::new(machine, 1024);
+                apu.write_reg(NR52_REG, 0x80);
+                for (i, mask) in READ_MASKS.iter().enumerate() {
+                    let address = 0xFF10 + i;
+                    if address == NR52_REG {
+                        continue;
+                    }
+
+                    // Triggering has side effects on other registers
+                    let value = match address {
+                        0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => value & 0x7F,
+                        _ => value,
+                    };
+                    apu.write_reg(address, value);
+                    assert_eq!(
+                        apu.read_reg(address),
+                        value | mask,
+                        "read of 0x{:04X} after writing 0x{:02X}",
+                        address,
+                        value
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pcm_registers() {
+        let mut apu = AudioProcessingUnit::new(Machine::GameBoyCGB, 1024);
+        apu.write_reg(NR52_REG, 0x80);
+        assert_eq!((apu.read_pcm12(), apu.read_pcm34()), (0, 0));
+
+        // Channel 2 at full volume and the highest frequency, with a
+        // 75% duty cycle so that the output is high a few steps after
+        // the trigger
+        apu.write_reg(0xFF16, 0xC0);
+        apu.write_reg(0xFF17, 0xF0);
+        apu.write_reg(0xFF18, 0xFF);
+        apu.write_reg(0xFF19, 0x87);
+        for _ in 0..5 {
+            apu.update_4t(4);
+        }
+        assert_eq!(apu.read_pcm12(), 0xF0);
+
+        apu.write_reg(NR52_REG, 0x00);
+        apu.update_4t(4);
+        assert_eq!(apu.read_pcm12(), 0x00);
+    }
+
+    #[test]
+    fn test_high_pass_follows_dacs() {
+        let mut apu = AudioProcessingUnit::new(Machine::GameBoyDMG, 1024);
+        apu.set_rates(CLOCK_SPEED as f64 / 4.0, 44100.0);
+        apu.write_reg(NR52_REG, 0x80);
+
+        // About 42 samples with the DACs off, and then as many with
+        // the DAC of channel 1 on
+        for _ in 0..1000 {
+            apu.update_4t(0);
+        }
+        apu.write_reg(0xFF12, 0xF0);
+        for _ in 0..1000 {
+            apu.update_4t(0);
+        }
+        assert_eq!(apu.dac_states.len(), 84);
+
+        let mut left = [10000; 84];
+        let mut right = [10000; 84];
+        apu.high_pass(&mut left, &mut right);
+        assert_eq!(left[..40], [0; 40]);
+        assert_eq!(right[..40], [0; 40]);
+        assert!(left[44..].iter().all(|s| *s != 0));
+        assert!(right[44..].iter().all(|s| *s != 0));
+        assert!(apu.dac_states.is_empty());
+    }
+
+    // Run the APU while the DIV counter counts from `from` to `to`,
+    // and return the number of frame sequencer steps
+    fn run(apu: &mut AudioProcessingUnit, from: u16, to: u16) -> usize {
+        let before = apu.frame_seq_step;
+        let mut steps = 0;
+        let mut div_counter = from;
+        while div_counter != to {
+            div_counter = div_counter.wrapping_add(4);
+            let step = apu.frame_seq_step;
+            apu.update_4t(div_counter);
+            if apu.frame_seq_step != step {
+                steps += 1;
+            }
+        }
+        assert_eq!(apu.frame_seq_step, ((before as usize + steps) & 7) as u8);
+        steps
+    }
+
+    #[test]
+    fn test_frame_sequencer_steps_on_falling_edge() {
+        let mut apu = AudioProcessingUnit::new(Machine::GameBoyDMG, 1024);
+        assert_eq!(run(&mut apu, 0, 0x1FFC), 0);
+        assert_eq!(run(&mut apu, 0x1FFC, 0x2000), 1);
+        assert_eq!(run(&mut apu, 0x2000, 0x8000), 3);
+
+        // Resetting DIV while the bit is set is a falling edge, but
+        // not while it's clear
+        run(&mut apu, 0x8000, 0x9000);
+        assert_eq!(run(&mut apu, 0xFFFC, 0), 1);
+        run(&mut apu, 0, 0x0800);
+        assert_eq!(run(&mut apu, 0xFFFC, 0), 0);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, Serializer};
+
 // Every channel has a DAC: a 4-bit digital-to-analog converter
 // that generates a voltage from -1 to +1 for values 0 to 15.
 pub struct DAC {
@@ -17,3 +19,9 @@ impl DAC {
         }
     }
 }
+
+impl SaveState for DAC {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.bool(&mut self.powered_on);
+    }
+}
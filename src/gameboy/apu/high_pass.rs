@@ -0,0 +1,97 @@
+// High-pass filter
+//
+// The audio output of the Game Boy goes through a capacitor, that
+// removes the DC offset of the signal. Without it, the output jumps
+// when channels are enabled or disabled, which is heard as clicks.
+//
+// The filter is applied to the resampled output. The capacitor only
+// charges while at least one DAC is powered on.
+//
+// See "Obscure Behavior" in the Game Boy sound hardware page on the
+// gbdev wiki.
+
+use super::super::emu::Machine;
+use super::super::CLOCK_SPEED;
+
+// Part of the charge that remains after each cycle. The MGB and the
+// CGB have a smaller capacitor than the DMG.
+const DMG_CHARGE_FACTOR: f64 = 0.999958;
+const MGB_CGB_CHARGE_FACTOR: f64 = 0.998943;
+
+pub struct HighPassFilter {
+    capacitor: f64,
+
+    // Part of the charge that remains after each sample
+    charge_factor: f64,
+
+    machine: Machine,
+}
+
+impl HighPassFilter {
+    pub fn new(machine: Machine) -> Self {
+        HighPassFilter {
+            capacitor: 0.0,
+            charge_factor: 1.0,
+            machine,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        let factor = match self.machine {
+            Machine::GameBoyMGB | Machine::GameBoyCGB => MGB_CGB_CHARGE_FACTOR,
+            _ => DMG_CHARGE_FACTOR,
+        };
+        self.charge_factor = factor.powf(CLOCK_SPEED as f64 / sample_rate);
+    }
+
+    pub fn filter(&mut self, sample: i16, dacs_enabled: bool) -> i16 {
+        if !dacs_enabled {
+            return 0;
+        }
+
+        let input = sample as f64;
+        let output = input - self.capacitor;
+        self.capacitor = input - output * self.charge_factor;
+        output.clamp(i16::MIN as f64, i16::MAX as f64) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removes_dc_offset() {
+        let mut hpf = HighPassFilter::new(Machine::GameBoyDMG);
+        hpf.set_sample_rate(44100.0);
+
+        // A constant input is passed at first, and then decays
+        assert_eq!(hpf.filter(10000, true), 10000);
+        for _ in 0..44100 {
+            hpf.filter(10000, true);
+        }
+        assert_eq!(hpf.filter(10000, true), 0);
+
+        // A change of level is passed through
+        assert!(hpf.filter(-10000, true) < -19000);
+        assert_eq!(hpf.filter(-10000, false), 0);
+    }
+
+    #[test]
+    fn test_charge_factor() {
+        // Output of a constant input after 100 samples
+        let decayed = |machine| {
+            let mut hpf = HighPassFilter::new(machine);
+            hpf.set_sample_rate(44100.0);
+            for _ in 0..100 {
+                hpf.filter(10000, true);
+            }
+            hpf.filter(10000, true)
+        };
+
+        // The capacitor of the MGB and the CGB charges faster
+        assert!(decayed(Machine::GameBoyDMG) > 5000);
+        assert_eq!(decayed(Machine::GameBoyMGB), 0);
+        assert_eq!(decayed(Machine::GameBoyCGB), 0);
+    }
+}
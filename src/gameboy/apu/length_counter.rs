@@ -1,4 +1,5 @@
 use super::super::emu::Machine;
+use crate::savestate::{SaveState, Serializer};
 
 // All channels have a length counter which counts down and disables
 // the channel when it reaches zero. The length counter can be
@@ -102,3 +103,10 @@ impl LengthCounter {
         }
     }
 }
+
+impl SaveState for LengthCounter {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.bool(&mut self._enabled);
+        s.u16(&mut self.value);
+    }
+}
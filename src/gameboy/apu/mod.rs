@@ -20,8 +20,10 @@
 
 pub mod apu;
 pub mod dac;
+pub mod high_pass;
 pub mod length_counter;
 pub mod noise_gen;
 pub mod square_gen;
 pub mod sweep;
+pub mod tap;
 pub mod wave_gen;
@@ -2,6 +2,7 @@ use super::super::emu::Machine;
 use super::super::mmu::{NR40_REG, NR41_REG, NR42_REG, NR43_REG, NR44_REG};
 use super::dac::DAC;
 use super::length_counter::LengthCounter;
+use crate::savestate::{SaveState, Serializer};
 
 pub struct NoiseSoundGenerator {
     // ---------
@@ -59,6 +60,10 @@ pub struct NoiseSoundGenerator {
 
     pub length_counter: LengthCounter,
     pub dac: DAC,
+
+    // Digital output of the channel, before the DAC. Read through
+    // PCM34 on CGB.
+    pub pcm: u8,
     machine: Machine,
 }
 
@@ -79,6 +84,7 @@ impl NoiseSoundGenerator {
             initial_volume: 0,
             length_counter: LengthCounter::new(machine, 64),
             dac: DAC::new(),
+            pcm: 0,
             machine,
         }
     }
@@ -98,6 +104,13 @@ impl NoiseSoundGenerator {
         self.dac = DAC::new();
     }
 
+    // Rate at which the LFSR is clocked, in Hz
+    pub fn frequency_hz(&self) -> f64 {
+        let divisor = NOISE_DIVISOR_MAP[(self.nr43 & 7) as usize] as f64;
+        let shift = (self.nr43 >> 4) as i32;
+        4194304.0 / (divisor * 2f64.powi(shift))
+    }
+
     pub fn read_reg(&self, address: usize) -> u8 {
         match address {
             NR40_REG => 0xFF,
@@ -118,10 +131,13 @@ impl NoiseSoundGenerator {
                     0b1011_1111
                 }
             }
-            _ => panic!(
-                "Invalid register in noise sound generator: 0x{:04x}",
-                address
-            ),
+            _ => {
+                invalid_access!(
+                    "Invalid register in noise sound generator: 0x{:04x}",
+                    address
+                );
+                0xFF
+            }
         }
     }
 
@@ -160,7 +176,10 @@ impl NoiseSoundGenerator {
                     self.trigger(seq_step);
                 }
             }
-            _ => panic!("invalid register {}", address),
+            _ => invalid_access!(
+                "Invalid register in noise sound generator: 0x{:04x}",
+                address
+            ),
         }
     }
 
@@ -233,12 +252,30 @@ impl NoiseSoundGenerator {
             }
         }
 
+        let out = if self.lfsr & 1 == 0 { 0 } else { 1 };
+        self.pcm = if self.enabled { out * self.envelope } else { 0 };
+
         if self.enabled {
-            let out = if self.lfsr & 1 == 0 { 0 } else { 1 };
-            let dac_input = out * self.envelope;
-            return self.dac.convert(dac_input);
+            return self.dac.convert(self.pcm);
         }
 
         0
     }
 }
+
+impl SaveState for NoiseSoundGenerator {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u8(&mut self.nr43);
+        s.u16(&mut self.frequency_timer);
+        s.u16(&mut self.lfsr);
+        s.u8(&mut self.polynomial_counter);
+        s.bool(&mut self.enabled);
+        s.u8(&mut self.envelope);
+        s.u8(&mut self.envelope_period);
+        s.u8(&mut self.envelope_periods_initial);
+        s.bool(&mut self.envelope_increasing);
+        s.u8(&mut self.initial_volume);
+        self.length_counter.serialize(s);
+        self.dac.serialize(s);
+    }
+}
@@ -6,6 +6,7 @@ use super::super::mmu::{
 use super::dac::DAC;
 use super::length_counter::LengthCounter;
 use super::sweep::Sweep;
+use crate::savestate::{SaveState, Serializer};
 
 // SquareWaveSoundGenerator
 // ------------------------
@@ -45,8 +46,9 @@ pub struct SquareWaveSoundGenerator {
     // Frequency. 10 bits. Bit 7..0 in NR13 + bit 9..8 in NR14.
     pub frequency: u16,
 
-    // Internal register. When this counter reaches zero,
-    // it is reset to the frequency value (NR13, NR14) and
+    // Internal register. Number of cycles until the next step of the
+    // duty position. When this counter reaches zero, it is reset to
+    // the period of the frequency (NR13, NR14) and
     // wave_duty_position moves to next position
     frequency_timer: u16,
 
@@ -94,6 +96,10 @@ pub struct SquareWaveSoundGenerator {
 
     pub length_counter: LengthCounter,
     pub dac: DAC,
+
+    // Digital output of the channel, before the DAC. Read through
+    // PCM12 on CGB.
+    pub pcm: u8,
     machine: Machine,
 }
 
@@ -121,6 +127,7 @@ impl SquareWaveSoundGenerator {
             wave_duty_position: 0,
             sweep: if with_sweep { Some(Sweep::new()) } else { None },
             dac: DAC::new(),
+            pcm: 0,
         }
     }
 
@@ -143,6 +150,11 @@ impl SquareWaveSoundGenerator {
         }
     }
 
+    // Frequency of the generated tone in Hz
+    pub fn frequency_hz(&self) -> f64 {
+        131072.0 / (2048 - self.frequency) as f64
+    }
+
     pub fn read_reg(&self, address: usize) -> u8 {
         match address {
             NR10_REG | NR20_REG => match self.sweep {
@@ -166,10 +178,13 @@ impl SquareWaveSoundGenerator {
                     0b1011_1111
                 }
             }
-            _ => panic!(
-                "Invalid register in square wave sound generator: 0x{:04x}",
-                address
-            ),
+            _ => {
+                invalid_access!(
+                    "Invalid register in square wave sound generator: 0x{:04x}",
+                    address
+                );
+                0xFF
+            }
         }
     }
 
@@ -220,14 +235,28 @@ impl SquareWaveSoundGenerator {
                     self.trigger(seq_step);
                 }
             }
-            _ => panic!("Invalid APU register: 0x{:04x}", address),
+            _ => invalid_access!("Invalid APU register: 0x{:04x}", address),
         }
     }
 
+    // Number of cycles between steps of the duty position
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
     fn trigger(&mut self, seq_step: u8) {
         self.enabled = true;
         self.length_counter.trigger(64, seq_step);
-        self.frequency_timer = (2048 - self.frequency) * 4;
+
+        // The hardware timer is an 11-bit counter, clocked every
+        // fourth cycle, that counts up from the frequency and steps
+        // the duty position when it overflows. Triggering reloads the
+        // counter from the frequency, except for the two low bits,
+        // which are left unchanged. The duty position isn't reset.
+        let counter = 2048 - self.frequency_timer / 4;
+        let counter = (self.frequency & !3) | (counter & 3);
+        self.frequency_timer = (2048 - counter) * 4;
+
         self.envelope_period = self.envelope_periods_initial;
         self.envelope = self.initial_volume;
 
@@ -242,16 +271,12 @@ impl SquareWaveSoundGenerator {
     }
 
     pub fn update_4t(&mut self, hz64: bool, hz128: bool, hz256: bool) -> i16 {
-        assert!(self.frequency_timer % 4 == 0);
-
         // Decrement frequency timer
-        // FIXME: Handle frequency timer being less than 4.
-        //        When so, add to the frequency timer instead:
-        //        `if (tmr <= 4) { tmr += freq } else { tmr -= 4 }`
         if self.frequency_timer <= 4 {
             // If frequency timer reaches 0, reset it to the selected frequency
-            // (NR13, NR14) and increment the wave duty position
-            self.frequency_timer = (2048 - self.frequency) * 4;
+            // (NR13, NR14) and increment the wave duty position. Any
+            // cycles left over are carried into the next period.
+            self.frequency_timer = self.frequency_timer + self.period() - 4;
             self.wave_duty_position = (self.wave_duty_position + 1) & 7;
         } else {
             self.frequency_timer -= 4;
@@ -304,11 +329,89 @@ impl SquareWaveSoundGenerator {
             }
         }
 
+        self.pcm = if self.enabled { out * self.envelope } else { 0 };
+
         if self.enabled {
-            let dac_input = out * self.envelope;
-            return self.dac.convert(dac_input);
+            return self.dac.convert(self.pcm);
         }
 
         0
     }
 }
+
+impl SaveState for SquareWaveSoundGenerator {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u16(&mut self.frequency);
+        s.u16(&mut self.frequency_timer);
+        s.usize(&mut self.duty);
+        s.u16(&mut self.wave_duty_position);
+        s.u8(&mut self.initial_volume);
+        s.bool(&mut self.envelope_increasing);
+        s.u8(&mut self.envelope_periods_initial);
+        s.u8(&mut self.envelope);
+        s.u8(&mut self.envelope_period);
+        s.bool(&mut self.enabled);
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.serialize(s);
+        }
+        self.length_counter.serialize(s);
+        self.dac.serialize(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::CLOCK_SPEED;
+    use super::*;
+
+    fn write(ch: &mut SquareWaveSoundGenerator, address: usize, value: u8) {
+        ch.write_reg(address, value, 0, true);
+    }
+
+    fn trigger(ch: &mut SquareWaveSoundGenerator, frequency: u16) {
+        write(ch, NR22_REG, 0xF0);
+        write(ch, NR23_REG, (frequency & 0xFF) as u8);
+        write(ch, NR24_REG, 0x80 | (frequency >> 8) as u8);
+    }
+
+    // Run for a number of cycles and return the number of steps of
+    // the duty position
+    fn run(ch: &mut SquareWaveSoundGenerator, cycles: usize) -> usize {
+        let mut steps = 0;
+        for _ in 0..cycles / 4 {
+            let position = ch.wave_duty_position;
+            ch.update_4t(false, false, false);
+            if ch.wave_duty_position != position {
+                steps += 1;
+            }
+        }
+        steps
+    }
+
+    #[test]
+    fn test_pitch() {
+        // 8 steps per period of the tone, for a second of cycles. The
+        // first step after the trigger may come early or late.
+        for frequency in [0x000, 0x400, 0x780, 0x7FF] {
+            let mut ch = SquareWaveSoundGenerator::new(false, Machine::GameBoyDMG);
+            trigger(&mut ch, frequency);
+            run(&mut ch, 2 * 8192);
+            let steps = run(&mut ch, CLOCK_SPEED);
+            assert_eq!(steps, (ch.frequency_hz() * 8.0) as usize);
+        }
+    }
+
+    #[test]
+    fn test_retrigger() {
+        let mut ch = SquareWaveSoundGenerator::new(false, Machine::GameBoyDMG);
+        trigger(&mut ch, 0x700);
+        run(&mut ch, 3 * 0x100 * 4 + 8);
+        assert_eq!(ch.wave_duty_position, 3);
+
+        // The duty position is kept, and so are the two low bits of
+        // the counter, which has counted two steps into the period
+        trigger(&mut ch, 0x700);
+        assert_eq!(ch.wave_duty_position, 3);
+        assert_eq!(ch.frequency_timer, (0x100 - 2) * 4);
+    }
+}
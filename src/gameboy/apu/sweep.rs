@@ -1,3 +1,26 @@
+use crate::savestate::{SaveState, Serializer};
+
+// Frequency sweep unit of sound channel 1.
+//
+// Obscure behaviors, as tested by blargg's "04-sweep" and
+// "05-sweep details":
+//
+// - On trigger, the shadow frequency is loaded from the channel
+//   frequency and the sweep timer is reloaded. If shift is non-zero,
+//   a frequency calculation and overflow check is made immediately,
+//   but the result is not written back.
+// - When the sweep timer expires, a new frequency is calculated and
+//   checked for overflow. Overflow disables the channel. This is
+//   done even when shift is zero, so a shift of zero can still
+//   disable the channel, but the frequency is never updated.
+// - If shift is non-zero and there was no overflow, the new frequency
+//   is written back, and then calculated a second time and checked for
+//   overflow again. The result of the second calculation is not used.
+// - A sweep period of zero is treated as 8 for the timer, but no
+//   calculations are made when the timer expires.
+// - Clearing the negate bit after at least one calculation has been
+//   made in negate mode since the last trigger disables the channel.
+// - Bit 7 of NR10 is unused and always reads as 1.
 pub struct Sweep {
     duration: u8,
     decrement: bool,
@@ -120,3 +143,133 @@ impl Sweep {
         }
     }
 }
+
+impl SaveState for Sweep {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u8(&mut self.duration);
+        s.bool(&mut self.decrement);
+        s.u8(&mut self.shift);
+        s.bool(&mut self.enabled);
+        s.u8(&mut self.counter);
+        s.u16(&mut self.shadow_frequency);
+        s.bool(&mut self.has_calculated_in_decrement_mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::emu::Machine;
+    use super::super::super::mmu::{NR10_REG, NR12_REG, NR13_REG, NR14_REG};
+    use super::super::square_gen::SquareWaveSoundGenerator;
+
+    fn write(ch: &mut SquareWaveSoundGenerator, address: usize, value: u8) {
+        ch.write_reg(address, value, 0, true);
+    }
+
+    // Set up channel 1 with the given NR10 value and
+    // frequency, and trigger it
+    fn trigger(nr10: u8, frequency: u16) -> SquareWaveSoundGenerator {
+        let mut ch = SquareWaveSoundGenerator::new(true, Machine::GameBoyDMG);
+        write(&mut ch, NR10_REG, nr10);
+        write(&mut ch, NR12_REG, 0xF0);
+        write(&mut ch, NR13_REG, (frequency & 0xFF) as u8);
+        write(&mut ch, NR14_REG, 0x80 | (frequency >> 8) as u8);
+        ch
+    }
+
+    fn tick_sweep(ch: &mut SquareWaveSoundGenerator, n: usize) {
+        for _ in 0..n {
+            ch.update_4t(false, true, false);
+        }
+    }
+
+    #[test]
+    fn test_overflow_check_on_trigger() {
+        assert!(trigger(0x01, 0x555).enabled);
+        assert!(!trigger(0x01, 0x556).enabled);
+
+        // No calculation on trigger when shift is zero
+        assert!(trigger(0x10, 0x7FF).enabled);
+    }
+
+    #[test]
+    fn test_shift_zero_checks_overflow_without_update() {
+        let mut ch = trigger(0x10, 0x3FF);
+        tick_sweep(&mut ch, 1);
+        assert!(ch.enabled);
+        assert_eq!(ch.frequency, 0x3FF);
+
+        let mut ch = trigger(0x10, 0x400);
+        tick_sweep(&mut ch, 1);
+        assert!(!ch.enabled);
+    }
+
+    #[test]
+    fn test_period_zero_never_calculates() {
+        let mut ch = trigger(0x01, 0x500);
+        tick_sweep(&mut ch, 16);
+        assert!(ch.enabled);
+        assert_eq!(ch.frequency, 0x500);
+    }
+
+    #[test]
+    fn test_second_calculation_disables_channel() {
+        // The new frequency (0x780) is written, but the second
+        // calculation (0xB40) overflows
+        let mut ch = trigger(0x11, 0x500);
+        tick_sweep(&mut ch, 1);
+        assert_eq!(ch.frequency, 0x780);
+        assert!(!ch.enabled);
+    }
+
+    #[test]
+    fn test_pitch_slides() {
+        // Period 2: the frequency is updated every second tick
+        let mut ch = trigger(0x21, 0x100);
+        for (previous, expected) in [
+            (0x100, 0x180),
+            (0x180, 0x240),
+            (0x240, 0x360),
+            (0x360, 0x510),
+        ] {
+            tick_sweep(&mut ch, 1);
+            assert_eq!(ch.frequency, previous);
+            tick_sweep(&mut ch, 1);
+            assert_eq!(ch.frequency, expected);
+            assert!(ch.enabled);
+        }
+
+        // 0x510 + 0x288 = 0x798 is written, but 0x798 + 0x3CC overflows
+        tick_sweep(&mut ch, 2);
+        assert_eq!(ch.frequency, 0x798);
+        assert!(!ch.enabled);
+
+        let mut ch = trigger(0x19, 0x400);
+        for expected in [0x200, 0x100, 0x80, 0x40] {
+            tick_sweep(&mut ch, 1);
+            assert_eq!(ch.frequency, expected);
+            assert!(ch.enabled);
+        }
+    }
+
+    #[test]
+    fn test_clearing_negate_after_calculation() {
+        let mut ch = trigger(0x19, 0x400);
+        write(&mut ch, NR10_REG, 0x11);
+        assert!(!ch.enabled);
+
+        // No calculation has been made, since shift is zero
+        let mut ch = trigger(0x18, 0x400);
+        write(&mut ch, NR10_REG, 0x10);
+        assert!(ch.enabled);
+    }
+
+    #[test]
+    fn test_nr10_readback() {
+        let mut ch = SquareWaveSoundGenerator::new(true, Machine::GameBoyDMG);
+        for (value, expected) in [(0x00, 0x80), (0x7F, 0xFF), (0xFF, 0xFF), (0x2A, 0xAA)] {
+            write(&mut ch, NR10_REG, value);
+            assert_eq!(ch.read_reg(NR10_REG), expected);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Per-channel sample tap
+//
+// Keeps a short history of the output of a single sound channel,
+// before mixing and muting. Used for visualization, such as the
+// oscilloscope in the audio window.
+
+// Number of samples kept by each tap
+pub const TAP_SIZE: usize = 2048;
+
+// Only every n'th sample from the channel is kept. The APU is updated
+// at 1 MHz, so the tap holds about 31 ms of audio.
+const TAP_DECIMATION: u32 = 16;
+
+pub struct ChannelTap {
+    samples: [i16; TAP_SIZE],
+
+    // Position where the next sample will be written
+    pos: usize,
+
+    counter: u32,
+}
+
+impl Default for ChannelTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChannelTap {
+    pub fn new() -> Self {
+        ChannelTap {
+            samples: [0; TAP_SIZE],
+            pos: 0,
+            counter: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: i16) {
+        self.counter += 1;
+        if self.counter < TAP_DECIMATION {
+            return;
+        }
+        self.counter = 0;
+
+        self.samples[self.pos] = sample;
+        self.pos = (self.pos + 1) % TAP_SIZE;
+    }
+
+    // Get sample n, where 0 is the oldest sample in the tap
+    pub fn get(&self, n: usize) -> i16 {
+        self.samples[(self.pos + n) % TAP_SIZE]
+    }
+
+    // Sample rate of the tap, given the rate the channel is updated at
+    pub fn sample_rate(update_rate: f64) -> f64 {
+        update_rate / TAP_DECIMATION as f64
+    }
+}
@@ -2,6 +2,7 @@ use super::super::emu::Machine;
 use super::super::mmu::{NR30_REG, NR31_REG, NR32_REG, NR33_REG, NR34_REG};
 use super::dac::DAC;
 use super::length_counter::LengthCounter;
+use crate::savestate::{SaveState, Serializer};
 
 pub const CH3_WAVE_MEMORY_SIZE: usize = 16;
 
@@ -72,6 +73,10 @@ pub struct WaveSoundGenerator {
 
     pub length_counter: LengthCounter,
     pub dac: DAC,
+
+    // Digital output of the channel, before the DAC. Read through
+    // PCM34 on CGB.
+    pub pcm: u8,
     machine: Machine,
 }
 
@@ -100,6 +105,7 @@ impl WaveSoundGenerator {
             enabled: false,
             volume_code: 0,
             dac: DAC::new(),
+            pcm: 0,
             wave_recently_read: false,
             sample_buffer: 0,
             machine,
@@ -115,10 +121,16 @@ impl WaveSoundGenerator {
         self.frequency_timer = 0;
         self.enabled = false;
         self.volume_code = 0;
+        self.wave_recently_read = false;
         self.sample_buffer = 0;
         self.dac = DAC::new();
     }
 
+    // Frequency of the played wave in Hz. One period is 32 samples.
+    pub fn frequency_hz(&self) -> f64 {
+        65536.0 / (2048 - self.frequency) as f64
+    }
+
     pub fn read_reg(&self, address: usize) -> u8 {
         match address {
             NR30_REG => {
@@ -139,7 +151,10 @@ impl WaveSoundGenerator {
                     0b1011_1111
                 }
             }
-            _ => panic!("invalid register in channel 3: {}", address),
+            _ => {
+                invalid_access!("Invalid register in channel 3: 0x{:04x}", address);
+                0xFF
+            }
         }
     }
 
@@ -198,7 +213,7 @@ impl WaveSoundGenerator {
                     self.trigger(seq_step);
                 }
             }
-            _ => panic!("invalid register in channel 3: {}", address),
+            _ => invalid_access!("Invalid register in channel 3: 0x{:04x}", address),
         }
     }
 
@@ -229,6 +244,11 @@ impl WaveSoundGenerator {
     }
 
     fn trigger(&mut self, seq_step: u8) {
+        // On DMG, triggering the channel while it reads a sample
+        // corrupts the start of wave RAM. If the byte being read is
+        // one of the first four, it's copied to the first byte.
+        // Otherwise the aligned four bytes it belongs to are copied
+        // to the first four bytes. Test: "10-wave trigger while on"
         match self.machine {
             Machine::GameBoyDMG => {
                 if self.enabled && self.frequency_timer <= 2 && self.dac.powered_on {
@@ -296,6 +316,8 @@ impl WaveSoundGenerator {
             _ => 0,
         };
 
+        self.pcm = if self.enabled { out } else { 0 };
+
         if self.enabled {
             return self.dac.convert(out);
         }
@@ -303,3 +325,85 @@ impl WaveSoundGenerator {
         0
     }
 }
+
+impl SaveState for WaveSoundGenerator {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u16(&mut self.frequency);
+        s.bytes(&mut self.wave);
+        s.bool(&mut self.enabled);
+        s.i16(&mut self.frequency_timer);
+        s.u16(&mut self.wave_position);
+        s.u8(&mut self.volume_code);
+        s.bool(&mut self.wave_recently_read);
+        s.u8(&mut self.sample_buffer);
+        self.length_counter.serialize(s);
+        self.dac.serialize(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAVE: [u8; CH3_WAVE_MEMORY_SIZE] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE,
+        0xFF,
+    ];
+
+    // Power on the DAC and trigger the channel at the given frequency
+    fn trigger(machine: Machine, frequency: u16) -> WaveSoundGenerator {
+        let mut ch = WaveSoundGenerator::new(machine);
+        ch.wave = WAVE;
+        ch.write_reg(NR30_REG, 0x80, 0, true);
+        ch.write_reg(NR33_REG, (frequency & 0xFF) as u8, 0, true);
+        ch.write_reg(NR34_REG, 0x80 | (frequency >> 8) as u8, 0, true);
+        ch
+    }
+
+    fn tick(ch: &mut WaveSoundGenerator, n: usize) {
+        for _ in 0..n {
+            ch.update_4t(false);
+        }
+    }
+
+    #[test]
+    fn test_trigger_corruption() {
+        // A period of four cycles reads one sample per tick once the
+        // channel is running, so the trigger hits a read
+        let mut ch = trigger(Machine::GameBoyDMG, 2046);
+        tick(&mut ch, 11);
+        assert_eq!(ch.wave_position, 9);
+        ch.write_reg(NR34_REG, 0x87, 0, true);
+        assert_eq!(ch.wave[0..4], WAVE[4..8]);
+        assert_eq!(ch.wave[4..], WAVE[4..]);
+
+        // Reading one of the first four bytes only corrupts the first
+        let mut ch = trigger(Machine::GameBoyDMG, 2046);
+        tick(&mut ch, 4);
+        ch.write_reg(NR34_REG, 0x87, 0, true);
+        assert_eq!(ch.wave[0], WAVE[1]);
+        assert_eq!(ch.wave[1..], WAVE[1..]);
+
+        let mut ch = trigger(Machine::GameBoyCGB, 2046);
+        tick(&mut ch, 11);
+        ch.write_reg(NR34_REG, 0x87, 0, true);
+        assert_eq!(ch.wave, WAVE);
+    }
+
+    #[test]
+    fn test_read_while_on() {
+        // The first sample is read on the third tick
+        let mut ch = trigger(Machine::GameBoyDMG, 2045);
+        tick(&mut ch, 3);
+        assert_eq!(ch.read_wave_reg(0xFF3F), WAVE[0]);
+        tick(&mut ch, 1);
+        assert_eq!(ch.read_wave_reg(0xFF3F), 0xFF);
+
+        let mut ch = trigger(Machine::GameBoyCGB, 2045);
+        tick(&mut ch, 4);
+        assert_eq!(ch.read_wave_reg(0xFF3F), WAVE[0]);
+
+        ch.write_reg(NR30_REG, 0x00, 0, true);
+        assert_eq!(ch.read_wave_reg(0xFF3F), WAVE[15]);
+    }
+}
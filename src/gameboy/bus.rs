@@ -0,0 +1,287 @@
+// Address decoding
+//
+// The address space is divided between the devices on the bus. Each
+// device is registered over one or more address ranges, and an access
+// goes to the device its address decodes to. The decoding is a lookup
+// in a table with one entry per address, so that ranges don't have to
+// be aligned to pages: OAM ends at 0xFE9F and high RAM at 0xFFFE.
+//
+// Devices that only need their own state, such as the cartridge and
+// work RAM, implement `MemoryMapped` and are accessed as trait
+// objects. The I/O registers and the interrupt enable register are
+// spread over several components, and are handled by the MMU itself.
+
+use super::emu::Machine;
+use super::mmu::{MemoryMapped, ResetKind};
+use crate::savestate::{SaveState, Serializer};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Device {
+    // ROM and external RAM
+    Cartridge,
+
+    // VRAM and OAM, in the PPU
+    Video,
+
+    // Work RAM and its echo
+    WorkRam,
+
+    // 0xFEA0-0xFEFF, next to OAM
+    Unusable,
+
+    Io,
+    HighRam,
+    InterruptEnable,
+    Unmapped,
+}
+
+pub struct AddressMap {
+    table: Box<[Device]>,
+}
+
+impl Default for AddressMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AddressMap {
+    // An address space where nothing is mapped
+    pub fn new() -> Self {
+        AddressMap {
+            table: vec![Device::Unmapped; 0x10000].into_boxed_slice(),
+        }
+    }
+
+    // The address space of the Game Boy
+    pub fn game_boy() -> Self {
+        let mut map = AddressMap::new();
+        map.map(0x0000, 0x7FFF, Device::Cartridge);
+        map.map(0x8000, 0x9FFF, Device::Video);
+        map.map(0xA000, 0xBFFF, Device::Cartridge);
+        map.map(0xC000, 0xFDFF, Device::WorkRam);
+        map.map(0xFE00, 0xFE9F, Device::Video);
+        map.map(0xFEA0, 0xFEFF, Device::Unusable);
+        map.map(0xFF00, 0xFF7F, Device::Io);
+        map.map(0xFF80, 0xFFFE, Device::HighRam);
+        map.map(0xFFFF, 0xFFFF, Device::InterruptEnable);
+        map
+    }
+
+    // Register `device` from `start` to `end`, both included. Replaces
+    // any device registered there before.
+    pub fn map(&mut self, start: usize, end: usize, device: Device) {
+        self.table[start..=end].fill(device);
+    }
+
+    #[inline]
+    pub fn decode(&self, addr: usize) -> Device {
+        self.table.get(addr).copied().unwrap_or(Device::Unmapped)
+    }
+}
+
+// Fill `data` with the content of RAM at power on: semi-random, or
+// cleared in deterministic mode
+fn power_on(data: &mut [u8], rng: &fastrand::Rng, deterministic: bool) {
+    if deterministic {
+        data.fill(0);
+    } else {
+        for b in data.iter_mut() {
+            *b = rng.u8(..);
+        }
+    }
+}
+
+// RAM without banking, starting at `start`
+pub struct Ram {
+    data: Vec<u8>,
+    start: usize,
+
+    // Generator of the power-on content
+    rng: fastrand::Rng,
+
+    // Clear the content at power on, instead of randomizing it
+    deterministic: bool,
+}
+
+impl Ram {
+    pub fn new(start: usize, size: usize) -> Self {
+        Ram {
+            data: vec![0; size],
+            start,
+            rng: fastrand::Rng::with_seed(0),
+            deterministic: crate::deterministic(),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
+
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+}
+
+impl MemoryMapped for Ram {
+    fn read(&self, addr: usize) -> u8 {
+        self.data[addr - self.start]
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        self.data[addr - self.start] = value;
+    }
+
+    fn reset(&mut self, kind: ResetKind) {
+        if kind == ResetKind::Hard {
+            power_on(&mut self.data, &self.rng, self.deterministic);
+        }
+    }
+}
+
+impl SaveState for Ram {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.bytes(&mut self.data);
+    }
+}
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+
+// Work RAM at 0xC000-0xDFFF, echoed at 0xE000-0xFDFF. The first 4 KB
+// are bank 0. The second 4 KB are bank 1 on the DMG, and on the CGB
+// any of bank 1-7, selected by SVBK. Selecting bank 0 selects bank 1,
+// but SVBK reads back as written.
+pub struct WorkRam {
+    data: Vec<u8>,
+    svbk: u8,
+    rng: fastrand::Rng,
+    deterministic: bool,
+}
+
+impl WorkRam {
+    pub fn new(machine: Machine) -> Self {
+        let banks = match machine {
+            Machine::GameBoyCGB => 8,
+            _ => 2,
+        };
+        WorkRam {
+            data: vec![0; banks * WRAM_BANK_SIZE],
+            svbk: 0,
+            rng: fastrand::Rng::with_seed(0),
+            deterministic: crate::deterministic(),
+        }
+    }
+
+    // All banks, in order
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = fastrand::Rng::with_seed(seed);
+    }
+
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+
+    // Bank mapped at 0xD000-0xDFFF
+    pub fn bank(&self) -> usize {
+        let banks = self.data.len() / WRAM_BANK_SIZE;
+        (self.svbk as usize % banks).max(1)
+    }
+
+    pub fn read_svbk(&self) -> u8 {
+        self.svbk
+    }
+
+    pub fn write_svbk(&mut self, value: u8) {
+        self.svbk = value & 7;
+    }
+
+    fn offset(&self, addr: usize) -> usize {
+        let offset = (addr - 0xC000) % (2 * WRAM_BANK_SIZE);
+        match offset < WRAM_BANK_SIZE {
+            true => offset,
+            false => self.bank() * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE,
+        }
+    }
+}
+
+impl MemoryMapped for WorkRam {
+    fn read(&self, addr: usize) -> u8 {
+        self.data[self.offset(addr)]
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        let offset = self.offset(addr);
+        self.data[offset] = value;
+    }
+
+    fn reset(&mut self, kind: ResetKind) {
+        self.svbk = 0;
+        if kind == ResetKind::Hard {
+            power_on(&mut self.data, &self.rng, self.deterministic);
+        }
+    }
+}
+
+impl SaveState for WorkRam {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.bytes(&mut self.data);
+        s.u8(&mut self.svbk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_boy_map() {
+        let map = AddressMap::game_boy();
+        assert_eq!(map.decode(0x4000), Device::Cartridge);
+        assert_eq!(map.decode(0xE000), Device::WorkRam);
+        assert_eq!(map.decode(0xFE9F), Device::Video);
+        assert_eq!(map.decode(0xFEA0), Device::Unusable);
+        assert_eq!(map.decode(0xFFFE), Device::HighRam);
+        assert_eq!(map.decode(0xFFFF), Device::InterruptEnable);
+        assert_eq!(map.decode(0x10000), Device::Unmapped);
+    }
+
+    #[test]
+    fn test_echo() {
+        let mut wram = WorkRam::new(Machine::GameBoyDMG);
+        wram.write(0xC123, 0x42);
+        wram.write(0xD123, 0x43);
+        assert_eq!(wram.read(0xE123), 0x42);
+        assert_eq!(wram.read(0xF123), 0x43);
+        assert_eq!(wram.bytes()[0x1123], 0x43);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mut wram = WorkRam::new(Machine::GameBoyCGB);
+        for bank in 0..8 {
+            wram.write_svbk(bank);
+            wram.write(0xD000, bank);
+        }
+
+        // Bank 0 selects bank 1
+        assert_eq!(wram.bank(), 7);
+        wram.write_svbk(0);
+        assert_eq!((wram.bank(), wram.read(0xD000)), (1, 1));
+        wram.write_svbk(0xFA);
+        assert_eq!((wram.read_svbk(), wram.read(0xD000)), (2, 2));
+        assert_eq!(wram.read(0xC000), 0);
+
+        // The DMG has a single bank
+        let mut wram = WorkRam::new(Machine::GameBoyDMG);
+        wram.write_svbk(3);
+        assert_eq!(wram.bank(), 1);
+    }
+}
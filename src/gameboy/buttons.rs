@@ -1,3 +1,7 @@
+use super::interrupt::IF_INP_BIT;
+use super::{CLOCK_SPEED, CYCLES_PER_FRAME};
+use crate::savestate::{SaveState, Serializer};
+
 #[derive(Copy, Clone)]
 pub enum ButtonType {
     Up = 64,
@@ -10,10 +14,35 @@ pub enum ButtonType {
     B = 2,
 }
 
+// P1 (0xFF00) is a 2x4 matrix. Writing 0 to bit 4 selects the
+// direction keys, and writing 0 to bit 5 selects the buttons. The
+// lower four bits are the input lines, and read as 0 if a key on any
+// selected row is pressed. With no row selected, they read as 1.
+//
+// The joypad interrupt is requested when an input line goes from high
+// to low, either by pressing a key or by selecting a row in which a
+// key is already pressed.
+//
+// Buttons with autofire are pressed and released every
+// `autofire_period` frames while held. As this is done here, the
+// toggling is recorded in input movies like any other button press.
 pub struct Buttons {
+    // Bit set to 0 for each pressed button: directions in the upper
+    // nibble and buttons in the lower
     button_state: u8,
     p1: u8,
     pub irq: u8,
+
+    // Buttons held by the player, as a mask of `ButtonType` values.
+    // The same as the pressed buttons, except for autofire.
+    held: u8,
+
+    // Buttons with autofire, as a mask of `ButtonType` values
+    pub autofire: u8,
+
+    // Number of frames autofire buttons stay pressed, and then released
+    pub autofire_period: usize,
+    autofire_released: bool,
 }
 
 impl Buttons {
@@ -22,28 +51,81 @@ impl Buttons {
             button_state: 0xff,
             p1: 0xff,
             irq: 0,
+            held: 0,
+            autofire: 0,
+            autofire_period: 2,
+            autofire_released: false,
         }
     }
 
+    // Release all buttons and reset the register, keeping the autofire
+    // settings
+    pub fn reset(&mut self) {
+        *self = Buttons {
+            autofire: self.autofire,
+            autofire_period: self.autofire_period,
+            ..Buttons::new()
+        };
+    }
+
     pub fn handle_press(&mut self, btn: ButtonType) {
-        self.button_state = self.button_state & !(btn as u8);
-        self.update();
-        // println!("Handle Press! {:x} {:x}", self.p1, self.button_state);
+        self.held |= btn as u8;
+        self.apply_held();
     }
 
     pub fn handle_release(&mut self, btn: ButtonType) {
-        self.button_state = self.button_state | btn as u8;
-        self.update();
-        // println!("Handle Release! {:x} {:x}", self.p1, self.button_state);
+        self.held &= !(btn as u8);
+        self.apply_held();
     }
 
     pub fn release_all(&mut self) {
-        self.button_state = 0;
+        self.held = 0;
+        self.apply_held();
+    }
+
+    // Press the held buttons, except autofire buttons in their
+    // released phase
+    fn apply_held(&mut self) {
+        let released = match self.autofire_released {
+            true => self.autofire,
+            false => 0,
+        };
+        self.button_state = !(self.held & !released);
+        self.update();
+    }
+
+    // Toggle autofire buttons for frame `frame`. May be called more
+    // than once per frame.
+    pub fn update_autofire(&mut self, frame: usize) {
+        let released = (frame / self.autofire_period.max(1)) % 2 == 1;
+        if released != self.autofire_released {
+            self.autofire_released = released;
+            self.apply_held();
+        }
+    }
+
+    // Autofire presses per second
+    pub fn autofire_rate(&self) -> f64 {
+        let fps = CLOCK_SPEED as f64 / CYCLES_PER_FRAME as f64;
+        fps / (2 * self.autofire_period.max(1)) as f64
+    }
+
+    // Currently pressed buttons, as a mask of `ButtonType` values
+    pub fn pressed(&self) -> u8 {
+        !self.button_state
+    }
+
+    // Press the buttons in the mask and release all others, without
+    // autofire
+    pub fn set_pressed(&mut self, pressed: u8) {
+        self.held = pressed;
+        self.button_state = !pressed;
         self.update();
     }
 
     pub fn write_p1(&mut self, v: u8) {
         self.p1 = 0xC0 | (v & 0x30) | (self.p1 & 0xF);
+        self.update();
     }
 
     pub fn read_p1(&self) -> u8 {
@@ -51,17 +133,29 @@ impl Buttons {
     }
 
     pub fn update(&mut self) {
-        let mut next = self.p1 & 0xF0;
+        let mut lines = 0x0F;
+
+        if self.p1 & 0x10 == 0 {
+            lines &= self.button_state >> 4;
+        }
 
-        if self.p1 & 0x10 != 0 {
-            next = next | self.button_state & 0x0F;
+        if self.p1 & 0x20 == 0 {
+            lines &= self.button_state & 0x0F;
         }
 
-        if self.p1 & 0x20 != 0 {
-            next = next | (self.button_state >> 4) & 0x0F;
+        if self.p1 & !lines & 0x0F != 0 {
+            self.irq = IF_INP_BIT;
         }
 
-        self.p1 = next;
+        self.p1 = (self.p1 & 0xF0) | lines;
+    }
+}
+
+impl SaveState for Buttons {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u8(&mut self.button_state);
+        s.u8(&mut self.p1);
+        s.u8(&mut self.irq);
     }
 }
 
@@ -101,4 +195,74 @@ mod tests {
         btn.update();
         assert!(btn.read_p1() & SELECT_OR_UP_MASK != 0)
     }
+
+    #[test]
+    fn test_matrix() {
+        let mut btn = Buttons::new();
+        btn.handle_press(ButtonType::Up);
+        btn.handle_press(ButtonType::A);
+
+        // No row selected
+        btn.write_p1(0x30);
+        assert_eq!(btn.read_p1(), 0xFF);
+
+        // Both rows selected
+        btn.write_p1(0x00);
+        assert_eq!(btn.read_p1(), 0xCA);
+
+        btn.release_all();
+        assert_eq!(btn.read_p1(), 0xCF);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut btn = Buttons::new();
+        btn.write_p1(P14_MASK);
+        assert_eq!(btn.irq, 0);
+
+        // Keys on a row that is not selected don't affect the lines
+        btn.handle_press(ButtonType::Right);
+        assert_eq!(btn.irq, 0);
+
+        btn.handle_press(ButtonType::Start);
+        assert_eq!(btn.irq, IF_INP_BIT);
+
+        // Selecting a row in which a key is pressed
+        btn.irq = 0;
+        btn.write_p1(P15_MASK);
+        assert_eq!(btn.irq, IF_INP_BIT);
+
+        // Releasing a key doesn't request an interrupt
+        btn.irq = 0;
+        btn.handle_release(ButtonType::Right);
+        assert_eq!(btn.irq, 0);
+    }
+
+    #[test]
+    fn test_autofire() {
+        let mut btn = Buttons::new();
+        btn.autofire = ButtonType::A as u8;
+        btn.autofire_period = 2;
+        btn.handle_press(ButtonType::A);
+        btn.handle_press(ButtonType::B);
+
+        let pressed: Vec<u8> = (0..6)
+            .map(|frame| {
+                btn.update_autofire(frame);
+                btn.handle_press(ButtonType::A);
+                btn.pressed()
+            })
+            .collect();
+        assert_eq!(pressed, [3, 3, 2, 2, 3, 3]);
+
+        // Autofire buttons are released when no longer held
+        btn.handle_release(ButtonType::A);
+        btn.update_autofire(8);
+        assert_eq!(btn.pressed(), 2);
+
+        // The settings are kept on reset
+        btn.reset();
+        assert_eq!(btn.pressed(), 0);
+        assert_eq!(btn.autofire, ButtonType::A as u8);
+    }
 }
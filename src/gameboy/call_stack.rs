@@ -0,0 +1,238 @@
+// Shadow call stack
+//
+// The stack in memory holds return addresses mixed with pushed
+// registers, so the calls are tracked separately as instructions are
+// executed: CALL, RST and interrupts push a frame, and RET and RETI
+// pop it.
+//
+// A return to another address than the one pushed by the matching
+// call means that the return address on the stack has been
+// overwritten. This is reported as stack corruption. Returns that
+// don't match a call at all, such as "PUSH HL, RET" used as a jump,
+// are ignored. Frames below the stack pointer at a return have been
+// abandoned, for example by popping the return address, and are
+// dropped.
+
+use super::registers::Registers;
+
+// Frames beyond this depth are dropped, oldest first. Programs that
+// never return, and instead reset the stack pointer, would otherwise
+// grow the stack forever.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CallFrame {
+    pub kind: FrameKind,
+
+    // Address of the call instruction, or of the instruction that
+    // was about to be executed when the interrupt was taken
+    pub caller: u16,
+
+    // Address of the called routine
+    pub target: u16,
+
+    pub return_addr: u16,
+
+    // Stack pointer after the return address was pushed
+    pub sp: u16,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct StackCorruption {
+    // Address of the return instruction
+    pub pc: u16,
+
+    // Return address pushed by the call, and the address returned to
+    pub expected: u16,
+    pub actual: u16,
+}
+
+pub struct CallStack {
+    pub frames: Vec<CallFrame>,
+
+    // Number of detected corruptions, and the most recent one
+    pub corruptions: usize,
+    pub last_corruption: Option<StackCorruption>,
+}
+
+impl Default for CallStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_call(op: u8) -> bool {
+    matches!(op, 0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC)
+}
+
+fn is_rst(op: u8) -> bool {
+    op & 0xC7 == 0xC7
+}
+
+fn is_return(op: u8) -> bool {
+    matches!(op, 0xC9 | 0xD9 | 0xC0 | 0xC8 | 0xD0 | 0xD8)
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack {
+            frames: Vec::new(),
+            corruptions: 0,
+            last_corruption: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = CallStack::new();
+    }
+
+    // Innermost frame
+    pub fn top(&self) -> Option<&CallFrame> {
+        self.frames.last()
+    }
+
+    pub fn push(&mut self, frame: CallFrame) {
+        if self.frames.len() == MAX_CALL_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    // Update the stack after executing the instruction `op` at `pc`.
+    // `sp` is the stack pointer before the instruction, and `reg` the
+    // registers after it. Conditional calls and returns only change
+    // the stack pointer when taken.
+    pub fn after_op(&mut self, op: u8, pc: u16, sp: u16, reg: &Registers) {
+        if reg.sp == sp.wrapping_sub(2) && (is_call(op) || is_rst(op)) {
+            let (kind, len) = match is_call(op) {
+                true => (FrameKind::Call, 3),
+                false => (FrameKind::Rst, 1),
+            };
+            self.push(CallFrame {
+                kind,
+                caller: pc,
+                target: reg.pc,
+                return_addr: pc.wrapping_add(len),
+                sp: reg.sp,
+            });
+        } else if reg.sp == sp.wrapping_add(2) && is_return(op) {
+            self.ret(pc, sp, reg.pc);
+        }
+    }
+
+    // An interrupt was taken, and `return_addr` pushed on the stack
+    pub fn interrupt(&mut self, return_addr: u16, reg: &Registers) {
+        self.push(CallFrame {
+            kind: FrameKind::Interrupt,
+            caller: return_addr,
+            target: reg.pc,
+            return_addr,
+            sp: reg.sp,
+        });
+    }
+
+    fn ret(&mut self, pc: u16, sp: u16, target: u16) {
+        while self.frames.last().is_some_and(|f| f.sp < sp) {
+            self.frames.pop();
+        }
+
+        match self.frames.last() {
+            Some(frame) if frame.sp == sp => {
+                if frame.return_addr != target {
+                    self.corruptions += 1;
+                    self.last_corruption = Some(StackCorruption {
+                        pc,
+                        expected: frame.return_addr,
+                        actual: target,
+                    });
+                }
+                self.frames.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regs(pc: u16, sp: u16) -> Registers {
+        let mut reg = Registers::new();
+        reg.pc = pc;
+        reg.sp = sp;
+        reg
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let mut stack = CallStack::new();
+
+        // CALL 0x2000 at 0x0150, RST 38 at 0x2000
+        stack.after_op(0xCD, 0x0150, 0xD000, &regs(0x2000, 0xCFFE));
+        stack.after_op(0xFF, 0x2000, 0xCFFE, &regs(0x0038, 0xCFFC));
+        assert_eq!(stack.frames.len(), 2);
+        assert_eq!(stack.top().unwrap().kind, FrameKind::Rst);
+        assert_eq!(stack.top().unwrap().return_addr, 0x2001);
+
+        // Untaken conditional call
+        stack.after_op(0xC4, 0x0038, 0xCFFC, &regs(0x003B, 0xCFFC));
+        assert_eq!(stack.frames.len(), 2);
+
+        stack.after_op(0xC9, 0x003B, 0xCFFC, &regs(0x2001, 0xCFFE));
+        stack.after_op(0xC9, 0x2001, 0xCFFE, &regs(0x0153, 0xD000));
+        assert!(stack.frames.is_empty());
+        assert_eq!(stack.corruptions, 0);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut stack = CallStack::new();
+        stack.interrupt(0x0200, &regs(0x0040, 0xCFFE));
+        assert_eq!(stack.top().unwrap().kind, FrameKind::Interrupt);
+        stack.after_op(0xD9, 0x0045, 0xCFFE, &regs(0x0200, 0xD000));
+        assert!(stack.frames.is_empty());
+    }
+
+    #[test]
+    fn test_corruption() {
+        let mut stack = CallStack::new();
+        stack.after_op(0xCD, 0x0150, 0xD000, &regs(0x2000, 0xCFFE));
+
+        // PUSH HL, RET is a jump, not a return from the call
+        stack.after_op(0xC9, 0x2001, 0xCFFC, &regs(0x3000, 0xCFFE));
+        assert_eq!(stack.frames.len(), 1);
+
+        stack.after_op(0xC9, 0x3000, 0xCFFE, &regs(0x1234, 0xD000));
+        assert!(stack.frames.is_empty());
+        assert_eq!(stack.corruptions, 1);
+        assert_eq!(
+            stack.last_corruption,
+            Some(StackCorruption {
+                pc: 0x3000,
+                expected: 0x0153,
+                actual: 0x1234,
+            })
+        );
+    }
+
+    #[test]
+    fn test_abandoned_frames() {
+        let mut stack = CallStack::new();
+        stack.after_op(0xCD, 0x0150, 0xD000, &regs(0x2000, 0xCFFE));
+        stack.after_op(0xCD, 0x2000, 0xCFFE, &regs(0x3000, 0xCFFC));
+
+        // The inner return address is popped, and the outer call
+        // returns normally
+        stack.after_op(0xC9, 0x3000, 0xCFFE, &regs(0x0153, 0xD000));
+        assert!(stack.frames.is_empty());
+        assert_eq!(stack.corruptions, 0);
+    }
+}
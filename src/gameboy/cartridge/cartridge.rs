@@ -1,22 +1,56 @@
 use super::{cartridge_header::CartridgeHeader, cartridge_type::CartridgeType};
 
-use super::super::mmu::MemoryMapped;
+use super::super::mmu::{MemoryMapped, ResetKind};
+use crate::savestate::{SaveState, Serializer};
 
-pub trait Cartridge: MemoryMapped {
+pub trait Cartridge: MemoryMapped + SaveState + Send {
     fn cartridge_type(&self) -> CartridgeType;
     fn header(&self) -> &CartridgeHeader;
     fn read_abs(&self, address: usize) -> u8;
+
+    // ROM bank mapped at 0x4000-0x7FFF
+    fn rom_bank(&self) -> usize;
+
+    // Cartridge RAM bank mapped at 0xA000-0xBFFF
+    fn ram_bank(&self) -> usize {
+        0
+    }
+
+    // Cartridge RAM, if there is any
+    fn ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // Clock registers (seconds, minutes, hours, day low and day high),
+    // if the cartridge has a real-time clock
+    fn rtc_registers(&self) -> Option<[u8; 5]> {
+        None
+    }
+
+    fn set_rtc_registers(&mut self, _registers: [u8; 5]) {}
+
+    // Registers and state of the memory bank controller, by name, for
+    // the debugger
+    fn debug_state(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
 }
 
+// An empty cartridge slot. Nothing drives the data bus, so reads are
+// pulled up to 0xFF.
 pub struct NoCartridge {}
 
 impl MemoryMapped for NoCartridge {
     fn read(&self, _address: usize) -> u8 {
-        0
+        0xFF
     }
 
     fn write(&mut self, _address: usize, _value: u8) {}
-    fn reset(&mut self) {}
+    fn reset(&mut self, _kind: ResetKind) {}
 }
 
 impl Cartridge for NoCartridge {
@@ -25,10 +59,18 @@ impl Cartridge for NoCartridge {
     }
 
     fn read_abs(&self, _address: usize) -> u8 {
-        0
+        0xFF
+    }
+
+    fn rom_bank(&self) -> usize {
+        1
     }
 
     fn header(&self) -> &CartridgeHeader {
         panic!("Can't return header when there's no cartridge in place")
     }
 }
+
+impl SaveState for NoCartridge {
+    fn serialize(&mut self, _s: &mut Serializer) {}
+}
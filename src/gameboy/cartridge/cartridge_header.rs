@@ -1,13 +1,121 @@
+use std::fmt;
+
 pub const ROM_BANK_SIZE: usize = 16384;
 pub const RAM_BANK_SIZE: usize = 8192;
 
+// Logo at 0x104-0x133, compared with the one in the boot ROM
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// Problems found when verifying a ROM. The boot ROM locks up if the
+// logo or the header checksum is wrong, so those are signs of a
+// corrupt dump. The global checksum isn't checked by the hardware,
+// and is wrong in many homebrew ROMs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderWarning {
+    Logo,
+    HeaderChecksum { expected: u8, actual: u8 },
+    GlobalChecksum { expected: u16, actual: u16 },
+    Truncated { size: usize, expected: usize },
+}
+
+impl HeaderWarning {
+    // True if the ROM is unlikely to run at all
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, HeaderWarning::GlobalChecksum { .. })
+    }
+}
+
+impl fmt::Display for HeaderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderWarning::Logo => write!(f, "Nintendo logo doesn't match"),
+            HeaderWarning::HeaderChecksum { expected, actual } => write!(
+                f,
+                "Header checksum is 0x{:02X}, but the header sums to 0x{:02X}",
+                expected, actual
+            ),
+            HeaderWarning::GlobalChecksum { expected, actual } => write!(
+                f,
+                "Global checksum is 0x{:04X}, but the ROM sums to 0x{:04X}",
+                expected, actual
+            ),
+            HeaderWarning::Truncated { size, expected } => write!(
+                f,
+                "ROM is {} bytes, but the header says {} bytes",
+                size, expected
+            ),
+        }
+    }
+}
+
+// Check the logo, checksums and size of a ROM
+pub fn verify_rom(rom: &[u8]) -> Vec<HeaderWarning> {
+    if rom.len() < 0x150 {
+        return vec![HeaderWarning::Truncated {
+            size: rom.len(),
+            expected: 2 * ROM_BANK_SIZE,
+        }];
+    }
+
+    let mut warnings = Vec::new();
+    if rom[0x104..0x134] != NINTENDO_LOGO {
+        warnings.push(HeaderWarning::Logo);
+    }
+
+    let header_checksum = rom[0x134..=0x14C]
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_sub(*b).wrapping_sub(1));
+    if header_checksum != rom[0x14D] {
+        warnings.push(HeaderWarning::HeaderChecksum {
+            expected: rom[0x14D],
+            actual: header_checksum,
+        });
+    }
+
+    let expected = ((rom[0x14E] as u16) << 8) | rom[0x14F] as u16;
+    let global_checksum = rom
+        .iter()
+        .enumerate()
+        .filter(|(addr, _)| *addr != 0x14E && *addr != 0x14F)
+        .fold(0u16, |sum, (_, b)| sum.wrapping_add(*b as u16));
+    if global_checksum != expected {
+        warnings.push(HeaderWarning::GlobalChecksum {
+            expected,
+            actual: global_checksum,
+        });
+    }
+
+    if rom[0x148] <= 8 && rom.len() < (2 * ROM_BANK_SIZE) << rom[0x148] {
+        warnings.push(HeaderWarning::Truncated {
+            size: rom.len(),
+            expected: (2 * ROM_BANK_SIZE) << rom[0x148],
+        });
+    }
+
+    warnings
+}
+
 pub struct CartridgeHeader {
+    pub title: String,
     pub licensee_code: [u8; 2],
     pub old_licensee_code: u8,
     pub checksum: u8,
     pub global_checksum: u16,
     pub sgb_features: bool,
     pub cartridge_type: u8,
+
+    // Sum of the title bytes (0x134-0x143) and the fourth letter of
+    // the title, used by the CGB boot ROM to colorize DMG games
+    pub title_checksum: u8,
+    pub title_fourth_letter: u8,
+
+    // Bit 7 is set for games that support CGB features
+    pub cgb_flag: u8,
+
     pub rom_bank_count: usize,
     pub rom_size: usize,
     pub ram_bank_count: usize,
@@ -23,26 +131,51 @@ impl CartridgeHeader {
             _ => 0,
         };
 
-        let ram_bank_count = match header[0x0149] {
-            0 => 0,
-            2 => 1,
-            3 => 4,
-            4 => 16,
-            5 => 8,
+        // Code 1 is unofficial, but used by some homebrew and tools
+        // for 2 KiB of RAM, less than a full bank
+        let ram_size = match header[0x0149] {
+            1 => 2 * 1024,
+            2 => RAM_BANK_SIZE,
+            3 => 4 * RAM_BANK_SIZE,
+            4 => 16 * RAM_BANK_SIZE,
+            5 => 8 * RAM_BANK_SIZE,
             _ => 0,
         };
 
+        // The last byte of the title is the CGB flag in games made for
+        // the CGB. Unused bytes are zero.
+        let title_end = match header[0x143] & 0x80 {
+            0 => 0x144,
+            _ => 0x143,
+        };
+        let title = header[0x134..title_end]
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                true => *b as char,
+                false => '?',
+            })
+            .collect::<String>();
+
+        let title_checksum = header[0x134..=0x143]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b));
+
         CartridgeHeader {
+            title: title.trim_end().to_string(),
             licensee_code,
             old_licensee_code: header[0x14B],
             checksum: header[0x14D],
             global_checksum: ((header[0x14E] as u16) << 8) | header[0x14F] as u16,
             sgb_features: header[0x146] == 0x03,
             cartridge_type: header[0x147],
+            title_checksum,
+            title_fourth_letter: header[0x137],
+            cgb_flag: header[0x143],
             rom_bank_count,
-            ram_bank_count,
+            ram_bank_count: ram_size.div_ceil(RAM_BANK_SIZE),
             rom_size: rom_bank_count * ROM_BANK_SIZE,
-            ram_size: ram_bank_count * RAM_BANK_SIZE,
+            ram_size,
         }
     }
 
@@ -114,4 +247,81 @@ impl CartridgeHeader {
         }
         .to_string()
     }
+
+    // Published by Nintendo. The new licensee code is only used when
+    // the old one is 0x33.
+    pub fn nintendo_licensed(&self) -> bool {
+        match self.old_licensee_code {
+            0x01 => true,
+            0x33 => &self.licensee_code == b"01",
+            _ => false,
+        }
+    }
+
+    // A game made for the DMG, without CGB features
+    pub fn dmg_only(&self) -> bool {
+        self.cgb_flag & 0x80 == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x134..0x13A].copy_from_slice(b"RUSTGB");
+        rom[0x14D] = 0x10;
+        rom[0x14E] = 0x17;
+        rom[0x14F] = 0x2D;
+        rom
+    }
+
+    #[test]
+    fn test_verify_rom() {
+        assert!(verify_rom(&rom()).is_empty());
+
+        let mut bad = rom();
+        bad[0x104] = 0;
+        bad[0x14D] = 0x11;
+        assert_eq!(
+            verify_rom(&bad),
+            [
+                HeaderWarning::Logo,
+                HeaderWarning::HeaderChecksum {
+                    expected: 0x11,
+                    actual: 0x10
+                },
+                HeaderWarning::GlobalChecksum {
+                    expected: 0x172D,
+                    actual: 0x1660
+                },
+            ]
+        );
+
+        assert!(!verify_rom(&bad)[2].is_fatal());
+
+        let mut truncated = rom();
+        truncated[0x148] = 1;
+        let warnings = verify_rom(&truncated);
+        assert_eq!(
+            warnings.last(),
+            Some(&HeaderWarning::Truncated {
+                size: 0x8000,
+                expected: 0x10000
+            })
+        );
+        assert!(warnings.last().unwrap().is_fatal());
+        assert!(verify_rom(&[0; 0x100])[0].is_fatal());
+    }
+
+    #[test]
+    fn test_title() {
+        let mut rom = rom();
+        assert_eq!(CartridgeHeader::from_header(&rom).title, "RUSTGB");
+
+        rom[0x134..0x144].copy_from_slice(b"POKEMON CRYSTAL\xC0");
+        assert_eq!(CartridgeHeader::from_header(&rom).title, "POKEMON CRYSTAL");
+    }
 }
@@ -1,3 +1,4 @@
+use super::cartridge_header::{CartridgeHeader, RAM_BANK_SIZE};
 use super::is_mbc1_multicart;
 use crate::utils::VecExt;
 
@@ -143,6 +144,15 @@ impl CartridgeType {
         }
     }
 
+    // True if cartridges of this type can be loaded
+    pub fn is_supported(&self) -> bool {
+        use self::CartridgeType::*;
+        matches!(
+            self,
+            NoMBC { .. } | MBC1 { .. } | MBC2 { .. } | MBC3 { .. } | MBC5 { .. }
+        )
+    }
+
     pub fn max_rom_size(&self) -> usize {
         use self::CartridgeType::*;
         match self {
@@ -171,8 +181,74 @@ impl CartridgeType {
         }
     }
 
-    #[allow(dead_code)]
-    fn has_rtc(&self) -> bool {
+    // The size of RAM to allocate for the cartridge. The RAM size in the
+    // header doesn't always agree with the cartridge type, so a warning
+    // is printed and a size that the MBC can address safely is used.
+    pub fn effective_ram_size(&self, header: &CartridgeHeader) -> usize {
+        use self::CartridgeType::*;
+        let max = self.max_ram_size();
+        let has_ram = match self {
+            NoMBC { ram, .. } | MBC1 { ram, .. } | MBC3 { ram, .. } | MBC5 { ram, .. } => *ram,
+            _ => true,
+        };
+
+        match (self, header.ram_size) {
+            // MBC2 has 512 x 4 bits of built-in RAM, and the header
+            // should report no RAM
+            (MBC2 { .. }, 0) => max,
+            (MBC2 { .. }, size) => {
+                println!(
+                    "Warning: header reports {} bytes of RAM, but MBC2 has {} bytes built-in",
+                    size, max
+                );
+                max
+            }
+            (_, 0) if has_ram => {
+                println!(
+                    "Warning: {} but header reports no RAM, using {} bytes",
+                    self.to_string(),
+                    RAM_BANK_SIZE
+                );
+                RAM_BANK_SIZE
+            }
+            (_, size) if !has_ram && size > 0 => {
+                println!(
+                    "Warning: header reports {} bytes of RAM, but the cartridge type has none",
+                    size
+                );
+                0
+            }
+            (_, size) if size > max => {
+                println!(
+                    "Warning: header reports {} bytes of RAM, but {} supports at most {} bytes",
+                    size,
+                    self.to_string(),
+                    max
+                );
+                max
+            }
+            // RAM smaller than a bank is mirrored, which is the same as
+            // allocating a full bank as long as nothing depends on the
+            // mirroring
+            (_, size) => size.div_ceil(RAM_BANK_SIZE) * RAM_BANK_SIZE,
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        use self::CartridgeType::*;
+        match self {
+            NoMBC { bat, .. } => *bat,
+            MBC1 { bat, .. } => *bat,
+            MBC2 { bat } => *bat,
+            MBC3 { bat, .. } => *bat,
+            MBC5 { bat, .. } => *bat,
+            MMM01 { bat, .. } => *bat,
+            MBC7 | PocketCamera | HuC1 | HuC3 => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_rtc(&self) -> bool {
         use self::CartridgeType::*;
         match self {
             MBC3 { rtc, .. } => *rtc,
@@ -180,3 +256,43 @@ impl CartridgeType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(cartridge_type: u8, ram_size_code: u8) -> CartridgeHeader {
+        let mut rom = vec![0; 0x150];
+        rom[0x147] = cartridge_type;
+        rom[0x149] = ram_size_code;
+        CartridgeHeader::from_header(&rom)
+    }
+
+    #[test]
+    fn test_effective_ram_size() {
+        let mbc1 = CartridgeType::MBC1 {
+            ram: true,
+            bat: false,
+            multicart: false,
+        };
+        assert_eq!(mbc1.effective_ram_size(&header(0x02, 3)), 32 * 1024);
+
+        // Missing, too small and too large RAM sizes
+        assert_eq!(mbc1.effective_ram_size(&header(0x02, 0)), RAM_BANK_SIZE);
+        assert_eq!(mbc1.effective_ram_size(&header(0x02, 1)), RAM_BANK_SIZE);
+        assert_eq!(mbc1.effective_ram_size(&header(0x02, 4)), 32 * 1024);
+
+        // RAM in the header of a cartridge type without RAM
+        let mbc5 = CartridgeType::MBC5 {
+            ram: false,
+            bat: false,
+            rumble: false,
+        };
+        assert_eq!(mbc5.effective_ram_size(&header(0x19, 3)), 0);
+
+        // MBC2 RAM is built-in
+        let mbc2 = CartridgeType::MBC2 { bat: true };
+        assert_eq!(mbc2.effective_ram_size(&header(0x06, 0)), 512);
+        assert_eq!(mbc2.effective_ram_size(&header(0x06, 2)), 512);
+    }
+}
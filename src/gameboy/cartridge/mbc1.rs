@@ -1,8 +1,11 @@
-use super::super::mmu::MemoryMapped;
+use super::super::mmu::{MemoryMapped, ResetKind};
 use crate::conv;
+use crate::savestate::{SaveState, Serializer};
 
 use super::{
-    cartridge::Cartridge, cartridge_header::CartridgeHeader, cartridge_type::CartridgeType,
+    cartridge::Cartridge,
+    cartridge_header::{CartridgeHeader, RAM_BANK_SIZE},
+    cartridge_type::CartridgeType,
 };
 
 pub struct MBC1 {
@@ -22,6 +25,7 @@ pub struct MBC1 {
     pub mode: u8,
 
     // Meta
+    ram_bank_count: usize,
     pub cartridge_type: CartridgeType,
     header: CartridgeHeader,
 }
@@ -35,7 +39,8 @@ impl MBC1 {
             *src = *dst
         }
 
-        let ram = match header.ram_size {
+        let ram_size = cartridge_type.effective_ram_size(&header);
+        let ram = match ram_size {
             0 => None,
             sz => Some(vec![0; sz].into_boxed_slice()),
         };
@@ -50,11 +55,12 @@ impl MBC1 {
             bank1: 0,
             bank2: 0,
             mode: 0,
+            ram_bank_count: ram_size / RAM_BANK_SIZE,
             cartridge_type,
             header,
         };
 
-        cartridge.reset();
+        cartridge.reset(ResetKind::Hard);
         cartridge
     }
 
@@ -69,7 +75,7 @@ impl MBC1 {
     }
 
     pub fn selected_ram_bank(&self) -> usize {
-        let bank_count = self.header.ram_bank_count;
+        let bank_count = self.ram_bank_count;
         let bank_mask = if bank_count > 0 {
             (bank_count - 1) as u8
         } else {
@@ -164,7 +170,14 @@ impl MemoryMapped for MBC1 {
         }
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self, kind: ResetKind) {
+        // Without a battery, the content of RAM is lost on power cycle
+        if kind == ResetKind::Hard && !self.cartridge_type.has_battery() {
+            if let Some(ram) = &mut self.ram {
+                ram.fill(0);
+            }
+        }
+
         self.ram_enabled = false;
         self.bank1 = 1;
         self.bank2 = 0;
@@ -178,6 +191,37 @@ impl Cartridge for MBC1 {
         return self.rom[address];
     }
 
+    fn rom_bank(&self) -> usize {
+        self.rom_offset_0x4000_0x7fff >> 14
+    }
+
+    fn ram_bank(&self) -> usize {
+        self.ram_offset / RAM_BANK_SIZE
+    }
+
+    fn debug_state(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("BANK1", format!("{:02X}", self.bank1)),
+            ("BANK2", format!("{:02X}", self.bank2)),
+            ("Mode", format!("{}", self.mode)),
+            ("RAM enabled", format!("{}", self.ram_enabled)),
+            (
+                "ROM bank 0x0000",
+                format!("{:02X}", self.rom_offset_0x0000_0x3fff >> 14),
+            ),
+            ("ROM bank 0x4000", format!("{:02X}", self.rom_bank())),
+            ("RAM bank", format!("{:02X}", self.ram_bank())),
+        ]
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        self.ram.as_deref()
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.ram.as_deref_mut()
+    }
+
     fn cartridge_type(&self) -> CartridgeType {
         return self.cartridge_type;
     }
@@ -186,3 +230,18 @@ impl Cartridge for MBC1 {
         &self.header
     }
 }
+
+impl SaveState for MBC1 {
+    fn serialize(&mut self, s: &mut Serializer) {
+        if let Some(ref mut ram) = self.ram {
+            s.bytes(ram);
+        }
+        s.usize(&mut self.rom_offset_0x0000_0x3fff);
+        s.usize(&mut self.rom_offset_0x4000_0x7fff);
+        s.usize(&mut self.ram_offset);
+        s.bool(&mut self.ram_enabled);
+        s.u8(&mut self.bank1);
+        s.u8(&mut self.bank2);
+        s.u8(&mut self.mode);
+    }
+}
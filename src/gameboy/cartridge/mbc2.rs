@@ -1,7 +1,8 @@
-use super::super::mmu::MemoryMapped;
+use super::super::mmu::{MemoryMapped, ResetKind};
 use super::{
     cartridge::Cartridge, cartridge_header::CartridgeHeader, cartridge_type::CartridgeType,
 };
+use crate::savestate::{SaveState, Serializer};
 
 pub struct MBC2 {
     // Memory buffers
@@ -29,9 +30,8 @@ impl MBC2 {
             *src = *dst
         }
 
-        // Note: the RAM size reported in the header seems to be 0 bytes,
-        // but MBC2 always have 512 x 4 bit RAM.
-        let ram = vec![0; cartridge_type.max_ram_size()].into_boxed_slice();
+        // MBC2 always has 512 x 4 bits of RAM, whatever the header says
+        let ram = vec![0; cartridge_type.effective_ram_size(&header)].into_boxed_slice();
 
         let mut cartridge = MBC2 {
             rom,
@@ -43,7 +43,7 @@ impl MBC2 {
             header,
         };
 
-        cartridge.reset();
+        cartridge.reset(ResetKind::Hard);
         cartridge
     }
 
@@ -62,6 +62,25 @@ impl Cartridge for MBC2 {
         self.rom[address]
     }
 
+    fn rom_bank(&self) -> usize {
+        self.rom_offset_0x4000_0x7fff >> 14
+    }
+
+    fn debug_state(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ROM bank", format!("{:02X}", self.bank)),
+            ("RAM enabled", format!("{}", self.ram_enabled)),
+        ]
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        Some(&self.ram)
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.ram)
+    }
+
     fn header(&self) -> &CartridgeHeader {
         &self.header
     }
@@ -100,9 +119,23 @@ impl MemoryMapped for MBC2 {
         }
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self, kind: ResetKind) {
+        // Without a battery, the content of RAM is lost on power cycle
+        if kind == ResetKind::Hard && !self.cartridge_type.has_battery() {
+            self.ram.fill(0);
+        }
+
         self.ram_enabled = false;
         self.bank = 1;
         self.update_offsets();
     }
 }
+
+impl SaveState for MBC2 {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.bytes(&mut self.ram);
+        s.usize(&mut self.rom_offset_0x4000_0x7fff);
+        s.bool(&mut self.ram_enabled);
+        s.u8(&mut self.bank);
+    }
+}
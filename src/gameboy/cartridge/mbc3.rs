@@ -1,4 +1,5 @@
-use super::super::mmu::MemoryMapped;
+use super::super::mmu::{MemoryMapped, ResetKind};
+use crate::savestate::{SaveState, Serializer};
 
 use super::{
     cartridge::Cartridge,
@@ -26,6 +27,7 @@ struct RTC {
 // - Resetting the day counter is also not implemented at al
 // - As the day counter only returns number of days since epoch % 512,
 //   the carry bit will never be set
+// - In deterministic mode the clock doesn't run at all
 impl RTC {
     fn new() -> Self {
         RTC {
@@ -39,6 +41,10 @@ impl RTC {
     }
 
     fn latch(&mut self) {
+        if crate::deterministic() {
+            return;
+        }
+
         let now = chrono::Local::now();
         self.second = now.second() as u8;
         self.minute = now.minute() as u8;
@@ -65,6 +71,23 @@ impl RTC {
         }
     }
 
+    fn registers(&self) -> [u8; 5] {
+        let mut registers = [0; 5];
+        for (i, r) in registers.iter_mut().enumerate() {
+            *r = self.read_register(0x08 + i as u8);
+        }
+        registers
+    }
+
+    fn set_registers(&mut self, registers: [u8; 5]) {
+        let [second, minute, hour, day_low, day_high] = registers;
+        self.second = second;
+        self.minute = minute;
+        self.hour = hour;
+        self.day_counter = ((day_high as u16 & 1) << 8) | day_low as u16;
+        self.halted = day_high & 0b0100_0000 != 0;
+    }
+
     fn write_latch(&mut self, value: u8) {
         match value {
             0 => self.prep_latch = true,
@@ -115,6 +138,7 @@ pub struct MBC3 {
     register_selection: u8,
 
     // Meta
+    ram_bank_count: usize,
     pub cartridge_type: CartridgeType,
     header: CartridgeHeader,
 }
@@ -128,7 +152,8 @@ impl MBC3 {
             *src = *dst
         }
 
-        let ram = match header.ram_size {
+        let ram_size = cartridge_type.effective_ram_size(&header);
+        let ram = match ram_size {
             0 => None,
             sz => Some(vec![0; sz].into_boxed_slice()),
         };
@@ -147,11 +172,12 @@ impl MBC3 {
             rom_bank: 1,
             register_selection: 0,
             aux_enabled: false,
+            ram_bank_count: ram_size / RAM_BANK_SIZE,
             cartridge_type,
             header,
         };
 
-        cartridge.reset();
+        cartridge.reset(ResetKind::Hard);
         cartridge
     }
 
@@ -179,7 +205,7 @@ impl MBC3 {
         let rom_mask = self.header.rom_bank_count - 1;
         self.rom_offset = (self.rom_bank as usize & rom_mask) * ROM_BANK_SIZE;
 
-        let bank_count = self.header.ram_bank_count;
+        let bank_count = self.ram_bank_count;
         let ram_mask = if bank_count > 0 { bank_count - 1 } else { 0 };
         self.ram_offset = (self.register_selection as usize & ram_mask) * RAM_BANK_SIZE;
     }
@@ -241,9 +267,13 @@ impl MemoryMapped for MBC3 {
         }
     }
 
-    fn reset(&mut self) {
-        if let Some(ram) = &mut self.ram {
-            ram.fill(0);
+    fn reset(&mut self, kind: ResetKind) {
+        // Without a battery, the content of RAM is lost on power cycle.
+        // The RTC always has a battery, so it keeps running.
+        if kind == ResetKind::Hard && !self.cartridge_type.has_battery() {
+            if let Some(ram) = &mut self.ram {
+                ram.fill(0);
+            }
         }
 
         self.rom_bank = 1;
@@ -265,4 +295,77 @@ impl Cartridge for MBC3 {
     fn read_abs(&self, address: usize) -> u8 {
         return self.rom[address];
     }
+
+    fn rom_bank(&self) -> usize {
+        self.rom_offset / ROM_BANK_SIZE
+    }
+
+    fn ram_bank(&self) -> usize {
+        self.ram_offset / RAM_BANK_SIZE
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        self.ram.as_deref()
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.ram.as_deref_mut()
+    }
+
+    fn rtc_registers(&self) -> Option<[u8; 5]> {
+        match self.rtc {
+            Some(ref rtc) if self.cartridge_type.has_rtc() => Some(rtc.registers()),
+            _ => None,
+        }
+    }
+
+    fn set_rtc_registers(&mut self, registers: [u8; 5]) {
+        if let Some(ref mut rtc) = self.rtc {
+            rtc.set_registers(registers);
+        }
+    }
+
+    fn debug_state(&self) -> Vec<(&'static str, String)> {
+        // 0x00-0x07 select a RAM bank, and 0x08-0x0C a clock register
+        let selected = match self.register_selection {
+            0x08..=0x0C => format!("RTC {:02X}", self.register_selection),
+            _ => format!("RAM bank {:02X}", self.ram_bank()),
+        };
+        let mut state = vec![
+            ("ROM bank", format!("{:02X}", self.rom_bank)),
+            ("Selected", selected),
+            ("RAM/RTC enabled", format!("{}", self.aux_enabled)),
+        ];
+        if let (Some(rtc), true) = (&self.rtc, self.cartridge_type.has_rtc()) {
+            let [second, minute, hour, day_low, day_high] = rtc.registers();
+            let day = ((day_high as u16 & 1) << 8) | day_low as u16;
+            state.push((
+                "RTC time",
+                format!("{}d {:02}:{:02}:{:02}", day, hour, minute, second),
+            ));
+            state.push(("RTC halted", format!("{}", rtc.halted)));
+        }
+        state
+    }
+}
+
+impl SaveState for MBC3 {
+    fn serialize(&mut self, s: &mut Serializer) {
+        if let Some(ref mut ram) = self.ram {
+            s.bytes(ram);
+        }
+        if let Some(ref mut rtc) = self.rtc {
+            s.u8(&mut rtc.second);
+            s.u8(&mut rtc.minute);
+            s.u8(&mut rtc.hour);
+            s.u16(&mut rtc.day_counter);
+            s.bool(&mut rtc.halted);
+            s.bool(&mut rtc.prep_latch);
+        }
+        s.usize(&mut self.rom_offset);
+        s.usize(&mut self.ram_offset);
+        s.u8(&mut self.rom_bank);
+        s.bool(&mut self.aux_enabled);
+        s.u8(&mut self.register_selection);
+    }
 }
@@ -1,7 +1,8 @@
-use super::super::mmu::MemoryMapped;
+use super::super::mmu::{MemoryMapped, ResetKind};
 use super::cartridge::Cartridge;
 use super::cartridge_header::{CartridgeHeader, RAM_BANK_SIZE, ROM_BANK_SIZE};
 use super::cartridge_type::CartridgeType;
+use crate::savestate::{SaveState, Serializer};
 
 pub struct MBC5 {
     // Memory buffers
@@ -18,6 +19,7 @@ pub struct MBC5 {
     pub rom_bank: usize,
 
     // Meta
+    ram_bank_count: usize,
     pub cartridge_type: CartridgeType,
     header: CartridgeHeader,
 }
@@ -31,7 +33,8 @@ impl MBC5 {
             *src = *dst
         }
 
-        let ram = match header.ram_size {
+        let ram_size = cartridge_type.effective_ram_size(&header);
+        let ram = match ram_size {
             0 => None,
             sz => Some(vec![0; sz].into_boxed_slice()),
         };
@@ -44,11 +47,12 @@ impl MBC5 {
             ram_offset: 0,
             rom_offset_0x4000_0x7fff: 0,
             ram_enabled: false,
+            ram_bank_count: ram_size / RAM_BANK_SIZE,
             cartridge_type,
             header,
         };
 
-        cartridge.reset();
+        cartridge.reset(ResetKind::Hard);
         cartridge
     }
 
@@ -75,7 +79,7 @@ impl MBC5 {
     fn update_offsets(&mut self) {
         let rom_mask = self.header.rom_bank_count - 1;
 
-        let bank_count = self.header.ram_bank_count;
+        let bank_count = self.ram_bank_count;
         let ram_mask = if bank_count > 0 { bank_count - 1 } else { 0 };
 
         self.rom_offset_0x4000_0x7fff = (self.rom_bank & rom_mask) * ROM_BANK_SIZE;
@@ -95,6 +99,30 @@ impl Cartridge for MBC5 {
     fn read_abs(&self, address: usize) -> u8 {
         self.rom[address]
     }
+
+    fn rom_bank(&self) -> usize {
+        self.rom_offset_0x4000_0x7fff / ROM_BANK_SIZE
+    }
+
+    fn ram_bank(&self) -> usize {
+        self.ram_offset / RAM_BANK_SIZE
+    }
+
+    fn debug_state(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ROM bank", format!("{:03X}", self.rom_bank)),
+            ("RAM bank", format!("{:02X}", self.ram_bank)),
+            ("RAM enabled", format!("{}", self.ram_enabled)),
+        ]
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        self.ram.as_deref()
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.ram.as_deref_mut()
+    }
 }
 
 impl MemoryMapped for MBC5 {
@@ -127,10 +155,30 @@ impl MemoryMapped for MBC5 {
         }
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self, kind: ResetKind) {
+        // Without a battery, the content of RAM is lost on power cycle
+        if kind == ResetKind::Hard && !self.cartridge_type.has_battery() {
+            if let Some(ram) = &mut self.ram {
+                ram.fill(0);
+            }
+        }
+
         self.rom_bank = 1;
         self.ram_bank = 0;
         self.ram_enabled = false;
         self.update_offsets();
     }
 }
+
+impl SaveState for MBC5 {
+    fn serialize(&mut self, s: &mut Serializer) {
+        if let Some(ref mut ram) = self.ram {
+            s.bytes(ram);
+        }
+        s.usize(&mut self.rom_offset_0x4000_0x7fff);
+        s.usize(&mut self.ram_offset);
+        s.bool(&mut self.ram_enabled);
+        s.usize(&mut self.ram_bank);
+        s.usize(&mut self.rom_bank);
+    }
+}
@@ -1,7 +1,8 @@
-use super::super::mmu::MemoryMapped;
+use super::super::mmu::{MemoryMapped, ResetKind};
 use super::{
     cartridge::Cartridge, cartridge_header::CartridgeHeader, cartridge_type::CartridgeType,
 };
+use crate::savestate::{SaveState, Serializer};
 
 pub struct NoMBC {
     // Memory buffers
@@ -19,17 +20,17 @@ impl NoMBC {
             *src = *dst
         }
 
-        let max_ram_size = cartridge_type.max_ram_size();
-        let ram = match max_ram_size {
+        let header = CartridgeHeader::from_header(data);
+        let ram = match cartridge_type.effective_ram_size(&header) {
             0 => None,
-            _ => Some(vec![0; max_ram_size].into_boxed_slice()),
+            sz => Some(vec![0; sz].into_boxed_slice()),
         };
 
         NoMBC {
             rom,
             ram,
             cartridge_type,
-            header: CartridgeHeader::from_header(data),
+            header,
         }
     }
 }
@@ -39,7 +40,7 @@ impl MemoryMapped for NoMBC {
         match address {
             0x0000..=0x7FFF => self.rom[address],
             0xA000..=0xBFFF => match &self.ram {
-                Some(ram) => ram[address],
+                Some(ram) => ram[address - 0xA000],
                 None => 0xFF,
             },
             _ => 0xFF,
@@ -57,7 +58,14 @@ impl MemoryMapped for NoMBC {
         }
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self, kind: ResetKind) {
+        // Without a battery, the content of RAM is lost on power cycle
+        if kind == ResetKind::Hard && !self.cartridge_type.has_battery() {
+            if let Some(ram) = &mut self.ram {
+                ram.fill(0);
+            }
+        }
+    }
 }
 
 impl Cartridge for NoMBC {
@@ -69,7 +77,27 @@ impl Cartridge for NoMBC {
         self.rom[address]
     }
 
+    fn rom_bank(&self) -> usize {
+        1
+    }
+
+    fn ram(&self) -> Option<&[u8]> {
+        self.ram.as_deref()
+    }
+
+    fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.ram.as_deref_mut()
+    }
+
     fn header(&self) -> &CartridgeHeader {
         &self.header
     }
 }
+
+impl SaveState for NoMBC {
+    fn serialize(&mut self, s: &mut Serializer) {
+        if let Some(ref mut ram) = self.ram {
+            s.bytes(ram);
+        }
+    }
+}
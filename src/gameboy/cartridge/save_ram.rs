@@ -0,0 +1,135 @@
+// Battery-backed cartridge RAM in .sav files
+//
+// A .sav file is the content of cartridge RAM. For cartridges with a
+// real-time clock, VBA, BGB and SameBoy append the state of the clock:
+//
+//   5 x u32 LE   Seconds, minutes, hours, day low and day high
+//   5 x u32 LE   Latched registers, in the same order
+//   u64 LE       UNIX time of saving (u32 in some older emulators)
+//
+// The emulated clock only has one set of registers, so both sets are
+// written with the same values, and the latched registers are used
+// when importing. The clock follows the host time, so the timestamp
+// is written but ignored when importing.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::cartridge::Cartridge;
+
+// Size of the clock state, with a 64 and 32 bit timestamp
+const RTC_SIZE: usize = 48;
+const RTC_SIZE_32: usize = 44;
+
+// Content of the .sav file for the cartridge. Fails if the cartridge
+// has neither RAM nor a clock.
+pub fn export_save(cartridge: &dyn Cartridge) -> Result<Vec<u8>, String> {
+    let rtc = cartridge.rtc_registers();
+    let mut data = cartridge.ram().map(|ram| ram.to_vec()).unwrap_or_default();
+    if data.is_empty() && rtc.is_none() {
+        return Err("the cartridge has no RAM".to_string());
+    }
+
+    if let Some(registers) = rtc {
+        for _ in 0..2 {
+            for r in registers {
+                data.extend_from_slice(&(r as u32).to_le_bytes());
+            }
+        }
+
+        let timestamp = match crate::deterministic() {
+            true => 0,
+            false => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |t| t.as_secs()),
+        };
+        data.extend_from_slice(&timestamp.to_le_bytes());
+    }
+
+    Ok(data)
+}
+
+// Load a .sav file, with or without the state of the clock
+pub fn import_save(cartridge: &mut dyn Cartridge, data: &[u8]) -> Result<(), String> {
+    let has_rtc = cartridge.rtc_registers().is_some();
+    let ram_size = cartridge.ram().map_or(0, |ram| ram.len());
+
+    let rtc = match data.len().checked_sub(ram_size) {
+        Some(0) if ram_size > 0 => None,
+        Some(RTC_SIZE) | Some(RTC_SIZE_32) if has_rtc => Some(&data[ram_size..]),
+        _ => {
+            return Err(format!(
+                "the save is {} bytes, but the cartridge has {} bytes of RAM{}",
+                data.len(),
+                ram_size,
+                if has_rtc { " and a clock" } else { "" }
+            ))
+        }
+    };
+
+    if let Some(ram) = cartridge.ram_mut() {
+        ram.copy_from_slice(&data[0..ram_size]);
+    }
+
+    if let Some(rtc) = rtc {
+        let mut registers = [0; 5];
+        for (i, r) in registers.iter_mut().enumerate() {
+            let offset = 20 + i * 4;
+            *r = rtc[offset];
+        }
+        cartridge.set_rtc_registers(registers);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::cartridge::cartridge_type::CartridgeType;
+    use crate::gameboy::cartridge::mbc3::MBC3;
+
+    fn mbc3(cartridge_code: u8) -> MBC3 {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = cartridge_code;
+        rom[0x149] = 0x02;
+        MBC3::new(CartridgeType::from_rom(&rom).unwrap(), &rom)
+    }
+
+    #[test]
+    fn test_raw_save() {
+        // MBC3 with RAM and battery, without a clock
+        let mut cartridge = mbc3(0x13);
+        cartridge.ram_mut().unwrap()[0x10] = 0x42;
+        let data = export_save(&cartridge).unwrap();
+        assert_eq!(data.len(), 0x2000);
+
+        let mut other = mbc3(0x13);
+        import_save(&mut other, &data).unwrap();
+        assert_eq!(other.ram().unwrap()[0x10], 0x42);
+
+        assert!(import_save(&mut other, &data[1..]).is_err());
+        assert!(import_save(&mut other, &[data.clone(), vec![0; RTC_SIZE]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_rtc_save() {
+        // MBC3 with RAM, battery and clock
+        let mut cartridge = mbc3(0x10);
+        cartridge.set_rtc_registers([12, 34, 5, 0x80, 0x41]);
+        let data = export_save(&cartridge).unwrap();
+        assert_eq!(data.len(), 0x2000 + RTC_SIZE);
+        assert_eq!(data[0x2000 + 12..0x2000 + 16], [0x80, 0, 0, 0]);
+
+        // Both 48 and 44 byte clock states are accepted
+        for size in [RTC_SIZE, RTC_SIZE_32] {
+            let mut other = mbc3(0x10);
+            import_save(&mut other, &data[0..0x2000 + size]).unwrap();
+            assert_eq!(other.rtc_registers(), Some([12, 34, 5, 0x80, 0x41]));
+        }
+
+        // Saves without the clock state leave the clock unchanged
+        let mut other = mbc3(0x10);
+        import_save(&mut other, &data[0..0x2000]).unwrap();
+        assert_eq!(other.rtc_registers(), Some([0, 0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,138 @@
+// Code/data logger
+//
+// Records how each byte of the cartridge ROM has been accessed: fetched
+// by the CPU as part of an instruction, or read as data. The log tells
+// code from data when disassembling a ROM, and shows how much of the
+// ROM has been covered by a play session.
+//
+// The log is saved in the CDL format of FCEUX and Mesen: one byte of
+// flags per byte of ROM, with bit 0 set for code and bit 1 for data.
+//
+// Logging is off until started, as it adds a lookup to every access.
+
+use super::cartridge::cartridge_header::ROM_BANK_SIZE;
+
+pub const CDL_CODE: u8 = 1;
+pub const CDL_DATA: u8 = 2;
+
+// Bytes of a ROM bank by how they were accessed. Bytes that were both
+// executed and read as data count as code.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct BankCoverage {
+    pub code: usize,
+    pub data: usize,
+    pub unused: usize,
+}
+
+impl BankCoverage {
+    // Share of the bank that has been accessed, from 0 to 1
+    pub fn covered(&self) -> f32 {
+        let total = self.code + self.data + self.unused;
+        match total {
+            0 => 0.0,
+            _ => (self.code + self.data) as f32 / total as f32,
+        }
+    }
+}
+
+pub struct CodeDataLog {
+    // Flags per byte of ROM, while logging
+    flags: Option<Vec<u8>>,
+}
+
+impl Default for CodeDataLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeDataLog {
+    pub fn new() -> Self {
+        CodeDataLog { flags: None }
+    }
+
+    // Start a new log of a ROM of `rom_size` bytes
+    pub fn start(&mut self, rom_size: usize) {
+        self.flags = Some(vec![0; rom_size]);
+    }
+
+    pub fn stop(&mut self) {
+        self.flags = None;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.flags.is_some()
+    }
+
+    #[inline]
+    pub fn log(&mut self, offset: usize, flag: u8) {
+        if let Some(f) = self.flags.as_mut().and_then(|flags| flags.get_mut(offset)) {
+            *f |= flag;
+        }
+    }
+
+    // The flags of each byte of ROM, empty if not logging
+    pub fn flags(&self) -> &[u8] {
+        match &self.flags {
+            Some(flags) => flags,
+            None => &[],
+        }
+    }
+
+    pub fn bank_coverage(&self) -> Vec<BankCoverage> {
+        self.flags()
+            .chunks(ROM_BANK_SIZE)
+            .map(|bank| {
+                let mut coverage = BankCoverage::default();
+                for f in bank {
+                    match (f & CDL_CODE != 0, f & CDL_DATA != 0) {
+                        (true, _) => coverage.code += 1,
+                        (false, true) => coverage.data += 1,
+                        (false, false) => coverage.unused += 1,
+                    }
+                }
+                coverage
+            })
+            .collect()
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), String> {
+        match &self.flags {
+            Some(flags) => std::fs::write(filename, flags).map_err(|e| e.to_string()),
+            None => Err("the code/data logger is not started".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bank_coverage() {
+        let mut cdl = CodeDataLog::new();
+        cdl.log(0, CDL_CODE);
+        assert!(cdl.bank_coverage().is_empty());
+
+        cdl.start(2 * ROM_BANK_SIZE);
+        cdl.log(0x0100, CDL_CODE);
+        cdl.log(0x0100, CDL_DATA);
+        cdl.log(0x0101, CDL_DATA);
+        cdl.log(0x4000, CDL_DATA);
+        cdl.log(0x8000, CDL_CODE);
+        assert_eq!(cdl.flags()[0x0100], CDL_CODE | CDL_DATA);
+
+        let coverage = cdl.bank_coverage();
+        assert_eq!(coverage.len(), 2);
+        assert_eq!(
+            coverage[0],
+            BankCoverage {
+                code: 1,
+                data: 1,
+                unused: ROM_BANK_SIZE - 2
+            }
+        );
+        assert_eq!(coverage[1].data, 1);
+        assert_eq!(coverage[1].covered(), 1.0 / ROM_BANK_SIZE as f32);
+    }
+}
@@ -0,0 +1,182 @@
+// SM83 CPU core
+//
+// The CPU owns its registers, and accesses the rest of the system
+// only through the `Bus` trait. On the Game Boy the bus is the MMU,
+// which advances the other subsystems as the CPU accesses memory. In
+// tests, it can be a flat 64 kB memory.
+
+use super::call_stack::CallStack;
+use super::instructions;
+use super::interrupt::handle_interrupts;
+use super::mmu::P1_REG;
+use super::registers::Registers;
+use crate::savestate::{SaveState, Serializer};
+
+pub trait Bus {
+    // Read and write memory as the CPU does, which takes 4 cycles
+    fn read(&mut self, addr: usize) -> u8;
+    fn write(&mut self, addr: usize, value: u8);
+
+    // Read a byte of an instruction, which takes 4 cycles
+    fn fetch(&mut self, addr: usize) -> u8 {
+        self.read(addr)
+    }
+
+    // Internal CPU cycles, without memory access
+    fn tick(&mut self, cycles: u32);
+
+    // Cycles spent in HALT. Unlike `tick`, OAM DMA is not advanced.
+    fn idle(&mut self, cycles: u32);
+
+    // Read and write memory without any side effects or cycles
+    fn peek(&self, addr: usize) -> u8;
+    fn poke(&mut self, addr: usize, value: u8);
+}
+
+pub struct Cpu {
+    pub reg: Registers,
+
+    // If interrupt handler was entered while executing
+    // the previous operation, this variable is set to
+    // the interrupt bit. Otherwise it's reset to zero.
+    pub entered_interrupt_handler: u8,
+
+    // Calls, RSTs and interrupts that have not yet returned
+    pub call_stack: CallStack,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    pub fn new() -> Self {
+        Cpu {
+            reg: Registers::new(),
+            entered_interrupt_handler: 0,
+            call_stack: CallStack::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Cpu::new();
+    }
+
+    pub fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = self.reg.pc;
+        let value = bus.fetch(pc as usize);
+        self.reg.pc = pc.wrapping_add(1);
+        value
+    }
+
+    pub fn fetch_u16(&mut self, bus: &mut impl Bus) -> u16 {
+        let lo = self.fetch(bus);
+        let hi = self.fetch(bus);
+        ((hi as u16) << 8) | (lo as u16)
+    }
+
+    // Execute the next instruction, or wait a cycle if halted or
+    // stopped, and then handle pending interrupts
+    pub fn step(&mut self, bus: &mut impl Bus) {
+        if self.reg.stopped {
+            // Leaves STOP mode when a selected joypad input line is
+            // low, whether or not the joypad interrupt is enabled
+            bus.idle(4);
+            if bus.peek(P1_REG) & 0x0F != 0x0F {
+                self.reg.stopped = false;
+            }
+        } else if !self.reg.halted {
+            let (pc, sp) = (self.reg.pc, self.reg.sp);
+            let op = bus.peek(pc as usize);
+            instructions::step(self, bus);
+            self.call_stack.after_op(op, pc, sp, &self.reg);
+        } else {
+            bus.idle(4);
+        }
+
+        let pc = self.reg.pc;
+        self.entered_interrupt_handler = handle_interrupts(self, bus);
+        if self.entered_interrupt_handler != 0 {
+            self.call_stack.interrupt(pc, &self.reg);
+        }
+    }
+}
+
+impl SaveState for Cpu {
+    // The call stack is a debugging aid, and is not saved. It's
+    // cleared on load, as it doesn't match the loaded state.
+    fn serialize(&mut self, s: &mut Serializer) {
+        self.reg.serialize(s);
+        s.u8(&mut self.entered_interrupt_handler);
+        if s.is_loading() {
+            self.call_stack = CallStack::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 64 kB of RAM, with no I/O registers
+    struct FlatBus {
+        mem: Vec<u8>,
+        cycles: u32,
+    }
+
+    impl Bus for FlatBus {
+        fn read(&mut self, addr: usize) -> u8 {
+            self.cycles += 4;
+            self.mem[addr]
+        }
+
+        fn write(&mut self, addr: usize, value: u8) {
+            self.cycles += 4;
+            self.mem[addr] = value;
+        }
+
+        fn tick(&mut self, cycles: u32) {
+            self.cycles += cycles;
+        }
+
+        fn idle(&mut self, cycles: u32) {
+            self.cycles += cycles;
+        }
+
+        fn peek(&self, addr: usize) -> u8 {
+            self.mem[addr]
+        }
+
+        fn poke(&mut self, addr: usize, value: u8) {
+            self.mem[addr] = value;
+        }
+    }
+
+    #[test]
+    fn test_step_on_flat_bus() {
+        // LD A, $42; PUSH AF; JP $1234
+        let mut bus = FlatBus {
+            mem: vec![0; 0x10000],
+            cycles: 0,
+        };
+        bus.mem[..6].copy_from_slice(&[0x3E, 0x42, 0xF5, 0xC3, 0x34, 0x12]);
+
+        let mut cpu = Cpu::new();
+        cpu.reg.sp = 0xD000;
+
+        cpu.step(&mut bus);
+        assert_eq!(cpu.reg.a, 0x42);
+        assert_eq!(bus.cycles, 8);
+
+        cpu.step(&mut bus);
+        assert_eq!(cpu.reg.sp, 0xCFFE);
+        assert_eq!(bus.mem[0xCFFF], 0x42);
+        assert_eq!(bus.cycles, 24);
+
+        cpu.step(&mut bus);
+        assert_eq!(cpu.reg.pc, 0x1234);
+        assert_eq!(bus.cycles, 40);
+    }
+}
@@ -8,6 +8,8 @@
 // The transfer will begin 4 clock cycles after the write.
 // During a transfer all reads of OAM memory will return 0xFF.
 
+use crate::savestate::{SaveState, Serializer};
+
 pub struct DMA {
     pub start_request: Option<u16>,
     pub start_request_delay: Option<u16>,
@@ -63,3 +65,13 @@ impl DMA {
         }
     }
 }
+
+impl SaveState for DMA {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.option_u16(&mut self.start_request);
+        s.option_u16(&mut self.start_request_delay);
+        s.option_u16(&mut self.start_address);
+        s.u16(&mut self.step);
+        s.u8(&mut self.last_write_dma_reg);
+    }
+}
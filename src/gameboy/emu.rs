@@ -1,15 +1,35 @@
+#[cfg(feature = "gui")]
+use std::collections::HashMap;
+use std::fs::File;
 use std::io::Write;
-use std::{collections::HashMap, fs::File};
 
+#[cfg(feature = "gui")]
 use egui::Key;
-use ringbuf::Producer;
 
-use crate::{core::Core, gameboy::instructions::format_mnemonic};
+use crate::{
+    core::{Core, Debuggable, MemoryRegion, RegisterValue},
+    gameboy::instructions::{self, format_mnemonic},
+    palette::DmgPalette,
+    savestate::{SaveState, Serializer},
+    wave_audio_recorder::AudioRecorder,
+};
 
 use super::buttons::ButtonType;
+use super::cartridge::cartridge::{Cartridge, NoCartridge};
+use super::cartridge::cartridge_header::verify_rom;
+use super::cartridge::cartridge_type::CartridgeType;
+use super::cartridge::load_cartridge;
+use super::cartridge::save_ram::{export_save, import_save};
+use super::cpu::Cpu;
+use super::frames::Frames;
+use super::movie::{format_buttons, ActiveMovie, Movie, MovieMode};
+use super::quirks::{QuirkRegistry, Quirks};
+#[cfg(feature = "scripting")]
+use super::script::ScriptHost;
 use super::{
-    mmu::MMU,
-    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    mmu::{ResetKind, MMU},
+    ppu::{DOTS_PER_LINE, SCREEN_HEIGHT, SCREEN_WIDTH},
+    CYCLES_PER_FRAME,
 };
 
 #[derive(Copy, Clone)]
@@ -30,9 +50,36 @@ pub enum Machine {
 }
 
 pub struct Emu {
+    pub cpu: Cpu,
     pub mmu: MMU,
     pub machine: Machine,
+    #[cfg(feature = "gui")]
     keymap: HashMap<Key, ButtonType>,
+
+    pub quirk_registry: QuirkRegistry,
+
+    // Quirks of the currently loaded cartridge
+    pub quirks: Quirks,
+
+    // Receives the audio output while recording
+    recorder: Option<Box<dyn AudioRecorder>>,
+
+    // Number of cycles the last call to `step_cycles` ran past
+    // its target. Deducted from the next call.
+    cycle_carry: u64,
+
+    // Input movie being recorded or played
+    movie: Option<ActiveMovie>,
+
+    // Script host, created when a script is first loaded or evaluated
+    #[cfg(feature = "scripting")]
+    script: Option<Box<ScriptHost>>,
+
+    // Cartridge RAM is exported to this file on shutdown
+    pub export_save_on_exit: Option<String>,
+
+    // The code/data log is written to this file on shutdown
+    pub cdl_on_exit: Option<String>,
 }
 
 impl Core for Emu {
@@ -44,22 +91,35 @@ impl Core for Emu {
         SCREEN_HEIGHT
     }
 
-    fn log_state(&self, f: &mut File) {
-        let reg = &self.mmu.reg;
+    fn trace_line(&self) -> Option<String> {
+        if self.mmu.bootstrap_mode {
+            return None;
+        }
+
+        let reg = &self.cpu.reg;
         let pc = reg.pc as usize;
-        if !self.mmu.bootstrap_mode {
-            let m0 = self.mmu.direct_read(pc);
-            let m1 = self.mmu.direct_read(pc + 1);
-            let m2 = self.mmu.direct_read(pc + 2);
-            let m3 = self.mmu.direct_read(pc + 3);
-            println!(
-                 "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: 00:{:04X} ({:02X} {:02X} {:02X} {:02X}) {}",
-                reg.a,reg.get_f(),reg.b,reg.c,reg.d,reg.e,reg.h,reg.l,reg.sp,pc,m0,m1,m2,m3, format_mnemonic(&self.mmu, pc),
-            );
-            let res = writeln!(
-                f, "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: 00:{:04X} ({:02X} {:02X} {:02X} {:02X}) {}",
-                reg.a,reg.get_f(),reg.b,reg.c,reg.d,reg.e,reg.h,reg.l,reg.sp,pc,m0,m1,m2,m3, format_mnemonic(&self.mmu, pc),
-            );
+        let m0 = self.mmu.direct_read(pc);
+        let m1 = self.mmu.direct_read(pc + 1);
+        let m2 = self.mmu.direct_read(pc + 2);
+        let m3 = self.mmu.direct_read(pc + 3);
+        Some(format!(
+            "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: 00:{:04X} ({:02X} {:02X} {:02X} {:02X}) {}",
+            reg.a,reg.get_f(),reg.b,reg.c,reg.d,reg.e,reg.h,reg.l,reg.sp,pc,m0,m1,m2,m3, format_mnemonic(&self.mmu, &self.cpu.reg, pc),
+        ))
+    }
+
+    fn input_text(&self) -> String {
+        format_buttons(self.mmu.buttons.pressed())
+    }
+
+    fn joypad_reads(&self) -> (u64, u8) {
+        (self.mmu.p1_reads, self.mmu.last_p1_read)
+    }
+
+    fn log_state(&self, f: &mut File) {
+        if let Some(line) = self.trace_line() {
+            println!("{}", line);
+            let res = writeln!(f, "{}", line);
             match res {
                 Ok(_) => {}
                 Err(_) => panic!("Failed to write log"),
@@ -72,17 +132,36 @@ impl Core for Emu {
     }
 
     fn at_source_code_breakpoint(&self) -> bool {
-        match self.mmu.direct_read(self.mmu.reg.pc as usize) {
+        match self.mmu.direct_read(self.cpu.reg.pc as usize) {
             0x40 => true,
             _ => false,
         }
     }
 
     fn exec_op(&mut self) {
-        self.mmu.exec_op();
+        self.update_movie();
+        self.update_script();
+        self.step();
+    }
+
+    fn run_one_frame(&mut self) {
+        Emu::run_one_frame(self);
+    }
+
+    fn save_state(&mut self) -> Vec<u8> {
+        Emu::save_state(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        Emu::load_state(self, data)
     }
 
+    #[cfg(feature = "gui")]
     fn update_input_state(&mut self, state: &egui::InputState) {
+        if self.is_playing_movie() {
+            return;
+        }
+
         for key in self.keymap.keys() {
             if state.key_down(*key) {
                 self.mmu.buttons.handle_press(self.keymap[&key])
@@ -94,6 +173,9 @@ impl Core for Emu {
     }
 
     fn release_all(&mut self) {
+        if self.is_playing_movie() {
+            return;
+        }
         self.mmu.buttons.release_all();
     }
 
@@ -110,28 +192,77 @@ impl Core for Emu {
     }
 
     fn pc(&self) -> usize {
-        self.mmu.reg.pc as usize
+        self.cpu.reg.pc as usize
+    }
+
+    fn register(&self, name: &str) -> Option<u16> {
+        let reg = &self.cpu.reg;
+        match name {
+            "A" => Some(reg.a as u16),
+            "F" => Some(reg.get_f() as u16),
+            "B" => Some(reg.b as u16),
+            "C" => Some(reg.c as u16),
+            "D" => Some(reg.d as u16),
+            "E" => Some(reg.e as u16),
+            "H" => Some(reg.h as u16),
+            "L" => Some(reg.l as u16),
+            "AF" => Some(reg.af()),
+            "BC" => Some(reg.bc()),
+            "DE" => Some(reg.de()),
+            "HL" => Some(reg.hl()),
+            "SP" => Some(reg.sp),
+            "PC" => Some(reg.pc),
+            _ => None,
+        }
+    }
+
+    fn peek(&self, addr: usize) -> u8 {
+        self.mmu.direct_read(addr & 0xFFFF)
+    }
+
+    fn bank(&self, addr: usize) -> usize {
+        match addr {
+            0x4000..=0x7FFF => self.mmu.cartridge.rom_bank(),
+            0xA000..=0xBFFF => self.mmu.cartridge.ram_bank(),
+            _ => 0,
+        }
+    }
+
+    fn in_boot_rom(&self) -> bool {
+        self.mmu.bootstrap_mode
     }
 
     fn scanline(&self) -> usize {
         self.mmu.ppu.ly
     }
 
+    fn beam_position(&self) -> usize {
+        self.mmu.ppu.beam_position()
+    }
+
+    fn cycle(&self) -> u64 {
+        self.mmu.timer.abs_cycle
+    }
+
     fn register_serial_output_buffer(&mut self, p: ringbuf::Producer<u8>) {
         self.mmu.serial.output = Some(p);
     }
 
     fn set_audio_rates(&mut self, clock_rate: f64, sample_rate: f64) {
-        self.mmu.apu.buf_left.set_rates(clock_rate, sample_rate);
-        self.mmu.apu.buf_right.set_rates(clock_rate, sample_rate);
+        self.mmu.apu.set_rates(clock_rate, sample_rate);
     }
 
     fn end_audio_frame(&mut self) {
-        self.mmu.apu.buf_left.end_frame(self.mmu.apu.buf_clock);
-        self.mmu.apu.buf_clock = 0;
+        let apu = &mut self.mmu.apu;
+        apu.buf_left.end_frame(apu.buf_clock);
+        apu.buf_right.end_frame(apu.buf_clock);
+        for buf in apu.stem_bufs.iter_mut() {
+            buf.end_frame(apu.buf_clock);
+        }
+        apu.buf_clock = 0;
     }
 
-    fn push_audio_samples(&mut self, p: &mut Producer<i16>) {
+    fn read_audio_samples(&mut self, dst: &mut Vec<i16>) {
         let mut b: [i16; 128] = [0; 128];
 
         while self.mmu.apu.buf_left.samples_avail() > 0 {
@@ -139,21 +270,184 @@ impl Core for Emu {
             if n == 0 {
                 break;
             }
-            p.push_slice(&b[..n]);
+
+            // The right channel is always read and filtered, so that
+            // the filter is up to date when a recording starts
+            let apu = &mut self.mmu.apu;
+            let mut right: [i16; 128] = [0; 128];
+            apu.buf_right.read_samples(&mut right[..n], false);
+            apu.high_pass(&mut b[..n], &mut right[..n]);
+            dst.extend_from_slice(&b[..n]);
+
+            // Only the left channel is played back
+            if let Some(ref mut recorder) = self.recorder {
+                for i in 0..n {
+                    recorder.stereo(b[i] as f32 / 32768.0, right[i] as f32 / 32768.0);
+                }
+
+                for (ch, buf) in apu.stem_bufs.iter_mut().enumerate() {
+                    let mut stem: [i16; 128] = [0; 128];
+                    buf.read_samples(&mut stem[..n], false);
+                    for sample in stem[..n].iter() {
+                        recorder.channel(ch, *sample as f32 / 32768.0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn to_rgba8(&self, dst: &mut Box<[u8]>, palette: &DmgPalette) {
+        self.mmu.ppu.to_rgba8(dst, palette);
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.mmu.ppu.buffer
+    }
+
+    fn capture(&self, filename: &str, palette: &DmgPalette) -> Result<(), std::io::Error> {
+        self.mmu.ppu.capture(filename, palette)
+    }
+
+    fn shutdown(&mut self) {
+        self.stop_movie();
+
+        if let Some(filename) = self.export_save_on_exit.take() {
+            match self.export_save(&filename) {
+                Ok(_) => println!("Exported cartridge RAM to {}", filename),
+                Err(e) => println!("Failed to export cartridge RAM: {}", e),
+            }
+        }
+
+        if let Some(filename) = self.cdl_on_exit.take() {
+            match self.mmu.cdl.save(&filename) {
+                Ok(_) => println!("Saved code/data log to {}", filename),
+                Err(e) => println!("Failed to save code/data log: {}", e),
+            }
         }
     }
 
-    fn to_rgba8(&self, dst: &mut Box<[u8]>, palette: Vec<(u8, u8, u8)>) {
-        let p: [(u8, u8, u8); 4] = [palette[0], palette[1], palette[2], palette[3]];
-        self.mmu.ppu.to_rgba8(dst, p);
+    #[cfg(feature = "scripting")]
+    fn overlay_text(&self) -> Vec<crate::core::OverlayText> {
+        match &self.script {
+            Some(script) => script.overlay(),
+            None => Vec::new(),
+        }
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn overlay_text(&self) -> Vec<crate::core::OverlayText> {
+        Vec::new()
+    }
+
+    fn input_state(&self) -> Vec<(&'static str, bool)> {
+        let pressed = self.mmu.buttons.pressed();
+        INPUT_DISPLAY
+            .iter()
+            .map(|(button, name)| (*name, pressed & *button as u8 != 0))
+            .collect()
+    }
+}
+
+const fn region(name: &'static str, start: usize, end: usize) -> MemoryRegion {
+    MemoryRegion { name, start, end }
+}
+
+// Buttons in the order of the input display, as laid out on the
+// console from left to right
+const INPUT_DISPLAY: [(ButtonType, &str); 8] = [
+    (ButtonType::Left, "<"),
+    (ButtonType::Up, "^"),
+    (ButtonType::Down, "v"),
+    (ButtonType::Right, ">"),
+    (ButtonType::Select, "Select"),
+    (ButtonType::Start, "Start"),
+    (ButtonType::B, "B"),
+    (ButtonType::A, "A"),
+];
+
+const MEMORY_REGIONS: [MemoryRegion; 10] = [
+    region("ROM", 0x0000, 0x7FFF),
+    region("VRAM", 0x8000, 0x9FFF),
+    region("Ext RAM", 0xA000, 0xBFFF),
+    region("WRAM", 0xC000, 0xDFFF),
+    region("Echo", 0xE000, 0xFDFF),
+    region("OAM", 0xFE00, 0xFE9F),
+    region("Unusable", 0xFEA0, 0xFEFF),
+    region("IO", 0xFF00, 0xFF7F),
+    region("HRAM", 0xFF80, 0xFFFE),
+    region("IE", 0xFFFF, 0xFFFF),
+];
+
+impl Debuggable for Emu {
+    fn registers(&self) -> Vec<RegisterValue> {
+        let reg = &self.cpu.reg;
+        let r8 = |name, value: u8| RegisterValue {
+            name,
+            value: value as u16,
+            bits: 8,
+        };
+        let r16 = |name, value| RegisterValue {
+            name,
+            value,
+            bits: 16,
+        };
+        vec![
+            r8("A", reg.a),
+            r8("B", reg.b),
+            r8("C", reg.c),
+            r8("D", reg.d),
+            r8("E", reg.e),
+            r8("F", reg.get_f()),
+            r8("H", reg.h),
+            r8("L", reg.l),
+            r16("SP", reg.sp),
+            r16("PC", reg.pc),
+        ]
+    }
+
+    fn disassemble(&self, addr: usize) -> (String, Option<usize>) {
+        let addr = addr & 0xFFFF;
+        let text = format_mnemonic(&self.mmu, &self.cpu.reg, addr);
+        (text, instructions::op_length(self.mmu.direct_read(addr)))
+    }
+
+    fn poke(&mut self, addr: usize, value: u8) {
+        self.mmu.direct_write(addr & 0xFFFF, value);
+    }
+
+    fn is_writable(&self, addr: usize) -> bool {
+        (addr & 0xFFFF) >= 0x8000
+    }
+
+    fn memory_size(&self) -> usize {
+        0x10000
+    }
+
+    fn memory_regions(&self) -> &'static [MemoryRegion] {
+        &MEMORY_REGIONS
+    }
+
+    fn dot(&self) -> usize {
+        self.mmu.ppu.position().1
+    }
+
+    fn frame_cycle(&self) -> usize {
+        let (line, dot) = self.mmu.ppu.position();
+        line * DOTS_PER_LINE + dot
+    }
+
+    fn cycles_per_frame(&self) -> usize {
+        CYCLES_PER_FRAME
     }
 }
 
 impl Emu {
     pub fn new(machine: Machine) -> Self {
         Emu {
+            cpu: Cpu::new(),
             mmu: MMU::new(machine),
             machine,
+            #[cfg(feature = "gui")]
             keymap: HashMap::from([
                 (Key::ArrowLeft, ButtonType::Left),
                 (Key::ArrowRight, ButtonType::Right),
@@ -164,22 +458,544 @@ impl Emu {
                 (Key::Enter, ButtonType::Start),
                 (Key::Space, ButtonType::Select),
             ]),
+            quirk_registry: QuirkRegistry::new(),
+            quirks: Quirks::new(),
+            recorder: None,
+            cycle_carry: 0,
+            movie: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+            export_save_on_exit: None,
+            cdl_on_exit: None,
+        }
+    }
+
+    // Execute one instruction and handle interrupts. Unlike
+    // `exec_op`, movies and scripts are not updated.
+    pub fn step(&mut self) {
+        let start = self.mmu.timing.start();
+        self.cpu.step(&mut self.mmu);
+        self.mmu.timing.end_op(start, self.mmu.ppu.frame_number);
+    }
+
+    // Run for `n` T-cycles and return the number of cycles that were
+    // actually executed.
+    //
+    // Instructions are never split, so the last instruction may run
+    // past `n`. The excess is carried over and deducted from the next
+    // call, so that over many calls the total number of executed cycles
+    // never drifts from the total requested by more than one instruction.
+    // This makes it possible to drive the emulator by an external clock,
+    // such as the audio or display clock of the frontend.
+    pub fn step_cycles(&mut self, n: u64) -> u64 {
+        if self.cycle_carry >= n {
+            self.cycle_carry -= n;
+            return 0;
+        }
+
+        let target = n - self.cycle_carry;
+        let start = self.mmu.timer.abs_cycle;
+
+        while self.mmu.timer.abs_cycle.wrapping_sub(start) < target {
+            self.update_movie();
+            self.update_script();
+            self.step();
+        }
+
+        let executed = self.mmu.timer.abs_cycle.wrapping_sub(start);
+        self.cycle_carry = executed - target;
+        executed
+    }
+
+    // Run for exactly one frame worth of cycles (70224), whether or not
+    // the display is enabled. Used for frame advance, where each step
+    // should be the same amount of emulated time. Returns the number
+    // of cycles executed.
+    pub fn run_one_frame(&mut self) -> u64 {
+        self.step_cycles(CYCLES_PER_FRAME as u64)
+    }
+
+    // Stream of completed frames, with the audio of each frame
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames::new(self)
+    }
+
+    // Identifies the machine and the game that a state belongs to
+    fn state_id(&self) -> u32 {
+        let machine = match self.machine {
+            Machine::GameBoyDMG => 0,
+            Machine::GameBoyMGB => 1,
+            Machine::GameBoySGB => 2,
+            Machine::GameBoyCGB => 3,
+        };
+        let checksum = match self.mmu.cartridge.cartridge_type() {
+            CartridgeType::NoCartridge => 0,
+            _ => self.mmu.cartridge.header().global_checksum,
+        };
+        machine << 16 | checksum as u32
+    }
+
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut s = Serializer::saving();
+        let mut id = self.state_id();
+        s.u32(&mut id);
+        self.serialize(&mut s);
+        s.finish().unwrap()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() < 4 || data[0..4] != self.state_id().to_le_bytes() {
+            return Err("state is for another machine or game".to_string());
+        }
+
+        // The state of a machine and game always has the same size.
+        // By checking it first, a broken state is rejected before
+        // anything is loaded.
+        if data.len() != self.save_state().len() {
+            return Err("state has the wrong size".to_string());
+        }
+
+        let mut s = Serializer::loading(&data[4..]);
+        self.serialize(&mut s);
+        s.finish().map(|_| ())
+    }
+
+    // Start sending the audio output to `recorder`. If `stems` is
+    // true, the output of each channel is also sent separately.
+    pub fn start_recording(&mut self, recorder: Box<dyn AudioRecorder>, stems: bool) {
+        self.stop_recording();
+        self.mmu.apu.enable_stems(stems);
+        self.recorder = Some(recorder);
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.flush();
+        }
+        self.mmu.apu.enable_stems(false);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // Power cycle and start recording the buttons of each frame.
+    // The movie is saved to `filename` when recording stops.
+    pub fn record_movie(&mut self, filename: &str) -> Result<(), String> {
+        if let CartridgeType::NoCartridge = self.mmu.cartridge.cartridge_type() {
+            return Err("no cartridge loaded".to_string());
+        }
+
+        let movie = Movie::new(self.mmu.cartridge.header(), 0);
+        self.start_movie(movie, MovieMode::Recording, filename);
+        Ok(())
+    }
+
+    // Power cycle and play the movie in `filename`. User input is
+    // ignored until the movie ends or is stopped.
+    pub fn play_movie(&mut self, filename: &str) -> Result<(), String> {
+        if let CartridgeType::NoCartridge = self.mmu.cartridge.cartridge_type() {
+            return Err("no cartridge loaded".to_string());
+        }
+
+        let movie = Movie::load(filename)?;
+        if !movie.matches(self.mmu.cartridge.header()) {
+            return Err("movie was recorded with another ROM".to_string());
+        }
+
+        self.start_movie(movie, MovieMode::Playing, filename);
+        Ok(())
+    }
+
+    fn start_movie(&mut self, movie: Movie, mode: MovieMode, filename: &str) {
+        self.stop_movie();
+
+        // Make the power-on state the same as when the movie was recorded
+        self.mmu.set_seed(movie.seed);
+        self.reset(ResetKind::Hard);
+        self.mmu.buttons.set_pressed(0);
+        self.cycle_carry = 0;
+
+        self.movie = Some(ActiveMovie::new(movie, mode, filename));
+    }
+
+    // Load cartridge RAM from a .sav file, with the clock state
+    // appended for cartridges with a clock
+    pub fn import_save(&mut self, filename: &str) -> Result<(), String> {
+        let data = std::fs::read(filename).map_err(|e| e.to_string())?;
+        import_save(self.mmu.cartridge.as_mut(), &data)
+    }
+
+    // Start a new code/data log over the ROM of the cartridge
+    pub fn start_cdl(&mut self) {
+        let rom_size = match self.mmu.cartridge.cartridge_type() {
+            CartridgeType::NoCartridge => 0,
+            _ => self.mmu.cartridge.header().rom_size,
+        };
+        self.mmu.cdl.start(rom_size);
+    }
+
+    // Write cartridge RAM to a .sav file, in the same format
+    pub fn export_save(&self, filename: &str) -> Result<(), String> {
+        let data = export_save(self.mmu.cartridge.as_ref())?;
+        std::fs::write(filename, data).map_err(|e| e.to_string())
+    }
+
+    // Stop recording or playing. A recorded movie is saved.
+    pub fn stop_movie(&mut self) {
+        if let Some(active) = self.movie.take() {
+            if active.mode == MovieMode::Recording {
+                match active.movie.save(&active.filename) {
+                    Ok(_) => println!(
+                        "Saved movie with {} frames to {}",
+                        active.movie.frames.len(),
+                        active.filename
+                    ),
+                    Err(e) => println!("Failed to save movie: {}", e),
+                }
+            } else {
+                self.mmu.buttons.set_pressed(0);
+            }
+        }
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(&self.movie, Some(active) if active.mode == MovieMode::Playing)
+    }
+
+    // Short description of the movie state, for the UI
+    pub fn movie_status(&self) -> Option<String> {
+        self.movie.as_ref().map(|active| match active.mode {
+            MovieMode::Recording => format!("Recording movie: frame {}", active.position()),
+            MovieMode::Playing => format!(
+                "Playing movie: frame {} of {}",
+                active.position(),
+                active.movie.frames.len()
+            ),
+        })
+    }
+
+    fn update_movie(&mut self) {
+        // Autofire is part of the recorded input, but not applied on
+        // top of a movie that is played
+        if !self.is_playing_movie() {
+            let frame = self.mmu.ppu.frame_number;
+            self.mmu.buttons.update_autofire(frame);
+        }
+
+        if let Some(active) = &mut self.movie {
+            if !active.update(self.mmu.ppu.frame_number, &mut self.mmu.buttons) {
+                println!("Movie playback finished");
+                self.stop_movie();
+            }
+        }
+    }
+
+    // Run `f` with the script host, which is temporarily taken out of
+    // the emulator so that both can be borrowed at the same time
+    #[cfg(feature = "scripting")]
+    fn with_script<R>(&mut self, f: impl FnOnce(&mut ScriptHost, &mut Emu) -> R) -> R {
+        let mut script = self
+            .script
+            .take()
+            .unwrap_or_else(|| Box::new(ScriptHost::new()));
+        let result = f(&mut script, self);
+        self.script = Some(script);
+        result
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn load_script(&mut self, filename: &str) -> Result<(), String> {
+        self.with_script(|script, emu| script.load(filename, emu))
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn load_script(&mut self, _filename: &str) -> Result<(), String> {
+        Err("built without the \"scripting\" feature".to_string())
+    }
+
+    // Evaluate code from the script console
+    #[cfg(feature = "scripting")]
+    pub fn eval_script(&mut self, code: &str) {
+        self.with_script(|script, emu| script.eval(emu, code));
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn eval_script(&mut self, _code: &str) {
+        println!("Scripting is not supported: built without the \"scripting\" feature");
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn script_console(&self) -> Vec<String> {
+        match &self.script {
+            Some(script) => script.console(),
+            None => Vec::new(),
+        }
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn script_console(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn update_script(&mut self) {
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            self.with_script(|script, emu| script.before_op(emu));
         }
     }
 
-    pub fn reset(&mut self) {
-        self.mmu.reset();
+    // A hard reset is a power cycle, and runs the boot ROM if one is
+    // loaded. A soft reset keeps the content of memory, and starts the
+    // cartridge at 0x0100 in the state the boot ROM leaves it in.
+    pub fn reset(&mut self, kind: ResetKind) {
+        self.cpu.reset();
+        self.mmu.reset(kind);
+        self.mmu.init();
+
+        if kind == ResetKind::Soft || !self.mmu.bootstrap_loaded() {
+            self.skip_bootrom();
+        }
     }
 
     pub fn init(&mut self) {
         self.mmu.init();
     }
 
+    // Start the cartridge without running the boot ROM, with the CPU
+    // registers and I/O registers set as the boot ROM leaves them
+    // Ref: https://gbdev.io/pandocs/Power_Up_Sequence.html
+    pub fn skip_bootrom(&mut self) {
+        self.mmu.init_post_boot_io();
+        self.mmu.bootstrap_mode = false;
+
+        let (checksum, dmg_only) = match self.mmu.cartridge.cartridge_type() {
+            CartridgeType::NoCartridge => (0, true),
+            _ => {
+                let header = self.mmu.cartridge.header();
+                (header.checksum, header.dmg_only())
+            }
+        };
+
+        let (af, bc, de, hl) = match self.machine {
+            // H and C are set unless the header checksum is 0
+            Machine::GameBoyDMG | Machine::GameBoyMGB => {
+                let a = match self.machine {
+                    Machine::GameBoyMGB => 0xFF,
+                    _ => 0x01,
+                };
+                let f = if checksum == 0 { 0x80 } else { 0xB0 };
+                ((a << 8) | f, 0x0013, 0x00D8, 0x014D)
+            }
+            Machine::GameBoySGB => (0x0100, 0x0014, 0x0000, 0xC060),
+            Machine::GameBoyCGB if dmg_only => (0x1180, 0x0000, 0x0008, 0x007C),
+            Machine::GameBoyCGB => (0x1180, 0x0000, 0xFF56, 0x000D),
+        };
+
+        // Done by the CGB boot ROM through KEY0
+        self.mmu.ppu.cgb_mode = matches!(self.machine, Machine::GameBoyCGB) && !dmg_only;
+
+        let reg = &mut self.cpu.reg;
+        reg.set_af(af);
+        reg.set_bc(bc);
+        reg.set_de(de);
+        reg.set_hl(hl);
+        reg.sp = 0xFFFE;
+        reg.pc = 0x0100;
+    }
+
     pub fn load_bootstrap(&mut self, path: &str) -> usize {
         self.mmu.load_bootstrap(&path)
     }
 
-    pub fn load_cartridge(&mut self, path: &str) {
-        self.mmu.load_cartridge(path);
+    pub fn load_cartridge(&mut self, path: &str) -> Result<(), String> {
+        self.mmu.load_cartridge(path)?;
+        self.apply_quirks();
+        Ok(())
+    }
+
+    pub fn load_cartridge_rom(&mut self, content: &Vec<u8>) -> Result<(), String> {
+        self.mmu.load_cartridge_rom(content)?;
+        self.apply_quirks();
+        Ok(())
+    }
+
+    // Pull the cartridge out while the machine is running. Reads of
+    // ROM and cartridge RAM return open bus, and writes are lost. The
+    // cartridge is returned with its RAM and mapper state, and can be
+    // inserted again. The code/data log, which is of its ROM, is
+    // stopped.
+    pub fn eject_cartridge(&mut self) -> Box<dyn Cartridge> {
+        self.mmu.header_warnings.clear();
+        self.mmu.cdl.stop();
+        std::mem::replace(&mut self.mmu.cartridge, Box::new(NoCartridge {}))
+    }
+
+    // Insert a cartridge while the machine is running, replacing the
+    // one in place, if any. As on hardware, the machine isn't reset:
+    // the running code keeps running, now reading the new cartridge.
+    pub fn insert_cartridge(&mut self, cartridge: Box<dyn Cartridge>) {
+        self.eject_cartridge();
+        self.mmu.cartridge = cartridge;
+        self.apply_quirks();
+    }
+
+    // Insert the cartridge of a ROM file while running
+    pub fn insert_rom(&mut self, path: &str) -> Result<(), String> {
+        let content = std::fs::read(path).map_err(|e| e.to_string())?;
+        self.insert_cartridge(load_cartridge(&content)?);
+        self.mmu.header_warnings = verify_rom(&content);
+        Ok(())
+    }
+
+    fn apply_quirks(&mut self) {
+        self.quirks = self.quirk_registry.lookup(self.mmu.cartridge.header());
+
+        if !self.quirks.is_empty() {
+            println!("Applying compatibility quirks: {:?}", self.quirks);
+        }
+
+        self.mmu.ppu.lyc_write_irq = !self.quirks.no_lyc_write_irq;
+
+        if self.quirks.no_strict_mode {
+            crate::set_strict_mode(false);
+        }
+    }
+}
+
+impl SaveState for Emu {
+    fn serialize(&mut self, s: &mut Serializer) {
+        self.cpu.serialize(s);
+        self.mmu.serialize(s);
+        s.u64(&mut self.cycle_carry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Put an infinite loop of 16-cycle jumps in RAM and start executing it
+    fn jump_loop() -> Emu {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.mmu.direct_write(0xC000, 0xC3);
+        emu.mmu.direct_write(0xC001, 0x00);
+        emu.mmu.direct_write(0xC002, 0xC0);
+        emu.cpu.reg.pc = 0xC000;
+        emu
+    }
+
+    #[test]
+    fn test_step_cycles_on_instruction_boundary() {
+        let mut emu = jump_loop();
+        assert_eq!(emu.step_cycles(64), 64);
+        assert_eq!(emu.mmu.timer.abs_cycle, 64);
+    }
+
+    #[test]
+    fn test_step_cycles_carry_over() {
+        let mut emu = jump_loop();
+        assert_eq!(emu.step_cycles(10), 16);
+        assert_eq!(emu.step_cycles(10), 16);
+        assert_eq!(emu.step_cycles(10), 0);
+        assert_eq!(emu.step_cycles(10), 16);
+
+        // 40 cycles requested, and 48 executed. The 8 cycles in
+        // excess are deducted from the next call.
+        assert_eq!(emu.step_cycles(8), 0);
+        assert_eq!(emu.mmu.timer.abs_cycle, 48);
+    }
+
+    #[test]
+    fn test_input_state() {
+        let mut emu = jump_loop();
+        emu.mmu.buttons.handle_press(ButtonType::Up);
+        emu.mmu.buttons.handle_press(ButtonType::A);
+        let pressed: Vec<&str> = emu
+            .input_state()
+            .iter()
+            .filter(|(_, pressed)| *pressed)
+            .map(|(name, _)| *name)
+            .collect();
+        assert_eq!(pressed, ["^", "A"]);
+    }
+
+    #[test]
+    fn test_run_one_frame() {
+        let mut emu = jump_loop();
+        for _ in 0..3 {
+            emu.run_one_frame();
+        }
+        assert_eq!(emu.mmu.timer.abs_cycle, 3 * CYCLES_PER_FRAME as u64);
+    }
+
+    #[test]
+    fn test_load_state() {
+        let mut emu = jump_loop();
+        emu.run_one_frame();
+        let state = emu.save_state();
+        emu.run_one_frame();
+        let (cycle, pc) = (emu.mmu.timer.abs_cycle, emu.cpu.reg.pc);
+
+        emu.mmu.direct_write(0xC000, 0x00);
+        emu.load_state(&state).unwrap();
+        assert_eq!(emu.mmu.direct_read(0xC000), 0xC3);
+        assert_eq!(emu.mmu.timer.abs_cycle, CYCLES_PER_FRAME as u64);
+        emu.run_one_frame();
+        assert_eq!((emu.mmu.timer.abs_cycle, emu.cpu.reg.pc), (cycle, pc));
+
+        // States of other machines are rejected
+        let mut cgb = Emu::new(Machine::GameBoyCGB);
+        assert!(cgb.load_state(&state).is_err());
+        assert!(emu.load_state(&state[0..100]).is_err());
+    }
+
+    #[test]
+    fn test_skip_bootrom() {
+        use crate::gameboy::mmu::LCDC_REG;
+
+        // Without a cartridge, the header checksum is 0
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.skip_bootrom();
+        let reg = &emu.cpu.reg;
+        assert_eq!(
+            (reg.af(), reg.bc(), reg.de(), reg.hl()),
+            (0x0180, 0x0013, 0x00D8, 0x014D)
+        );
+        assert_eq!((reg.sp, reg.pc), (0xFFFE, 0x0100));
+        assert!(!emu.mmu.bootstrap_mode);
+        assert_eq!(emu.mmu.direct_read(LCDC_REG), 0x91);
+
+        // A reset without a boot ROM skips it again
+        emu.cpu.reg.pc = 0xC000;
+        emu.reset(ResetKind::Soft);
+        assert_eq!(emu.cpu.reg.pc, 0x0100);
+
+        let mut emu = Emu::new(Machine::GameBoyCGB);
+        emu.skip_bootrom();
+        assert_eq!((emu.cpu.reg.af(), emu.cpu.reg.de()), (0x1180, 0x0008));
+    }
+
+    #[test]
+    fn test_hot_swap_cartridge() {
+        let mut rom = vec![0x42; 0x8000];
+        rom[0x147..=0x149].fill(0);
+
+        let mut emu = jump_loop();
+        assert!(load_cartridge(&rom[..0x100].to_vec()).is_err());
+        emu.insert_cartridge(load_cartridge(&rom).unwrap());
+        assert_eq!(emu.mmu.direct_read(0x4000), 0x42);
+
+        // Open bus while ejected, and the machine keeps running
+        let cartridge = emu.eject_cartridge();
+        assert_eq!(emu.mmu.direct_read(0x4000), 0xFF);
+        assert_eq!(emu.mmu.direct_read(0xA000), 0xFF);
+        assert_eq!(emu.step_cycles(64), 64);
+        assert_eq!(emu.cpu.reg.pc, 0xC000);
+
+        emu.insert_cartridge(cartridge);
+        assert_eq!(emu.mmu.direct_read(0x0150), 0x42);
+        assert!(emu.insert_rom("no-such-rom.gb").is_err());
     }
 }
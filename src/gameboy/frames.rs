@@ -0,0 +1,109 @@
+// Frame streaming
+//
+// Runs the emulator a frame at a time, for tools such as encoders and
+// agents that work with whole frames rather than cycles:
+//
+//   let mut frames = emu.frames();
+//   while let Some(frame) = frames.next_frame() {
+//       encoder.push(frame.number, frame.framebuffer, frame.audio);
+//   }
+//
+// Each frame borrows the framebuffer of the emulator, so this is not
+// an `Iterator`: a frame must be dropped before the next one is run.
+// Input can be given between frames with `emu()`.
+
+use crate::core::Core;
+
+use super::emu::Emu;
+use super::mmu::LCDC_REG;
+use super::{CLOCK_SPEED, CYCLES_PER_FRAME};
+
+// Sample rate of the audio, unless the rates have already been set
+pub const DEFAULT_SAMPLE_RATE: f64 = 48000.0;
+
+pub struct FrameRef<'a> {
+    // Frame number of the PPU. Doesn't advance while the display is off.
+    pub number: usize,
+
+    // One palette index per pixel, in the format of the PPU buffer
+    pub framebuffer: &'a [u8],
+
+    // Mono audio samples produced during the frame
+    pub audio: &'a [i16],
+}
+
+pub struct Frames<'a> {
+    emu: &'a mut Emu,
+    audio: Vec<i16>,
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(emu: &'a mut Emu) -> Self {
+        if emu.mmu.apu.sample_rate() == 0.0 {
+            emu.set_audio_rates(CLOCK_SPEED as f64 / 4.0, DEFAULT_SAMPLE_RATE);
+        }
+        Frames {
+            emu,
+            audio: Vec::new(),
+        }
+    }
+
+    // Run until the next frame is complete. While the display is off
+    // no frames are produced, so a frame worth of cycles counts as a
+    // frame, with the framebuffer unchanged. The first frame after the
+    // display is enabled may take longer, so that is given two frames
+    // worth of cycles. Never ends, but returns an Option so that it
+    // can be used with `while let`.
+    pub fn next_frame(&mut self) -> Option<FrameRef<'_>> {
+        let emu = &mut *self.emu;
+        let frame = emu.mmu.ppu.frame_number;
+        let start = emu.mmu.timer.abs_cycle;
+        let max_cycles = match emu.mmu.direct_read(LCDC_REG) & 0x80 != 0 {
+            true => 2 * CYCLES_PER_FRAME as u64,
+            false => CYCLES_PER_FRAME as u64,
+        };
+        while emu.mmu.ppu.frame_number == frame && emu.mmu.timer.abs_cycle - start < max_cycles {
+            emu.exec_op();
+        }
+
+        emu.end_audio_frame();
+        self.audio.clear();
+        emu.read_audio_samples(&mut self.audio);
+
+        Some(FrameRef {
+            number: emu.mmu.ppu.frame_number,
+            framebuffer: &emu.mmu.ppu.buffer,
+            audio: &self.audio,
+        })
+    }
+
+    // The emulator, for example to press buttons between frames
+    pub fn emu(&mut self) -> &mut Emu {
+        &mut *self.emu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::Machine;
+
+    #[test]
+    fn test_frames() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.skip_bootrom();
+        let mut frames = emu.frames();
+
+        let first = frames.next_frame().unwrap().number;
+        let frame = frames.next_frame().unwrap();
+        assert_eq!(frame.number, first + 1);
+        assert_eq!(frame.framebuffer.len(), 160 * 144);
+        assert!(!frame.audio.is_empty());
+
+        // With the display off, frames are still produced
+        frames.emu().mmu.direct_write(LCDC_REG, 0x00);
+        let cycle = frames.emu().mmu.timer.abs_cycle;
+        frames.next_frame().unwrap();
+        assert!(frames.emu().mmu.timer.abs_cycle - cycle >= CYCLES_PER_FRAME as u64);
+    }
+}
@@ -1,4 +1,5 @@
-use super::mmu::{IE_REG, IF_REG, MMU};
+use super::cpu::{Bus, Cpu};
+use super::mmu::{IE_REG, IF_REG, KEY1_REG};
 use super::registers::Registers;
 
 pub fn _op_cycles(op: u8) -> u32 {
@@ -55,26 +56,26 @@ fn add_i8_to_u16(a: u16, b: i8) -> u16 {
 
 // 16-bit push operation
 // Flags: - - - -
-pub fn push_op(mmu: &mut MMU, value: u16) {
+pub fn push_op(cpu: &mut Cpu, bus: &mut impl Bus, value: u16) {
     // For correct emulation the high byte is pushed first, then the low byte
-    let sp = mmu.reg.sp.wrapping_sub(1);
-    mmu.write(sp as usize, ((value >> 8) & 0xFF) as u8);
+    let sp = cpu.reg.sp.wrapping_sub(1);
+    bus.write(sp as usize, ((value >> 8) & 0xFF) as u8);
     let sp = sp.wrapping_sub(1);
-    mmu.write(sp as usize, (value & 0xFF) as u8);
+    bus.write(sp as usize, (value & 0xFF) as u8);
 
-    mmu.reg.sp = sp;
+    cpu.reg.sp = sp;
 }
 
 // 16-bit pop operation
 // Flags: - - - -
 // Cycles: 12
 // Note that flags are still affected by POP AF
-fn pop_op(mmu: &mut MMU) -> u16 {
-    let sp = mmu.reg.sp;
-    let lo = mmu.read(sp as usize);
+fn pop_op(cpu: &mut Cpu, bus: &mut impl Bus) -> u16 {
+    let sp = cpu.reg.sp;
+    let lo = bus.read(sp as usize);
     let sp = sp.wrapping_add(1);
-    let hi = mmu.read(sp as usize);
-    mmu.reg.sp = sp.wrapping_add(1);
+    let hi = bus.read(sp as usize);
+    cpu.reg.sp = sp.wrapping_add(1);
     ((hi as u16) << 8) | (lo as u16)
 }
 
@@ -223,11 +224,11 @@ pub fn swap_op(reg: &mut Registers, value: u8) -> u8 {
     res
 }
 
-pub fn rst_op(mmu: &mut MMU, address: u16) {
-    let pc = mmu.reg.pc;
-    mmu.tick(4);
-    push_op(mmu, pc);
-    mmu.reg.pc = address;
+pub fn rst_op(cpu: &mut Cpu, bus: &mut impl Bus, address: u16) {
+    let pc = cpu.reg.pc;
+    bus.tick(4);
+    push_op(cpu, bus, pc);
+    cpu.reg.pc = address;
 }
 
 pub fn rrc_op(reg: &mut Registers, value: u8) -> u8 {
@@ -330,8 +331,8 @@ fn daa_op(reg: &mut Registers) {
     reg.half_carry = false;
 }
 
-pub fn step(mmu: &mut MMU) {
-    let op: u8 = mmu.fetch();
+pub fn step(cpu: &mut Cpu, bus: &mut impl Bus) {
+    let op: u8 = cpu.fetch(bus);
 
     match op {
         // NOP: no operation
@@ -345,13 +346,13 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: - - - -
         0x76 => {
-            if mmu.reg.ime != 0 {
-                mmu.reg.halted = true;
+            if cpu.reg.ime != 0 {
+                cpu.reg.halted = true;
             } else {
-                let if_reg = mmu.direct_read(IF_REG);
-                let ie_reg = mmu.direct_read(IE_REG);
+                let if_reg = bus.peek(IF_REG);
+                let ie_reg = bus.peek(IE_REG);
                 if if_reg & ie_reg & 0x1F == 0 {
-                    mmu.reg.halted = true;
+                    cpu.reg.halted = true;
                 } else {
                     // FIXME: Emulate HALT bug: next op is executed twice
                     // if a single byte op. If a multi byte op, it's even worse.
@@ -365,35 +366,35 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: - 0 0 1
         0x37 => {
-            mmu.reg.neg = false;
-            mmu.reg.half_carry = false;
-            mmu.reg.carry = true;
+            cpu.reg.neg = false;
+            cpu.reg.half_carry = false;
+            cpu.reg.carry = true;
         }
 
         // DAA: ...
         // Length: 1
         // Cycles: 4
         // Flags: Z - 0 C
-        0x27 => daa_op(&mut mmu.reg),
+        0x27 => daa_op(&mut cpu.reg),
 
         // LD rr, d16: load immediate (d16) into 16-bit register rr
         // Length: 3
         // Cycles: 12
         // Flags: - - - -
         0x01 => {
-            mmu.reg.c = mmu.fetch();
-            mmu.reg.b = mmu.fetch();
+            cpu.reg.c = cpu.fetch(bus);
+            cpu.reg.b = cpu.fetch(bus);
         }
         0x11 => {
-            mmu.reg.e = mmu.fetch();
-            mmu.reg.d = mmu.fetch();
+            cpu.reg.e = cpu.fetch(bus);
+            cpu.reg.d = cpu.fetch(bus);
         }
         0x21 => {
-            mmu.reg.l = mmu.fetch();
-            mmu.reg.h = mmu.fetch();
+            cpu.reg.l = cpu.fetch(bus);
+            cpu.reg.h = cpu.fetch(bus);
         }
         0x31 => {
-            mmu.reg.sp = mmu.fetch_u16();
+            cpu.reg.sp = cpu.fetch_u16(bus);
         }
 
         // LD (rr), A: stores the contents of register A in the memory specified by register pair BC or DE.
@@ -401,14 +402,14 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x02 => {
-            let bc = mmu.reg.bc();
-            let a = mmu.reg.a;
-            mmu.write(bc as usize, a);
+            let bc = cpu.reg.bc();
+            let a = cpu.reg.a;
+            bus.write(bc as usize, a);
         }
         0x12 => {
-            let de = mmu.reg.de();
-            let a = mmu.reg.a;
-            mmu.write(de as usize, a);
+            let de = cpu.reg.de();
+            let a = cpu.reg.a;
+            bus.write(de as usize, a);
         }
 
         // LD A, (nn): loads value stored in memory at address nn (immediate)
@@ -416,8 +417,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16
         // Flags: - - - -
         0xFA => {
-            let addr = mmu.fetch_u16();
-            mmu.reg.a = mmu.read(addr as usize);
+            let addr = cpu.fetch_u16(bus);
+            cpu.reg.a = bus.read(addr as usize);
         }
 
         // INC n: increment register n
@@ -425,32 +426,32 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: Z 0 H -
         0x04 => {
-            let b = mmu.reg.b;
-            mmu.reg.b = inc_op(&mut mmu.reg, b);
+            let b = cpu.reg.b;
+            cpu.reg.b = inc_op(&mut cpu.reg, b);
         }
         0x0C => {
-            let c = mmu.reg.c;
-            mmu.reg.c = inc_op(&mut mmu.reg, c);
+            let c = cpu.reg.c;
+            cpu.reg.c = inc_op(&mut cpu.reg, c);
         }
         0x14 => {
-            let d = mmu.reg.d;
-            mmu.reg.d = inc_op(&mut mmu.reg, d);
+            let d = cpu.reg.d;
+            cpu.reg.d = inc_op(&mut cpu.reg, d);
         }
         0x1C => {
-            let e = mmu.reg.e;
-            mmu.reg.e = inc_op(&mut mmu.reg, e);
+            let e = cpu.reg.e;
+            cpu.reg.e = inc_op(&mut cpu.reg, e);
         }
         0x24 => {
-            let h = mmu.reg.h;
-            mmu.reg.h = inc_op(&mut mmu.reg, h);
+            let h = cpu.reg.h;
+            cpu.reg.h = inc_op(&mut cpu.reg, h);
         }
         0x2C => {
-            let l = mmu.reg.l;
-            mmu.reg.l = inc_op(&mut mmu.reg, l);
+            let l = cpu.reg.l;
+            cpu.reg.l = inc_op(&mut cpu.reg, l);
         }
         0x3C => {
-            let a = mmu.reg.a;
-            mmu.reg.a = inc_op(&mut mmu.reg, a);
+            let a = cpu.reg.a;
+            cpu.reg.a = inc_op(&mut cpu.reg, a);
         }
 
         // INC (HL): increment memory stored at HL
@@ -458,35 +459,35 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12
         // Flags: Z 0 H -
         0x34 => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            let v = inc_op(&mut mmu.reg, v);
-            mmu.write(hl as usize, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            let v = inc_op(&mut cpu.reg, v);
+            bus.write(hl as usize, v);
         }
 
         // INC nn: increments content of register pair nn by 1
         // Length: 1
         // Cycles: 8
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0x03 => {
-            let bc = inc16_op(mmu.reg.bc());
-            mmu.reg.set_bc(bc);
-            mmu.tick(4);
+            let bc = inc16_op(cpu.reg.bc());
+            cpu.reg.set_bc(bc);
+            bus.tick(4);
         }
         0x13 => {
-            let de = inc16_op(mmu.reg.de());
-            mmu.reg.set_de(de);
-            mmu.tick(4);
+            let de = inc16_op(cpu.reg.de());
+            cpu.reg.set_de(de);
+            bus.tick(4);
         }
         0x23 => {
-            let hl = inc16_op(mmu.reg.hl());
-            mmu.reg.set_hl(hl);
-            mmu.tick(4);
+            let hl = inc16_op(cpu.reg.hl());
+            cpu.reg.set_hl(hl);
+            bus.tick(4);
         }
         0x33 => {
-            mmu.reg.sp = inc16_op(mmu.reg.sp);
-            mmu.tick(4);
+            cpu.reg.sp = inc16_op(cpu.reg.sp);
+            bus.tick(4);
         }
 
         // DEC n: decrement register n
@@ -494,57 +495,57 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: Z 1 H -
         0x05 => {
-            let b = mmu.reg.b;
-            mmu.reg.b = dec_op(&mut mmu.reg, b);
+            let b = cpu.reg.b;
+            cpu.reg.b = dec_op(&mut cpu.reg, b);
         }
         0x0D => {
-            let c = mmu.reg.c;
-            mmu.reg.c = dec_op(&mut mmu.reg, c);
+            let c = cpu.reg.c;
+            cpu.reg.c = dec_op(&mut cpu.reg, c);
         }
         0x15 => {
-            let d = mmu.reg.d;
-            mmu.reg.d = dec_op(&mut mmu.reg, d);
+            let d = cpu.reg.d;
+            cpu.reg.d = dec_op(&mut cpu.reg, d);
         }
         0x1D => {
-            let e = mmu.reg.e;
-            mmu.reg.e = dec_op(&mut mmu.reg, e);
+            let e = cpu.reg.e;
+            cpu.reg.e = dec_op(&mut cpu.reg, e);
         }
         0x25 => {
-            let h = mmu.reg.h;
-            mmu.reg.h = dec_op(&mut mmu.reg, h);
+            let h = cpu.reg.h;
+            cpu.reg.h = dec_op(&mut cpu.reg, h);
         }
         0x2D => {
-            let l = mmu.reg.l;
-            mmu.reg.l = dec_op(&mut mmu.reg, l);
+            let l = cpu.reg.l;
+            cpu.reg.l = dec_op(&mut cpu.reg, l);
         }
         0x3D => {
-            let a = mmu.reg.a;
-            mmu.reg.a = dec_op(&mut mmu.reg, a);
+            let a = cpu.reg.a;
+            cpu.reg.a = dec_op(&mut cpu.reg, a);
         }
 
         // DEC rr: decrement register pair rr
         // Length: 1
         // Cycles: 8
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0x0B => {
-            let bc = mmu.reg.bc();
-            mmu.reg.set_bc(bc.wrapping_sub(1));
-            mmu.tick(4);
+            let bc = cpu.reg.bc();
+            cpu.reg.set_bc(bc.wrapping_sub(1));
+            bus.tick(4);
         }
         0x1B => {
-            let de = mmu.reg.de();
-            mmu.reg.set_de(de.wrapping_sub(1));
-            mmu.tick(4);
+            let de = cpu.reg.de();
+            cpu.reg.set_de(de.wrapping_sub(1));
+            bus.tick(4);
         }
         0x2B => {
-            let hl = mmu.reg.hl();
-            mmu.reg.set_hl(hl.wrapping_sub(1));
-            mmu.tick(4);
+            let hl = cpu.reg.hl();
+            cpu.reg.set_hl(hl.wrapping_sub(1));
+            bus.tick(4);
         }
         0x3B => {
-            mmu.reg.sp = mmu.reg.sp.wrapping_sub(1);
-            mmu.tick(4);
+            cpu.reg.sp = cpu.reg.sp.wrapping_sub(1);
+            bus.tick(4);
         }
 
         // DEC (HL): decrement memory stored at HL
@@ -552,10 +553,10 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12
         // Flags: Z 1 H -
         0x35 => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            let v = dec_op(&mut mmu.reg, v);
-            mmu.write(hl as usize, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            let v = dec_op(&mut cpu.reg, v);
+            bus.write(hl as usize, v);
         }
 
         // ADD r, ADD (hl): add register r or value at (hl) to accumulator
@@ -563,37 +564,37 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for op 0x86)
         // Flags: Z 0 H C
         0x80 => {
-            let b = mmu.reg.b;
-            add_op(&mut mmu.reg, b);
+            let b = cpu.reg.b;
+            add_op(&mut cpu.reg, b);
         }
         0x81 => {
-            let c = mmu.reg.c;
-            add_op(&mut mmu.reg, c);
+            let c = cpu.reg.c;
+            add_op(&mut cpu.reg, c);
         }
         0x82 => {
-            let d = mmu.reg.d;
-            add_op(&mut mmu.reg, d);
+            let d = cpu.reg.d;
+            add_op(&mut cpu.reg, d);
         }
         0x83 => {
-            let e = mmu.reg.e;
-            add_op(&mut mmu.reg, e);
+            let e = cpu.reg.e;
+            add_op(&mut cpu.reg, e);
         }
         0x84 => {
-            let h = mmu.reg.h;
-            add_op(&mut mmu.reg, h);
+            let h = cpu.reg.h;
+            add_op(&mut cpu.reg, h);
         }
         0x85 => {
-            let l = mmu.reg.l;
-            add_op(&mut mmu.reg, l);
+            let l = cpu.reg.l;
+            add_op(&mut cpu.reg, l);
         }
         0x86 => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            add_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            add_op(&mut cpu.reg, v);
         }
         0x87 => {
-            let a = mmu.reg.a;
-            add_op(&mut mmu.reg, a)
+            let a = cpu.reg.a;
+            add_op(&mut cpu.reg, a)
         }
 
         // ADD A, d8: add immediate value d8 to A
@@ -601,8 +602,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: Z 0 H C
         0xC6 => {
-            let v = mmu.fetch();
-            add_op(&mut mmu.reg, v);
+            let v = cpu.fetch(bus);
+            add_op(&mut cpu.reg, v);
         }
 
         // ADC A, r: add register r + carry to A
@@ -610,37 +611,37 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for op 0x8E)
         // Flags: Z 0 H C
         0x88 => {
-            let b = mmu.reg.b;
-            adc_op(&mut mmu.reg, b);
+            let b = cpu.reg.b;
+            adc_op(&mut cpu.reg, b);
         }
         0x89 => {
-            let c = mmu.reg.c;
-            adc_op(&mut mmu.reg, c);
+            let c = cpu.reg.c;
+            adc_op(&mut cpu.reg, c);
         }
         0x8A => {
-            let d = mmu.reg.d;
-            adc_op(&mut mmu.reg, d);
+            let d = cpu.reg.d;
+            adc_op(&mut cpu.reg, d);
         }
         0x8B => {
-            let e = mmu.reg.e;
-            adc_op(&mut mmu.reg, e);
+            let e = cpu.reg.e;
+            adc_op(&mut cpu.reg, e);
         }
         0x8C => {
-            let h = mmu.reg.h;
-            adc_op(&mut mmu.reg, h);
+            let h = cpu.reg.h;
+            adc_op(&mut cpu.reg, h);
         }
         0x8D => {
-            let l = mmu.reg.l;
-            adc_op(&mut mmu.reg, l);
+            let l = cpu.reg.l;
+            adc_op(&mut cpu.reg, l);
         }
         0x8E => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            adc_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            adc_op(&mut cpu.reg, v);
         }
         0x8F => {
-            let a = mmu.reg.a;
-            adc_op(&mut mmu.reg, a);
+            let a = cpu.reg.a;
+            adc_op(&mut cpu.reg, a);
         }
 
         // ADC A, d8: add immediate value + carry to A
@@ -649,8 +650,8 @@ pub fn step(mmu: &mut MMU) {
         // Flags: Z 0 H C
         //0xCE => { let d8 = mem.read(reg.pc + 1); adc_op(reg, d8) }
         0xCE => {
-            let v = mmu.fetch();
-            adc_op(&mut mmu.reg, v);
+            let v = cpu.fetch(bus);
+            adc_op(&mut cpu.reg, v);
         }
 
         // SBC A, r: subtract register r and carry from A
@@ -658,69 +659,69 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8)
         // Flags: Z 1 H C
         0x98 => {
-            let b = mmu.reg.b;
-            sbc_op(&mut mmu.reg, b)
+            let b = cpu.reg.b;
+            sbc_op(&mut cpu.reg, b)
         }
         0x99 => {
-            let c = mmu.reg.c;
-            sbc_op(&mut mmu.reg, c)
+            let c = cpu.reg.c;
+            sbc_op(&mut cpu.reg, c)
         }
         0x9A => {
-            let d = mmu.reg.d;
-            sbc_op(&mut mmu.reg, d)
+            let d = cpu.reg.d;
+            sbc_op(&mut cpu.reg, d)
         }
         0x9B => {
-            let e = mmu.reg.e;
-            sbc_op(&mut mmu.reg, e)
+            let e = cpu.reg.e;
+            sbc_op(&mut cpu.reg, e)
         }
         0x9C => {
-            let h = mmu.reg.h;
-            sbc_op(&mut mmu.reg, h)
+            let h = cpu.reg.h;
+            sbc_op(&mut cpu.reg, h)
         }
         0x9D => {
-            let l = mmu.reg.l;
-            sbc_op(&mut mmu.reg, l)
+            let l = cpu.reg.l;
+            sbc_op(&mut cpu.reg, l)
         }
         0x9E => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            sbc_op(&mut mmu.reg, v)
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            sbc_op(&mut cpu.reg, v)
         }
         0x9F => {
-            let a = mmu.reg.a;
-            sbc_op(&mut mmu.reg, a)
+            let a = cpu.reg.a;
+            sbc_op(&mut cpu.reg, a)
         }
 
         // SBC A, d8: subtract immediate value and carry from A
         0xDE => {
-            let d8 = mmu.fetch();
-            sbc_op(&mut mmu.reg, d8)
+            let d8 = cpu.fetch(bus);
+            sbc_op(&mut cpu.reg, d8)
         }
 
         // ADD HL, rr: adds value of register pair rr to HL and stores result in HL
         // Length: 1
         // Cycles: 8
         // Flags: - 0 H C
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0x09 => {
-            let bc = mmu.reg.bc();
-            add_hl_op(&mut mmu.reg, bc);
-            mmu.tick(4);
+            let bc = cpu.reg.bc();
+            add_hl_op(&mut cpu.reg, bc);
+            bus.tick(4);
         }
         0x19 => {
-            let de = mmu.reg.de();
-            add_hl_op(&mut mmu.reg, de);
-            mmu.tick(4);
+            let de = cpu.reg.de();
+            add_hl_op(&mut cpu.reg, de);
+            bus.tick(4);
         }
         0x29 => {
-            let hl = mmu.reg.hl();
-            add_hl_op(&mut mmu.reg, hl);
-            mmu.tick(4);
+            let hl = cpu.reg.hl();
+            add_hl_op(&mut cpu.reg, hl);
+            bus.tick(4);
         }
         0x39 => {
-            let sp = mmu.reg.sp;
-            add_hl_op(&mut mmu.reg, sp);
-            mmu.tick(4);
+            let sp = cpu.reg.sp;
+            add_hl_op(&mut cpu.reg, sp);
+            bus.tick(4);
         }
 
         // ADD SP, d8: add immediate value d8 to SP
@@ -730,17 +731,17 @@ pub fn step(mmu: &mut MMU) {
         // TODO: this is very similar to the add_hl_op. could they be combined?
         0xE8 => {
             // let value = mem.read_i8(reg.pc + 1) as u16;
-            let value = mmu.fetch() as i8 as u16;
+            let value = cpu.fetch(bus) as i8 as u16;
 
-            let hc = ((mmu.reg.sp & 0x0F) + (value & 0x0F)) > 0x0F;
+            let hc = ((cpu.reg.sp & 0x0F) + (value & 0x0F)) > 0x0F;
 
-            mmu.reg.half_carry = hc;
-            mmu.reg.carry = (mmu.reg.sp & 0xFF) + (value & 0xFF) > 0xFF;
-            mmu.reg.zero = false;
-            mmu.reg.neg = false;
+            cpu.reg.half_carry = hc;
+            cpu.reg.carry = (cpu.reg.sp & 0xFF) + (value & 0xFF) > 0xFF;
+            cpu.reg.zero = false;
+            cpu.reg.neg = false;
 
-            mmu.reg.sp = mmu.reg.sp.wrapping_add(value);
-            mmu.tick(8);
+            cpu.reg.sp = cpu.reg.sp.wrapping_add(value);
+            bus.tick(8);
         }
 
         // SUB r, SUB (hl): subtract register r or value at (hl) from accumulator
@@ -748,37 +749,37 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for op 0x96)
         // Flags: Z 1 H C
         0x90 => {
-            let b = mmu.reg.b;
-            sub_op(&mut mmu.reg, b)
+            let b = cpu.reg.b;
+            sub_op(&mut cpu.reg, b)
         }
         0x91 => {
-            let c = mmu.reg.c;
-            sub_op(&mut mmu.reg, c)
+            let c = cpu.reg.c;
+            sub_op(&mut cpu.reg, c)
         }
         0x92 => {
-            let d = mmu.reg.d;
-            sub_op(&mut mmu.reg, d)
+            let d = cpu.reg.d;
+            sub_op(&mut cpu.reg, d)
         }
         0x93 => {
-            let e = mmu.reg.e;
-            sub_op(&mut mmu.reg, e)
+            let e = cpu.reg.e;
+            sub_op(&mut cpu.reg, e)
         }
         0x94 => {
-            let h = mmu.reg.h;
-            sub_op(&mut mmu.reg, h)
+            let h = cpu.reg.h;
+            sub_op(&mut cpu.reg, h)
         }
         0x95 => {
-            let l = mmu.reg.l;
-            sub_op(&mut mmu.reg, l)
+            let l = cpu.reg.l;
+            sub_op(&mut cpu.reg, l)
         }
         0x96 => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            sub_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            sub_op(&mut cpu.reg, v);
         }
         0x97 => {
-            let a = mmu.reg.a;
-            sub_op(&mut mmu.reg, a)
+            let a = cpu.reg.a;
+            sub_op(&mut cpu.reg, a)
         }
 
         // SUB d8: subtract immediate value d8 from A
@@ -786,8 +787,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: Z 1 H C
         0xD6 => {
-            let v = mmu.fetch();
-            sub_op(&mut mmu.reg, v);
+            let v = cpu.fetch(bus);
+            sub_op(&mut cpu.reg, v);
         }
 
         // AND r, AND (hl), AND d8: set A to "A AND r", or "A AND (hl)""
@@ -795,41 +796,41 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for op 0xA6 and 0xE6)
         // Flags: Z 0 1 0
         0xA0 => {
-            let b = mmu.reg.b;
-            and_op(&mut mmu.reg, b)
+            let b = cpu.reg.b;
+            and_op(&mut cpu.reg, b)
         }
         0xA1 => {
-            let c = mmu.reg.c;
-            and_op(&mut mmu.reg, c)
+            let c = cpu.reg.c;
+            and_op(&mut cpu.reg, c)
         }
         0xA2 => {
-            let d = mmu.reg.d;
-            and_op(&mut mmu.reg, d)
+            let d = cpu.reg.d;
+            and_op(&mut cpu.reg, d)
         }
         0xA3 => {
-            let e = mmu.reg.e;
-            and_op(&mut mmu.reg, e)
+            let e = cpu.reg.e;
+            and_op(&mut cpu.reg, e)
         }
         0xA4 => {
-            let h = mmu.reg.h;
-            and_op(&mut mmu.reg, h)
+            let h = cpu.reg.h;
+            and_op(&mut cpu.reg, h)
         }
         0xA5 => {
-            let l = mmu.reg.l;
-            and_op(&mut mmu.reg, l)
+            let l = cpu.reg.l;
+            and_op(&mut cpu.reg, l)
         }
         0xA6 => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            and_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            and_op(&mut cpu.reg, v);
         }
         0xA7 => {
-            let a = mmu.reg.a;
-            and_op(&mut mmu.reg, a)
+            let a = cpu.reg.a;
+            and_op(&mut cpu.reg, a)
         }
         0xE6 => {
-            let v = mmu.fetch();
-            and_op(&mut mmu.reg, v)
+            let v = cpu.fetch(bus);
+            and_op(&mut cpu.reg, v)
         }
 
         // OR r, OR (hl): set A to "A OR r", or "A OR (hl)""
@@ -837,41 +838,41 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for op 0xB6 and 0xF6)
         // Flags: Z 0 0 0
         0xB0 => {
-            let b = mmu.reg.b;
-            or_op(&mut mmu.reg, b)
+            let b = cpu.reg.b;
+            or_op(&mut cpu.reg, b)
         }
         0xB1 => {
-            let c = mmu.reg.c;
-            or_op(&mut mmu.reg, c)
+            let c = cpu.reg.c;
+            or_op(&mut cpu.reg, c)
         }
         0xB2 => {
-            let d = mmu.reg.d;
-            or_op(&mut mmu.reg, d)
+            let d = cpu.reg.d;
+            or_op(&mut cpu.reg, d)
         }
         0xB3 => {
-            let e = mmu.reg.e;
-            or_op(&mut mmu.reg, e)
+            let e = cpu.reg.e;
+            or_op(&mut cpu.reg, e)
         }
         0xB4 => {
-            let h = mmu.reg.h;
-            or_op(&mut mmu.reg, h)
+            let h = cpu.reg.h;
+            or_op(&mut cpu.reg, h)
         }
         0xB5 => {
-            let l = mmu.reg.l;
-            or_op(&mut mmu.reg, l)
+            let l = cpu.reg.l;
+            or_op(&mut cpu.reg, l)
         }
         0xB6 => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            or_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            or_op(&mut cpu.reg, v);
         }
         0xB7 => {
-            let a = mmu.reg.a;
-            or_op(&mut mmu.reg, a)
+            let a = cpu.reg.a;
+            or_op(&mut cpu.reg, a)
         }
         0xF6 => {
-            let v = mmu.fetch();
-            or_op(&mut mmu.reg, v)
+            let v = cpu.fetch(bus);
+            or_op(&mut cpu.reg, v)
         }
 
         // RRCA: ...
@@ -880,9 +881,9 @@ pub fn step(mmu: &mut MMU) {
         // Flags: 0 0 0 C
         // Note that rrc_op() sets Z flag, but RRCA should always clear Z flag
         0x0F => {
-            let a = mmu.reg.a;
-            mmu.reg.a = rrc_op(&mut mmu.reg, a);
-            mmu.reg.zero = false;
+            let a = cpu.reg.a;
+            cpu.reg.a = rrc_op(&mut cpu.reg, a);
+            cpu.reg.zero = false;
         }
 
         // RRA: ...
@@ -891,81 +892,81 @@ pub fn step(mmu: &mut MMU) {
         // Flags: 0 0 0 C
         // Note that rr_op() sets Z flag, but RRA should always clear Z flag
         0x1F => {
-            let a = mmu.reg.a;
-            mmu.reg.a = rr_op(&mut mmu.reg, a);
-            mmu.reg.zero = false;
+            let a = cpu.reg.a;
+            cpu.reg.a = rr_op(&mut cpu.reg, a);
+            cpu.reg.zero = false;
         }
 
         // LD n, d: load immediate into register n
         // Length: 2
         // Cycles: 8
         // Flags: - - - -
-        0x06 => mmu.reg.b = mmu.fetch(),
-        0x0E => mmu.reg.c = mmu.fetch(),
-        0x16 => mmu.reg.d = mmu.fetch(),
-        0x1E => mmu.reg.e = mmu.fetch(),
-        0x26 => mmu.reg.h = mmu.fetch(),
-        0x2E => mmu.reg.l = mmu.fetch(),
-        0x3E => mmu.reg.a = mmu.fetch(),
+        0x06 => cpu.reg.b = cpu.fetch(bus),
+        0x0E => cpu.reg.c = cpu.fetch(bus),
+        0x16 => cpu.reg.d = cpu.fetch(bus),
+        0x1E => cpu.reg.e = cpu.fetch(bus),
+        0x26 => cpu.reg.h = cpu.fetch(bus),
+        0x2E => cpu.reg.l = cpu.fetch(bus),
+        0x3E => cpu.reg.a = cpu.fetch(bus),
 
         // LD n, m: load value of register m into register n
         // Length: 1
         // Cycles: 4
         // Flags: - - - -
         0x7F => {}                     // LD A,A
-        0x78 => mmu.reg.a = mmu.reg.b, // LD A,B
-        0x79 => mmu.reg.a = mmu.reg.c, // LD A,C
-        0x7A => mmu.reg.a = mmu.reg.d, // LD A,D
-        0x7B => mmu.reg.a = mmu.reg.e, // LD A,E
-        0x7C => mmu.reg.a = mmu.reg.h, // LD A,H
-        0x7D => mmu.reg.a = mmu.reg.l, // LD A,L
-
-        0x47 => mmu.reg.b = mmu.reg.a, // LD B,A
+        0x78 => cpu.reg.a = cpu.reg.b, // LD A,B
+        0x79 => cpu.reg.a = cpu.reg.c, // LD A,C
+        0x7A => cpu.reg.a = cpu.reg.d, // LD A,D
+        0x7B => cpu.reg.a = cpu.reg.e, // LD A,E
+        0x7C => cpu.reg.a = cpu.reg.h, // LD A,H
+        0x7D => cpu.reg.a = cpu.reg.l, // LD A,L
+
+        0x47 => cpu.reg.b = cpu.reg.a, // LD B,A
         0x40 => {}                     // LD B,B
-        0x41 => mmu.reg.b = mmu.reg.c, // LD B,C
-        0x42 => mmu.reg.b = mmu.reg.d, // LD B,D
-        0x43 => mmu.reg.b = mmu.reg.e, // LD B,E
-        0x44 => mmu.reg.b = mmu.reg.h, // LD B,H
-        0x45 => mmu.reg.b = mmu.reg.l, // LD B,L
-
-        0x4F => mmu.reg.c = mmu.reg.a, // LD C,A
-        0x48 => mmu.reg.c = mmu.reg.b, // LD C,B
+        0x41 => cpu.reg.b = cpu.reg.c, // LD B,C
+        0x42 => cpu.reg.b = cpu.reg.d, // LD B,D
+        0x43 => cpu.reg.b = cpu.reg.e, // LD B,E
+        0x44 => cpu.reg.b = cpu.reg.h, // LD B,H
+        0x45 => cpu.reg.b = cpu.reg.l, // LD B,L
+
+        0x4F => cpu.reg.c = cpu.reg.a, // LD C,A
+        0x48 => cpu.reg.c = cpu.reg.b, // LD C,B
         0x49 => {}                     // LD C,C
-        0x4A => mmu.reg.c = mmu.reg.d, // LD C,D
-        0x4B => mmu.reg.c = mmu.reg.e, // LD C,E
-        0x4C => mmu.reg.c = mmu.reg.h, // LD C,H
-        0x4D => mmu.reg.c = mmu.reg.l, // LD C,L
-
-        0x57 => mmu.reg.d = mmu.reg.a, // LD D,A
-        0x50 => mmu.reg.d = mmu.reg.b, // LD D,B
-        0x51 => mmu.reg.d = mmu.reg.c, // LD D,C
+        0x4A => cpu.reg.c = cpu.reg.d, // LD C,D
+        0x4B => cpu.reg.c = cpu.reg.e, // LD C,E
+        0x4C => cpu.reg.c = cpu.reg.h, // LD C,H
+        0x4D => cpu.reg.c = cpu.reg.l, // LD C,L
+
+        0x57 => cpu.reg.d = cpu.reg.a, // LD D,A
+        0x50 => cpu.reg.d = cpu.reg.b, // LD D,B
+        0x51 => cpu.reg.d = cpu.reg.c, // LD D,C
         0x52 => {}                     // LD D,D
-        0x53 => mmu.reg.d = mmu.reg.e, // LD D,E
-        0x54 => mmu.reg.d = mmu.reg.h, // LD D,H
-        0x55 => mmu.reg.d = mmu.reg.l, // LD D,L
-
-        0x5F => mmu.reg.e = mmu.reg.a, // LD E,A
-        0x58 => mmu.reg.e = mmu.reg.b, // LD E,B
-        0x59 => mmu.reg.e = mmu.reg.c, // LD E,C
-        0x5A => mmu.reg.e = mmu.reg.d, // LD E,D
+        0x53 => cpu.reg.d = cpu.reg.e, // LD D,E
+        0x54 => cpu.reg.d = cpu.reg.h, // LD D,H
+        0x55 => cpu.reg.d = cpu.reg.l, // LD D,L
+
+        0x5F => cpu.reg.e = cpu.reg.a, // LD E,A
+        0x58 => cpu.reg.e = cpu.reg.b, // LD E,B
+        0x59 => cpu.reg.e = cpu.reg.c, // LD E,C
+        0x5A => cpu.reg.e = cpu.reg.d, // LD E,D
         0x5B => {}                     // LD E,E
-        0x5C => mmu.reg.e = mmu.reg.h, // LD E,H
-        0x5D => mmu.reg.e = mmu.reg.l, // LD E,L
-
-        0x67 => mmu.reg.h = mmu.reg.a, // LD H,A
-        0x60 => mmu.reg.h = mmu.reg.b, // LD H,B
-        0x61 => mmu.reg.h = mmu.reg.c, // LD H,C
-        0x62 => mmu.reg.h = mmu.reg.d, // LD H,D
-        0x63 => mmu.reg.h = mmu.reg.e, // LD H,E
+        0x5C => cpu.reg.e = cpu.reg.h, // LD E,H
+        0x5D => cpu.reg.e = cpu.reg.l, // LD E,L
+
+        0x67 => cpu.reg.h = cpu.reg.a, // LD H,A
+        0x60 => cpu.reg.h = cpu.reg.b, // LD H,B
+        0x61 => cpu.reg.h = cpu.reg.c, // LD H,C
+        0x62 => cpu.reg.h = cpu.reg.d, // LD H,D
+        0x63 => cpu.reg.h = cpu.reg.e, // LD H,E
         0x64 => {}                     // LD H,H
-        0x65 => mmu.reg.h = mmu.reg.l, // LD H,L
-
-        0x6F => mmu.reg.l = mmu.reg.a, // LD L,A
-        0x68 => mmu.reg.l = mmu.reg.b, // LD L,B
-        0x69 => mmu.reg.l = mmu.reg.c, // LD L,C
-        0x6A => mmu.reg.l = mmu.reg.d, // LD L,D
-        0x6B => mmu.reg.l = mmu.reg.e, // LD L,E
-        0x6C => mmu.reg.l = mmu.reg.h, // LD L,H
+        0x65 => cpu.reg.h = cpu.reg.l, // LD H,L
+
+        0x6F => cpu.reg.l = cpu.reg.a, // LD L,A
+        0x68 => cpu.reg.l = cpu.reg.b, // LD L,B
+        0x69 => cpu.reg.l = cpu.reg.c, // LD L,C
+        0x6A => cpu.reg.l = cpu.reg.d, // LD L,D
+        0x6B => cpu.reg.l = cpu.reg.e, // LD L,E
+        0x6C => cpu.reg.l = cpu.reg.h, // LD L,H
         0x6D => {}                     // LD L,L
 
         // LD n, (hl): store value at (hl) in register n
@@ -973,32 +974,32 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x46 => {
-            let hl = mmu.reg.hl();
-            mmu.reg.b = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.b = bus.read(hl as usize)
         }
         0x4E => {
-            let hl = mmu.reg.hl();
-            mmu.reg.c = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.c = bus.read(hl as usize)
         }
         0x56 => {
-            let hl = mmu.reg.hl();
-            mmu.reg.d = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.d = bus.read(hl as usize)
         }
         0x5E => {
-            let hl = mmu.reg.hl();
-            mmu.reg.e = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.e = bus.read(hl as usize)
         }
         0x66 => {
-            let hl = mmu.reg.hl();
-            mmu.reg.h = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.h = bus.read(hl as usize)
         }
         0x6E => {
-            let hl = mmu.reg.hl();
-            mmu.reg.l = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.l = bus.read(hl as usize)
         }
         0x7E => {
-            let hl = mmu.reg.hl();
-            mmu.reg.a = mmu.read(hl as usize)
+            let hl = cpu.reg.hl();
+            cpu.reg.a = bus.read(hl as usize)
         }
 
         // LD n, (mm): load value from memory into register n
@@ -1006,12 +1007,12 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x0A => {
-            let bc = mmu.reg.bc();
-            mmu.reg.a = mmu.read(bc as usize)
+            let bc = cpu.reg.bc();
+            cpu.reg.a = bus.read(bc as usize)
         }
         0x1A => {
-            let de = mmu.reg.de();
-            mmu.reg.a = mmu.read(de as usize)
+            let de = cpu.reg.de();
+            cpu.reg.a = bus.read(de as usize)
         }
 
         // LD ($FF00+n), A: Put A into memory address $FF00+n
@@ -1019,10 +1020,10 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12
         // Flags: - - - -
         0xE0 => {
-            let n = mmu.fetch();
+            let n = cpu.fetch(bus);
             let adr = (n as u16).wrapping_add(0xFF00);
-            let a = mmu.reg.a;
-            mmu.write(adr as usize, a);
+            let a = cpu.reg.a;
+            bus.write(adr as usize, a);
         }
 
         // LD A, ($FF00+n): read from memory $FF00+n to register A
@@ -1030,8 +1031,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12
         // Flags: - - - -
         0xF0 => {
-            let n = mmu.fetch();
-            mmu.reg.a = mmu.read(0xFF00 + n as usize);
+            let n = cpu.fetch(bus);
+            cpu.reg.a = bus.read(0xFF00 + n as usize);
         }
 
         // LD (HL), n: store register value to memory at address HL
@@ -1039,50 +1040,50 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x70 => {
-            let hl = mmu.reg.hl();
-            let b = mmu.reg.b;
-            mmu.write(hl as usize, b)
+            let hl = cpu.reg.hl();
+            let b = cpu.reg.b;
+            bus.write(hl as usize, b)
         }
         0x71 => {
-            let hl = mmu.reg.hl();
-            let c = mmu.reg.c;
-            mmu.write(hl as usize, c)
+            let hl = cpu.reg.hl();
+            let c = cpu.reg.c;
+            bus.write(hl as usize, c)
         }
         0x72 => {
-            let hl = mmu.reg.hl();
-            let d = mmu.reg.d;
-            mmu.write(hl as usize, d)
+            let hl = cpu.reg.hl();
+            let d = cpu.reg.d;
+            bus.write(hl as usize, d)
         }
         0x73 => {
-            let hl = mmu.reg.hl();
-            let e = mmu.reg.e;
-            mmu.write(hl as usize, e)
+            let hl = cpu.reg.hl();
+            let e = cpu.reg.e;
+            bus.write(hl as usize, e)
         }
         0x74 => {
-            let hl = mmu.reg.hl();
-            let h = mmu.reg.h;
-            mmu.write(hl as usize, h)
+            let hl = cpu.reg.hl();
+            let h = cpu.reg.h;
+            bus.write(hl as usize, h)
         }
         0x75 => {
-            let hl = mmu.reg.hl();
-            let l = mmu.reg.l;
-            mmu.write(hl as usize, l)
+            let hl = cpu.reg.hl();
+            let l = cpu.reg.l;
+            bus.write(hl as usize, l)
         }
         0x77 => {
-            let hl = mmu.reg.hl();
-            let a = mmu.reg.a;
-            mmu.write(hl as usize, a)
+            let hl = cpu.reg.hl();
+            let a = cpu.reg.a;
+            bus.write(hl as usize, a)
         }
 
         // RET: set PC to 16-bit value popped from stack
         // Length: 1
         // Cycles: 16
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         // TODO: why is RET 16 cycles when POP BC is 12 cycles?
         0xC9 => {
-            mmu.reg.pc = pop_op(mmu);
-            mmu.tick(4);
+            cpu.reg.pc = pop_op(cpu, bus);
+            bus.tick(4);
         }
 
         // RETI: set PC to 16-bit value popped from stack and enable IME
@@ -1091,10 +1092,10 @@ pub fn step(mmu: &mut MMU) {
         // Flags: - - - -
         // This function is really EI followed by RET
         0xD9 => {
-            mmu.reg.ime = 1;
-            mmu.reg.pc = pop_op(mmu);
-            mmu.tick(4);
-            mmu.reg.ime = 2;
+            cpu.reg.ime = 1;
+            cpu.reg.pc = pop_op(cpu, bus);
+            bus.tick(4);
+            cpu.reg.ime = 2;
         }
 
         // RET Z: set PC to 16-bit value popped from stack if Z-flag is set
@@ -1104,33 +1105,33 @@ pub fn step(mmu: &mut MMU) {
         // Length: 1
         // Cycles: 20/8
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0xC8 => {
-            mmu.tick(4);
-            if mmu.reg.zero {
-                mmu.reg.pc = pop_op(mmu);
-                mmu.tick(4);
+            bus.tick(4);
+            if cpu.reg.zero {
+                cpu.reg.pc = pop_op(cpu, bus);
+                bus.tick(4);
             }
         }
         0xD8 => {
-            mmu.tick(4);
-            if mmu.reg.carry {
-                mmu.reg.pc = pop_op(mmu);
-                mmu.tick(4);
+            bus.tick(4);
+            if cpu.reg.carry {
+                cpu.reg.pc = pop_op(cpu, bus);
+                bus.tick(4);
             }
         }
         0xC0 => {
-            mmu.tick(4);
-            if !mmu.reg.zero {
-                mmu.reg.pc = pop_op(mmu);
-                mmu.tick(4);
+            bus.tick(4);
+            if !cpu.reg.zero {
+                cpu.reg.pc = pop_op(cpu, bus);
+                bus.tick(4);
             }
         }
         0xD0 => {
-            mmu.tick(4);
-            if !mmu.reg.carry {
-                mmu.reg.pc = pop_op(mmu);
-                mmu.tick(4);
+            bus.tick(4);
+            if !cpu.reg.carry {
+                cpu.reg.pc = pop_op(cpu, bus);
+                bus.tick(4);
             }
         }
 
@@ -1139,13 +1140,13 @@ pub fn step(mmu: &mut MMU) {
         // Length: 3
         // Cycles: 24
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0xCD => {
-            let to = mmu.fetch_u16();
-            let pc = mmu.reg.pc;
-            mmu.tick(4);
-            push_op(mmu, pc);
-            mmu.reg.pc = to;
+            let to = cpu.fetch_u16(bus);
+            let pc = cpu.reg.pc;
+            bus.tick(4);
+            push_op(cpu, bus, pc);
+            cpu.reg.pc = to;
         }
 
         // CALL NZ, a16: if Z-flag is not set, push address of next
@@ -1153,14 +1154,14 @@ pub fn step(mmu: &mut MMU) {
         // Length: 3
         // Cycles: 24/12
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0xC4 => {
-            let to = mmu.fetch_u16();
-            if !mmu.reg.zero {
-                let pc = mmu.reg.pc;
-                mmu.tick(4);
-                push_op(mmu, pc);
-                mmu.reg.pc = to;
+            let to = cpu.fetch_u16(bus);
+            if !cpu.reg.zero {
+                let pc = cpu.reg.pc;
+                bus.tick(4);
+                push_op(cpu, bus, pc);
+                cpu.reg.pc = to;
             }
         }
 
@@ -1169,14 +1170,14 @@ pub fn step(mmu: &mut MMU) {
         // Length: 3
         // Cycles: 24/12
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0xD4 => {
-            let to = mmu.fetch_u16();
-            if !mmu.reg.carry {
-                let pc = mmu.reg.pc;
-                mmu.tick(4);
-                push_op(mmu, pc);
-                mmu.reg.pc = to;
+            let to = cpu.fetch_u16(bus);
+            if !cpu.reg.carry {
+                let pc = cpu.reg.pc;
+                bus.tick(4);
+                push_op(cpu, bus, pc);
+                cpu.reg.pc = to;
             }
         }
 
@@ -1186,12 +1187,12 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 24/12
         // Flags: - - - -
         0xCC => {
-            let to = mmu.fetch_u16();
-            if mmu.reg.zero {
-                let pc = mmu.reg.pc;
-                mmu.tick(4);
-                push_op(mmu, pc);
-                mmu.reg.pc = to;
+            let to = cpu.fetch_u16(bus);
+            if cpu.reg.zero {
+                let pc = cpu.reg.pc;
+                bus.tick(4);
+                push_op(cpu, bus, pc);
+                cpu.reg.pc = to;
             }
         }
 
@@ -1201,12 +1202,12 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 24/12
         // Flags: - - - -
         0xDC => {
-            let to = mmu.fetch_u16();
-            if mmu.reg.carry {
-                let pc = mmu.reg.pc;
-                mmu.tick(4);
-                push_op(mmu, pc);
-                mmu.reg.pc = to;
+            let to = cpu.fetch_u16(bus);
+            if cpu.reg.carry {
+                let pc = cpu.reg.pc;
+                bus.tick(4);
+                push_op(cpu, bus, pc);
+                cpu.reg.pc = to;
             }
         }
 
@@ -1215,28 +1216,28 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16
         // Flags: - - - -
         0xC7 => {
-            rst_op(mmu, 0x0000);
+            rst_op(cpu, bus, 0x0000);
         }
         0xCF => {
-            rst_op(mmu, 0x0008);
+            rst_op(cpu, bus, 0x0008);
         }
         0xD7 => {
-            rst_op(mmu, 0x0010);
+            rst_op(cpu, bus, 0x0010);
         }
         0xDF => {
-            rst_op(mmu, 0x0018);
+            rst_op(cpu, bus, 0x0018);
         }
         0xE7 => {
-            rst_op(mmu, 0x0020);
+            rst_op(cpu, bus, 0x0020);
         }
         0xEF => {
-            rst_op(mmu, 0x0028);
+            rst_op(cpu, bus, 0x0028);
         }
         0xF7 => {
-            rst_op(mmu, 0x0030);
+            rst_op(cpu, bus, 0x0030);
         }
         0xFF => {
-            rst_op(mmu, 0x0038);
+            rst_op(cpu, bus, 0x0038);
         }
 
         // PUSH nn: push 16-bit register nn to stack
@@ -1244,24 +1245,24 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16
         // Flags: - - - -
         0xC5 => {
-            let bc = mmu.reg.bc();
-            mmu.tick(4);
-            push_op(mmu, bc);
+            let bc = cpu.reg.bc();
+            bus.tick(4);
+            push_op(cpu, bus, bc);
         }
         0xD5 => {
-            let de = mmu.reg.de();
-            mmu.tick(4);
-            push_op(mmu, de);
+            let de = cpu.reg.de();
+            bus.tick(4);
+            push_op(cpu, bus, de);
         }
         0xE5 => {
-            let hl = mmu.reg.hl();
-            mmu.tick(4);
-            push_op(mmu, hl);
+            let hl = cpu.reg.hl();
+            bus.tick(4);
+            push_op(cpu, bus, hl);
         }
         0xF5 => {
-            let af = mmu.reg.af();
-            mmu.tick(4);
-            push_op(mmu, af);
+            let af = cpu.reg.af();
+            bus.tick(4);
+            push_op(cpu, bus, af);
         }
 
         // POP nn: pop value from stack and store in 16-bit register nn
@@ -1269,20 +1270,20 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12
         // Flags: - - - -
         0xC1 => {
-            let v = pop_op(mmu);
-            mmu.reg.set_bc(v);
+            let v = pop_op(cpu, bus);
+            cpu.reg.set_bc(v);
         }
         0xD1 => {
-            let v = pop_op(mmu);
-            mmu.reg.set_de(v);
+            let v = pop_op(cpu, bus);
+            cpu.reg.set_de(v);
         }
         0xE1 => {
-            let v = pop_op(mmu);
-            mmu.reg.set_hl(v);
+            let v = pop_op(cpu, bus);
+            cpu.reg.set_hl(v);
         }
         0xF1 => {
-            let v = pop_op(mmu);
-            mmu.reg.set_af(v);
+            let v = pop_op(cpu, bus);
+            cpu.reg.set_af(v);
         }
 
         0xE2 => {
@@ -1292,9 +1293,9 @@ pub fn step(mmu: &mut MMU) {
             // Flags: - - - -
             // Note: The opcode table at pastraiser.com specify
             // invalid length of 2. The correct length is 1.
-            let addr = 0xFF00 + mmu.reg.c as u16;
-            let a = mmu.reg.a;
-            mmu.write(addr as usize, a);
+            let addr = 0xFF00 + cpu.reg.c as u16;
+            let a = cpu.reg.a;
+            bus.write(addr as usize, a);
         }
 
         // LD A, ($FF00+C): store value at address 0xFF00 + C in A
@@ -1302,25 +1303,25 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0xF2 => {
-            let addr = 0xFF00 + mmu.reg.c as u16;
-            mmu.reg.a = mmu.read(addr as usize);
+            let addr = 0xFF00 + cpu.reg.c as u16;
+            cpu.reg.a = bus.read(addr as usize);
         }
 
         // JR d8: relative jump
         // Length: 2
         // Cycles: 12
         // Flags: - - - -
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0x18 => {
-            let offs = mmu.fetch() as i8;
+            let offs = cpu.fetch(bus) as i8;
 
-            mmu.reg.pc = if offs >= 0 {
-                mmu.reg.pc.wrapping_add(offs as u16)
+            cpu.reg.pc = if offs >= 0 {
+                cpu.reg.pc.wrapping_add(offs as u16)
             } else {
-                mmu.reg.pc.wrapping_sub(-offs as u16)
+                cpu.reg.pc.wrapping_sub(-offs as u16)
             };
 
-            mmu.tick(4);
+            bus.tick(4);
         }
 
         // JR NZ, d8: jump d8 relative to PC if Z flag is not set
@@ -1328,14 +1329,14 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12/8
         // Flags: - - - -
         0x20 => {
-            let offs = mmu.fetch() as i8;
-            if !mmu.reg.zero {
-                mmu.reg.pc = if offs >= 0 {
-                    mmu.reg.pc.wrapping_add(offs as u16)
+            let offs = cpu.fetch(bus) as i8;
+            if !cpu.reg.zero {
+                cpu.reg.pc = if offs >= 0 {
+                    cpu.reg.pc.wrapping_add(offs as u16)
                 } else {
-                    mmu.reg.pc.wrapping_sub(-offs as u16)
+                    cpu.reg.pc.wrapping_sub(-offs as u16)
                 };
-                mmu.tick(4);
+                bus.tick(4);
             }
         }
 
@@ -1344,14 +1345,14 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12/8
         // Flags: - - - -
         0x30 => {
-            let offs = mmu.fetch() as i8;
-            if !mmu.reg.carry {
-                mmu.reg.pc = if offs >= 0 {
-                    mmu.reg.pc.wrapping_add(offs as u16)
+            let offs = cpu.fetch(bus) as i8;
+            if !cpu.reg.carry {
+                cpu.reg.pc = if offs >= 0 {
+                    cpu.reg.pc.wrapping_add(offs as u16)
                 } else {
-                    mmu.reg.pc.wrapping_sub(-offs as u16)
+                    cpu.reg.pc.wrapping_sub(-offs as u16)
                 };
-                mmu.tick(4);
+                bus.tick(4);
             }
         }
 
@@ -1360,14 +1361,14 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12/8
         // Flags: - - - -
         0x28 => {
-            let offs = mmu.fetch() as i8;
-            if mmu.reg.zero {
-                mmu.reg.pc = if offs >= 0 {
-                    mmu.reg.pc.wrapping_add(offs as u16)
+            let offs = cpu.fetch(bus) as i8;
+            if cpu.reg.zero {
+                cpu.reg.pc = if offs >= 0 {
+                    cpu.reg.pc.wrapping_add(offs as u16)
                 } else {
-                    mmu.reg.pc.wrapping_sub(-offs as u16)
+                    cpu.reg.pc.wrapping_sub(-offs as u16)
                 };
-                mmu.tick(4);
+                bus.tick(4);
             }
         }
 
@@ -1376,16 +1377,16 @@ pub fn step(mmu: &mut MMU) {
             // Length: 2
             // Cycles: 12/8
             // Flags: - - - -
-            let offs = mmu.fetch() as i8;
+            let offs = cpu.fetch(bus) as i8;
 
-            if mmu.reg.carry {
-                mmu.reg.pc = if offs >= 0 {
-                    mmu.reg.pc.wrapping_add(offs as u16)
+            if cpu.reg.carry {
+                cpu.reg.pc = if offs >= 0 {
+                    cpu.reg.pc.wrapping_add(offs as u16)
                 } else {
-                    mmu.reg.pc.wrapping_sub(-offs as u16)
+                    cpu.reg.pc.wrapping_sub(-offs as u16)
                 };
 
-                mmu.tick(4);
+                bus.tick(4);
             }
         }
 
@@ -1395,17 +1396,17 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16/12
         // Flags: - - - -
         0xC2 => {
-            let to = mmu.fetch_u16();
-            if !mmu.reg.zero {
-                mmu.reg.pc = to;
-                mmu.tick(4);
+            let to = cpu.fetch_u16(bus);
+            if !cpu.reg.zero {
+                cpu.reg.pc = to;
+                bus.tick(4);
             }
         }
         0xCA => {
-            let to = mmu.fetch_u16();
-            if mmu.reg.zero {
-                mmu.reg.pc = to;
-                mmu.tick(4);
+            let to = cpu.fetch_u16(bus);
+            if cpu.reg.zero {
+                cpu.reg.pc = to;
+                bus.tick(4);
             }
         }
 
@@ -1415,17 +1416,17 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16/12
         // Flags: - - - -
         0xD2 => {
-            let to = mmu.fetch_u16();
-            if !mmu.reg.carry {
-                mmu.reg.pc = to;
-                mmu.tick(4);
+            let to = cpu.fetch_u16(bus);
+            if !cpu.reg.carry {
+                cpu.reg.pc = to;
+                bus.tick(4);
             }
         }
         0xDA => {
-            let to = mmu.fetch_u16();
-            if mmu.reg.carry {
-                mmu.reg.pc = to;
-                mmu.tick(4);
+            let to = cpu.fetch_u16(bus);
+            if cpu.reg.carry {
+                cpu.reg.pc = to;
+                bus.tick(4);
             }
         }
 
@@ -1434,8 +1435,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16
         // Flags: - - - -
         0xC3 => {
-            mmu.reg.pc = mmu.fetch_u16();
-            mmu.tick(4);
+            cpu.reg.pc = cpu.fetch_u16(bus);
+            bus.tick(4);
         }
 
         // JP (HL): jump to address HL, or in other words: PC = HL
@@ -1445,7 +1446,7 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: - - - -
         0xE9 => {
-            mmu.reg.pc = mmu.reg.hl();
+            cpu.reg.pc = cpu.reg.hl();
         }
 
         0xF9 => {
@@ -1453,8 +1454,8 @@ pub fn step(mmu: &mut MMU) {
             // Length: 1
             // Cycles: 8
             // Flags: - - - -
-            mmu.reg.sp = mmu.reg.hl();
-            mmu.tick(4);
+            cpu.reg.sp = cpu.reg.hl();
+            bus.tick(4);
         }
 
         // LD (HL-), A: put A into memory address HL, decrement HL
@@ -1462,10 +1463,10 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x32 => {
-            let hl = mmu.reg.hl();
-            let a = mmu.reg.a;
-            mmu.write(hl as usize, a);
-            mmu.reg.set_hl(hl.wrapping_sub(1));
+            let hl = cpu.reg.hl();
+            let a = cpu.reg.a;
+            bus.write(hl as usize, a);
+            cpu.reg.set_hl(hl.wrapping_sub(1));
         }
 
         // XOR N: assign A xor N to A
@@ -1473,37 +1474,37 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for op 0xAE)
         // Flags: Z 0 0 0
         0xA8 => {
-            let b = mmu.reg.b;
-            xor_op(&mut mmu.reg, b);
+            let b = cpu.reg.b;
+            xor_op(&mut cpu.reg, b);
         }
         0xA9 => {
-            let c = mmu.reg.c;
-            xor_op(&mut mmu.reg, c);
+            let c = cpu.reg.c;
+            xor_op(&mut cpu.reg, c);
         }
         0xAA => {
-            let d = mmu.reg.d;
-            xor_op(&mut mmu.reg, d);
+            let d = cpu.reg.d;
+            xor_op(&mut cpu.reg, d);
         }
         0xAB => {
-            let e = mmu.reg.e;
-            xor_op(&mut mmu.reg, e);
+            let e = cpu.reg.e;
+            xor_op(&mut cpu.reg, e);
         }
         0xAC => {
-            let h = mmu.reg.h;
-            xor_op(&mut mmu.reg, h);
+            let h = cpu.reg.h;
+            xor_op(&mut cpu.reg, h);
         }
         0xAD => {
-            let l = mmu.reg.l;
-            xor_op(&mut mmu.reg, l);
+            let l = cpu.reg.l;
+            xor_op(&mut cpu.reg, l);
         }
         0xAE => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            xor_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            xor_op(&mut cpu.reg, v);
         }
         0xAF => {
-            let a = mmu.reg.a;
-            xor_op(&mut mmu.reg, a);
+            let a = cpu.reg.a;
+            xor_op(&mut cpu.reg, a);
         }
 
         // XOR d8: assign A xor d8 to A
@@ -1511,8 +1512,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: Z 0 0 0
         0xEE => {
-            let v = mmu.fetch();
-            xor_op(&mut mmu.reg, v);
+            let v = cpu.fetch(bus);
+            xor_op(&mut cpu.reg, v);
         }
 
         // RLA: Rotate the contents of register A to the left
@@ -1520,10 +1521,10 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: 0 0 0 C
         0x17 => {
-            let b0 = if mmu.reg.carry { 1 } else { 0 };
-            let b8 = mmu.reg.a & 128 != 0;
-            mmu.reg.set_znhc(false, false, false, b8);
-            mmu.reg.a = mmu.reg.a << 1 | b0;
+            let b0 = if cpu.reg.carry { 1 } else { 0 };
+            let b8 = cpu.reg.a & 128 != 0;
+            cpu.reg.set_znhc(false, false, false, b8);
+            cpu.reg.a = cpu.reg.a << 1 | b0;
         }
 
         // LD (HL+), A: store value of A at (HL) and increment HL
@@ -1533,10 +1534,10 @@ pub fn step(mmu: &mut MMU) {
         // Alt mnemonic 1: LD (HLI), A
         // Alt mnemonic 2: LDI (HL), A
         0x22 => {
-            let hl = mmu.reg.hl();
-            let a = mmu.reg.a;
-            mmu.write(hl as usize, a);
-            mmu.reg.inc_hl();
+            let hl = cpu.reg.hl();
+            let a = cpu.reg.a;
+            bus.write(hl as usize, a);
+            cpu.reg.inc_hl();
         }
 
         // LD (HL), d8: store immediate value at (HL)
@@ -1544,9 +1545,9 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 12
         // Flags: - - - -
         0x36 => {
-            let v = mmu.fetch();
-            let hl = mmu.reg.hl();
-            mmu.write(hl as usize, v);
+            let v = cpu.fetch(bus);
+            let hl = cpu.reg.hl();
+            bus.write(hl as usize, v);
         }
 
         // LD A, (HL+): load value from (HL) to A and increment HL
@@ -1554,9 +1555,9 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x2A => {
-            let hl = mmu.reg.hl();
-            mmu.reg.a = mmu.read(hl as usize);
-            mmu.reg.inc_hl();
+            let hl = cpu.reg.hl();
+            cpu.reg.a = bus.read(hl as usize);
+            cpu.reg.inc_hl();
         }
 
         // LD A, (HL-): load value from (HL) to A and decrement HL
@@ -1564,9 +1565,9 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: - - - -
         0x3A => {
-            let hl = mmu.reg.hl();
-            mmu.reg.a = mmu.read(hl as usize);
-            mmu.reg.set_hl(hl.wrapping_sub(1));
+            let hl = cpu.reg.hl();
+            cpu.reg.a = bus.read(hl as usize);
+            cpu.reg.set_hl(hl.wrapping_sub(1));
         }
 
         // LD (a16), A: store value of A at address a16
@@ -1574,9 +1575,9 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 16
         // Flags: - - - -
         0xEA => {
-            let addr = mmu.fetch_u16();
-            let a = mmu.reg.a;
-            mmu.write(addr as usize, a);
+            let addr = cpu.fetch_u16(bus);
+            let a = cpu.reg.a;
+            bus.write(addr as usize, a);
         }
 
         // LD (a16), SP: store SP at address (a16)
@@ -1584,9 +1585,10 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 20
         // Flags: - - - -
         0x08 => {
-            let addr = mmu.fetch_u16();
-            let sp = mmu.reg.sp;
-            mmu.write_u16(addr as usize, sp);
+            let addr = cpu.fetch_u16(bus);
+            let sp = cpu.reg.sp;
+            bus.write(addr as usize, (sp & 0xFF) as u8);
+            bus.write(addr as usize + 1, (sp >> 8) as u8);
         }
 
         // LD HL, SP+d8: load HL with value of SP + immediate value r8
@@ -1594,17 +1596,17 @@ pub fn step(mmu: &mut MMU) {
         // Length: 2
         // Cycles: 12
         // Flags: 0 0 H C
-        // TODO: placement of mmu.tick()?
+        // TODO: placement of bus.tick()?
         0xF8 => {
             // let value = mem.read_i8(reg.pc + 1) as u16;
-            let value = mmu.fetch() as i8 as u16;
-            mmu.reg.zero = false;
-            mmu.reg.neg = false;
-            mmu.reg.half_carry = ((mmu.reg.sp & 0x0F) + (value & 0x0F)) > 0x0F;
-            mmu.reg.carry = (mmu.reg.sp & 0xFF) + (value & 0xFF) > 0xFF;
-            let hl = mmu.reg.sp.wrapping_add(value);
-            mmu.reg.set_hl(hl);
-            mmu.tick(4);
+            let value = cpu.fetch(bus) as i8 as u16;
+            cpu.reg.zero = false;
+            cpu.reg.neg = false;
+            cpu.reg.half_carry = ((cpu.reg.sp & 0x0F) + (value & 0x0F)) > 0x0F;
+            cpu.reg.carry = (cpu.reg.sp & 0xFF) + (value & 0xFF) > 0xFF;
+            let hl = cpu.reg.sp.wrapping_add(value);
+            cpu.reg.set_hl(hl);
+            bus.tick(4);
         }
 
         // CP r, CP (hl): Compare r (or value at (hl)) with A. Same as SUB but throws away the result
@@ -1612,36 +1614,36 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4 (8 for "CP (hl)")
         // Flags: Z 1 H C
         0xB8 => {
-            let b = mmu.reg.b;
-            cp_op(&mut mmu.reg, b);
+            let b = cpu.reg.b;
+            cp_op(&mut cpu.reg, b);
         }
         0xB9 => {
-            let c = mmu.reg.c;
-            cp_op(&mut mmu.reg, c);
+            let c = cpu.reg.c;
+            cp_op(&mut cpu.reg, c);
         }
         0xBA => {
-            let d = mmu.reg.d;
-            cp_op(&mut mmu.reg, d);
+            let d = cpu.reg.d;
+            cp_op(&mut cpu.reg, d);
         }
         0xBB => {
-            let e = mmu.reg.e;
-            cp_op(&mut mmu.reg, e);
+            let e = cpu.reg.e;
+            cp_op(&mut cpu.reg, e);
         }
         0xBC => {
-            let h = mmu.reg.h;
-            cp_op(&mut mmu.reg, h);
+            let h = cpu.reg.h;
+            cp_op(&mut cpu.reg, h);
         }
         0xBD => {
-            let l = mmu.reg.l;
-            cp_op(&mut mmu.reg, l);
+            let l = cpu.reg.l;
+            cp_op(&mut cpu.reg, l);
         }
         0xBE => {
-            let hl = mmu.reg.hl();
-            let v = mmu.read(hl as usize);
-            cp_op(&mut mmu.reg, v);
+            let hl = cpu.reg.hl();
+            let v = bus.read(hl as usize);
+            cp_op(&mut cpu.reg, v);
         }
         0xBF => {
-            mmu.reg.set_znhc(true, true, false, false);
+            cpu.reg.set_znhc(true, true, false, false);
         }
 
         // CP u8: Compare A with immediate
@@ -1649,8 +1651,8 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 8
         // Flags: Z 1 H C
         0xFE => {
-            let v = mmu.fetch();
-            cp_op(&mut mmu.reg, v);
+            let v = cpu.fetch(bus);
+            cp_op(&mut cpu.reg, v);
         }
 
         0xF3 => {
@@ -1658,7 +1660,7 @@ pub fn step(mmu: &mut MMU) {
             // Length: 1
             // Cycles: 4
             // Flags: - - - -
-            mmu.reg.ime = 0;
+            cpu.reg.ime = 0;
         }
 
         0xFB => {
@@ -1666,8 +1668,8 @@ pub fn step(mmu: &mut MMU) {
             // Length: 1
             // Cycles: 4
             // Flags: - - - -
-            if mmu.reg.ime == 0 {
-                mmu.reg.ime = 1;
+            if cpu.reg.ime == 0 {
+                cpu.reg.ime = 1;
             }
         }
 
@@ -1677,13 +1679,13 @@ pub fn step(mmu: &mut MMU) {
         // Flags: 0 0 0 C
         0x07 => {
             // FIXME: don't we have multiple impl of this?
-            let a = (mmu.reg.a as u32) << 1;
+            let a = (cpu.reg.a as u32) << 1;
             if a > 0xFF {
-                mmu.reg.a = (a & 0xFF) as u8 | 1;
-                mmu.reg.set_znhc(false, false, false, true);
+                cpu.reg.a = (a & 0xFF) as u8 | 1;
+                cpu.reg.set_znhc(false, false, false, true);
             } else {
-                mmu.reg.a = (a & 0xFF) as u8;
-                mmu.reg.set_znhc(false, false, false, false);
+                cpu.reg.a = (a & 0xFF) as u8;
+                cpu.reg.set_znhc(false, false, false, false);
             }
         }
 
@@ -1692,9 +1694,9 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: - 1 1 -
         0x2F => {
-            mmu.reg.a = !mmu.reg.a;
-            mmu.reg.neg = true;
-            mmu.reg.half_carry = true;
+            cpu.reg.a = !cpu.reg.a;
+            cpu.reg.neg = true;
+            cpu.reg.half_carry = true;
         }
 
         // CCF: Flip carry flag
@@ -1702,16 +1704,22 @@ pub fn step(mmu: &mut MMU) {
         // Cycles: 4
         // Flags: - 0 0 C
         0x3F => {
-            mmu.reg.carry = !mmu.reg.carry;
-            mmu.reg.half_carry = false;
-            mmu.reg.neg = false;
+            cpu.reg.carry = !cpu.reg.carry;
+            cpu.reg.half_carry = false;
+            cpu.reg.neg = false;
         }
 
         // STOP 0
         // Length: 1 (not 2, see https://stackoverflow.com/questions/41353869)
         // Cycles: 4
         0x10 => {
-            mmu.reg.stopped = true;
+            // On CGB, STOP switches speed instead if requested in
+            // KEY1: bit 0 set in normal speed. Double speed is not
+            // emulated, so the switch is ignored. On DMG, KEY1 reads
+            // as 0xFF.
+            if bus.peek(KEY1_REG) & 0x81 != 0x01 {
+                cpu.reg.stopped = true;
+            }
         }
 
         // Prefix 0xCB instructions
@@ -1720,570 +1728,570 @@ pub fn step(mmu: &mut MMU) {
         // all operations with op code 0x*6 and 0x*E which
         // consume 16 cycles.
         0xCB => {
-            let op2 = mmu.fetch();
+            let op2 = cpu.fetch(bus);
             match op2 {
                 // RLC n: rotate register n left
                 // Length:
                 0x00 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = rlc_op(&mut mmu.reg, b);
+                    let b = cpu.reg.b;
+                    cpu.reg.b = rlc_op(&mut cpu.reg, b);
                 }
                 0x01 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = rlc_op(&mut mmu.reg, c);
+                    let c = cpu.reg.c;
+                    cpu.reg.c = rlc_op(&mut cpu.reg, c);
                 }
                 0x02 => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = rlc_op(&mut mmu.reg, d);
+                    let d = cpu.reg.d;
+                    cpu.reg.d = rlc_op(&mut cpu.reg, d);
                 }
                 0x03 => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = rlc_op(&mut mmu.reg, e);
+                    let e = cpu.reg.e;
+                    cpu.reg.e = rlc_op(&mut cpu.reg, e);
                 }
                 0x04 => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = rlc_op(&mut mmu.reg, h);
+                    let h = cpu.reg.h;
+                    cpu.reg.h = rlc_op(&mut cpu.reg, h);
                 }
                 0x05 => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = rlc_op(&mut mmu.reg, l);
+                    let l = cpu.reg.l;
+                    cpu.reg.l = rlc_op(&mut cpu.reg, l);
                 }
                 0x06 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let rot = rlc_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, rot);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let rot = rlc_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, rot);
                 }
                 0x07 => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = rlc_op(&mut mmu.reg, a);
+                    let a = cpu.reg.a;
+                    cpu.reg.a = rlc_op(&mut cpu.reg, a);
                 }
 
                 // RLC n: rotate register n right
                 0x08 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = rrc_op(&mut mmu.reg, b);
+                    let b = cpu.reg.b;
+                    cpu.reg.b = rrc_op(&mut cpu.reg, b);
                 }
                 0x09 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = rrc_op(&mut mmu.reg, c);
+                    let c = cpu.reg.c;
+                    cpu.reg.c = rrc_op(&mut cpu.reg, c);
                 }
                 0x0A => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = rrc_op(&mut mmu.reg, d);
+                    let d = cpu.reg.d;
+                    cpu.reg.d = rrc_op(&mut cpu.reg, d);
                 }
                 0x0B => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = rrc_op(&mut mmu.reg, e);
+                    let e = cpu.reg.e;
+                    cpu.reg.e = rrc_op(&mut cpu.reg, e);
                 }
                 0x0C => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = rrc_op(&mut mmu.reg, h);
+                    let h = cpu.reg.h;
+                    cpu.reg.h = rrc_op(&mut cpu.reg, h);
                 }
                 0x0D => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = rrc_op(&mut mmu.reg, l);
+                    let l = cpu.reg.l;
+                    cpu.reg.l = rrc_op(&mut cpu.reg, l);
                 }
                 0x0E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let rot = rrc_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, rot);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let rot = rrc_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, rot);
                 }
                 0x0F => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = rrc_op(&mut mmu.reg, a);
+                    let a = cpu.reg.a;
+                    cpu.reg.a = rrc_op(&mut cpu.reg, a);
                 }
 
                 // RL n: rotate register n left with carry flag
                 0x10 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = rl_op(&mut mmu.reg, b);
+                    let b = cpu.reg.b;
+                    cpu.reg.b = rl_op(&mut cpu.reg, b);
                 }
                 0x11 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = rl_op(&mut mmu.reg, c);
+                    let c = cpu.reg.c;
+                    cpu.reg.c = rl_op(&mut cpu.reg, c);
                 }
                 0x12 => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = rl_op(&mut mmu.reg, d);
+                    let d = cpu.reg.d;
+                    cpu.reg.d = rl_op(&mut cpu.reg, d);
                 }
                 0x13 => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = rl_op(&mut mmu.reg, e);
+                    let e = cpu.reg.e;
+                    cpu.reg.e = rl_op(&mut cpu.reg, e);
                 }
                 0x14 => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = rl_op(&mut mmu.reg, h);
+                    let h = cpu.reg.h;
+                    cpu.reg.h = rl_op(&mut cpu.reg, h);
                 }
                 0x15 => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = rl_op(&mut mmu.reg, l);
+                    let l = cpu.reg.l;
+                    cpu.reg.l = rl_op(&mut cpu.reg, l);
                 }
                 0x16 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let rot = rl_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, rot);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let rot = rl_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, rot);
                 }
                 0x17 => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = rl_op(&mut mmu.reg, a);
+                    let a = cpu.reg.a;
+                    cpu.reg.a = rl_op(&mut cpu.reg, a);
                 }
 
                 // RR n, rotate register n right with carry flag
                 0x18 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = rr_op(&mut mmu.reg, b)
+                    let b = cpu.reg.b;
+                    cpu.reg.b = rr_op(&mut cpu.reg, b)
                 }
                 0x19 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = rr_op(&mut mmu.reg, c)
+                    let c = cpu.reg.c;
+                    cpu.reg.c = rr_op(&mut cpu.reg, c)
                 }
                 0x1A => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = rr_op(&mut mmu.reg, d)
+                    let d = cpu.reg.d;
+                    cpu.reg.d = rr_op(&mut cpu.reg, d)
                 }
                 0x1B => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = rr_op(&mut mmu.reg, e)
+                    let e = cpu.reg.e;
+                    cpu.reg.e = rr_op(&mut cpu.reg, e)
                 }
                 0x1C => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = rr_op(&mut mmu.reg, h)
+                    let h = cpu.reg.h;
+                    cpu.reg.h = rr_op(&mut cpu.reg, h)
                 }
                 0x1D => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = rr_op(&mut mmu.reg, l)
+                    let l = cpu.reg.l;
+                    cpu.reg.l = rr_op(&mut cpu.reg, l)
                 }
                 0x1E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let rot = rr_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, rot);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let rot = rr_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, rot);
                 }
                 0x1F => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = rr_op(&mut mmu.reg, a)
+                    let a = cpu.reg.a;
+                    cpu.reg.a = rr_op(&mut cpu.reg, a)
                 }
 
                 // SLA r
                 0x20 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = sla_op(&mut mmu.reg, b)
+                    let b = cpu.reg.b;
+                    cpu.reg.b = sla_op(&mut cpu.reg, b)
                 }
                 0x21 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = sla_op(&mut mmu.reg, c)
+                    let c = cpu.reg.c;
+                    cpu.reg.c = sla_op(&mut cpu.reg, c)
                 }
                 0x22 => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = sla_op(&mut mmu.reg, d)
+                    let d = cpu.reg.d;
+                    cpu.reg.d = sla_op(&mut cpu.reg, d)
                 }
                 0x23 => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = sla_op(&mut mmu.reg, e)
+                    let e = cpu.reg.e;
+                    cpu.reg.e = sla_op(&mut cpu.reg, e)
                 }
                 0x24 => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = sla_op(&mut mmu.reg, h)
+                    let h = cpu.reg.h;
+                    cpu.reg.h = sla_op(&mut cpu.reg, h)
                 }
                 0x25 => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = sla_op(&mut mmu.reg, l)
+                    let l = cpu.reg.l;
+                    cpu.reg.l = sla_op(&mut cpu.reg, l)
                 }
                 0x26 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let result = sla_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, result);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let result = sla_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, result);
                 }
                 0x27 => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = sla_op(&mut mmu.reg, a)
+                    let a = cpu.reg.a;
+                    cpu.reg.a = sla_op(&mut cpu.reg, a)
                 }
 
                 // SRA r
                 0x28 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = sra_op(&mut mmu.reg, b)
+                    let b = cpu.reg.b;
+                    cpu.reg.b = sra_op(&mut cpu.reg, b)
                 }
                 0x29 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = sra_op(&mut mmu.reg, c)
+                    let c = cpu.reg.c;
+                    cpu.reg.c = sra_op(&mut cpu.reg, c)
                 }
                 0x2A => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = sra_op(&mut mmu.reg, d)
+                    let d = cpu.reg.d;
+                    cpu.reg.d = sra_op(&mut cpu.reg, d)
                 }
                 0x2B => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = sra_op(&mut mmu.reg, e)
+                    let e = cpu.reg.e;
+                    cpu.reg.e = sra_op(&mut cpu.reg, e)
                 }
                 0x2C => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = sra_op(&mut mmu.reg, h)
+                    let h = cpu.reg.h;
+                    cpu.reg.h = sra_op(&mut cpu.reg, h)
                 }
                 0x2D => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = sra_op(&mut mmu.reg, l)
+                    let l = cpu.reg.l;
+                    cpu.reg.l = sra_op(&mut cpu.reg, l)
                 }
                 0x2E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let result = sra_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, result);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let result = sra_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, result);
                 }
                 0x2F => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = sra_op(&mut mmu.reg, a)
+                    let a = cpu.reg.a;
+                    cpu.reg.a = sra_op(&mut cpu.reg, a)
                 }
 
                 // SWAP r
                 0x30 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = swap_op(&mut mmu.reg, b)
+                    let b = cpu.reg.b;
+                    cpu.reg.b = swap_op(&mut cpu.reg, b)
                 }
                 0x31 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = swap_op(&mut mmu.reg, c)
+                    let c = cpu.reg.c;
+                    cpu.reg.c = swap_op(&mut cpu.reg, c)
                 }
                 0x32 => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = swap_op(&mut mmu.reg, d)
+                    let d = cpu.reg.d;
+                    cpu.reg.d = swap_op(&mut cpu.reg, d)
                 }
                 0x33 => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = swap_op(&mut mmu.reg, e)
+                    let e = cpu.reg.e;
+                    cpu.reg.e = swap_op(&mut cpu.reg, e)
                 }
                 0x34 => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = swap_op(&mut mmu.reg, h)
+                    let h = cpu.reg.h;
+                    cpu.reg.h = swap_op(&mut cpu.reg, h)
                 }
                 0x35 => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = swap_op(&mut mmu.reg, l)
+                    let l = cpu.reg.l;
+                    cpu.reg.l = swap_op(&mut cpu.reg, l)
                 }
                 0x36 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let result = swap_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, result);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let result = swap_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, result);
                 }
                 0x37 => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = swap_op(&mut mmu.reg, a)
+                    let a = cpu.reg.a;
+                    cpu.reg.a = swap_op(&mut cpu.reg, a)
                 }
 
                 // SRL r
                 0x38 => {
-                    let b = mmu.reg.b;
-                    mmu.reg.b = srl_op(&mut mmu.reg, b)
+                    let b = cpu.reg.b;
+                    cpu.reg.b = srl_op(&mut cpu.reg, b)
                 }
                 0x39 => {
-                    let c = mmu.reg.c;
-                    mmu.reg.c = srl_op(&mut mmu.reg, c)
+                    let c = cpu.reg.c;
+                    cpu.reg.c = srl_op(&mut cpu.reg, c)
                 }
                 0x3A => {
-                    let d = mmu.reg.d;
-                    mmu.reg.d = srl_op(&mut mmu.reg, d)
+                    let d = cpu.reg.d;
+                    cpu.reg.d = srl_op(&mut cpu.reg, d)
                 }
                 0x3B => {
-                    let e = mmu.reg.e;
-                    mmu.reg.e = srl_op(&mut mmu.reg, e)
+                    let e = cpu.reg.e;
+                    cpu.reg.e = srl_op(&mut cpu.reg, e)
                 }
                 0x3C => {
-                    let h = mmu.reg.h;
-                    mmu.reg.h = srl_op(&mut mmu.reg, h)
+                    let h = cpu.reg.h;
+                    cpu.reg.h = srl_op(&mut cpu.reg, h)
                 }
                 0x3D => {
-                    let l = mmu.reg.l;
-                    mmu.reg.l = srl_op(&mut mmu.reg, l)
+                    let l = cpu.reg.l;
+                    cpu.reg.l = srl_op(&mut cpu.reg, l)
                 }
                 0x3E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    let result = srl_op(&mut mmu.reg, v);
-                    mmu.write(hl as usize, result);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    let result = srl_op(&mut cpu.reg, v);
+                    bus.write(hl as usize, result);
                 }
                 0x3F => {
-                    let a = mmu.reg.a;
-                    mmu.reg.a = srl_op(&mut mmu.reg, a)
+                    let a = cpu.reg.a;
+                    cpu.reg.a = srl_op(&mut cpu.reg, a)
                 }
 
                 // BIT b, r: test if bit 'b' in register 'r' is set
                 // Flags: Z 0 1 -
                 // TODO: does op 0x46, 0x4E, etc really consume 16 cycles?
                 0x40 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 0, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 0, b);
                 }
                 0x41 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 0, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 0, c);
                 }
                 0x42 => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 0, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 0, d);
                 }
                 0x43 => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 0, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 0, e);
                 }
                 0x44 => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 0, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 0, h);
                 }
                 0x45 => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 0, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 0, l);
                 }
                 0x46 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 0, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 0, v)
                 }
                 0x47 => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 0, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 0, a);
                 }
 
                 0x48 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 1, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 1, b);
                 }
                 0x49 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 1, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 1, c);
                 }
                 0x4A => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 1, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 1, d);
                 }
                 0x4B => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 1, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 1, e);
                 }
                 0x4C => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 1, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 1, h);
                 }
                 0x4D => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 1, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 1, l);
                 }
                 0x4E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 1, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 1, v)
                 }
                 0x4F => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 1, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 1, a);
                 }
 
                 0x50 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 2, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 2, b);
                 }
                 0x51 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 2, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 2, c);
                 }
                 0x52 => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 2, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 2, d);
                 }
                 0x53 => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 2, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 2, e);
                 }
                 0x54 => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 2, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 2, h);
                 }
                 0x55 => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 2, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 2, l);
                 }
                 0x56 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 2, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 2, v)
                 }
                 0x57 => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 2, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 2, a);
                 }
 
                 0x58 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 3, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 3, b);
                 }
                 0x59 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 3, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 3, c);
                 }
                 0x5A => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 3, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 3, d);
                 }
                 0x5B => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 3, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 3, e);
                 }
                 0x5C => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 3, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 3, h);
                 }
                 0x5D => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 3, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 3, l);
                 }
                 0x5E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 3, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 3, v)
                 }
                 0x5F => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 3, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 3, a);
                 }
 
                 0x60 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 4, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 4, b);
                 }
                 0x61 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 4, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 4, c);
                 }
                 0x62 => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 4, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 4, d);
                 }
                 0x63 => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 4, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 4, e);
                 }
                 0x64 => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 4, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 4, h);
                 }
                 0x65 => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 4, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 4, l);
                 }
                 0x66 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 4, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 4, v)
                 }
                 0x67 => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 4, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 4, a);
                 }
 
                 0x68 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 5, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 5, b);
                 }
                 0x69 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 5, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 5, c);
                 }
                 0x6A => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 5, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 5, d);
                 }
                 0x6B => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 5, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 5, e);
                 }
                 0x6C => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 5, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 5, h);
                 }
                 0x6D => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 5, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 5, l);
                 }
                 0x6E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 5, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 5, v)
                 }
                 0x6F => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 5, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 5, a);
                 }
 
                 0x70 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 6, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 6, b);
                 }
                 0x71 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 6, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 6, c);
                 }
                 0x72 => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 6, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 6, d);
                 }
                 0x73 => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 6, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 6, e);
                 }
                 0x74 => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 6, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 6, h);
                 }
                 0x75 => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 6, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 6, l);
                 }
                 0x76 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 6, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 6, v)
                 }
                 0x77 => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 6, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 6, a);
                 }
 
                 0x78 => {
-                    let b = mmu.reg.b;
-                    bit_op(&mut mmu.reg, 7, b);
+                    let b = cpu.reg.b;
+                    bit_op(&mut cpu.reg, 7, b);
                 }
                 0x79 => {
-                    let c = mmu.reg.c;
-                    bit_op(&mut mmu.reg, 7, c);
+                    let c = cpu.reg.c;
+                    bit_op(&mut cpu.reg, 7, c);
                 }
                 0x7A => {
-                    let d = mmu.reg.d;
-                    bit_op(&mut mmu.reg, 7, d);
+                    let d = cpu.reg.d;
+                    bit_op(&mut cpu.reg, 7, d);
                 }
                 0x7B => {
-                    let e = mmu.reg.e;
-                    bit_op(&mut mmu.reg, 7, e);
+                    let e = cpu.reg.e;
+                    bit_op(&mut cpu.reg, 7, e);
                 }
                 0x7C => {
-                    let h = mmu.reg.h;
-                    bit_op(&mut mmu.reg, 7, h);
+                    let h = cpu.reg.h;
+                    bit_op(&mut cpu.reg, 7, h);
                 }
                 0x7D => {
-                    let l = mmu.reg.l;
-                    bit_op(&mut mmu.reg, 7, l);
+                    let l = cpu.reg.l;
+                    bit_op(&mut cpu.reg, 7, l);
                 }
                 0x7E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    bit_op(&mut mmu.reg, 7, v)
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bit_op(&mut cpu.reg, 7, v)
                 }
                 0x7F => {
-                    let a = mmu.reg.a;
-                    bit_op(&mut mmu.reg, 7, a);
+                    let a = cpu.reg.a;
+                    bit_op(&mut cpu.reg, 7, a);
                 }
 
                 // RES b, r: reset bit b in register r
@@ -2291,443 +2299,443 @@ pub fn step(mmu: &mut MMU) {
                 // Cycles: 8
                 // Flags: - - - -
                 0x80 => {
-                    mmu.reg.b &= !1;
+                    cpu.reg.b &= !1;
                 }
                 0x81 => {
-                    mmu.reg.c &= !1;
+                    cpu.reg.c &= !1;
                 }
                 0x82 => {
-                    mmu.reg.d &= !1;
+                    cpu.reg.d &= !1;
                 }
                 0x83 => {
-                    mmu.reg.e &= !1;
+                    cpu.reg.e &= !1;
                 }
                 0x84 => {
-                    mmu.reg.h &= !1;
+                    cpu.reg.h &= !1;
                 }
                 0x85 => {
-                    mmu.reg.l &= !1;
+                    cpu.reg.l &= !1;
                 }
                 0x86 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !1);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !1);
                 }
                 0x87 => {
-                    mmu.reg.a &= !1;
+                    cpu.reg.a &= !1;
                 }
 
                 0x88 => {
-                    mmu.reg.b &= !2;
+                    cpu.reg.b &= !2;
                 }
                 0x89 => {
-                    mmu.reg.c &= !2;
+                    cpu.reg.c &= !2;
                 }
                 0x8A => {
-                    mmu.reg.d &= !2;
+                    cpu.reg.d &= !2;
                 }
                 0x8B => {
-                    mmu.reg.e &= !2;
+                    cpu.reg.e &= !2;
                 }
                 0x8C => {
-                    mmu.reg.h &= !2;
+                    cpu.reg.h &= !2;
                 }
                 0x8D => {
-                    mmu.reg.l &= !2;
+                    cpu.reg.l &= !2;
                 }
                 0x8E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !2);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !2);
                 }
                 0x8F => {
-                    mmu.reg.a &= !2;
+                    cpu.reg.a &= !2;
                 }
 
                 0x90 => {
-                    mmu.reg.b &= !4;
+                    cpu.reg.b &= !4;
                 }
                 0x91 => {
-                    mmu.reg.c &= !4;
+                    cpu.reg.c &= !4;
                 }
                 0x92 => {
-                    mmu.reg.d &= !4;
+                    cpu.reg.d &= !4;
                 }
                 0x93 => {
-                    mmu.reg.e &= !4;
+                    cpu.reg.e &= !4;
                 }
                 0x94 => {
-                    mmu.reg.h &= !4;
+                    cpu.reg.h &= !4;
                 }
                 0x95 => {
-                    mmu.reg.l &= !4;
+                    cpu.reg.l &= !4;
                 }
                 0x96 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !4);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !4);
                 }
                 0x97 => {
-                    mmu.reg.a &= !4;
+                    cpu.reg.a &= !4;
                 }
 
                 0x98 => {
-                    mmu.reg.b &= !8;
+                    cpu.reg.b &= !8;
                 }
                 0x99 => {
-                    mmu.reg.c &= !8;
+                    cpu.reg.c &= !8;
                 }
                 0x9A => {
-                    mmu.reg.d &= !8;
+                    cpu.reg.d &= !8;
                 }
                 0x9B => {
-                    mmu.reg.e &= !8;
+                    cpu.reg.e &= !8;
                 }
                 0x9C => {
-                    mmu.reg.h &= !8;
+                    cpu.reg.h &= !8;
                 }
                 0x9D => {
-                    mmu.reg.l &= !8;
+                    cpu.reg.l &= !8;
                 }
                 0x9E => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !8);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !8);
                 }
                 0x9F => {
-                    mmu.reg.a &= !8;
+                    cpu.reg.a &= !8;
                 }
 
                 0xA0 => {
-                    mmu.reg.b &= !16;
+                    cpu.reg.b &= !16;
                 }
                 0xA1 => {
-                    mmu.reg.c &= !16;
+                    cpu.reg.c &= !16;
                 }
                 0xA2 => {
-                    mmu.reg.d &= !16;
+                    cpu.reg.d &= !16;
                 }
                 0xA3 => {
-                    mmu.reg.e &= !16;
+                    cpu.reg.e &= !16;
                 }
                 0xA4 => {
-                    mmu.reg.h &= !16;
+                    cpu.reg.h &= !16;
                 }
                 0xA5 => {
-                    mmu.reg.l &= !16;
+                    cpu.reg.l &= !16;
                 }
                 0xA6 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !16);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !16);
                 }
                 0xA7 => {
-                    mmu.reg.a &= !16;
+                    cpu.reg.a &= !16;
                 }
 
                 0xA8 => {
-                    mmu.reg.b &= !32;
+                    cpu.reg.b &= !32;
                 }
                 0xA9 => {
-                    mmu.reg.c &= !32;
+                    cpu.reg.c &= !32;
                 }
                 0xAA => {
-                    mmu.reg.d &= !32;
+                    cpu.reg.d &= !32;
                 }
                 0xAB => {
-                    mmu.reg.e &= !32;
+                    cpu.reg.e &= !32;
                 }
                 0xAC => {
-                    mmu.reg.h &= !32;
+                    cpu.reg.h &= !32;
                 }
                 0xAD => {
-                    mmu.reg.l &= !32;
+                    cpu.reg.l &= !32;
                 }
                 0xAE => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !32);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !32);
                 }
                 0xAF => {
-                    mmu.reg.a &= !32;
+                    cpu.reg.a &= !32;
                 }
 
                 0xB0 => {
-                    mmu.reg.b &= !64;
+                    cpu.reg.b &= !64;
                 }
                 0xB1 => {
-                    mmu.reg.c &= !64;
+                    cpu.reg.c &= !64;
                 }
                 0xB2 => {
-                    mmu.reg.d &= !64;
+                    cpu.reg.d &= !64;
                 }
                 0xB3 => {
-                    mmu.reg.e &= !64;
+                    cpu.reg.e &= !64;
                 }
                 0xB4 => {
-                    mmu.reg.h &= !64;
+                    cpu.reg.h &= !64;
                 }
                 0xB5 => {
-                    mmu.reg.l &= !64;
+                    cpu.reg.l &= !64;
                 }
                 0xB6 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !64);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !64);
                 }
                 0xB7 => {
-                    mmu.reg.a &= !64;
+                    cpu.reg.a &= !64;
                 }
 
                 0xB8 => {
-                    mmu.reg.b &= !128;
+                    cpu.reg.b &= !128;
                 }
                 0xB9 => {
-                    mmu.reg.c &= !128;
+                    cpu.reg.c &= !128;
                 }
                 0xBA => {
-                    mmu.reg.d &= !128;
+                    cpu.reg.d &= !128;
                 }
                 0xBB => {
-                    mmu.reg.e &= !128;
+                    cpu.reg.e &= !128;
                 }
                 0xBC => {
-                    mmu.reg.h &= !128;
+                    cpu.reg.h &= !128;
                 }
                 0xBD => {
-                    mmu.reg.l &= !128;
+                    cpu.reg.l &= !128;
                 }
                 0xBE => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v & !128);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v & !128);
                 }
                 0xBF => {
-                    mmu.reg.a &= !128;
+                    cpu.reg.a &= !128;
                 }
 
                 // SET b, r: set bit b in register r
                 // Flags: - - - -
                 0xC0 => {
-                    mmu.reg.b |= 1;
+                    cpu.reg.b |= 1;
                 }
                 0xC1 => {
-                    mmu.reg.c |= 1;
+                    cpu.reg.c |= 1;
                 }
                 0xC2 => {
-                    mmu.reg.d |= 1;
+                    cpu.reg.d |= 1;
                 }
                 0xC3 => {
-                    mmu.reg.e |= 1;
+                    cpu.reg.e |= 1;
                 }
                 0xC4 => {
-                    mmu.reg.h |= 1;
+                    cpu.reg.h |= 1;
                 }
                 0xC5 => {
-                    mmu.reg.l |= 1;
+                    cpu.reg.l |= 1;
                 }
                 0xC6 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 1);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 1);
                 }
                 0xC7 => {
-                    mmu.reg.a |= 1;
+                    cpu.reg.a |= 1;
                 }
 
                 0xC8 => {
-                    mmu.reg.b |= 2;
+                    cpu.reg.b |= 2;
                 }
                 0xC9 => {
-                    mmu.reg.c |= 2;
+                    cpu.reg.c |= 2;
                 }
                 0xCA => {
-                    mmu.reg.d |= 2;
+                    cpu.reg.d |= 2;
                 }
                 0xCB => {
-                    mmu.reg.e |= 2;
+                    cpu.reg.e |= 2;
                 }
                 0xCC => {
-                    mmu.reg.h |= 2;
+                    cpu.reg.h |= 2;
                 }
                 0xCD => {
-                    mmu.reg.l |= 2;
+                    cpu.reg.l |= 2;
                 }
                 0xCE => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 2);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 2);
                 }
                 0xCF => {
-                    mmu.reg.a |= 2;
+                    cpu.reg.a |= 2;
                 }
 
                 0xD0 => {
-                    mmu.reg.b |= 4;
+                    cpu.reg.b |= 4;
                 }
                 0xD1 => {
-                    mmu.reg.c |= 4;
+                    cpu.reg.c |= 4;
                 }
                 0xD2 => {
-                    mmu.reg.d |= 4;
+                    cpu.reg.d |= 4;
                 }
                 0xD3 => {
-                    mmu.reg.e |= 4;
+                    cpu.reg.e |= 4;
                 }
                 0xD4 => {
-                    mmu.reg.h |= 4;
+                    cpu.reg.h |= 4;
                 }
                 0xD5 => {
-                    mmu.reg.l |= 4;
+                    cpu.reg.l |= 4;
                 }
                 0xD6 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 4);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 4);
                 }
                 0xD7 => {
-                    mmu.reg.a |= 4;
+                    cpu.reg.a |= 4;
                 }
 
                 0xD8 => {
-                    mmu.reg.b |= 8;
+                    cpu.reg.b |= 8;
                 }
                 0xD9 => {
-                    mmu.reg.c |= 8;
+                    cpu.reg.c |= 8;
                 }
                 0xDA => {
-                    mmu.reg.d |= 8;
+                    cpu.reg.d |= 8;
                 }
                 0xDB => {
-                    mmu.reg.e |= 8;
+                    cpu.reg.e |= 8;
                 }
                 0xDC => {
-                    mmu.reg.h |= 8;
+                    cpu.reg.h |= 8;
                 }
                 0xDD => {
-                    mmu.reg.l |= 8;
+                    cpu.reg.l |= 8;
                 }
                 0xDE => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 8);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 8);
                 }
                 0xDF => {
-                    mmu.reg.a |= 8;
+                    cpu.reg.a |= 8;
                 }
 
                 0xE0 => {
-                    mmu.reg.b |= 16;
+                    cpu.reg.b |= 16;
                 }
                 0xE1 => {
-                    mmu.reg.c |= 16;
+                    cpu.reg.c |= 16;
                 }
                 0xE2 => {
-                    mmu.reg.d |= 16;
+                    cpu.reg.d |= 16;
                 }
                 0xE3 => {
-                    mmu.reg.e |= 16;
+                    cpu.reg.e |= 16;
                 }
                 0xE4 => {
-                    mmu.reg.h |= 16;
+                    cpu.reg.h |= 16;
                 }
                 0xE5 => {
-                    mmu.reg.l |= 16;
+                    cpu.reg.l |= 16;
                 }
                 0xE6 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 16);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 16);
                 }
                 0xE7 => {
-                    mmu.reg.a |= 16;
+                    cpu.reg.a |= 16;
                 }
 
                 0xE8 => {
-                    mmu.reg.b |= 32;
+                    cpu.reg.b |= 32;
                 }
                 0xE9 => {
-                    mmu.reg.c |= 32;
+                    cpu.reg.c |= 32;
                 }
                 0xEA => {
-                    mmu.reg.d |= 32;
+                    cpu.reg.d |= 32;
                 }
                 0xEB => {
-                    mmu.reg.e |= 32;
+                    cpu.reg.e |= 32;
                 }
                 0xEC => {
-                    mmu.reg.h |= 32;
+                    cpu.reg.h |= 32;
                 }
                 0xED => {
-                    mmu.reg.l |= 32;
+                    cpu.reg.l |= 32;
                 }
                 0xEE => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 32);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 32);
                 }
                 0xEF => {
-                    mmu.reg.a |= 32;
+                    cpu.reg.a |= 32;
                 }
 
                 0xF0 => {
-                    mmu.reg.b |= 64;
+                    cpu.reg.b |= 64;
                 }
                 0xF1 => {
-                    mmu.reg.c |= 64;
+                    cpu.reg.c |= 64;
                 }
                 0xF2 => {
-                    mmu.reg.d |= 64;
+                    cpu.reg.d |= 64;
                 }
                 0xF3 => {
-                    mmu.reg.e |= 64;
+                    cpu.reg.e |= 64;
                 }
                 0xF4 => {
-                    mmu.reg.h |= 64;
+                    cpu.reg.h |= 64;
                 }
                 0xF5 => {
-                    mmu.reg.l |= 64;
+                    cpu.reg.l |= 64;
                 }
                 0xF6 => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 64);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 64);
                 }
                 0xF7 => {
-                    mmu.reg.a |= 64;
+                    cpu.reg.a |= 64;
                 }
 
                 0xF8 => {
-                    mmu.reg.b |= 128;
+                    cpu.reg.b |= 128;
                 }
                 0xF9 => {
-                    mmu.reg.c |= 128;
+                    cpu.reg.c |= 128;
                 }
                 0xFA => {
-                    mmu.reg.d |= 128;
+                    cpu.reg.d |= 128;
                 }
                 0xFB => {
-                    mmu.reg.e |= 128;
+                    cpu.reg.e |= 128;
                 }
                 0xFC => {
-                    mmu.reg.h |= 128;
+                    cpu.reg.h |= 128;
                 }
                 0xFD => {
-                    mmu.reg.l |= 128;
+                    cpu.reg.l |= 128;
                 }
                 0xFE => {
-                    let hl = mmu.reg.hl();
-                    let v = mmu.read(hl as usize);
-                    mmu.write(hl as usize, v | 128);
+                    let hl = cpu.reg.hl();
+                    let v = bus.read(hl as usize);
+                    bus.write(hl as usize, v | 128);
                 }
                 0xFF => {
-                    mmu.reg.a |= 128;
+                    cpu.reg.a |= 128;
                 }
             }
         }
 
         _ => {
-            panic!("Unsupported opcode at 0x{:04X}: 0x{:02X}", mmu.reg.pc, op);
+            panic!("Unsupported opcode at 0x{:04X}: 0x{:02X}", cpu.reg.pc, op);
         }
     }
 }
@@ -3023,90 +3031,100 @@ const SIMPLE_MNEMONICS: [&str; 256] = [
     "RST  38H",
 ];
 
-pub fn format_mnemonic(mmu: &MMU, addr: usize) -> String {
-    let op: u8 = mmu.direct_read(addr);
+fn peek_u16(bus: &impl Bus, addr: usize) -> u16 {
+    let lo = bus.peek(addr);
+    let hi = bus.peek(addr + 1);
+    ((hi as u16) << 8) | (lo as u16)
+}
+
+fn peek_i8(bus: &impl Bus, addr: usize) -> i8 {
+    bus.peek(addr) as i8
+}
+
+pub fn format_mnemonic(bus: &impl Bus, reg: &Registers, addr: usize) -> String {
+    let op: u8 = bus.peek(addr);
 
     match op {
-        0x01 => format!("LD   BC, ${:04X}", mmu.direct_read_u16(addr + 1)),
+        0x01 => format!("LD   BC, ${:04X}", peek_u16(bus, addr + 1)),
 
         // LD n, d: load immediate into register n
-        0x06 => format!("LD   B, ${:02X}", mmu.direct_read(addr + 1)),
-        0x08 => format!("LD   ${:02X}, SP", mmu.direct_read(addr + 1)),
-        0x0E => format!("LD   C, ${:02X}", mmu.direct_read(addr + 1)),
-        0x16 => format!("LD   D, ${:02X}", mmu.direct_read(addr + 1)),
-        0x1E => format!("LD   E, ${:02X}", mmu.direct_read(addr + 1)),
-        0x26 => format!("LD   H, ${:02X}", mmu.direct_read(addr + 1)),
-        0x2E => format!("LD   L, ${:02X}", mmu.direct_read(addr + 1)),
-        0x3E => format!("LD   A, ${:02X}", mmu.direct_read(addr + 1)),
+        0x06 => format!("LD   B, ${:02X}", bus.peek(addr + 1)),
+        0x08 => format!("LD   ${:02X}, SP", bus.peek(addr + 1)),
+        0x0E => format!("LD   C, ${:02X}", bus.peek(addr + 1)),
+        0x16 => format!("LD   D, ${:02X}", bus.peek(addr + 1)),
+        0x1E => format!("LD   E, ${:02X}", bus.peek(addr + 1)),
+        0x26 => format!("LD   H, ${:02X}", bus.peek(addr + 1)),
+        0x2E => format!("LD   L, ${:02X}", bus.peek(addr + 1)),
+        0x3E => format!("LD   A, ${:02X}", bus.peek(addr + 1)),
 
         0x11 => {
-            let lo = mmu.direct_read(addr + 1);
-            let hi = mmu.direct_read(addr + 2);
+            let lo = bus.peek(addr + 1);
+            let hi = bus.peek(addr + 2);
             format!("LD   DE, ${:02X}{:02X}", hi, lo)
         }
 
         0x18 => {
-            let rel = mmu.direct_read_i8(addr + 1);
+            let rel = peek_i8(bus, addr + 1);
             let abs = add_i8_to_u16(addr as u16 + 2, rel);
             format!("JR   {}  ; jump to 0x{:04X}", rel, abs)
         }
 
         0x1A => {
-            let de = mmu.reg.de();
-            let val = mmu.direct_read(de as usize);
+            let de = reg.de();
+            let val = bus.peek(de as usize);
             format!("LD   A, (DE)  ; DE=0x{:04X} (DE)=0x{:02X}", de, val)
         }
 
         0x20 => {
-            let rel = mmu.direct_read_i8(addr + 1);
+            let rel = peek_i8(bus, addr + 1);
             let abs = add_i8_to_u16(addr as u16 + 2, rel);
             format!("JR   NZ, {}    ; jump to 0x{:04X}", rel, abs)
         }
 
         0x21 => {
-            let lo = mmu.direct_read(addr + 1);
-            let hi = mmu.direct_read(addr + 2);
+            let lo = bus.peek(addr + 1);
+            let hi = bus.peek(addr + 2);
             format!("LD   HL, ${:02X}{:02X}", hi, lo)
         }
 
         0x28 => {
-            let rel = mmu.direct_read_i8(addr + 1);
+            let rel = peek_i8(bus, addr + 1);
             let abs = add_i8_to_u16(addr as u16 + 2, rel);
             format!("JR   Z, {}        ; jump to 0x{:04X}", rel, abs)
         }
 
         0x30 => {
-            let rel = mmu.direct_read_i8(addr + 1);
+            let rel = peek_i8(bus, addr + 1);
             let abs = add_i8_to_u16(addr as u16 + 2, rel);
             format!("JR   NC, {}    ; jump to 0x{:04X}", rel, abs)
         }
 
         0x31 => {
-            let lo = mmu.direct_read(addr + 1);
-            let hi = mmu.direct_read(addr + 2);
+            let lo = bus.peek(addr + 1);
+            let hi = bus.peek(addr + 2);
             format!("LD   SP, ${:02X}{:02X}", hi, lo)
         }
 
-        0x36 => format!("LD   (HL), 0x{:02X}", mmu.direct_read(addr + 1)),
+        0x36 => format!("LD   (HL), 0x{:02X}", bus.peek(addr + 1)),
 
         0x38 => {
-            let rel = mmu.direct_read_i8(addr + 1);
+            let rel = peek_i8(bus, addr + 1);
             let abs = add_i8_to_u16(addr as u16 + 2, rel);
             format!("JR   C, {}        ; jump to 0x{:04X}", rel, abs)
         }
 
         0xBE => format!(
             "CP   (HL)  ; HL=0x{:04X} (HL)=0x{:02X}",
-            mmu.reg.hl(),
-            mmu.direct_read(mmu.reg.hl() as usize)
+            reg.hl(),
+            bus.peek(reg.hl() as usize)
         ),
 
-        0xC2 => format!("JP   NZ, 0x{:04X}", mmu.direct_read_u16(addr + 1)),
-        0xC3 => format!("JP   0x{:04X}", mmu.direct_read_u16(addr + 1)),
-        0xC4 => format!("CALL NZ, ${:04X}", mmu.direct_read_u16(addr + 1)),
-        0xC6 => format!("ADD  A, 0x{:02X}", mmu.direct_read(addr + 1)),
+        0xC2 => format!("JP   NZ, 0x{:04X}", peek_u16(bus, addr + 1)),
+        0xC3 => format!("JP   0x{:04X}", peek_u16(bus, addr + 1)),
+        0xC4 => format!("CALL NZ, ${:04X}", peek_u16(bus, addr + 1)),
+        0xC6 => format!("ADD  A, 0x{:02X}", bus.peek(addr + 1)),
 
-        0xCA => format!("JP   Z, 0x{:04X}", mmu.direct_read_u16(addr + 1)),
+        0xCA => format!("JP   Z, 0x{:04X}", peek_u16(bus, addr + 1)),
 
         0xCB => {
             let regs: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
@@ -3116,37 +3134,37 @@ pub fn format_mnemonic(mmu: &MMU, addr: usize) -> String {
                 "RES 2,", "RES 3,", "RES 4,", "RES 5,", "RES 6,", "RES 7,", "SET 0,", "SET 1,",
                 "SET 2,", "SET 3,", "SET 4,", "SET 5,", "SET 6,", "SET 7,",
             ];
-            let op2 = mmu.direct_read(addr + 1);
+            let op2 = bus.peek(addr + 1);
             let reg = regs[(op2 & 7) as usize];
             let mnemonic = ops[(op2 >> 4) as usize];
             format!("{} {}", mnemonic, reg)
         }
 
-        0xCC => format!("CALL Z, 0x{:02X}", mmu.direct_read_u16(addr + 1)),
-        0xCD => format!("CALL ${:04X}", mmu.direct_read_u16(addr + 1)),
-        0xCE => format!("ADC  A, 0x{:02X}", mmu.direct_read(addr + 1)),
+        0xCC => format!("CALL Z, 0x{:02X}", peek_u16(bus, addr + 1)),
+        0xCD => format!("CALL ${:04X}", peek_u16(bus, addr + 1)),
+        0xCE => format!("ADC  A, 0x{:02X}", bus.peek(addr + 1)),
 
-        0xD2 => format!("JP   NC, 0x{:04X}", mmu.direct_read_u16(addr + 1)),
-        0xD4 => format!("CALL NC, 0x{:04X}", mmu.direct_read_u16(addr + 1)),
-        0xD6 => format!("SUB  0x{:02X}", mmu.direct_read(addr + 1)),
-        0xDA => format!("JP   C, 0x{:04X}", mmu.direct_read_u16(addr + 1)),
-        0xDC => format!("CALL C, 0x{:02X}", mmu.direct_read_u16(addr + 1)),
+        0xD2 => format!("JP   NC, 0x{:04X}", peek_u16(bus, addr + 1)),
+        0xD4 => format!("CALL NC, 0x{:04X}", peek_u16(bus, addr + 1)),
+        0xD6 => format!("SUB  0x{:02X}", bus.peek(addr + 1)),
+        0xDA => format!("JP   C, 0x{:04X}", peek_u16(bus, addr + 1)),
+        0xDC => format!("CALL C, 0x{:02X}", peek_u16(bus, addr + 1)),
         0xDD => format!("! Illegal op code: 0x{:02X}", op),
-        0xDE => format!("SBC  A, 0x{:02X}", mmu.direct_read(addr + 1)),
+        0xDE => format!("SBC  A, 0x{:02X}", bus.peek(addr + 1)),
 
-        0xE0 => format!("LD   ($FF00+${:02X}), A", mmu.direct_read(addr + 1)),
-        0xEA => format!("LD   (${:04X}), A", mmu.direct_read_u16(addr + 1)),
-        0xE6 => format!("AND  ${:02X}", mmu.direct_read(addr + 1)),
+        0xE0 => format!("LD   ($FF00+${:02X}), A", bus.peek(addr + 1)),
+        0xEA => format!("LD   (${:04X}), A", peek_u16(bus, addr + 1)),
+        0xE6 => format!("AND  ${:02X}", bus.peek(addr + 1)),
         0xEC => format!("! Illegal op code: 0x{:02X}", op),
         0xED => format!("! Illegal op code: 0x{:02X}", op),
-        0xEE => format!("XOR  0x{:02X}", mmu.direct_read(addr + 1)),
+        0xEE => format!("XOR  0x{:02X}", bus.peek(addr + 1)),
 
-        0xF0 => format!("LD   A, ($FF00+${:02X})", mmu.direct_read(addr + 1)),
-        0xF6 => format!("OR   0x{:02X}", mmu.direct_read(addr + 1)),
-        0xF8 => format!("LD   HL, SP + ${:02X}", mmu.direct_read(addr + 1)),
-        0xFA => format!("LD   A, (${:04X})", mmu.direct_read_u16(addr + 1)),
+        0xF0 => format!("LD   A, ($FF00+${:02X})", bus.peek(addr + 1)),
+        0xF6 => format!("OR   0x{:02X}", bus.peek(addr + 1)),
+        0xF8 => format!("LD   HL, SP + ${:02X}", bus.peek(addr + 1)),
+        0xFA => format!("LD   A, (${:04X})", peek_u16(bus, addr + 1)),
         0xFC => format!("! Illegal op code: 0x{:02X}", op),
-        0xFE => format!("CP   ${:02X}", mmu.direct_read(addr + 1)),
+        0xFE => format!("CP   ${:02X}", bus.peek(addr + 1)),
 
         _ => {
             let easy = SIMPLE_MNEMONICS[op as usize];
@@ -0,0 +1,386 @@
+// IO register descriptions
+//
+// Names and bit fields of the IO registers, used by the debugger to
+// show the registers decoded. Registers without fields, such as SCX,
+// are plain values.
+
+use super::mmu::{
+    BGP_REG, DIV_REG, DMA_REG, IE_REG, IF_REG, KEY1_REG, LCDC_REG, LYC_REG, LY_REG, NR10_REG,
+    NR11_REG, NR12_REG, NR13_REG, NR14_REG, NR21_REG, NR22_REG, NR23_REG, NR24_REG, NR30_REG,
+    NR31_REG, NR32_REG, NR33_REG, NR34_REG, NR41_REG, NR42_REG, NR43_REG, NR44_REG, NR50_REG,
+    NR51_REG, NR52_REG, OBP0_REG, OBP1_REG, P1_REG, PCM12_REG, PCM34_REG, SB_REG, SCX_REG, SCY_REG,
+    SC_REG, STAT_REG, SVBK_REG, TAC_REG, TIMA_REG, TMA_REG, VBK_REG, WX_REG, WY_REG,
+};
+
+pub struct BitField {
+    pub name: &'static str,
+
+    // Lowest bit, and number of bits
+    pub shift: u8,
+    pub width: u8,
+
+    // Names of the values. Single bits without names are shown as
+    // off or on, and other fields as numbers.
+    pub values: &'static [&'static str],
+}
+
+pub struct IoRegister {
+    pub name: &'static str,
+    pub address: usize,
+    pub group: &'static str,
+    pub fields: &'static [BitField],
+}
+
+const fn bit(name: &'static str, shift: u8) -> BitField {
+    BitField {
+        name,
+        shift,
+        width: 1,
+        values: &[],
+    }
+}
+
+const fn named_bit(name: &'static str, shift: u8, values: &'static [&'static str]) -> BitField {
+    BitField {
+        name,
+        shift,
+        width: 1,
+        values,
+    }
+}
+
+const fn field(
+    name: &'static str,
+    shift: u8,
+    width: u8,
+    values: &'static [&'static str],
+) -> BitField {
+    BitField {
+        name,
+        shift,
+        width,
+        values,
+    }
+}
+
+const fn reg(
+    name: &'static str,
+    address: usize,
+    group: &'static str,
+    fields: &'static [BitField],
+) -> IoRegister {
+    IoRegister {
+        name,
+        address,
+        group,
+        fields,
+    }
+}
+
+const BUTTON: &[&str] = &["pressed", "released"];
+const SELECT: &[&str] = &["selected", "not selected"];
+const SHADES: &[&str] = &["white", "light gray", "dark gray", "black"];
+const TILE_MAP: &[&str] = &["9800", "9C00"];
+const DUTY: &[&str] = &["12.5%", "25%", "50%", "75%"];
+const ENVELOPE: &[&str] = &["decrease", "increase"];
+
+const INTERRUPTS: &[BitField] = &[
+    bit("Joypad", 4),
+    bit("Serial", 3),
+    bit("Timer", 2),
+    bit("STAT", 1),
+    bit("VBlank", 0),
+];
+
+const LENGTH_DUTY: &[BitField] = &[field("Duty", 6, 2, DUTY), field("Length", 0, 6, &[])];
+
+const VOLUME_ENVELOPE: &[BitField] = &[
+    field("Volume", 4, 4, &[]),
+    named_bit("Envelope", 3, ENVELOPE),
+    field("Pace", 0, 3, &[]),
+];
+
+const PERIOD_HIGH_CONTROL: &[BitField] = &[
+    bit("Trigger", 7),
+    bit("Length enable", 6),
+    field("Period high", 0, 3, &[]),
+];
+
+const PALETTE: &[BitField] = &[
+    field("Color 3", 6, 2, SHADES),
+    field("Color 2", 4, 2, SHADES),
+    field("Color 1", 2, 2, SHADES),
+    field("Color 0", 0, 2, SHADES),
+];
+
+pub const IO_REGISTERS: &[IoRegister] = &[
+    reg(
+        "P1",
+        P1_REG,
+        "Joypad",
+        &[
+            named_bit("Buttons", 5, SELECT),
+            named_bit("Directions", 4, SELECT),
+            named_bit("Down/Start", 3, BUTTON),
+            named_bit("Up/Select", 2, BUTTON),
+            named_bit("Left/B", 1, BUTTON),
+            named_bit("Right/A", 0, BUTTON),
+        ],
+    ),
+    reg("SB", SB_REG, "Serial", &[]),
+    reg(
+        "SC",
+        SC_REG,
+        "Serial",
+        &[
+            named_bit("Transfer", 7, &["idle", "active"]),
+            named_bit("Speed", 1, &["normal", "fast"]),
+            named_bit("Clock", 0, &["external", "internal"]),
+        ],
+    ),
+    reg("DIV", DIV_REG, "Timer", &[]),
+    reg("TIMA", TIMA_REG, "Timer", &[]),
+    reg("TMA", TMA_REG, "Timer", &[]),
+    reg(
+        "TAC",
+        TAC_REG,
+        "Timer",
+        &[
+            bit("Enable", 2),
+            field(
+                "Clock",
+                0,
+                2,
+                &["4096 Hz", "262144 Hz", "65536 Hz", "16384 Hz"],
+            ),
+        ],
+    ),
+    reg("IF", IF_REG, "Interrupts", INTERRUPTS),
+    reg("IE", IE_REG, "Interrupts", INTERRUPTS),
+    reg(
+        "NR10",
+        NR10_REG,
+        "Sound",
+        &[
+            field("Sweep pace", 4, 3, &[]),
+            named_bit("Direction", 3, &["increase", "decrease"]),
+            field("Step", 0, 3, &[]),
+        ],
+    ),
+    reg("NR11", NR11_REG, "Sound", LENGTH_DUTY),
+    reg("NR12", NR12_REG, "Sound", VOLUME_ENVELOPE),
+    reg("NR13", NR13_REG, "Sound", &[]),
+    reg("NR14", NR14_REG, "Sound", PERIOD_HIGH_CONTROL),
+    reg("NR21", NR21_REG, "Sound", LENGTH_DUTY),
+    reg("NR22", NR22_REG, "Sound", VOLUME_ENVELOPE),
+    reg("NR23", NR23_REG, "Sound", &[]),
+    reg("NR24", NR24_REG, "Sound", PERIOD_HIGH_CONTROL),
+    reg("NR30", NR30_REG, "Sound", &[bit("DAC", 7)]),
+    reg("NR31", NR31_REG, "Sound", &[]),
+    reg(
+        "NR32",
+        NR32_REG,
+        "Sound",
+        &[field("Volume", 5, 2, &["mute", "100%", "50%", "25%"])],
+    ),
+    reg("NR33", NR33_REG, "Sound", &[]),
+    reg("NR34", NR34_REG, "Sound", PERIOD_HIGH_CONTROL),
+    reg("NR41", NR41_REG, "Sound", &[field("Length", 0, 6, &[])]),
+    reg("NR42", NR42_REG, "Sound", VOLUME_ENVELOPE),
+    reg(
+        "NR43",
+        NR43_REG,
+        "Sound",
+        &[
+            field("Clock shift", 4, 4, &[]),
+            named_bit("LFSR width", 3, &["15 bits", "7 bits"]),
+            field("Clock divider", 0, 3, &[]),
+        ],
+    ),
+    reg(
+        "NR44",
+        NR44_REG,
+        "Sound",
+        &[bit("Trigger", 7), bit("Length enable", 6)],
+    ),
+    reg(
+        "NR50",
+        NR50_REG,
+        "Sound",
+        &[
+            bit("VIN left", 7),
+            field("Left volume", 4, 3, &[]),
+            bit("VIN right", 3),
+            field("Right volume", 0, 3, &[]),
+        ],
+    ),
+    reg(
+        "NR51",
+        NR51_REG,
+        "Sound",
+        &[
+            bit("CH4 left", 7),
+            bit("CH3 left", 6),
+            bit("CH2 left", 5),
+            bit("CH1 left", 4),
+            bit("CH4 right", 3),
+            bit("CH3 right", 2),
+            bit("CH2 right", 1),
+            bit("CH1 right", 0),
+        ],
+    ),
+    reg(
+        "NR52",
+        NR52_REG,
+        "Sound",
+        &[
+            bit("Sound", 7),
+            bit("CH4", 3),
+            bit("CH3", 2),
+            bit("CH2", 1),
+            bit("CH1", 0),
+        ],
+    ),
+    reg(
+        "LCDC",
+        LCDC_REG,
+        "Video",
+        &[
+            bit("LCD", 7),
+            named_bit("Window tile map", 6, TILE_MAP),
+            bit("Window", 5),
+            named_bit("Tile data", 4, &["8800", "8000"]),
+            named_bit("BG tile map", 3, TILE_MAP),
+            named_bit("Object size", 2, &["8x8", "8x16"]),
+            bit("Objects", 1),
+            bit("BG and window", 0),
+        ],
+    ),
+    reg(
+        "STAT",
+        STAT_REG,
+        "Video",
+        &[
+            bit("LYC interrupt", 6),
+            bit("OAM interrupt", 5),
+            bit("VBlank interrupt", 4),
+            bit("HBlank interrupt", 3),
+            named_bit("LY=LYC", 2, &["no", "yes"]),
+            field("Mode", 0, 2, &["HBlank", "VBlank", "OAM scan", "Drawing"]),
+        ],
+    ),
+    reg("SCY", SCY_REG, "Video", &[]),
+    reg("SCX", SCX_REG, "Video", &[]),
+    reg("LY", LY_REG, "Video", &[]),
+    reg("LYC", LYC_REG, "Video", &[]),
+    reg("DMA", DMA_REG, "Video", &[]),
+    reg("BGP", BGP_REG, "Video", PALETTE),
+    reg("OBP0", OBP0_REG, "Video", PALETTE),
+    reg("OBP1", OBP1_REG, "Video", PALETTE),
+    reg("WY", WY_REG, "Video", &[]),
+    reg("WX", WX_REG, "Video", &[]),
+    reg(
+        "KEY1",
+        KEY1_REG,
+        "CGB",
+        &[
+            named_bit("Speed", 7, &["normal", "double"]),
+            named_bit("Switch", 0, &["no", "armed"]),
+        ],
+    ),
+    reg("VBK", VBK_REG, "CGB", &[field("VRAM bank", 0, 1, &[])]),
+    reg("SVBK", SVBK_REG, "CGB", &[field("WRAM bank", 0, 3, &[])]),
+    reg(
+        "PCM12",
+        PCM12_REG,
+        "CGB",
+        &[field("CH2", 4, 4, &[]), field("CH1", 0, 4, &[])],
+    ),
+    reg(
+        "PCM34",
+        PCM34_REG,
+        "CGB",
+        &[field("CH4", 4, 4, &[]), field("CH3", 0, 4, &[])],
+    ),
+];
+
+// Register groups, in the order they are listed
+pub const IO_REGISTER_GROUPS: [&str; 7] = [
+    "Joypad",
+    "Serial",
+    "Timer",
+    "Interrupts",
+    "Sound",
+    "Video",
+    "CGB",
+];
+
+impl BitField {
+    pub fn value(&self, reg: u8) -> u8 {
+        (reg >> self.shift) & ((1u16 << self.width) - 1) as u8
+    }
+
+    // Value of the field as text, such as "50%" or "3"
+    pub fn format(&self, reg: u8) -> String {
+        let value = self.value(reg);
+        match self.values.get(value as usize) {
+            Some(name) => name.to_string(),
+            None if self.width == 1 => ["off", "on"][value as usize].to_string(),
+            None => value.to_string(),
+        }
+    }
+}
+
+impl IoRegister {
+    // All fields, as "name: value"
+    pub fn decode(&self, value: u8) -> Vec<String> {
+        self.fields
+            .iter()
+            .map(|f| format!("{}: {}", f.name, f.format(value)))
+            .collect()
+    }
+}
+
+pub fn find_io_register(address: usize) -> Option<&'static IoRegister> {
+    IO_REGISTERS.iter().find(|r| r.address == address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let stat = find_io_register(STAT_REG).unwrap();
+        assert_eq!(
+            stat.decode(0x46),
+            [
+                "LYC interrupt: on",
+                "OAM interrupt: off",
+                "VBlank interrupt: off",
+                "HBlank interrupt: off",
+                "LY=LYC: yes",
+                "Mode: OAM scan",
+            ]
+        );
+
+        let nr11 = find_io_register(NR11_REG).unwrap();
+        assert_eq!(nr11.decode(0x83), ["Duty: 50%", "Length: 3"]);
+    }
+
+    #[test]
+    fn test_fields_within_register() {
+        for reg in IO_REGISTERS {
+            assert!(IO_REGISTER_GROUPS.contains(&reg.group));
+            for field in reg.fields {
+                assert!(
+                    field.shift + field.width <= 8,
+                    "{} {}",
+                    reg.name,
+                    field.name
+                );
+                assert!(field.values.len() <= 1 << field.width);
+            }
+        }
+    }
+}
@@ -4,18 +4,22 @@ use std::fs::File;
 use std::io::Read;
 
 use super::emu::Machine;
-use super::interrupt::{IF_INP_BIT, IF_LCDC_BIT, IF_TMR_BIT, IF_VBLANK_BIT};
+use super::interrupt::{IF_INP_BIT, IF_LCDC_BIT, IF_SERIAL_BIT, IF_TMR_BIT, IF_VBLANK_BIT};
 
 use super::apu::apu::{AudioProcessingUnit, SAMPLES_PER_FRAME};
+use super::bus::{AddressMap, Device, Ram, WorkRam};
 use super::buttons::Buttons;
+use super::cartridge::cartridge_header::{verify_rom, HeaderWarning, ROM_BANK_SIZE};
 use super::cartridge::{cartridge::Cartridge, cartridge::NoCartridge, load_cartridge};
+use super::cdl::{CodeDataLog, CDL_CODE, CDL_DATA};
+use super::cpu::Bus;
 use super::dma::DMA;
-use super::instructions;
-use super::interrupt::handle_interrupts;
 use super::ppu::PPU;
-use super::registers::Registers;
+use super::ppu_events::PpuEventLog;
 use super::serial::Serial;
 use super::timer::Timer;
+use super::timing::{Subsystem, SubsystemTiming};
+use crate::savestate::{SaveState, Serializer};
 
 pub const OAM_OFFSET: usize = 0xFE00;
 
@@ -46,6 +50,27 @@ pub const OBP1_REG: usize = 0xFF49;
 pub const WY_REG: usize = 0xFF4A;
 pub const WX_REG: usize = 0xFF4B;
 
+// CGB compatibility mode, written by the boot ROM
+pub const KEY0_REG: usize = 0xFF4C;
+
+// CGB speed switch
+pub const KEY1_REG: usize = 0xFF4D;
+
+// CGB VRAM and WRAM bank select
+pub const VBK_REG: usize = 0xFF4F;
+pub const SVBK_REG: usize = 0xFF70;
+
+// Boot ROM disable
+pub const BOOT_REG: usize = 0xFF50;
+
+// Sizes of the DMG and CGB boot ROMs
+const BOOTSTRAP_SIZE_DMG: usize = 0x100;
+const BOOTSTRAP_SIZE_CGB: usize = 0x900;
+
+// CGB digital output of the sound channels
+pub const PCM12_REG: usize = 0xFF76;
+pub const PCM34_REG: usize = 0xFF77;
+
 // Sound registers
 // - Sound Generator 1
 pub const NR10_REG: usize = 0xFF10;
@@ -76,30 +101,129 @@ pub const NR50_REG: usize = 0xFF24;
 pub const NR51_REG: usize = 0xFF25;
 pub const NR52_REG: usize = 0xFF26;
 
+// Bits that always read as 1 in the I/O registers 0xFF00 to 0xFF7F,
+// because they are unused or not connected. Registers that don't
+// exist at all read as 0xFF.
+// Ref: https://gbdev.io/pandocs/Hardware_Reg_List.html and the
+// unused_hwio test in the mooneye-gb test suite.
+#[rustfmt::skip]
+const DMG_IO_READ_MASK: [u8; 0x80] = [
+    // P1    SB    SC    -     DIV   TIMA  TMA   TAC   -     -     -     -     -     -     -     IF
+    0xC0, 0x00, 0x7E, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // NR10  NR11  NR12  NR13  NR14  -     NR21  NR22  NR23  NR24  NR30  NR31  NR32  NR33  NR34  -
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // NR41  NR42  NR43  NR44  NR50  NR51  NR52  -     -     -     -     -     -     -     -     -
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // Wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // LCDC  STAT  SCY   SCX   LY    LYC   DMA   BGP   OBP0  OBP1  WY    WX    -     -     -     -
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+// Same as above, for the Color Game Boy. SC has a clock speed bit,
+// and the CGB-only registers are not masked.
+#[rustfmt::skip]
+const CGB_IO_READ_MASK: [u8; 0x80] = [
+    // P1    SB    SC    -     DIV   TIMA  TMA   TAC   -     -     -     -     -     -     -     IF
+    0xC0, 0x00, 0x7C, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // NR10  NR11  NR12  NR13  NR14  -     NR21  NR22  NR23  NR24  NR30  NR31  NR32  NR33  NR34  -
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // NR41  NR42  NR43  NR44  NR50  NR51  NR52  -     -     -     -     -     -     -     -     -
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // Wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // LCDC  STAT  SCY   SCX   LY    LYC   DMA   BGP   OBP0  OBP1  WY    WX    -     KEY1  -     VBK
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, 0xFF, 0xFE,
+    // BOOT  HDMA1 HDMA2 HDMA3 HDMA4 HDMA5 RP    -     -     -     -     -     -     -     -     -
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x3C, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // -     -     -     -     -     -     -     -     BCPS  BCPD  OCPS  OCPD  OPRI  -     -     -
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x40, 0x00, 0x40, 0x00, 0xFE, 0xFF, 0xFF, 0xFF,
+    // SVBK  -     FF72  FF73  FF74  FF75  PCM12 PCM34 -     -     -     -     -     -     -     -
+    0xF8, 0xFF, 0x00, 0x00, 0x00, 0x8F, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+// Values of the I/O registers when the boot ROM has finished, on the
+// DMG and the CGB. Registers that aren't listed are 0. Bits that
+// always read as 1 are included, as in the documented values.
+//
+// DIV and STAT are left out, since they depend on how long the boot
+// ROM ran, as are the write-only sound registers, which always read
+// as 0xFF. NR52 comes first, as the other sound registers can only be
+// written while the sound hardware is on.
+// Ref: https://gbdev.io/pandocs/Power_Up_Sequence.html
+#[rustfmt::skip]
+const POST_BOOT_IO: [(usize, u8, u8); 22] = [
+    // Register DMG   CGB
+    (P1_REG,    0xCF, 0xCF),
+    (SB_REG,    0x00, 0x00),
+    (SC_REG,    0x7E, 0x7F),
+    (TIMA_REG,  0x00, 0x00),
+    (TMA_REG,   0x00, 0x00),
+    (TAC_REG,   0xF8, 0xF8),
+    (IF_REG,    0xE1, 0xE1),
+    (NR52_REG,  0xF1, 0xF1),
+    (NR10_REG,  0x80, 0x80),
+    (NR11_REG,  0xBF, 0xBF),
+    (NR12_REG,  0xF3, 0xF3),
+    (NR21_REG,  0x3F, 0x3F),
+    (NR22_REG,  0x00, 0x00),
+    (NR30_REG,  0x7F, 0x7F),
+    (NR32_REG,  0x9F, 0x9F),
+    (NR50_REG,  0x77, 0x77),
+    (NR51_REG,  0xF3, 0xF3),
+    (LCDC_REG,  0x91, 0x91),
+    (SCY_REG,   0x00, 0x00),
+    (SCX_REG,   0x00, 0x00),
+    (DMA_REG,   0xFF, 0x00),
+    (BGP_REG,   0xFC, 0xFC),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResetKind {
+    // Like pressing a reset button: the CPU and all hardware registers
+    // are reset, but the content of all memory is preserved, as is
+    // cartridge RAM and the RTC.
+    Soft,
+
+    // Power cycle: like a soft reset, but the content of memory is
+    // lost. Cartridge RAM without battery is cleared, and work RAM,
+    // high RAM and the wave RAM get new semi-random content.
+    Hard,
+}
+
 // FIXME: Same as MemoryMapped, but using u16 instead of usize.
 //        All code should be updated to use MemoryMapped instead.
 pub trait MemoryMapped16 {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
 
-    // Perform reset as after power cycle
-    fn reset(&mut self);
+    // Perform a soft or hard reset. See ResetKind.
+    fn reset(&mut self, kind: ResetKind);
 }
 
 pub trait MemoryMapped {
     fn read(&self, address: usize) -> u8;
     fn write(&mut self, address: usize, value: u8);
 
-    // Perform reset as after power cycle
-    fn reset(&mut self);
+    // Perform a soft or hard reset. See ResetKind.
+    fn reset(&mut self, kind: ResetKind);
 }
 
 pub struct MMU {
-    pub reg: Registers,
+    pub machine: Machine,
     pub cartridge: Box<dyn Cartridge>,
 
-    // RAM bank (0xC000 to 0xCFFF)
-    pub ram: [u8; 0x2000],
+    // Problems found in the ROM of the cartridge when it was loaded
+    pub header_warnings: Vec<HeaderWarning>,
+
+    // Devices at each address
+    map: AddressMap,
+
+    // Work RAM (0xC000 to 0xDFFF, echoed at 0xE000 to 0xFDFF)
+    pub wram: WorkRam,
 
     // I/O registers (0xFF00 to 0xFFFF)
     // FIXME: these are used to allow emulator to progress.
@@ -107,10 +231,14 @@ pub struct MMU {
     pub io_reg: [u8; 0x80],
     ie_reg: u8,
 
-    // Internal RAM (0xFF80 to 0xFFFF)
-    pub internal_ram: [u8; 0x7F],
+    // High RAM (0xFF80 to 0xFFFE)
+    pub hram: Ram,
 
-    bootstrap: [u8; 0x100],
+    // The DMG boot ROM is 256 bytes, mapped at 0x0000-0x00FF. The CGB
+    // boot ROM is 2304 bytes, and is also mapped at 0x0200-0x08FF, so
+    // that the cartridge header in between can be read.
+    bootstrap: [u8; BOOTSTRAP_SIZE_CGB],
+    bootstrap_size: usize,
     pub bootstrap_mode: bool,
     pub watch_triggered: bool,
 
@@ -123,24 +251,35 @@ pub struct MMU {
 
     pub display_updated: bool,
 
-    // If interrupt handler was entered while executing
-    // the previous operation, this variable is set to
-    // the interrupt bit. Otherwise it's reset to zero.
-    pub entered_interrupt_handler: u8,
-
     pub sample_count: u32,
+
+    // Number of times the CPU has read P1, and the last value read
+    pub p1_reads: u64,
+    pub last_p1_read: u8,
+
+    // Time spent in each subsystem (with the "timing" feature)
+    pub timing: SubsystemTiming,
+
+    // Writes to PPU registers, with the position of the PPU
+    pub ppu_events: PpuEventLog,
+
+    // Accesses to cartridge ROM, by instruction fetches and other reads
+    pub cdl: CodeDataLog,
 }
 
 impl MMU {
     pub fn new(machine: Machine) -> Self {
         MMU {
-            reg: Registers::new(),
+            machine,
             cartridge: Box::new(NoCartridge {}),
-            ram: [0; 0x2000],
+            header_warnings: Vec::new(),
+            map: AddressMap::game_boy(),
+            wram: WorkRam::new(machine),
             io_reg: [0; 0x80],
             ie_reg: 0,
-            internal_ram: [0; 0x7F],
-            bootstrap: [0; 0x100],
+            hram: Ram::new(0xFF80, 0x7F),
+            bootstrap: [0; BOOTSTRAP_SIZE_CGB],
+            bootstrap_size: 0,
             bootstrap_mode: true,
             watch_triggered: false,
             timer: Timer::new(),
@@ -148,36 +287,57 @@ impl MMU {
             ppu: PPU::new(machine),
             buttons: Buttons::new(),
             display_updated: false,
-            entered_interrupt_handler: 0,
 
             // Create APU that will buffer up to 10 frames of audio
             apu: AudioProcessingUnit::new(machine, SAMPLES_PER_FRAME as u32 * 10),
 
             sample_count: 0,
-            serial: Serial::new(None),
+            serial: Serial::new(machine, None, None),
+            p1_reads: 0,
+            last_p1_read: 0,
+            timing: SubsystemTiming::new(),
+            ppu_events: PpuEventLog::new(),
+            cdl: CodeDataLog::new(),
         }
     }
 
-    pub fn reset(&mut self) {
-        self.reg = Registers::new();
-        self.cartridge.reset();
-        self.ram.fill(0);
+    pub fn reset(&mut self, kind: ResetKind) {
+        self.cartridge.reset(kind);
         self.io_reg.fill(0);
         self.ie_reg = 0;
-        self.internal_ram.fill(0);
+        self.wram.reset(kind);
+        self.hram.reset(kind);
         self.bootstrap_mode = true;
         self.watch_triggered = false;
         self.timer = Timer::new();
         self.dma = DMA::new();
-        self.ppu.reset();
-        self.buttons = Buttons::new();
+        self.ppu.reset(kind);
+        self.buttons.reset();
         self.display_updated = false;
-        self.entered_interrupt_handler = 0;
 
         // The APU shares a ringbuf with audio code so it can't be recreated
-        self.apu.reset();
+        self.apu.reset(kind);
 
-        self.serial = Serial::new(None);
+        self.serial = Serial::new(
+            self.machine,
+            self.serial.output.take(),
+            self.serial.device.take(),
+        );
+    }
+
+    // Seed the generators of the power-on content of RAM
+    pub fn set_seed(&mut self, seed: u64) {
+        self.wram.set_seed(seed);
+        self.hram.set_seed(seed.wrapping_add(1));
+        self.apu.set_seed(seed);
+    }
+
+    // Clear RAM and use a fixed wave RAM pattern on power cycle. Set
+    // from `crate::deterministic()` when the MMU is created.
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.wram.set_deterministic(enabled);
+        self.hram.set_deterministic(enabled);
+        self.apu.set_deterministic(enabled);
     }
 
     pub fn init(&mut self) {
@@ -189,125 +349,233 @@ impl MMU {
         self.io_reg[0xFF03 & 0x4F] = 0xFF;
     }
 
-    pub fn wakeup_if_halted(&mut self) {
-        if self.reg.halted {
-            self.reg.halted = false;
+    // Set the I/O registers to the values the boot ROM leaves them in
+    pub fn init_post_boot_io(&mut self) {
+        let cgb = matches!(self.machine, Machine::GameBoyCGB);
+        for (addr, dmg_value, cgb_value) in POST_BOOT_IO {
+            let value = if cgb { cgb_value } else { dmg_value };
+            match addr {
+                // Only set the register, without starting a transfer
+                DMA_REG => self.dma.last_write_dma_reg = value,
+
+                _ => self.direct_write(addr, value),
+            }
         }
     }
 
     pub fn get_if_reg(&self) -> u8 {
-        return self.ppu.irq | self.timer.irq | self.buttons.irq;
+        return self.ppu.irq | self.timer.irq | self.serial.irq | self.buttons.irq;
     }
 
     pub fn set_if_reg(&mut self, value: u8) {
         self.ppu.irq = value & (IF_VBLANK_BIT | IF_LCDC_BIT);
         self.timer.irq = value & IF_TMR_BIT;
+        self.serial.irq = value & IF_SERIAL_BIT;
         self.buttons.irq = value & IF_INP_BIT;
     }
 
     pub fn clear_if_reg_bits(&mut self, mask: u8) {
         self.ppu.irq &= !mask;
         self.timer.irq &= !mask;
+        self.serial.irq &= !mask;
         self.buttons.irq &= !mask;
     }
 
-    pub fn exec_op(&mut self) {
-        if !self.reg.halted {
-            instructions::step(self);
-        } else {
-            self.tick(4);
-        }
-
-        self.entered_interrupt_handler = handle_interrupts(self);
+    pub fn tick(&mut self, cycles: u32) {
+        self.tick_cycles(cycles, true);
     }
 
-    pub fn tick(&mut self, cycles: u32) {
+    // Advance all subsystems. OAM DMA is only advanced if `dma` is
+    // true, which it isn't while the CPU is halted.
+    fn tick_cycles(&mut self, cycles: u32, dma: bool) {
         assert!(cycles % 4 == 0);
 
+        let start = self.timing.start();
         for _ in 0..cycles / 4 {
             self.timer.update_4t();
-            self.apu.update_4t(self.timer.cycle);
+            self.apu.update_4t(self.timer.div_counter());
+            self.serial.update_4t(self.timer.div_counter());
         }
+        self.timing.stop(Subsystem::Apu, start);
 
         self.buttons.update();
 
+        let start = self.timing.start();
         let updated = self.ppu.update(cycles);
         self.display_updated = self.display_updated || updated;
+        if updated {
+            self.ppu_events.end_frame();
+        }
+        self.timing.stop(Subsystem::Ppu, start);
 
-        if !self.reg.halted {
+        let start = self.timing.start();
+        if dma {
             for _ in 0..(cycles / 4) {
                 if self.dma.is_active() {
                     let offset = self.dma.start_address.unwrap() as usize;
                     let idx = self.dma.step as usize;
-                    let b = if offset < 0xE000 {
-                        self.direct_read(offset + idx)
-                    } else {
-                        self.ram[(offset + idx - 0xE000) as usize]
-                    };
+                    let b = self.dma_read(offset + idx);
                     self.ppu.write(OAM_OFFSET + idx, b)
                 }
                 self.dma.update();
             }
         }
+        self.timing.stop(Subsystem::Mmu, start);
+    }
+
+    // Read a byte of an OAM DMA transfer. DMA sees work RAM in the
+    // whole 0xE000-0xFFFF range, not just in the echo region, so
+    // sources in OAM and I/O space read work RAM rather than the
+    // registers.
+    fn dma_read(&self, addr: usize) -> u8 {
+        match addr {
+            0xE000..=0xFFFF => self.wram.read(addr),
+            _ => self.direct_read(addr),
+        }
     }
 
     pub fn load_bootstrap(&mut self, filename: &str) -> usize {
         // Open and read content of boot rom
         let mut f = File::open(filename).expect("failed to open boot rom");
-        f.read(&mut self.bootstrap)
-            .expect("failed to read content of boot rom")
+        let mut content = Vec::new();
+        f.read_to_end(&mut content)
+            .expect("failed to read content of boot rom");
+        if content.len() != BOOTSTRAP_SIZE_DMG && content.len() != BOOTSTRAP_SIZE_CGB {
+            println!(
+                "Unexpected boot ROM size: {} bytes. Expected {} or {}.",
+                content.len(),
+                BOOTSTRAP_SIZE_DMG,
+                BOOTSTRAP_SIZE_CGB
+            );
+        }
+
+        let size = content.len().min(BOOTSTRAP_SIZE_CGB);
+        self.bootstrap.fill(0);
+        self.bootstrap[0..size].copy_from_slice(&content[0..size]);
+        self.bootstrap_size = size;
+        size
     }
 
-    pub fn load_cartridge(&mut self, filename: &str) {
-        self.cartridge = load_cartridge(filename.to_string());
+    pub fn bootstrap_loaded(&self) -> bool {
+        self.bootstrap_size > 0
     }
 
-    pub fn fetch(&mut self) -> u8 {
-        let pc = self.reg.pc;
-        let value = self.read(pc as usize);
-        self.reg.pc = pc.wrapping_add(1);
-        value
+    // True if `addr` is in the boot ROM while it's mapped
+    fn in_bootstrap(&self, addr: usize) -> bool {
+        self.bootstrap_mode
+            && match addr {
+                0x0000..=0x00FF => true,
+                0x0200..=0x08FF => self.bootstrap_size > BOOTSTRAP_SIZE_DMG,
+                _ => false,
+            }
     }
 
-    pub fn fetch_u16(&mut self) -> u16 {
-        let lo = self.fetch();
-        let hi = self.fetch();
-        return ((hi as u16) << 8) | (lo as u16);
+    pub fn load_cartridge(&mut self, filename: &str) -> Result<(), String> {
+        let content = std::fs::read(filename).map_err(|e| e.to_string())?;
+        self.load_cartridge_rom(&content)
+    }
+
+    // Load the cartridge of a ROM that has been read already
+    pub fn load_cartridge_rom(&mut self, content: &Vec<u8>) -> Result<(), String> {
+        self.header_warnings = verify_rom(content);
+        for warning in self.header_warnings.iter() {
+            println!("Warning: {}", warning);
+        }
+        self.cartridge = load_cartridge(content)?;
+        Ok(())
     }
 
     pub fn read(&mut self, addr: usize) -> u8 {
+        self.log_rom_access(addr, CDL_DATA);
+        self.read_cycle(addr)
+    }
+
+    // Read a byte of an instruction
+    pub fn fetch(&mut self, addr: usize) -> u8 {
+        self.log_rom_access(addr, CDL_CODE);
+        self.read_cycle(addr)
+    }
+
+    #[inline]
+    fn log_rom_access(&mut self, addr: usize, flag: u8) {
+        if !self.cdl.is_enabled() || addr >= 0x8000 || self.in_bootstrap(addr) {
+            return;
+        }
+        let offset = match addr {
+            0x0000..=0x3FFF => addr,
+            _ => self.cartridge.rom_bank() * ROM_BANK_SIZE + addr - ROM_BANK_SIZE,
+        };
+        self.cdl.log(offset, flag);
+    }
+
+    fn read_cycle(&mut self, addr: usize) -> u8 {
         self.tick(4);
-        self.direct_read(addr)
+        let start = self.timing.start();
+        let value = self.direct_read(addr);
+        self.timing.stop(Subsystem::Mmu, start);
+        if addr == P1_REG {
+            self.p1_reads += 1;
+            self.last_p1_read = value;
+        }
+        value
+    }
+
+    // Bits of I/O register `addr` that always read as 1
+    fn io_read_mask(&self, addr: usize) -> u8 {
+        match self.machine {
+            Machine::GameBoyCGB => CGB_IO_READ_MASK[addr - 0xFF00],
+            _ => DMG_IO_READ_MASK[addr - 0xFF00],
+        }
+    }
+
+    // The device mapped at `device`'s addresses, if it only needs its
+    // own state. Other addresses are handled by the MMU.
+    fn device(&self, device: Device) -> Option<&dyn MemoryMapped> {
+        match device {
+            Device::Cartridge => Some(self.cartridge.as_ref()),
+            Device::Video => Some(&self.ppu),
+            Device::WorkRam => Some(&self.wram),
+            Device::HighRam => Some(&self.hram),
+            _ => None,
+        }
+    }
+
+    fn device_mut(&mut self, device: Device) -> Option<&mut dyn MemoryMapped> {
+        match device {
+            Device::Cartridge => Some(self.cartridge.as_mut()),
+            Device::Video => Some(&mut self.ppu),
+            Device::WorkRam => Some(&mut self.wram),
+            Device::HighRam => Some(&mut self.hram),
+            _ => None,
+        }
     }
 
     pub fn direct_read(&self, addr: usize) -> u8 {
-        match addr {
-            0x0000..=0x00FF => {
-                if self.bootstrap_mode {
-                    self.bootstrap[addr as usize]
-                } else {
-                    self.cartridge.read(addr)
-                }
-            }
-            0x0100..=0x3FFF => self.cartridge.read(addr),
-            0x4000..=0x7FFF => self.cartridge.read(addr), // self.romx[(addr - 0x4000) as usize],
-            0x8000..=0x9FFF => self.ppu.read(addr),
-            0xA000..=0xBFFF => self.cartridge.read(addr),
-            0xC000..=0xCFFF => self.ram[(addr - 0xC000)], // RAM
-            0xD000..=0xDFFF => self.ram[(addr - 0xC000)], // RAM (switchable on GBC)
-            0xE000..=0xFDFF => self.ram[(addr - 0xE000)], // RAM echo
-            0xFE00..=0xFE9F => self.ppu.read(addr),
-
-            // Unused and undocumented area. Seems to return 0 on DMG, and
-            // random values on CGB.
-            0xFEA0..=0xFEFF => 0x00,
+        // The boot ROM is mapped over the cartridge until disabled
+        if self.in_bootstrap(addr) {
+            return self.bootstrap[addr];
+        }
+
+        let device = self.map.decode(addr);
+        if let Some(d) = self.device(device) {
+            return d.read(addr);
+        }
+        match device {
+            Device::Io => self.read_io_reg(addr) | self.io_read_mask(addr),
+            Device::Unusable => self.read_unusable(addr),
+            Device::InterruptEnable => self.ie_reg,
+            _ => panic!("Read of unhandled address: 0x{:x}", addr),
+        }
+    }
 
+    fn read_io_reg(&self, addr: usize) -> u8 {
+        match addr {
             // Special registers in area 0xFF00 to 0xFFFF
             SB_REG..=SC_REG => self.serial.read_reg(addr),
             P1_REG => self.buttons.read_p1(),
             IF_REG => self.get_if_reg(),
             DIV_REG => self.timer.read_div(),
-            TIMA_REG => self.timer.tima,
+            TIMA_REG => self.timer.read_tima(),
             TMA_REG => self.timer.tma,
             TAC_REG => self.timer.tac,
             LCDC_REG => self.ppu.read(addr),
@@ -325,15 +593,27 @@ impl MMU {
 
             // Sound registers
             0xFF10..=0xFF3F => self.apu.read_reg(addr),
+            PCM12_REG if matches!(self.machine, Machine::GameBoyCGB) => self.apu.read_pcm12(),
+            VBK_REG if matches!(self.machine, Machine::GameBoyCGB) => self.ppu.read(addr),
+            SVBK_REG if matches!(self.machine, Machine::GameBoyCGB) => self.wram.read_svbk(),
+            PCM34_REG if matches!(self.machine, Machine::GameBoyCGB) => self.apu.read_pcm34(),
 
             // Use self.io_reg for I/O registers that have not been implemented yet
-            0xFF00..=0xFF7F => self.io_reg[(addr - 0xFF00) as usize],
-
-            0xFF80..=0xFFFE => self.internal_ram[(addr - 0xFF80) as usize],
-
-            IE_REG => self.ie_reg,
+            _ => self.io_reg[(addr - 0xFF00) as usize],
+        }
+    }
 
-            _ => panic!("Read of unhandled address: 0x{:x}", addr),
+    // The unusable area at 0xFEA0-0xFEFF reads 0xFF while the PPU
+    // blocks OAM. Otherwise DMG reads 0, and CGB revision E repeats
+    // the high nibble of the low address byte, as in 0xAA for 0xFEAx.
+    // Older CGB revisions return other values, which are not emulated.
+    fn read_unusable(&self, addr: usize) -> u8 {
+        if !self.ppu.is_oam_accessible() {
+            return 0xFF;
+        }
+        match self.machine {
+            Machine::GameBoyCGB => ((addr & 0xF0) | ((addr >> 4) & 0x0F)) as u8,
+            _ => 0x00,
         }
     }
 
@@ -368,21 +648,34 @@ impl MMU {
 
     pub fn write(&mut self, addr: usize, value: u8) {
         self.tick(4);
-        self.direct_write(addr, value)
+        if self.ppu_events.enabled && PpuEventLog::is_logged(addr) {
+            let (line, dot) = self.ppu.position();
+            self.ppu_events.record(line, dot, addr, value);
+        }
+        let start = self.timing.start();
+        self.direct_write(addr, value);
+        self.timing.stop(Subsystem::Mmu, start);
     }
 
     pub fn direct_write(&mut self, addr: usize, value: u8) {
-        match addr {
-            0x0000..=0x3FFF => self.cartridge.write(addr, value),
-            0x4000..=0x7FFF => self.cartridge.write(addr, value),
-            0x8000..=0x9FFF => self.ppu.write(addr, value),
-            0xA000..=0xBFFF => self.cartridge.write(addr, value),
-            0xC000..=0xCFFF => self.ram[(addr - 0xC000)] = value,
-            0xD000..=0xDFFF => self.ram[(addr - 0xC000)] = value,
-            0xE000..=0xFDFF => self.ram[(addr - 0xE000)] = value,
-            0xFE00..=0xFE9F => self.ppu.write(addr, value),
-            0xFEA0..=0xFEFF => {}
+        let device = self.map.decode(addr);
+        if let Some(d) = self.device_mut(device) {
+            d.write(addr, value);
+            return;
+        }
+        match device {
+            Device::Io => self.write_io_reg(addr, value),
+            Device::Unusable => {}
+            Device::InterruptEnable => {
+                println!("SET IE TO {}", value);
+                self.ie_reg = value
+            }
+            _ => panic!("Write to unhandled address: 0x{:x}", addr),
+        }
+    }
 
+    fn write_io_reg(&mut self, addr: usize, value: u8) {
+        match addr {
             // Sound registers
             0xFF10..=0xFF3F => self.apu.write_reg(addr, value),
 
@@ -390,9 +683,9 @@ impl MMU {
             SB_REG => self.serial.write_reg(SB_REG, value),
             SC_REG => self.serial.write_reg(SC_REG, value),
             DIV_REG => self.timer.write_div(value),
-            TIMA_REG => self.timer.tima = value,
-            TMA_REG => self.timer.tma = value,
-            TAC_REG => self.timer.tac = value,
+            TIMA_REG => self.timer.write_tima(value),
+            TMA_REG => self.timer.write_tma(value),
+            TAC_REG => self.timer.write_tac(value),
             0xFF08 => println!("write to 0xFF08 - undocumented!: {}", value),
             IF_REG => self.set_if_reg(value),
 
@@ -409,32 +702,467 @@ impl MMU {
             WY_REG => self.ppu.write(addr, value),
             WX_REG => self.ppu.write(addr, value),
 
-            0xFF4D => println!("write to 0xFF4D - KEY1 (CGB only): {}", value),
+            // Bit 2 selects DMG compatibility. Locked once the boot ROM
+            // is disabled, and always reads 0xFF.
+            KEY0_REG => {
+                if matches!(self.machine, Machine::GameBoyCGB) && self.bootstrap_mode {
+                    self.ppu.cgb_mode = value & 4 == 0;
+                }
+            }
+
+            // Only the speed switch request bit is writable
+            KEY1_REG => {
+                if matches!(self.machine, Machine::GameBoyCGB) {
+                    self.io_reg[KEY1_REG - 0xFF00] = value & 1;
+                }
+            }
+
+            VBK_REG if matches!(self.machine, Machine::GameBoyCGB) => self.ppu.write(addr, value),
+            SVBK_REG if matches!(self.machine, Machine::GameBoyCGB) => self.wram.write_svbk(value),
+
+            // Read only
+            PCM12_REG | PCM34_REG => {}
 
-            // 0xFF50: write 1 to disable bootstrap ROM
-            0xFF50 => self.bootstrap_mode = false,
+            // Write 1 to disable the bootstrap ROM. It can't be
+            // enabled again, other than by a reset.
+            BOOT_REG => {
+                if value & 1 != 0 {
+                    self.bootstrap_mode = false;
+                }
+            }
 
             // Invalid registers, that are still used by for example Tetris
             // https://www.reddit.com/r/EmuDev/comments/5nixai/gb_tetris_writing_to_unused_memory/
             0xFF7F => {}
 
-            0xFF00..=0xFF7F => self.io_reg[(addr - 0xFF00) as usize] = value,
-            0xFF80..=0xFFFE => self.internal_ram[(addr - 0xFF80) as usize] = value,
+            _ => self.io_reg[(addr - 0xFF00) as usize] = value,
+        };
+    }
+}
+
+impl Bus for MMU {
+    fn read(&mut self, addr: usize) -> u8 {
+        MMU::read(self, addr)
+    }
 
-            IE_REG => {
-                println!("SET IE TO {}", value);
-                self.ie_reg = value
+    fn fetch(&mut self, addr: usize) -> u8 {
+        MMU::fetch(self, addr)
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        MMU::write(self, addr, value)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.tick_cycles(cycles, true);
+    }
+
+    fn idle(&mut self, cycles: u32) {
+        self.tick_cycles(cycles, false);
+    }
+
+    fn peek(&self, addr: usize) -> u8 {
+        self.direct_read(addr)
+    }
+
+    fn poke(&mut self, addr: usize, value: u8) {
+        self.direct_write(addr, value)
+    }
+}
+
+impl SaveState for MMU {
+    fn serialize(&mut self, s: &mut Serializer) {
+        self.cartridge.serialize(s);
+        self.wram.serialize(s);
+        s.bytes(&mut self.io_reg);
+        s.u8(&mut self.ie_reg);
+        self.hram.serialize(s);
+        s.bool(&mut self.bootstrap_mode);
+        self.timer.serialize(s);
+        self.dma.serialize(s);
+        self.ppu.serialize(s);
+        self.buttons.serialize(s);
+        self.apu.serialize(s);
+        self.serial.serialize(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Registers with bits that are read-only or that change by
+    // themselves. They are tested separately.
+    const READ_ONLY_BITS: [usize; 5] = [P1_REG, DIV_REG, STAT_REG, LY_REG, NR52_REG];
+
+    fn write_and_read(addr: usize, value: u8) -> u8 {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+
+        // Sound registers can only be written when powered on
+        if (0xFF10..=0xFF3F).contains(&addr) {
+            mmu.direct_write(NR52_REG, 0x80);
+        }
+
+        mmu.direct_write(addr, value);
+        mmu.direct_read(addr)
+    }
+
+    #[test]
+    fn test_io_register_readback() {
+        for addr in 0xFF00..=0xFF7F {
+            if READ_ONLY_BITS.contains(&addr) {
+                continue;
             }
 
-            _ => panic!("Write to unhandled address: 0x{:x}", addr),
-        };
+            let mask = DMG_IO_READ_MASK[addr - 0xFF00];
+            for value in [0x00, 0xFF, 0x5A, 0xA5] {
+                assert_eq!(
+                    write_and_read(addr, value),
+                    value | mask,
+                    "read of 0x{:04X} after writing 0x{:02X}",
+                    addr,
+                    value
+                );
+            }
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn pop(&mut self) -> u16 {
-        let sp = self.reg.sp;
-        let v = self.read_u16(sp as usize);
-        self.reg.sp = sp.wrapping_add(2);
-        v
+    // Read-back of I/O registers after writing 0x00 and 0xFF, from the
+    // unused_hwio tests of mooneye-gb and Pan Docs
+    const DMG_READBACK: [(usize, u8, u8); 17] = [
+        (SC_REG, 0x7E, 0xFF),
+        (0xFF03, 0xFF, 0xFF),
+        (TAC_REG, 0xF8, 0xFF),
+        (IF_REG, 0xE0, 0xFF),
+        (NR10_REG, 0x80, 0xFF),
+        (NR11_REG, 0x3F, 0xFF),
+        (NR13_REG, 0xFF, 0xFF),
+        (NR14_REG, 0xBF, 0xFF),
+        (NR30_REG, 0x7F, 0xFF),
+        (NR32_REG, 0x9F, 0xFF),
+        (NR41_REG, 0xFF, 0xFF),
+        (NR44_REG, 0xBF, 0xFF),
+        (0xFF27, 0xFF, 0xFF),
+        (LCDC_REG, 0x00, 0xFF),
+        (0xFF4D, 0xFF, 0xFF),
+        (0xFF4F, 0xFF, 0xFF),
+        (0xFF70, 0xFF, 0xFF),
+    ];
+
+    const CGB_READBACK: [(usize, u8, u8); 8] = [
+        (SC_REG, 0x7C, 0xFF),
+        (IF_REG, 0xE0, 0xFF),
+        (0xFF4D, 0x7E, 0x7F),
+        (0xFF4F, 0xFE, 0xFF),
+        (0xFF68, 0x40, 0xFF),
+        (0xFF6A, 0x40, 0xFF),
+        (0xFF70, 0xF8, 0xFF),
+        (0xFF75, 0x8F, 0xFF),
+    ];
+
+    #[test]
+    fn test_io_register_hardware_values() {
+        for (machine, table) in [
+            (Machine::GameBoyDMG, &DMG_READBACK[..]),
+            (Machine::GameBoyCGB, &CGB_READBACK[..]),
+        ] {
+            for (addr, zeros, ones) in table.iter() {
+                for (value, expected) in [(0x00, *zeros), (0xFF, *ones)] {
+                    let mut mmu = MMU::new(machine);
+                    mmu.direct_write(NR52_REG, 0x80);
+                    mmu.direct_write(*addr, value);
+                    assert_eq!(
+                        mmu.direct_read(*addr),
+                        expected,
+                        "read of 0x{:04X} after writing 0x{:02X}",
+                        addr,
+                        value
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_io_register_readback_read_only_bits() {
+        assert_eq!(write_and_read(P1_REG, 0x00) & 0xF0, 0xC0);
+        assert_eq!(write_and_read(P1_REG, 0xFF) & 0xF0, 0xF0);
+        assert_eq!(write_and_read(DIV_REG, 0xFF), 0x00);
+        assert_eq!(write_and_read(STAT_REG, 0x00) & 0xF8, 0x80);
+        assert_eq!(write_and_read(STAT_REG, 0xFF) & 0xF8, 0xF8);
+        assert_eq!(write_and_read(NR52_REG, 0x00), 0x70);
+        assert_eq!(write_and_read(NR52_REG, 0xFF), 0xF0);
+    }
+
+    #[test]
+    fn test_boot_register_write_once() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.direct_write(BOOT_REG, 0x00);
+        assert!(mmu.bootstrap_mode);
+        mmu.direct_write(BOOT_REG, 0x01);
+        assert!(!mmu.bootstrap_mode);
+        mmu.direct_write(BOOT_REG, 0x00);
+        assert!(!mmu.bootstrap_mode);
+        assert_eq!(mmu.direct_read(BOOT_REG), 0xFF);
+
+        mmu.reset(ResetKind::Soft);
+        assert!(mmu.bootstrap_mode);
+    }
+
+    #[test]
+    fn test_post_boot_io() {
+        for (machine, sc, dma) in [
+            (Machine::GameBoyDMG, 0x7E, 0xFF),
+            (Machine::GameBoyCGB, 0x7F, 0x00),
+        ] {
+            let mut mmu = MMU::new(machine);
+            mmu.init_post_boot_io();
+            assert_eq!(mmu.direct_read(SC_REG), sc);
+            assert_eq!(mmu.direct_read(DMA_REG), dma);
+            assert!(!mmu.dma.is_active());
+            assert_eq!(mmu.direct_read(LCDC_REG), 0x91);
+            assert_eq!(mmu.direct_read(BGP_REG), 0xFC);
+            assert_eq!(mmu.direct_read(IF_REG), 0xE1);
+            assert_eq!(mmu.direct_read(NR14_REG), 0xBF);
+            assert_eq!(mmu.direct_read(NR50_REG), 0x77);
+            assert_eq!(mmu.direct_read(NR52_REG) & 0x80, 0x80);
+        }
+    }
+
+    #[test]
+    fn test_cgb_bootstrap_mapping() {
+        let mut mmu = MMU::new(Machine::GameBoyCGB);
+        let cart = mmu.direct_read(0x0150);
+        mmu.bootstrap.fill(0x42);
+        mmu.bootstrap_size = BOOTSTRAP_SIZE_DMG;
+        assert_eq!(mmu.direct_read(0x00FF), 0x42);
+        assert_eq!(mmu.direct_read(0x0200), cart);
+
+        // The header is read from the cartridge
+        mmu.bootstrap_size = BOOTSTRAP_SIZE_CGB;
+        assert_eq!(mmu.direct_read(0x0150), cart);
+        assert_eq!(mmu.direct_read(0x0200), 0x42);
+        assert_eq!(mmu.direct_read(0x08FF), 0x42);
+        assert_eq!(mmu.direct_read(0x0900), cart);
+
+        mmu.direct_write(BOOT_REG, 0x01);
+        assert_eq!(mmu.direct_read(0x0000), cart);
+        assert_eq!(mmu.direct_read(0x0200), cart);
+    }
+
+    #[test]
+    fn test_soft_reset_preserves_memory() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.direct_write(0xC000, 0x42);
+        mmu.direct_write(0xFF80, 0x43);
+        mmu.direct_write(0x8000, 0x44);
+        mmu.direct_write(NR52_REG, 0x80);
+        mmu.direct_write(0xFF30, 0x45);
+        mmu.direct_write(SCX_REG, 0x46);
+
+        mmu.reset(ResetKind::Soft);
+        assert_eq!(mmu.direct_read(0xC000), 0x42);
+        assert_eq!(mmu.direct_read(0xFF80), 0x43);
+        assert_eq!(mmu.direct_read(0x8000), 0x44);
+        assert_eq!(mmu.direct_read(0xFF30), 0x45);
+        assert_eq!(mmu.direct_read(SCX_REG), 0);
+
+        // Work RAM and high RAM get semi-random content, which is
+        // tested in test_deterministic_power_cycle
+        mmu.reset(ResetKind::Hard);
+        assert_eq!(mmu.direct_read(0x8000), 0);
+    }
+
+    #[test]
+    fn test_echo_ram() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.write(0xC123, 0x42);
+        assert_eq!(mmu.read(0xE123), 0x42);
+        mmu.write(0xFDFF, 0x43);
+        assert_eq!(mmu.read(0xDDFF), 0x43);
+    }
+
+    #[test]
+    fn test_unusable_area() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.write(0xFEA5, 0x42);
+        assert_eq!(mmu.read(0xFEA5), 0x00);
+
+        let mut mmu = MMU::new(Machine::GameBoyCGB);
+        assert_eq!(mmu.read(0xFEA5), 0xAA);
+        assert_eq!(mmu.read(0xFEFF), 0xFF);
+
+        // Blocked while the PPU reads OAM. There's no OAM search on
+        // the first line after the LCD is turned on.
+        mmu.write(LCDC_REG, 0x80);
+        mmu.tick(super::super::ppu::DOTS_PER_LINE as u32 + 4);
+        assert!(!mmu.ppu.is_oam_accessible());
+        assert_eq!(mmu.read(0xFEA5), 0xFF);
+    }
+
+    #[test]
+    fn test_unmapped_io_registers() {
+        for machine in [Machine::GameBoyDMG, Machine::GameBoyCGB] {
+            let mut mmu = MMU::new(machine);
+            for addr in [0xFF03, 0xFF08, 0xFF27, 0xFF4C, 0xFF7F] {
+                mmu.write(addr, 0x00);
+                assert_eq!(mmu.read(addr), 0xFF, "{:04X}", addr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pcm_registers_read_only() {
+        let mut mmu = MMU::new(Machine::GameBoyCGB);
+        mmu.write(PCM12_REG, 0x5A);
+        mmu.write(PCM34_REG, 0x5A);
+        assert_eq!((mmu.read(PCM12_REG), mmu.read(PCM34_REG)), (0x00, 0x00));
+
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        assert_eq!(mmu.read(PCM12_REG), 0xFF);
+    }
+
+    #[test]
+    fn test_code_data_log() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.bootstrap_mode = false;
+        mmu.cdl.start(0x8000);
+        mmu.fetch(0x0150);
+        mmu.read(0x4000);
+        mmu.read(0xC000);
+        assert_eq!(mmu.cdl.flags()[0x0150], CDL_CODE);
+        assert_eq!(mmu.cdl.flags()[0x4000], CDL_DATA);
+        assert_eq!(mmu.cdl.flags().iter().filter(|f| **f != 0).count(), 2);
+
+        // Not while the boot ROM is mapped
+        mmu.bootstrap_mode = true;
+        mmu.fetch(0x0000);
+        assert_eq!(mmu.cdl.flags()[0x0000], 0);
+    }
+
+    #[test]
+    fn test_bank_registers() {
+        let mut mmu = MMU::new(Machine::GameBoyCGB);
+        mmu.write(0x8000, 0x10);
+        mmu.write(0xD000, 0x20);
+        mmu.write(VBK_REG, 0x03);
+        mmu.write(SVBK_REG, 0x0A);
+        assert_eq!((mmu.read(VBK_REG), mmu.read(SVBK_REG)), (0xFF, 0xFA));
+        assert_eq!((mmu.read(0x8000), mmu.read(0xD000)), (0x00, 0x00));
+        mmu.write(0x8000, 0x11);
+        mmu.write(0xD000, 0x22);
+
+        mmu.write(VBK_REG, 0);
+        mmu.write(SVBK_REG, 0);
+        assert_eq!((mmu.read(VBK_REG), mmu.read(SVBK_REG)), (0xFE, 0xF8));
+        assert_eq!((mmu.read(0x8000), mmu.read(0xD000)), (0x10, 0x20));
+        assert_eq!(mmu.ppu.vram1[0], 0x11);
+
+        // Not banked on the DMG
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.write(0xD000, 0x20);
+        mmu.write(SVBK_REG, 2);
+        assert_eq!((mmu.read(SVBK_REG), mmu.read(0xD000)), (0xFF, 0x20));
+    }
+
+    // Start OAM DMA through the normal write path, and wait for it
+    // to complete
+    fn run_dma(mmu: &mut MMU, source: u8) {
+        mmu.write(DMA_REG, source);
+        mmu.tick(4 * 162);
+    }
+
+    #[test]
+    fn test_dma_from_echo_ram() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        for i in 0..0xA0 {
+            mmu.direct_write(0xC100 + i, i as u8);
+            mmu.direct_write(0xDE00 + i, !i as u8);
+        }
+
+        run_dma(&mut mmu, 0xE1);
+        assert_eq!(mmu.direct_read(OAM_OFFSET), 0x00);
+        assert_eq!(mmu.direct_read(OAM_OFFSET + 0x9F), 0x9F);
+
+        // Above the echo region, DMA still reads work RAM
+        run_dma(&mut mmu, 0xFE);
+        assert_eq!(mmu.direct_read(OAM_OFFSET), 0xFF);
+        assert_eq!(mmu.direct_read(OAM_OFFSET + 0x9F), 0x60);
+    }
+
+    #[test]
+    fn test_push_and_pop_with_sp_in_io_space() {
+        use crate::gameboy::cpu::Cpu;
+
+        // PUSH BC, POP DE
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.bootstrap_mode = false;
+        mmu.direct_write(0xC000, 0xC5);
+        mmu.direct_write(0xC001, 0xD1);
+        for i in 0..0xA0 {
+            mmu.direct_write(0xC100 + i, i as u8);
+        }
+
+        // Pushing to 0xFF47-0xFF46 writes BGP and starts OAM DMA
+        let mut cpu = Cpu::new();
+        cpu.reg.pc = 0xC000;
+        cpu.reg.sp = 0xFF48;
+        cpu.reg.set_bc(0xE4C1);
+        cpu.step(&mut mmu);
+        assert_eq!(mmu.direct_read(BGP_REG), 0xE4);
+        assert_eq!(mmu.direct_read(DMA_REG), 0xC1);
+        mmu.tick(4 * 162);
+        assert_eq!(mmu.direct_read(OAM_OFFSET + 0x42), 0x42);
+
+        // Popping from P1 counts as a joypad read
+        cpu.reg.sp = P1_REG as u16;
+        cpu.step(&mut mmu);
+        assert_eq!(mmu.p1_reads, 1);
+        assert_eq!(cpu.reg.sp, 0xFF02);
+    }
+
+    #[test]
+    fn test_stop_wakes_on_joypad_input() {
+        use crate::gameboy::buttons::ButtonType;
+        use crate::gameboy::cpu::Cpu;
+
+        // STOP, NOP
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.bootstrap_mode = false;
+        mmu.direct_write(0xC000, 0x10);
+        mmu.direct_write(0xC001, 0x00);
+        mmu.direct_write(P1_REG, 0x10);
+
+        let mut cpu = Cpu::new();
+        cpu.reg.pc = 0xC000;
+        for _ in 0..10 {
+            cpu.step(&mut mmu);
+        }
+        assert!(cpu.reg.stopped);
+        assert_eq!(cpu.reg.pc, 0xC001);
+
+        // Interrupts are disabled, but the joypad interrupt is requested
+        mmu.buttons.handle_press(ButtonType::A);
+        assert_eq!(mmu.get_if_reg() & IF_INP_BIT, IF_INP_BIT);
+        cpu.step(&mut mmu);
+        cpu.step(&mut mmu);
+        assert!(!cpu.reg.stopped);
+        assert_eq!(cpu.reg.pc, 0xC002);
+    }
+
+    #[test]
+    fn test_deterministic_power_cycle() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.set_deterministic(true);
+        let wave = mmu.apu.ch3.wave;
+        for _ in 0..3 {
+            mmu.reset(ResetKind::Hard);
+            assert_eq!(mmu.apu.ch3.wave, wave);
+            assert!(mmu.wram.bytes().iter().all(|b| *b == 0));
+        }
+
+        mmu.set_deterministic(false);
+        mmu.reset(ResetKind::Hard);
+        assert!(mmu.wram.bytes().iter().any(|b| *b != 0));
     }
 }
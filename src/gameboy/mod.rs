@@ -1,15 +1,29 @@
 pub mod apu;
+pub mod bus;
 pub mod buttons;
+pub mod call_stack;
 pub mod cartridge;
+pub mod cdl;
+pub mod cpu;
 mod dma;
 pub mod emu;
+pub mod frames;
 pub mod instructions;
 mod interrupt;
+pub mod io_registers;
 pub mod mmu;
+pub mod movie;
 pub mod ppu;
+pub mod ppu_events;
+pub mod printer;
+pub mod quirks;
+pub mod ram_search;
 pub mod registers;
-mod serial;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod serial;
 mod timer;
+pub mod timing;
 
 pub const CLOCK_SPEED: usize = 4194304;
 pub const CYCLES_PER_FRAME: usize = 70224;
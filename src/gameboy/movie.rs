@@ -0,0 +1,301 @@
+// Input movies
+//
+// A movie is the state of the buttons for every frame, starting from
+// a power cycle. Replaying a movie on the same ROM gives exactly the
+// same result as when it was recorded, which can be used for test
+// automation and tool-assisted speedruns.
+//
+// Movies are stored as text. The first line identifies the format,
+// followed by a header, followed by one line per frame:
+//
+//   rustboy-movie 1
+//   checksum 3A
+//   global-checksum 16BF
+//   seed 0
+//   frames
+//   ........
+//   .......A
+//   ...R...A
+//
+// Each frame line has one character per button, in the order
+// Up, Down, Left, Right, Start, Select, B, A. A '.' means the
+// button is released.
+//
+// The checksums are taken from the cartridge header and used to
+// check that the movie is played on the ROM it was recorded on.
+// The seed is used for the parts of the power-on state that are
+// random, such as the DMG wave RAM.
+
+use super::buttons::{ButtonType, Buttons};
+use super::cartridge::cartridge_header::CartridgeHeader;
+
+const MAGIC: &str = "rustboy-movie 1";
+
+const BUTTON_CHARS: [(ButtonType, char); 8] = [
+    (ButtonType::Up, 'U'),
+    (ButtonType::Down, 'D'),
+    (ButtonType::Left, 'L'),
+    (ButtonType::Right, 'R'),
+    (ButtonType::Start, 'S'),
+    (ButtonType::Select, 's'),
+    (ButtonType::B, 'B'),
+    (ButtonType::A, 'A'),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Movie {
+    pub checksum: u8,
+    pub global_checksum: u16,
+    pub seed: u64,
+
+    // Pressed buttons of each frame, as a mask of `ButtonType` values
+    pub frames: Vec<u8>,
+}
+
+impl Movie {
+    pub fn new(header: &CartridgeHeader, seed: u64) -> Self {
+        Movie {
+            checksum: header.checksum,
+            global_checksum: header.global_checksum,
+            seed,
+            frames: Vec::new(),
+        }
+    }
+
+    // Check that the movie was recorded on the cartridge with `header`
+    pub fn matches(&self, header: &CartridgeHeader) -> bool {
+        self.checksum == header.checksum && self.global_checksum == header.global_checksum
+    }
+
+    pub fn load(filename: &str) -> Result<Movie, String> {
+        match std::fs::read_to_string(filename) {
+            Ok(content) => Movie::parse(&content),
+            Err(e) => Err(format!("failed to read {}: {}", filename, e)),
+        }
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), std::io::Error> {
+        std::fs::write(filename, self.to_text())
+    }
+
+    pub fn parse(content: &str) -> Result<Movie, String> {
+        let mut lines = content.lines().map(|l| l.trim_end());
+
+        if lines.next() != Some(MAGIC) {
+            return Err("not a movie file".to_string());
+        }
+
+        let mut checksum = None;
+        let mut global_checksum = None;
+        let mut seed = 0;
+
+        for line in &mut lines {
+            if line == "frames" {
+                break;
+            }
+
+            let (key, value) = match line.split_once(' ') {
+                Some(kv) => kv,
+                None => return Err(format!("invalid header line: {}", line)),
+            };
+
+            let invalid = || format!("invalid value of {}: {}", key, value);
+            match key {
+                "checksum" => {
+                    checksum = Some(u8::from_str_radix(value, 16).map_err(|_| invalid())?)
+                }
+                "global-checksum" => {
+                    global_checksum = Some(u16::from_str_radix(value, 16).map_err(|_| invalid())?)
+                }
+                "seed" => seed = value.parse().map_err(|_| invalid())?,
+                // Ignore unknown keys, so that newer versions can add to the header
+                _ => {}
+            }
+        }
+
+        let mut frames = Vec::new();
+        for (i, line) in lines.enumerate() {
+            frames.push(parse_frame(line).ok_or(format!("invalid frame {}: {}", i, line))?);
+        }
+
+        match (checksum, global_checksum) {
+            (Some(checksum), Some(global_checksum)) => Ok(Movie {
+                checksum,
+                global_checksum,
+                seed,
+                frames,
+            }),
+            _ => Err("missing checksum in movie header".to_string()),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\nchecksum {:02X}\nglobal-checksum {:04X}\nseed {}\nframes\n",
+            MAGIC, self.checksum, self.global_checksum, self.seed
+        );
+        for pressed in self.frames.iter() {
+            text.push_str(&format_buttons(*pressed));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// A movie that is being recorded or played
+pub struct ActiveMovie {
+    pub movie: Movie,
+    pub mode: MovieMode,
+
+    // File the movie is saved to when recording stops
+    pub filename: String,
+
+    // Frame number of the PPU when the buttons were last recorded
+    // or applied. None until the first instruction.
+    last_frame: Option<usize>,
+
+    // Number of frames applied during playback
+    played: usize,
+}
+
+impl ActiveMovie {
+    pub fn new(movie: Movie, mode: MovieMode, filename: &str) -> Self {
+        ActiveMovie {
+            movie,
+            mode,
+            filename: filename.to_string(),
+            last_frame: None,
+            played: 0,
+        }
+    }
+
+    // Number of frames recorded or played so far
+    pub fn position(&self) -> usize {
+        match self.mode {
+            MovieMode::Recording => self.movie.frames.len(),
+            MovieMode::Playing => self.played,
+        }
+    }
+
+    // Called before each instruction. At the first instruction of each
+    // frame, the buttons are recorded or set from the movie. This is
+    // done at the first instruction, rather than when the frame ends,
+    // as the frontend updates the buttons between frames.
+    //
+    // Returns false when playback has reached the end of the movie.
+    pub fn update(&mut self, frame_number: usize, buttons: &mut Buttons) -> bool {
+        if self.last_frame == Some(frame_number) {
+            return true;
+        }
+        self.last_frame = Some(frame_number);
+
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.push(buttons.pressed());
+                true
+            }
+            MovieMode::Playing => match self.movie.frames.get(self.played) {
+                Some(pressed) => {
+                    buttons.set_pressed(*pressed);
+                    self.played += 1;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+fn parse_frame(line: &str) -> Option<u8> {
+    if line.chars().count() != BUTTON_CHARS.len() {
+        return None;
+    }
+
+    let mut pressed = 0;
+    for (c, (button, name)) in line.chars().zip(BUTTON_CHARS.iter()) {
+        if c == *name {
+            pressed |= *button as u8;
+        } else if c != '.' {
+            return None;
+        }
+    }
+    Some(pressed)
+}
+
+// Pressed buttons as text, in the same format as the frame lines
+pub fn format_buttons(pressed: u8) -> String {
+    BUTTON_CHARS
+        .iter()
+        .map(|(button, c)| {
+            if pressed & *button as u8 != 0 {
+                *c
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        let movie = Movie {
+            checksum: 0x3A,
+            global_checksum: 0x16BF,
+            seed: 42,
+            frames: vec![0, ButtonType::A as u8, 0xFF, ButtonType::Select as u8],
+        };
+
+        let text = movie.to_text();
+        assert!(text.ends_with("frames\n........\n.......A\nUDLRSsBA\n.....s..\n"));
+        assert_eq!(Movie::parse(&text), Ok(movie));
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let movie = Movie {
+            checksum: 0,
+            global_checksum: 0,
+            seed: 0,
+            frames: vec![],
+        };
+        let mut buttons = Buttons::new();
+        let mut active = ActiveMovie::new(movie, MovieMode::Recording, "");
+
+        // Buttons are recorded once per frame
+        active.update(0, &mut buttons);
+        buttons.handle_press(ButtonType::A);
+        active.update(0, &mut buttons);
+        active.update(1, &mut buttons);
+        buttons.handle_press(ButtonType::Up);
+        active.update(2, &mut buttons);
+        assert_eq!(active.movie.frames, vec![0x00, 0x01, 0x41]);
+
+        let mut active = ActiveMovie::new(active.movie, MovieMode::Playing, "");
+        buttons.set_pressed(0);
+        for (frame, pressed) in [0x00, 0x01, 0x41].iter().enumerate() {
+            assert!(active.update(frame, &mut buttons));
+            assert_eq!(buttons.pressed(), *pressed);
+        }
+        assert!(!active.update(3, &mut buttons));
+    }
+
+    #[test]
+    fn test_invalid_movies() {
+        assert!(Movie::parse("hello\n").is_err());
+        assert!(Movie::parse("rustboy-movie 1\nseed 0\nframes\n").is_err());
+        assert!(Movie::parse(
+            "rustboy-movie 1\nchecksum 00\nglobal-checksum 0000\nframes\nX.......\n"
+        )
+        .is_err());
+    }
+}
@@ -25,17 +25,21 @@
 // it is equivalent to 2 T-cycles.
 
 use super::emu::Machine;
+use crate::palette::DmgPalette;
+use crate::savestate::{SaveState, Serializer};
 
 use super::{
     interrupt::{IF_LCDC_BIT, IF_VBLANK_BIT},
     mmu::{
-        MemoryMapped, BGP_REG, LCDC_REG, LYC_REG, LY_REG, OAM_OFFSET, OBP0_REG, OBP1_REG, SCX_REG,
-        SCY_REG, STAT_REG, WX_REG, WY_REG,
+        MemoryMapped, ResetKind, BGP_REG, LCDC_REG, LYC_REG, LY_REG, OAM_OFFSET, OBP0_REG,
+        OBP1_REG, SCX_REG, SCY_REG, STAT_REG, VBK_REG, WX_REG, WY_REG,
     },
 };
 
 pub const SCREEN_WIDTH: usize = 160;
 pub const SCREEN_HEIGHT: usize = 144;
+pub const DOTS_PER_LINE: usize = 456;
+pub const LINES_PER_FRAME: usize = 154;
 pub const OAM_SIZE: usize = 0xA0;
 pub const OAM_OBJECT_SIZE: usize = 4;
 pub const OAM_OBJECT_COUNT: usize = OAM_SIZE / OAM_OBJECT_SIZE;
@@ -45,6 +49,9 @@ pub const VRAM_OFFSET: usize = 0x8000;
 pub const VRAM_END: usize = VRAM_OFFSET + VRAM_SIZE - 1;
 pub const MAX_SPRITES_PER_SCANLINE: usize = 10;
 
+// Number of dots that LY reads 153 on the last line
+const LINE_153_LY_DOTS: usize = 4;
+
 pub const WINDOW_TILE_MAP_OFFSET_0: usize = 0x9800;
 pub const WINDOW_TILE_MAP_OFFSET_1: usize = 0x9C00;
 pub const BG_TILE_MAP_OFFSET_0: usize = 0x9800;
@@ -52,6 +59,12 @@ pub const BG_TILE_MAP_OFFSET_1: usize = 0x9C00;
 pub const BG_AND_WINDOW_TILE_DATA_OFFSET_0: usize = 0x8800;
 pub const BG_AND_WINDOW_TILE_DATA_OFFSET_1: usize = 0x8000;
 
+// Color source bits of pixels in the display buffer
+pub const SOURCE_BG: u8 = 0b0000_0000;
+pub const SOURCE_WINDOW: u8 = 0b0100_0000;
+pub const SOURCE_OBJ0: u8 = 0b1000_0000;
+pub const SOURCE_OBJ1: u8 = 0b1100_0000;
+
 pub const TILE_ROWS: usize = 32;
 pub const TILE_COLUMNS: usize = 32;
 pub const TILE_WIDTH: usize = 8;
@@ -183,6 +196,27 @@ pub enum TileAddressingMode {
     Secondary,
 }
 
+// Layers drawn to the buffer. Hiding a layer is a debugging aid, to
+// isolate graphics issues. Only the pixels drawn are affected, not
+// the emulated state, such as the window line counter or the objects
+// selected for a scanline.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Layers {
+    pub background: bool,
+    pub window: bool,
+    pub objects: bool,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            background: true,
+            window: true,
+            objects: true,
+        }
+    }
+}
+
 pub struct PPU {
     // LCD + PPU enabled. Bit 7 in LCDC.
     enabled: bool,
@@ -193,7 +227,7 @@ pub struct PPU {
     pub window_tile_map_offset: usize,
 
     // Window area enabled. Controlled through LCDC, bit 5.
-    window_enabled: bool,
+    pub window_enabled: bool,
 
     // Offset to BG and window tile data. Controlled through LCDC, bit 4:
     // 0: 8800..97FF
@@ -217,6 +251,10 @@ pub struct PPU {
     // Accessed through register STAT, bit 6.
     lyc_interrupt_enabled: bool,
 
+    // Request the STAT interrupt when a write to LYC or STAT makes the
+    // STAT line go high. Can be disabled by a per-game quirk.
+    pub lyc_write_irq: bool,
+
     // Mode 2 (OAM search) interrupt enabled. Register: STAT, bit 5
     oam_search_interrupt_enabled: bool,
 
@@ -226,13 +264,29 @@ pub struct PPU {
     // Mode 0 (HBlank) interrupt enabled. RegisteR: STAT, bit 3
     hblank_interrupt_enabled: bool,
 
-    // Various meaning. Controlled through LCDC, bit 0.
-    // TODO: document me!
+    // Level of the STAT interrupt line: all enabled STAT sources ORed
+    // together. The interrupt is requested when it goes from low to
+    // high, so a source that becomes active while another one already
+    // holds the line high doesn't request another ("STAT blocking").
+    stat_line: bool,
+
+    // LCDC bit 0. On the DMG, the background and window are only
+    // drawn when set. In CGB mode they are always drawn, and lose
+    // priority over objects when cleared.
     bg_and_window_enable_prio: bool,
 
+    // CGB features are enabled: true on a CGB, unless running a DMG
+    // game in compatibility mode
+    pub cgb_mode: bool,
+
     // Video RAM (0x8000..0x9FFF)
     pub vram: [u8; VRAM_SIZE],
 
+    // Second bank of video RAM, on the CGB, and the bank the CPU sees
+    // at 0x8000, selected by VBK. Only bank 0 is drawn for now.
+    pub vram1: [u8; VRAM_SIZE],
+    vram_bank: u8,
+
     // Buffer for final pixel data.
     // Each byte in the buffer holds the final color plus
     // some extra meta-data:
@@ -240,7 +294,8 @@ pub struct PPU {
     // Bit 7..6: Color source
     //           00: Background
     //           01: Window
-    //           10: Sprite
+    //           10: Sprite, using OBP0
+    //           11: Sprite, using OBP1
     // Bit 0..1: Color (DMG). 0 = darkest, 3 = lightest
     pub buffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
 
@@ -258,6 +313,11 @@ pub struct PPU {
 
     scanline_timer: usize,
 
+    // Set on line 0 after the LCD is turned on, until pixel transfer
+    // starts. There is no OAM search on that line: STAT reads mode 0
+    // for the first 80 dots, and no mode 2 interrupt is requested.
+    first_line: bool,
+
     // Selected OAM objects (sprites) for current scanline. Max 10.
     scanline_objects: [usize; 10],
 
@@ -266,28 +326,28 @@ pub struct PPU {
 
     // Assigns gray shades for bg and window color indexes. DMG only.
     // Accessed through register BGP (0xFF47).
-    bg_palette: [u8; 4],
+    pub bg_palette: [u8; 4],
 
     // First object palette. Accessed through register OBP0.
-    obj0_palette: [u8; 4],
+    pub obj0_palette: [u8; 4],
 
     // Second object palette. Accessed through register OBP1.
-    obj1_palette: [u8; 4],
+    pub obj1_palette: [u8; 4],
 
     // Scroll Y. Accessed through register SCY (0xFF42)
-    scy: usize,
+    pub scy: usize,
 
     // Scroll X. Accessed through register SCX (0xFF43)
-    scx: usize,
+    pub scx: usize,
 
     // LY compare register.
     ly_compare: usize,
 
     // Horizontal offset of the top-left corner of the window area
-    wx: usize,
+    pub wx: usize,
 
     // Vertical offset of the top-left corner of the window area
-    wy: usize,
+    pub wy: usize,
 
     // Window line counter, similar to `ly`
     window_ly: usize,
@@ -297,6 +357,9 @@ pub struct PPU {
 
     // Frame number
     pub frame_number: usize,
+
+    // Layers to draw. Not part of the emulated hardware.
+    pub layers: Layers,
 }
 
 // Get offset to the tile data based on the selected addressing mode.
@@ -316,6 +379,8 @@ impl PPU {
             frame_number: 0,
             irq: 0,
             vram: [0; VRAM_SIZE],
+            vram1: [0; VRAM_SIZE],
+            vram_bank: 0,
             buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
             oam: [Sprite::default(); OAM_SIZE / OAM_OBJECT_SIZE],
             mode: Mode::OAMSearch,
@@ -339,13 +404,18 @@ impl PPU {
             object_height: 8,
             objects_enabled: false,
             bg_and_window_enable_prio: false,
+            cgb_mode: matches!(machine, Machine::GameBoyCGB),
             lyc_interrupt_enabled: false,
+            lyc_write_irq: true,
             oam_search_interrupt_enabled: false,
             hblank_interrupt_enabled: false,
             vblank_interrupt_enabled: false,
+            stat_line: false,
             ly_compare: 0,
+            first_line: false,
             scanline_objects: [0; MAX_SPRITES_PER_SCANLINE],
             scanline_object_count: 0,
+            layers: Layers::default(),
         }
     }
 
@@ -376,6 +446,48 @@ impl PPU {
         self.scanline_object_count = count;
     }
 
+    // Height of objects, 8 or 16, as selected by LCDC bit 2
+    pub fn object_height(&self) -> usize {
+        self.object_height
+    }
+
+    // All objects on `line`, in OAM order. Only the first
+    // MAX_SPRITES_PER_SCANLINE are drawn.
+    pub fn objects_on_line(&self, line: usize) -> Vec<usize> {
+        let line = line as i32;
+        let height = self.object_height as i32;
+        (0..OAM_OBJECT_COUNT)
+            .filter(|n| line >= self.oam[*n].y && line < self.oam[*n].y + height)
+            .collect()
+    }
+
+    // Color indexes of object `n`, row by row, with flips applied
+    pub fn object_pixels(&self, n: usize) -> Vec<u8> {
+        let obj = &self.oam[n];
+        let tile_index = match self.object_height {
+            16 => obj.tile_index & !1,
+            _ => obj.tile_index,
+        };
+
+        let mut pixels = Vec::with_capacity(8 * self.object_height);
+        for y in 0..self.object_height {
+            let ty = match obj.flip_y {
+                true => self.object_height - 1 - y,
+                false => y,
+            };
+            let lo = self.vram[tile_index * TILE_SIZE + ty * 2];
+            let hi = self.vram[tile_index * TILE_SIZE + ty * 2 + 1];
+            for x in 0..8 {
+                let tx = match obj.flip_x {
+                    true => 7 - x,
+                    false => x,
+                };
+                pixels.push(((lo >> (7 - tx)) & 1) | (((hi >> (7 - tx)) & 1) << 1));
+            }
+        }
+        pixels
+    }
+
     // Returns true if the window area is enabled and the given
     // coordinate is within the window area.
     fn is_within_window(&self, x: usize, y: usize) -> bool {
@@ -393,9 +505,15 @@ impl PPU {
 
         for lx in 0..SCREEN_WIDTH {
             let mut bg_pxl = 0;
+            let mut bg_source = SOURCE_BG;
             let mut spr_pxl = None;
             let mut bg_over_obj = false;
 
+            // Color index of the background or window pixel, before the
+            // palette, and the priority bit of its attributes on the CGB
+            let mut bg_color = 0;
+            let mut bg_priority = false;
+
             // Draw sprites
             if self.objects_enabled {
                 for s in 0..self.scanline_object_count {
@@ -427,9 +545,9 @@ impl PPU {
 
                         if pxl != 0 {
                             spr_pxl = if spr.dmg_use_second_palette {
-                                Some(self.obj1_palette[pxl as usize])
+                                Some(self.obj1_palette[pxl as usize] | SOURCE_OBJ1)
                             } else {
-                                Some(self.obj0_palette[pxl as usize])
+                                Some(self.obj0_palette[pxl as usize] | SOURCE_OBJ0)
                             };
                             bg_over_obj = spr.bg_and_window_over_obj;
                             break;
@@ -439,13 +557,16 @@ impl PPU {
             }
 
             // Draw background
-            if self.bg_and_window_enable_prio {
+            if self.bg_and_window_enable_prio || self.cgb_mode {
+                let attributes;
                 let pxl = if self.is_within_window(lx, self.ly) {
+                    bg_source = SOURCE_WINDOW;
                     let tile_map_offset =
                         self.window_tile_map_offset - 0x8000 + ((self.window_ly) / 8) * 32;
                     let tile_index = (lx + 7 - self.wx) / 8;
                     let tile_line = self.window_ly % 8;
                     let tile_id = self.vram[tile_map_offset + tile_index];
+                    attributes = self.vram1[tile_map_offset + tile_index];
 
                     let offset = get_tile_data_offset(tile_id, self.tile_addressing_mode) - 0x8000;
                     let offset = offset + tile_line * 2;
@@ -460,6 +581,7 @@ impl PPU {
                     let tile_index = ((lx + self.scx) % 256) / 8; // "tile column"
                     let tile_line = (self.scy + self.ly) % 8;
                     let tile_id = self.vram[tile_map_offset + tile_index];
+                    attributes = self.vram1[tile_map_offset + tile_index];
 
                     let offset = get_tile_data_offset(tile_id, self.tile_addressing_mode) - 0x8000;
                     let offset = offset + tile_line * 2;
@@ -471,15 +593,39 @@ impl PPU {
                     ((lo >> (7 - tx)) & 1) | (((hi >> (7 - tx)) & 1) << 1)
                 };
 
-                bg_pxl = self.bg_palette[pxl as usize];
+                let visible = match bg_source {
+                    SOURCE_WINDOW => self.layers.window,
+                    _ => self.layers.background,
+                };
+                if visible {
+                    bg_pxl = self.bg_palette[pxl as usize];
+                    bg_color = pxl;
+                    bg_priority = self.cgb_mode && attributes & 0x80 != 0;
+                }
             }
 
-            self.buffer[scanline_offset + lx] = if bg_over_obj && bg_pxl != 0 {
-                bg_pxl
+            if !self.layers.objects {
+                spr_pxl = None;
+            }
+
+            // Objects are drawn over background color 0. On the DMG,
+            // the priority bit of the object puts it behind other
+            // colors, after the palette is applied. In CGB mode, the
+            // priority bit of the object or of the tile attributes
+            // does, before the palette, unless LCDC bit 0 is cleared.
+            let bg_wins = match self.cgb_mode {
+                true => {
+                    bg_color != 0 && self.bg_and_window_enable_prio && (bg_over_obj || bg_priority)
+                }
+                false => bg_over_obj && bg_pxl != 0,
+            };
+
+            self.buffer[scanline_offset + lx] = if bg_wins {
+                bg_pxl | bg_source
             } else {
                 match spr_pxl {
                     Some(v) => v,
-                    _ => bg_pxl,
+                    _ => bg_pxl | bg_source,
                 }
             }
         }
@@ -495,15 +641,17 @@ impl PPU {
             Mode::PixelTransfer => {
                 if self.scanline_timer == 80 + 160 {
                     self.render_scanline();
-                    if self.hblank_interrupt_enabled {
-                        self.irq |= IF_LCDC_BIT;
-                    }
                     self.mode = Mode::HorizontalBlank;
                 }
             }
 
             Mode::HorizontalBlank => {
-                if self.scanline_timer == 456 {
+                if self.first_line && self.scanline_timer == 80 {
+                    self.first_line = false;
+                    self.mode = Mode::PixelTransfer;
+                }
+
+                if self.scanline_timer == DOTS_PER_LINE {
                     self.scanline_timer = 0;
 
                     if self.wx <= 166 && self.wy <= 143 && self.ly >= self.wy {
@@ -511,35 +659,26 @@ impl PPU {
                     }
 
                     self.ly += 1;
-                    if self.lyc_interrupt_enabled && self.ly_compare == self.ly {
-                        self.irq |= IF_LCDC_BIT;
-                    }
                     if self.ly == SCREEN_HEIGHT {
                         self.irq |= IF_VBLANK_BIT;
-                        if self.vblank_interrupt_enabled {
-                            self.irq |= IF_LCDC_BIT;
-                        }
                         self.mode = Mode::VerticalBlank;
                     } else {
                         self.mode = Mode::OAMSearch;
-                        if self.oam_search_interrupt_enabled {
-                            self.irq |= IF_LCDC_BIT;
-                        }
                     }
                 }
             }
 
             Mode::VerticalBlank => {
-                if self.scanline_timer == 456 {
+                // LY switches to 0 early on line 153, which is compared
+                // with LYC as well, through `ly_register`
+                if self.scanline_timer == DOTS_PER_LINE {
                     self.ly += 1;
                     self.scanline_timer = 0;
                     if self.ly == 154 {
                         self.mode = Mode::OAMSearch;
                         self.window_ly = 0;
                         self.ly = 0;
-                        if self.oam_search_interrupt_enabled {
-                            self.irq |= IF_LCDC_BIT;
-                        }
+                        self.update_stat_line(true);
                         self.frame_number = self.frame_number.wrapping_add(1);
                         return true;
                     }
@@ -547,10 +686,76 @@ impl PPU {
             }
         }
 
+        self.update_stat_line(true);
         self.scanline_timer += 1;
         false
     }
 
+    // LY as read by the CPU and compared with LYC. On line 153, LY
+    // only reads 153 for the first few dots, and 0 for the rest of
+    // the line. Line 0 then follows with LY still 0.
+    pub fn ly_register(&self) -> usize {
+        match self.ly {
+            153 if self.scanline_timer >= LINE_153_LY_DOTS => 0,
+            ly => ly,
+        }
+    }
+
+    // True when the LY=LYC condition is currently requesting a STAT interrupt
+    fn lyc_interrupt_line(&self) -> bool {
+        self.lyc_interrupt_enabled && self.ly_register() == self.ly_compare
+    }
+
+    // True when any of the enabled STAT sources is active. The mode
+    // sources are inactive while the LCD is off.
+    fn stat_sources(&self) -> bool {
+        let mode = self.enabled
+            && match self.mode {
+                Mode::HorizontalBlank => self.hblank_interrupt_enabled,
+                Mode::VerticalBlank => self.vblank_interrupt_enabled,
+                Mode::OAMSearch => self.oam_search_interrupt_enabled,
+                Mode::PixelTransfer => false,
+            };
+        mode || self.lyc_interrupt_line()
+    }
+
+    // Update the level of the STAT line, and request the interrupt on
+    // a rising edge if `request` is true
+    fn update_stat_line(&mut self, request: bool) {
+        let line = self.stat_sources();
+        if request && line && !self.stat_line {
+            self.irq |= IF_LCDC_BIT;
+        }
+        self.stat_line = line;
+    }
+
+    // Current scanline, and position within it. The end of a line is
+    // handled at the start of the next update, so the last update may
+    // have stopped one dot past it.
+    pub fn position(&self) -> (usize, usize) {
+        if self.scanline_timer >= DOTS_PER_LINE {
+            (
+                (self.ly + 1) % LINES_PER_FRAME,
+                self.scanline_timer - DOTS_PER_LINE,
+            )
+        } else {
+            (self.ly, self.scanline_timer)
+        }
+    }
+
+    // Number of pixels of the buffer drawn in the current frame. A
+    // line is drawn all at once, at the end of pixel transfer, so this
+    // is always a whole number of lines.
+    pub fn beam_position(&self) -> usize {
+        let (line, dot) = self.position();
+        match line {
+            _ if !self.enabled => SCREEN_WIDTH * SCREEN_HEIGHT,
+            0..=143 if dot > 80 + 160 => (line + 1) * SCREEN_WIDTH,
+            0..=143 => line * SCREEN_WIDTH,
+            _ => SCREEN_WIDTH * SCREEN_HEIGHT,
+        }
+    }
+
     pub fn update(&mut self, cycles: u32) -> bool {
         assert!(cycles % 2 == 0);
         let mut display_update = false;
@@ -564,30 +769,26 @@ impl PPU {
     // or when the display is disabled.
     // Ref:
     // https://gbdev.io/pandocs/Accessing_VRAM_and_OAM.html
-    fn is_oam_accessible(&self) -> bool {
+    pub fn is_oam_accessible(&self) -> bool {
         match self.mode {
             Mode::HorizontalBlank | Mode::VerticalBlank => true,
             _ => !self.enabled,
         }
     }
 
-    pub fn to_rgba8(&self, buf: &mut Box<[u8]>, palette: [(u8, u8, u8); 4]) {
+    pub fn to_rgba8(&self, buf: &mut Box<[u8]>, palette: &DmgPalette) {
         for i in 0..(SCREEN_WIDTH * SCREEN_HEIGHT) {
             let p = i << 2;
-            let c = (self.buffer[i] as usize) & 3;
-            buf[p + 0] = palette[c].0;
-            buf[p + 1] = palette[c].1;
-            buf[p + 2] = palette[c].2;
+            let c = palette.color(self.buffer[i]);
+            buf[p + 0] = c.0;
+            buf[p + 1] = c.1;
+            buf[p + 2] = c.2;
             buf[p + 3] = 0xFF;
         }
     }
 
     // Capture current framebuffer. Return as stream.
-    pub fn capture(
-        &self,
-        filename: &str,
-        palette: [(u8, u8, u8); 4],
-    ) -> Result<(), std::io::Error> {
+    pub fn capture(&self, filename: &str, palette: &DmgPalette) -> Result<(), std::io::Error> {
         use png::HasParameters;
         use std::fs::File;
         use std::io::BufWriter;
@@ -597,7 +798,7 @@ impl PPU {
         self.to_rgba8(&mut rgba8, palette);
 
         let path = Path::new(filename);
-        let file = File::create(path).unwrap();
+        let file = File::create(path)?;
         let ref mut w = BufWriter::new(file);
 
         let mut encoder = png::Encoder::new(w, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
@@ -642,7 +843,9 @@ impl MemoryMapped for PPU {
                 lcdc
             }
             STAT_REG => {
+                // The mode reads 0 while the LCD is off
                 let mut stat: u8 = match self.mode {
+                    _ if !self.enabled => 0,
                     Mode::HorizontalBlank => 0,
                     Mode::VerticalBlank => 1,
                     Mode::OAMSearch => 2,
@@ -660,7 +863,7 @@ impl MemoryMapped for PPU {
                 if self.hblank_interrupt_enabled {
                     stat |= 8;
                 }
-                if self.ly == self.ly_compare {
+                if self.ly_register() == self.ly_compare {
                     stat |= 4;
                 }
 
@@ -668,7 +871,7 @@ impl MemoryMapped for PPU {
             }
             SCY_REG => self.scy as u8,
             SCX_REG => self.scx as u8,
-            LY_REG => (self.ly & 0xFF) as u8,
+            LY_REG => (self.ly_register() & 0xFF) as u8,
             LYC_REG => self.ly_compare as u8,
             BGP_REG => {
                 let p = self.bg_palette;
@@ -676,15 +879,19 @@ impl MemoryMapped for PPU {
             }
             OBP0_REG => {
                 let p = self.obj0_palette;
-                (p[1] << 2) | (p[2] << 4) | (p[3] << 6)
+                p[0] | (p[1] << 2) | (p[2] << 4) | (p[3] << 6)
             }
             OBP1_REG => {
                 let p = self.obj1_palette;
-                (p[1] << 2) | (p[2] << 4) | (p[3] << 6)
+                p[0] | (p[1] << 2) | (p[2] << 4) | (p[3] << 6)
             }
             WX_REG => self.wx as u8,
             WY_REG => self.wy as u8,
-            VRAM_OFFSET..=VRAM_END => self.vram[(address - VRAM_OFFSET) as usize],
+            VRAM_OFFSET..=VRAM_END => match self.vram_bank {
+                0 => self.vram[address - VRAM_OFFSET],
+                _ => self.vram1[address - VRAM_OFFSET],
+            },
+            VBK_REG => self.vram_bank,
             OAM_OFFSET..=OAM_END => {
                 if self.is_oam_accessible() {
                     let idx = (address - OAM_OFFSET) / OAM_OBJECT_SIZE;
@@ -692,13 +899,20 @@ impl MemoryMapped for PPU {
                 }
                 0xFF
             }
-            _ => panic!("0x{:04x} is not mapped to PPU", address),
+            _ => {
+                invalid_access!("0x{:04x} is not mapped to PPU", address);
+                0xFF
+            }
         }
     }
 
     fn write(&mut self, address: usize, value: u8) {
         match address {
-            VRAM_OFFSET..=VRAM_END => self.vram[(address - VRAM_OFFSET) as usize] = value,
+            VRAM_OFFSET..=VRAM_END => match self.vram_bank {
+                0 => self.vram[address - VRAM_OFFSET] = value,
+                _ => self.vram1[address - VRAM_OFFSET] = value,
+            },
+            VBK_REG => self.vram_bank = value & 1,
             OAM_OFFSET..=OAM_END => {
                 if self.is_oam_accessible() {
                     let idx = (address - OAM_OFFSET) / OAM_OBJECT_SIZE;
@@ -715,19 +929,32 @@ impl MemoryMapped for PPU {
                 self.bg_palette[3] = (value >> 6) & 3;
             }
             OBP0_REG => {
-                self.obj0_palette[0] = 0;
+                // Color 0 is always transparent for objects, so bits 0-1
+                // are unused. They are stored and read back all the same.
+                self.obj0_palette[0] = value & 3;
                 self.obj0_palette[1] = (value >> 2) & 3;
                 self.obj0_palette[2] = (value >> 4) & 3;
                 self.obj0_palette[3] = (value >> 6) & 3;
             }
             OBP1_REG => {
-                self.obj1_palette[0] = 0;
+                self.obj1_palette[0] = value & 3;
                 self.obj1_palette[1] = (value >> 2) & 3;
                 self.obj1_palette[2] = (value >> 4) & 3;
                 self.obj1_palette[3] = (value >> 6) & 3;
             }
             LCDC_REG => {
-                self.enabled = (value & 128) != 0;
+                // Turning the LCD on starts a frame from line 0, which
+                // has a different timing than the following lines
+                let enabled = (value & 128) != 0;
+                if enabled && !self.enabled {
+                    self.ly = 0;
+                    self.window_ly = 0;
+                    self.scanline_timer = 0;
+                    self.mode = Mode::HorizontalBlank;
+                    self.first_line = true;
+                }
+                self.enabled = enabled;
+                self.update_stat_line(false);
                 self.window_tile_map_offset = if value & 64 == 0 {
                     WINDOW_TILE_MAP_OFFSET_0
                 } else {
@@ -748,25 +975,367 @@ impl MemoryMapped for PPU {
                 self.objects_enabled = value & 2 != 0;
                 self.bg_and_window_enable_prio = value & 1 != 0;
             }
+            // The STAT line is re-evaluated immediately when LYC or STAT
+            // is written. If it goes high as a result of the write, the
+            // interrupt is requested right away. This is used by games
+            // that reprogram LYC mid-frame for split screen effects.
             STAT_REG => {
                 self.lyc_interrupt_enabled = value & 64 != 0;
                 self.oam_search_interrupt_enabled = value & 32 != 0;
                 self.vblank_interrupt_enabled = value & 16 != 0;
                 self.hblank_interrupt_enabled = value & 8 != 0;
+                self.update_stat_line(self.lyc_write_irq);
+            }
+            LYC_REG => {
+                self.ly_compare = value as usize;
+                self.update_stat_line(self.lyc_write_irq);
             }
-            LYC_REG => self.ly_compare = value as usize,
             WX_REG => self.wx = value as usize,
             WY_REG => self.wy = value as usize,
 
-            _ => panic!("0x{:04x} is not mapped to PPU for writing", address),
+            _ => invalid_access!("0x{:04x} is not mapped to PPU for writing", address),
         };
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self, kind: ResetKind) {
+        let mut ppu = PPU::new(self.machine);
+
+        // VRAM and OAM keep their content unless power is lost
+        if kind == ResetKind::Soft {
+            ppu.vram = self.vram;
+            ppu.vram1 = self.vram1;
+            ppu.oam = self.oam;
+        }
+
+        // Not part of the emulated hardware
+        ppu.lyc_write_irq = self.lyc_write_irq;
+        ppu.frame_number = self.frame_number;
+        ppu.layers = self.layers;
+
         // 3 is the brightest color for DMG
-        self.buffer.fill(3);
-        self.vram.fill(0);
-        self.oam = [Sprite::default(); OAM_SIZE / OAM_OBJECT_SIZE];
-        self.irq = 0;
+        ppu.buffer.fill(3);
+
+        *self = ppu;
+    }
+}
+
+impl SaveState for PPU {
+    fn serialize(&mut self, s: &mut Serializer) {
+        for flag in [
+            &mut self.enabled,
+            &mut self.window_enabled,
+            &mut self.objects_enabled,
+            &mut self.lyc_interrupt_enabled,
+            &mut self.oam_search_interrupt_enabled,
+            &mut self.vblank_interrupt_enabled,
+            &mut self.hblank_interrupt_enabled,
+            &mut self.stat_line,
+            &mut self.bg_and_window_enable_prio,
+            &mut self.cgb_mode,
+            &mut self.first_line,
+        ] {
+            s.bool(flag);
+        }
+
+        let mut secondary = matches!(self.tile_addressing_mode, TileAddressingMode::Secondary);
+        s.bool(&mut secondary);
+        self.tile_addressing_mode = match secondary {
+            true => TileAddressingMode::Secondary,
+            false => TileAddressingMode::Primary,
+        };
+
+        let mut mode = match self.mode {
+            Mode::HorizontalBlank => 0,
+            Mode::VerticalBlank => 1,
+            Mode::OAMSearch => 2,
+            Mode::PixelTransfer => 3,
+        };
+        s.u8(&mut mode);
+        self.mode = match mode {
+            0 => Mode::HorizontalBlank,
+            1 => Mode::VerticalBlank,
+            2 => Mode::OAMSearch,
+            _ => Mode::PixelTransfer,
+        };
+
+        // OAM is saved as it's seen from the CPU
+        let mut oam = [0; OAM_SIZE];
+        for (i, b) in oam.iter_mut().enumerate() {
+            *b = self.oam[i / OAM_OBJECT_SIZE].read(i);
+        }
+        s.bytes(&mut oam);
+        for (i, b) in oam.iter().enumerate() {
+            self.oam[i / OAM_OBJECT_SIZE].write(i, *b);
+        }
+
+        s.bytes(&mut self.vram);
+        s.bytes(&mut self.vram1);
+        s.u8(&mut self.vram_bank);
+        s.bytes(&mut self.buffer);
+        s.bytes(&mut self.bg_palette);
+        s.bytes(&mut self.obj0_palette);
+        s.bytes(&mut self.obj1_palette);
+        for v in [
+            &mut self.window_tile_map_offset,
+            &mut self.bg_tile_map_offset,
+            &mut self.object_height,
+            &mut self.ly,
+            &mut self.scanline_timer,
+            &mut self.scanline_object_count,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly_compare,
+            &mut self.wx,
+            &mut self.wy,
+            &mut self.window_ly,
+            &mut self.frame_number,
+        ] {
+            s.usize(v);
+        }
+        for obj in self.scanline_objects.iter_mut() {
+            s.usize(obj);
+        }
+        s.u8(&mut self.irq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const STAT_LYC_INTERRUPT: u8 = 64;
+    const STAT_COINCIDENCE: u8 = 4;
+
+    #[test]
+    fn test_lyc_write_updates_coincidence_flag() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.ly = 42;
+        ppu.write(LYC_REG, 41);
+        assert!(ppu.read(STAT_REG) & STAT_COINCIDENCE == 0);
+        ppu.write(LYC_REG, 42);
+        assert!(ppu.read(STAT_REG) & STAT_COINCIDENCE != 0);
+        ppu.write(LYC_REG, 43);
+        assert!(ppu.read(STAT_REG) & STAT_COINCIDENCE == 0);
+    }
+
+    #[test]
+    fn test_lyc_write_requests_interrupt() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.ly = 42;
+        ppu.write(LYC_REG, 10);
+        ppu.write(STAT_REG, STAT_LYC_INTERRUPT);
+        assert_eq!(ppu.irq, 0);
+        ppu.write(LYC_REG, 42);
+        assert_eq!(ppu.irq, IF_LCDC_BIT);
+    }
+
+    #[test]
+    fn test_lyc_write_without_interrupt_enabled() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.ly = 42;
+        ppu.write(LYC_REG, 42);
+        assert_eq!(ppu.irq, 0);
+    }
+
+    #[test]
+    fn test_lyc_write_with_unchanged_coincidence() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.ly = 42;
+        ppu.write(LYC_REG, 42);
+        ppu.write(STAT_REG, STAT_LYC_INTERRUPT);
+        ppu.irq = 0;
+
+        // Condition was already true, so there is no new interrupt
+        ppu.write(LYC_REG, 42);
+        assert_eq!(ppu.irq, 0);
+    }
+
+    #[test]
+    fn test_beam_position() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        assert_eq!(ppu.beam_position(), SCREEN_WIDTH * SCREEN_HEIGHT);
+
+        ppu.write(LCDC_REG, 0x80);
+        ppu.ly = 10;
+        ppu.scanline_timer = 100;
+        assert_eq!(ppu.beam_position(), 10 * SCREEN_WIDTH);
+        ppu.scanline_timer = 300;
+        assert_eq!(ppu.beam_position(), 11 * SCREEN_WIDTH);
+        ppu.ly = 150;
+        assert_eq!(ppu.beam_position(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn test_objects() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        for n in 0..OAM_OBJECT_COUNT {
+            ppu.oam[n].write(0, if n < 12 { 16 + 4 } else { 0 });
+        }
+        assert!(ppu.objects_on_line(3).is_empty());
+        assert_eq!(ppu.objects_on_line(4).len(), 12);
+        assert_eq!(ppu.objects_on_line(11).len(), 12);
+        assert!(ppu.objects_on_line(12).is_empty());
+
+        // A diagonal line in tile 1, flipped
+        for y in 0..8 {
+            ppu.vram[TILE_SIZE + y * 2] = 0x80 >> y;
+        }
+        ppu.oam[0].write(2, 1);
+        ppu.oam[0].write(3, 0x20);
+        let pixels = ppu.object_pixels(0);
+        assert_eq!(pixels.len(), 64);
+        assert_eq!(pixels[7], 1);
+        assert_eq!(pixels[0], 0);
+        assert_eq!(pixels[8 * 7], 1);
+    }
+
+    #[test]
+    fn test_hidden_layers() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.write(LCDC_REG, 0x93);
+        ppu.write(BGP_REG, 0xE4);
+        ppu.write(OBP0_REG, 0xE4);
+
+        // Background of color 3, and an object of color 1 at (0, 0)
+        ppu.vram[0..16].fill(0xFF);
+        ppu.vram[16..32].copy_from_slice(&[0xFF, 0x00].repeat(8));
+        for (offset, value) in [16, 8, 1, 0].iter().enumerate() {
+            ppu.oam[0].write(offset, *value);
+        }
+
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[0], 1 | SOURCE_OBJ0);
+        assert_eq!(ppu.buffer[8], 3 | SOURCE_BG);
+
+        ppu.layers.objects = false;
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[0], 3 | SOURCE_BG);
+
+        ppu.layers.background = false;
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[0], SOURCE_BG);
+
+        ppu.reset(ResetKind::Soft);
+        assert!(!ppu.layers.background);
+    }
+
+    #[test]
+    fn test_cgb_priority() {
+        let mut ppu = PPU::new(Machine::GameBoyCGB);
+        ppu.write(LCDC_REG, 0x93);
+        ppu.write(BGP_REG, 0xE4);
+        ppu.write(OBP0_REG, 0xE4);
+
+        // Background of color 3, and an object of color 1 at (0, 0)
+        ppu.vram[0..16].fill(0xFF);
+        ppu.vram[16..32].copy_from_slice(&[0xFF, 0x00].repeat(8));
+        for (offset, value) in [16, 8, 1, 0].iter().enumerate() {
+            ppu.oam[0].write(offset, *value);
+        }
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[0], 1 | SOURCE_OBJ0);
+
+        // The priority bit of the tile attributes
+        ppu.vram1[0x1800] = 0x80;
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[0], 3 | SOURCE_BG);
+
+        // LCDC bit 0 gives objects priority, but the background is
+        // still drawn
+        ppu.write(LCDC_REG, 0x92);
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[0], 1 | SOURCE_OBJ0);
+        assert_eq!(ppu.buffer[8], 3 | SOURCE_BG);
+
+        // Not in DMG compatibility mode
+        ppu.cgb_mode = false;
+        ppu.render_scanline();
+        assert_eq!(ppu.buffer[8], SOURCE_BG);
+    }
+
+    #[test]
+    fn test_first_line_after_lcd_enable() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.write(STAT_REG, 32);
+        ppu.write(LCDC_REG, 0x80);
+        assert_eq!(ppu.read(LY_REG), 0);
+
+        // Mode 0 instead of OAM search, then pixel transfer
+        ppu.update(80);
+        assert_eq!(ppu.read(STAT_REG) & 3, 0);
+        ppu.update(2);
+        assert_eq!(ppu.read(STAT_REG) & 3, 3);
+        assert_eq!(ppu.irq, 0);
+
+        // The next line has the normal timing
+        ppu.update((DOTS_PER_LINE - 80) as u32);
+        assert_eq!(ppu.read(LY_REG), 1);
+        assert_eq!(ppu.read(STAT_REG) & 3, 2);
+        assert_eq!(ppu.irq, IF_LCDC_BIT);
+    }
+
+    #[test]
+    fn test_line_153_reads_as_0() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.write(LCDC_REG, 0x80);
+        ppu.write(LYC_REG, 0);
+        ppu.write(STAT_REG, STAT_LYC_INTERRUPT);
+        while ppu.ly != 153 {
+            ppu.update(2);
+        }
+        ppu.irq = 0;
+        assert_eq!(ppu.read(LY_REG), 153);
+        assert!(ppu.read(STAT_REG) & STAT_COINCIDENCE == 0);
+
+        ppu.update(4);
+        assert_eq!(ppu.read(LY_REG), 0);
+        assert!(ppu.read(STAT_REG) & STAT_COINCIDENCE != 0);
+        assert_eq!(ppu.irq, IF_LCDC_BIT);
+
+        // No new interrupt when line 0 starts
+        ppu.irq = 0;
+        ppu.update(DOTS_PER_LINE as u32);
+        assert_eq!((ppu.ly, ppu.read(LY_REG)), (0, 0));
+        assert_eq!(ppu.irq & IF_LCDC_BIT, 0);
+    }
+
+    #[test]
+    fn test_stat_write_requests_interrupt() {
+        let mut ppu = PPU::new(Machine::GameBoyDMG);
+        ppu.ly = 42;
+        ppu.write(LYC_REG, 42);
+        assert_eq!(ppu.irq, 0);
+        ppu.write(STAT_REG, STAT_LYC_INTERRUPT);
+        assert_eq!(ppu.irq, IF_LCDC_BIT);
+    }
+
+    #[test]
+    fn test_stat_blocking() {
+        const STAT_HBLANK_INTERRUPT: u8 = 8;
+
+        // Run to the HBlank of line 2, with LYC on another line
+        let run_to_hblank = |stat: u8| {
+            let mut ppu = PPU::new(Machine::GameBoyDMG);
+            ppu.write(LCDC_REG, 0x80);
+            ppu.write(LYC_REG, 100);
+            ppu.write(STAT_REG, stat);
+            while ppu.ly != 2 || ppu.read(STAT_REG) & 3 != 0 {
+                ppu.update(2);
+            }
+            ppu.irq = 0;
+            ppu
+        };
+
+        // The HBlank source holds the STAT line high, so LYC matching
+        // during HBlank doesn't request another interrupt
+        let mut ppu = run_to_hblank(STAT_HBLANK_INTERRUPT | STAT_LYC_INTERRUPT);
+        ppu.write(LYC_REG, 2);
+        assert_eq!(ppu.irq, 0);
+        ppu.update(4);
+        assert_eq!(ppu.irq, 0);
+
+        // Without it, the match does
+        let mut ppu = run_to_hblank(STAT_LYC_INTERRUPT);
+        ppu.write(LYC_REG, 2);
+        assert_eq!(ppu.irq, IF_LCDC_BIT);
     }
 }
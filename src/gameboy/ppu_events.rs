@@ -0,0 +1,112 @@
+// PPU event log
+//
+// Records CPU writes to the registers that affect rendering, together
+// with the position of the PPU when the write happened. Raster effects
+// depend on writes happening at the right scanline and dot, which is
+// hard to see in a trace but obvious when the writes are plotted over
+// the frame.
+//
+// The events of the frame being rendered are collected, and moved to
+// `last_frame` when the frame is complete.
+
+use super::mmu::{BGP_REG, LCDC_REG, OBP0_REG, OBP1_REG, SCX_REG, SCY_REG, WX_REG, WY_REG};
+
+// Registers whose writes are logged
+pub const LOGGED_REGISTERS: [usize; 8] = [
+    LCDC_REG, SCY_REG, SCX_REG, BGP_REG, OBP0_REG, OBP1_REG, WY_REG, WX_REG,
+];
+
+// Writes beyond this number per frame are dropped
+const MAX_EVENTS_PER_FRAME: usize = 10_000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PpuEvent {
+    pub line: usize,
+    pub dot: usize,
+    pub address: usize,
+    pub value: u8,
+}
+
+pub struct PpuEventLog {
+    // Writes are only recorded when enabled
+    pub enabled: bool,
+
+    current: Vec<PpuEvent>,
+    last_frame: Vec<PpuEvent>,
+}
+
+impl Default for PpuEventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PpuEventLog {
+    pub fn new() -> Self {
+        PpuEventLog {
+            enabled: false,
+            current: Vec::new(),
+            last_frame: Vec::new(),
+        }
+    }
+
+    pub fn is_logged(address: usize) -> bool {
+        LOGGED_REGISTERS.contains(&address)
+    }
+
+    pub fn record(&mut self, line: usize, dot: usize, address: usize, value: u8) {
+        if self.current.len() < MAX_EVENTS_PER_FRAME {
+            self.current.push(PpuEvent {
+                line,
+                dot,
+                address,
+                value,
+            });
+        }
+    }
+
+    // Called when a frame is complete
+    pub fn end_frame(&mut self) {
+        self.last_frame = std::mem::take(&mut self.current);
+    }
+
+    // Events of the last complete frame
+    pub fn last_frame(&self) -> &[PpuEvent] {
+        &self.last_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameboy::emu::{Emu, Machine};
+    use crate::gameboy::mmu::{LCDC_REG, SCX_REG};
+
+    #[test]
+    fn test_scanline_writes() {
+        // LD A,n; LDH (SCX),A
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.mmu.direct_write(LCDC_REG, 0x80);
+        emu.mmu.ppu_events.enabled = true;
+        for (i, b) in [0x3E, 0x05, 0xE0, 0x43, 0x18, 0xFA].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+
+        // Writes made directly, such as by the debugger, are not logged
+        emu.mmu.direct_write(SCX_REG, 0);
+
+        let frame = emu.mmu.ppu.frame_number;
+        while emu.mmu.ppu.frame_number < frame + 2 {
+            emu.step();
+        }
+
+        let events = emu.mmu.ppu_events.last_frame();
+        assert!(events.len() > 154);
+        assert!(events.iter().all(|e| e.address == SCX_REG && e.value == 5));
+        assert!(events.iter().all(|e| e.line < 154 && e.dot < 456));
+        assert!(events
+            .windows(2)
+            .all(|w| (w[0].line, w[0].dot) < (w[1].line, w[1].dot)));
+    }
+}
@@ -0,0 +1,341 @@
+// Game Boy Printer
+//
+// The printer is connected to the serial port, and receives packets
+// from the Game Boy:
+//
+//   0x88 0x33 command compression length(2) data checksum(2) 0x00 0x00
+//
+// The length and checksum are little endian, and the checksum is the
+// sum of all bytes from the command to the end of the data. While the
+// last two bytes are sent, the printer answers with 0x81 and then its
+// status. All other bytes are answered with 0x00.
+//
+// Image data is sent in packets of 640 bytes: two rows of 20 tiles, in
+// the same 2bpp format as in VRAM. When the print command is received,
+// the data collected so far is turned into an image with the palette
+// given in the command, and sent to `output`.
+
+use std::sync::mpsc::Sender;
+
+use super::serial::SerialDevice;
+
+// Commands
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+
+// Status bits
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+// Width of the paper in pixels, and in tiles
+pub const PRINTER_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PRINTER_WIDTH / 8;
+
+// Size of the image buffer: nine data packets
+const BUFFER_SIZE: usize = 0x1680;
+
+// Number of status requests that report that printing is in progress
+// after the print command. Games wait for printing to start and finish.
+const PRINTING_STATUS_COUNT: u8 = 4;
+
+// Size of the packet header, from the command to the length
+const HEADER_SIZE: usize = 4;
+
+// A printed image, as shades (0-3) from white to black
+pub struct PrintedImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Magic1,
+    Magic2,
+    Header,
+    Data,
+    Checksum,
+    Alive,
+    Status,
+}
+
+pub struct Printer {
+    state: State,
+
+    // Command, compression and length, and then the data and checksum
+    // of the packet being received
+    header: Vec<u8>,
+    data: Vec<u8>,
+    checksum: Vec<u8>,
+
+    // Decompressed image data, waiting for the print command
+    buffer: Vec<u8>,
+
+    status: u8,
+    printing: u8,
+
+    output: Sender<PrintedImage>,
+}
+
+// Expand run-length encoded data. A byte with bit 7 clear is followed
+// by that many plus one literal bytes. A byte with bit 7 set is
+// followed by a single byte that is repeated (byte & 0x7F) + 2 times.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+        if control & 0x80 != 0 {
+            if let Some(b) = data.get(i) {
+                result.extend(std::iter::repeat_n(*b, (control & 0x7F) + 2));
+            }
+            i += 1;
+        } else {
+            let end = (i + control + 1).min(data.len());
+            result.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    result
+}
+
+// Turn tile data into an image with the given palette. Incomplete tile
+// rows at the end of the data are ignored.
+fn render(data: &[u8], palette: u8) -> PrintedImage {
+    let tile_rows = data.len() / (TILES_PER_ROW * 16);
+    let height = tile_rows * 8;
+    let mut pixels = vec![0; PRINTER_WIDTH * height];
+
+    for (n, tile) in data
+        .chunks_exact(16)
+        .take(tile_rows * TILES_PER_ROW)
+        .enumerate()
+    {
+        let left = (n % TILES_PER_ROW) * 8;
+        let top = (n / TILES_PER_ROW) * 8;
+        for y in 0..8 {
+            let (lo, hi) = (tile[y * 2], tile[y * 2 + 1]);
+            for x in 0..8 {
+                let color = ((lo >> (7 - x)) & 1) | (((hi >> (7 - x)) & 1) << 1);
+                pixels[(top + y) * PRINTER_WIDTH + left + x] = (palette >> (color * 2)) & 3;
+            }
+        }
+    }
+
+    PrintedImage {
+        width: PRINTER_WIDTH,
+        height,
+        pixels,
+    }
+}
+
+impl Printer {
+    pub fn new(output: Sender<PrintedImage>) -> Self {
+        Printer {
+            state: State::Magic1,
+            header: Vec::new(),
+            data: Vec::new(),
+            checksum: Vec::new(),
+            buffer: Vec::new(),
+            status: 0,
+            printing: 0,
+            output,
+        }
+    }
+
+    fn data_length(&self) -> usize {
+        self.header[2] as usize | ((self.header[3] as usize) << 8)
+    }
+
+    fn checksum_ok(&self) -> bool {
+        let sum = self
+            .header
+            .iter()
+            .chain(self.data.iter())
+            .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+        sum == u16::from_le_bytes([self.checksum[0], self.checksum[1]])
+    }
+
+    fn execute(&mut self) {
+        if !self.checksum_ok() {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.header[0] {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            }
+            PRINT if self.data.len() >= 3 => {
+                let palette = self.data[2];
+                let image = render(&self.buffer, palette);
+                self.buffer.clear();
+
+                // Fails only if nothing is receiving the images
+                let _ = self.output.send(image);
+                self.status &= !(STATUS_UNPROCESSED | STATUS_FULL);
+                self.printing = PRINTING_STATUS_COUNT;
+            }
+            DATA => {
+                let data = match self.header[1] {
+                    0 => self.data.clone(),
+                    _ => decompress(&self.data),
+                };
+                let space = BUFFER_SIZE - self.buffer.len();
+                self.buffer
+                    .extend_from_slice(&data[..data.len().min(space)]);
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_FULL;
+                }
+            }
+
+            // Status requests, and unknown commands
+            _ => {}
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        if self.printing > 0 {
+            self.printing -= 1;
+            self.status | STATUS_PRINTING
+        } else {
+            self.status
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, value: u8) -> u8 {
+        let mut response = 0x00;
+        self.state = match self.state {
+            State::Magic1 if value == 0x88 => State::Magic2,
+            State::Magic1 => State::Magic1,
+            State::Magic2 if value == 0x33 => {
+                self.header.clear();
+                self.data.clear();
+                self.checksum.clear();
+                State::Header
+            }
+            State::Magic2 => State::Magic1,
+            State::Header => {
+                self.header.push(value);
+                match self.header.len() {
+                    HEADER_SIZE if self.data_length() > 0 => State::Data,
+                    HEADER_SIZE => State::Checksum,
+                    _ => State::Header,
+                }
+            }
+            State::Data => {
+                self.data.push(value);
+                if self.data.len() == self.data_length() {
+                    State::Checksum
+                } else {
+                    State::Data
+                }
+            }
+            State::Checksum => {
+                self.checksum.push(value);
+                if self.checksum.len() == 2 {
+                    self.execute();
+                    State::Alive
+                } else {
+                    State::Checksum
+                }
+            }
+            State::Alive => {
+                response = 0x81;
+                State::Status
+            }
+            State::Status => {
+                response = self.status();
+                State::Magic1
+            }
+        };
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    // Send a packet. Returns the last two responses: alive and status.
+    fn send(printer: &mut Printer, command: u8, compression: u8, data: &[u8]) -> (u8, u8) {
+        let len = (data.len() as u16).to_le_bytes();
+        let header = [command, compression, len[0], len[1]];
+        let sum = header
+            .iter()
+            .chain(data.iter())
+            .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+
+        let mut packet = vec![0x88, 0x33];
+        packet.extend_from_slice(&header);
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(&sum.to_le_bytes());
+        for b in packet {
+            assert_eq!(printer.exchange(b), 0x00);
+        }
+        (printer.exchange(0), printer.exchange(0))
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(
+            decompress(&[0x01, 1, 2, 0x81, 7, 0x00, 3]),
+            [1, 2, 7, 7, 7, 3]
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let (sender, receiver) = channel();
+        let mut printer = Printer::new(sender);
+
+        assert_eq!(send(&mut printer, INIT, 0, &[]), (0x81, 0x00));
+
+        // Two rows of tiles, with the first pixel of every tile set to
+        // color 3, and the rest to color 1
+        let mut tile = [0xFF, 0x00].repeat(8);
+        tile[1] = 0x80;
+        let data = tile.repeat(TILES_PER_ROW * 2);
+        assert_eq!(
+            send(&mut printer, DATA, 0, &data),
+            (0x81, STATUS_UNPROCESSED)
+        );
+
+        // Palette: color 1 is light gray, color 3 black
+        let (_, status) = send(&mut printer, PRINT, 0, &[1, 0x13, 0xC4, 0x40]);
+        assert_eq!(status, STATUS_PRINTING);
+
+        let image = receiver.try_recv().unwrap();
+        assert_eq!((image.width, image.height), (160, 16));
+        assert_eq!(&image.pixels[0..9], [3, 1, 1, 1, 1, 1, 1, 1, 3]);
+
+        // Printing is reported for a few status requests
+        for _ in 1..PRINTING_STATUS_COUNT {
+            assert_eq!(send(&mut printer, 0x0F, 0, &[]).1, STATUS_PRINTING);
+        }
+        assert_eq!(send(&mut printer, 0x0F, 0, &[]).1, 0);
+    }
+
+    #[test]
+    fn test_checksum_error() {
+        let (sender, _receiver) = channel();
+        let mut printer = Printer::new(sender);
+        for b in [0x88, 0x33, INIT, 0, 0, 0, 0x02, 0x00] {
+            printer.exchange(b);
+        }
+        assert_eq!(printer.exchange(0), 0x81);
+        assert_eq!(printer.exchange(0), STATUS_CHECKSUM_ERROR);
+    }
+}
@@ -0,0 +1,182 @@
+// Per-game compatibility quirks
+//
+// Some games depend on hardware behaviour that isn't emulated
+// accurately yet, or trip on behaviour that is. Quirks enable
+// workarounds for specific titles. They are listed in an embedded
+// TOML file, and the user may add or override entries with their
+// own file. Quirks are looked up and applied when a cartridge is
+// loaded.
+
+use serde::Deserialize;
+
+use super::cartridge::cartridge_header::CartridgeHeader;
+
+const EMBEDDED_QUIRKS: &str = include_str!("quirks.toml");
+
+#[derive(Deserialize)]
+struct QuirkEntry {
+    // Only used to make the list readable
+    #[allow(dead_code)]
+    title: Option<String>,
+
+    header_checksum: u8,
+    global_checksum: Option<u16>,
+
+    no_lyc_write_irq: Option<bool>,
+    no_strict_mode: Option<bool>,
+}
+
+impl QuirkEntry {
+    fn matches(&self, header: &CartridgeHeader) -> bool {
+        self.header_checksum == header.checksum
+            && match self.global_checksum {
+                Some(checksum) => checksum == header.global_checksum,
+                None => true,
+            }
+    }
+}
+
+#[derive(Deserialize)]
+struct QuirkFile {
+    #[serde(default)]
+    game: Vec<QuirkEntry>,
+}
+
+// The quirks that apply to a single game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    pub no_lyc_write_irq: bool,
+    pub no_strict_mode: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Quirks {
+    pub fn new() -> Self {
+        Quirks {
+            no_lyc_write_irq: false,
+            no_strict_mode: false,
+        }
+    }
+
+    fn apply(&mut self, entry: &QuirkEntry) {
+        if let Some(value) = entry.no_lyc_write_irq {
+            self.no_lyc_write_irq = value;
+        }
+        if let Some(value) = entry.no_strict_mode {
+            self.no_strict_mode = value;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Quirks::new()
+    }
+}
+
+pub struct QuirkRegistry {
+    // Embedded entries first, followed by the user's entries,
+    // so that the user's entries take precedence
+    entries: Vec<QuirkEntry>,
+}
+
+fn parse(content: &str) -> Result<Vec<QuirkEntry>, String> {
+    match toml::from_str::<QuirkFile>(content) {
+        Ok(file) => Ok(file.game),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl Default for QuirkRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QuirkRegistry {
+    pub fn new() -> Self {
+        QuirkRegistry {
+            entries: parse(EMBEDDED_QUIRKS).expect("Invalid embedded quirk list"),
+        }
+    }
+
+    // Load the user's quirk file
+    pub fn load_overrides(&mut self, filename: &str) -> Result<(), String> {
+        let content = std::fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        self.add_overrides(&content)
+    }
+
+    pub fn add_overrides(&mut self, content: &str) -> Result<(), String> {
+        let mut entries = parse(content)?;
+        self.entries.append(&mut entries);
+        Ok(())
+    }
+
+    // Number of games with quirks
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lookup(&self, header: &CartridgeHeader) -> Quirks {
+        let mut quirks = Quirks::new();
+        for entry in self.entries.iter().filter(|e| e.matches(header)) {
+            quirks.apply(entry);
+        }
+        quirks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(checksum: u8, global_checksum: u16) -> CartridgeHeader {
+        let mut rom = vec![0; 0x150];
+        rom[0x14D] = checksum;
+        rom[0x14E] = (global_checksum >> 8) as u8;
+        rom[0x14F] = global_checksum as u8;
+        CartridgeHeader::from_header(&rom)
+    }
+
+    #[test]
+    fn test_embedded_quirks_parse() {
+        QuirkRegistry::new();
+    }
+
+    #[test]
+    fn test_lookup_and_override() {
+        let mut registry = QuirkRegistry::new();
+        registry
+            .add_overrides(
+                r#"
+                [[game]]
+                header_checksum = 0x12
+                no_lyc_write_irq = true
+                no_strict_mode = true
+
+                [[game]]
+                header_checksum = 0x12
+                global_checksum = 0x3456
+                no_strict_mode = false
+                "#,
+            )
+            .unwrap();
+
+        let quirks = registry.lookup(&header(0x12, 0x3456));
+        assert!(quirks.no_lyc_write_irq);
+        assert!(!quirks.no_strict_mode);
+
+        let quirks = registry.lookup(&header(0x12, 0x0000));
+        assert!(quirks.no_lyc_write_irq);
+        assert!(quirks.no_strict_mode);
+
+        assert!(registry.lookup(&header(0x13, 0x3456)).is_empty());
+    }
+}
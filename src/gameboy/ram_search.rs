@@ -0,0 +1,148 @@
+// RAM search
+//
+// Finds the address of a value in RAM, such as the number of lives,
+// the way cheat finders do: an initial scan records every byte of
+// RAM, and each filter then keeps the addresses whose value matches,
+// compared with the value it had at the previous scan. Running the
+// game between filters narrows the list down:
+//
+//   search.start(&mmu);        // 3 lives
+//   search.filter(&mmu, SearchFilter::EqualTo(3));
+//   ...lose a life...
+//   search.filter(&mmu, SearchFilter::Decreased);
+//
+// The found address can be frozen, or written as a GameShark code.
+
+use super::mmu::MMU;
+
+// Cartridge RAM, work RAM and high RAM
+pub const SEARCH_RANGES: [(usize, usize); 3] =
+    [(0xA000, 0xBFFF), (0xC000, 0xDFFF), (0xFF80, 0xFFFE)];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchFilter {
+    EqualTo(u8),
+    Increased,
+    Decreased,
+    Unchanged,
+    Changed,
+}
+
+impl SearchFilter {
+    fn matches(&self, previous: u8, value: u8) -> bool {
+        match self {
+            SearchFilter::EqualTo(v) => value == *v,
+            SearchFilter::Increased => value > previous,
+            SearchFilter::Decreased => value < previous,
+            SearchFilter::Unchanged => value == previous,
+            SearchFilter::Changed => value != previous,
+        }
+    }
+}
+
+pub struct RamSearch {
+    // Addresses that have matched all filters so far, with their
+    // values at the last scan. None before the initial scan.
+    candidates: Option<Vec<(usize, u8)>>,
+}
+
+impl Default for RamSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RamSearch {
+    pub fn new() -> Self {
+        RamSearch { candidates: None }
+    }
+
+    // Record the value of every address of RAM
+    pub fn start(&mut self, mmu: &MMU) {
+        let candidates = SEARCH_RANGES
+            .iter()
+            .flat_map(|(start, end)| *start..=*end)
+            .map(|addr| (addr, mmu.direct_read(addr)))
+            .collect();
+        self.candidates = Some(candidates);
+    }
+
+    // Keep the addresses that match `filter`, and record their values
+    // for the next filter. Starts a new search if there is none.
+    pub fn filter(&mut self, mmu: &MMU, filter: SearchFilter) {
+        if self.candidates.is_none() {
+            self.start(mmu);
+        }
+        if let Some(candidates) = self.candidates.as_mut() {
+            candidates.retain_mut(|(addr, previous)| {
+                let value = mmu.direct_read(*addr);
+                let keep = filter.matches(*previous, value);
+                *previous = value;
+                keep
+            });
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.candidates = None;
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.candidates.is_some()
+    }
+
+    // The matching addresses, with their values at the last scan
+    pub fn results(&self) -> &[(usize, u8)] {
+        match &self.candidates {
+            Some(candidates) => candidates,
+            None => &[],
+        }
+    }
+}
+
+// GameShark code that writes `value` to `addr` every frame. The code
+// is 01 (RAM bank 1), the value, and the address in little endian.
+pub fn gameshark_code(addr: usize, value: u8) -> String {
+    format!(
+        "01{:02X}{:02X}{:02X}",
+        value,
+        addr & 0xFF,
+        (addr >> 8) & 0xFF
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::Machine;
+
+    #[test]
+    fn test_ram_search() {
+        let mut mmu = MMU::new(Machine::GameBoyDMG);
+        mmu.direct_write(0xC123, 3);
+        mmu.direct_write(0xFF90, 3);
+
+        let mut search = RamSearch::new();
+        assert!(!search.is_started());
+        search.filter(&mmu, SearchFilter::EqualTo(3));
+        assert_eq!(search.results(), [(0xC123, 3), (0xFF90, 3)]);
+
+        mmu.direct_write(0xC123, 2);
+        search.filter(&mmu, SearchFilter::Decreased);
+        assert_eq!(search.results(), [(0xC123, 2)]);
+
+        search.filter(&mmu, SearchFilter::Unchanged);
+        assert_eq!(search.results(), [(0xC123, 2)]);
+        search.filter(&mmu, SearchFilter::Increased);
+        assert!(search.results().is_empty());
+
+        search.reset();
+        search.start(&mmu);
+        assert_eq!(search.results().len(), 0x2000 + 0x2000 + 0x7F);
+    }
+
+    #[test]
+    fn test_gameshark_code() {
+        assert_eq!(gameshark_code(0xC0A5, 0x63), "0163A5C0");
+    }
+}
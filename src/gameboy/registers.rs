@@ -1,3 +1,5 @@
+use crate::savestate::{SaveState, Serializer};
+
 pub const Z_BIT: u8 = 1 << 7; // zero flag
 pub const N_BIT: u8 = 1 << 6; // subtract flag
 pub const H_BIT: u8 = 1 << 5; // half carry flag
@@ -142,3 +144,32 @@ impl Registers {
         self.carry = c;
     }
 }
+
+impl SaveState for Registers {
+    fn serialize(&mut self, s: &mut Serializer) {
+        for r in [
+            &mut self.a,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+            &mut self.ime,
+        ] {
+            s.u8(r);
+        }
+        s.u16(&mut self.sp);
+        s.u16(&mut self.pc);
+        for flag in [
+            &mut self.zero,
+            &mut self.neg,
+            &mut self.half_carry,
+            &mut self.carry,
+            &mut self.stopped,
+            &mut self.halted,
+        ] {
+            s.bool(flag);
+        }
+    }
+}
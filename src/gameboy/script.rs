@@ -0,0 +1,398 @@
+// Scripting
+//
+// Scripts are written in Rhai (https://rhai.rs) and can be used to
+// write bots, visualizers and other tools that need to interact with
+// the running game. A script is loaded with `--script <file>`, or from
+// the script console.
+//
+// Functions available to scripts:
+//
+//   read(addr)                 Read a byte of memory
+//   write(addr, value)         Write a byte to memory
+//   reg(name)                  Read a register: a, f, b, c, d, e, h, l,
+//                              af, bc, de, hl, sp or pc
+//   set_reg(name, value)       Write a register
+//   frame()                    Current frame number
+//   add_breakpoint(addr)       Call on_breakpoint(addr) when the
+//                              instruction at addr is about to execute
+//   remove_breakpoint(addr)
+//   draw_text(x, y, text)      Draw text over the screen. Coordinates
+//                              are in Game Boy pixels. The text is
+//                              cleared at the start of each frame.
+//   print(text)                Print to the script console
+//
+// Functions defined by the script are called on these events, if
+// they exist:
+//
+//   on_start()                 When the script has been loaded
+//   on_frame()                 At the start of each frame
+//   on_breakpoint(addr)        At a breakpoint added by the script
+//
+// Rhai functions can't access variables defined outside of them, so
+// state that is kept between calls is stored in `this`, which is an
+// object map shared by all callbacks. For example:
+//
+//   fn on_start() { this.jumps = 0; }
+//   fn on_breakpoint(addr) { this.jumps += 1; }
+//   fn on_frame() { draw_text(0, 0, `jumps: ${this.jumps}`); }
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::core::OverlayText;
+
+use super::emu::Emu;
+use super::registers::Registers;
+
+// Number of lines kept in the console
+const MAX_CONSOLE_LINES: usize = 1000;
+
+// Pointer to the emulator, for the functions called by the script.
+// It's only set while `ScriptHost::call` runs, during which the
+// emulator is exclusively borrowed by the script host. It's atomic
+// only so that the emulator can be moved to another thread.
+type EmuPtr = Arc<AtomicPtr<Emu>>;
+
+fn with_emu<R>(ptr: &EmuPtr, f: impl FnOnce(&mut Emu) -> R) -> R {
+    let emu = ptr.load(Ordering::Relaxed);
+    assert!(!emu.is_null(), "script accessed emulator outside of a call");
+    // Safety: see EmuPtr
+    f(unsafe { &mut *emu })
+}
+
+// State shared between the host and the functions called by the script
+struct ScriptState {
+    breakpoints: HashSet<u16>,
+    overlay: Vec<OverlayText>,
+    console: Vec<String>,
+}
+
+impl ScriptState {
+    fn print(&mut self, line: &str) {
+        if self.console.len() >= MAX_CONSOLE_LINES {
+            self.console.remove(0);
+        }
+        self.console.push(line.to_string());
+    }
+}
+
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+
+    // Bound to `this` in callbacks
+    this: Dynamic,
+
+    emu: EmuPtr,
+    state: Arc<Mutex<ScriptState>>,
+
+    // Frame number when on_frame was last called
+    last_frame: Option<usize>,
+    has_on_frame: bool,
+}
+
+fn get_reg(reg: &Registers, name: &str) -> Option<i64> {
+    let value = match name {
+        "a" => reg.a as u16,
+        "f" => reg.get_f() as u16,
+        "b" => reg.b as u16,
+        "c" => reg.c as u16,
+        "d" => reg.d as u16,
+        "e" => reg.e as u16,
+        "h" => reg.h as u16,
+        "l" => reg.l as u16,
+        "af" => reg.af(),
+        "bc" => reg.bc(),
+        "de" => reg.de(),
+        "hl" => reg.hl(),
+        "sp" => reg.sp,
+        "pc" => reg.pc,
+        _ => return None,
+    };
+    Some(value as i64)
+}
+
+fn set_reg(reg: &mut Registers, name: &str, value: i64) -> bool {
+    let v8 = value as u8;
+    let v16 = value as u16;
+    match name {
+        "a" => reg.a = v8,
+        "f" => reg.set_af(((reg.a as u16) << 8) | v8 as u16),
+        "b" => reg.b = v8,
+        "c" => reg.c = v8,
+        "d" => reg.d = v8,
+        "e" => reg.e = v8,
+        "h" => reg.h = v8,
+        "l" => reg.l = v8,
+        "af" => reg.set_af(v16),
+        "bc" => reg.set_bc(v16),
+        "de" => reg.set_de(v16),
+        "hl" => reg.set_hl(v16),
+        "sp" => reg.sp = v16,
+        "pc" => reg.pc = v16,
+        _ => return false,
+    }
+    true
+}
+
+fn register_api(engine: &mut Engine, emu: &EmuPtr, state: &Arc<Mutex<ScriptState>>) {
+    let ptr = emu.clone();
+    engine.register_fn("read", move |addr: i64| -> i64 {
+        with_emu(&ptr, |emu| emu.mmu.direct_read(addr as u16 as usize) as i64)
+    });
+
+    let ptr = emu.clone();
+    engine.register_fn("write", move |addr: i64, value: i64| {
+        with_emu(&ptr, |emu| {
+            emu.mmu.direct_write(addr as u16 as usize, value as u8)
+        })
+    });
+
+    let ptr = emu.clone();
+    engine.register_fn(
+        "reg",
+        move |name: &str| -> Result<i64, Box<EvalAltResult>> {
+            with_emu(&ptr, |emu| get_reg(&emu.cpu.reg, name))
+                .ok_or_else(|| format!("unknown register: {}", name).into())
+        },
+    );
+
+    let ptr = emu.clone();
+    engine.register_fn(
+        "set_reg",
+        move |name: &str, value: i64| -> Result<(), Box<EvalAltResult>> {
+            if with_emu(&ptr, |emu| set_reg(&mut emu.cpu.reg, name, value)) {
+                Ok(())
+            } else {
+                Err(format!("unknown register: {}", name).into())
+            }
+        },
+    );
+
+    let ptr = emu.clone();
+    engine.register_fn("frame", move || -> i64 {
+        with_emu(&ptr, |emu| emu.mmu.ppu.frame_number as i64)
+    });
+
+    let st = state.clone();
+    engine.register_fn("add_breakpoint", move |addr: i64| {
+        st.lock().unwrap().breakpoints.insert(addr as u16);
+    });
+
+    let st = state.clone();
+    engine.register_fn("remove_breakpoint", move |addr: i64| {
+        st.lock().unwrap().breakpoints.remove(&(addr as u16));
+    });
+
+    let st = state.clone();
+    engine.register_fn("draw_text", move |x: i64, y: i64, text: &str| {
+        st.lock().unwrap().overlay.push(OverlayText {
+            x: x as f32,
+            y: y as f32,
+            text: text.to_string(),
+        });
+    });
+
+    let st = state.clone();
+    engine.on_print(move |s| st.lock().unwrap().print(s));
+
+    let st = state.clone();
+    engine.on_debug(move |s, _, pos| st.lock().unwrap().print(&format!("{:?}: {}", pos, s)));
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptHost {
+    // Create a host without a script. Code can still be evaluated
+    // from the console.
+    pub fn new() -> Self {
+        let state = Arc::new(Mutex::new(ScriptState {
+            breakpoints: HashSet::new(),
+            overlay: Vec::new(),
+            console: Vec::new(),
+        }));
+        let emu: EmuPtr = Arc::new(AtomicPtr::new(std::ptr::null_mut()));
+
+        let mut engine = Engine::new();
+        register_api(&mut engine, &emu, &state);
+
+        ScriptHost {
+            engine,
+            ast: AST::empty(),
+            scope: Scope::new(),
+            this: Map::new().into(),
+            emu,
+            state,
+            last_frame: None,
+            has_on_frame: false,
+        }
+    }
+
+    // Load and run a script, replacing the current one. Returns an
+    // error if the script fails to compile or run.
+    pub fn load(&mut self, filename: &str, emu: &mut Emu) -> Result<(), String> {
+        let ast = self
+            .engine
+            .compile_file(filename.into())
+            .map_err(|e| e.to_string())?;
+
+        self.has_on_frame = ast.iter_functions().any(|f| f.name == "on_frame");
+        let has_on_start = ast.iter_functions().any(|f| f.name == "on_start");
+
+        self.ast = ast;
+        self.scope = Scope::new();
+        self.this = Map::new().into();
+        self.last_frame = None;
+        {
+            let mut state = self.state.lock().unwrap();
+            state.breakpoints.clear();
+            state.overlay.clear();
+        }
+
+        let _ = self.run(emu, None)?;
+        if has_on_start {
+            self.call(emu, "on_start", ())?;
+        }
+
+        Ok(())
+    }
+
+    // Run the top level statements of the script, or of `code` if
+    // given, and return the value of the last one.
+    fn run(&mut self, emu: &mut Emu, code: Option<&str>) -> Result<Dynamic, String> {
+        let ast = match code {
+            Some(code) => {
+                let line = self.engine.compile(code).map_err(|e| e.to_string())?;
+                self.ast.clone_functions_only().merge(&line)
+            }
+            None => self.ast.clone(),
+        };
+
+        self.emu.store(emu, Ordering::Relaxed);
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut self.scope, &ast);
+        self.emu.store(std::ptr::null_mut(), Ordering::Relaxed);
+
+        result.map_err(|e| e.to_string())
+    }
+
+    fn call(&mut self, emu: &mut Emu, name: &str, args: impl rhai::FuncArgs) -> Result<(), String> {
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(false)
+            .bind_this_ptr(&mut self.this);
+
+        self.emu.store(emu, Ordering::Relaxed);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            args,
+        );
+        self.emu.store(std::ptr::null_mut(), Ordering::Relaxed);
+
+        result.map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    // Evaluate a line from the console and print the result
+    pub fn eval(&mut self, emu: &mut Emu, code: &str) {
+        self.state.lock().unwrap().print(&format!("> {}", code));
+        let output = match self.run(emu, Some(code)) {
+            Ok(value) if value.is_unit() => return,
+            Ok(value) => value.to_string(),
+            Err(e) => format!("Error: {}", e),
+        };
+        self.state.lock().unwrap().print(&output);
+    }
+
+    // Called before each instruction. Runs the frame and breakpoint
+    // callbacks. Errors in callbacks are printed to the console.
+    pub fn before_op(&mut self, emu: &mut Emu) {
+        let frame = emu.mmu.ppu.frame_number;
+        if self.last_frame != Some(frame) {
+            self.last_frame = Some(frame);
+            self.state.lock().unwrap().overlay.clear();
+            if self.has_on_frame {
+                if let Err(e) = self.call(emu, "on_frame", ()) {
+                    self.state.lock().unwrap().print(&format!("Error: {}", e));
+                }
+            }
+        }
+
+        let pc = emu.cpu.reg.pc;
+        if !emu.cpu.reg.halted && self.state.lock().unwrap().breakpoints.contains(&pc) {
+            if let Err(e) = self.call(emu, "on_breakpoint", (pc as i64,)) {
+                self.state.lock().unwrap().print(&format!("Error: {}", e));
+            }
+        }
+    }
+
+    pub fn console(&self) -> Vec<String> {
+        self.state.lock().unwrap().console.clone()
+    }
+
+    pub fn overlay(&self) -> Vec<OverlayText> {
+        self.state.lock().unwrap().overlay.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::Machine;
+
+    #[test]
+    fn test_script_callbacks() {
+        let filename = std::env::temp_dir().join("rustboy-test-script.rhai");
+        std::fs::write(
+            &filename,
+            r#"
+            fn on_start() {
+                this.hits = 0;
+                add_breakpoint(0xC000);
+            }
+            fn on_breakpoint(addr) {
+                this.hits += 1;
+                write(0xC100, this.hits);
+                draw_text(1, 2, `hits: ${this.hits}`);
+            }
+            "#,
+        )
+        .unwrap();
+
+        // Infinite loop: JP C000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.mmu.direct_write(0xC000, 0xC3);
+        emu.mmu.direct_write(0xC001, 0x00);
+        emu.mmu.direct_write(0xC002, 0xC0);
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut host = ScriptHost::new();
+        host.load(filename.to_str().unwrap(), &mut emu).unwrap();
+
+        for _ in 0..3 {
+            host.before_op(&mut emu);
+            emu.step();
+        }
+
+        assert_eq!(emu.mmu.direct_read(0xC100), 3);
+        assert_eq!(host.overlay().last().unwrap().text, "hits: 3");
+
+        host.eval(&mut emu, "reg(\"pc\") + read(0xC100)");
+        host.eval(&mut emu, "reg(\"xy\")");
+        let console = host.console();
+        assert_eq!(console[1], (0xC000 + 3).to_string());
+        assert!(console[3].starts_with("Error"));
+    }
+}
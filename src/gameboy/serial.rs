@@ -1,16 +1,43 @@
 use ringbuf::Producer;
 
+use super::emu::Machine;
+use super::interrupt::IF_SERIAL_BIT;
 use super::mmu::{SB_REG, SC_REG};
+use crate::savestate::{SaveState, Serializer};
 
-// This is a much simplified implementation of the serial transfer
-// functionality in Gameboy. It only supports writing to a ringbuf,
-// which can be used for easy monitoring of test roms etc.
+// Serial port
 //
-// Full functionality includes giving interrupt when transfer
-// finishes and shifting out bytes bit by bit, will simultaneously
-// shifting in bytes from the other endpoint.
+// A transfer shifts SB out one bit at a time, most significant bit
+// first, while simultaneously shifting in a bit from the other end.
+// When all eight bits have been shifted, bit 7 of SC is cleared and
+// the serial interrupt is requested.
+//
+// With the internal clock, a bit is shifted at 8192 Hz, on the falling
+// edge of bit 8 of the divider counter. The CGB can also shift at
+// 262144 Hz, on bit 3. With the external clock, bits are shifted by
+// the other end. Only devices driven by the Game Boy's clock can be
+// connected, such as the printer, so transfers with the external clock
+// never complete. With nothing connected, incoming bits are all 1.
+//
+// Every byte sent is also pushed to `output`, which is used for
+// monitoring test roms etc.
+
+// Divider counter bit that clocks the transfer
+const NORMAL_CLOCK_BIT: u16 = 1 << 8;
+const FAST_CLOCK_BIT: u16 = 1 << 3;
+
+// Something connected to the serial port, such as a printer. The
+// device is always driven by the Game Boy's internal clock.
+pub trait SerialDevice: Send {
+    // Called when a transfer starts, with the byte sent by the Game
+    // Boy. Returns the byte sent back, which is shifted in during the
+    // transfer.
+    fn exchange(&mut self, value: u8) -> u8;
+}
 
 pub struct Serial {
+    machine: Machine,
+
     // SB (0xFF01): Serial Transfer Data
     reg_sb: u8,
 
@@ -22,15 +49,39 @@ pub struct Serial {
     // Bit 0: shift clock (0 = external, 1 = internal)
     reg_sc: u8,
 
+    // Number of bits left to shift in the current transfer
+    bits_left: u8,
+
+    // Bits not yet shifted in, most significant first
+    incoming: u8,
+
+    // State of the clock bit at the previous update, to detect
+    // falling edges
+    clock: bool,
+
+    pub irq: u8,
+
     pub output: Option<Producer<u8>>,
+
+    pub device: Option<Box<dyn SerialDevice>>,
 }
 
 impl Serial {
-    pub fn new(output: Option<Producer<u8>>) -> Self {
+    pub fn new(
+        machine: Machine,
+        output: Option<Producer<u8>>,
+        device: Option<Box<dyn SerialDevice>>,
+    ) -> Self {
         Serial {
+            machine,
             reg_sb: 0,
             reg_sc: 0,
+            bits_left: 0,
+            incoming: 0xFF,
+            clock: false,
+            irq: 0,
             output,
+            device,
         }
     }
 
@@ -47,12 +98,49 @@ impl Serial {
             SB_REG => self.reg_sb = value,
             SC_REG => {
                 self.reg_sc = value;
-                self.send(self.reg_sb);
+                if value & 0x80 != 0 {
+                    self.bits_left = 8;
+                    self.send(self.reg_sb);
+                    self.incoming = match (value & 1, self.device.as_mut()) {
+                        (1, Some(device)) => device.exchange(self.reg_sb),
+                        _ => 0xFF,
+                    };
+                } else {
+                    self.bits_left = 0;
+                }
             }
             _ => panic!(),
         }
     }
 
+    fn clock_bit(&self) -> u16 {
+        match self.machine {
+            Machine::GameBoyCGB if self.reg_sc & 2 != 0 => FAST_CLOCK_BIT,
+            _ => NORMAL_CLOCK_BIT,
+        }
+    }
+
+    // Advance 4 cycles. `div_counter` is the 16-bit counter of the
+    // timer, which also drives the internal serial clock.
+    pub fn update_4t(&mut self, div_counter: u16) {
+        let clock = div_counter & self.clock_bit() != 0;
+        let falling_edge = self.clock && !clock;
+        self.clock = clock;
+
+        // Internal clock only
+        if !falling_edge || self.bits_left == 0 || self.reg_sc & 1 == 0 {
+            return;
+        }
+
+        self.reg_sb = (self.reg_sb << 1) | (self.incoming >> 7);
+        self.incoming <<= 1;
+        self.bits_left -= 1;
+        if self.bits_left == 0 {
+            self.reg_sc &= 0x7F;
+            self.irq = IF_SERIAL_BIT;
+        }
+    }
+
     fn send(&mut self, value: u8) {
         // Pushes SB register to output buffer, or prints
         // to stdout if no output buffer available.
@@ -62,3 +150,96 @@ impl Serial {
         }
     }
 }
+
+impl SaveState for Serial {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u8(&mut self.reg_sb);
+        s.u8(&mut self.reg_sc);
+        s.u8(&mut self.bits_left);
+        s.u8(&mut self.incoming);
+        s.bool(&mut self.clock);
+        s.u8(&mut self.irq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run until the transfer completes. Returns the number of cycles.
+    fn run_transfer(serial: &mut Serial, div_counter: &mut u16) -> usize {
+        let mut cycles = 0;
+        while serial.read_reg(SC_REG) & 0x80 != 0 {
+            *div_counter = div_counter.wrapping_add(4);
+            serial.update_4t(*div_counter);
+            cycles += 4;
+            assert!(cycles < 10000);
+        }
+        cycles
+    }
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let (producer, mut consumer) = ringbuf::RingBuffer::new(4).split();
+        let mut serial = Serial::new(Machine::GameBoyDMG, Some(producer), None);
+        let mut div_counter = 0;
+
+        serial.write_reg(SB_REG, 0x42);
+        serial.write_reg(SC_REG, 0x81);
+        assert_eq!(consumer.pop(), Some(0x42));
+
+        // A bit every 512 cycles, starting with the first falling edge
+        assert_eq!(run_transfer(&mut serial, &mut div_counter), 8 * 512);
+        assert_eq!(serial.read_reg(SB_REG), 0xFF);
+        assert_eq!(serial.irq, IF_SERIAL_BIT);
+
+        // Fast clock is ignored on DMG
+        serial.irq = 0;
+        serial.write_reg(SC_REG, 0x83);
+        assert_eq!(run_transfer(&mut serial, &mut div_counter), 8 * 512);
+
+        let mut serial = Serial::new(Machine::GameBoyCGB, None, None);
+        serial.write_reg(SC_REG, 0x83);
+        let mut div_counter = 0;
+        assert_eq!(run_transfer(&mut serial, &mut div_counter), 8 * 16);
+    }
+
+    #[test]
+    fn test_external_clock_never_completes() {
+        let mut serial = Serial::new(Machine::GameBoyDMG, None, None);
+        serial.write_reg(SB_REG, 0x42);
+        serial.write_reg(SC_REG, 0x80);
+        for n in 0..10000u16 {
+            serial.update_4t(n.wrapping_mul(4));
+        }
+        assert_eq!(serial.read_reg(SC_REG), 0x80);
+        assert_eq!(serial.read_reg(SB_REG), 0x42);
+        assert_eq!(serial.irq, 0);
+    }
+
+    struct Inverter;
+
+    impl SerialDevice for Inverter {
+        fn exchange(&mut self, value: u8) -> u8 {
+            !value
+        }
+    }
+
+    #[test]
+    fn test_device() {
+        let mut serial = Serial::new(Machine::GameBoyDMG, None, Some(Box::new(Inverter)));
+        let mut div_counter = 0;
+        serial.write_reg(SB_REG, 0x42);
+        serial.write_reg(SC_REG, 0x81);
+
+        // Bits are shifted in one at a time
+        while serial.bits_left > 4 {
+            div_counter += 4;
+            serial.update_4t(div_counter);
+        }
+        assert_eq!(serial.read_reg(SB_REG), 0x2B);
+
+        run_transfer(&mut serial, &mut div_counter);
+        assert_eq!(serial.read_reg(SB_REG), 0xBD);
+    }
+}
@@ -1,30 +1,61 @@
 // References:
 // http://gbdev.gg8.se/wiki/articles/Timer_and_Divider_Registers
 // http://gbdev.gg8.se/wiki/articles/Timer_Obscure_Behaviour
+//
+// The timer is not updated cycle by cycle. Instead, DIV and TIMA are
+// computed from the absolute cycle count when needed:
+//
+// - The internal 16-bit counter (DIV is the top 8 bits) is the number
+//   of cycles since the counter was last reset by a write to DIV.
+//
+// - TIMA is incremented on the falling edge of one of the counter
+//   bits (selected by TAC), ANDed with the timer enable bit. Between
+//   writes to DIV, TIMA or TAC, the number of increments is simply the
+//   number of times the counter passed a multiple of twice the selected
+//   bit. TIMA is only brought up to date ("synced") when the registers
+//   are written, or when TIMA overflows.
+//
+// - The cycle of the next overflow is calculated in advance, so that
+//   the interrupt can be requested on exactly the right cycle without
+//   any work being done for the cycles in between.
+//
+// Writes to DIV and TAC may cause the selected bit to go from high to
+// low without the counter passing a multiple of its period. This is
+// handled by comparing the state of the bit before and after each
+// write, which is how the obscure behaviours described in the
+// references above appear on real hardware.
+//
+// When TIMA overflows, it's not reloaded right away. For 4 cycles it
+// holds zero, and then it's reloaded from TMA and the interrupt is
+// requested. A write to TIMA during those 4 cycles cancels both. On
+// the cycle of the reload, writes to TIMA are ignored, and writes to
+// TMA are copied to TIMA as well.
 
 use super::interrupt::IF_TMR_BIT;
+use crate::savestate::{SaveState, Serializer};
 
 const CLOCK_SELECTION: [u16; 4] = [512, 8, 32, 128];
 
 const TAC_ENABLE_BIT: u8 = 4;
 
 pub struct Timer {
-    // Absolute cycle count since start of emulator.
-    // This is only used for statistics and debugging.
-    // It is not used by the emulator.
+    // Absolute cycle count since start of emulator
     pub abs_cycle: u64,
 
-    // The internal 16-bit counter. DIV is the top 8 bits.
-    pub cycle: u16,
+    // Absolute cycle when the internal counter was last reset
+    div_base: u64,
+
+    // Absolute cycle when TIMA was last brought up to date
+    sync_cycle: u64,
 
-    // TIMA is incremented when one specific bit goes
-    // from high to low. Therefore we need to store
-    // the previous cycle to compare with, because
-    // the bit might have gone low because DIV has
-    // been written to, and TIMA should be incremented
-    // in that case as well.
-    pub prev_bit_state: bool,
-    pub prev_cycle: u16,
+    // Absolute cycle of the next TIMA overflow, or u64::MAX
+    // if the timer is disabled
+    next_overflow: u64,
+
+    // Absolute cycle when TIMA will be reloaded from TMA, after an
+    // overflow, and the cycle of the last reload
+    reload_cycle: Option<u64>,
+    last_reload: Option<u64>,
 
     // TAC register: controller register
     // Bit 2: 0 = stop timer, 1 = start timer
@@ -37,10 +68,10 @@ pub struct Timer {
     // 11: 16 384 Hz
     pub tac: u8,
 
-    // TIMA register: timer counter
+    // TIMA register: timer counter, as of `sync_cycle`.
     // When TIMA overflows an interrupt is generated and
     // TIMA is reset to the value of TMA
-    pub tima: u8,
+    tima: u8,
 
     // TMA register: reset value of TIMA
     pub tma: u8,
@@ -55,9 +86,11 @@ impl Timer {
     pub fn new() -> Self {
         Timer {
             abs_cycle: 0,
-            cycle: 0,
-            prev_cycle: 0,
-            prev_bit_state: false,
+            div_base: 0,
+            sync_cycle: 0,
+            next_overflow: u64::MAX,
+            reload_cycle: None,
+            last_reload: None,
             tac: 0,
             tima: 0,
             tma: 0,
@@ -66,40 +99,427 @@ impl Timer {
         }
     }
 
+    // The counter bit that TIMA is clocked by, or None if
+    // the timer is disabled
+    fn selected_bit(&self) -> Option<u64> {
+        if self.tac & TAC_ENABLE_BIT != 0 {
+            Some(CLOCK_SELECTION[(self.tac & 3) as usize] as u64)
+        } else {
+            None
+        }
+    }
+
+    // Number of cycles since the counter was reset. Not wrapped
+    // to 16 bits, so that it can be used to count edges.
+    fn counter_at(&self, cycle: u64) -> u64 {
+        cycle - self.div_base
+    }
+
+    // The internal 16-bit counter. DIV is the top 8 bits.
+    pub fn div_counter(&self) -> u16 {
+        self.counter_at(self.abs_cycle) as u16
+    }
+
+    // The input to the TIMA falling edge detector
+    fn signal(&self) -> bool {
+        match self.selected_bit() {
+            Some(bit) => self.counter_at(self.abs_cycle) & bit != 0,
+            None => false,
+        }
+    }
+
+    // Number of falling edges of the selected bit since the last sync
+    fn edges_since_sync(&self) -> u64 {
+        match self.selected_bit() {
+            Some(bit) => {
+                let period = bit * 2;
+                self.counter_at(self.abs_cycle) / period - self.counter_at(self.sync_cycle) / period
+            }
+            None => 0,
+        }
+    }
+
+    // TIMA overflowed on the current cycle
+    fn overflow(&mut self) {
+        self.tima = 0;
+        self.reload_cycle = Some(self.abs_cycle + 4);
+    }
+
+    fn reload(&mut self) {
+        self.tima = self.tma;
+        self.irq |= IF_TMR_BIT;
+        self.reload_cycle = None;
+        self.last_reload = Some(self.abs_cycle);
+    }
+
+    fn increment_tima(&mut self) {
+        if self.tima == 0xFF {
+            self.overflow();
+        } else {
+            self.tima += 1;
+        }
+    }
+
+    // Bring TIMA up to date with the current cycle
+    fn sync(&mut self) {
+        let mut edges = self.edges_since_sync();
+
+        while edges > 0 {
+            let until_overflow = 0x100 - self.tima as u64;
+            if edges == until_overflow {
+                // TIMA is synced on every overflow, so the last
+                // edge is on the current cycle
+                edges = 0;
+                self.overflow();
+            } else if edges > until_overflow {
+                edges -= until_overflow;
+                self.irq |= IF_TMR_BIT;
+                self.tima = self.tma;
+            } else {
+                self.tima += edges as u8;
+                edges = 0;
+            }
+        }
+
+        self.sync_cycle = self.abs_cycle;
+    }
+
+    // Calculate the cycle of the next TIMA overflow. Must be called
+    // after every sync.
+    fn schedule(&mut self) {
+        self.next_overflow = match self.selected_bit() {
+            Some(bit) => {
+                let period = bit * 2;
+                let edges = 0x100 - self.tima as u64;
+                let counter = self.counter_at(self.sync_cycle);
+                self.div_base + (counter / period + edges) * period
+            }
+            None => u64::MAX,
+        };
+    }
+
+    // Apply a register write. If the write makes the edge detector
+    // input go low, TIMA is incremented.
+    fn write_with_glitch<F: FnOnce(&mut Timer)>(&mut self, write: F) {
+        self.sync();
+        let before = self.signal();
+        write(self);
+        if before && !self.signal() {
+            self.increment_tima();
+        }
+        self.schedule();
+    }
+
     pub fn write_div(&mut self, _value: u8) {
         // Value is ignored: no matter what value is written
         // the cycle counter is always reset to zero
-        self.cycle = 0;
+        self.write_with_glitch(|timer| {
+            timer.div_base = timer.abs_cycle;
+            timer.sync_cycle = timer.abs_cycle;
+        });
     }
 
     pub fn read_div(&self) -> u8 {
-        (self.cycle >> 8) as u8
+        (self.div_counter() >> 8) as u8
+    }
+
+    pub fn write_tac(&mut self, value: u8) {
+        self.write_with_glitch(|timer| timer.tac = value);
+    }
+
+    pub fn write_tima(&mut self, value: u8) {
+        self.sync();
+        if self.last_reload != Some(self.abs_cycle) {
+            self.tima = value;
+            self.reload_cycle = None;
+        }
+        self.schedule();
+    }
+
+    pub fn read_tima(&self) -> u8 {
+        // There is never an overflow between the last sync
+        // and the current cycle, so this can't wrap
+        self.tima + self.edges_since_sync() as u8
+    }
+
+    pub fn write_tma(&mut self, value: u8) {
+        self.tma = value;
+        if self.last_reload == Some(self.abs_cycle) {
+            self.sync();
+            self.tima = value;
+            self.schedule();
+        }
     }
 
     pub fn update_4t(&mut self) {
         self.abs_cycle = self.abs_cycle.wrapping_add(4);
-        self.cycle = self.cycle.wrapping_add(4);
 
-        let bit = if self.tac & TAC_ENABLE_BIT != 0 {
-            CLOCK_SELECTION[(self.tac & 3) as usize]
-        } else {
-            0
-        };
+        if self.reload_cycle == Some(self.abs_cycle) {
+            self.sync();
+            self.reload();
+            self.schedule();
+        }
+
+        if self.abs_cycle >= self.next_overflow {
+            self.sync();
+            self.schedule();
+        }
+    }
+}
+
+impl SaveState for Timer {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.u64(&mut self.abs_cycle);
+        s.u64(&mut self.div_base);
+        s.u64(&mut self.sync_cycle);
+        s.u64(&mut self.next_overflow);
+        s.option_u64(&mut self.reload_cycle);
+        s.option_u64(&mut self.last_reload);
+        s.u8(&mut self.tac);
+        s.u8(&mut self.tima);
+        s.u8(&mut self.tma);
+        s.u8(&mut self.irq);
+    }
+}
 
-        // Note that since this function is called every
-        // 4'th T-cycle, the cycle count is always divisible
-        // by 4 and so are all the possible clock selections.
-        let bit_state = self.cycle & bit != 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if self.prev_bit_state && !bit_state {
-            if self.tima == 0xFF {
+    // Straightforward implementation of the timer that updates
+    // the counter and the falling edge detector every 4 cycles
+    // and after every write. Used as reference for `Timer`.
+    struct ReferenceTimer {
+        counter: u16,
+        signal: bool,
+        tac: u8,
+        tima: u8,
+        tma: u8,
+        irq: u8,
+        reload: bool,
+    }
+
+    impl ReferenceTimer {
+        fn new() -> Self {
+            ReferenceTimer {
+                counter: 0,
+                signal: false,
+                tac: 0,
+                tima: 0,
+                tma: 0,
+                irq: 0,
+                reload: false,
+            }
+        }
+
+        fn update_signal(&mut self) {
+            let signal = self.tac & TAC_ENABLE_BIT != 0
+                && self.counter & CLOCK_SELECTION[(self.tac & 3) as usize] != 0;
+            if self.signal && !signal {
+                if self.tima == 0xFF {
+                    self.tima = 0;
+                    self.reload = true;
+                } else {
+                    self.tima += 1;
+                }
+            }
+            self.signal = signal;
+        }
+
+        fn update_4t(&mut self) {
+            self.counter = self.counter.wrapping_add(4);
+            if self.reload {
+                self.reload = false;
                 self.irq |= IF_TMR_BIT;
                 self.tima = self.tma;
-            } else {
-                self.tima = self.tima + 1;
             }
+            self.update_signal();
+        }
+
+        fn write_div(&mut self) {
+            self.counter = 0;
+            self.update_signal();
+        }
+
+        fn write_tac(&mut self, value: u8) {
+            self.tac = value;
+            self.update_signal();
+        }
+    }
+
+    fn run(timer: &mut Timer, reference: &mut ReferenceTimer, cycles: usize) {
+        for _ in 0..cycles / 4 {
+            timer.update_4t();
+            reference.update_4t();
+            assert_eq!(timer.read_div(), (reference.counter >> 8) as u8);
+            assert_eq!(timer.read_tima(), reference.tima);
+            assert_eq!(timer.irq, reference.irq);
+        }
+    }
+
+    #[test]
+    fn test_div_increments_every_256_cycles() {
+        let mut timer = Timer::new();
+        for n in 0..1024 {
+            assert_eq!(timer.read_div(), (n / 64) as u8);
+            timer.update_4t();
+        }
+    }
+
+    #[test]
+    fn test_tima_clock_selection() {
+        for (clock, period) in [(0, 1024), (1, 16), (2, 64), (3, 256)] {
+            let mut timer = Timer::new();
+            timer.write_tac(TAC_ENABLE_BIT | clock);
+            for _ in 0..(period / 4 - 1) {
+                timer.update_4t();
+            }
+            assert_eq!(timer.read_tima(), 0);
+            timer.update_4t();
+            assert_eq!(timer.read_tima(), 1);
         }
+    }
+
+    #[test]
+    fn test_tima_overflow() {
+        let mut timer = Timer::new();
+        timer.write_tma(0x42);
+        timer.write_tima(0xFE);
+        timer.write_tac(TAC_ENABLE_BIT | 1);
 
-        self.prev_bit_state = bit_state;
+        for _ in 0..4 {
+            timer.update_4t();
+        }
+        assert_eq!(timer.read_tima(), 0xFF);
+        assert_eq!(timer.irq, 0);
+
+        // TIMA is zero for 4 cycles before it's reloaded
+        for _ in 0..4 {
+            timer.update_4t();
+        }
+        assert_eq!(timer.read_tima(), 0);
+        assert_eq!(timer.irq, 0);
+
+        timer.update_4t();
+        assert_eq!(timer.read_tima(), 0x42);
+        assert_eq!(timer.irq, IF_TMR_BIT);
+    }
+
+    // Run until TIMA has just overflowed, and is about to be reloaded
+    fn overflowed_timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.write_tma(0x42);
+        timer.write_tima(0xFF);
+        timer.write_tac(TAC_ENABLE_BIT | 1);
+        for _ in 0..4 {
+            timer.update_4t();
+        }
+        assert_eq!(timer.read_tima(), 0);
+        timer
+    }
+
+    #[test]
+    fn test_tima_write_cancels_reload() {
+        let mut timer = overflowed_timer();
+        timer.write_tima(0x10);
+        timer.update_4t();
+        assert_eq!(timer.read_tima(), 0x10);
+        assert_eq!(timer.irq, 0);
+    }
+
+    #[test]
+    fn test_writes_on_reload_cycle() {
+        // TIMA writes are ignored
+        let mut timer = overflowed_timer();
+        timer.update_4t();
+        timer.write_tima(0x10);
+        assert_eq!(timer.read_tima(), 0x42);
+        assert_eq!(timer.irq, IF_TMR_BIT);
+
+        // TMA writes go to TIMA as well, but only on that cycle
+        timer.write_tma(0x20);
+        assert_eq!(timer.read_tima(), 0x20);
+        timer.update_4t();
+        timer.write_tma(0x30);
+        assert_eq!(timer.read_tima(), 0x20);
+    }
+
+    #[test]
+    fn test_tima_write() {
+        let mut timer = Timer::new();
+        timer.write_tac(TAC_ENABLE_BIT | 1);
+        for _ in 0..12 {
+            timer.update_4t();
+        }
+        assert_eq!(timer.read_tima(), 3);
+        timer.write_tima(0x80);
+        assert_eq!(timer.read_tima(), 0x80);
+        for _ in 0..4 {
+            timer.update_4t();
+        }
+        assert_eq!(timer.read_tima(), 0x81);
+    }
+
+    #[test]
+    fn test_div_write_with_selected_bit_high() {
+        // With 262 144 Hz selected, bit 3 of the counter is used.
+        // Resetting the counter while the bit is high increments TIMA.
+        let mut timer = Timer::new();
+        timer.write_tac(TAC_ENABLE_BIT | 1);
+        timer.update_4t();
+        timer.update_4t();
+        assert_eq!(timer.read_tima(), 0);
+        timer.write_div(0);
+        assert_eq!(timer.read_tima(), 1);
+    }
+
+    #[test]
+    fn test_tac_disable_with_selected_bit_high() {
+        let mut timer = Timer::new();
+        timer.write_tac(TAC_ENABLE_BIT | 1);
+        timer.update_4t();
+        timer.update_4t();
+        timer.write_tac(1);
+        assert_eq!(timer.read_tima(), 1);
+    }
+
+    #[test]
+    fn test_div_write_all_phases() {
+        for tac in 0..8 {
+            for phase in (0..2048).step_by(4) {
+                let mut timer = Timer::new();
+                let mut reference = ReferenceTimer::new();
+                timer.write_tma(0xF0);
+                reference.tma = 0xF0;
+                timer.write_tac(tac);
+                reference.write_tac(tac);
+
+                run(&mut timer, &mut reference, phase);
+                timer.write_div(0);
+                reference.write_div();
+                assert_eq!(timer.read_tima(), reference.tima);
+                run(&mut timer, &mut reference, 2048);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tac_change_all_combinations() {
+        for old_tac in 0..8 {
+            for new_tac in 0..8 {
+                for phase in (0..1024).step_by(4) {
+                    let mut timer = Timer::new();
+                    let mut reference = ReferenceTimer::new();
+                    timer.write_tac(old_tac);
+                    reference.write_tac(old_tac);
+
+                    run(&mut timer, &mut reference, phase);
+                    timer.write_tac(new_tac);
+                    reference.write_tac(new_tac);
+                    assert_eq!(timer.read_tima(), reference.tima);
+                    run(&mut timer, &mut reference, 1024);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,136 @@
+// Per-subsystem timing
+//
+// With the "timing" feature enabled, the wall-clock time spent in the
+// CPU, PPU, APU and MMU dispatch is accumulated for each frame, to show
+// where emulation time goes. Without the feature, all measurements
+// compile to nothing.
+//
+// The CPU time is what remains of the total time of each instruction
+// after the other subsystems have been deducted. The timer is updated
+// together with the APU, and is counted as part of it. OAM DMA is
+// counted as part of the MMU.
+
+#[derive(Clone, Copy)]
+pub enum Subsystem {
+    Ppu = 0,
+    Apu = 1,
+    Mmu = 2,
+}
+
+pub const SUBSYSTEM_NAMES: [&str; 4] = ["CPU", "PPU", "APU", "MMU"];
+
+#[cfg(feature = "timing")]
+mod imp {
+    use std::time::{Duration, Instant};
+
+    use super::Subsystem;
+
+    pub type Stamp = Instant;
+
+    #[derive(Default)]
+    pub struct SubsystemTiming {
+        // Time of the current and the last complete frame, indexed by
+        // `Subsystem` + 1. Index 0 is the total time.
+        current: [Duration; 4],
+        last_frame: Option<[Duration; 4]>,
+        frame_number: usize,
+
+        // Sum of all complete frames
+        total: [Duration; 4],
+    }
+
+    // Deduct the other subsystems from the total, which gives the time
+    // of the CPU
+    fn split(times: [Duration; 4]) -> [Duration; 4] {
+        let others = times[1] + times[2] + times[3];
+        [
+            times[0].saturating_sub(others),
+            times[1],
+            times[2],
+            times[3],
+        ]
+    }
+
+    impl SubsystemTiming {
+        pub fn new() -> Self {
+            SubsystemTiming::default()
+        }
+
+        #[inline(always)]
+        pub fn start(&self) -> Stamp {
+            Instant::now()
+        }
+
+        #[inline(always)]
+        pub fn stop(&mut self, subsystem: Subsystem, start: Stamp) {
+            self.current[subsystem as usize + 1] += start.elapsed();
+        }
+
+        // Add the total time of an instruction. Ends the frame if
+        // `frame_number` has changed.
+        #[inline(always)]
+        pub fn end_op(&mut self, start: Stamp, frame_number: usize) {
+            self.current[0] += start.elapsed();
+
+            if frame_number != self.frame_number {
+                self.frame_number = frame_number;
+                for (total, time) in self.total.iter_mut().zip(self.current.iter()) {
+                    *total += *time;
+                }
+                self.last_frame = Some(self.current);
+                self.current = [Duration::ZERO; 4];
+            }
+        }
+
+        // Time spent in each subsystem during the last complete frame,
+        // in the order of SUBSYSTEM_NAMES
+        pub fn last_frame(&self) -> Option<[Duration; 4]> {
+            self.last_frame.map(split)
+        }
+
+        // Time spent in each subsystem during all complete frames
+        pub fn total(&self) -> Option<[Duration; 4]> {
+            Some(split(self.total))
+        }
+    }
+}
+
+#[cfg(not(feature = "timing"))]
+mod imp {
+    use std::time::Duration;
+
+    use super::Subsystem;
+
+    #[derive(Clone, Copy)]
+    pub struct Stamp;
+
+    #[derive(Default)]
+    pub struct SubsystemTiming {}
+
+    impl SubsystemTiming {
+        pub fn new() -> Self {
+            SubsystemTiming {}
+        }
+
+        #[inline(always)]
+        pub fn start(&self) -> Stamp {
+            Stamp
+        }
+
+        #[inline(always)]
+        pub fn stop(&mut self, _subsystem: Subsystem, _start: Stamp) {}
+
+        #[inline(always)]
+        pub fn end_op(&mut self, _start: Stamp, _frame_number: usize) {}
+
+        pub fn last_frame(&self) -> Option<[Duration; 4]> {
+            None
+        }
+
+        pub fn total(&self) -> Option<[Duration; 4]> {
+            None
+        }
+    }
+}
+
+pub use imp::{Stamp, SubsystemTiming};
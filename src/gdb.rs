@@ -0,0 +1,375 @@
+// GDB remote serial protocol stub
+//
+// Lets GDB, or an IDE that talks to GDB servers, debug the emulated
+// CPU over TCP. Start the emulator with `--gdb <port>` and connect
+// with `target remote localhost:<port>`.
+//
+// Supported: reading and writing registers and memory, software and
+// hardware breakpoints (both are handled the same way), single step,
+// continue, and interrupting execution with Ctrl-C.
+//
+// GDB has no built-in support for the Game Boy CPU, so the register
+// layout is the one used by the Z80 targets: AF, BC, DE, HL, SP and
+// PC, 16 bits each, little endian. Memory is the 16-bit address space
+// as seen by the CPU, with the currently selected banks.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::gameboy::emu::Emu;
+use crate::utils::parse_hex_bytes;
+
+// Number of registers in the `g` packet
+const REGISTER_COUNT: usize = 6;
+
+// Number of instructions to execute between checks for Ctrl-C
+const INTERRUPT_CHECK_INTERVAL: usize = 4096;
+
+// Reply sent when execution stops: signal 5 (SIGTRAP)
+const STOP_REPLY: &str = "S05";
+
+enum Response {
+    Reply(String),
+    Resume { step: bool },
+    Detach,
+}
+
+struct GdbStub<'a> {
+    emu: &'a mut Emu,
+    breakpoints: HashSet<u16>,
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+// Parse "addr,len" with hexadecimal numbers
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    let (addr, len) = (parse_hex(addr)?, parse_hex(len)?);
+    match addr.checked_add(len) {
+        Some(end) if end <= 0x10000 => Some((addr, len)),
+        _ => None,
+    }
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, b| sum.wrapping_add(b))
+}
+
+impl<'a> GdbStub<'a> {
+    fn new(emu: &'a mut Emu) -> Self {
+        GdbStub {
+            emu,
+            breakpoints: HashSet::new(),
+        }
+    }
+
+    fn registers(&self) -> [u16; REGISTER_COUNT] {
+        let reg = &self.emu.cpu.reg;
+        [reg.af(), reg.bc(), reg.de(), reg.hl(), reg.sp, reg.pc]
+    }
+
+    fn set_register(&mut self, n: usize, value: u16) -> bool {
+        let reg = &mut self.emu.cpu.reg;
+        match n {
+            0 => reg.set_af(value),
+            1 => reg.set_bc(value),
+            2 => reg.set_de(value),
+            3 => reg.set_hl(value),
+            4 => reg.sp = value,
+            5 => reg.pc = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn handle(&mut self, packet: &str) -> Response {
+        let first = packet.chars().next().map_or(0, char::len_utf8);
+        let (command, args) = packet.split_at(first);
+        let reply = |s: &str| Response::Reply(s.to_string());
+
+        match command {
+            "?" => reply(STOP_REPLY),
+
+            "g" => Response::Reply(
+                self.registers()
+                    .iter()
+                    .map(|r| format!("{:02x}{:02x}", r & 0xFF, r >> 8))
+                    .collect(),
+            ),
+
+            "G" => match parse_hex_bytes(args) {
+                Some(bytes) if bytes.len() == REGISTER_COUNT * 2 => {
+                    for (n, b) in bytes.chunks(2).enumerate() {
+                        self.set_register(n, u16::from_le_bytes([b[0], b[1]]));
+                    }
+                    reply("OK")
+                }
+                _ => reply("E01"),
+            },
+
+            "p" => match parse_hex(args).filter(|n| *n < REGISTER_COUNT) {
+                Some(n) => {
+                    let r = self.registers()[n];
+                    Response::Reply(format!("{:02x}{:02x}", r & 0xFF, r >> 8))
+                }
+                None => reply("E01"),
+            },
+
+            "P" => {
+                let parsed = args
+                    .split_once('=')
+                    .and_then(|(n, v)| Some((parse_hex(n)?, parse_hex_bytes(v)?)));
+                match parsed {
+                    Some((n, v))
+                        if v.len() == 2
+                            && self.set_register(n, u16::from_le_bytes([v[0], v[1]])) =>
+                    {
+                        reply("OK")
+                    }
+                    _ => reply("E01"),
+                }
+            }
+
+            "m" => match parse_range(args) {
+                Some((addr, len)) => Response::Reply(
+                    (addr..addr + len)
+                        .map(|a| format!("{:02x}", self.emu.mmu.direct_read(a)))
+                        .collect(),
+                ),
+                None => reply("E01"),
+            },
+
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, parse_hex_bytes(data)?)));
+                match parsed {
+                    Some(((addr, len), data)) if data.len() == len => {
+                        for (i, b) in data.iter().enumerate() {
+                            self.emu.mmu.direct_write(addr + i, *b);
+                        }
+                        reply("OK")
+                    }
+                    _ => reply("E01"),
+                }
+            }
+
+            "c" | "s" => {
+                if let Some(addr) = parse_hex(args) {
+                    self.emu.cpu.reg.pc = addr as u16;
+                }
+                Response::Resume {
+                    step: command == "s",
+                }
+            }
+
+            // Software and hardware breakpoints: "Z0,addr,kind"
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let addr = parts.next().and_then(parse_hex);
+                match (kind, addr) {
+                    (Some("0") | Some("1"), Some(addr)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(addr as u16);
+                        } else {
+                            self.breakpoints.remove(&(addr as u16));
+                        }
+                        reply("OK")
+                    }
+                    // Watchpoints are not supported
+                    _ => reply(""),
+                }
+            }
+
+            "q" if args.starts_with("Supported") => reply("PacketSize=1000"),
+            "q" if args == "Attached" => reply("1"),
+            "D" | "k" => Response::Detach,
+
+            // An empty reply means that the command is not supported
+            _ => reply(""),
+        }
+    }
+
+    // Execute until a breakpoint is reached or `interrupted` returns
+    // true, or a single instruction if `step` is true.
+    fn resume(&mut self, step: bool, mut interrupted: impl FnMut() -> bool) -> String {
+        let mut count: usize = 0;
+        loop {
+            self.emu.step();
+
+            if step || self.breakpoints.contains(&self.emu.cpu.reg.pc) {
+                break;
+            }
+
+            count += 1;
+            if count.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && interrupted() {
+                break;
+            }
+        }
+        STOP_REPLY.to_string()
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0; 1];
+        match self.stream.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+
+    // Read the next packet and acknowledge it. Returns None if the
+    // connection was closed.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip everything before the start of the packet, such as
+            // acknowledgements and Ctrl-C received while stopped
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                    None => return Ok(None),
+                }
+            }
+
+            let mut sum = [0; 2];
+            self.stream.read_exact(&mut sum)?;
+
+            let data = String::from_utf8_lossy(&data).to_string();
+            let expected = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+
+            if expected == Some(checksum(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum(data))?;
+        self.stream.flush()
+    }
+
+    // Check, without blocking, if Ctrl-C has been received
+    fn interrupted(&mut self) -> bool {
+        let mut buf = [0; 1];
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let result = self.stream.read(&mut buf);
+        let _ = self.stream.set_nonblocking(false);
+        matches!(result, Ok(1) if buf[0] == 0x03)
+    }
+}
+
+// Wait for a connection on `port` and serve it until GDB detaches
+pub fn run_gdb_stub(emu: &mut Emu, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for GDB to connect on port {}", port);
+    let (stream, addr) = listener.accept()?;
+    println!("GDB connected from {}", addr);
+
+    stream.set_nodelay(true)?;
+    let mut conn = Connection { stream };
+    let mut stub = GdbStub::new(emu);
+
+    while let Some(packet) = conn.read_packet()? {
+        match stub.handle(&packet) {
+            Response::Reply(s) => conn.write_packet(&s)?,
+            Response::Resume { step } => {
+                let reply = stub.resume(step, || conn.interrupted());
+                conn.write_packet(&reply)?;
+            }
+            Response::Detach => {
+                conn.write_packet("OK")?;
+                break;
+            }
+        }
+    }
+
+    println!("GDB disconnected");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::Machine;
+
+    fn reply(stub: &mut GdbStub, packet: &str) -> String {
+        match stub.handle(packet) {
+            Response::Reply(s) => s,
+            _ => panic!("expected reply to {}", packet),
+        }
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        let mut stub = GdbStub::new(&mut emu);
+
+        assert_eq!(reply(&mut stub, "P5=3412"), "OK");
+        assert_eq!(reply(&mut stub, "p5"), "3412");
+        assert_eq!(&reply(&mut stub, "g")[20..24], "3412");
+
+        assert_eq!(reply(&mut stub, "Mc000,3:0102ff"), "OK");
+        assert_eq!(reply(&mut stub, "mc000,3"), "0102ff");
+        assert_eq!(reply(&mut stub, "mffff,2"), "E01");
+        assert_eq!(reply(&mut stub, "m10,ffffffffffffffff"), "E01");
+        assert_eq!(reply(&mut stub, "Mc000,2:a\u{e9}b"), "E01");
+        assert_eq!(reply(&mut stub, "\u{e9}"), "");
+        assert_eq!(reply(&mut stub, "vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn test_breakpoint_and_step() {
+        // NOP, NOP, JP 0xC000
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        for (i, b) in [0x00, 0x00, 0xC3, 0x00, 0xC0].iter().enumerate() {
+            emu.mmu.direct_write(0xC000 + i, *b);
+        }
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut stub = GdbStub::new(&mut emu);
+        assert!(matches!(stub.handle("s"), Response::Resume { step: true }));
+        stub.resume(true, || false);
+        assert_eq!(stub.emu.cpu.reg.pc, 0xC001);
+
+        assert_eq!(reply(&mut stub, "Z0,c002,1"), "OK");
+        assert_eq!(stub.resume(false, || false), STOP_REPLY);
+        assert_eq!(stub.emu.cpu.reg.pc, 0xC002);
+
+        // Continuing from a breakpoint runs until it's reached again
+        stub.resume(false, || false);
+        assert_eq!(stub.emu.cpu.reg.pc, 0xC002);
+
+        assert_eq!(reply(&mut stub, "z0,c002,1"), "OK");
+        assert_eq!(stub.resume(false, || true), STOP_REPLY);
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum("OK"), 0x9A);
+    }
+}
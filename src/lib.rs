@@ -1,16 +1,37 @@
 extern crate ctrlc;
 extern crate num_traits;
 extern crate png;
+#[cfg(feature = "gui")]
 extern crate winit;
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 #[macro_use]
 pub mod macros;
 
+pub mod about;
+pub mod bench;
+#[cfg(feature = "gui")]
+pub mod config;
 pub mod conv;
 pub mod core;
 pub mod debug;
+pub mod frame_hash;
+pub mod frame_sink;
 pub mod gameboy;
+pub mod gdb;
+pub mod memory_dump;
+pub mod palette;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod replay_buffer;
+pub mod savestate;
+pub mod server;
+pub mod sm83_json;
+pub mod symbols;
 pub mod test_runner;
+pub mod thumbnails;
+#[cfg(feature = "gui")]
 pub mod ui;
 pub mod utils;
 pub mod wave_audio_recorder;
@@ -18,3 +39,37 @@ pub mod wave_audio_recorder;
 pub const APPNAME: &str = "Rustboy?";
 pub const VERSION: &str = "0.0.0";
 pub const AUTHOR: &str = "Jonatan Magnusson <jonatan.magnusson@gmail.com>";
+
+// In strict mode, accesses to unhandled addresses panic instead of
+// being logged. Intended for development of the emulator only.
+static STRICT_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict_mode(enabled: bool) {
+    STRICT_MODE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn strict_mode() -> bool {
+    STRICT_MODE.load(Ordering::Relaxed)
+}
+
+// In deterministic mode, the emulator does not depend on anything but
+// the ROM and the input, so that a ROM and an input movie always give
+// the same frames and audio. The sources of nondeterminism are:
+//
+// - The MBC3 real-time clock, which follows the wall clock. In
+//   deterministic mode it only has the values written by the game.
+// - The DMG wave RAM, which gets semi-random content on power cycle.
+//   In deterministic mode it's always the same pattern.
+// - Work RAM and high RAM, which also get semi-random content on
+//   power cycle. In deterministic mode they are cleared.
+//
+// VRAM is always cleared on power cycle.
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+pub fn set_deterministic(enabled: bool) {
+    DETERMINISTIC.store(enabled, Ordering::Relaxed);
+}
+
+pub fn deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
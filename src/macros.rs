@@ -7,3 +7,18 @@ macro_rules! panic_debug {
         panic!()
     };
 }
+
+// Report an access to an address that is not handled by a register
+// dispatcher. In strict mode this panics, which is useful when working
+// on the emulator. Otherwise the error is only logged, and the caller
+// is expected to carry on, usually by returning an open-bus value.
+#[macro_export]
+macro_rules! invalid_access {
+    ($($arg:tt)*) => {
+        if $crate::strict_mode() {
+            panic!($($arg)*);
+        } else {
+            println!("Error: {}", format!($($arg)*));
+        }
+    };
+}
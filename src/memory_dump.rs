@@ -0,0 +1,138 @@
+// Memory dumps
+//
+// Copies a range of the address space to a binary file, or a file
+// back into memory, for reverse engineering. A range is given as hex
+// addresses, such as "C000-DFFF", or as the name of a memory region
+// of the core, such as "VRAM", "WRAM", "OAM" or "Ext RAM". Case and
+// spaces in region names are ignored.
+//
+// Memory is read and written with `peek` and `poke`, so a dump of the
+// cartridge RAM only has content while the RAM is enabled, and the
+// content of the current banks is dumped.
+
+use crate::core::{Core, Debuggable};
+
+fn parse_addr(s: &str) -> Result<usize, String> {
+    let digits = s.trim().trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {}", s))
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+// Parse a range of addresses of `core`, both ends included. A single
+// address is a range of one byte.
+pub fn parse_range(s: &str, core: &impl Debuggable) -> Result<(usize, usize), String> {
+    let regions = core.memory_regions();
+    if let Some(region) = regions.iter().find(|r| normalize(r.name) == normalize(s)) {
+        return Ok((region.start, region.end));
+    }
+
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+        None => {
+            let addr = parse_addr(s)?;
+            (addr, addr)
+        }
+    };
+    if start > end {
+        return Err(format!("invalid range: {}", s));
+    }
+    if end >= core.memory_size() {
+        return Err(format!(
+            "range ends past the end of memory at {:X}: {}",
+            core.memory_size() - 1,
+            s
+        ));
+    }
+    Ok((start, end))
+}
+
+// Split a command line option such as "VRAM=vram.bin" into the range
+// and the filename
+pub fn parse_option(s: &str) -> Result<(&str, &str), String> {
+    match s.split_once('=') {
+        Some((range, filename)) if !range.is_empty() && !filename.is_empty() => {
+            Ok((range, filename))
+        }
+        _ => Err(format!("expected RANGE=FILE, got: {}", s)),
+    }
+}
+
+// Write memory from `start` to `end` to a file. Returns the number of
+// bytes written.
+pub fn dump_memory(
+    core: &impl Core,
+    start: usize,
+    end: usize,
+    filename: &str,
+) -> Result<usize, String> {
+    let data: Vec<u8> = (start..=end).map(|addr| core.peek(addr)).collect();
+    std::fs::write(filename, &data).map_err(|e| e.to_string())?;
+    Ok(data.len())
+}
+
+// Write the content of a file to memory, starting at `start`. Content
+// beyond the end of the address space is ignored. Returns the number
+// of bytes written to memory.
+pub fn load_memory(
+    core: &mut impl Debuggable,
+    start: usize,
+    filename: &str,
+) -> Result<usize, String> {
+    let data = std::fs::read(filename).map_err(|e| e.to_string())?;
+    let count = data.len().min(core.memory_size().saturating_sub(start));
+    for (n, value) in data.iter().take(count).enumerate() {
+        core.poke(start + n, *value);
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::{Emu, Machine};
+
+    #[test]
+    fn test_parse_range() {
+        let emu = Emu::new(Machine::GameBoyDMG);
+        assert_eq!(parse_range("C000-C0FF", &emu), Ok((0xC000, 0xC0FF)));
+        assert_eq!(parse_range("0xFF80", &emu), Ok((0xFF80, 0xFF80)));
+        assert_eq!(parse_range("vram", &emu), Ok((0x8000, 0x9FFF)));
+        assert_eq!(parse_range("extram", &emu), Ok((0xA000, 0xBFFF)));
+        assert!(parse_range("C0FF-C000", &emu).is_err());
+        assert!(parse_range("nowhere", &emu).is_err());
+
+        // Ranges must end within the address space
+        assert_eq!(parse_range("FF00-FFFF", &emu), Ok((0xFF00, 0xFFFF)));
+        assert!(parse_range("FF00-10000", &emu).is_err());
+        assert!(parse_range("12345", &emu).is_err());
+
+        assert_eq!(parse_option("OAM=oam.bin"), Ok(("OAM", "oam.bin")));
+        assert!(parse_option("OAM").is_err());
+    }
+
+    #[test]
+    fn test_dump_and_load() {
+        let filename = std::env::temp_dir().join("rustboy-test-dump.bin");
+        let filename = filename.to_str().unwrap();
+
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        for n in 0..0x10 {
+            emu.poke(0xC000 + n, n as u8);
+        }
+        assert_eq!(dump_memory(&emu, 0xC000, 0xC00F, filename), Ok(0x10));
+
+        assert_eq!(load_memory(&mut emu, 0xD000, filename), Ok(0x10));
+        assert_eq!(emu.peek(0xD00F), 0x0F);
+
+        // The end of the address space is not wrapped
+        assert_eq!(load_memory(&mut emu, 0xFFF8, filename), Ok(8));
+        assert_eq!(emu.peek(0xFFFE), 0x06);
+        std::fs::remove_file(filename).unwrap();
+    }
+}
@@ -0,0 +1,434 @@
+// DMG color palettes
+//
+// The DMG has no colors, only four shades. The PPU produces a shade
+// (0-3) per pixel, along with the source of the pixel (see the PPU
+// buffer). The shades are turned into RGB colors when the framebuffer
+// is converted to an image, using one set of four colors for the
+// background and window, and one set for each object palette.
+
+use serde::{Deserialize, Serialize};
+
+use crate::gameboy::cartridge::cartridge_header::CartridgeHeader;
+
+pub type Rgb = (u8, u8, u8);
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DmgPalette {
+    // Colors of background and window pixels
+    pub bg: [Rgb; 4],
+
+    // Colors of object pixels using OBP0 and OBP1
+    pub obj0: [Rgb; 4],
+    pub obj1: [Rgb; 4],
+}
+
+impl DmgPalette {
+    // Palette with the same colors for background and objects
+    pub const fn uniform(colors: [Rgb; 4]) -> Self {
+        DmgPalette {
+            bg: colors,
+            obj0: colors,
+            obj1: colors,
+        }
+    }
+
+    // Index (0-11) of a framebuffer pixel in `colors()`
+    pub fn index(&self, px: u8) -> u8 {
+        let shade = px & 3;
+        match px >> 6 {
+            2 => 4 + shade,
+            3 => 8 + shade,
+            _ => shade,
+        }
+    }
+
+    // All colors of the palette: background, OBJ0 and OBJ1
+    pub fn colors(&self) -> [Rgb; 12] {
+        let mut colors = [(0, 0, 0); 12];
+        colors[0..4].copy_from_slice(&self.bg);
+        colors[4..8].copy_from_slice(&self.obj0);
+        colors[8..12].copy_from_slice(&self.obj1);
+        colors
+    }
+
+    // Color of a framebuffer pixel
+    pub fn color(&self, px: u8) -> Rgb {
+        let shade = (px & 3) as usize;
+        match px >> 6 {
+            2 => self.obj0[shade],
+            3 => self.obj1[shade],
+            _ => self.bg[shade],
+        }
+    }
+}
+
+// Shades of green, resembling the original DMG screen
+pub const CLASSIC_GREEN: DmgPalette = DmgPalette::uniform([
+    (0x9B, 0xBC, 0x0F),
+    (0x8B, 0xAC, 0x0F),
+    (0x30, 0x62, 0x30),
+    (0x0F, 0x38, 0x0F),
+]);
+
+// Shades of gray, resembling the Game Boy Pocket screen
+pub const POCKET_GRAY: DmgPalette = DmgPalette::uniform([
+    (0xC4, 0xCF, 0xA1),
+    (0x8B, 0x95, 0x6D),
+    (0x4D, 0x53, 0x3C),
+    (0x1F, 0x1F, 0x1F),
+]);
+
+// Plain grayscale. These are the colors used by the dmg-acid2
+// reference images.
+pub const GRAYSCALE: DmgPalette = DmgPalette::uniform([
+    (0xFF, 0xFF, 0xFF),
+    (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55),
+    (0x00, 0x00, 0x00),
+]);
+
+// Palette selected by the CGB boot ROM when pressing Left
+pub const CGB_BLUE: DmgPalette = DmgPalette {
+    bg: [
+        (0xFF, 0xFF, 0xFF),
+        (0x63, 0xA5, 0xFF),
+        (0x00, 0x00, 0xFF),
+        (0x00, 0x00, 0x00),
+    ],
+    obj0: [
+        (0xFF, 0xFF, 0xFF),
+        (0xFF, 0x84, 0x84),
+        (0x94, 0x3A, 0x3A),
+        (0x00, 0x00, 0x00),
+    ],
+    obj1: [
+        (0xFF, 0xFF, 0xFF),
+        (0xFF, 0x84, 0x84),
+        (0x94, 0x3A, 0x3A),
+        (0x00, 0x00, 0x00),
+    ],
+};
+
+// Palette selected by the CGB boot ROM when pressing Up and A
+pub const CGB_RED: DmgPalette = DmgPalette {
+    bg: [
+        (0xFF, 0xFF, 0xFF),
+        (0xFF, 0x84, 0x84),
+        (0x94, 0x3A, 0x3A),
+        (0x00, 0x00, 0x00),
+    ],
+    obj0: [
+        (0xFF, 0xFF, 0xFF),
+        (0x7B, 0xFF, 0x31),
+        (0x00, 0x84, 0x00),
+        (0x00, 0x00, 0x00),
+    ],
+    obj1: [
+        (0xFF, 0xFF, 0xFF),
+        (0x63, 0xA5, 0xFF),
+        (0x00, 0x00, 0xFF),
+        (0x00, 0x00, 0x00),
+    ],
+};
+
+pub const PRESETS: [(&str, DmgPalette); 5] = [
+    ("Classic green", CLASSIC_GREEN),
+    ("Pocket gray", POCKET_GRAY),
+    ("Grayscale", GRAYSCALE),
+    ("CGB blue", CGB_BLUE),
+    ("CGB red", CGB_RED),
+];
+
+// Name of the preset matching `palette`, if any
+pub fn preset_name(palette: &DmgPalette) -> Option<&'static str> {
+    PRESETS
+        .iter()
+        .find(|(_, p)| p == palette)
+        .map(|(name, _)| *name)
+}
+
+// CGB colorization
+//
+// When a DMG game runs on a CGB, the boot ROM picks a palette for it
+// from a table, using the sum of the title bytes in the header. Only
+// games published by Nintendo are in the table. Some sums are shared
+// by several titles, in which case the fourth letter of the title
+// tells them apart. Games that aren't found get the default palette.
+//
+// Each game is given a combination of three palettes, for the
+// background and the two object palettes. The tables below are those
+// of the boot ROM.
+
+// Palette of games that aren't in the table
+pub const CGB_DEFAULT: DmgPalette = DmgPalette {
+    bg: [
+        (0xFF, 0xFF, 0xFF),
+        (0x7B, 0xFF, 0x31),
+        (0x00, 0x63, 0xC5),
+        (0x00, 0x00, 0x00),
+    ],
+    obj0: [
+        (0xFF, 0xFF, 0xFF),
+        (0xFF, 0x84, 0x84),
+        (0x94, 0x3A, 0x3A),
+        (0x00, 0x00, 0x00),
+    ],
+    obj1: [
+        (0xFF, 0xFF, 0xFF),
+        (0xFF, 0x84, 0x84),
+        (0x94, 0x3A, 0x3A),
+        (0x00, 0x00, 0x00),
+    ],
+};
+
+// The palettes of the boot ROM, in the BGR555 format of CGB palette
+// RAM, four colors each
+const BOOT_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB, // 1
+    0x7FFF, 0x6E31, 0x454A, 0x0000, // 2
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, // 3
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, // 4
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000, // 6
+    0x7FFF, 0x03EF, 0x01D6, 0x0000, // 7
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, // 8
+    0x7E74, 0x03FF, 0x0180, 0x0000, // 9
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000, // 11
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, // 12
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, // 13
+    0x03ED, 0x7FFF, 0x255F, 0x0000, // 14
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000, // 16
+    0x231F, 0x035F, 0x00F2, 0x0009, // 17
+    0x7FFF, 0x03EA, 0x011F, 0x0000, // 18
+    0x299F, 0x001A, 0x000C, 0x0000, // 19
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120, // 21
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, // 22
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F, // 23
+    0x7FFF, 0x03FF, 0x001F, 0x0000, // 24
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000, // 26
+    0x0000, 0x4200, 0x037F, 0x7FFF, // 27
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, // 28
+    0x7FFF, 0x1BEF, 0x6180, 0x0000, // 29
+];
+
+// Offset of palette `n` in BOOT_COLORS
+const fn pal(n: usize) -> usize {
+    n * 4
+}
+
+// Combinations of palettes for OBJ0, OBJ1 and the background, as
+// offsets in BOOT_COLORS. A few start in the middle of a palette.
+const COMBINATIONS: [(usize, usize, usize); 51] = [
+    (pal(4), pal(4), pal(29)),         // 0
+    (pal(18), pal(18), pal(18)),       // 1
+    (pal(20), pal(20), pal(20)),       // 2
+    (pal(24), pal(24), pal(24)),       // 3
+    (pal(9), pal(9), pal(9)),          // 4
+    (pal(0), pal(0), pal(0)),          // 5
+    (pal(27), pal(27), pal(27)),       // 6
+    (pal(5), pal(5), pal(5)),          // 7
+    (pal(12), pal(12), pal(12)),       // 8
+    (pal(26), pal(26), pal(26)),       // 9
+    (pal(16), pal(8), pal(8)),         // 10
+    (pal(4), pal(28), pal(28)),        // 11
+    (pal(4), pal(2), pal(2)),          // 12
+    (pal(3), pal(4), pal(4)),          // 13
+    (pal(4), pal(29), pal(29)),        // 14
+    (pal(28), pal(4), pal(28)),        // 15
+    (pal(2), pal(17), pal(2)),         // 16
+    (pal(16), pal(16), pal(8)),        // 17
+    (pal(4), pal(4), pal(7)),          // 18
+    (pal(4), pal(4), pal(18)),         // 19
+    (pal(4), pal(4), pal(20)),         // 20
+    (pal(19), pal(19), pal(9)),        // 21
+    (pal(4) - 1, pal(4) - 1, pal(11)), // 22
+    (pal(17), pal(17), pal(2)),        // 23
+    (pal(4), pal(4), pal(2)),          // 24
+    (pal(4), pal(4), pal(3)),          // 25
+    (pal(28), pal(28), pal(0)),        // 26
+    (pal(3), pal(3), pal(0)),          // 27
+    (pal(0), pal(0), pal(1)),          // 28
+    (pal(18), pal(22), pal(18)),       // 29
+    (pal(20), pal(22), pal(20)),       // 30
+    (pal(24), pal(22), pal(24)),       // 31
+    (pal(16), pal(22), pal(8)),        // 32
+    (pal(17), pal(4), pal(13)),        // 33
+    (pal(28) - 1, pal(0), pal(14)),    // 34
+    (pal(28) - 1, pal(4), pal(15)),    // 35
+    (pal(19), pal(22), pal(9)),        // 36
+    (pal(16), pal(28), pal(10)),       // 37
+    (pal(4), pal(23), pal(28)),        // 38
+    (pal(17), pal(22), pal(2)),        // 39
+    (pal(4), pal(0), pal(2)),          // 40
+    (pal(4), pal(28), pal(3)),         // 41
+    (pal(28), pal(3), pal(0)),         // 42
+    (pal(3), pal(28), pal(4)),         // 43
+    (pal(21), pal(28), pal(4)),        // 44
+    (pal(3), pal(28), pal(0)),         // 45
+    (pal(25), pal(3), pal(28)),        // 46
+    (pal(0), pal(28), pal(8)),         // 47
+    (pal(4), pal(3), pal(28)),         // 48
+    (pal(28), pal(3), pal(6)),         // 49
+    (pal(4), pal(28), pal(29)),        // 50
+];
+
+// Title checksums of the games in the table. The last 29 share
+// their checksum with another game, and are told apart by the fourth
+// letter of the title.
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0xE8, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+const FIRST_DUPLICATE: usize = 65;
+
+// Fourth letter of the titles from FIRST_DUPLICATE on
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+// Combination of each game in TITLE_CHECKSUMS
+const TITLE_COMBINATIONS: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 34, 23, 18, 29, 28,
+];
+
+// Convert a BGR555 color to RGB
+fn rgb(color: u16) -> Rgb {
+    let channel = |shift: u16| ((((color >> shift) & 0x1F) as u32 * 255 + 15) / 31) as u8;
+    (channel(0), channel(5), channel(10))
+}
+
+// Four colors from `offset` in BOOT_COLORS
+fn boot_colors(offset: usize) -> [Rgb; 4] {
+    let mut colors = [(0, 0, 0); 4];
+    for (i, color) in colors.iter_mut().enumerate() {
+        *color = rgb(BOOT_COLORS[offset + i]);
+    }
+    colors
+}
+
+// Index of a game in TITLE_CHECKSUMS, or 0 if not found
+fn title_index(checksum: u8, fourth_letter: u8) -> usize {
+    TITLE_CHECKSUMS
+        .iter()
+        .enumerate()
+        .position(|(i, c)| {
+            *c == checksum
+                && (i < FIRST_DUPLICATE || FOURTH_LETTERS[i - FIRST_DUPLICATE] == fourth_letter)
+        })
+        .unwrap_or(0)
+}
+
+// The palette the CGB boot ROM would pick for a DMG game
+pub fn colorization(header: &CartridgeHeader) -> DmgPalette {
+    let index = match header.nintendo_licensed() {
+        true => title_index(header.title_checksum, header.title_fourth_letter),
+        false => 0,
+    };
+    let (obj0, obj1, bg) = COMBINATIONS[TITLE_COMBINATIONS[index] as usize];
+    DmgPalette {
+        bg: boot_colors(bg),
+        obj0: boot_colors(obj0),
+        obj1: boot_colors(obj1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_source_selects_colors() {
+        // Background, window, OBJ0 and OBJ1 pixels with shade 1
+        assert_eq!(CGB_RED.color(0b0000_0001), CGB_RED.bg[1]);
+        assert_eq!(CGB_RED.color(0b0100_0001), CGB_RED.bg[1]);
+        assert_eq!(CGB_RED.color(0b1000_0001), CGB_RED.obj0[1]);
+        assert_eq!(CGB_RED.color(0b1100_0001), CGB_RED.obj1[1]);
+
+        let colors = CGB_RED.colors();
+        for px in [0x01, 0x42, 0x83, 0xC1] {
+            assert_eq!(colors[CGB_RED.index(px) as usize], CGB_RED.color(px));
+        }
+    }
+
+    // Header of a game published by Nintendo
+    fn nintendo_rom(title: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x150];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom[0x14B] = 0x01;
+        rom
+    }
+
+    fn colorize(title: &[u8]) -> DmgPalette {
+        colorization(&CartridgeHeader::from_header(&nintendo_rom(title)))
+    }
+
+    #[test]
+    fn test_colorization() {
+        let mut rom = nintendo_rom(b"POKEMON RED");
+        let header = CartridgeHeader::from_header(&rom);
+        assert_eq!(header.title_checksum, 0x14);
+        assert!(header.dmg_only());
+        let red = colorization(&header);
+        assert_eq!((red.bg, red.obj0), (CGB_RED.bg, CGB_RED.obj0));
+        assert_eq!(red.obj1, CGB_RED.bg);
+
+        // Licensed through the new licensee code
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        assert_eq!(colorization(&CartridgeHeader::from_header(&rom)), red);
+
+        // Other publishers get the default palette
+        rom[0x144..0x146].copy_from_slice(b"08");
+        assert_eq!(
+            colorization(&CartridgeHeader::from_header(&rom)),
+            CGB_DEFAULT
+        );
+        assert_eq!(colorize(b"NOT IN TABLE"), CGB_DEFAULT);
+        let yellow = [
+            (0xFF, 0xFF, 0xFF),
+            (0xFF, 0xFF, 0x00),
+            (0xFF, 0x00, 0x00),
+            (0x00, 0x00, 0x00),
+        ];
+        assert_eq!(colorize(b"TETRIS"), DmgPalette::uniform(yellow));
+    }
+
+    #[test]
+    fn test_colorization_fourth_letter() {
+        // Both have checksum 0x46
+        let mario = colorize(b"SUPER MARIOLAND");
+        let metroid = colorize(b"METROID2");
+        assert_ne!(mario, metroid);
+        assert_eq!(mario.obj0[0], (0, 0, 0));
+        assert_eq!(metroid.bg, CGB_BLUE.bg);
+
+        // Both have checksum 0x61
+        assert_eq!(colorize(b"POKEMON BLUE").bg, CGB_BLUE.bg);
+        let green = [
+            (0xFF, 0xFF, 0xFF),
+            (0x7B, 0xFF, 0x31),
+            (0x00, 0x84, 0x00),
+            (0x00, 0x00, 0x00),
+        ];
+        assert_eq!(colorize(b"VEGAS STAKES").bg, green);
+
+        // Checksum 0xB3, the only one in the table three times
+        assert_eq!(title_index(0xB3, b'B'), FIRST_DUPLICATE);
+        assert_eq!(title_index(0xB3, b'U'), FIRST_DUPLICATE + 14);
+        assert_eq!(title_index(0xB3, b'R'), FIRST_DUPLICATE + 28);
+
+        // A shared checksum with another fourth letter isn't found
+        assert_eq!(title_index(0x46, b'X'), 0);
+    }
+}
@@ -0,0 +1,153 @@
+// Python module
+//
+// A small wrapper around `Emu` for Python, mostly for reinforcement
+// learning, where the frame server protocol is too slow. Build and
+// install it in the current virtualenv with maturin:
+//
+//   maturin develop --release --features python
+//
+// and use it like this:
+//
+//   import rustboy
+//   gb = rustboy.GameBoy("tetris.gb")
+//   gb.set_buttons(["start"])
+//   gb.step_frames(60)
+//   screen = gb.framebuffer()   # 144x160 numpy array of shades 0-3
+//   score = gb.read_memory(0xC0A0)
+//
+// Without a boot ROM, the boot ROM is skipped. Audio is emulated but
+// not returned.
+
+use std::path::Path;
+
+use numpy::{PyArray1, PyArray2, PyArray3, PyArrayMethods};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::gameboy::emu::{Emu, Machine};
+use crate::gameboy::mmu::ResetKind;
+use crate::gameboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::palette::GRAYSCALE;
+use crate::server::BUTTONS;
+
+#[pyclass(name = "GameBoy", unsendable)]
+pub struct PyGameBoy {
+    emu: Emu,
+}
+
+fn check_addr(addr: usize) -> PyResult<usize> {
+    match addr {
+        0..=0xFFFF => Ok(addr),
+        _ => Err(PyValueError::new_err(format!(
+            "invalid address: 0x{:x}",
+            addr
+        ))),
+    }
+}
+
+#[pymethods]
+impl PyGameBoy {
+    #[new]
+    #[pyo3(signature = (rom, boot_rom=None, cgb=false))]
+    fn new(rom: &str, boot_rom: Option<&str>, cgb: bool) -> PyResult<Self> {
+        for path in [Some(rom), boot_rom].iter().flatten() {
+            if !Path::new(path).is_file() {
+                return Err(PyIOError::new_err(format!("file not found: {}", path)));
+            }
+        }
+
+        let machine = match cgb {
+            true => Machine::GameBoyCGB,
+            false => Machine::GameBoyDMG,
+        };
+        let mut emu = Emu::new(machine);
+        emu.init();
+        if let Some(path) = boot_rom {
+            emu.load_bootstrap(path);
+        }
+        emu.load_cartridge(rom).map_err(PyValueError::new_err)?;
+        if boot_rom.is_none() {
+            emu.skip_bootrom();
+        }
+        Ok(PyGameBoy { emu })
+    }
+
+    // Run `count` frames and return the frame number
+    #[pyo3(signature = (count=1))]
+    fn step_frames(&mut self, count: usize) -> usize {
+        let mut frames = self.emu.frames();
+        for _ in 0..count {
+            frames.next_frame();
+        }
+        self.emu.mmu.ppu.frame_number
+    }
+
+    // The screen as a 144x160 array of shades, 0 (white) to 3 (black)
+    fn framebuffer<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        let pixels: Vec<u8> = self.emu.mmu.ppu.buffer.iter().map(|px| px & 3).collect();
+        PyArray1::from_vec(py, pixels)
+            .reshape([SCREEN_HEIGHT, SCREEN_WIDTH])
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    // The screen as a 144x160x3 array of RGB colors, in grayscale
+    fn rgb<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let mut rgba = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4].into_boxed_slice();
+        self.emu.mmu.ppu.to_rgba8(&mut rgba, &GRAYSCALE);
+        let rgb: Vec<u8> = rgba.chunks(4).flat_map(|px| px[0..3].to_vec()).collect();
+        PyArray1::from_vec(py, rgb)
+            .reshape([SCREEN_HEIGHT, SCREEN_WIDTH, 3])
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    // Press the listed buttons and release all others. Valid buttons:
+    // up, down, left, right, a, b, start, select.
+    fn set_buttons(&mut self, buttons: Vec<String>) -> PyResult<()> {
+        let mut pressed = 0;
+        for name in buttons.iter() {
+            match BUTTONS.iter().find(|(n, _)| n == name) {
+                Some((_, button)) => pressed |= *button as u8,
+                None => return Err(PyValueError::new_err(format!("unknown button: {}", name))),
+            }
+        }
+        self.emu.mmu.buttons.set_pressed(pressed);
+        Ok(())
+    }
+
+    fn read_memory(&self, addr: usize) -> PyResult<u8> {
+        Ok(self.emu.mmu.direct_read(check_addr(addr)?))
+    }
+
+    fn write_memory(&mut self, addr: usize, value: u8) -> PyResult<()> {
+        self.emu.mmu.direct_write(check_addr(addr)?, value);
+        Ok(())
+    }
+
+    #[pyo3(signature = (hard=true))]
+    fn reset(&mut self, hard: bool) {
+        self.emu.reset(match hard {
+            true => ResetKind::Hard,
+            false => ResetKind::Soft,
+        });
+    }
+
+    fn save_state<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.emu.save_state())
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> PyResult<()> {
+        self.emu.load_state(data).map_err(PyValueError::new_err)
+    }
+
+    #[getter]
+    fn frame_number(&self) -> usize {
+        self.emu.mmu.ppu.frame_number
+    }
+}
+
+#[pymodule]
+fn rustboy(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyGameBoy>()?;
+    Ok(())
+}
@@ -0,0 +1,313 @@
+// Instant replay
+//
+// The replay buffer keeps the last N frames in memory so that a
+// glitch can be exported after it has been seen on screen, rather
+// than having to be reproduced while recording.
+//
+// Each frame is stored compressed: the framebuffer only holds
+// 2-bit color indices, so four pixels are packed into every byte,
+// followed by a simple run-length encoding of the packed bytes.
+// A frame that is mostly a single color (which is common) ends up
+// at a few hundred bytes. Audio for the frame is stored as-is.
+
+use std::collections::VecDeque;
+#[cfg(feature = "recording")]
+use std::fs::File;
+#[cfg(feature = "recording")]
+use std::io::BufWriter;
+
+use crate::palette::DmgPalette;
+
+// Number of frames kept by default. About 10 seconds at ~60 fps.
+pub const DEFAULT_REPLAY_FRAMES: usize = 600;
+
+struct ReplayFrame {
+    // Run-length encoded, 2bpp packed color indices.
+    // Stored as (count, value) pairs.
+    pixels: Vec<u8>,
+    audio: Vec<i16>,
+}
+
+pub struct ReplayBuffer {
+    frames: VecDeque<ReplayFrame>,
+    capacity: usize,
+
+    // Size of the frames, only needed for export
+    #[cfg_attr(not(feature = "recording"), allow(dead_code))]
+    width: usize,
+    #[cfg_attr(not(feature = "recording"), allow(dead_code))]
+    height: usize,
+}
+
+fn pack_2bpp(buf: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(buf.len().div_ceil(4));
+    for chunk in buf.chunks(4) {
+        let mut b = 0;
+        for (i, px) in chunk.iter().enumerate() {
+            b |= (px & 3) << (i * 2);
+        }
+        packed.push(b);
+    }
+    packed
+}
+
+fn unpack_2bpp(packed: &[u8], dst: &mut [u8]) {
+    for (i, px) in dst.iter_mut().enumerate() {
+        *px = (packed[i / 4] >> ((i % 4) * 2)) & 3;
+    }
+}
+
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut iter = data.iter().peekable();
+    while let Some(&value) = iter.next() {
+        let mut count: u8 = 1;
+        while count < 255 && iter.peek() == Some(&&value) {
+            iter.next();
+            count += 1;
+        }
+        encoded.push(count);
+        encoded.push(value);
+    }
+    encoded
+}
+
+fn rle_decode(encoded: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for pair in encoded.chunks(2) {
+        for _ in 0..pair[0] {
+            data.push(pair[1]);
+        }
+    }
+    data
+}
+
+impl ReplayBuffer {
+    pub fn new(width: usize, height: usize, capacity: usize) -> Self {
+        ReplayBuffer {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            width,
+            height,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // Approximate memory used by the stored frames, in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|f| f.pixels.len() + f.audio.len() * 2)
+            .sum()
+    }
+
+    // Add a completed frame. `framebuffer` holds one color index (0-3)
+    // per pixel and `audio` the samples generated during the frame.
+    // The oldest frame is dropped when the buffer is full.
+    pub fn push(&mut self, framebuffer: &[u8], audio: &[i16]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(ReplayFrame {
+            pixels: rle_encode(&pack_2bpp(framebuffer)),
+            audio: audio.to_vec(),
+        });
+    }
+
+    // Decompress frame n (0 is the oldest frame) into color indices
+    #[cfg_attr(not(feature = "recording"), allow(dead_code))]
+    fn frame(&self, n: usize, dst: &mut [u8]) {
+        unpack_2bpp(&rle_decode(&self.frames[n].pixels), dst);
+    }
+
+    // Export all buffered frames as an animated GIF.
+    //
+    // Only the shade of each pixel is kept in the buffer, so all
+    // pixels are exported with the background colors of the palette.
+    //
+    // GIF frame delays are specified in 1/100 seconds, which does not
+    // match the Game Boy frame rate. Only every `skip`'th frame is
+    // written, and the delay is calculated from the accumulated time
+    // so that the animation stays in sync with the audio.
+    #[cfg(feature = "recording")]
+    pub fn export_gif(
+        &self,
+        filename: &str,
+        palette: &DmgPalette,
+        fps: f64,
+        skip: usize,
+    ) -> Result<(), std::io::Error> {
+        let file = File::create(filename)?;
+        let w = BufWriter::new(file);
+
+        let mut gif_palette: Vec<u8> = Vec::with_capacity(12);
+        for (r, g, b) in palette.bg.iter() {
+            gif_palette.extend([*r, *g, *b]);
+        }
+
+        let mut encoder = gif::Encoder::new(w, self.width as u16, self.height as u16, &gif_palette)
+            .map_err(std::io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(std::io::Error::other)?;
+
+        let skip = skip.max(1);
+        let mut pixels = vec![0; self.width * self.height];
+        let mut n = 0;
+
+        while n < self.frames.len() {
+            self.frame(n, &mut pixels);
+
+            let t0 = ((n as f64 / fps) * 100.0).round() as u16;
+            let t1 = (((n + skip) as f64 / fps) * 100.0).round() as u16;
+
+            let frame = gif::Frame {
+                width: self.width as u16,
+                height: self.height as u16,
+                delay: t1 - t0,
+                buffer: std::borrow::Cow::Borrowed(&pixels),
+                ..gif::Frame::default()
+            };
+
+            encoder.write_frame(&frame).map_err(std::io::Error::other)?;
+
+            n += skip;
+        }
+
+        Ok(())
+    }
+
+    // Export the audio of all buffered frames as a mono WAV file
+    #[cfg(feature = "recording")]
+    pub fn export_wav(&self, filename: &str, sample_rate: u32) -> Result<(), hound::Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut writer = hound::WavWriter::create(filename, spec)?;
+        for frame in self.frames.iter() {
+            for sample in frame.audio.iter() {
+                writer.write_sample(*sample)?;
+            }
+        }
+        writer.finalize()
+    }
+
+    // Export the replay as `<basename>.gif` and `<basename>.wav`
+    #[cfg(feature = "recording")]
+    pub fn export(
+        &self,
+        basename: &str,
+        palette: &DmgPalette,
+        fps: f64,
+        sample_rate: u32,
+    ) -> Result<(), String> {
+        let gif_filename = format!("{}.gif", basename);
+        let wav_filename = format!("{}.wav", basename);
+
+        self.export_gif(&gif_filename, palette, fps, 2)
+            .map_err(|e| format!("Failed to write {}: {}", gif_filename, e))?;
+        self.export_wav(&wav_filename, sample_rate)
+            .map_err(|e| format!("Failed to write {}: {}", wav_filename, e))?;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "recording"))]
+    pub fn export(
+        &self,
+        _basename: &str,
+        _palette: &DmgPalette,
+        _fps: f64,
+        _sample_rate: u32,
+    ) -> Result<(), String> {
+        Err("Exporting requires the \"recording\" feature".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    fn test_frame(seed: usize) -> Vec<u8> {
+        // Long runs of a single color (longer than a 255 byte run)
+        // followed by noisy lines, which is what real frames look like
+        let mut buf = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for (i, px) in buf.iter_mut().enumerate() {
+            if i >= SCREEN_WIDTH * 100 {
+                *px = ((i * 7 + seed) / 3 % 4) as u8;
+            } else if i >= SCREEN_WIDTH * 50 {
+                *px = 2;
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut replay = ReplayBuffer::new(SCREEN_WIDTH, SCREEN_HEIGHT, 2);
+        let frames: Vec<Vec<u8>> = (0..3).map(test_frame).collect();
+        for frame in frames.iter() {
+            replay.push(frame, &[]);
+        }
+
+        // The oldest frame was dropped when the third one was pushed
+        assert_eq!(replay.len(), 2);
+
+        let mut dst = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for n in 0..2 {
+            replay.frame(n, &mut dst);
+            assert_eq!(dst, frames[n + 1]);
+        }
+
+        // A pixel count that is not a multiple of four
+        let odd = [1, 2, 3, 0, 3, 2, 1];
+        let mut dst = [0; 7];
+        unpack_2bpp(&rle_decode(&rle_encode(&pack_2bpp(&odd))), &mut dst);
+        assert_eq!(dst, odd);
+    }
+
+    #[cfg(feature = "recording")]
+    #[test]
+    fn test_export_wav_length() {
+        let mut replay = ReplayBuffer::new(SCREEN_WIDTH, SCREEN_HEIGHT, 4);
+        let frame = test_frame(0);
+        let mut total = 0;
+        for len in [700, 701, 0, 702, 699] {
+            let audio: Vec<i16> = (0..len).map(|i| i as i16).collect();
+            replay.push(&frame, &audio);
+            total += len;
+        }
+
+        // The first frame is no longer buffered
+        total -= 700;
+
+        let filename = std::env::temp_dir().join("rustboy-test-replay.wav");
+        let filename = filename.to_str().unwrap();
+        replay.export_wav(filename, 44100).unwrap();
+
+        let reader = hound::WavReader::open(filename).unwrap();
+        assert_eq!(reader.len() as usize, total);
+        std::fs::remove_file(filename).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+// Savestates
+//
+// The state of the emulated machine is written as a flat sequence of
+// little-endian values. Every part of the machine implements
+// `SaveState` with a single `serialize` function, that is used both
+// to save and to load the state. Since the same code does both, the
+// order of the values can't get out of sync.
+//
+// State files have a small header with a thumbnail of the screen at
+// the time of saving, followed by the state of the machine:
+//
+//   "RBSTATE\0"              Magic
+//   u16                      Format version
+//   u16, u16                 Thumbnail width and height
+//   width * height bytes     Thumbnail, one palette index per pixel
+//   ...                      State of the machine
+//
+// States are saved in numbered slots, in a directory per game.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"RBSTATE\0";
+
+// Incremented when the state of any part of the machine changes
+const VERSION: u16 = 7;
+
+// Number of slots, each bound to a function key
+pub const STATE_SLOTS: usize = 10;
+
+// Directory in which the per-game state directories are created
+pub const DEFAULT_STATE_DIR: &str = "states";
+
+// The thumbnail is the screen downscaled by this factor
+const THUMBNAIL_SCALE: usize = 2;
+
+pub trait SaveState {
+    // Save or load the state, depending on the mode of `s`
+    fn serialize(&mut self, s: &mut Serializer);
+}
+
+pub struct Serializer {
+    data: Vec<u8>,
+    pos: usize,
+    loading: bool,
+    error: Option<String>,
+}
+
+macro_rules! integer {
+    ($name:ident, $t:ty) => {
+        pub fn $name(&mut self, value: &mut $t) {
+            let mut bytes = value.to_le_bytes();
+            self.raw(&mut bytes);
+            *value = <$t>::from_le_bytes(bytes);
+        }
+    };
+}
+
+impl Serializer {
+    pub fn saving() -> Self {
+        Serializer {
+            data: Vec::new(),
+            pos: 0,
+            loading: false,
+            error: None,
+        }
+    }
+
+    pub fn loading(data: &[u8]) -> Self {
+        Serializer {
+            data: data.to_vec(),
+            pos: 0,
+            loading: true,
+            error: None,
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    // Record an error. Values are left unchanged from then on.
+    pub fn fail(&mut self, error: &str) {
+        if self.error.is_none() {
+            self.error = Some(error.to_string());
+        }
+    }
+
+    fn raw(&mut self, bytes: &mut [u8]) {
+        if !self.loading {
+            self.data.extend_from_slice(bytes);
+            return;
+        }
+
+        match self.data.get(self.pos..self.pos + bytes.len()) {
+            Some(src) if self.error.is_none() => bytes.copy_from_slice(src),
+            Some(_) => {}
+            None => self.fail("unexpected end of state"),
+        }
+        self.pos += bytes.len();
+    }
+
+    integer!(u8, u8);
+    integer!(u16, u16);
+    integer!(u32, u32);
+    integer!(u64, u64);
+    integer!(i16, i16);
+    integer!(i32, i32);
+
+    pub fn usize(&mut self, value: &mut usize) {
+        let mut v = *value as u64;
+        self.u64(&mut v);
+        *value = v as usize;
+    }
+
+    pub fn bool(&mut self, value: &mut bool) {
+        let mut v = *value as u8;
+        self.u8(&mut v);
+        *value = v != 0;
+    }
+
+    // A block of memory. The size is not saved, so it must be the
+    // same when loading.
+    pub fn bytes(&mut self, value: &mut [u8]) {
+        self.raw(value);
+    }
+
+    pub fn option_u16(&mut self, value: &mut Option<u16>) {
+        let (mut some, mut v) = (value.is_some(), value.unwrap_or(0));
+        self.bool(&mut some);
+        self.u16(&mut v);
+        *value = if some { Some(v) } else { None };
+    }
+
+    pub fn option_u64(&mut self, value: &mut Option<u64>) {
+        let (mut some, mut v) = (value.is_some(), value.unwrap_or(0));
+        self.bool(&mut some);
+        self.u64(&mut v);
+        *value = if some { Some(v) } else { None };
+    }
+
+    // The saved data, or the first error when loading
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        match self.error {
+            Some(e) => Err(e),
+            None if self.loading && self.pos != self.data.len() => {
+                Err("unexpected data at end of state".to_string())
+            }
+            None => Ok(self.data),
+        }
+    }
+}
+
+// Downscaled screen, with one palette index per pixel like the
+// framebuffer
+#[derive(Clone)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Thumbnail {
+    pub fn from_framebuffer(framebuffer: &[u8], width: usize, height: usize) -> Self {
+        let (w, h) = (width / THUMBNAIL_SCALE, height / THUMBNAIL_SCALE);
+        let mut pixels = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let offset = y * THUMBNAIL_SCALE * width + x * THUMBNAIL_SCALE;
+                pixels.push(framebuffer.get(offset).copied().unwrap_or(0));
+            }
+        }
+        Thumbnail {
+            width: w,
+            height: h,
+            pixels,
+        }
+    }
+}
+
+pub struct StateFile {
+    pub thumbnail: Thumbnail,
+    pub state: Vec<u8>,
+}
+
+impl StateFile {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let thumb = &self.thumbnail;
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(thumb.width as u16).to_le_bytes());
+        data.extend_from_slice(&(thumb.height as u16).to_le_bytes());
+        data.extend_from_slice(&thumb.pixels);
+        data.extend_from_slice(&self.state);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<StateFile, String> {
+        let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+
+        if data.len() < 14 || &data[0..8] != MAGIC {
+            return Err("not a state file".to_string());
+        }
+        if u16_at(8) != VERSION {
+            return Err(format!("unsupported state version {}", u16_at(8)));
+        }
+
+        let (width, height) = (u16_at(10) as usize, u16_at(12) as usize);
+        let end = 14 + width * height;
+        if data.len() < end {
+            return Err("unexpected end of state".to_string());
+        }
+
+        Ok(StateFile {
+            thumbnail: Thumbnail {
+                width,
+                height,
+                pixels: data[14..end].to_vec(),
+            },
+            state: data[end..].to_vec(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, self.to_bytes()).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<StateFile, String> {
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        StateFile::from_bytes(&data)
+    }
+}
+
+// The numbered state slots of a game
+pub struct StateSlots {
+    dir: PathBuf,
+}
+
+impl StateSlots {
+    // The states of each game are kept in a directory named after
+    // the ROM file
+    pub fn for_rom(state_dir: &str, rom: &str) -> Self {
+        let name = Path::new(rom)
+            .file_stem()
+            .map_or("default".into(), |s| s.to_string_lossy());
+        StateSlots {
+            dir: Path::new(state_dir).join(name.as_ref()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Slots are numbered from 1
+    pub fn path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("slot{}.state", slot))
+    }
+
+    pub fn save(&self, slot: usize, file: &StateFile) -> Result<(), String> {
+        file.save(&self.path(slot))
+    }
+
+    pub fn load(&self, slot: usize) -> Result<StateFile, String> {
+        StateFile::load(&self.path(slot))
+    }
+
+    // Thumbnails of all slots, None for empty slots
+    pub fn thumbnails(&self) -> Vec<Option<Thumbnail>> {
+        (1..=STATE_SLOTS)
+            .map(|slot| self.load(slot).ok().map(|file| file.thumbnail))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializer_round_trip() {
+        let (mut a, mut b, mut c, mut d) = (0x12u8, 0x3456u16, true, Some(7u16));
+        let mut mem = [1, 2, 3];
+        let mut s = Serializer::saving();
+        s.u8(&mut a);
+        s.u16(&mut b);
+        s.bool(&mut c);
+        s.option_u16(&mut d);
+        s.bytes(&mut mem);
+        let data = s.finish().unwrap();
+
+        let (mut a2, mut b2, mut c2, mut d2, mut mem2) = (0, 0, false, None, [0; 3]);
+        let mut s = Serializer::loading(&data);
+        s.u8(&mut a2);
+        s.u16(&mut b2);
+        s.bool(&mut c2);
+        s.option_u16(&mut d2);
+        s.bytes(&mut mem2);
+        s.finish().unwrap();
+        assert_eq!((a2, b2, c2, d2, mem2), (a, b, c, d, mem));
+
+        // Too little data leaves the values unchanged
+        let mut s = Serializer::loading(&data[0..2]);
+        let mut b3 = 0;
+        s.u8(&mut a2);
+        s.u16(&mut b3);
+        assert_eq!(b3, 0);
+        assert!(s.finish().is_err());
+    }
+
+    #[test]
+    fn test_state_file() {
+        let framebuffer: Vec<u8> = (0..160 * 144).map(|i| (i % 4) as u8).collect();
+        let file = StateFile {
+            thumbnail: Thumbnail::from_framebuffer(&framebuffer, 160, 144),
+            state: vec![1, 2, 3],
+        };
+        let loaded = StateFile::from_bytes(&file.to_bytes()).unwrap();
+        assert_eq!((loaded.thumbnail.width, loaded.thumbnail.height), (80, 72));
+        assert_eq!(loaded.thumbnail.pixels[0..3], [0, 2, 0]);
+        assert_eq!(loaded.state, [1, 2, 3]);
+
+        assert!(StateFile::from_bytes(b"RBSTATE").is_err());
+    }
+
+    #[test]
+    fn test_slot_paths() {
+        let slots = StateSlots::for_rom("states", "roms/tetris.gb");
+        assert_eq!(slots.path(3), Path::new("states/tetris/slot3.state"));
+    }
+}
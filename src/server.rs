@@ -0,0 +1,310 @@
+// Frame server
+//
+// Runs the emulator without UI, driven by a line-based protocol over
+// stdin/stdout. Meant for controlling the emulator from other programs,
+// such as Python scripts for reinforcement learning experiments or
+// automated analysis of games. Emulation is fully deterministic: the
+// same sequence of commands always gives the same result.
+//
+// Each command is a single line. Each command gives a single line of
+// response, starting with "OK" or "ERR". Any other output (such as log
+// messages from the emulator) should be ignored by the client.
+//
+// When the server is ready, it sends a line with "READY".
+//
+// Commands:
+//
+//   frame [buttons]           Run one frame with the given buttons held
+//   frames <n> [buttons]      Run n frames with the given buttons held
+//                             Both respond: OK <frame number> <hash>
+//   hash                      OK <SHA-1 of the framebuffer>
+//   screen                    OK <one hex digit (0-3) per pixel>
+//   readmem <addr> <len>      OK <bytes as hex>
+//   writemem <addr> <bytes>   Write bytes (hex) starting at addr
+//   reset [soft|hard]         Reset the emulator. Default is hard reset.
+//   savestate <file>          Save the state of the emulator to a file
+//   loadstate <file>          Load a state saved by savestate
+//   quit                      Stop the server
+//
+// Buttons are given as a comma separated list, for example "a,right".
+// Valid buttons: up, down, left, right, a, b, start, select.
+// Addresses are hexadecimal.
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::gameboy::buttons::ButtonType;
+use crate::gameboy::emu::Emu;
+use crate::gameboy::mmu::ResetKind;
+use crate::gameboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::CYCLES_PER_FRAME;
+use crate::savestate::{StateFile, Thumbnail};
+use crate::utils::parse_hex_bytes;
+
+pub(crate) const BUTTONS: [(&str, ButtonType); 8] = [
+    ("up", ButtonType::Up),
+    ("down", ButtonType::Down),
+    ("left", ButtonType::Left),
+    ("right", ButtonType::Right),
+    ("a", ButtonType::A),
+    ("b", ButtonType::B),
+    ("start", ButtonType::Start),
+    ("select", ButtonType::Select),
+];
+
+// Press the listed buttons and release all others
+fn set_buttons(emu: &mut Emu, buttons: Option<&str>) -> Result<(), String> {
+    let pressed: Vec<&str> = match buttons {
+        Some(list) => list.split(',').filter(|s| !s.is_empty()).collect(),
+        None => vec![],
+    };
+
+    for name in pressed.iter() {
+        if !BUTTONS.iter().any(|(n, _)| n == name) {
+            return Err(format!("unknown button: {}", name));
+        }
+    }
+
+    for (name, button) in BUTTONS.iter() {
+        if pressed.contains(name) {
+            emu.mmu.buttons.handle_press(*button);
+        } else {
+            emu.mmu.buttons.handle_release(*button);
+        }
+    }
+
+    Ok(())
+}
+
+// Run until the next frame is complete. If the display is disabled,
+// no frames are produced, so give up after two frames worth of cycles.
+fn run_frame(emu: &mut Emu) {
+    let frame = emu.mmu.ppu.frame_number;
+    let start = emu.mmu.timer.abs_cycle;
+    while emu.mmu.ppu.frame_number == frame
+        && emu.mmu.timer.abs_cycle - start < 2 * CYCLES_PER_FRAME as u64
+    {
+        emu.step();
+    }
+}
+
+fn frame_hash(emu: &Emu) -> String {
+    sha1_smol::Sha1::from(&emu.mmu.ppu.buffer[..])
+        .digest()
+        .to_string()
+}
+
+fn parse_addr(s: Option<&str>) -> Result<usize, String> {
+    match s {
+        Some(s) => usize::from_str_radix(s.trim_start_matches("0x"), 16)
+            .ok()
+            .filter(|addr| *addr <= 0xFFFF)
+            .ok_or(format!("invalid address: {}", s)),
+        None => Err("missing address".to_string()),
+    }
+}
+
+fn to_hex(bytes: impl Iterator<Item = u8>) -> String {
+    bytes.map(|b| format!("{:02X}", b)).collect()
+}
+
+// Execute a single command. Returns the response, without the
+// "OK" prefix, or an error.
+fn execute(emu: &mut Emu, line: &str) -> Result<String, String> {
+    let mut args = line.split_whitespace();
+    let command = args.next().unwrap_or("");
+
+    match command {
+        "frame" | "frames" => {
+            let count = if command == "frames" {
+                match args.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) => n,
+                    _ => return Err("invalid frame count".to_string()),
+                }
+            } else {
+                1
+            };
+
+            set_buttons(emu, args.next())?;
+            for _ in 0..count {
+                run_frame(emu);
+            }
+
+            Ok(format!("{} {}", emu.mmu.ppu.frame_number, frame_hash(emu)))
+        }
+
+        "hash" => Ok(frame_hash(emu)),
+
+        "screen" => Ok(emu
+            .mmu
+            .ppu
+            .buffer
+            .iter()
+            .map(|px| char::from_digit((px & 3) as u32, 16).unwrap())
+            .collect()),
+
+        "readmem" => {
+            let addr = parse_addr(args.next())?;
+            let len = match args.next().map(|n| usize::from_str_radix(n, 16)) {
+                Some(Ok(n)) if addr.checked_add(n).is_some_and(|end| end <= 0x10000) => n,
+                _ => return Err("invalid length".to_string()),
+            };
+            Ok(to_hex((addr..addr + len).map(|a| emu.mmu.direct_read(a))))
+        }
+
+        "writemem" => {
+            let addr = parse_addr(args.next())?;
+            let bytes = parse_hex_bytes(args.next().unwrap_or("")).ok_or("invalid hex bytes")?;
+            if addr + bytes.len() > 0x10000 {
+                return Err("write past end of memory".to_string());
+            }
+            for (i, b) in bytes.iter().enumerate() {
+                emu.mmu.direct_write(addr + i, *b);
+            }
+            Ok(String::new())
+        }
+
+        "reset" => {
+            let kind = match args.next() {
+                None | Some("hard") => ResetKind::Hard,
+                Some("soft") => ResetKind::Soft,
+                Some(other) => return Err(format!("invalid reset kind: {}", other)),
+            };
+            emu.reset(kind);
+            Ok(String::new())
+        }
+
+        "savestate" => {
+            let path = args.next().ok_or("missing file name")?;
+            let file = StateFile {
+                thumbnail: Thumbnail::from_framebuffer(
+                    &emu.mmu.ppu.buffer,
+                    SCREEN_WIDTH,
+                    SCREEN_HEIGHT,
+                ),
+                state: emu.save_state(),
+            };
+            file.save(Path::new(path))?;
+            Ok(String::new())
+        }
+
+        "loadstate" => {
+            let path = args.next().ok_or("missing file name")?;
+            let file = StateFile::load(Path::new(path))?;
+            emu.load_state(&file.state)?;
+            Ok(String::new())
+        }
+
+        _ => Err(format!("unknown command: {}", command)),
+    }
+}
+
+fn respond<W: Write>(output: &mut W, response: Result<String, String>) -> std::io::Result<()> {
+    match response {
+        Ok(s) if s.is_empty() => writeln!(output, "OK")?,
+        Ok(s) => writeln!(output, "OK {}", s)?,
+        Err(e) => writeln!(output, "ERR {}", e)?,
+    }
+    output.flush()
+}
+
+// Serve commands from `input` until it's closed or "quit" is received
+pub fn run_server<R: BufRead, W: Write>(
+    emu: &mut Emu,
+    input: R,
+    mut output: W,
+) -> std::io::Result<()> {
+    writeln!(output, "READY")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line == "quit" {
+            return respond(&mut output, Ok(String::new()));
+        }
+
+        respond(&mut output, execute(emu, line))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::emu::Machine;
+
+    fn serve(commands: &str) -> Vec<String> {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.init();
+        let mut output = Vec::new();
+        run_server(&mut emu, commands.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_commands() {
+        let out = serve("writemem C000 DEADBEEF\nreadmem C001 2\nreadmem 10000 1\n");
+        assert_eq!(
+            out,
+            vec!["READY", "OK", "OK ADBE", "ERR invalid address: 10000"]
+        );
+    }
+
+    #[test]
+    fn test_frames_are_deterministic() {
+        let out1 = serve("frames 3 a,start\nframe\n");
+        let out2 = serve("frames 3 a,start\nframe\n");
+        assert_eq!(out1, out2);
+        assert!(out1[1].starts_with("OK "));
+    }
+
+    #[test]
+    fn test_savestate() {
+        let filename = std::env::temp_dir().join("rustboy-test-server.state");
+        let filename = filename.to_string_lossy();
+        let out = serve(&format!(
+            "frames 10\nsavestate {0}\nframes 5\nloadstate {0}\nframes 5\n",
+            filename
+        ));
+        assert_eq!(out[2], "OK");
+        assert_eq!(out[4], "OK");
+        assert_eq!(out[3], out[5]);
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let out = serve("frame jump\nfoo\nquit\nhash\n");
+        assert_eq!(
+            out,
+            vec![
+                "READY",
+                "ERR unknown button: jump",
+                "ERR unknown command: foo",
+                "OK"
+            ]
+        );
+
+        // Malformed arguments are errors, not panics
+        let out = serve("readmem 10 FFFFFFFFFFFFFFFF\nwritemem C000 a\u{e9}b\nwritemem C000 +F\n");
+        assert_eq!(
+            out,
+            vec![
+                "READY",
+                "ERR invalid length",
+                "ERR invalid hex bytes",
+                "ERR invalid hex bytes"
+            ]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+// Single-step CPU tests
+//
+// Runs the JSON test vectors from the SingleStepTests project
+// (https://github.com/SingleStepTests/sm83) against the CPU core. Each
+// file holds a thousand tests of one opcode. A test gives the initial
+// registers and memory, and the expected state and bus activity after
+// executing a single instruction.
+//
+// The CPU runs on a flat 64 kB memory that records every bus access,
+// so the timing of reads and writes is verified cycle by cycle. There
+// is no I/O, so the tests don't depend on the rest of the system.
+//
+// Run with `--test sm83-json --test-dir <path to v1 directory>`, or
+// with `cargo test` and the path in the `SM83_TESTS` environment
+// variable.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::gameboy::cpu::{Bus, Cpu};
+use crate::gameboy::instructions;
+
+// Number of failed tests to print for each file
+const MAX_REPORTED_FAILURES: usize = 3;
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ime: u8,
+    ie: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+// Address, data and the state of the read, write and memory request
+// pins during a machine cycle, such as "r-m" for a read. The address
+// and data are null when the bus is idle.
+type Cycle = Option<(Option<u16>, Option<u8>, String)>;
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<Cycle>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+    Internal,
+}
+
+// Flat memory that records every access
+struct TestBus {
+    mem: Vec<u8>,
+    accesses: Vec<Access>,
+}
+
+impl Bus for TestBus {
+    fn read(&mut self, addr: usize) -> u8 {
+        let value = self.mem[addr];
+        self.accesses.push(Access::Read(addr as u16, value));
+        value
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        self.mem[addr] = value;
+        self.accesses.push(Access::Write(addr as u16, value));
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            self.accesses.push(Access::Internal);
+        }
+    }
+
+    fn idle(&mut self, cycles: u32) {
+        self.tick(cycles);
+    }
+
+    fn peek(&self, addr: usize) -> u8 {
+        self.mem[addr]
+    }
+
+    fn poke(&mut self, addr: usize, value: u8) {
+        self.mem[addr] = value;
+    }
+}
+
+fn expected_access(cycle: &Cycle) -> Access {
+    match cycle {
+        Some((Some(addr), Some(value), pins)) if pins.starts_with('r') => {
+            Access::Read(*addr, *value)
+        }
+        Some((Some(addr), Some(value), pins)) if pins.contains('w') => Access::Write(*addr, *value),
+        _ => Access::Internal,
+    }
+}
+
+fn registers(cpu: &Cpu) -> [(&'static str, u16); 11] {
+    let reg = &cpu.reg;
+    [
+        ("A", reg.a as u16),
+        ("F", reg.get_f() as u16),
+        ("B", reg.b as u16),
+        ("C", reg.c as u16),
+        ("D", reg.d as u16),
+        ("E", reg.e as u16),
+        ("H", reg.h as u16),
+        ("L", reg.l as u16),
+        ("SP", reg.sp),
+        ("PC", reg.pc),
+        ("IME", (reg.ime == 2) as u16),
+    ]
+}
+
+fn expected_registers(state: &State) -> [u16; 11] {
+    [
+        state.a as u16,
+        state.f as u16,
+        state.b as u16,
+        state.c as u16,
+        state.d as u16,
+        state.e as u16,
+        state.h as u16,
+        state.l as u16,
+        state.sp,
+        state.pc,
+        state.ime as u16,
+    ]
+}
+
+// Run a single test. Returns a description of the first difference
+// from the expected state, if any.
+fn run_test(test: &TestCase) -> Result<(), String> {
+    let init = &test.initial;
+    let mut bus = TestBus {
+        mem: vec![0; 0x10000],
+        accesses: Vec::new(),
+    };
+    if let Some(ie) = init.ie {
+        bus.mem[0xFFFF] = ie;
+    }
+    for (addr, value) in &init.ram {
+        bus.mem[*addr as usize] = *value;
+    }
+
+    let mut cpu = Cpu::new();
+    cpu.reg.set_af(((init.a as u16) << 8) | init.f as u16);
+    cpu.reg.b = init.b;
+    cpu.reg.c = init.c;
+    cpu.reg.d = init.d;
+    cpu.reg.e = init.e;
+    cpu.reg.h = init.h;
+    cpu.reg.l = init.l;
+    cpu.reg.sp = init.sp;
+    cpu.reg.pc = init.pc;
+    cpu.reg.ime = if init.ime != 0 { 2 } else { 0 };
+
+    // Interrupts are not part of the tests, so only the instruction
+    // is executed
+    instructions::step(&mut cpu, &mut bus);
+
+    let expected = expected_registers(&test.expected);
+    for ((name, actual), expected) in registers(&cpu).iter().zip(expected.iter()) {
+        if actual != expected {
+            return Err(format!(
+                "{}: expected 0x{:04X}, was 0x{:04X}",
+                name, expected, actual
+            ));
+        }
+    }
+
+    for (addr, value) in &test.expected.ram {
+        let actual = bus.mem[*addr as usize];
+        if actual != *value {
+            return Err(format!(
+                "memory at 0x{:04X}: expected 0x{:02X}, was 0x{:02X}",
+                addr, value, actual
+            ));
+        }
+    }
+
+    let expected: Vec<Access> = test.cycles.iter().map(expected_access).collect();
+    if bus.accesses != expected {
+        return Err(format!(
+            "bus activity: expected {:?}, was {:?}",
+            expected, bus.accesses
+        ));
+    }
+
+    Ok(())
+}
+
+// Run all tests in a file. Returns the number of tests and the
+// failures, as "test name: difference".
+fn run_file(path: &Path) -> Result<(usize, Vec<String>), io::Error> {
+    let content = fs::read_to_string(path)?;
+    let tests: Vec<TestCase> = serde_json::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let failures = tests
+        .iter()
+        .filter_map(|test| {
+            run_test(test)
+                .err()
+                .map(|e| format!("{}: {}", test.name, e))
+        })
+        .collect();
+
+    Ok((tests.len(), failures))
+}
+
+// Run the tests in all JSON files in `dir`. Returns the number of
+// failed tests.
+pub fn run_sm83_tests(dir: &str) -> Result<usize, io::Error> {
+    let mut files: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    let mut total = 0;
+    let mut failed = 0;
+    for path in files {
+        let (count, failures) = run_file(&path)?;
+        let name = path.file_name().unwrap().to_string_lossy();
+        if failures.is_empty() {
+            println!("{}: ok", name);
+        } else {
+            println!("{}: {} of {} failed", name, failures.len(), count);
+            for failure in failures.iter().take(MAX_REPORTED_FAILURES) {
+                println!("  {}", failure);
+            }
+        }
+        total += count;
+        failed += failures.len();
+    }
+
+    println!("{} of {} tests passed", total - failed, total);
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_test() {
+        // LD (HL), A: the opcode fetch and a write
+        let json = r#"{
+            "name": "77 0000",
+            "initial": {
+                "pc": 49152, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0,
+                "e": 0, "f": 176, "h": 208, "l": 0, "ime": 0, "ie": 0,
+                "ram": [[49152, 119]]
+            },
+            "final": {
+                "pc": 49153, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0,
+                "e": 0, "f": 176, "h": 208, "l": 0, "ime": 0, "ie": 0,
+                "ram": [[49152, 119], [53248, 66]]
+            },
+            "cycles": [[49152, 119, "r-m"], [53248, 66, "-wm"]]
+        }"#;
+        let mut test: TestCase = serde_json::from_str(json).unwrap();
+        assert_eq!(run_test(&test), Ok(()));
+
+        test.cycles.reverse();
+        assert!(run_test(&test).unwrap_err().starts_with("bus activity"));
+
+        test.expected.a = 0;
+        assert_eq!(
+            run_test(&test),
+            Err("A: expected 0x0000, was 0x0042".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sm83_vectors() {
+        // The test vectors are not part of the repository
+        let dir = match std::env::var("SM83_TESTS") {
+            Ok(dir) => dir,
+            Err(_) => return,
+        };
+        assert_eq!(run_sm83_tests(&dir).unwrap(), 0);
+    }
+}
@@ -0,0 +1,310 @@
+// Symbols and source listings
+//
+// Labels are loaded from the symbol files written by RGBDS (`rgblink
+// -n`) and WLA-DX (`wlalink -S`), which have one label per line:
+//
+//   01:4000 Main
+//
+// and from RGBDS map files (`rgblink -m`), where labels are listed
+// per bank and section:
+//
+//   ROMX bank #1:
+//     SECTION: $4000-$40ff ($0100 bytes) ["Main"]
+//              $4000 = Main
+//
+// Source listings have one source line per line, prefixed by the
+// address of the code it assembled to, with or without the bank:
+//
+//   01:4000 ld a, [hl+]
+//
+// Only the switchable ROM bank and the cartridge RAM bank are told
+// apart. Labels in other areas are looked up by address alone.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Extensions of the files loaded next to the ROM, if present
+pub const SYMBOL_FILE_EXTENSIONS: [&str; 3] = ["sym", "map", "lst"];
+
+pub struct SymbolTable {
+    // Labels by bank and address
+    labels: BTreeMap<(usize, usize), String>,
+
+    // Source lines by bank and address
+    source: HashMap<(usize, usize), String>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Banks are only significant in the switchable ROM area and cartridge
+// RAM. ROMs without banking have their switchable area listed as bank 0.
+fn key(bank: usize, addr: usize) -> (usize, usize) {
+    match addr {
+        0x4000..=0x7FFF => (bank.max(1), addr),
+        0xA000..=0xBFFF => (bank, addr),
+        _ => (0, addr),
+    }
+}
+
+// True if the memory at an address is banked
+pub fn is_banked(addr: usize) -> bool {
+    matches!(addr, 0x4000..=0x7FFF | 0xA000..=0xBFFF)
+}
+
+// Memory area of an address. A label never covers more than one.
+fn area(addr: usize) -> usize {
+    match addr {
+        0x0000..=0x3FFF => 0,
+        0x4000..=0x7FFF => 1,
+        0x8000..=0x9FFF => 2,
+        0xA000..=0xBFFF => 3,
+        0xC000..=0xFDFF => 4,
+        _ => 5,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s.trim_start_matches('$'), 16).ok()
+}
+
+// Parse "BB:AAAA" or "AAAA"
+fn parse_address(s: &str) -> Option<(usize, usize)> {
+    match s.split_once(':') {
+        Some((bank, addr)) => Some((parse_hex(bank)?, parse_hex(addr)?)),
+        None if s.len() == 4 => Some((0, parse_hex(s)?)),
+        None => None,
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable {
+            labels: BTreeMap::new(),
+            source: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.source.is_empty()
+    }
+
+    pub fn add_label(&mut self, bank: usize, addr: usize, name: &str) {
+        self.labels.insert(key(bank, addr), name.to_string());
+    }
+
+    // Load a symbol file, map file or source listing, depending on
+    // the extension. Returns the number of labels or lines loaded.
+    pub fn load(&mut self, filename: &str) -> io::Result<usize> {
+        let content = fs::read_to_string(filename)?;
+        let extension = Path::new(filename)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("sym") => Ok(self.parse_sym(&content)),
+            Some("map") => Ok(self.parse_map(&content)),
+            Some("lst") => Ok(self.parse_listing(&content)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unsupported file type (supported: .sym, .map, .lst)",
+            )),
+        }
+    }
+
+    // Load the symbol files next to a ROM, such as game.sym for
+    // game.gb. Returns the names of the files loaded.
+    pub fn load_for_rom(&mut self, rom: &str) -> Vec<String> {
+        let mut loaded = Vec::new();
+        for extension in SYMBOL_FILE_EXTENSIONS {
+            let path = Path::new(rom).with_extension(extension);
+            if !path.is_file() {
+                continue;
+            }
+            let filename = path.to_string_lossy().to_string();
+            match self.load(&filename) {
+                Ok(_) => loaded.push(filename),
+                Err(e) => println!("Failed to load symbols from {}: {}", filename, e),
+            }
+        }
+        loaded
+    }
+
+    fn parse_sym(&mut self, content: &str) -> usize {
+        let mut count = 0;
+
+        // WLA-DX has several sections, of which only [labels] holds
+        // labels. RGBDS has no sections.
+        let mut in_labels = true;
+
+        for line in content.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.starts_with('[') {
+                in_labels = line == "[labels]";
+                continue;
+            }
+            if !in_labels {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let address = parts.next().filter(|s| s.contains(':'));
+            if let (Some((bank, addr)), Some(name)) =
+                (address.and_then(parse_address), parts.next())
+            {
+                self.add_label(bank, addr, name);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn parse_map(&mut self, content: &str) -> usize {
+        let mut count = 0;
+        let mut bank = 0;
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            // Start of a bank, such as "ROMX bank #1:"
+            if let Some(pos) = line.to_lowercase().find("bank #") {
+                let number = line[pos + 6..].trim_end_matches(':');
+                bank = number.parse().unwrap_or(0);
+                continue;
+            }
+
+            // A label, such as "$4000 = Main"
+            if let Some((addr, name)) = line.split_once(" = ") {
+                if let (true, Some(addr)) = (addr.starts_with('$'), parse_hex(addr)) {
+                    self.add_label(bank, addr, name.trim());
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn parse_listing(&mut self, content: &str) -> usize {
+        let mut count = 0;
+        for line in content.lines() {
+            let (address, text) = match line.split_once(char::is_whitespace) {
+                Some(split) => split,
+                None => continue,
+            };
+            let text = text.trim();
+            if let (Some((bank, addr)), false) = (parse_address(address), text.is_empty()) {
+                // Lines with only a label are followed by the code at
+                // the same address, which is the more useful line
+                self.source.insert(key(bank, addr), text.to_string());
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // Label at exactly this address
+    pub fn label(&self, bank: usize, addr: usize) -> Option<&str> {
+        self.labels.get(&key(bank, addr)).map(|s| s.as_str())
+    }
+
+    // Address of a label, as (bank, address)
+    pub fn find(&self, name: &str) -> Option<(usize, usize)> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(key, _)| *key)
+    }
+
+    // Describe an address as the closest preceding label in the same
+    // bank and memory area, such as "Main" or "Main+$12"
+    pub fn describe(&self, bank: usize, addr: usize) -> Option<String> {
+        let (bank, addr) = key(bank, addr);
+        let ((label_bank, label_addr), name) = self.labels.range(..=(bank, addr)).next_back()?;
+        if *label_bank != bank || area(*label_addr) != area(addr) {
+            return None;
+        }
+        match addr - label_addr {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+${:X}", name, offset)),
+        }
+    }
+
+    // Source line that assembled to this address
+    pub fn source_line(&self, bank: usize, addr: usize) -> Option<&str> {
+        self.source.get(&key(bank, addr)).map(|s| s.as_str())
+    }
+
+    // Append the label of an address to a trace line, if known
+    pub fn annotate(&self, bank: usize, addr: usize, line: String) -> String {
+        match self.describe(bank, addr) {
+            Some(label) => format!("{} ; {}", line, label),
+            None => line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sym() {
+        let mut symbols = SymbolTable::new();
+        let sym = "; File generated by rgblink\n\
+                   00:0150 Start\n\
+                   01:4000 Main\n\
+                   02:4000 Other ; comment\n\
+                   00:c000 wBuffer\n\
+                   01:a000 sSave\n";
+        assert_eq!(symbols.parse_sym(sym), 5);
+
+        assert_eq!(symbols.label(0, 0x150), Some("Start"));
+        assert_eq!(symbols.describe(0, 0x153), Some("Start+$3".to_string()));
+        assert_eq!(symbols.describe(2, 0x4010), Some("Other+$10".to_string()));
+        assert_eq!(symbols.describe(3, 0x4010), None);
+        assert_eq!(symbols.describe(1, 0xC001), Some("wBuffer+$1".to_string()));
+        assert_eq!(symbols.describe(0, 0x8000), None);
+        assert_eq!(symbols.find("Main"), Some((1, 0x4000)));
+        assert_eq!(symbols.find("Missing"), None);
+
+        // Cartridge RAM is banked from bank 0
+        assert_eq!(symbols.label(1, 0xA000), Some("sSave"));
+        assert_eq!(symbols.label(0, 0xA000), None);
+    }
+
+    #[test]
+    fn test_wla_sym() {
+        let mut symbols = SymbolTable::new();
+        let sym = "[labels]\n01:4000 Main\n[definitions]\n00000010 _sizeof_Main\n";
+        assert_eq!(symbols.parse_sym(sym), 1);
+        assert_eq!(symbols.label(1, 0x4000), Some("Main"));
+    }
+
+    #[test]
+    fn test_map() {
+        let mut symbols = SymbolTable::new();
+        let map = "ROM0 bank #0:\n\
+                   \tSECTION: $0150-$0152 ($0003 bytes) [\"Start\"]\n\
+                   \t         $0150 = Start\n\
+                   ROMX bank #3:\n\
+                   \tSECTION: $4000-$4000 ($0001 bytes) [\"Main\"]\n\
+                   \t         $4000 = Main\n";
+        assert_eq!(symbols.parse_map(map), 2);
+        assert_eq!(symbols.find("Start"), Some((0, 0x150)));
+        assert_eq!(symbols.find("Main"), Some((3, 0x4000)));
+    }
+
+    #[test]
+    fn test_listing() {
+        let mut symbols = SymbolTable::new();
+        let listing = "0150 Start:\n0150     di\n01:4000   ld a, [hl+]\n; comment\n";
+        assert_eq!(symbols.parse_listing(listing), 3);
+        assert_eq!(symbols.source_line(0, 0x150), Some("di"));
+        assert_eq!(symbols.source_line(1, 0x4000), Some("ld a, [hl+]"));
+        assert_eq!(symbols.source_line(2, 0x4000), None);
+    }
+}
@@ -1,9 +1,40 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
 use ringbuf::RingBuffer;
+use serde::Serialize;
 
 use crate::debug::Debug;
-use crate::gameboy::emu::Emu;
+use crate::frame_sink::{to_rgb, write_png};
+use crate::gameboy::emu::{Emu, Machine};
+use crate::gameboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::CLOCK_SPEED;
+use crate::palette::GRAYSCALE;
+use crate::thumbnails::is_rom;
 use crate::utils::read_zero_terminated_string;
 
+// Default time limit for each ROM in batch mode, in emulated seconds.
+// The slowest single Blargg tests finish in about 30 seconds.
+pub const DEFAULT_TEST_TIMEOUT: usize = 60;
+
+// Number of instructions to execute between checks of the result
+const CHECK_INTERVAL: usize = 1024;
+
+// Register values and serial output of a passed Mooneye test
+const MOONEYE_FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+// Serial output of a failed Mooneye test
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+// Number of frames to run before capturing the screen. dmg-acid2 has
+// finished drawing by then.
+pub const DEFAULT_CAPTURE_FRAMES: usize = 355;
+
+// Written to the working directory when the screen doesn't match the
+// reference image
+const DIFF_FILENAME: &str = "diff.png";
+
 pub fn test_runner_expect(expect: &str, emu: &mut Emu) {
     let echo_serial: bool = false;
     let mut output: String = "".to_string();
@@ -12,7 +43,7 @@ pub fn test_runner_expect(expect: &str, emu: &mut Emu) {
     emu.mmu.serial.output = Some(producer);
 
     loop {
-        emu.mmu.exec_op();
+        emu.step();
 
         match consumer.pop() {
             Some(c) => {
@@ -41,28 +72,106 @@ pub fn test_runner_expect(expect: &str, emu: &mut Emu) {
     std::process::exit(0);
 }
 
+fn run_frames(emu: &mut Emu, debug: &mut Debug, frames: usize) {
+    let mut frame: usize = 0;
+    while frame < frames {
+        debug.before_op(emu);
+        emu.step();
+        if emu.mmu.display_updated {
+            frame += 1;
+            emu.mmu.display_updated = false;
+        }
+    }
+}
+
+// Read a PNG file as 8-bit RGB. Returns the width, height and pixels.
+fn read_png_rgb(filename: &str) -> Result<(usize, usize, Vec<u8>), String> {
+    use png::HasParameters;
+
+    let file = fs::File::open(filename).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+
+    if info.bit_depth != png::BitDepth::Eight {
+        return Err(format!("unsupported bit depth: {:?}", info.bit_depth));
+    }
+
+    let rgb = match info.color_type {
+        png::ColorType::RGB => buf,
+        png::ColorType::RGBA => buf.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|g| [*g, *g, *g]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect(),
+        ct => return Err(format!("unsupported color type: {:?}", ct)),
+    };
+    Ok((info.width as usize, info.height as usize, rgb))
+}
+
+// Compare two RGB images of the same size. Returns the number of
+// pixels that differ, and a diff image with the differing pixels in
+// red on top of a faded copy of the expected image.
+pub fn compare_images(actual: &[u8], expected: &[u8]) -> (usize, Vec<u8>) {
+    let mut mismatches = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (a, e) in actual.chunks(3).zip(expected.chunks(3)) {
+        if a == e {
+            let gray = ((e[0] as u16 + e[1] as u16 + e[2] as u16) / 3) as u8;
+            let faded = 0xC0 + gray / 4;
+            diff.extend([faded, faded, faded]);
+        } else {
+            mismatches += 1;
+            diff.extend([0xFF, 0x00, 0x00]);
+        }
+    }
+    (mismatches, diff)
+}
+
+// Run for a number of frames and compare the screen with a reference
+// image, such as the dmg-acid2 reference. A diff image is written if
+// they don't match.
+pub fn test_runner_compare(reference: &str, frames: usize, emu: &mut Emu, debug: &mut Debug) {
+    let (width, height, expected) = match read_png_rgb(reference) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("Failed to read reference image {}: {}", reference, e);
+            std::process::exit(1);
+        }
+    };
+    if (width, height) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        println!(
+            "Reference image is {}x{}, expected {}x{}",
+            width, height, SCREEN_WIDTH, SCREEN_HEIGHT
+        );
+        std::process::exit(1);
+    }
+
+    run_frames(emu, debug, frames);
+
+    // Grayscale is used in the dmg-acid2 ref images
+    let actual = to_rgb(&emu.mmu.ppu.buffer, GRAYSCALE);
+    let (mismatches, diff) = compare_images(&actual, &expected);
+    if mismatches == 0 {
+        println!("PASS!");
+        std::process::exit(0);
+    }
+
+    println!("{} pixels differ from {}", mismatches, reference);
+    match write_png(DIFF_FILENAME, width, height, &diff) {
+        Ok(()) => println!("Diff written to {}", DIFF_FILENAME),
+        Err(e) => println!("Failed to write diff image: {}", e),
+    }
+    std::process::exit(1);
+}
+
 pub fn test_runner(variant: &str, emu: &mut Emu, debug: &mut Debug) {
     match variant {
         "capture" => {
-            let mut frame: usize = 0;
-            while frame < 355 {
-                debug.before_op(emu);
-                emu.mmu.exec_op();
-                if emu.mmu.display_updated {
-                    frame += 1;
-                    emu.mmu.display_updated = false;
-                }
-            }
-
-            // These are the colors used in the dmg-acid2 ref images
-            let palette: [(u8, u8, u8); 4] = [
-                (0xFF, 0xFF, 0xFF),
-                (0xAA, 0xAA, 0xAA),
-                (0x55, 0x55, 0x55),
-                (0x00, 0x00, 0x00),
-            ];
+            run_frames(emu, debug, DEFAULT_CAPTURE_FRAMES);
 
-            emu.mmu.ppu.capture("capture.png", palette).unwrap();
+            // Grayscale is used in the dmg-acid2 ref images
+            emu.mmu.ppu.capture("capture.png", &GRAYSCALE).unwrap();
             std::process::exit(0);
         }
 
@@ -84,11 +193,11 @@ pub fn test_runner(variant: &str, emu: &mut Emu, debug: &mut Debug) {
             debug.source_code_breakpoints = true;
 
             while debug.before_op(emu) {
-                emu.mmu.exec_op();
+                emu.step();
             }
 
             // Verify that registers hold Fibonacci sequence
-            let reg = &emu.mmu.reg;
+            let reg = &emu.cpu.reg;
             if reg.b == 3 && reg.c == 5 && reg.d == 8 && reg.e == 13 && reg.h == 21 && reg.l == 34 {
                 println!("Fibonacci registers: ok!");
             } else {
@@ -117,21 +226,21 @@ pub fn test_runner(variant: &str, emu: &mut Emu, debug: &mut Debug) {
             while emu.mmu.direct_read(0xA000) != 0x80 {
                 // println!(
                 //     "PC = {:04x} 0xA000 = {}, 0xFF01={}, 0xFF02={} {}",
-                //     emu.mmu.reg.pc,
+                //     emu.cpu.reg.pc,
                 //     emu.mmu.direct_read(0xA000),
                 //     emu.mmu.direct_read(0xFF01),
                 //     emu.mmu.direct_read(0xFF02),
-                //     format_mnemonic(&emu.mmu, emu.mmu.reg.pc),
+                //     format_mnemonic(&emu.mmu, &emu.cpu.reg, emu.cpu.reg.pc),
                 // );
                 debug.before_op(emu);
-                emu.mmu.exec_op();
+                emu.step();
             }
 
             // Then run until test finishes (0xA000 != 0x80)
             while emu.mmu.direct_read(0xA000) == 0x80 {
                 // println!("0xA000 = {}", emu.mmu.direct_read(0xA000));
                 // debug.before_op(emu);
-                emu.mmu.exec_op();
+                emu.step();
             }
 
             // Validate signature
@@ -174,7 +283,404 @@ pub fn test_runner(variant: &str, emu: &mut Emu, debug: &mut Debug) {
             println!(" - mooneye");
             println!(" - blargg");
             println!(" - capture");
+            println!(" - compare (with --test-reference)");
             std::process::exit(1);
         }
     }
 }
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+}
+
+#[derive(Serialize)]
+pub struct TestResult {
+    pub rom: String,
+    pub outcome: Outcome,
+
+    // Reason for the outcome, such as the result code or the text
+    // printed by the test
+    pub message: String,
+
+    // Emulated time until the outcome was known
+    pub seconds: f64,
+}
+
+#[derive(Serialize)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub timeout: usize,
+    pub results: Vec<TestResult>,
+}
+
+// Checks the state of a running test ROM for signs that it has
+// finished. Both the Blargg and the Mooneye conventions are checked,
+// so the suite doesn't have to be known in advance.
+struct ResultDetector {
+    serial: Vec<u8>,
+
+    // Set when the Blargg status byte at 0xA000 has been 0x80
+    // ("running") with a valid signature
+    blargg_running: bool,
+}
+
+impl ResultDetector {
+    fn new() -> Self {
+        ResultDetector {
+            serial: Vec::new(),
+            blargg_running: false,
+        }
+    }
+
+    fn serial_text(&self) -> String {
+        String::from_utf8_lossy(&self.serial).trim().to_string()
+    }
+
+    fn check(&mut self, emu: &Emu) -> Option<(Outcome, String)> {
+        // Mooneye: "LD B,B" when done, with Fibonacci numbers
+        // in the registers if the test passed
+        if emu.mmu.direct_read(emu.cpu.reg.pc as usize) == 0x40 {
+            let reg = &emu.cpu.reg;
+            let regs = [reg.b, reg.c, reg.d, reg.e, reg.h, reg.l];
+            return Some(if regs == MOONEYE_FIBONACCI {
+                (Outcome::Pass, "Fibonacci registers".to_string())
+            } else {
+                (Outcome::Fail, format!("registers {:02X?}", regs))
+            });
+        }
+
+        // Mooneye tests also send the result over serial
+        if self.serial.ends_with(&MOONEYE_FIBONACCI) {
+            return Some((Outcome::Pass, "Fibonacci serial output".to_string()));
+        }
+        if self.serial.ends_with(&MOONEYE_FAIL) {
+            return Some((Outcome::Fail, "failure serial output".to_string()));
+        }
+
+        // Blargg: result code in cartridge RAM, and text output both
+        // in cartridge RAM and over serial
+        let signature = [0xA001, 0xA002, 0xA003].map(|a| emu.mmu.direct_read(a));
+        if signature == [0xDE, 0xB0, 0x61] {
+            let status = emu.mmu.direct_read(0xA000);
+            if status == 0x80 {
+                self.blargg_running = true;
+            } else if self.blargg_running {
+                let text = read_zero_terminated_string(&emu.mmu, 0xA004)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                let outcome = if status == 0 {
+                    Outcome::Pass
+                } else {
+                    Outcome::Fail
+                };
+                return Some((outcome, format!("result code {:02X}: {}", status, text)));
+            }
+        }
+
+        let text = self.serial_text();
+        if text.ends_with("Passed") || text.ends_with("Passed all tests") {
+            return Some((Outcome::Pass, text));
+        }
+        if text.contains("Failed") {
+            return Some((Outcome::Fail, text));
+        }
+
+        None
+    }
+}
+
+// Run a single test ROM until it reports a result, or until
+// `timeout` emulated seconds have passed
+fn run_test_rom(rom: &Path, bootstrap_rom: &str, machine: Machine, timeout: usize) -> TestResult {
+    let name = rom.to_string_lossy().to_string();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut emu = Emu::new(machine);
+        emu.init();
+        emu.load_bootstrap(bootstrap_rom);
+        if let Err(e) = emu.load_cartridge(&name) {
+            return (Outcome::Fail, format!("failed to load: {}", e), 0.0);
+        }
+
+        let serial_buf = RingBuffer::<u8>::new(16);
+        let (producer, mut consumer) = serial_buf.split();
+        emu.mmu.serial.output = Some(producer);
+
+        let mut detector = ResultDetector::new();
+        let limit = (timeout * CLOCK_SPEED) as u64;
+        let seconds = |emu: &Emu| emu.mmu.timer.abs_cycle as f64 / CLOCK_SPEED as f64;
+
+        while emu.mmu.timer.abs_cycle < limit {
+            for _ in 0..CHECK_INTERVAL {
+                emu.step();
+                while let Some(c) = consumer.pop() {
+                    detector.serial.push(c);
+                }
+
+                // Mooneye tests end with "LD B,B" in an infinite loop,
+                // so it has to be checked after every instruction
+                if emu.mmu.direct_read(emu.cpu.reg.pc as usize) == 0x40 {
+                    break;
+                }
+            }
+
+            if let Some((outcome, message)) = detector.check(&emu) {
+                return (outcome, message, seconds(&emu));
+            }
+        }
+
+        let message = match detector.serial_text() {
+            text if text.is_empty() => "no result".to_string(),
+            text => text,
+        };
+        (Outcome::Timeout, message, seconds(&emu))
+    }));
+
+    let (outcome, message, seconds) = match result {
+        Ok(result) => result,
+        Err(_) => (Outcome::Fail, "emulation panicked".to_string(), 0.0),
+    };
+
+    TestResult {
+        rom: name,
+        outcome,
+        message,
+        seconds,
+    }
+}
+
+// All ROMs in `dir` and its subdirectories, sorted by path
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if is_rom(&path) {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(())
+}
+
+// Run every test ROM in `rom_dir`, and its subdirectories, with a
+// limit of `timeout` emulated seconds per ROM
+pub fn run_test_dir(
+    rom_dir: &str,
+    bootstrap_rom: &str,
+    machine: Machine,
+    timeout: usize,
+) -> Result<TestReport, std::io::Error> {
+    let mut roms = Vec::new();
+    find_roms(Path::new(rom_dir), &mut roms)?;
+
+    let mut report = TestReport {
+        passed: 0,
+        failed: 0,
+        timeout: 0,
+        results: Vec::new(),
+    };
+
+    for rom in roms.iter() {
+        let result = run_test_rom(rom, bootstrap_rom, machine, timeout);
+        let label = match result.outcome {
+            Outcome::Pass => {
+                report.passed += 1;
+                "PASS"
+            }
+            Outcome::Fail => {
+                report.failed += 1;
+                "FAIL"
+            }
+            Outcome::Timeout => {
+                report.timeout += 1;
+                "TIME"
+            }
+        };
+        println!("{} {}: {}", label, result.rom, result.message);
+        report.results.push(result);
+    }
+
+    println!(
+        "{} passed, {} failed, {} timed out",
+        report.passed, report.failed, report.timeout
+    );
+
+    Ok(report)
+}
+
+impl TestReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("failed to serialize test report")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        md.push_str(&format!(
+            "{} passed, {} failed, {} timed out\n\n",
+            self.passed, self.failed, self.timeout
+        ));
+        md.push_str("| ROM | Result | Time (s) | Message |\n");
+        md.push_str("| --- | --- | ---: | --- |\n");
+        for result in self.results.iter() {
+            let outcome = match result.outcome {
+                Outcome::Pass => "pass",
+                Outcome::Fail => "fail",
+                Outcome::Timeout => "timeout",
+            };
+            md.push_str(&format!(
+                "| {} | {} | {:.1} | {} |\n",
+                result.rom,
+                outcome,
+                result.seconds,
+                result.message.replace('|', "\\|").replace('\n', " ")
+            ));
+        }
+        md
+    }
+
+    // Write the report as markdown if the filename ends with ".md",
+    // and as JSON otherwise
+    pub fn save(&self, filename: &str) -> Result<(), std::io::Error> {
+        let content = if filename.ends_with(".md") {
+            self.to_markdown()
+        } else {
+            self.to_json()
+        };
+        fs::write(filename, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_mooneye_registers() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.mmu.direct_write(0xC000, 0x40);
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut detector = ResultDetector::new();
+        let (outcome, _) = detector.check(&emu).unwrap();
+        assert_eq!(outcome, Outcome::Fail);
+
+        let reg = &mut emu.cpu.reg;
+        reg.b = 3;
+        reg.c = 5;
+        reg.d = 8;
+        reg.e = 13;
+        reg.h = 21;
+        reg.l = 34;
+        let (outcome, _) = detector.check(&emu).unwrap();
+        assert_eq!(outcome, Outcome::Pass);
+    }
+
+    #[test]
+    fn test_detect_serial_output() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.cpu.reg.pc = 0xC000;
+
+        let mut detector = ResultDetector::new();
+        detector.serial.extend(b"cpu_instrs\n\n01:ok ");
+        assert!(detector.check(&emu).is_none());
+
+        detector.serial.extend(b"\n\nPassed all tests\n");
+        let (outcome, message) = detector.check(&emu).unwrap();
+        assert_eq!(outcome, Outcome::Pass);
+        assert!(message.starts_with("cpu_instrs"));
+
+        let mut detector = ResultDetector::new();
+        detector.serial.extend(&MOONEYE_FAIL);
+        assert_eq!(detector.check(&emu).unwrap().0, Outcome::Fail);
+    }
+
+    #[test]
+    fn test_compare_images() {
+        let pixels: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|i| (i % 4) as u8)
+            .collect();
+        let expected = to_rgb(&pixels, GRAYSCALE);
+        let path = std::env::temp_dir().join("rustboy_test_compare.png");
+        let filename = path.to_str().unwrap();
+        write_png(filename, SCREEN_WIDTH, SCREEN_HEIGHT, &expected).unwrap();
+        let (width, height, reference) = read_png_rgb(filename).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((width, height), (SCREEN_WIDTH, SCREEN_HEIGHT));
+        assert_eq!(compare_images(&expected, &reference).0, 0);
+
+        let mut actual = expected.clone();
+        actual[3..6].copy_from_slice(&[1, 2, 3]);
+        let (mismatches, diff) = compare_images(&actual, &reference);
+        assert_eq!(mismatches, 1);
+        assert_eq!(diff[3..6], [0xFF, 0x00, 0x00]);
+        assert_ne!(diff[0..3], [0xFF, 0x00, 0x00]);
+    }
+
+    // Run the ROMs in a directory of a test suite. The test ROMs are
+    // not part of the repository, so nothing is run unless the path to
+    // the suite is given in the environment variable `suite_var`:
+    // MOONEYE_TESTS for the Mooneye test suite and BLARGG_TESTS for
+    // Blargg's tests. The boot ROM is taken from RUSTBOY_BOOT_ROM, or
+    // the default location.
+    fn run_suite_dir(suite_var: &str, dir: &str) {
+        let suite = match std::env::var(suite_var) {
+            Ok(suite) => suite,
+            Err(_) => return,
+        };
+        let bootstrap = std::env::var("RUSTBOY_BOOT_ROM")
+            .unwrap_or_else(|_| crate::gameboy::BOOTSTRAP_ROM.to_string());
+        let path = Path::new(&suite).join(dir);
+
+        let report = run_test_dir(
+            path.to_str().unwrap(),
+            &bootstrap,
+            Machine::GameBoyDMG,
+            DEFAULT_TEST_TIMEOUT,
+        )
+        .unwrap();
+        let failed: Vec<&str> = report
+            .results
+            .iter()
+            .filter(|r| r.outcome != Outcome::Pass)
+            .map(|r| r.rom.as_str())
+            .collect();
+        assert!(failed.is_empty(), "failed: {:?}", failed);
+    }
+
+    #[test]
+    fn test_mooneye_timer() {
+        run_suite_dir("MOONEYE_TESTS", "acceptance/timer");
+    }
+
+    // Unused bits of registers, OAM and unmapped I/O registers
+    #[test]
+    fn test_mooneye_bits() {
+        run_suite_dir("MOONEYE_TESTS", "acceptance/bits");
+    }
+
+    // The individual ROMs are used rather than the combined ones, so
+    // that a failure names the failing test
+    #[test]
+    fn test_blargg_cpu_instrs() {
+        run_suite_dir("BLARGG_TESTS", "cpu_instrs/individual");
+    }
+
+    #[test]
+    fn test_blargg_instr_timing() {
+        run_suite_dir("BLARGG_TESTS", "instr_timing");
+    }
+
+    #[test]
+    fn test_blargg_mem_timing() {
+        run_suite_dir("BLARGG_TESTS", "mem_timing/individual");
+    }
+}
@@ -0,0 +1,155 @@
+// Bulk headless screenshots
+//
+// Runs every ROM in a directory for a number of frames without any
+// UI, and captures a screenshot of each. The screenshots are named by
+// the SHA-1 of the ROM file, which is what most ROM databases (such
+// as No-Intro) use to identify games. Useful for frontends building
+// game galleries, and for smoke-testing a large library of games.
+//
+// The boot ROM is skipped, as in the Python module, and the screen is
+// saved at half size.
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use crate::frame_sink::{to_rgb, write_png};
+use crate::gameboy::emu::{Emu, Machine};
+use crate::gameboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::palette::CLASSIC_GREEN;
+
+// Default number of frames to run before capturing the screen.
+// About 10 seconds, which is enough to get past most publisher logos.
+pub const DEFAULT_THUMBNAIL_FRAMES: usize = 600;
+
+// The screen is shrunk by this factor, to 80x72 pixels
+const THUMBNAIL_DOWNSCALE: usize = 2;
+
+const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "sgb"];
+
+pub(crate) fn is_rom(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ROM_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+fn rom_hash(path: &Path) -> Result<String, std::io::Error> {
+    let content = fs::read(path)?;
+    Ok(sha1_smol::Sha1::from(&content).digest().to_string())
+}
+
+// Shrink an RGB image by `factor`, averaging each block of pixels.
+// Pixels that don't fill a whole block at the right and bottom edges
+// are dropped.
+fn downscale(rgb: &[u8], width: usize, height: usize, factor: usize) -> Vec<u8> {
+    let (w, h) = (width / factor, height / factor);
+    let mut scaled = Vec::with_capacity(w * h * 3);
+    for y in 0..h {
+        for x in 0..w {
+            for c in 0..3 {
+                let mut sum = 0;
+                for dy in 0..factor {
+                    for dx in 0..factor {
+                        let i = (y * factor + dy) * width + x * factor + dx;
+                        sum += rgb[i * 3 + c] as usize;
+                    }
+                }
+                scaled.push((sum / (factor * factor)) as u8);
+            }
+        }
+    }
+    scaled
+}
+
+// Run a single ROM for `frames` frames and capture the screen
+fn capture_rom(rom: &Path, machine: Machine, frames: usize, filename: &str) -> Result<(), String> {
+    let rom = rom.to_string_lossy().to_string();
+
+    // Emulation of broken or unsupported games might panic. Catch
+    // it so that the rest of the library is still processed.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut emu = Emu::new(machine);
+        emu.init();
+        emu.load_cartridge(&rom)?;
+        emu.skip_bootrom();
+
+        let target = emu.mmu.ppu.frame_number + frames;
+        while emu.mmu.ppu.frame_number < target {
+            emu.step();
+        }
+
+        let rgb = to_rgb(&emu.mmu.ppu.buffer, CLASSIC_GREEN);
+        let f = THUMBNAIL_DOWNSCALE;
+        let thumbnail = downscale(&rgb, SCREEN_WIDTH, SCREEN_HEIGHT, f);
+        write_png(filename, SCREEN_WIDTH / f, SCREEN_HEIGHT / f, &thumbnail)
+            .map_err(|e| format!("failed to write {}: {}", filename, e))
+    }));
+
+    match result {
+        Ok(result) => result,
+        Err(_) => Err("emulation panicked".to_string()),
+    }
+}
+
+// Capture screenshots of all ROMs in `rom_dir` into `output_dir`.
+// Returns the number of ROMs that failed.
+pub fn capture_thumbnails(
+    rom_dir: &str,
+    output_dir: &str,
+    machine: Machine,
+    frames: usize,
+) -> Result<usize, std::io::Error> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(rom_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_rom(path))
+        .collect();
+    roms.sort();
+
+    fs::create_dir_all(output_dir)?;
+
+    let mut failed = 0;
+
+    for rom in roms.iter() {
+        let hash = match rom_hash(rom) {
+            Ok(hash) => hash,
+            Err(e) => {
+                println!("FAIL {}: {}", rom.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let filename = Path::new(output_dir).join(format!("{}.png", hash));
+        let filename = filename.to_string_lossy();
+
+        match capture_rom(rom, machine, frames, &filename) {
+            Ok(_) => println!("OK   {} -> {}", rom.display(), filename),
+            Err(e) => {
+                println!("FAIL {}: {}", rom.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("Captured {} of {} ROMs", roms.len() - failed, roms.len());
+
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downscale() {
+        // 4x2 pixels, shrunk to 2x1
+        let rgb = [
+            0, 0, 0, 255, 255, 255, 10, 20, 30, 10, 20, 30, //
+            0, 0, 0, 255, 255, 255, 30, 40, 50, 30, 40, 50,
+        ];
+        assert_eq!(downscale(&rgb, 4, 2, 2), vec![127, 127, 127, 20, 30, 40]);
+        assert_eq!(downscale(&rgb, 4, 2, 1), rgb.to_vec());
+    }
+}
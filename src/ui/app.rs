@@ -1,22 +1,53 @@
-use std::{iter, sync::Arc, time::Instant, usize::MAX};
+use std::{
+    iter,
+    sync::{Arc, Mutex},
+    time::Instant,
+    usize::MAX,
+};
 
-use crate::{debug::Debug, gameboy::emu::Emu, APPNAME};
-use egui::{FontDefinitions, Label};
+use crate::{
+    config::Config,
+    debug::Debug,
+    frame_sink::{
+        create_video_sink, save_blended_png, save_png, BlendMode, FrameRecorder, PngSequenceSink,
+        VideoFormat,
+    },
+    gameboy::emu::Emu,
+    palette::{preset_name, DmgPalette, PRESETS},
+    replay_buffer::{ReplayBuffer, DEFAULT_REPLAY_FRAMES},
+    savestate::{StateFile, StateSlots, Thumbnail, DEFAULT_STATE_DIR},
+    APPNAME,
+};
+use egui::{Color32, FontDefinitions, Key, Label, Rect, Sense, TextureId, Ui};
 use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
 use egui_winit_platform::{Platform, PlatformDescriptor};
 use ringbuf::{Consumer, RingBuffer};
 use wgpu::{Device, FilterMode, Queue, Surface, SurfaceConfiguration};
+use winit::event::{ElementState, ModifiersState, VirtualKeyCode};
 use winit::window::Window;
 use winit::{event::Event::*, event_loop::ControlFlow};
 
-use crate::{core::Core, gameboy::CLOCK_SPEED};
+use crate::{
+    core::{Core, Debuggable, OverlayText},
+    gameboy::CLOCK_SPEED,
+};
 
 use super::{
-    audio_player::AudioPlayer, gameboy::main_window::MainWindow, render_stats::RenderStats,
+    audio_player::AudioPlayer,
+    audio_settings_window::AudioSettingsWindow,
+    core_thread::{Command, CoreState, CoreThread, Frame},
+    gameboy::main_window::MainWindow,
+    render_stats::{render_stats_overlay, PlaybackStats, RenderStats},
+    screen::{dim_from, render_input_display, ScaleMode, ScreenFilter},
+    states_window::{SlotAction, StatesWindow},
+    theme::{apply_theme, Theme, MAX_FONT_SIZE, MIN_FONT_SIZE},
+    utils::{render_waveform, timestamped_filename},
+    window_layout,
 };
 
 pub const PIXEL_SIZE: usize = 4;
 pub const TARGET_FPS: f64 = 59.727500569606;
+pub const AUDIO_SAMPLE_RATE: u32 = 44100;
 
 /// A custom event type for the winit app.
 pub enum AppEvent {
@@ -71,56 +102,433 @@ pub struct MoeApp<T: Core, W: MainWindow<T>> {
     audio: AudioPlayer,
     texture_buffer: Box<[u8]>,
 
+    // Screen texture after the filter has been applied
+    filtered_buffer: Vec<u8>,
+
+    config: Config,
+
+    // File that the configuration is saved to
+    config_file: String,
+
+    // Theme and font size currently applied to the UI
+    applied_theme: Option<(Theme, f32)>,
+
     // Statistics for the UI frame rate
     ui_render_stats: RenderStats,
 
-    // Statistics for the emulator frame rate
+    // Statistics for the emulator frame rate, with the host time
+    // spent emulating each frame
     pub emu_render_stats: RenderStats,
     previous_frame_time: Option<f32>,
 
-    core: T,
+    // Audio and dropped frames, shown in the stats overlay together
+    // with the frame times
+    playback_stats: PlaybackStats,
+    stats_overlay: bool,
+
+    // The last few seconds of frames and audio, for instant replay export
+    replay: ReplayBuffer,
+
+    // Audio samples of the most recent frame
+    audio_frame: Vec<i16>,
+
+    // Dumps frames to a directory as PNG files
+    frame_dump: Option<FrameRecorder>,
+
+    // Video recording, and the settings used for the next recording
+    video: Option<FrameRecorder>,
+    video_format: VideoFormat,
+    video_skip: usize,
+    video_scale: usize,
+
+    // Save a screenshot when this frame is reached
+    capture_frame: Option<usize>,
+
+    // If set, screenshots are the last two frames blended together
+    screenshot_blend: Option<BlendMode>,
+
+    // In frame advance mode, the emulator is paused and
+    // runs a single frame at a time when requested
+    frame_advance: bool,
+
+    // Play the audio of each frame stepped in frame advance mode
+    scrub_audio: bool,
+
+    // The most recent frame, and its frame number
+    framebuffer: Vec<u8>,
+    frame_number: usize,
+
+    // The frame before the most recent, for blended screenshots
+    previous_framebuffer: Vec<u8>,
+
+    // In beam racing mode, the screen shows the framebuffer of the
+    // core as it is being drawn, with the part not yet drawn in this
+    // frame dimmed. Updated on every UI frame, also while stepping.
+    beam_racing: bool,
+    live_framebuffer: Vec<u8>,
+    beam_position: usize,
+
+    // Savestate slots of the current game, and the window listing them
+    state_slots: StateSlots,
+    states_window: StatesWindow,
+    states_window_open: bool,
+
+    audio_settings_window: AudioSettingsWindow,
+    audio_settings_open: bool,
+
+    // Set once the saved window layout has been handed to egui
+    layout_restored: bool,
+
+    // Palette of a DMG game colorized by the CGB boot ROM. Used
+    // instead of the configured palette when colorization is enabled.
+    colorization: Option<DmgPalette>,
+
+    // Modifier keys held, for the savestate hotkeys
+    modifiers: ModifiersState,
+
+    // The core and the debugger, shared with the core thread
+    state: Arc<Mutex<CoreState<T>>>,
+    core_thread: Option<CoreThread>,
+
     main_window: W,
 }
 
-impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
+impl<T: 'static + Debuggable + Send, W: 'static + MainWindow<T>> MoeApp<T, W> {
     pub fn setup_serial(&mut self) {
         let buf = RingBuffer::<u8>::new(128);
         let (producer, consumer) = buf.split();
-        self.core.register_serial_output_buffer(producer);
+        self.state
+            .lock()
+            .unwrap()
+            .core
+            .register_serial_output_buffer(producer);
         self.serial_buffer_consumer = Some(consumer);
     }
 
     pub fn setup_audio(&mut self) {
-        self.audio.setup();
-        self.core.set_audio_rates(CLOCK_SPEED as f64 / 4.0, 44100.0)
+        self.audio.setup(&self.config.audio);
+        self.state
+            .lock()
+            .unwrap()
+            .core
+            .set_audio_rates(CLOCK_SPEED as f64 / 4.0, AUDIO_SAMPLE_RATE as f64)
     }
 
-    pub fn run_until_next_frame(&mut self, debug: &mut Debug) {
-        let frame = self.core.current_frame();
+    // Restart playback with the current audio settings
+    fn restart_audio(&mut self) {
+        self.audio.setup(&self.config.audio);
+        let producer = self.audio.producer.take();
+        self.send(Command::SetAudio(producer));
+    }
 
-        while debug.before_op(&mut self.core) && frame == self.core.current_frame() {
-            self.core.exec_op();
+    fn send(&self, command: Command) {
+        if let Some(ref thread) = self.core_thread {
+            thread.send(command);
         }
+    }
 
-        if self.core.current_frame() != frame {
-            self.core.end_audio_frame();
-            match self.audio.producer {
-                Some(ref mut p) => self.core.push_audio_samples(p),
-                None => {}
+    // Pass the frames completed by the core thread on to replay and
+    // recording
+    fn receive_frames(&mut self, elapsed_time: f64) {
+        let frames: Vec<Frame> = match self.core_thread {
+            Some(ref thread) => thread.frames().collect(),
+            None => return,
+        };
+
+        let stats = &mut self.playback_stats;
+        stats.unshown_frames += frames.len().saturating_sub(1);
+        if let Some(frame) = frames.last() {
+            stats.audio_fill = frame.audio_fill;
+            stats.late_frames = frame.late_frames;
+        }
+        stats.dropped_samples += frames.iter().map(|f| f.dropped_samples).sum::<usize>();
+
+        for frame in frames {
+            self.replay.push(&frame.framebuffer, &frame.audio);
+            self.dump_frame(frame.number, &frame.framebuffer);
+            self.emu_render_stats
+                .on_new_frame(elapsed_time, Some(frame.time));
+
+            self.audio_frame = frame.audio;
+            self.frame_number = frame.number;
+            let previous = std::mem::replace(&mut self.framebuffer, frame.framebuffer);
+            let older = std::mem::replace(&mut self.previous_framebuffer, previous);
+            if let Some(ref thread) = self.core_thread {
+                thread.recycle(older);
+            }
+        }
+    }
+
+    fn toggle_frame_advance(&mut self) {
+        self.frame_advance = !self.frame_advance;
+        self.send(Command::FrameAdvance(self.frame_advance));
+    }
+
+    fn advance_frame(&self) {
+        self.send(Command::Advance {
+            play_audio: self.scrub_audio,
+        });
+    }
+
+    // Dump every frame (minus skipped frames) to a directory as PNG files
+    pub fn record_frames(&mut self, dir: &str, skip: usize) -> Result<(), std::io::Error> {
+        let sink = PngSequenceSink::create(dir, self.fb_width, self.fb_height, 1, self.palette())?;
+        self.frame_dump = Some(FrameRecorder::new(Box::new(sink), skip, TARGET_FPS));
+        Ok(())
+    }
+
+    pub fn set_video_options(&mut self, format: VideoFormat, skip: usize, scale: usize) {
+        self.video_format = format;
+        self.video_skip = skip;
+        self.video_scale = scale;
+    }
+
+    fn start_video(&mut self) {
+        let filename = timestamped_filename("video", self.video_format.extension());
+        match create_video_sink(
+            self.video_format,
+            &filename,
+            self.fb_width,
+            self.fb_height,
+            self.video_scale,
+            self.palette(),
+        ) {
+            Ok(sink) => {
+                self.video = Some(FrameRecorder::new(sink, self.video_skip, TARGET_FPS));
+                println!("Recording video to {}", filename);
+            }
+            Err(e) => println!("Failed to start video recording: {}", e),
+        }
+    }
+
+    pub fn stop_video(&mut self) {
+        if let Some(video) = self.video.take() {
+            match video.finish() {
+                Ok(_) => println!("Video recording stopped"),
+                Err(e) => println!("Failed to finish video recording: {}", e),
             }
         }
     }
 
+    fn toggle_video(&mut self) {
+        if self.video.is_some() {
+            self.stop_video();
+        } else {
+            self.start_video();
+        }
+    }
+
+    // Save a screenshot when frame `frame` is reached
+    pub fn capture_at_frame(&mut self, frame: usize) {
+        self.capture_frame = Some(frame);
+    }
+
+    pub fn set_screenshot_blend(&mut self, blend: Option<BlendMode>) {
+        self.screenshot_blend = blend;
+    }
+
+    fn dump_frame(&mut self, frame: usize, framebuffer: &[u8]) {
+        if self.capture_frame == Some(frame) {
+            self.capture_frame = None;
+            self.save_screenshot(
+                &format!("capture-{:06}.png", frame),
+                framebuffer,
+                &self.framebuffer,
+            );
+        }
+
+        if let Some(ref mut recorder) = self.frame_dump {
+            if let Err(e) = recorder.push(frame, framebuffer) {
+                println!("Failed to dump frame: {}", e);
+                self.frame_dump = None;
+            }
+        }
+
+        if let Some(ref mut recorder) = self.video {
+            if let Err(e) = recorder.push(frame, framebuffer) {
+                println!("Failed to record video frame: {}", e);
+                self.video = None;
+            }
+        }
+    }
+
+    // Save `framebuffer` as a screenshot. `previous` is the frame
+    // before, which is blended in if enabled.
+    fn save_screenshot(&self, filename: &str, framebuffer: &[u8], previous: &[u8]) {
+        let (w, h, palette) = (self.fb_width, self.fb_height, self.palette());
+        let result = match self.screenshot_blend {
+            Some(mode) if previous.len() == framebuffer.len() => {
+                save_blended_png(filename, framebuffer, previous, w, h, palette, mode)
+            }
+            _ => save_png(filename, framebuffer, w, h, palette),
+        };
+        match result {
+            Ok(_) => println!("Saved screenshot to {}", filename),
+            Err(e) => println!("Failed to save screenshot: {}", e),
+        }
+    }
+
     fn render_texture(&mut self) {
-        let palette: [(u8, u8, u8); 4] = [
-            (0x9B, 0xBC, 0x0F),
-            (0x8B, 0xAC, 0x0F),
-            (0x30, 0x62, 0x30),
-            (0x0f, 0x38, 0x0f),
+        let palette = self.palette();
+        let framebuffer = match self.beam_racing {
+            true => &self.live_framebuffer,
+            false => &self.framebuffer,
+        };
+        for (px, dst) in framebuffer
+            .iter()
+            .zip(self.texture_buffer.chunks_exact_mut(PIXEL_SIZE))
+        {
+            let (r, g, b) = palette.color(*px);
+            dst.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+        if self.beam_racing {
+            dim_from(&mut self.texture_buffer, self.beam_position);
+        }
+        self.config.display.filter.apply(
+            &self.texture_buffer,
+            self.fb_width,
+            self.fb_height,
+            &mut self.filtered_buffer,
+        );
+    }
+
+    pub fn set_state_slots(&mut self, slots: StateSlots) {
+        self.state_slots = slots;
+    }
+
+    fn save_state(&mut self, core: &mut T, slot: usize) {
+        let file = StateFile {
+            thumbnail: Thumbnail::from_framebuffer(
+                core.framebuffer(),
+                self.fb_width,
+                self.fb_height,
+            ),
+            state: core.save_state(),
+        };
+        match self.state_slots.save(slot, &file) {
+            Ok(_) => println!("Saved state to slot {}", slot),
+            Err(e) => println!("Failed to save state to slot {}: {}", slot, e),
+        }
+        self.states_window.refresh();
+    }
+
+    fn load_state(&mut self, core: &mut T, slot: usize) {
+        match self
+            .state_slots
+            .load(slot)
+            .and_then(|file| core.load_state(&file.state))
+        {
+            Ok(_) => {
+                println!("Loaded state from slot {}", slot);
+
+                // Show the loaded screen, also while paused
+                self.framebuffer = core.framebuffer().to_vec();
+            }
+            Err(e) => println!("Failed to load state from slot {}: {}", slot, e),
+        }
+    }
+
+    // F1-F10 load the state of slot 1-10. With shift held, the state
+    // is saved to the slot instead.
+    fn handle_state_hotkey(&mut self, key: VirtualKeyCode) {
+        const KEYS: [VirtualKeyCode; 10] = [
+            VirtualKeyCode::F1,
+            VirtualKeyCode::F2,
+            VirtualKeyCode::F3,
+            VirtualKeyCode::F4,
+            VirtualKeyCode::F5,
+            VirtualKeyCode::F6,
+            VirtualKeyCode::F7,
+            VirtualKeyCode::F8,
+            VirtualKeyCode::F9,
+            VirtualKeyCode::F10,
         ];
 
-        self.core
-            .to_rgba8(&mut self.texture_buffer, palette.to_vec())
+        if let Some(i) = KEYS.iter().position(|k| *k == key) {
+            let state = self.state.clone();
+            let mut state = state.lock().unwrap();
+            if self.modifiers.shift() {
+                self.save_state(&mut state.core, i + 1);
+            } else {
+                self.load_state(&mut state.core, i + 1);
+            }
+        }
+    }
+
+    pub fn set_config(&mut self, config: Config, filename: &str) {
+        let open = &config.layout.open;
+        self.main_window.open_windows_by_name(open);
+        self.states_window_open |= open.iter().any(|name| name == "savestates");
+        self.audio_settings_open |= open.iter().any(|name| name == "audio-settings");
+
+        self.config = config;
+        self.config_file = filename.to_string();
+    }
+
+    // Save the window layout to the config file. Other settings in
+    // the file are left as they are.
+    fn save_layout(&mut self, ctx: &egui::Context) {
+        let mut open = self.main_window.open_windows();
+        if self.states_window_open {
+            open.push("savestates".to_string());
+        }
+        if self.audio_settings_open {
+            open.push("audio-settings".to_string());
+        }
+        self.config.layout.open = open;
+        self.config.layout.windows = window_layout::current(ctx);
+
+        // A file that can't be read is not overwritten
+        let mut saved = match Config::load(&self.config_file) {
+            Ok(config) => config,
+            Err(_) if !std::path::Path::new(&self.config_file).exists() => Config::default(),
+            Err(e) => {
+                println!("Failed to save window layout: {}", e);
+                return;
+            }
+        };
+        saved.layout = self.config.layout.clone();
+        match saved.save(&self.config_file) {
+            Ok(_) => println!("Saved window layout to {}", self.config_file),
+            Err(e) => println!("Failed to save window layout: {}", e),
+        }
+    }
+
+    pub fn set_colorization(&mut self, palette: Option<DmgPalette>) {
+        self.colorization = palette;
+    }
+
+    // Palette used to display the framebuffer
+    fn palette(&self) -> DmgPalette {
+        match self.colorization {
+            Some(palette) if self.config.display.colorize => palette,
+            _ => self.config.display.palette,
+        }
+    }
+
+    fn save_config(&self) {
+        match self.config.save(&self.config_file) {
+            Ok(_) => println!("Saved settings to {}", self.config_file),
+            Err(e) => println!("Failed to save settings: {}", e),
+        }
+    }
+
+    // Write the content of the replay buffer to a GIF and a WAV file
+    // named by the current time.
+    fn export_replay(&self) {
+        let basename = format!("replay-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        match self
+            .replay
+            .export(&basename, &self.palette(), TARGET_FPS, AUDIO_SAMPLE_RATE)
+        {
+            Ok(_) => println!(
+                "Exported {} frames of instant replay to {}.gif and {}.wav",
+                self.replay.len(),
+                basename,
+                basename
+            ),
+            Err(e) => println!("Failed to export instant replay: {}", e),
+        }
     }
 
     pub fn render_next_frame(
@@ -133,7 +541,6 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
         queue: &Queue,
         egui_rpass: &mut RenderPass,
         surface_config: &SurfaceConfiguration,
-        debug: &mut Debug,
     ) {
         let output_frame = match surface.get_current_texture() {
             Ok(frame) => frame,
@@ -170,14 +577,15 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
             repaint_signal: repaint_signal.clone(),
         });
 
+        let filter_scale = self.config.display.filter.scale() as u32;
         let (width, height, current_frame) = (
-            self.core.screen_width() as u32,
-            self.core.screen_height() as u32,
-            self.core.current_frame(),
+            self.fb_width as u32 * filter_scale,
+            self.fb_height as u32 * filter_scale,
+            self.frame_number,
         );
 
         // Copy Gameboy screen to texture if it has changed since last render
-        if self.fb_texture.is_none() || self.fb_texture_frame != current_frame {
+        if self.fb_texture.is_none() || self.fb_texture_frame != current_frame || self.beam_racing {
             let texture_size = wgpu::Extent3d {
                 width,
                 height,
@@ -205,7 +613,7 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
                     origin: wgpu::Origin3d::ZERO,
                     aspect: wgpu::TextureAspect::All,
                 },
-                &self.texture_buffer,
+                &self.filtered_buffer,
                 wgpu::ImageDataLayout {
                     offset: 0,
                     bytes_per_row: std::num::NonZeroU32::new(4 * width),
@@ -225,7 +633,15 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
         }
 
         // Build the whole app UI
-        self.update(&platform.context(), &mut frame, debug, queue);
+        self.update(&platform.context(), &mut frame, queue);
+
+        if self.config.display.fullscreen != window.fullscreen().is_some() {
+            window.set_fullscreen(if self.config.display.fullscreen {
+                Some(winit::window::Fullscreen::Borderless(None))
+            } else {
+                None
+            });
+        }
 
         // End the UI frame
         let frame_output = platform.end_frame(Some(&window));
@@ -287,66 +703,426 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
             fb_texture: None,
             fb_texture_frame: MAX,
             texture_buffer: vec![0; w * h * PIXEL_SIZE].into_boxed_slice(),
+            filtered_buffer: Vec::new(),
+            config: Config::default(),
+            config_file: crate::config::DEFAULT_CONFIG_FILE.to_string(),
+            applied_theme: None,
             ui_render_stats: Default::default(),
             emu_render_stats: Default::default(),
             serial_buffer_consumer: None,
             previous_frame_time: None,
+            playback_stats: Default::default(),
+            stats_overlay: false,
+            replay: ReplayBuffer::new(w, h, DEFAULT_REPLAY_FRAMES),
+            audio_frame: Vec::new(),
+            frame_dump: None,
+            video: None,
+            video_format: VideoFormat::Gif,
+            video_skip: 1,
+            video_scale: 2,
+            capture_frame: None,
+            screenshot_blend: None,
+            frame_advance: false,
+            scrub_audio: false,
+            framebuffer: vec![0; w * h],
+            frame_number: 0,
+            previous_framebuffer: Vec::new(),
+            beam_racing: false,
+            live_framebuffer: Vec::new(),
+            beam_position: 0,
+            state_slots: StateSlots::for_rom(DEFAULT_STATE_DIR, ""),
+            states_window: StatesWindow::new(),
+            states_window_open: false,
+            audio_settings_window: AudioSettingsWindow::new(),
+            audio_settings_open: false,
+            layout_restored: false,
+            colorization: None,
+            modifiers: ModifiersState::empty(),
+            state: Arc::new(Mutex::new(CoreState {
+                core,
+                debug: Debug::new(),
+            })),
+            core_thread: None,
             main_window,
-            core,
         }
     }
 
-    fn update(
-        &mut self,
-        ctx: &egui::Context,
-        frame: &epi::Frame,
-        debug: &mut Debug,
-        queue: &Queue,
-    ) {
+    fn update(&mut self, ctx: &egui::Context, frame: &epi::Frame, queue: &Queue) {
         if let Some(ref mut consumer) = self.serial_buffer_consumer {
             while let Some(ch) = consumer.pop() {
                 self.main_window.append_serial(ch)
             }
         }
 
+        if !self.layout_restored {
+            window_layout::restore(ctx, &self.config.layout.windows);
+            self.layout_restored = true;
+        }
+
+        let theme = (self.config.ui.theme, self.config.ui.font_size);
+        if self.applied_theme != Some(theme) {
+            apply_theme(ctx, theme.0, theme.1);
+            self.applied_theme = Some(theme);
+        }
+
+        // The core thread waits while the core is locked by the UI
+        let state = self.state.clone();
+        let mut state = state.lock().unwrap();
+        let CoreState { core, debug } = &mut *state;
+
+        if self.beam_racing {
+            self.live_framebuffer.clear();
+            self.live_framebuffer.extend_from_slice(core.framebuffer());
+            self.beam_position = core.beam_position();
+        }
+
         // Handle keyboard input
         if ctx.wants_keyboard_input() {
-            self.core.release_all();
+            core.release_all();
         } else {
-            self.core.update_input_state(&ctx.input());
+            // Space is used to advance frames, so it must not
+            // reach the emulated buttons in frame advance mode
+            let mut input = ctx.input().clone();
+            if self.frame_advance {
+                input.keys_down.remove(&Key::Space);
+            }
+            core.update_input_state(&input);
+
+            if ctx.input().key_pressed(Key::Backspace) {
+                self.toggle_frame_advance();
+            }
+
+            if self.frame_advance && ctx.input().key_pressed(Key::Space) {
+                self.advance_frame();
+            }
+
+            if ctx.input().key_pressed(Key::P) {
+                self.save_screenshot(
+                    &timestamped_filename("screenshot", "png"),
+                    &self.framebuffer,
+                    &self.previous_framebuffer,
+                );
+            }
+
+            if ctx.input().key_pressed(Key::V) {
+                self.toggle_video();
+            }
+
+            if ctx.input().key_pressed(Key::F) {
+                self.config.display.fullscreen = !self.config.display.fullscreen;
+            }
+
+            if ctx.input().key_pressed(Key::O) {
+                self.stats_overlay = !self.stats_overlay;
+            }
+
+            if ctx.input().key_pressed(Key::I) {
+                self.config.display.input_display = !self.config.display.input_display;
+            }
         }
 
         // Update render stats with new frame info
         self.ui_render_stats
             .on_new_frame(ctx.input().time, frame.info().cpu_usage);
 
-        self.main_window
-            .render(ctx, &mut self.core, debug, queue, &self.ui_render_stats);
+        let overlay = core.overlay_text();
+        let input = match self.config.display.input_display {
+            true => core.input_state(),
+            false => Vec::new(),
+        };
+
+        if self.stats_overlay {
+            egui::Area::new("stats_overlay")
+                .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        render_stats_overlay(
+                            ui,
+                            &self.ui_render_stats,
+                            &self.emu_render_stats,
+                            &self.playback_stats,
+                        )
+                    });
+                });
+        }
+
+        // In fullscreen mode, only the screen is shown
+        if self.config.display.fullscreen {
+            if let Some(texture_id) = self.fb_texture {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::none().fill(Color32::BLACK))
+                    .show(ctx, |ui| {
+                        self.render_screen(ui, texture_id, &overlay, &input)
+                    });
+            }
+            return;
+        }
+
+        self.main_window.render(
+            ctx,
+            core,
+            debug,
+            queue,
+            &self.ui_render_stats,
+            &self.emu_render_stats,
+        );
 
+        // The screen window never grows larger than the OS window, and
+        // the screen is scaled to fit in it
         if let Some(texture_id) = self.fb_texture {
-            egui::Window::new("Gameboy").show(ctx, |ui| {
-                let scale: usize = 3;
-                let size = egui::Vec2::new(
-                    (self.fb_width * scale) as f32,
-                    (self.fb_height * scale) as f32,
-                );
+            let max_size = ctx.available_rect().size();
+            let window = egui::Window::new("Gameboy")
+                .resizable(true)
+                .default_size(egui::vec2(
+                    (self.fb_width * 3) as f32,
+                    (self.fb_height * 3) as f32,
+                ))
+                .resize(|r| r.max_size(max_size));
+            window_layout::show(ctx, "Gameboy", window, |ui| {
+                self.render_controls(ui);
+                self.render_display_settings(ui);
+                self.render_screen(ui, texture_id, &overlay, &input);
+            });
+        }
+
+        if self.states_window_open {
+            let mut open = true;
+            let palette = self.palette();
+            let action = self
+                .states_window
+                .render(ctx, &self.state_slots, &palette, &mut open);
+            self.states_window_open = open;
+            match action {
+                Some(SlotAction::Save(slot)) => self.save_state(core, slot),
+                Some(SlotAction::Load(slot)) => self.load_state(core, slot),
+                None => {}
+            }
+        }
 
-                let r = ui.image(texture_id, size);
-                match r.hover_pos() {
-                    Some(p) => {
-                        let x = (p[0] - r.rect.left()) as usize / scale;
-                        let y = (p[1] - r.rect.top()) as usize / scale;
-                        r.on_hover_ui_at_pointer(|ui| {
-                            ui.add(Label::new(format!("({}, {})", x, y)));
-                        });
+        if self.audio_settings_open {
+            let mut open = true;
+            let apply = self.audio_settings_window.render(
+                ctx,
+                &mut self.config.audio,
+                &self.audio,
+                &mut open,
+            );
+            self.audio_settings_open = open;
+            if apply {
+                self.restart_audio();
+            }
+        }
+    }
+
+    // Show the screen in all available space, scaled as configured
+    fn render_screen(
+        &self,
+        ui: &mut Ui,
+        texture_id: TextureId,
+        overlay: &[OverlayText],
+        input: &[(&str, bool)],
+    ) {
+        let (rect, resp) = ui.allocate_exact_size(ui.available_size(), Sense::hover());
+        let size =
+            self.config
+                .display
+                .scale_mode
+                .screen_size(self.fb_width, self.fb_height, rect.size());
+        let screen_rect = Rect::from_center_size(rect.center(), size);
+
+        egui::Image::new(texture_id, size).paint_at(ui, screen_rect);
+
+        let scale = size.x / self.fb_width as f32;
+        for overlay in overlay.iter() {
+            let pos = screen_rect.left_top() + egui::vec2(overlay.x, overlay.y) * scale;
+            ui.painter().text(
+                pos,
+                egui::Align2::LEFT_TOP,
+                &overlay.text,
+                egui::FontId::monospace(8.0 * scale),
+                Color32::WHITE,
+            );
+        }
+
+        render_input_display(ui, screen_rect, scale, input);
+
+        if let Some(p) = resp.hover_pos() {
+            if screen_rect.contains(p) {
+                let x = ((p.x - screen_rect.left()) / size.x * self.fb_width as f32) as usize;
+                let y = ((p.y - screen_rect.top()) / size.y * self.fb_height as f32) as usize;
+                resp.on_hover_ui_at_pointer(|ui| {
+                    ui.add(Label::new(format!("({}, {})", x, y)));
+                });
+            }
+        }
+    }
+
+    fn render_display_settings(&mut self, ui: &mut Ui) {
+        let colorization = self.colorization.is_some();
+        let display = &mut self.config.display;
+        let previous_filter = display.filter;
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Scale")
+                .selected_text(display.scale_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in ScaleMode::ALL {
+                        ui.selectable_value(&mut display.scale_mode, mode, mode.name());
+                    }
+                });
+
+            egui::ComboBox::from_label("Filter")
+                .selected_text(display.filter.name())
+                .show_ui(ui, |ui| {
+                    for filter in ScreenFilter::ALL {
+                        ui.selectable_value(&mut display.filter, filter, filter.name());
+                    }
+                });
+
+            ui.checkbox(&mut display.fullscreen, "Fullscreen (F)");
+        });
+
+        let (beam_racing, stats_overlay) = (&mut self.beam_racing, &mut self.stats_overlay);
+        ui.horizontal(|ui| {
+            ui.checkbox(beam_racing, "Beam racing")
+                .on_hover_text("Show the frame as it is drawn, up to the current line");
+            ui.checkbox(stats_overlay, "Stats (O)");
+            ui.checkbox(&mut display.input_display, "Input (I)");
+        });
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Palette")
+                .selected_text(preset_name(&display.palette).unwrap_or("Custom"))
+                .show_ui(ui, |ui| {
+                    for (name, palette) in PRESETS {
+                        ui.selectable_value(&mut display.palette, palette, name);
+                    }
+                });
+
+            // Only offered when a DMG game runs on a CGB
+            if colorization {
+                ui.checkbox(&mut display.colorize, "CGB colorization");
+            }
+        });
+
+        ui.collapsing("Palette colors", |ui| {
+            let palette = &mut display.palette;
+            for (label, colors) in [
+                ("BG  ", &mut palette.bg),
+                ("OBJ0", &mut palette.obj0),
+                ("OBJ1", &mut palette.obj1),
+            ] {
+                ui.horizontal(|ui| {
+                    ui.monospace(label);
+                    for color in colors.iter_mut() {
+                        let mut rgb = [color.0, color.1, color.2];
+                        if ui.color_edit_button_srgb(&mut rgb).changed() {
+                            *color = (rgb[0], rgb[1], rgb[2]);
+                        }
                     }
-                    None => {}
+                });
+            }
+        });
+
+        // Recreate the texture, which has a different size per filter
+        if display.filter != previous_filter {
+            self.fb_texture = None;
+        }
+
+        let ui_config = &mut self.config.ui;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Theme")
+                .selected_text(ui_config.theme.name())
+                .show_ui(ui, |ui| {
+                    for theme in Theme::ALL {
+                        ui.selectable_value(&mut ui_config.theme, theme, theme.name());
+                    }
+                });
+
+            ui.add(
+                egui::Slider::new(&mut ui_config.font_size, MIN_FONT_SIZE..=MAX_FONT_SIZE)
+                    .step_by(1.0)
+                    .text("Font size"),
+            );
+        });
+
+        if ui.button("Save settings").clicked() {
+            self.save_config();
+        }
+    }
+
+    fn render_controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Screenshot").clicked() {
+                self.save_screenshot(
+                    &timestamped_filename("screenshot", "png"),
+                    &self.framebuffer,
+                    &self.previous_framebuffer,
+                );
+            }
+            egui::ComboBox::from_id_source("screenshot_blend")
+                .selected_text(match self.screenshot_blend {
+                    None => "Single frame",
+                    Some(BlendMode::Average) => "Average of 2 frames",
+                    Some(BlendMode::Or) => "OR of 2 frames",
+                })
+                .show_ui(ui, |ui| {
+                    let blend = &mut self.screenshot_blend;
+                    ui.selectable_value(blend, None, "Single frame");
+                    ui.selectable_value(blend, Some(BlendMode::Average), "Average of 2 frames");
+                    ui.selectable_value(blend, Some(BlendMode::Or), "OR of 2 frames");
+                });
+            let label = if self.video.is_some() {
+                "Stop video"
+            } else {
+                "Record video"
+            };
+            if ui.button(label).clicked() {
+                self.toggle_video();
+            }
+            if ui.button("Export replay").clicked() {
+                self.export_replay();
+            }
+            if ui
+                .selectable_label(self.states_window_open, "Savestates")
+                .clicked()
+            {
+                self.states_window_open = !self.states_window_open;
+                self.states_window.refresh();
+            }
+            if ui
+                .selectable_label(self.audio_settings_open, "Audio")
+                .clicked()
+            {
+                self.audio_settings_open = !self.audio_settings_open;
+                self.audio_settings_window.refresh();
+            }
+            if ui
+                .selectable_label(self.frame_advance, "Frame advance (Backspace)")
+                .clicked()
+            {
+                self.toggle_frame_advance();
+            }
+            if self.frame_advance {
+                if ui.button("Next frame (Space)").clicked() {
+                    self.advance_frame();
                 }
-            });
+                ui.checkbox(&mut self.scrub_audio, "Play audio");
+            }
+            ui.label(format!(
+                "{} frames ({} KiB)",
+                self.replay.len(),
+                self.replay.memory_usage() / 1024
+            ));
+        });
+
+        // Show the audio of the last stepped frame
+        if self.frame_advance {
+            render_waveform(ui, &self.audio_frame);
         }
     }
 
-    pub fn run_with_wgpu(mut self, mut debug: Debug) {
+    pub fn run_with_wgpu(mut self, debug: Debug) {
         let event_loop = winit::event_loop::EventLoop::with_user_event();
         let window = winit::window::WindowBuilder::new()
             .with_decorations(true)
@@ -354,8 +1130,8 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
             .with_transparent(false)
             .with_title(APPNAME)
             .with_inner_size(winit::dpi::PhysicalSize {
-                width: 2800 as u32,
-                height: 1800 as u32,
+                width: self.config.layout.width,
+                height: self.config.layout.height,
             })
             .build(&event_loop)
             .unwrap();
@@ -420,6 +1196,13 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
         self.setup_serial();
         self.main_window.init(&device, &mut egui_rpass);
 
+        self.state.lock().unwrap().debug = debug;
+        self.core_thread = Some(CoreThread::spawn(
+            self.state.clone(),
+            self.audio.producer.take(),
+            TARGET_FPS,
+        ));
+
         event_loop.run(move |event, _, control_flow| {
             if false {
                 print_event(&event);
@@ -440,7 +1223,6 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
                         &queue,
                         &mut egui_rpass,
                         &surface_config,
-                        &mut debug,
                     );
                 }
 
@@ -451,8 +1233,9 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
                     // let elapsed_time = now.duration_since(emulator_frame_timestamp).as_micros() as u64;
 
                     if now >= next_frame_instant {
-                        // Run emulator until next frame is ready
-                        self.run_until_next_frame(&mut debug);
+                        // Collect the frames completed by the core thread
+                        let abs_elapsed_time = now.duration_since(start_time).as_secs_f64();
+                        self.receive_frames(abs_elapsed_time);
 
                         // Calculate the time for the next frame to be rendered
                         next_frame_instant = next_frame_instant + one_frame_duration;
@@ -463,11 +1246,6 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
                             next_frame_instant = now;
                         }
 
-                        // Record frame render time statistics
-                        let abs_elapsed_time = now.duration_since(start_time).as_secs_f64();
-                        self.emu_render_stats
-                            .on_new_frame(abs_elapsed_time, Some(0.0));
-
                         window.request_redraw();
                     }
 
@@ -483,11 +1261,31 @@ impl<T: 'static + Core, W: 'static + MainWindow<T>> MoeApp<T, W> {
                             surface_config.width = size.width;
                             surface_config.height = size.height;
                             surface.configure(&device, &surface_config);
+                            self.config.layout.width = size.width;
+                            self.config.layout.height = size.height;
                         }
                         window.request_redraw();
                     }
 
+                    winit::event::WindowEvent::ModifiersChanged(modifiers) => {
+                        self.modifiers = modifiers;
+                    }
+
+                    winit::event::WindowEvent::KeyboardInput { input, .. } => {
+                        if let (ElementState::Pressed, Some(key)) =
+                            (input.state, input.virtual_keycode)
+                        {
+                            self.handle_state_hotkey(key);
+                        }
+                    }
+
                     winit::event::WindowEvent::CloseRequested => {
+                        self.save_layout(&platform.context());
+                        self.stop_video();
+                        if let Some(mut thread) = self.core_thread.take() {
+                            thread.stop();
+                        }
+                        self.state.lock().unwrap().core.shutdown();
                         *control_flow = ControlFlow::Exit;
                     }
 
@@ -1,102 +1,86 @@
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    Sample, SampleFormat, Stream, StreamConfig,
-};
-use ringbuf::{Producer, RingBuffer};
+// Plays the emulated audio on an output device, through one of the
+// backends in audio_sink.rs. Without the "audio" feature, nothing is
+// played and `producer` is always None.
+//
+// The backend, device and buffer size are selected in the audio
+// settings. A larger buffer gives fewer underruns on a busy system, at
+// the cost of more latency.
 
-pub trait AudioRecorder {
-    fn mono(&mut self, sample: f32);
-    fn gen1(&mut self, sample: f32);
-    fn gen2(&mut self, sample: f32);
-    fn flush(&mut self);
-}
+use std::time::Duration;
+
+use ringbuf::Producer;
+
+use crate::config::AudioConfig;
+use crate::ui::audio_sink::{fallback_order, sample_buffer, AudioBackend, AudioSink};
 
 pub struct AudioPlayer {
-    stream: Option<Stream>,
+    sink: Option<Box<dyn AudioSink>>,
     pub producer: Option<Producer<i16>>,
+
+    // The backend that is playing
+    pub backend: Option<AudioBackend>,
+}
+
+impl Default for AudioPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AudioPlayer {
     pub fn new() -> Self {
         AudioPlayer {
-            stream: None,
+            sink: None,
             producer: None,
+            backend: None,
         }
     }
 
-    pub fn setup(&mut self) {
-        let buf = RingBuffer::<i16>::new(((48000 * 10) / 60) as usize);
-        let (producer, mut consumer) = buf.split();
-        self.producer = Some(producer);
-
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("no output device available");
-
-        let mut supported_configs_range = device
-            .supported_output_configs()
-            .expect("error while querying configs");
-
-        let config = supported_configs_range
-            .next()
-            .expect("no supported config?")
-            .with_max_sample_rate();
+    // Name of the device that is playing
+    pub fn device_name(&self) -> Option<String> {
+        self.sink.as_ref().and_then(|sink| sink.device_name())
+    }
 
-        println!("Selected audio config: {:?}", config);
+    pub fn sample_rate(&self) -> u32 {
+        self.sink.as_ref().map_or(0, |sink| sink.sample_rate())
+    }
 
-        let err_fn = |err| eprintln!("an error occured on the output audio stream: {}", err);
-        let sample_format = config.sample_format();
-        let config: StreamConfig = config.into();
+    // Buffer sizes supported by the device, in frames
+    pub fn buffer_range(&self) -> Option<(u32, u32)> {
+        self.sink.as_ref().and_then(|sink| sink.buffer_range())
+    }
 
-        let channels = config.channels as usize;
+    // The most recently measured latency, if anything has been played
+    pub fn latency(&self) -> Option<Duration> {
+        self.sink.as_ref().and_then(|sink| sink.latency())
+    }
 
-        let mut next_value = move || match consumer.pop() {
-            Some(sample) => (sample as f32) / 32768.0,
-            None => 0.0,
-        };
+    // Start playing with the configured backend, or the first of the
+    // others that starts. Any previous stream is stopped, and a new
+    // producer is created for the samples.
+    pub fn setup(&mut self, config: &AudioConfig) {
+        self.sink = None;
+        self.producer = None;
+        self.backend = None;
+
+        let backends = fallback_order(&AudioBackend::available(), config.backend.as_deref());
+        if backends.is_empty() {
+            println!("Audio playback requires the \"audio\" feature");
+            return;
+        }
 
-        fn write_beep<T: Sample>(
-            output: &mut [T],
-            channels: usize,
-            next_sample: &mut dyn FnMut() -> f32,
-        ) {
-            for frame in output.chunks_mut(channels) {
-                let value: T = cpal::Sample::from::<f32>(&next_sample());
-                for sample in frame.iter_mut() {
-                    *sample = value;
+        for backend in backends {
+            let (producer, consumer) = sample_buffer();
+            match backend.open(config, consumer) {
+                Ok(sink) => {
+                    self.sink = Some(sink);
+                    self.producer = Some(producer);
+                    self.backend = Some(backend);
+                    return;
                 }
+                Err(e) => println!("Failed to start audio with {}: {}", backend.name(), e),
             }
         }
-
-        let stream = match sample_format {
-            SampleFormat::F32 => device.build_output_stream(
-                &config,
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    write_beep::<f32>(data, channels, &mut next_value)
-                },
-                err_fn,
-            ),
-
-            SampleFormat::I16 => device.build_output_stream(
-                &config,
-                move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
-                    write_beep::<i16>(data, channels, &mut next_value)
-                },
-                err_fn,
-            ),
-
-            SampleFormat::U16 => device.build_output_stream(
-                &config,
-                move |data: &mut [u16], _: &cpal::OutputCallbackInfo| {
-                    write_beep::<u16>(data, channels, &mut next_value)
-                },
-                err_fn,
-            ),
-        }
-        .unwrap();
-
-        stream.play().unwrap();
-        self.stream = Some(stream);
+        println!("No audio output");
     }
 }
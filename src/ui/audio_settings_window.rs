@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use egui::Context;
+
+use crate::config::AudioConfig;
+use crate::ui::audio_player::AudioPlayer;
+use crate::ui::audio_sink::{fallback_order, AudioBackend};
+use crate::ui::window_layout;
+
+// Buffer sizes offered in the settings, in frames
+const BUFFER_SIZES: [u32; 6] = [128, 256, 512, 1024, 2048, 4096];
+
+// Selects the audio backend, output device and buffer size
+pub struct AudioSettingsWindow {
+    // Output devices of a backend, listed when the window is opened
+    // since enumerating them can be slow
+    devices: Option<(AudioBackend, Vec<String>)>,
+}
+
+impl Default for AudioSettingsWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn buffer_size_name(size: Option<u32>) -> String {
+    match size {
+        Some(frames) => format!("{} frames", frames),
+        None => "Default".to_string(),
+    }
+}
+
+fn format_ms(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl AudioSettingsWindow {
+    pub fn new() -> Self {
+        AudioSettingsWindow { devices: None }
+    }
+
+    // List the devices again the next time the window is shown
+    pub fn refresh(&mut self) {
+        self.devices = None;
+    }
+
+    // Returns true if the settings should be applied
+    pub fn render(
+        &mut self,
+        ctx: &Context,
+        config: &mut AudioConfig,
+        player: &AudioPlayer,
+        open: &mut bool,
+    ) -> bool {
+        // The devices of the backend that is tried first
+        let available = AudioBackend::available();
+        let backend = fallback_order(&available, config.backend.as_deref())
+            .first()
+            .copied();
+        if let Some(backend) = backend {
+            if self.devices.as_ref().map(|(b, _)| *b) != Some(backend) {
+                self.devices = Some((backend, backend.device_names()));
+            }
+        }
+        let devices = self.devices.as_ref().map_or(&[][..], |(_, d)| d);
+
+        let mut apply = false;
+        let mut refresh = false;
+        window_layout::show(
+            ctx,
+            "Audio settings",
+            egui::Window::new("Audio settings")
+                .open(open)
+                .resizable(false),
+            |ui| {
+                egui::Grid::new("audio_settings_grid_id").show(ui, |ui| {
+                    ui.label("Backend");
+                    let previous = config.backend.clone();
+                    egui::ComboBox::from_id_source("audio_backend")
+                        .selected_text(config.backend.as_deref().unwrap_or("Default"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut config.backend, None, "Default");
+                            for b in available.iter() {
+                                let name = Some(b.name().to_string());
+                                ui.selectable_value(&mut config.backend, name, b.name());
+                            }
+                        });
+                    // Device names differ between backends
+                    if config.backend != previous {
+                        config.device = None;
+                    }
+                    ui.end_row();
+
+                    ui.label("Device");
+                    egui::ComboBox::from_id_source("audio_device")
+                        .selected_text(config.device.as_deref().unwrap_or("Default"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut config.device, None, "Default");
+                            for name in devices.iter() {
+                                ui.selectable_value(&mut config.device, Some(name.clone()), name);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Buffer size");
+                    egui::ComboBox::from_id_source("audio_buffer_size")
+                        .selected_text(buffer_size_name(config.buffer_size))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut config.buffer_size, None, "Default");
+                            for size in BUFFER_SIZES {
+                                ui.selectable_value(
+                                    &mut config.buffer_size,
+                                    Some(size),
+                                    buffer_size_name(Some(size)),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                });
+
+                ui.label("A larger buffer plays without gaps on a busy system, but adds latency.");
+                ui.separator();
+
+                match (player.device_name(), player.backend) {
+                    (Some(name), Some(backend)) => {
+                        ui.label(format!(
+                            "Playing on {} at {} Hz with {}",
+                            name,
+                            player.sample_rate(),
+                            backend.name()
+                        ));
+                        if let Some((min, max)) = player.buffer_range() {
+                            ui.label(format!("Supported buffer sizes: {} to {} frames", min, max));
+                        }
+                        match player.latency() {
+                            Some(latency) => ui.label(format!("Latency: {}", format_ms(latency))),
+                            None => ui.label("Latency: unknown"),
+                        };
+                    }
+                    _ => {
+                        ui.label("No audio output");
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Apply").clicked() {
+                        apply = true;
+                    }
+                    if ui.button("Refresh devices").clicked() {
+                        refresh = true;
+                    }
+                });
+            },
+        );
+
+        if refresh {
+            self.refresh();
+        }
+        apply
+    }
+}
@@ -0,0 +1,153 @@
+// Audio output backends
+//
+// The emulated audio is played through one of several backends: cpal,
+// with the "audio" feature, and SDL2, with the "sdl2-audio" feature.
+// The backend in the audio settings is tried first, and if it fails to
+// start, the others are tried in turn. This keeps audio working on
+// systems where one of them doesn't get along with the audio stack.
+//
+// Samples are passed to the backend through a ring buffer: the core
+// thread pushes them, and the output callback of the backend pops
+// them. The buffer holds 10 frames of audio.
+
+use std::time::Duration;
+
+use ringbuf::{Consumer, Producer, RingBuffer};
+
+use crate::config::AudioConfig;
+
+// A started output stream. It plays until dropped.
+pub trait AudioSink {
+    // Name of the device that is playing
+    fn device_name(&self) -> Option<String>;
+
+    fn sample_rate(&self) -> u32;
+
+    // Buffer sizes supported by the device, in frames
+    fn buffer_range(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    // The most recently measured latency, if anything has been played
+    fn latency(&self) -> Option<Duration>;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AudioBackend {
+    Cpal,
+    Sdl2,
+}
+
+impl AudioBackend {
+    // All backends, in order of preference
+    pub const ALL: [AudioBackend; 2] = [AudioBackend::Cpal, AudioBackend::Sdl2];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioBackend::Cpal => "cpal",
+            AudioBackend::Sdl2 => "sdl2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AudioBackend> {
+        AudioBackend::ALL
+            .iter()
+            .copied()
+            .find(|b| b.name().eq_ignore_ascii_case(name))
+    }
+
+    // Whether the backend has been built in
+    pub fn is_available(&self) -> bool {
+        match self {
+            AudioBackend::Cpal => cfg!(feature = "audio"),
+            AudioBackend::Sdl2 => cfg!(feature = "sdl2-audio"),
+        }
+    }
+
+    // The backends that have been built in, in order of preference
+    pub fn available() -> Vec<AudioBackend> {
+        AudioBackend::ALL
+            .iter()
+            .copied()
+            .filter(|b| b.is_available())
+            .collect()
+    }
+
+    // Names of the output devices of the backend
+    pub fn device_names(&self) -> Vec<String> {
+        match self {
+            #[cfg(feature = "audio")]
+            AudioBackend::Cpal => crate::ui::cpal_sink::device_names(),
+            #[cfg(feature = "sdl2-audio")]
+            AudioBackend::Sdl2 => crate::ui::sdl_sink::device_names(),
+            #[allow(unreachable_patterns)]
+            _ => Vec::new(),
+        }
+    }
+
+    // Start playing the samples of `consumer` on the configured device
+    #[allow(unused_variables)]
+    pub fn open(
+        &self,
+        config: &AudioConfig,
+        consumer: Consumer<i16>,
+    ) -> Result<Box<dyn AudioSink>, String> {
+        match self {
+            #[cfg(feature = "audio")]
+            AudioBackend::Cpal => Ok(Box::new(crate::ui::cpal_sink::CpalSink::open(
+                config, consumer,
+            )?)),
+            #[cfg(feature = "sdl2-audio")]
+            AudioBackend::Sdl2 => Ok(Box::new(crate::ui::sdl_sink::SdlSink::open(
+                config, consumer,
+            )?)),
+            #[allow(unreachable_patterns)]
+            _ => Err(format!("built without the {} backend", self.name())),
+        }
+    }
+}
+
+// The backends to try, in order: the preferred one, if available,
+// and then the others
+pub fn fallback_order(available: &[AudioBackend], preferred: Option<&str>) -> Vec<AudioBackend> {
+    let mut order = available.to_vec();
+    if let Some(name) = preferred {
+        match AudioBackend::from_name(name) {
+            Some(backend) => {
+                if let Some(i) = order.iter().position(|b| *b == backend) {
+                    let backend = order.remove(i);
+                    order.insert(0, backend);
+                }
+            }
+            None => println!("Unknown audio backend: {}", name),
+        }
+    }
+    order
+}
+
+// The ring buffer between the core thread and the output callback
+pub fn sample_buffer() -> (Producer<i16>, Consumer<i16>) {
+    RingBuffer::<i16>::new(((48000 * 10) / 60) as usize).split()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_order() {
+        let both = [AudioBackend::Cpal, AudioBackend::Sdl2];
+        assert_eq!(fallback_order(&both, None), both.to_vec());
+        assert_eq!(
+            fallback_order(&both, Some("SDL2")),
+            vec![AudioBackend::Sdl2, AudioBackend::Cpal]
+        );
+        assert_eq!(fallback_order(&both, Some("pulse")), both.to_vec());
+
+        // A preferred backend that isn't built in is skipped
+        assert_eq!(
+            fallback_order(&[AudioBackend::Cpal], Some("sdl2")),
+            vec![AudioBackend::Cpal]
+        );
+    }
+}
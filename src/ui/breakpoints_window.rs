@@ -1,41 +1,78 @@
 use egui::{Button, Context};
 
-use crate::debug::{Breakpoint, Debug};
+use crate::debug::{
+    format_address, parse_address, BankBoundaryCheck, Breakpoint, Debug, RegisterWatchpoint,
+};
+use crate::ui::window_layout;
 
 pub struct BreakpointsWindow {
     add_breakpoint_input: String,
+    add_watchpoint_input: String,
 }
 
 impl BreakpointsWindow {
     pub fn new() -> Self {
         BreakpointsWindow {
             add_breakpoint_input: "".to_string(),
+            add_watchpoint_input: "".to_string(),
         }
     }
 
     pub fn render(&mut self, ctx: &Context, debug: &mut Debug, open: &mut bool) {
-        egui::Window::new("Breakpoints")
-            .open(open)
-            .resizable(true)
-            .show(ctx, |ui| {
+        window_layout::show(
+            ctx,
+            "Breakpoints",
+            egui::Window::new("Breakpoints").open(open).resizable(true),
+            |ui| {
                 ui.scope(|ui| {
                     ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
 
                     ui.horizontal(|ui| {
-                        match usize::from_str_radix(&self.add_breakpoint_input, 16) {
-                            Ok(adr) => {
+                        match parse_address(&self.add_breakpoint_input, &debug.symbols) {
+                            Some((bank, adr)) => {
                                 ui.text_edit_singleline(&mut self.add_breakpoint_input);
                                 if ui.button("✚").clicked() {
-                                    debug.add_breakpoint(adr, Breakpoint { enabled: true });
+                                    debug.add_breakpoint(
+                                        adr,
+                                        Breakpoint {
+                                            enabled: true,
+                                            bank,
+                                        },
+                                    );
+                                }
+                            }
+                            None => {
+                                ui.text_edit_singleline(&mut self.add_breakpoint_input)
+                                    .on_hover_text("Address, such as 4123 or 03:4123, or label");
+                                ui.add_enabled(false, Button::new("✚"));
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        match RegisterWatchpoint::parse(&self.add_watchpoint_input) {
+                            Ok(wp) => {
+                                ui.text_edit_singleline(&mut self.add_watchpoint_input);
+                                if ui.button("✚").clicked() {
+                                    debug.register_watchpoints.push(wp);
                                 }
                             }
                             Err(_) => {
-                                ui.text_edit_singleline(&mut self.add_breakpoint_input);
+                                ui.text_edit_singleline(&mut self.add_watchpoint_input)
+                                    .on_hover_text("Register condition, such as SP < C000");
                                 ui.add_enabled(false, Button::new("✚"));
                             }
                         }
                     });
 
+                    ui.horizontal(|ui| {
+                        ui.label("Bank boundary:");
+                        let check = &mut debug.bank_boundary_check;
+                        ui.selectable_value(check, BankBoundaryCheck::Off, "Off");
+                        ui.selectable_value(check, BankBoundaryCheck::Warn, "Warn");
+                        ui.selectable_value(check, BankBoundaryCheck::Break, "Break");
+                    });
+
                     ui.separator();
 
                     egui::Grid::new("breakpoints_grid_id").show(ui, |ui| {
@@ -44,14 +81,32 @@ impl BreakpointsWindow {
                                 let mut en = bp.enabled;
                                 ui.checkbox(&mut en, "");
                                 bp.enabled = en;
-                                ui.label(format!("{:04X}", adr));
+                                let address = format_address(bp.bank, *adr);
+                                match debug.symbols.describe(bp.bank.unwrap_or(0), *adr) {
+                                    Some(label) => ui.label(format!("{} {}", address, label)),
+                                    None => ui.label(address),
+                                };
                                 ui.end_row();
                             }
                         }
+
+                        let mut removed = None;
+                        for (n, wp) in debug.register_watchpoints.iter_mut().enumerate() {
+                            ui.checkbox(&mut wp.enabled, "");
+                            ui.label(wp.to_string());
+                            if ui.small_button("🗑").clicked() {
+                                removed = Some(n);
+                            }
+                            ui.end_row();
+                        }
+                        if let Some(n) = removed {
+                            debug.register_watchpoints.remove(n);
+                        }
                     });
 
                     ui.allocate_space(ui.available_size());
                 });
-            });
+            },
+        );
     }
 }
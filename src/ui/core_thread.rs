@@ -0,0 +1,278 @@
+// Emulator core thread
+//
+// The core runs on its own thread, so that a slow UI frame, for example
+// with many debug windows open, or waiting for vsync, doesn't delay
+// emulation and cause audio underruns or dropped frames.
+//
+// The core and the debugger are shared with the UI behind a mutex. The
+// thread only holds it while emulating a frame, and the UI only while
+// building its windows. Input and debugger state is changed by the UI
+// directly on the shared core, everything else is sent as commands.
+//
+// Completed frames are sent to the UI together with their audio. The
+// framebuffers are sent back to the thread when the UI is done with
+// them, so the two threads take turns with the same couple of buffers.
+
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryIter};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use ringbuf::Producer;
+
+use crate::core::Debuggable;
+use crate::debug::Debug;
+
+// State shared by the UI and the core thread
+pub struct CoreState<T> {
+    pub core: T,
+    pub debug: Debug,
+}
+
+pub enum Command {
+    // Enable or disable frame advance mode, in which the emulator is
+    // paused and runs a single frame at a time when requested
+    FrameAdvance(bool),
+
+    // Run a single frame in frame advance mode
+    Advance { play_audio: bool },
+
+    // Play audio through a new output stream, after the audio
+    // settings have changed
+    SetAudio(Option<Producer<i16>>),
+
+    Shutdown,
+}
+
+// A completed frame
+pub struct Frame {
+    pub number: usize,
+    pub framebuffer: Vec<u8>,
+    pub audio: Vec<i16>,
+
+    // Host time, in seconds, spent emulating the frame
+    pub time: f32,
+
+    // Samples waiting in the audio output buffer after the audio of
+    // the frame was queued, and the size of the buffer
+    pub audio_fill: Option<(usize, usize)>,
+
+    // Samples of the frame that did not fit in the audio output buffer
+    pub dropped_samples: usize,
+
+    // Frames skipped since the thread started, because emulation fell
+    // behind
+    pub late_frames: usize,
+}
+
+pub struct CoreThread {
+    commands: Sender<Command>,
+    frames: Receiver<Frame>,
+    recycled: Sender<Vec<u8>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Worker<T> {
+    state: Arc<Mutex<CoreState<T>>>,
+    commands: Receiver<Command>,
+    frames: Sender<Frame>,
+    recycled: Receiver<Vec<u8>>,
+    audio: Option<Producer<i16>>,
+    frame_advance: bool,
+    late_frames: usize,
+}
+
+impl<T: Debuggable> Worker<T> {
+    fn run(mut self, fps: f64) {
+        let frame_duration = Duration::from_secs_f64(1.0 / fps);
+        let mut next_frame = Instant::now();
+
+        loop {
+            let timeout = next_frame.saturating_duration_since(Instant::now());
+            match self.commands.recv_timeout(timeout) {
+                Ok(Command::FrameAdvance(enabled)) => self.frame_advance = enabled,
+                Ok(Command::Advance { play_audio }) => {
+                    if self.frame_advance {
+                        self.advance(play_audio);
+                    }
+                }
+                Ok(Command::SetAudio(audio)) => self.audio = audio,
+                Ok(Command::Shutdown) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => {
+                    if !self.frame_advance {
+                        self.run_until_next_frame();
+                    }
+
+                    // If emulation has fallen behind, continue from
+                    // now rather than trying to catch up
+                    next_frame += frame_duration;
+                    let now = Instant::now();
+                    if now > next_frame {
+                        let behind = (now - next_frame).as_secs_f64();
+                        self.late_frames += (behind / frame_duration.as_secs_f64()) as usize;
+                        next_frame = now;
+                    }
+                }
+            }
+        }
+    }
+
+    fn run_until_next_frame(&mut self) {
+        let start = Instant::now();
+        let state = self.state.clone();
+        let mut state = state.lock().unwrap();
+        let CoreState { core, debug } = &mut *state;
+
+        let frame = core.current_frame();
+        while debug.before_op(core) && frame == core.current_frame() {
+            core.exec_op();
+        }
+
+        if core.current_frame() != frame {
+            debug.apply_frozen(core);
+            self.finish_frame(core, true, start);
+        }
+    }
+
+    fn advance(&mut self, play_audio: bool) {
+        let start = Instant::now();
+        let state = self.state.clone();
+        let mut state = state.lock().unwrap();
+        let CoreState { core, debug } = &mut *state;
+        core.run_one_frame();
+        debug.apply_frozen(core);
+        self.finish_frame(core, play_audio, start);
+    }
+
+    // Collect the audio of the frame and send it to the UI
+    fn finish_frame(&mut self, core: &mut T, play_audio: bool, start: Instant) {
+        core.end_audio_frame();
+        let mut audio = Vec::new();
+        core.read_audio_samples(&mut audio);
+        let mut dropped_samples = 0;
+        if let (true, Some(producer)) = (play_audio, self.audio.as_mut()) {
+            dropped_samples = audio.len() - producer.push_slice(&audio);
+        }
+        let audio_fill = self.audio.as_ref().map(|p| (p.len(), p.capacity()));
+
+        let mut framebuffer = self.recycled.try_recv().unwrap_or_default();
+        framebuffer.clear();
+        framebuffer.extend_from_slice(core.framebuffer());
+
+        // Fails only if the UI is gone, in which case the thread is
+        // about to be stopped
+        let _ = self.frames.send(Frame {
+            number: core.current_frame(),
+            framebuffer,
+            audio,
+            time: start.elapsed().as_secs_f32(),
+            audio_fill,
+            dropped_samples,
+            late_frames: self.late_frames,
+        });
+    }
+}
+
+impl CoreThread {
+    // Start emulating at `fps` frames per second. Audio is played
+    // through `audio`, if given.
+    pub fn spawn<T: 'static + Debuggable + Send>(
+        state: Arc<Mutex<CoreState<T>>>,
+        audio: Option<Producer<i16>>,
+        fps: f64,
+    ) -> Self {
+        let (commands, command_receiver) = channel();
+        let (frame_sender, frames) = channel();
+        let (recycled, recycled_receiver) = channel();
+
+        let worker = Worker {
+            state,
+            commands: command_receiver,
+            frames: frame_sender,
+            recycled: recycled_receiver,
+            audio,
+            frame_advance: false,
+            late_frames: 0,
+        };
+
+        let handle = std::thread::Builder::new()
+            .name("core".to_string())
+            .spawn(move || worker.run(fps))
+            .expect("failed to start core thread");
+
+        CoreThread {
+            commands,
+            frames,
+            recycled,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    // Frames completed since the last call
+    pub fn frames(&self) -> TryIter<'_, Frame> {
+        self.frames.try_iter()
+    }
+
+    // Return a framebuffer to the core thread, for reuse
+    pub fn recycle(&self, framebuffer: Vec<u8>) {
+        let _ = self.recycled.send(framebuffer);
+    }
+
+    // Stop the thread and wait for the current frame to complete
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.send(Command::Shutdown);
+            if handle.join().is_err() {
+                println!("Core thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for CoreThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Core;
+    use crate::gameboy::emu::{Emu, Machine};
+
+    #[test]
+    fn test_frames_and_frame_advance() {
+        let mut emu = Emu::new(Machine::GameBoyDMG);
+        emu.mmu.bootstrap_mode = false;
+        emu.mmu.direct_write(0xFF40, 0x91);
+        let state = Arc::new(Mutex::new(CoreState {
+            core: emu,
+            debug: Debug::new(),
+        }));
+
+        // Run much faster than real time
+        let mut thread = CoreThread::spawn(state.clone(), None, 1000.0);
+        let frame = thread.frames.recv().unwrap();
+        assert_eq!(frame.framebuffer.len(), 160 * 144);
+        thread.recycle(frame.framebuffer);
+
+        // In frame advance mode, a frame is only run when requested
+        thread.send(Command::FrameAdvance(true));
+        std::thread::sleep(Duration::from_millis(50));
+        thread.frames().for_each(drop);
+
+        thread.send(Command::Advance { play_audio: false });
+        let frame = thread.frames.recv().unwrap();
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(thread.frames().count(), 0);
+        assert_eq!(state.lock().unwrap().core.current_frame(), frame.number);
+
+        thread.stop();
+    }
+}
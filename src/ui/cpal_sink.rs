@@ -0,0 +1,205 @@
+// Audio output with cpal, the default backend
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, Device, Sample, SampleFormat, SampleRate, Stream, StreamConfig,
+    SupportedBufferSize,
+};
+use ringbuf::Consumer;
+
+use crate::config::AudioConfig;
+use crate::ui::app::AUDIO_SAMPLE_RATE;
+use crate::ui::audio_sink::AudioSink;
+
+pub struct CpalSink {
+    // Plays until dropped
+    _stream: Stream,
+
+    device_name: Option<String>,
+    sample_rate: u32,
+    buffer_range: Option<(u32, u32)>,
+
+    // Latency measured by the output callback, in microseconds: the
+    // samples waiting to be played, the device buffer, and the delay
+    // until the device plays them
+    latency: Arc<AtomicU32>,
+}
+
+// Names of the available output devices
+pub fn device_names() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+        Err(e) => {
+            println!("Failed to list audio devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+// The configured device, or the default device if it's not found
+fn find_device(config: &AudioConfig) -> Option<Device> {
+    let host = cpal::default_host();
+    if let Some(ref name) = config.device {
+        let found = host
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|d| d.name().ok().as_ref() == Some(name)));
+        match found {
+            Some(device) => return Some(device),
+            None => println!(
+                "Audio device not found: {}. Using the default device.",
+                name
+            ),
+        }
+    }
+    host.default_output_device()
+}
+
+impl CpalSink {
+    // Start playing on the configured device
+    pub fn open(config: &AudioConfig, mut consumer: Consumer<i16>) -> Result<Self, String> {
+        let device = find_device(config).ok_or("no audio output device available")?;
+
+        let supported_configs = device
+            .supported_output_configs()
+            .map_err(|e| format!("failed to query audio device: {}", e))?
+            .collect::<Vec<_>>();
+
+        // Use the sample rate of the emulated audio if the device
+        // supports it, and the highest sample rate otherwise
+        let rate = SampleRate(AUDIO_SAMPLE_RATE);
+        let supported = supported_configs
+            .iter()
+            .find(|c| c.min_sample_rate() <= rate && rate <= c.max_sample_rate())
+            .map(|c| c.clone().with_sample_rate(rate))
+            .or_else(|| {
+                supported_configs
+                    .first()
+                    .map(|c| c.clone().with_max_sample_rate())
+            })
+            .ok_or("no supported audio config")?;
+
+        println!("Selected audio config: {:?}", supported);
+
+        let buffer_range = match *supported.buffer_size() {
+            SupportedBufferSize::Range { min, max } => Some((min, max)),
+            SupportedBufferSize::Unknown => None,
+        };
+
+        let err_fn = |err| eprintln!("an error occured on the output audio stream: {}", err);
+        let sample_format = supported.sample_format();
+        let mut stream_config: StreamConfig = supported.into();
+        if let Some(frames) = config.buffer_size {
+            let frames = match buffer_range {
+                Some((min, max)) => frames.clamp(min, max),
+                None => frames,
+            };
+            stream_config.buffer_size = BufferSize::Fixed(frames);
+        }
+
+        let channels = stream_config.channels as usize;
+        let sample_rate = stream_config.sample_rate.0;
+
+        let latency = Arc::new(AtomicU32::new(0));
+        let measured = latency.clone();
+        let mut next_value = move |queued: &mut usize| match consumer.pop() {
+            Some(sample) => {
+                *queued = consumer.len();
+                (sample as f32) / 32768.0
+            }
+            None => 0.0,
+        };
+
+        fn write_beep<T: Sample>(
+            output: &mut [T],
+            channels: usize,
+            next_sample: &mut dyn FnMut(&mut usize) -> f32,
+        ) -> usize {
+            let mut queued = 0;
+            for frame in output.chunks_mut(channels) {
+                let value: T = cpal::Sample::from::<f32>(&next_sample(&mut queued));
+                for sample in frame.iter_mut() {
+                    *sample = value;
+                }
+            }
+            queued
+        }
+
+        // Samples left in the ring buffer and the device buffer, plus
+        // the delay reported by the device
+        let measure = move |info: &cpal::OutputCallbackInfo, frames: usize, queued: usize| {
+            let timestamp = info.timestamp();
+            let delay = timestamp
+                .playback
+                .duration_since(&timestamp.callback)
+                .unwrap_or_default();
+            let buffered = Duration::from_secs_f64((frames + queued) as f64 / sample_rate as f64);
+            measured.store((delay + buffered).as_micros() as u32, Ordering::Relaxed);
+        };
+
+        let stream = match sample_format {
+            SampleFormat::F32 => device.build_output_stream(
+                &stream_config,
+                move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                    let queued = write_beep::<f32>(data, channels, &mut next_value);
+                    measure(info, data.len() / channels, queued);
+                },
+                err_fn,
+            ),
+
+            SampleFormat::I16 => device.build_output_stream(
+                &stream_config,
+                move |data: &mut [i16], info: &cpal::OutputCallbackInfo| {
+                    let queued = write_beep::<i16>(data, channels, &mut next_value);
+                    measure(info, data.len() / channels, queued);
+                },
+                err_fn,
+            ),
+
+            SampleFormat::U16 => device.build_output_stream(
+                &stream_config,
+                move |data: &mut [u16], info: &cpal::OutputCallbackInfo| {
+                    let queued = write_beep::<u16>(data, channels, &mut next_value);
+                    measure(info, data.len() / channels, queued);
+                },
+                err_fn,
+            ),
+        };
+
+        let stream = stream.map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(CpalSink {
+            _stream: stream,
+            device_name: device.name().ok(),
+            sample_rate,
+            buffer_range,
+            latency,
+        })
+    }
+}
+
+impl AudioSink for CpalSink {
+    fn device_name(&self) -> Option<String> {
+        self.device_name.clone()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn buffer_range(&self) -> Option<(u32, u32)> {
+        self.buffer_range
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        match self.latency.load(Ordering::Relaxed) {
+            0 => None,
+            us => Some(Duration::from_micros(us as u64)),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use crate::core::{Debuggable, RegisterValue};
+use crate::debug::{WatchExpression, WatchFormat};
+use crate::symbols::{is_banked, SymbolTable};
+use crate::ui::window_layout;
+
+// cycle   reg   prev reg   frm
+// 0       5     0
+// 0       5     0
+// 0       5     0
+// 0       5     0
+// 1       20    5
+// 1       20    5
+// 1       20    5
+// 1       20    5
+// 2       12    12
+// 2       12    12
+// 2       12    12
+// 2       12    12
+
+use egui::{Button, Context, Label, RichText, Ui};
+
+pub struct RegistersView {
+    prev: Vec<RegisterValue>,
+    compare_with: Vec<RegisterValue>,
+    prev_cycle: u64,
+}
+
+fn format_register(reg: &RegisterValue) -> String {
+    match reg.bits {
+        8 => format!("{:02X}", reg.value),
+        _ => format!("{:04X}", reg.value),
+    }
+}
+
+impl RegistersView {
+    pub fn new() -> Self {
+        return RegistersView {
+            prev: Vec::new(),
+            compare_with: Vec::new(),
+            prev_cycle: 0,
+        };
+    }
+
+    fn render_register(ui: &mut Ui, reg: &RegisterValue, prev: Option<&RegisterValue>) {
+        ui.label(format!("{}:", reg.name));
+
+        let value_text = format_register(reg);
+
+        match prev {
+            Some(prev) if prev.value != reg.value => {
+                let bg = ui.visuals().selection.bg_fill;
+                let fg = ui.visuals().selection.stroke.color;
+                let lbl = Label::new(RichText::new(value_text).background_color(bg).color(fg));
+                ui.add(lbl)
+                    .on_hover_text(format!("Was: {}", format_register(prev)));
+            }
+            _ => {
+                ui.label(value_text);
+            }
+        }
+    }
+
+    // Render all registers of `bits` size on one line
+    fn render_row(&self, ui: &mut Ui, registers: &[RegisterValue], bits: u8) {
+        for reg in registers.iter().filter(|r| r.bits == bits) {
+            let prev = self.compare_with.iter().find(|r| r.name == reg.name);
+            RegistersView::render_register(ui, reg, prev);
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut Ui, core: &impl Debuggable) {
+        let registers = core.registers();
+
+        ui.scope(|ui| {
+            ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+            ui.horizontal(|ui| self.render_row(ui, &registers, 8));
+            ui.horizontal(|ui| {
+                self.render_row(ui, &registers, 16);
+                ui.label(format!("Cycle: {}", core.cycle()));
+            });
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Frame: {} Line: {} Dot: {} ROM bank: {:02X} RAM bank: {:02X}",
+                    core.current_frame(),
+                    core.scanline(),
+                    core.dot(),
+                    core.bank(0x4000),
+                    core.bank(0xA000)
+                ));
+
+                // How much of the frame has been executed
+                let (cycle, total) = (core.frame_cycle(), core.cycles_per_frame());
+                ui.add(
+                    egui::ProgressBar::new(cycle as f32 / total as f32)
+                        .desired_width(160.0)
+                        .text(format!("{}/{}", cycle, total)),
+                );
+            });
+        });
+
+        // The previous register values should only be updated when
+        // another instructions has been executed. There's currently
+        // no better way to do that than to check if PC has changed
+        // since last render. This is only an approximation as the PC
+        // can also be changed by the debugger, and the PC may not
+        // change if it's on a jump instruction to the same address.
+        if self.prev_cycle != core.cycle() {
+            self.compare_with = std::mem::replace(&mut self.prev, registers);
+            self.prev_cycle = core.cycle();
+        }
+    }
+}
+
+pub struct DisassemblyView {
+    start_address: usize,
+    follow_pc: bool,
+}
+
+impl DisassemblyView {
+    pub fn new() -> Self {
+        DisassemblyView {
+            start_address: 0,
+            follow_pc: true,
+        }
+    }
+
+    // Find the last visible address. Labels take a line of their own.
+    fn stop_address(
+        &mut self,
+        core: &impl Debuggable,
+        symbols: &SymbolTable,
+        lines: usize,
+    ) -> usize {
+        let mut adr = self.start_address;
+        let mut n = 0;
+
+        while n < lines {
+            if symbols.label(core.bank(adr), adr).is_some() {
+                n += 1;
+            }
+            match core.disassemble(adr).1 {
+                Some(len) => adr += len,
+                None => break,
+            }
+            n += 1;
+        }
+
+        adr
+    }
+
+    fn update_range(&mut self, core: &impl Debuggable, symbols: &SymbolTable, lines: usize) {
+        if !self.follow_pc {
+            return;
+        }
+
+        let pc = core.pc();
+
+        if pc < self.start_address {
+            self.start_address = pc;
+            return;
+        }
+
+        let stop_address = self.stop_address(core, symbols, lines);
+        if pc > stop_address {
+            self.start_address = pc;
+            return;
+        }
+    }
+
+    fn render_content(
+        &mut self,
+        ui: &mut Ui,
+        core: &impl Debuggable,
+        symbols: &SymbolTable,
+        lines: usize,
+    ) {
+        let mut addr = self.start_address;
+        let pc = core.pc();
+        let mut n = 0;
+
+        while n < lines {
+            let bank = core.bank(addr);
+            if let Some(label) = symbols.label(bank, addr) {
+                ui.label(format!("{}:", label));
+                n += 1;
+            }
+
+            // Banked addresses are prefixed with their bank
+            let address = match is_banked(addr) {
+                true => format!("{:02x}:{:04x}", bank, addr),
+                false => format!("   {:04x}", addr),
+            };
+            let (mnemonic, len) = core.disassemble(addr);
+            let text = match symbols.source_line(bank, addr) {
+                Some(source) => format!("{}: {:<16} {}", address, mnemonic, source),
+                None => format!("{}: {}", address, mnemonic),
+            };
+
+            let lbl;
+            if addr == pc {
+                let bg = ui.visuals().selection.bg_fill;
+                let fg = ui.visuals().selection.stroke.color;
+                lbl = Label::new(RichText::new(text).background_color(bg).color(fg));
+            } else {
+                lbl = Label::new(text);
+            }
+
+            ui.add(lbl);
+            n += 1;
+
+            match len {
+                Some(len) => addr += len,
+                None => break,
+            }
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut Ui, core: &impl Debuggable, symbols: &SymbolTable) {
+        ui.scope(|ui| {
+            ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+            let row_height = 16.0; //ui.fonts().row_height(TextStyle::Monospace) + 2.0;
+            let avail_height = ui.available_height();
+            let lines = (avail_height / row_height) as usize;
+            if lines >= 1 {
+                self.update_range(core, symbols, lines - 1);
+                self.render_content(ui, core, symbols, lines - 1);
+            }
+            ui.allocate_space(ui.available_size());
+        });
+    }
+}
+
+// Watch expressions, evaluated whenever execution stops
+pub struct WatchView {
+    watches: Vec<WatchExpression>,
+    input: String,
+
+    // Cycle of the last evaluation
+    evaluated_at: Option<u64>,
+}
+
+impl Default for WatchView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WatchView {
+    pub fn new() -> Self {
+        WatchView {
+            watches: Vec::new(),
+            input: String::new(),
+            evaluated_at: None,
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut Ui, core: &impl Debuggable, paused: bool) {
+        if paused && self.evaluated_at != Some(core.cycle()) {
+            for watch in self.watches.iter_mut() {
+                watch.update(core);
+            }
+            self.evaluated_at = Some(core.cycle());
+        }
+
+        ui.scope(|ui| {
+            ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+
+            ui.horizontal(|ui| match WatchExpression::parse(&self.input) {
+                Ok(mut watch) => {
+                    ui.text_edit_singleline(&mut self.input);
+                    if ui.button("✚").clicked() {
+                        watch.update(core);
+                        self.watches.push(watch);
+                    }
+                }
+                Err(_) => {
+                    ui.text_edit_singleline(&mut self.input)
+                        .on_hover_text("Expression, such as HL, SP-2 or [HL+1]");
+                    ui.add_enabled(false, Button::new("✚"));
+                }
+            });
+
+            let mut removed = None;
+            egui::Grid::new("watch_grid").show(ui, |ui| {
+                for (n, watch) in self.watches.iter_mut().enumerate() {
+                    ui.label(&watch.text);
+                    ui.label(watch.display());
+                    ui.horizontal(|ui| {
+                        let format = &mut watch.format;
+                        ui.selectable_value(format, WatchFormat::Hex, "Hex");
+                        ui.selectable_value(format, WatchFormat::Decimal, "Dec");
+                        ui.selectable_value(format, WatchFormat::Binary, "Bin");
+                    });
+                    if ui.small_button("🗑").clicked() {
+                        removed = Some(n);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(n) = removed {
+                self.watches.remove(n);
+            }
+        });
+    }
+}
+
+pub struct DebugWindow {
+    dis_view: DisassemblyView,
+    registers_view: RegistersView,
+    watch_view: WatchView,
+}
+
+impl DebugWindow {
+    pub fn new() -> Self {
+        DebugWindow {
+            dis_view: DisassemblyView::new(),
+            registers_view: RegistersView::new(),
+            watch_view: WatchView::new(),
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        ctx: &Context,
+        core: &impl Debuggable,
+        symbols: &SymbolTable,
+        paused: bool,
+        open: &mut bool,
+    ) {
+        window_layout::show(
+            ctx,
+            "Debugger",
+            egui::Window::new("Debugger").open(open).resizable(true),
+            |ui| {
+                self.registers_view.render(ui, core);
+                ui.separator();
+                self.watch_view.render(ui, core, paused);
+                ui.separator();
+                self.dis_view.render(ui, core, symbols);
+            },
+        );
+    }
+}
@@ -1,6 +1,86 @@
 use egui::{emath, epaint, pos2, vec2, Context, Rect, Sense, Shape, Stroke, Ui};
 
-use crate::gameboy::{apu::wave_gen::CH3_WAVE_MEMORY_SIZE, emu::Emu};
+use crate::gameboy::{
+    apu::{
+        tap::{ChannelTap, TAP_SIZE},
+        wave_gen::CH3_WAVE_MEMORY_SIZE,
+    },
+    emu::Emu,
+};
+use crate::ui::window_layout;
+
+#[cfg(feature = "recording")]
+use crate::{ui::utils::timestamped_filename, wave_audio_recorder::WaveAudioRecorder};
+
+// Number of tap samples shown in the oscilloscope
+const SCOPE_SAMPLES: usize = TAP_SIZE / 2;
+
+// Draw the recent output of a channel. To get a stable image of
+// periodic waveforms, the plot starts at the first rising edge
+// found in the older half of the tap.
+pub fn render_oscilloscope(ui: &mut Ui, tap: &ChannelTap) {
+    let height = ui.spacing().slider_width / 2.0;
+    let size = vec2(ui.available_size_before_wrap().x, height);
+    let (rect, _) = ui.allocate_at_least(size, Sense::hover());
+    let style = ui.style().noninteractive();
+
+    let mut shapes = Vec::with_capacity(2);
+    shapes.push(Shape::Rect(epaint::RectShape {
+        rect,
+        rounding: style.rounding,
+        fill: ui.visuals().extreme_bg_color,
+        stroke: ui.style().noninteractive().bg_stroke,
+    }));
+
+    let mut start = 0;
+    for n in 1..(TAP_SIZE - SCOPE_SAMPLES) {
+        if tap.get(n - 1) <= 0 && tap.get(n) > 0 {
+            start = n;
+            break;
+        }
+    }
+
+    let scope_rect = Rect::from_x_y_ranges(
+        0.0..=SCOPE_SAMPLES as f32,
+        i16::MAX as f32..=i16::MIN as f32,
+    );
+    let to_screen = emath::RectTransform::from_to(scope_rect, rect);
+
+    let points = (0..SCOPE_SAMPLES)
+        .map(|n| to_screen.transform_pos_clamped(pos2(n as f32, tap.get(start + n) as f32)))
+        .collect();
+
+    shapes.push(Shape::line(
+        points,
+        Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+
+    ui.painter().extend(shapes);
+}
+
+// Heading for a channel, with mute and solo toggles and frequency
+fn render_channel_heading(ui: &mut Ui, emu: &mut Emu, n: usize, frequency: f64) {
+    let apu = &mut emu.mmu.apu;
+
+    ui.horizontal(|ui| {
+        ui.heading(format!("Channel {}", n + 1));
+
+        if ui.selectable_label(apu.muted[n], "Mute").clicked() {
+            apu.muted[n] = !apu.muted[n];
+        }
+
+        if ui.selectable_label(apu.solo == Some(n), "Solo").clicked() {
+            apu.solo = match apu.solo {
+                Some(s) if s == n => None,
+                _ => Some(n),
+            };
+        }
+
+        ui.label(format!("{:.1} Hz", frequency));
+    });
+
+    render_oscilloscope(ui, &apu.taps[n]);
+}
 
 pub fn render_wavetable(ui: &mut Ui, emu: &mut Emu) {
     let sample_count = CH3_WAVE_MEMORY_SIZE * 2;
@@ -46,37 +126,102 @@ pub fn render_wavetable(ui: &mut Ui, emu: &mut Emu) {
     ui.painter().extend(shapes);
 }
 
-pub fn render_audio_window(ctx: &Context, emu: &mut Emu, open: &mut bool) {
-    egui::Window::new("Audio").open(open).show(ctx, |ui| {
-        ui.heading("Channel 1");
-        ui.label(format!("Enabled: {}", emu.mmu.apu.s1.enabled));
-        ui.label(format!("Envelope: {}", emu.mmu.apu.s1.envelope));
-        ui.label(format!("Frequency: {}", emu.mmu.apu.s1.frequency));
-
-        ui.heading("Channel 2");
-        ui.label(format!("Enabled: {}", emu.mmu.apu.s2.enabled));
-        ui.label(format!("Envelope: {}", emu.mmu.apu.s2.envelope));
-
-        ui.heading("Channel 3");
-        ui.label(format!("Enabled: {}", emu.mmu.apu.ch3.enabled));
-        ui.label(format!("Volume Code: {}", emu.mmu.apu.ch3.volume_code));
-        ui.label(format!(
-            "Length counter: {}",
-            emu.mmu.apu.ch3.length_counter.value,
-        ));
-        ui.label(format!(
-            "Frequency timer: {}",
-            emu.mmu.apu.ch3.frequency_timer
-        ));
-        ui.label(format!("Wave position: {}", emu.mmu.apu.ch3.wave_position));
-        render_wavetable(ui, emu);
-
-        ui.heading("Channel 4");
-        ui.label(format!("Enabled: {}", emu.mmu.apu.ch4.enabled));
-        ui.label(format!("LFSR: {}", emu.mmu.apu.ch4.lfsr));
-        ui.label(format!(
-            "Frequency timer: {}",
-            emu.mmu.apu.ch4.frequency_timer
-        ));
-    });
+pub struct AudioWindow {
+    // Record one file per channel in addition to the mixed output
+    record_stems: bool,
+}
+
+impl Default for AudioWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioWindow {
+    pub fn new() -> Self {
+        AudioWindow {
+            record_stems: false,
+        }
+    }
+
+    #[cfg(feature = "recording")]
+    fn start_recording(&self, emu: &mut Emu) {
+        let filename = timestamped_filename("recording", "wav");
+        let basename = filename.trim_end_matches(".wav");
+        let sample_rate = emu.mmu.apu.sample_rate() as u32;
+
+        match WaveAudioRecorder::create(basename, sample_rate, self.record_stems) {
+            Ok(recorder) => {
+                emu.start_recording(Box::new(recorder), self.record_stems);
+                println!("Recording audio to {}", filename);
+            }
+            Err(e) => println!("Failed to start audio recording: {}", e),
+        }
+    }
+
+    #[cfg(not(feature = "recording"))]
+    fn start_recording(&self, _emu: &mut Emu) {
+        println!("Audio recording requires the \"recording\" feature");
+    }
+
+    fn render_recording_controls(&mut self, ui: &mut Ui, emu: &mut Emu) {
+        ui.horizontal(|ui| {
+            if emu.is_recording() {
+                if ui.button("Stop recording").clicked() {
+                    emu.stop_recording();
+                    println!("Audio recording stopped");
+                }
+            } else {
+                if ui.button("Record WAV").clicked() {
+                    self.start_recording(emu);
+                }
+                ui.checkbox(&mut self.record_stems, "One file per channel");
+            }
+        });
+    }
+
+    pub fn render(&mut self, ctx: &Context, emu: &mut Emu, open: &mut bool) {
+        window_layout::show(ctx, "Audio", egui::Window::new("Audio").open(open), |ui| {
+            self.render_recording_controls(ui, emu);
+            ui.separator();
+            render_channels(ui, emu);
+        });
+    }
+}
+
+fn render_channels(ui: &mut Ui, emu: &mut Emu) {
+    let frequency = emu.mmu.apu.s1.frequency_hz();
+    render_channel_heading(ui, emu, 0, frequency);
+    ui.label(format!("Enabled: {}", emu.mmu.apu.s1.enabled));
+    ui.label(format!("Envelope: {}", emu.mmu.apu.s1.envelope));
+    ui.label(format!("Frequency: {}", emu.mmu.apu.s1.frequency));
+
+    let frequency = emu.mmu.apu.s2.frequency_hz();
+    render_channel_heading(ui, emu, 1, frequency);
+    ui.label(format!("Enabled: {}", emu.mmu.apu.s2.enabled));
+    ui.label(format!("Envelope: {}", emu.mmu.apu.s2.envelope));
+
+    let frequency = emu.mmu.apu.ch3.frequency_hz();
+    render_channel_heading(ui, emu, 2, frequency);
+    ui.label(format!("Enabled: {}", emu.mmu.apu.ch3.enabled));
+    ui.label(format!("Volume Code: {}", emu.mmu.apu.ch3.volume_code));
+    ui.label(format!(
+        "Length counter: {}",
+        emu.mmu.apu.ch3.length_counter.value,
+    ));
+    ui.label(format!(
+        "Frequency timer: {}",
+        emu.mmu.apu.ch3.frequency_timer
+    ));
+    ui.label(format!("Wave position: {}", emu.mmu.apu.ch3.wave_position));
+    render_wavetable(ui, emu);
+
+    let frequency = emu.mmu.apu.ch4.frequency_hz();
+    render_channel_heading(ui, emu, 3, frequency);
+    ui.label(format!("Enabled: {}", emu.mmu.apu.ch4.enabled));
+    ui.label(format!("LFSR: {}", emu.mmu.apu.ch4.lfsr));
+    ui.label(format!(
+        "Frequency timer: {}",
+        emu.mmu.apu.ch4.frequency_timer
+    ));
 }
@@ -0,0 +1,81 @@
+use egui::{Color32, Context};
+
+use crate::core::Core;
+use crate::gameboy::call_stack::FrameKind;
+use crate::gameboy::emu::Emu;
+use crate::symbols::SymbolTable;
+use crate::ui::window_layout;
+
+// Address, followed by the label if known
+fn format_address(emu: &Emu, symbols: &SymbolTable, addr: u16) -> String {
+    let addr = addr as usize;
+    match symbols.describe(emu.bank(addr), addr) {
+        Some(label) => format!("{:04X} {}", addr, label),
+        None => format!("{:04X}", addr),
+    }
+}
+
+pub fn render_call_stack_window(
+    ctx: &Context,
+    emu: &mut Emu,
+    symbols: &SymbolTable,
+    open: &mut bool,
+) {
+    window_layout::show(
+        ctx,
+        "Call stack",
+        egui::Window::new("Call stack").open(open).resizable(true),
+        |ui| {
+            ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+
+            let stack = &emu.cpu.call_stack;
+            if let Some(ref corruption) = stack.last_corruption {
+                let text = format!(
+                    "Stack corruption: return at {:04X} to {:04X}, expected {:04X} ({} in total)",
+                    corruption.pc, corruption.actual, corruption.expected, stack.corruptions
+                );
+                ui.horizontal(|ui| {
+                    ui.colored_label(Color32::LIGHT_RED, text);
+                    if ui.button("Clear").clicked() {
+                        emu.cpu.call_stack.corruptions = 0;
+                        emu.cpu.call_stack.last_corruption = None;
+                    }
+                });
+                ui.separator();
+            }
+
+            let stack = &emu.cpu.call_stack;
+            if stack.frames.is_empty() {
+                ui.label("No calls");
+                return;
+            }
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("call_stack_grid_id")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Routine");
+                        ui.label("Via");
+                        ui.label("Called from");
+                        ui.label("Returns to");
+                        ui.label("SP");
+                        ui.end_row();
+
+                        // Innermost frame first
+                        for frame in stack.frames.iter().rev() {
+                            ui.label(format_address(emu, symbols, frame.target));
+                            ui.label(match frame.kind {
+                                FrameKind::Call => "CALL",
+                                FrameKind::Rst => "RST",
+                                FrameKind::Interrupt => "IRQ",
+                            });
+                            ui.label(format_address(emu, symbols, frame.caller));
+                            ui.label(format_address(emu, symbols, frame.return_addr));
+                            ui.label(format!("{:04X}", frame.sp));
+                            ui.end_row();
+                        }
+                    });
+            });
+        },
+    );
+}
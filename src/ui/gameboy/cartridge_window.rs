@@ -1,26 +1,236 @@
-use egui::Context;
+use egui::{Button, Color32, Context, Ui};
 
+use crate::gameboy::cartridge::cartridge::Cartridge;
+use crate::gameboy::cartridge::cartridge_header::{CartridgeHeader, HeaderWarning};
+use crate::gameboy::cartridge::cartridge_type::CartridgeType;
 use crate::gameboy::emu::Emu;
+use crate::ui::window_layout;
 
-pub struct CartridgeWindow {}
+fn cgb_support(header: &CartridgeHeader) -> &'static str {
+    match header.cgb_flag {
+        0xC0 => "CGB only",
+        0x80 => "CGB enhanced",
+        _ => "No",
+    }
+}
+
+// Describe a checksum as verified, or with the value it should have
+fn checksum(value: String, warning: Option<&HeaderWarning>) -> String {
+    match warning {
+        Some(HeaderWarning::HeaderChecksum { actual, .. }) => {
+            format!("{} (bad, should be {:02X})", value, actual)
+        }
+        Some(HeaderWarning::GlobalChecksum { actual, .. }) => {
+            format!("{} (bad, should be {:04X})", value, actual)
+        }
+        _ => format!("{} (ok)", value),
+    }
+}
+
+pub struct CartridgeWindow {
+    cdl_filename: String,
+    cdl_status: String,
+
+    // The cartridge last pulled out, which can be inserted again
+    ejected: Option<Box<dyn Cartridge>>,
+    rom_filename: String,
+    slot_status: String,
+}
 
 impl CartridgeWindow {
     pub fn new() -> Self {
-        CartridgeWindow {}
+        CartridgeWindow {
+            cdl_filename: "rom.cdl".to_string(),
+            cdl_status: String::new(),
+            ejected: None,
+            rom_filename: String::new(),
+            slot_status: String::new(),
+        }
     }
 
-    pub fn render(&mut self, ctx: &Context, emu: &mut Emu, open: &mut bool) {
+    // Pull out and insert cartridges while running
+    fn render_slot(&mut self, ui: &mut Ui, emu: &mut Emu) {
+        ui.horizontal(|ui| {
+            let empty = matches!(
+                emu.mmu.cartridge.cartridge_type(),
+                CartridgeType::NoCartridge
+            );
+            if ui.add_enabled(!empty, Button::new("Eject")).clicked() {
+                self.ejected = Some(emu.eject_cartridge());
+                self.slot_status = String::new();
+            }
+            if ui
+                .add_enabled(self.ejected.is_some(), Button::new("Reinsert"))
+                .clicked()
+            {
+                if let Some(cartridge) = self.ejected.take() {
+                    emu.insert_cartridge(cartridge);
+                }
+            }
+
+            ui.text_edit_singleline(&mut self.rom_filename)
+                .on_hover_text("ROM file");
+            let insert = Button::new("Insert ROM");
+            if ui
+                .add_enabled(!self.rom_filename.is_empty(), insert)
+                .clicked()
+            {
+                self.slot_status = match emu.insert_rom(&self.rom_filename) {
+                    Ok(_) => format!("Inserted {}", self.rom_filename),
+                    Err(e) => format!("Failed to insert {}: {}", self.rom_filename, e),
+                };
+            }
+        });
+        ui.label(&self.slot_status);
+    }
+
+    // Start and save the code/data log, and show the share of each ROM
+    // bank that has been executed or read
+    fn render_cdl(&mut self, ui: &mut Ui, emu: &mut Emu) {
+        ui.horizontal(|ui| {
+            ui.label("Code/data log:");
+            match emu.mmu.cdl.is_enabled() {
+                true => {
+                    if ui.button("Stop").on_hover_text("Discard the log").clicked() {
+                        emu.mmu.cdl.stop();
+                    }
+                }
+                false => {
+                    if ui.button("Start").clicked() {
+                        emu.start_cdl();
+                    }
+                }
+            }
+            ui.text_edit_singleline(&mut self.cdl_filename);
+            let save = Button::new("Save");
+            if ui.add_enabled(emu.mmu.cdl.is_enabled(), save).clicked() {
+                self.cdl_status = match emu.mmu.cdl.save(&self.cdl_filename) {
+                    Ok(_) => format!("Saved {}", self.cdl_filename),
+                    Err(e) => format!("Failed to save: {}", e),
+                };
+            }
+        });
+        ui.label(&self.cdl_status);
+
+        let coverage = emu.mmu.cdl.bank_coverage();
+        if coverage.is_empty() {
+            return;
+        }
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                egui::Grid::new("cdl_grid").striped(true).show(ui, |ui| {
+                    ui.label("Bank");
+                    ui.label("Code");
+                    ui.label("Data");
+                    ui.label("Covered");
+                    ui.end_row();
+
+                    for (bank, c) in coverage.iter().enumerate() {
+                        ui.label(format!("{:02X}", bank));
+                        ui.label(format!("{}", c.code));
+                        ui.label(format!("{}", c.data));
+                        ui.label(format!("{:.1}%", c.covered() * 100.0));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    fn render_header(&mut self, ui: &mut Ui, emu: &Emu) {
         let c = &emu.mmu.cartridge;
-        let t = &c.cartridge_type();
-
-        egui::Window::new("Cartridge").open(open).show(ctx, |ui| {
-            ui.label(format!("Cartridge type: {}", t.to_string()));
-            ui.label(format!("Type code: {}", c.read_abs(0x147)));
-            ui.label(format!("Licensee: {}", c.header().licensee()));
-            ui.label(format!("ROM banks: {}", c.header().rom_bank_count));
-            ui.label(format!("ROM size: {}", c.header().rom_size));
-            ui.label(format!("ROM size: {} (max)", t.max_rom_size()));
-            ui.label(format!("RAM size: {}", c.header().ram_size));
+        let t = c.cartridge_type();
+        let h = c.header();
+        let warnings = &emu.mmu.header_warnings;
+        let licensee = match h.old_licensee_code {
+            0x33 => h.licensee(),
+            code => format!("{:02X} (old code)", code),
+        };
+        let header_checksum = checksum(
+            format!("{:02X}", h.checksum),
+            warnings
+                .iter()
+                .find(|w| matches!(w, HeaderWarning::HeaderChecksum { .. })),
+        );
+        let global_checksum = checksum(
+            format!("{:04X}", h.global_checksum),
+            warnings
+                .iter()
+                .find(|w| matches!(w, HeaderWarning::GlobalChecksum { .. })),
+        );
+
+        let rows = [
+            ("Title", h.title.clone()),
+            (
+                "Cartridge type",
+                format!("{} ({:02X})", t.to_string(), h.cartridge_type),
+            ),
+            ("CGB", cgb_support(h).to_string()),
+            ("SGB", format!("{}", h.sgb_features)),
+            ("Licensee", licensee),
+            (
+                "ROM size",
+                format!("{} ({} banks)", h.rom_size, h.rom_bank_count),
+            ),
+            ("ROM size (max)", format!("{}", t.max_rom_size())),
+            (
+                "RAM size",
+                format!("{} ({} banks)", h.ram_size, h.ram_bank_count),
+            ),
+            ("Header checksum", header_checksum),
+            ("Global checksum", global_checksum),
+        ];
+        egui::Grid::new("cartridge_header_grid").show(ui, |ui| {
+            for (name, value) in rows {
+                ui.label(name);
+                ui.label(value);
+                ui.end_row();
+            }
         });
     }
+
+    // Live registers of the memory bank controller
+    fn render_mapper(&mut self, ui: &mut Ui, emu: &Emu) {
+        let state = emu.mmu.cartridge.debug_state();
+        if state.is_empty() {
+            return;
+        }
+        egui::Grid::new("cartridge_mapper_grid").show(ui, |ui| {
+            for (name, value) in state {
+                ui.label(name);
+                ui.label(value);
+                ui.end_row();
+            }
+        });
+    }
+
+    pub fn render(&mut self, ctx: &Context, emu: &mut Emu, open: &mut bool) {
+        window_layout::show(
+            ctx,
+            "Cartridge",
+            egui::Window::new("Cartridge").open(open),
+            |ui| {
+                self.render_slot(ui, emu);
+                ui.separator();
+                if let CartridgeType::NoCartridge = emu.mmu.cartridge.cartridge_type() {
+                    ui.label("No cartridge");
+                    return;
+                }
+
+                self.render_header(ui, emu);
+                for warning in emu.mmu.header_warnings.iter() {
+                    let color = match warning.is_fatal() {
+                        true => Color32::RED,
+                        false => Color32::YELLOW,
+                    };
+                    ui.colored_label(color, warning.to_string());
+                }
+
+                ui.separator();
+                self.render_mapper(ui, emu);
+                ui.separator();
+                self.render_cdl(ui, emu);
+            },
+        );
+    }
 }
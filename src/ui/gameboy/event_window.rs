@@ -0,0 +1,140 @@
+use egui::{vec2, Color32, Context, Pos2, Rect, Sense, Ui};
+
+use crate::gameboy::emu::Emu;
+use crate::gameboy::io_registers::find_io_register;
+use crate::gameboy::mmu::{
+    BGP_REG, LCDC_REG, OBP0_REG, OBP1_REG, SCX_REG, SCY_REG, WX_REG, WY_REG,
+};
+use crate::gameboy::ppu::{DOTS_PER_LINE, LINES_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::ppu_events::PpuEvent;
+use crate::ui::window_layout;
+
+// Size of a dot and a line on screen
+const DOT_WIDTH: f32 = 1.0;
+const LINE_HEIGHT: f32 = 2.0;
+
+// Dots of OAM scan at the start of each line, and the shortest
+// possible pixel transfer that follows it
+const OAM_SCAN_DOTS: usize = 80;
+const MIN_DRAWING_DOTS: usize = 172;
+
+// Events within this many dots of the mouse are listed when hovering
+const HOVER_DISTANCE: usize = 4;
+
+fn event_color(address: usize) -> Color32 {
+    match address {
+        LCDC_REG => Color32::RED,
+        SCX_REG | SCY_REG => Color32::GREEN,
+        BGP_REG => Color32::LIGHT_BLUE,
+        OBP0_REG | OBP1_REG => Color32::GOLD,
+        WX_REG | WY_REG => Color32::from_rgb(255, 0, 255),
+        _ => Color32::WHITE,
+    }
+}
+
+fn format_event(event: &PpuEvent) -> String {
+    let name = find_io_register(event.address).map_or("?", |r| r.name);
+    format!(
+        "{:3} {:3}  {:<4} {:02X}",
+        event.line, event.dot, name, event.value
+    )
+}
+
+// Shows the writes to PPU registers during the last frame, plotted at
+// the scanline and dot at which they were made. The background shows
+// the visible area: lines 0-143, from the end of OAM scan.
+fn render_timing_grid(ui: &mut Ui, events: &[PpuEvent]) {
+    let size = vec2(
+        DOTS_PER_LINE as f32 * DOT_WIDTH,
+        LINES_PER_FRAME as f32 * LINE_HEIGHT,
+    );
+    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+    let painter = ui.painter_at(rect);
+
+    let area = |dot: usize, line: usize, dots: usize, lines: usize| {
+        Rect::from_min_size(
+            rect.min + vec2(dot as f32 * DOT_WIDTH, line as f32 * LINE_HEIGHT),
+            vec2(dots as f32 * DOT_WIDTH, lines as f32 * LINE_HEIGHT),
+        )
+    };
+
+    painter.rect_filled(rect, 0.0, Color32::from_gray(24));
+    painter.rect_filled(
+        area(0, 0, OAM_SCAN_DOTS, SCREEN_HEIGHT),
+        0.0,
+        Color32::from_gray(40),
+    );
+    painter.rect_filled(
+        area(OAM_SCAN_DOTS, 0, MIN_DRAWING_DOTS, SCREEN_HEIGHT),
+        0.0,
+        Color32::from_gray(64),
+    );
+
+    // A grid line every 8 scanlines, and at the last visible pixel
+    for line in (0..LINES_PER_FRAME).step_by(8) {
+        painter.rect_filled(area(0, line, DOTS_PER_LINE, 1), 0.0, Color32::from_gray(32));
+    }
+    painter.rect_filled(
+        area(OAM_SCAN_DOTS + SCREEN_WIDTH, 0, 1, SCREEN_HEIGHT),
+        0.0,
+        Color32::from_gray(96),
+    );
+
+    for event in events {
+        let center = rect.min
+            + vec2(
+                (event.dot as f32 + 0.5) * DOT_WIDTH,
+                (event.line as f32 + 0.5) * LINE_HEIGHT,
+            );
+        painter.circle_filled(center, 1.5, event_color(event.address));
+    }
+
+    if let Some(Pos2 { x, y }) = response.hover_pos() {
+        let dot = ((x - rect.min.x) / DOT_WIDTH) as usize;
+        let line = ((y - rect.min.y) / LINE_HEIGHT) as usize;
+        let nearby: Vec<String> = events
+            .iter()
+            .filter(|e| e.line == line && e.dot.abs_diff(dot) <= HOVER_DISTANCE)
+            .map(format_event)
+            .collect();
+        let text = format!("Line {} dot {}\n{}", line, dot, nearby.join("\n"));
+        response.on_hover_text(text);
+    }
+}
+
+pub fn render_event_window(ctx: &Context, emu: &mut Emu, open: &mut bool) {
+    window_layout::show(
+        ctx,
+        "PPU events",
+        egui::Window::new("PPU events").open(open).resizable(true),
+        |ui| {
+            let log = &mut emu.mmu.ppu_events;
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut log.enabled, "Record");
+                for (name, address) in [
+                    ("LCDC", LCDC_REG),
+                    ("SCX/SCY", SCX_REG),
+                    ("BGP", BGP_REG),
+                    ("OBP0/1", OBP0_REG),
+                    ("WX/WY", WX_REG),
+                ] {
+                    ui.colored_label(event_color(address), name);
+                }
+            });
+
+            let events = log.last_frame();
+            render_timing_grid(ui, events);
+
+            ui.separator();
+            ui.label(format!("{} writes in the last frame", events.len()));
+            ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+            egui::ScrollArea::vertical()
+                .max_height(160.0)
+                .show(ui, |ui| {
+                    for event in events {
+                        ui.label(format_event(event));
+                    }
+                });
+        },
+    );
+}
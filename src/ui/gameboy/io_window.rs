@@ -0,0 +1,96 @@
+use egui::{Context, Label, Sense, TextEdit, Ui};
+
+use crate::gameboy::emu::Emu;
+use crate::gameboy::io_registers::{IoRegister, IO_REGISTERS, IO_REGISTER_GROUPS};
+use crate::ui::window_layout;
+
+// Lists the IO registers, decoded into their bit fields. Values can
+// be changed while execution is stopped.
+pub struct IoWindow {
+    group: &'static str,
+
+    // Address of the register being edited, and the value as entered
+    editing: Option<usize>,
+    edit_value: String,
+}
+
+impl Default for IoWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoWindow {
+    pub fn new() -> Self {
+        IoWindow {
+            group: IO_REGISTER_GROUPS[0],
+            editing: None,
+            edit_value: String::new(),
+        }
+    }
+
+    fn render_value(&mut self, ui: &mut Ui, emu: &mut Emu, reg: &IoRegister, paused: bool) {
+        let value = emu.mmu.direct_read(reg.address);
+
+        if paused && self.editing == Some(reg.address) {
+            let resp = ui.add(TextEdit::singleline(&mut self.edit_value).desired_width(24.0));
+            if resp.lost_focus() {
+                if ui.input().key_pressed(egui::Key::Enter) {
+                    if let Ok(value) = u8::from_str_radix(self.edit_value.trim(), 16) {
+                        emu.mmu.direct_write(reg.address, value);
+                    }
+                }
+                self.editing = None;
+            }
+            return;
+        }
+
+        let resp = ui.add(Label::new(format!("{:02X}", value)).sense(Sense::click()));
+        if paused && resp.on_hover_text("Click to edit").clicked() {
+            self.editing = Some(reg.address);
+            self.edit_value = format!("{:02X}", value);
+        }
+    }
+
+    pub fn render(&mut self, ctx: &Context, emu: &mut Emu, paused: bool, open: &mut bool) {
+        window_layout::show(
+            ctx,
+            "IO registers",
+            egui::Window::new("IO registers").open(open).resizable(true),
+            |ui| {
+                ui.horizontal(|ui| {
+                    for group in IO_REGISTER_GROUPS {
+                        ui.selectable_value(&mut self.group, group, group);
+                    }
+                });
+
+                if !paused {
+                    ui.label("Stop execution to edit values");
+                }
+
+                ui.separator();
+
+                let group = self.group;
+                ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("io_registers_grid_id")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for reg in IO_REGISTERS.iter().filter(|r| r.group == group) {
+                                let value = emu.mmu.direct_read(reg.address);
+                                ui.label(reg.name);
+                                ui.label(format!("{:04X}", reg.address));
+                                self.render_value(ui, emu, reg, paused);
+                                ui.vertical(|ui| {
+                                    for field in reg.decode(value) {
+                                        ui.label(field);
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                });
+            },
+        );
+    }
+}
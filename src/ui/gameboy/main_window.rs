@@ -1,32 +1,49 @@
-use egui::Context;
+use egui::{Context, Key};
 use egui_wgpu_backend::RenderPass;
 use wgpu::{Device, Queue};
 
-use crate::debug::Debug;
+use crate::debug::{Debug, ExecState};
+use crate::gameboy::buttons::ButtonType;
 use crate::gameboy::emu::Emu;
+use crate::gameboy::mmu::ResetKind;
 use crate::gameboy::ppu::SCREEN_HEIGHT;
 use crate::ui::serial_window::SerialWindow;
+use crate::ui::utils::timestamped_filename;
 use crate::APPNAME;
 
-use super::super::{breakpoints_window::BreakpointsWindow, render_stats::RenderStats};
+use super::super::{
+    breakpoints_window::BreakpointsWindow,
+    debug_window::DebugWindow,
+    memory_window::MemoryWindow,
+    render_stats::{render_performance, RenderStats},
+    trace_window::TraceWindow,
+};
 
 use super::{
-    audio_window::render_audio_window, cartridge_window::CartridgeWindow,
-    debug_window::DebugWindow, memory_window::MemoryWindow, oam_window::render_oam_window,
-    ppu_window::render_video_window, vram_window::VRAMWindow,
+    audio_window::AudioWindow, call_stack_window::render_call_stack_window,
+    cartridge_window::CartridgeWindow, event_window::render_event_window, io_window::IoWindow,
+    oam_window::render_oam_window, ppu_window::render_video_window, printer_window::PrinterWindow,
+    ram_search_window::RamSearchWindow, script_window::ScriptWindow, vram_window::VRAMWindow,
 };
 
 pub trait MainWindow<T> {
     fn init(&mut self, device: &Device, egui_rpass: &mut RenderPass);
     fn append_serial(&mut self, data: u8);
 
+    // Names of the open windows, for the saved layout
+    fn open_windows(&mut self) -> Vec<String>;
+
+    // Open the named windows. Other windows are left as they are.
+    fn open_windows_by_name(&mut self, names: &[String]);
+
     fn render(
         &mut self,
         ctx: &Context,
         emu: &mut T,
         debug: &mut Debug,
         queue: &Queue,
-        render_stats: &RenderStats,
+        ui_stats: &RenderStats,
+        emu_stats: &RenderStats,
     );
 }
 
@@ -40,18 +57,38 @@ pub struct GameboyMainWindow {
     breakpoints_window: BreakpointsWindow,
     breakpoints_window_open: bool,
 
+    trace_window: TraceWindow,
+    trace_window_open: bool,
+
+    call_stack_window_open: bool,
+
     pub serial_window: SerialWindow,
     serial_window_open: bool,
 
+    pub printer_window: PrinterWindow,
+    printer_window_open: bool,
+
     cartridge_window: CartridgeWindow,
     cartridge_window_open: bool,
 
     memory_window: MemoryWindow,
     memory_window_open: bool,
 
+    ram_search_window: RamSearchWindow,
+    ram_search_window_open: bool,
+
+    io_window: IoWindow,
+    io_window_open: bool,
+
+    audio_window: AudioWindow,
     audio_window_open: bool,
+
+    script_window: ScriptWindow,
+    script_window_open: bool,
+
     ppu_window_open: bool,
     oam_window_open: bool,
+    event_window_open: bool,
 }
 
 impl MainWindow<Emu> for GameboyMainWindow {
@@ -63,37 +100,91 @@ impl MainWindow<Emu> for GameboyMainWindow {
         self.serial_window.append(data)
     }
 
+    fn open_windows(&mut self) -> Vec<String> {
+        self.window_flags()
+            .iter()
+            .filter(|(_, open)| **open)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    fn open_windows_by_name(&mut self, names: &[String]) {
+        for (name, open) in self.window_flags() {
+            if names.iter().any(|n| n == name) {
+                *open = true;
+            }
+        }
+    }
+
     fn render(
         &mut self,
         ctx: &Context,
         emu: &mut Emu,
         debug: &mut Debug,
         queue: &Queue,
-        render_stats: &RenderStats,
+        ui_stats: &RenderStats,
+        emu_stats: &RenderStats,
     ) {
         self.render_toolbar(ctx, emu, debug);
         self.render_menu(ctx);
 
+        if !ctx.wants_keyboard_input() {
+            let input = ctx.input();
+            if input.modifiers.command && input.key_pressed(Key::R) {
+                match input.modifiers.shift {
+                    true => emu.reset(ResetKind::Hard),
+                    false => emu.reset(ResetKind::Soft),
+                }
+            }
+        }
+
+        let paused = debug.state == ExecState::STEP;
         self.vram_window
             .render(ctx, emu, queue, &mut self.vram_window_open);
-        self.debug_window
-            .render(ctx, emu, &mut self.debug_window_open);
+        self.debug_window.render(
+            ctx,
+            emu,
+            &debug.symbols,
+            paused,
+            &mut self.debug_window_open,
+        );
         self.breakpoints_window
             .render(ctx, debug, &mut self.breakpoints_window_open);
+        self.trace_window
+            .render(ctx, debug, &mut self.trace_window_open);
+        render_call_stack_window(ctx, emu, &debug.symbols, &mut self.call_stack_window_open);
         self.serial_window.render(ctx, &mut self.serial_window_open);
+        self.printer_window
+            .render(ctx, &mut self.printer_window_open);
         self.cartridge_window
             .render(ctx, emu, &mut self.cartridge_window_open);
-        self.memory_window
-            .render(ctx, emu, &mut self.memory_window_open);
+        let frozen = self
+            .ram_search_window
+            .render(ctx, emu, &mut self.ram_search_window_open);
+        if let Some((addr, value)) = frozen {
+            debug.frozen.insert(addr, value);
+        }
+        self.memory_window.render(
+            ctx,
+            emu,
+            &mut debug.frozen,
+            paused,
+            &mut self.memory_window_open,
+        );
+        self.io_window
+            .render(ctx, emu, paused, &mut self.io_window_open);
 
-        render_audio_window(ctx, emu, &mut self.audio_window_open);
+        self.audio_window
+            .render(ctx, emu, &mut self.audio_window_open);
+        self.script_window
+            .render(ctx, emu, &mut self.script_window_open);
         render_video_window(ctx, emu, &mut self.ppu_window_open);
-        render_oam_window(ctx, emu, &mut self.oam_window_open);
+        render_oam_window(ctx, emu, paused, &mut self.oam_window_open);
+        render_event_window(ctx, emu, &mut self.event_window_open);
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading(APPNAME);
-            ui.label(format!("UI FPS: {:.1}", render_stats.fps()));
-            ui.label(format!("Emulator FPS: {:.10}", render_stats.fps()));
+            render_performance(ui, ui_stats, emu_stats, emu.mmu.timing.last_frame());
             egui::warn_if_debug_build(ui);
         });
     }
@@ -108,18 +199,60 @@ impl GameboyMainWindow {
             debug_window_open: false,
             breakpoints_window: BreakpointsWindow::new(),
             breakpoints_window_open: false,
+            trace_window: TraceWindow::new(),
+            trace_window_open: false,
+            call_stack_window_open: false,
             serial_window: SerialWindow::new(),
             serial_window_open: false,
+            printer_window: PrinterWindow::new(),
+            printer_window_open: false,
             cartridge_window: CartridgeWindow::new(),
             cartridge_window_open: false,
             memory_window: MemoryWindow::new(),
             memory_window_open: false,
+            ram_search_window: RamSearchWindow::new(),
+            ram_search_window_open: false,
+            io_window: IoWindow::new(),
+            io_window_open: false,
+            audio_window: AudioWindow::new(),
             audio_window_open: false,
+            script_window: ScriptWindow::new(),
+            script_window_open: false,
             ppu_window_open: false,
             oam_window_open: false,
+            event_window_open: false,
         }
     }
 
+    // Open flags of the windows, by the names used in the saved layout
+    fn window_flags(&mut self) -> [(&'static str, &mut bool); 16] {
+        [
+            ("vram", &mut self.vram_window_open),
+            ("debugger", &mut self.debug_window_open),
+            ("breakpoints", &mut self.breakpoints_window_open),
+            ("trace", &mut self.trace_window_open),
+            ("call-stack", &mut self.call_stack_window_open),
+            ("serial", &mut self.serial_window_open),
+            ("printer", &mut self.printer_window_open),
+            ("cartridge", &mut self.cartridge_window_open),
+            ("memory", &mut self.memory_window_open),
+            ("ram-search", &mut self.ram_search_window_open),
+            ("io", &mut self.io_window_open),
+            ("audio", &mut self.audio_window_open),
+            ("script", &mut self.script_window_open),
+            ("ppu", &mut self.ppu_window_open),
+            ("oam", &mut self.oam_window_open),
+            ("events", &mut self.event_window_open),
+        ]
+    }
+
+    // Show the debugger and breakpoint windows, for example when
+    // starting with execution stopped
+    pub fn open_debugger(&mut self) {
+        self.debug_window_open = true;
+        self.breakpoints_window_open = true;
+    }
+
     fn render_toolbar(&mut self, ctx: &Context, emu: &mut Emu, debug: &mut Debug) {
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -137,9 +270,60 @@ impl GameboyMainWindow {
                     debug.break_on_scanline((emu.mmu.ppu.ly + 1) % SCREEN_HEIGHT);
                     debug.continue_execution();
                 }
-                if ui.button("Reset").clicked() {
-                    emu.reset();
+                if ui.button("Trace to break").clicked() {
+                    debug.trace_to_breakpoint();
+                }
+                if ui
+                    .button("Soft reset")
+                    .on_hover_text("Restart the cartridge, keeping memory (Ctrl+R)")
+                    .clicked()
+                {
+                    emu.reset(ResetKind::Soft);
+                }
+                if ui
+                    .button("Hard reset")
+                    .on_hover_text("Power cycle (Ctrl+Shift+R)")
+                    .clicked()
+                {
+                    emu.reset(ResetKind::Hard);
+                }
+
+                ui.separator();
+                match emu.movie_status() {
+                    Some(status) => {
+                        if ui.button("Stop movie").clicked() {
+                            emu.stop_movie();
+                        }
+                        ui.label(status);
+                    }
+                    None => {
+                        if ui.button("Record movie").clicked() {
+                            let filename = timestamped_filename("movie", "txt");
+                            match emu.record_movie(&filename) {
+                                Ok(_) => println!("Recording movie to {}", filename),
+                                Err(e) => println!("Failed to record movie: {}", e),
+                            }
+                        }
+                    }
+                }
+
+                ui.separator();
+                let buttons = &mut emu.mmu.buttons;
+                ui.label("Turbo:");
+                for (button, name) in [(ButtonType::A, "A"), (ButtonType::B, "B")] {
+                    let mask = button as u8;
+                    let mut enabled = buttons.autofire & mask != 0;
+                    if ui.checkbox(&mut enabled, name).changed() {
+                        buttons.autofire ^= mask;
+                    }
                 }
+                let rate = buttons.autofire_rate();
+                ui.add(
+                    egui::DragValue::new(&mut buttons.autofire_period)
+                        .clamp_range(1..=30)
+                        .suffix(" frames"),
+                )
+                .on_hover_text(format!("{:.1} presses per second", rate));
             });
         });
     }
@@ -158,6 +342,13 @@ impl GameboyMainWindow {
                     self.serial_window_open = !self.serial_window_open;
                 }
 
+                if ui
+                    .selectable_label(self.printer_window_open, "Printer")
+                    .clicked()
+                {
+                    self.printer_window_open = !self.printer_window_open;
+                }
+
                 if ui
                     .selectable_label(self.debug_window_open, "Debugger")
                     .clicked()
@@ -172,6 +363,20 @@ impl GameboyMainWindow {
                     self.breakpoints_window_open = !self.breakpoints_window_open;
                 }
 
+                if ui
+                    .selectable_label(self.trace_window_open, "Trace")
+                    .clicked()
+                {
+                    self.trace_window_open = !self.trace_window_open;
+                }
+
+                if ui
+                    .selectable_label(self.call_stack_window_open, "Call stack")
+                    .clicked()
+                {
+                    self.call_stack_window_open = !self.call_stack_window_open;
+                }
+
                 if ui
                     .selectable_label(self.cartridge_window_open, "Cartridge")
                     .clicked()
@@ -186,6 +391,20 @@ impl GameboyMainWindow {
                     self.memory_window_open = !self.memory_window_open;
                 }
 
+                if ui
+                    .selectable_label(self.ram_search_window_open, "RAM search")
+                    .clicked()
+                {
+                    self.ram_search_window_open = !self.ram_search_window_open;
+                }
+
+                if ui
+                    .selectable_label(self.io_window_open, "IO registers")
+                    .clicked()
+                {
+                    self.io_window_open = !self.io_window_open;
+                }
+
                 if ui
                     .selectable_label(self.audio_window_open, "APU (Audio)")
                     .clicked()
@@ -206,6 +425,20 @@ impl GameboyMainWindow {
                 {
                     self.oam_window_open = !self.oam_window_open;
                 }
+
+                if ui
+                    .selectable_label(self.event_window_open, "PPU events")
+                    .clicked()
+                {
+                    self.event_window_open = !self.event_window_open;
+                }
+
+                if ui
+                    .selectable_label(self.script_window_open, "Script")
+                    .clicked()
+                {
+                    self.script_window_open = !self.script_window_open;
+                }
             });
         });
     }
@@ -1,10 +1,14 @@
 pub mod audio_window;
+pub mod call_stack_window;
 pub mod cartridge_window;
-pub mod debug_window;
+pub mod event_window;
+pub mod io_window;
 pub mod main_window;
-pub mod memory_window;
 pub mod oam_window;
 pub mod ppu_window;
+pub mod printer_window;
+pub mod ram_search_window;
+pub mod script_window;
 pub mod tile_data_view;
 pub mod tile_map_view;
 pub mod vram_window;
@@ -1,22 +1,106 @@
-use egui::Context;
+use egui::{vec2, Color32, Context, DragValue, Sense, Ui};
 
 use crate::gameboy::{
     emu::Emu,
     mmu::OAM_OFFSET,
-    ppu::{OAM_OBJECT_COUNT, OAM_OBJECT_SIZE},
+    ppu::{MAX_SPRITES_PER_SCANLINE, OAM_OBJECT_COUNT, OAM_OBJECT_SIZE, PPU, SCREEN_HEIGHT},
 };
+use crate::ui::window_layout;
 
-pub fn render_oam_window(ctx: &Context, emu: &mut Emu, open: &mut bool) {
-    egui::Window::new("OAM")
-        .open(open)
-        .vscroll(true)
-        .show(ctx, |ui| {
+// Size of a pixel of the object previews
+const PREVIEW_SCALE: f32 = 2.0;
+
+// Draw object `n` with its palette and flips. Color 0 is transparent.
+fn render_preview(ui: &mut Ui, ppu: &PPU, n: usize) {
+    let height = ppu.object_height();
+    let size = vec2(8.0, height as f32) * PREVIEW_SCALE;
+    let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+    let palette = match ppu.oam[n].dmg_use_second_palette {
+        true => ppu.obj1_palette,
+        false => ppu.obj0_palette,
+    };
+
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    for (i, color) in ppu.object_pixels(n).iter().enumerate() {
+        if *color == 0 {
+            continue;
+        }
+        let pos = rect.min + vec2((i % 8) as f32, (i / 8) as f32) * PREVIEW_SCALE;
+        let shade = 255 - (palette[*color as usize] & 3) * 85;
+        let pixel = egui::Rect::from_min_size(pos, vec2(PREVIEW_SCALE, PREVIEW_SCALE));
+        painter.rect_filled(pixel, 0.0, Color32::from_gray(shade));
+    }
+}
+
+// Lines with more objects than can be drawn, as ranges such as "16-23"
+fn overflow_lines(ppu: &PPU) -> Vec<String> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for line in 0..SCREEN_HEIGHT {
+        if ppu.objects_on_line(line).len() <= MAX_SPRITES_PER_SCANLINE {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => format!("{}", start),
+            false => format!("{}-{}", start, end),
+        })
+        .collect()
+}
+
+fn render_scanline_summary(ui: &mut Ui, ppu: &PPU) {
+    let ly = ppu.ly;
+    let count = ppu.objects_on_line(ly).len();
+    match count > MAX_SPRITES_PER_SCANLINE {
+        true => ui.colored_label(
+            Color32::RED,
+            format!(
+                "Line {}: {} objects, {} not drawn",
+                ly,
+                count,
+                count - MAX_SPRITES_PER_SCANLINE
+            ),
+        ),
+        false => ui.label(format!("Line {}: {} objects", ly, count)),
+    };
+
+    let overflow = overflow_lines(ppu);
+    if !overflow.is_empty() {
+        ui.label(format!(
+            "More than {} objects on lines: {}",
+            MAX_SPRITES_PER_SCANLINE,
+            overflow.join(", ")
+        ));
+    }
+}
+
+// Objects on the current scanline are highlighted: in green if drawn,
+// and in red if dropped by the limit of objects per line. Attributes
+// can only be edited while paused.
+pub fn render_oam_window(ctx: &Context, emu: &mut Emu, paused: bool, open: &mut bool) {
+    window_layout::show(
+        ctx,
+        "OAM",
+        egui::Window::new("OAM").open(open).vscroll(true),
+        |ui| {
+            render_scanline_summary(ui, &emu.mmu.ppu);
+            ui.separator();
+
+            let on_line = emu.mmu.ppu.objects_on_line(emu.mmu.ppu.ly);
             egui::Grid::new("oam_grid")
                 .num_columns(2)
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
                     ui.heading("Address");
+                    ui.heading("Preview");
                     ui.heading("X");
                     ui.heading("Y");
                     ui.heading("Tile");
@@ -27,20 +111,42 @@ pub fn render_oam_window(ctx: &Context, emu: &mut Emu, open: &mut bool) {
                     ui.end_row();
 
                     for n in 0..OAM_OBJECT_COUNT {
+                        let address = format!("#{}  {:04X}", n, OAM_OFFSET + OAM_OBJECT_SIZE * n);
+                        match on_line.iter().position(|obj| *obj == n) {
+                            Some(i) if i < MAX_SPRITES_PER_SCANLINE => {
+                                ui.colored_label(Color32::GREEN, address)
+                            }
+                            Some(_) => ui.colored_label(Color32::RED, address),
+                            None => ui.label(address),
+                        };
+                        render_preview(ui, &emu.mmu.ppu, n);
+
                         let ob = &mut emu.mmu.ppu.oam[n];
-                        ui.label(format!("#{}  {:04X}", n, OAM_OFFSET + OAM_OBJECT_SIZE * n));
-                        ui.label(format!("{}", ob.x));
-                        ui.label(format!("{}", ob.y));
-                        ui.label(format!("{}", ob.tile_index));
-                        ui.checkbox(&mut ob.bg_and_window_over_obj, "");
-                        ui.checkbox(&mut ob.flip_x, "");
-                        ui.checkbox(&mut ob.flip_y, "");
+                        if paused {
+                            ui.add(DragValue::new(&mut ob.x).clamp_range(-8..=247));
+                            ui.add(DragValue::new(&mut ob.y).clamp_range(-16..=239));
+                            ui.add(DragValue::new(&mut ob.tile_index).clamp_range(0..=255));
+                        } else {
+                            ui.label(format!("{}", ob.x));
+                            ui.label(format!("{}", ob.y));
+                            ui.label(format!("{}", ob.tile_index));
+                        }
+                        ui.add_enabled(
+                            paused,
+                            egui::Checkbox::new(&mut ob.bg_and_window_over_obj, ""),
+                        );
+                        ui.add_enabled(paused, egui::Checkbox::new(&mut ob.flip_x, ""));
+                        ui.add_enabled(paused, egui::Checkbox::new(&mut ob.flip_y, ""));
 
                         // FIXME: use cgb_palette_number if CGB
-                        ui.label(format!("{}", if ob.dmg_use_second_palette { 1 } else { 0 }));
+                        ui.add_enabled(
+                            paused,
+                            egui::Checkbox::new(&mut ob.dmg_use_second_palette, "OBP1"),
+                        );
 
                         ui.end_row();
                     }
                 });
-        });
+        },
+    );
 }
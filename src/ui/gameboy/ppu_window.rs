@@ -8,6 +8,7 @@ use crate::gameboy::{
         BG_TILE_MAP_OFFSET_1, WINDOW_TILE_MAP_OFFSET_0, WINDOW_TILE_MAP_OFFSET_1,
     },
 };
+use crate::ui::window_layout;
 
 fn read_only_checkbox(ui: &mut Ui, mut checked: bool) {
     ui.checkbox(&mut checked, "");
@@ -147,11 +148,26 @@ fn render_property_grid(ui: &mut Ui, emu: &mut Emu) {
 }
 
 pub fn render_video_window(ctx: &Context, emu: &mut Emu, open: &mut bool) {
-    egui::Window::new("Video / PPU").open(open).show(ctx, |ui| {
-        egui::Grid::new("ppu_properties_grid")
-            .num_columns(2)
-            .spacing([40.0, 4.0])
-            .striped(true)
-            .show(ui, |grid_ui| render_property_grid(grid_ui, emu));
-    });
+    window_layout::show(
+        ctx,
+        "Video / PPU",
+        egui::Window::new("Video / PPU").open(open),
+        |ui| {
+            // Only affects what's drawn, not the emulation
+            let layers = &mut emu.mmu.ppu.layers;
+            ui.horizontal(|ui| {
+                ui.label("Show:");
+                ui.checkbox(&mut layers.background, "Background");
+                ui.checkbox(&mut layers.window, "Window");
+                ui.checkbox(&mut layers.objects, "Objects");
+            });
+            ui.separator();
+
+            egui::Grid::new("ppu_properties_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |grid_ui| render_property_grid(grid_ui, emu));
+        },
+    );
 }
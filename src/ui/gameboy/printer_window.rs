@@ -0,0 +1,130 @@
+use std::sync::mpsc::Receiver;
+
+use egui::{vec2, Color32, Context, Rect, Sense, Ui};
+
+use crate::frame_sink::save_png;
+use crate::gameboy::printer::PrintedImage;
+use crate::palette::GRAYSCALE;
+use crate::ui::utils::timestamped_filename;
+use crate::ui::window_layout;
+
+// Shows the images printed with the Game Boy Printer. Every printed
+// image is also saved as a PNG file.
+pub struct PrinterWindow {
+    images: Option<Receiver<PrintedImage>>,
+
+    // Printed images, with the files they were saved to
+    printed: Vec<(String, PrintedImage)>,
+    selected: usize,
+}
+
+impl Default for PrinterWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn render_image(ui: &mut Ui, image: &PrintedImage) {
+    let (rect, _) = ui.allocate_exact_size(
+        vec2(image.width as f32, image.height as f32),
+        Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    for (n, shade) in image.pixels.iter().enumerate() {
+        let (x, y) = (n % image.width, n / image.width);
+        let pos = rect.min + vec2(x as f32, y as f32);
+        let (r, g, b) = GRAYSCALE.bg[*shade as usize & 3];
+        painter.rect_filled(
+            Rect::from_min_size(pos, vec2(1.0, 1.0)),
+            0.0,
+            Color32::from_rgb(r, g, b),
+        );
+    }
+}
+
+impl PrinterWindow {
+    pub fn new() -> Self {
+        PrinterWindow {
+            images: None,
+            printed: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    // Receive images printed by the printer connected to the serial port
+    pub fn attach(&mut self, images: Receiver<PrintedImage>) {
+        self.images = Some(images);
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.images.is_some()
+    }
+
+    // Save and keep images printed since the last call. Returns true
+    // if anything was printed.
+    fn receive(&mut self) -> bool {
+        let images = match self.images {
+            Some(ref images) => images.try_iter().collect::<Vec<_>>(),
+            None => return false,
+        };
+
+        let printed = !images.is_empty();
+        for image in images {
+            let prefix = format!("printout-{}", self.printed.len() + 1);
+            let filename = timestamped_filename(&prefix, "png");
+            match save_png(
+                &filename,
+                &image.pixels,
+                image.width,
+                image.height,
+                GRAYSCALE,
+            ) {
+                Ok(_) => println!("Saved printout to {}", filename),
+                Err(e) => println!("Failed to save printout: {}", e),
+            }
+            self.printed.push((filename, image));
+        }
+        printed
+    }
+
+    pub fn render(&mut self, ctx: &Context, open: &mut bool) {
+        // Open the window as soon as something is printed
+        if self.receive() {
+            self.selected = self.printed.len() - 1;
+            *open = true;
+        }
+
+        window_layout::show(
+            ctx,
+            "Printer",
+            egui::Window::new("Printer").open(open).resizable(true),
+            |ui| {
+                if !self.is_attached() {
+                    ui.label("Start with --printer to connect a printer");
+                    return;
+                }
+
+                if self.printed.is_empty() {
+                    ui.label("Nothing printed yet");
+                    return;
+                }
+
+                ui.horizontal(|ui| {
+                    for (n, (filename, _)) in self.printed.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected, n, format!("{}", n + 1))
+                            .on_hover_text(filename);
+                    }
+                });
+
+                ui.separator();
+
+                if let Some((filename, image)) = self.printed.get(self.selected) {
+                    ui.label(filename);
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| render_image(ui, image));
+                }
+            },
+        );
+    }
+}
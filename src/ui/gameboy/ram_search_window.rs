@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use egui::{Context, TextEdit, Ui};
+
+use crate::gameboy::emu::Emu;
+use crate::gameboy::ram_search::{gameshark_code, RamSearch, SearchFilter};
+use crate::ui::window_layout;
+
+// Results beyond this number are counted, but not listed
+const MAX_LISTED_RESULTS: usize = 100;
+
+// Parse a value, in decimal or, with a 0x or $ prefix, in hex
+fn parse_value(s: &str) -> Option<u8> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+pub struct RamSearchWindow {
+    search: RamSearch,
+    value_input: String,
+
+    // Names given to addresses, kept between searches
+    labels: HashMap<usize, String>,
+    status: String,
+}
+
+impl Default for RamSearchWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RamSearchWindow {
+    pub fn new() -> Self {
+        RamSearchWindow {
+            search: RamSearch::new(),
+            value_input: String::new(),
+            labels: HashMap::new(),
+            status: String::new(),
+        }
+    }
+
+    fn render_filters(&mut self, ui: &mut Ui, emu: &Emu) {
+        ui.horizontal(|ui| {
+            if ui.button("New search").clicked() {
+                self.search.start(&emu.mmu);
+            }
+
+            ui.label("Value:");
+            ui.add(TextEdit::singleline(&mut self.value_input).desired_width(40.0));
+            let value = parse_value(&self.value_input);
+            if ui
+                .add_enabled(value.is_some(), egui::Button::new("Equal to"))
+                .clicked()
+            {
+                if let Some(value) = value {
+                    self.search.filter(&emu.mmu, SearchFilter::EqualTo(value));
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let filters = [
+                ("Increased", SearchFilter::Increased),
+                ("Decreased", SearchFilter::Decreased),
+                ("Unchanged", SearchFilter::Unchanged),
+                ("Changed", SearchFilter::Changed),
+            ];
+            for (name, filter) in filters {
+                let button = egui::Button::new(name);
+                if ui.add_enabled(self.search.is_started(), button).clicked() {
+                    self.search.filter(&emu.mmu, filter);
+                }
+            }
+        });
+    }
+
+    // Returns an address and value to freeze, if requested
+    fn render_results(&mut self, ui: &mut Ui, emu: &Emu) -> Option<(usize, u8)> {
+        let mut freeze = None;
+        let RamSearchWindow {
+            search,
+            labels,
+            status,
+            ..
+        } = self;
+        let results = search.results();
+        match (search.is_started(), results.len()) {
+            (false, _) => ui.label("No search"),
+            (true, n) if n > MAX_LISTED_RESULTS => {
+                ui.label(format!("{} results, showing {}", n, MAX_LISTED_RESULTS))
+            }
+            (true, n) => ui.label(format!("{} results", n)),
+        };
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("ram_search_grid")
+                .num_columns(5)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Address");
+                    ui.label("Value");
+                    ui.label("Previous");
+                    ui.label("Label");
+                    ui.end_row();
+
+                    for (addr, previous) in results.iter().take(MAX_LISTED_RESULTS) {
+                        let value = emu.mmu.direct_read(*addr);
+                        ui.label(format!("{:04X}", addr));
+                        ui.label(format!("{} ({:02X})", value, value));
+                        ui.label(format!("{} ({:02X})", previous, previous));
+
+                        let label = labels.entry(*addr).or_default();
+                        ui.add(TextEdit::singleline(label).desired_width(100.0));
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Freeze").clicked() {
+                                freeze = Some((*addr, value));
+                            }
+                            let code = gameshark_code(*addr, value);
+                            if ui
+                                .button("GameShark")
+                                .on_hover_text(format!("Copy {} to the clipboard", code))
+                                .clicked()
+                            {
+                                *status = format!("Copied {}", code);
+                                ui.output().copied_text = code;
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+        });
+
+        freeze
+    }
+
+    // Returns an address and value to freeze, if requested
+    pub fn render(&mut self, ctx: &Context, emu: &mut Emu, open: &mut bool) -> Option<(usize, u8)> {
+        let mut freeze = None;
+        window_layout::show(
+            ctx,
+            "RAM search",
+            egui::Window::new("RAM search").open(open).resizable(true),
+            |ui| {
+                self.render_filters(ui, emu);
+                ui.label(&self.status);
+                ui.separator();
+                freeze = self.render_results(ui, emu);
+            },
+        );
+        freeze
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("12"), Some(12));
+        assert_eq!(parse_value(" 0x1F"), Some(0x1F));
+        assert_eq!(parse_value("$FF"), Some(0xFF));
+        assert_eq!(parse_value("256"), None);
+    }
+}
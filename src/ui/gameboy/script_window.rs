@@ -0,0 +1,72 @@
+use egui::{Context, Key};
+
+use crate::gameboy::emu::Emu;
+use crate::ui::window_layout;
+
+// Console for loading scripts and evaluating script code
+#[derive(Default)]
+pub struct ScriptWindow {
+    filename: String,
+    input: String,
+}
+
+impl ScriptWindow {
+    pub fn new() -> Self {
+        ScriptWindow {
+            filename: String::new(),
+            input: String::new(),
+        }
+    }
+
+    pub fn render(&mut self, ctx: &Context, emu: &mut Emu, open: &mut bool) {
+        window_layout::show(
+            ctx,
+            "Script",
+            egui::Window::new("Script")
+                .open(open)
+                .resizable(true)
+                .default_size(egui::vec2(500.0, 300.0)),
+            |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.text_edit_singleline(&mut self.filename);
+                    if ui.button("Load").clicked() {
+                        match emu.load_script(&self.filename) {
+                            Ok(_) => println!("Loaded script {}", self.filename),
+                            Err(e) => println!("Failed to load script: {}", e),
+                        }
+                    }
+                });
+
+                ui.separator();
+
+                let lines = emu.script_console();
+                ui.scope(|ui| {
+                    ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, true])
+                        .max_height(ui.available_height() - 30.0)
+                        .stick_to_bottom()
+                        .show(ui, |ui| {
+                            for line in lines.iter() {
+                                ui.label(line);
+                            }
+                        });
+                });
+
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.input)
+                        .code_editor()
+                        .desired_width(f32::INFINITY),
+                );
+                if response.lost_focus() && ui.input().key_pressed(Key::Enter) {
+                    if !self.input.trim().is_empty() {
+                        emu.eval_script(&self.input);
+                    }
+                    self.input.clear();
+                    response.request_focus();
+                }
+            },
+        );
+    }
+}
@@ -6,7 +6,7 @@ use crate::gameboy::{emu::Emu, ppu::PPU};
 
 use super::super::{
     pixbuf::PixBuf,
-    utils::{render_grid, render_tile},
+    utils::{render_grid, render_palette, render_tile, timestamped_filename},
 };
 
 const TILES_PER_ROW: usize = 16;
@@ -17,9 +17,19 @@ const TILE_HEIGHT: usize = 8;
 const TILE_STRIDE: usize = 2;
 const TILE_SIZE: usize = TILE_STRIDE * TILE_HEIGHT;
 
+// Palette used when rendering tiles
+#[derive(PartialEq)]
+enum TilePalette {
+    Raw,
+    Bg,
+    Obj0,
+    Obj1,
+}
+
 pub struct TileDataView {
     buf: PixBuf,
     grid: bool,
+    palette: TilePalette,
 }
 
 impl TileDataView {
@@ -27,6 +37,7 @@ impl TileDataView {
         TileDataView {
             buf: PixBuf::new(TILES_PER_ROW * TILE_WIDTH, (TILE_COUNT / TILES_PER_ROW) * 8),
             grid: false,
+            palette: TilePalette::Raw,
         }
     }
 
@@ -35,6 +46,13 @@ impl TileDataView {
     }
 
     fn render_texture(&mut self, ppu: &PPU) {
+        let palette = match self.palette {
+            TilePalette::Raw => None,
+            TilePalette::Bg => Some(ppu.bg_palette),
+            TilePalette::Obj0 => Some(ppu.obj0_palette),
+            TilePalette::Obj1 => Some(ppu.obj1_palette),
+        };
+
         for row in 0..(TILE_COUNT / TILES_PER_ROW) {
             for col in 0..TILES_PER_ROW {
                 render_tile(
@@ -43,17 +61,102 @@ impl TileDataView {
                     &mut self.buf,
                     col * TILE_WIDTH,
                     row * TILE_HEIGHT,
+                    palette,
                 )
             }
         }
         self.buf.dirty = true;
     }
 
+    fn render_tooltip(ui: &mut Ui, idx: usize, ppu: &PPU) {
+        ui.add(Label::new(format!(
+            "Index: {}, Address: 0x{:04x}",
+            idx,
+            idx * TILE_SIZE + 0x8000
+        )));
+
+        // Tiles 0..255 are addressed with an unsigned index from 0x8000,
+        // and tiles 128..383 with a signed index from 0x9000.
+        if idx < 256 {
+            ui.add(Label::new(format!("0x8000 (u8) index: {}", idx)));
+        }
+        if idx >= 128 {
+            ui.add(Label::new(format!(
+                "0x8800 (i8) index: {}",
+                (idx as i32 - 256) as i8
+            )));
+        }
+
+        // Objects that currently use this tile, and their attributes
+        for (n, obj) in ppu.oam.iter().enumerate() {
+            if obj.tile_index == idx {
+                ui.add(Label::new(format!(
+                    "OAM #{}: ({}, {}){}{}{}{}",
+                    n,
+                    obj.x,
+                    obj.y,
+                    if obj.flip_x { " flip-x" } else { "" },
+                    if obj.flip_y { " flip-y" } else { "" },
+                    if obj.dmg_use_second_palette {
+                        " OBP1"
+                    } else {
+                        " OBP0"
+                    },
+                    if obj.bg_and_window_over_obj {
+                        " behind-bg"
+                    } else {
+                        ""
+                    },
+                )));
+            }
+        }
+    }
+
+    fn export_png(&self) {
+        let filename = timestamped_filename("tile-data", "png");
+        match self.buf.save_png(&filename) {
+            Ok(_) => println!("Exported tile data to {}", filename),
+            Err(e) => println!("Failed to export tile data: {}", e),
+        }
+    }
+
     pub fn render(&mut self, ui: &mut Ui, emu: &mut Emu, queue: &Queue) {
         self.render_texture(&emu.mmu.ppu);
         self.buf.prepare(queue);
 
-        ui.horizontal(|ui| ui.checkbox(&mut self.grid, "Show grid"));
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.grid, "Show grid");
+            if ui.button("Export PNG").clicked() {
+                self.export_png();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Palette:");
+            if ui.radio(self.palette == TilePalette::Raw, "Raw").clicked() {
+                self.palette = TilePalette::Raw;
+            }
+            if ui.radio(self.palette == TilePalette::Bg, "BGP").clicked() {
+                self.palette = TilePalette::Bg;
+            }
+            if ui
+                .radio(self.palette == TilePalette::Obj0, "OBP0")
+                .clicked()
+            {
+                self.palette = TilePalette::Obj0;
+            }
+            if ui
+                .radio(self.palette == TilePalette::Obj1, "OBP1")
+                .clicked()
+            {
+                self.palette = TilePalette::Obj1;
+            }
+        });
+
+        let ppu = &emu.mmu.ppu;
+        render_palette(ui, "BGP ", ppu.bg_palette);
+        render_palette(ui, "OBP0", ppu.obj0_palette);
+        render_palette(ui, "OBP1", ppu.obj1_palette);
 
         if let Some(texture_id) = self.buf.texture_id {
             let scale: usize = 2;
@@ -79,11 +182,7 @@ impl TileDataView {
                     let row = (p[1] - resp.rect.top()) as usize / (8 * scale);
                     resp.on_hover_ui_at_pointer(|ui| {
                         let idx = row * TILES_PER_ROW + col;
-                        ui.add(Label::new(format!(
-                            "Index: {}, Address: 0x{:04x}",
-                            idx,
-                            idx * TILE_SIZE + 0x8000
-                        )));
+                        TileDataView::render_tooltip(ui, idx, ppu);
                     });
                 }
                 None => {}
@@ -1,18 +1,18 @@
-use egui::{Label, Ui};
+use egui::{Color32, Label, Pos2, Rect, Stroke, Ui};
 use egui_wgpu_backend::RenderPass;
 use wgpu::{Device, Queue};
 
 use crate::gameboy::{
     emu::Emu,
     ppu::{
-        get_tile_data_offset, TileAddressingMode, PPU, TILE_COLUMNS, TILE_HEIGHT, TILE_ROWS,
-        TILE_WIDTH,
+        get_tile_data_offset, TileAddressingMode, PPU, SCREEN_HEIGHT, SCREEN_WIDTH, TILE_COLUMNS,
+        TILE_HEIGHT, TILE_ROWS, TILE_WIDTH,
     },
 };
 
 use super::super::{
     pixbuf::PixBuf,
-    utils::{render_grid, render_tile},
+    utils::{render_grid, render_tile, timestamped_filename},
 };
 
 pub enum TileMapArea {
@@ -38,6 +38,9 @@ pub struct TileMapView {
     tile_addressing_mode: Option<TileAddressingMode>,
 
     grid: bool,
+
+    // Show the area covered by the screen (SCX/SCY) and window (WX/WY)
+    viewport: bool,
 }
 
 impl TileMapView {
@@ -47,6 +50,7 @@ impl TileMapView {
             tile_map_area: TileMapArea::AutoBG,
             tile_addressing_mode: None,
             grid: false,
+            viewport: true,
         }
     }
 
@@ -54,14 +58,18 @@ impl TileMapView {
         self.buf.init(device, rpass);
     }
 
-    // Find tile data offset at given row and column.
-    // Returns the tile index and tile data offset.
-    fn get_tile_data_offset(&self, col: usize, row: usize, ppu: &PPU) -> (u8, usize) {
-        let map_offs = match self.tile_map_area {
+    fn tile_map_offset(&self, ppu: &PPU) -> usize {
+        match self.tile_map_area {
             TileMapArea::AutoBG => ppu.bg_tile_map_offset,
             TileMapArea::AutoWindow => ppu.window_tile_map_offset,
             TileMapArea::Fixed(o) => o,
-        };
+        }
+    }
+
+    // Find tile data offset at given row and column.
+    // Returns the tile index and tile data offset.
+    fn get_tile_data_offset(&self, col: usize, row: usize, ppu: &PPU) -> (u8, usize) {
+        let map_offs = self.tile_map_offset(ppu);
 
         let mode = match self.tile_addressing_mode {
             Some(m) => m,
@@ -84,6 +92,7 @@ impl TileMapView {
                     &mut self.buf,
                     col * TILE_WIDTH,
                     row * TILE_HEIGHT,
+                    None,
                 )
             }
         }
@@ -91,6 +100,59 @@ impl TileMapView {
         self.buf.dirty = true;
     }
 
+    // Outline the parts of the tile map that are currently visible.
+    // The background viewport wraps around the edges of the map, so
+    // it may have to be drawn as up to four separate rectangles.
+    fn render_viewport(&self, ui: &Ui, r: Rect, ppu: &PPU) {
+        let map_width = (TILE_COLUMNS * TILE_WIDTH) as f32;
+        let map_height = (TILE_ROWS * TILE_HEIGHT) as f32;
+        let scale = r.width() / map_width;
+        let painter = ui.painter_at(r);
+        let map_offs = self.tile_map_offset(ppu);
+
+        let outline = |x: f32, y: f32, w: f32, h: f32, color: Color32| {
+            let min = Pos2::new(r.left() + x * scale, r.top() + y * scale);
+            let rect = Rect::from_min_size(min, egui::Vec2::new(w * scale, h * scale));
+            painter.rect_stroke(rect, 0.0, Stroke::new(2.0, color));
+        };
+
+        if map_offs == ppu.bg_tile_map_offset {
+            let color = Color32::from_rgb(240, 80, 80);
+            for dx in [0.0, -map_width] {
+                for dy in [0.0, -map_height] {
+                    outline(
+                        ppu.scx as f32 + dx,
+                        ppu.scy as f32 + dy,
+                        SCREEN_WIDTH as f32,
+                        SCREEN_HEIGHT as f32,
+                        color,
+                    );
+                }
+            }
+        }
+
+        // The window is always drawn from the top-left corner of its
+        // tile map, and covers the screen from (WX-7, WY).
+        if map_offs == ppu.window_tile_map_offset
+            && ppu.window_enabled
+            && ppu.wx <= 166
+            && ppu.wy < SCREEN_HEIGHT
+        {
+            let width = SCREEN_WIDTH as f32 - (ppu.wx as f32 - 7.0);
+            let height = (SCREEN_HEIGHT - ppu.wy) as f32;
+            outline(0.0, 0.0, width, height, Color32::from_rgb(80, 160, 240));
+        }
+    }
+
+    fn export_png(&self, ppu: &PPU) {
+        let prefix = format!("tile-map-{:04x}", self.tile_map_offset(ppu));
+        let filename = timestamped_filename(&prefix, "png");
+        match self.buf.save_png(&filename) {
+            Ok(_) => println!("Exported tile map to {}", filename),
+            Err(e) => println!("Failed to export tile map: {}", e),
+        }
+    }
+
     pub fn render(&mut self, ui: &mut Ui, emu: &mut Emu, queue: &Queue) {
         let scale: usize = 2;
         self.render_texture(&emu.mmu.ppu);
@@ -147,24 +209,43 @@ impl TileMapView {
 
             ui.horizontal(|ui| {
                 ui.checkbox(&mut self.grid, "Show grid");
+                ui.checkbox(&mut self.viewport, "Show viewport");
+                if ui.button("Export PNG").clicked() {
+                    self.export_png(&emu.mmu.ppu);
+                }
             });
 
+            let ppu = &emu.mmu.ppu;
+            ui.label(format!(
+                "SCX: {} SCY: {} WX: {} WY: {}",
+                ppu.scx, ppu.scy, ppu.wx, ppu.wy
+            ));
+
             let resp = ui.image(texture_id, size);
             if self.grid {
                 render_grid(ui, resp.rect, TILE_COLUMNS, TILE_ROWS, None);
             }
+            if self.viewport {
+                self.render_viewport(ui, resp.rect, ppu);
+            }
 
             match resp.hover_pos() {
                 Some(p) => {
                     let col = (p[0] - resp.rect.left()) as usize / (8 * scale);
                     let row = (p[1] - resp.rect.top()) as usize / (8 * scale);
                     resp.on_hover_ui_at_pointer(|ui| {
-                        let (idx, offs) = self.get_tile_data_offset(col, row, &emu.mmu.ppu);
+                        let (idx, offs) = self.get_tile_data_offset(col, row, ppu);
                         ui.add(Label::new(format!(
                             "Index: {}, Data: 0x{:04x}",
                             idx,
                             offs + 0x8000
                         )));
+                        ui.add(Label::new(format!(
+                            "Column: {}, Row: {}, Map: 0x{:04x}",
+                            col,
+                            row,
+                            self.tile_map_offset(ppu) + row * TILE_COLUMNS + col
+                        )));
                     });
                 }
                 None => {}
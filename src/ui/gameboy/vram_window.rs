@@ -3,6 +3,7 @@ use egui_wgpu_backend::RenderPass;
 use wgpu::{Device, Queue};
 
 use crate::gameboy::emu::Emu;
+use crate::ui::window_layout;
 
 use super::{tile_data_view::TileDataView, tile_map_view::TileMapView};
 
@@ -45,13 +46,18 @@ impl VRAMWindow {
     }
 
     pub fn render(&mut self, ctx: &Context, emu: &mut Emu, queue: &Queue, open: &mut bool) {
-        egui::Window::new("Video RAM").open(open).show(ctx, |ui| {
-            self.render_tabs(ui);
-            match self.selected_tab.as_str() {
-                "tile-data" => self.tile_data_view.render(ui, emu, queue),
-                "tile-map" => self.tile_map_view.render(ui, emu, queue),
-                _ => {}
-            };
-        });
+        window_layout::show(
+            ctx,
+            "Video RAM",
+            egui::Window::new("Video RAM").open(open),
+            |ui| {
+                self.render_tabs(ui);
+                match self.selected_tab.as_str() {
+                    "tile-data" => self.tile_data_view.render(ui, emu, queue),
+                    "tile-map" => self.tile_map_view.render(ui, emu, queue),
+                    _ => {}
+                };
+            },
+        );
     }
 }
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use egui::{vec2, Color32, Rect, Sense, TextEdit, Ui};
+
+use crate::core::Debuggable;
+
+// Ways to show a range of memory other than as bytes
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interpretation {
+    // 2bpp tiles, as stored in VRAM
+    Tiles,
+
+    // Text, decoded with the selected charset
+    Text,
+
+    // 16-bit little-endian words
+    Words,
+}
+
+// Maps bytes to characters. Games rarely use ASCII, so a table can be
+// loaded from a ".tbl" file, the format used by most ROM hacking tools:
+// one "XX=text" line per byte value.
+pub enum Charset {
+    Ascii,
+    Table(HashMap<u8, String>),
+}
+
+impl Charset {
+    pub fn load_table(filename: &str) -> Result<Charset, String> {
+        let content = std::fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        Charset::parse_table(&content)
+    }
+
+    pub fn parse_table(content: &str) -> Result<Charset, String> {
+        let mut table = HashMap::new();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value = line
+                .split_once('=')
+                .and_then(|(hex, text)| Some((u8::from_str_radix(hex.trim(), 16).ok()?, text)));
+            match value {
+                Some((b, text)) => {
+                    table.insert(b, text.to_string());
+                }
+                None => return Err(format!("invalid entry on line {}: {}", n + 1, line)),
+            }
+        }
+        Ok(Charset::Table(table))
+    }
+
+    pub fn decode(&self, b: u8) -> String {
+        match self {
+            Charset::Ascii => match b {
+                32..=126 => (b as char).to_string(),
+                _ => ".".to_string(),
+            },
+            Charset::Table(table) => match table.get(&b) {
+                Some(text) => text.clone(),
+                None => ".".to_string(),
+            },
+        }
+    }
+}
+
+// Color indices (0-3) of the eight pixels of one row of a 2bpp tile
+pub fn tile_row(lo: u8, hi: u8) -> [u8; 8] {
+    let mut row = [0; 8];
+    for (col, px) in row.iter_mut().enumerate() {
+        *px = ((lo >> (7 - col)) & 1) | (((hi >> (7 - col)) & 1) << 1);
+    }
+    row
+}
+
+// Shows the memory from a start address as tiles, text or words
+pub struct InterpretView {
+    pub interpretation: Interpretation,
+    charset: Charset,
+    table_filename: String,
+    length_input: String,
+}
+
+impl Default for InterpretView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterpretView {
+    const TILES_PER_ROW: usize = 16;
+    const WORDS_PER_ROW: usize = 8;
+    const CHARS_PER_ROW: usize = 32;
+    const TILE_PIXEL_SIZE: f32 = 2.0;
+
+    pub fn new() -> Self {
+        InterpretView {
+            interpretation: Interpretation::Tiles,
+            charset: Charset::Ascii,
+            table_filename: String::new(),
+            length_input: "100".to_string(),
+        }
+    }
+
+    fn length(&self) -> usize {
+        usize::from_str_radix(self.length_input.trim_start_matches("0x"), 16)
+            .unwrap_or(0)
+            .min(0x1000)
+    }
+
+    fn render_tiles(&self, ui: &mut Ui, core: &impl Debuggable, start: usize, len: usize) {
+        let tiles = len.div_ceil(16);
+        let columns = tiles.min(InterpretView::TILES_PER_ROW);
+        let rows = tiles.div_ceil(InterpretView::TILES_PER_ROW);
+        let size = 8.0 * InterpretView::TILE_PIXEL_SIZE;
+        let (rect, _) = ui.allocate_exact_size(
+            vec2(columns as f32 * size, rows as f32 * size),
+            Sense::hover(),
+        );
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+        for tile in 0..tiles {
+            let left = rect.left() + (tile % InterpretView::TILES_PER_ROW) as f32 * size;
+            let top = rect.top() + (tile / InterpretView::TILES_PER_ROW) as f32 * size;
+            for y in 0..8 {
+                let adr = start + tile * 16 + y * 2;
+                if adr + 1 >= 0x10000 {
+                    break;
+                }
+                let lo = core.peek(adr);
+                let hi = core.peek(adr + 1);
+                for (x, px) in tile_row(lo, hi).iter().enumerate() {
+                    // Color index 0 is the lightest shade, as with
+                    // the default palette
+                    let pos = egui::pos2(
+                        left + x as f32 * InterpretView::TILE_PIXEL_SIZE,
+                        top + y as f32 * InterpretView::TILE_PIXEL_SIZE,
+                    );
+                    let r =
+                        Rect::from_min_size(pos, vec2(1.0, 1.0) * InterpretView::TILE_PIXEL_SIZE);
+                    painter.rect_filled(r, 0.0, Color32::from_gray(255 - px * 85));
+                }
+            }
+        }
+    }
+
+    fn render_text(&self, ui: &mut Ui, core: &impl Debuggable, start: usize, len: usize) {
+        for offset in (0..len).step_by(InterpretView::CHARS_PER_ROW) {
+            let adr = start + offset;
+            let end = (start + len).min(adr + InterpretView::CHARS_PER_ROW);
+            let text: String = (adr..end)
+                .filter(|a| *a < 0x10000)
+                .map(|a| self.charset.decode(core.peek(a)))
+                .collect();
+            ui.label(format!("{:04X}  {}", adr, text));
+        }
+    }
+
+    fn render_words(&self, ui: &mut Ui, core: &impl Debuggable, start: usize, len: usize) {
+        for offset in (0..len).step_by(InterpretView::WORDS_PER_ROW * 2) {
+            let adr = start + offset;
+            let end = (start + len).min(adr + InterpretView::WORDS_PER_ROW * 2);
+            let words: Vec<String> = (adr..end)
+                .step_by(2)
+                .filter(|a| a + 1 < 0x10000)
+                .map(|a| {
+                    let lo = core.peek(a) as u16;
+                    let hi = core.peek(a + 1) as u16;
+                    format!("{:04X}", (hi << 8) | lo)
+                })
+                .collect();
+            ui.label(format!("{:04X}  {}", adr, words.join(" ")));
+        }
+    }
+
+    fn render_charset(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Charset:");
+            if ui
+                .selectable_label(matches!(self.charset, Charset::Ascii), "ASCII")
+                .clicked()
+            {
+                self.charset = Charset::Ascii;
+            }
+            ui.add(TextEdit::singleline(&mut self.table_filename).desired_width(160.0));
+            if ui.button("Load table").clicked() {
+                match Charset::load_table(&self.table_filename) {
+                    Ok(charset) => self.charset = charset,
+                    Err(e) => println!("Failed to load table {}: {}", self.table_filename, e),
+                }
+            }
+        });
+    }
+
+    pub fn render(&mut self, ui: &mut Ui, core: &impl Debuggable, start: usize) {
+        ui.horizontal(|ui| {
+            ui.label("Show as:");
+            let mode = &mut self.interpretation;
+            ui.selectable_value(mode, Interpretation::Tiles, "Tiles");
+            ui.selectable_value(mode, Interpretation::Text, "Text");
+            ui.selectable_value(mode, Interpretation::Words, "Words");
+            ui.label("Length:");
+            ui.add(TextEdit::singleline(&mut self.length_input).desired_width(40.0));
+        });
+
+        if self.interpretation == Interpretation::Text {
+            self.render_charset(ui);
+        }
+
+        let len = self.length();
+        ui.scope(|ui| {
+            ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+            egui::ScrollArea::vertical()
+                .id_source("interpret_view")
+                .max_height(200.0)
+                .show(ui, |ui| match self.interpretation {
+                    Interpretation::Tiles => self.render_tiles(ui, core, start, len),
+                    Interpretation::Text => self.render_text(ui, core, start, len),
+                    Interpretation::Words => self.render_words(ui, core, start, len),
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_row() {
+        assert_eq!(tile_row(0b1010_0000, 0b1100_0001), [3, 2, 1, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn test_table_charset() {
+        let charset = Charset::parse_table("80=A\n81=B\r\nFF=<end>\n\n").unwrap();
+        assert_eq!(charset.decode(0x81), "B");
+        assert_eq!(charset.decode(0xFF), "<end>");
+        assert_eq!(charset.decode(0x41), ".");
+        assert!(Charset::parse_table("XY=A").is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::BTreeMap;
+
+use egui::{Checkbox, Color32, Context, RichText, ScrollArea, TextEdit, Ui};
+
+use crate::core::Debuggable;
+use crate::debug::format_address;
+use crate::memory_dump::{dump_memory, load_memory, parse_range};
+use crate::symbols::is_banked;
+use crate::ui::window_layout;
+
+use super::interpret_view::InterpretView;
+
+// Highlight colors of the memory regions, in the order the regions
+// are listed by the core
+const REGION_COLORS: [(u8, u8, u8); 10] = [
+    (0x80, 0xA0, 0xE0),
+    (0x60, 0xC0, 0x60),
+    (0xC0, 0x90, 0x60),
+    (0xD0, 0xD0, 0x70),
+    (0x90, 0x90, 0x90),
+    (0xE0, 0x70, 0xE0),
+    (0x70, 0x70, 0x70),
+    (0xE0, 0x60, 0x60),
+    (0x60, 0xD0, 0xD0),
+    (0xE0, 0x60, 0x60),
+];
+
+fn region_color(n: usize) -> Color32 {
+    let (r, g, b) = REGION_COLORS[n % REGION_COLORS.len()];
+    Color32::from_rgb(r, g, b)
+}
+
+// Name and highlight color of the memory region of `addr`
+fn region(core: &impl Debuggable, addr: usize) -> Option<(&'static str, Color32)> {
+    core.memory_regions()
+        .iter()
+        .enumerate()
+        .find(|(_, r)| addr >= r.start && addr <= r.end)
+        .map(|(n, r)| (r.name, region_color(n)))
+}
+
+// Parse a search pattern. In hex mode, the pattern is a list of
+// bytes such as "DE AD BE EF" or "DEADBEEF". In ASCII mode the
+// pattern is used as-is.
+fn parse_pattern(pattern: &str, ascii: bool) -> Option<Vec<u8>> {
+    if ascii {
+        if pattern.is_empty() {
+            return None;
+        }
+        return Some(pattern.as_bytes().to_vec());
+    }
+
+    let digits: String = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(digits.len() / 2);
+    for i in (0..digits.len()).step_by(2) {
+        match u8::from_str_radix(&digits[i..i + 2], 16) {
+            Ok(b) => bytes.push(b),
+            Err(_) => return None,
+        }
+    }
+    Some(bytes)
+}
+
+pub struct MemoryView {
+    mem_size: usize,
+
+    // Currently selected address, if any
+    selected: Option<usize>,
+
+    // Text of the cell being edited
+    edit_value: String,
+
+    // Give keyboard focus to the edited cell on next render
+    focus_edit: bool,
+
+    // Scroll to this address on next render
+    scroll_to: Option<usize>,
+
+    // Color bytes by memory region
+    highlight_regions: bool,
+}
+
+impl MemoryView {
+    const BYTES_PER_ROW: usize = 16;
+    const ROW_HEIGHT: f32 = 20.0; // FIXME: ui.fonts()[text_style].row_height();
+
+    pub fn new(mem_size: usize) -> Self {
+        MemoryView {
+            mem_size,
+            selected: None,
+            edit_value: String::new(),
+            focus_edit: false,
+            scroll_to: None,
+            highlight_regions: true,
+        }
+    }
+
+    pub fn select(&mut self, addr: usize, core: &impl Debuggable) {
+        self.selected = Some(addr);
+        self.edit_value = format!("{:02X}", core.peek(addr));
+        self.scroll_to = Some(addr);
+    }
+
+    // Search for `pattern`, starting at the byte after the current
+    // selection. Wraps around at the end of the address space.
+    fn find_next(&self, pattern: &[u8], core: &impl Debuggable) -> Option<usize> {
+        let start = match self.selected {
+            Some(adr) => adr + 1,
+            None => 0,
+        };
+
+        for i in 0..self.mem_size {
+            let adr = (start + i) % self.mem_size;
+            if adr + pattern.len() > self.mem_size {
+                continue;
+            }
+            if pattern
+                .iter()
+                .enumerate()
+                .all(|(n, b)| core.peek(adr + n) == *b)
+            {
+                return Some(adr);
+            }
+        }
+
+        None
+    }
+
+    fn render_cell(
+        &mut self,
+        adr: usize,
+        ui: &mut Ui,
+        core: &mut impl Debuggable,
+        frozen: &mut BTreeMap<usize, u8>,
+    ) {
+        let b = core.peek(adr);
+
+        // Bytes that can't be written, such as ROM, are only selected
+        let selected = self.selected == Some(adr);
+        if selected && core.is_writable(adr) {
+            let resp = ui.add(TextEdit::singleline(&mut self.edit_value).desired_width(16.0));
+            if self.focus_edit {
+                resp.request_focus();
+                self.focus_edit = false;
+            }
+            if resp.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                if let Ok(value) = u8::from_str_radix(&self.edit_value, 16) {
+                    core.poke(adr, value);
+                    if frozen.contains_key(&adr) {
+                        frozen.insert(adr, value);
+                    }
+                }
+
+                // Move on to the next byte, like most hex editors do
+                let next = (adr + 1) % self.mem_size;
+                self.selected = Some(next);
+                self.edit_value = format!("{:02X}", core.peek(next));
+                self.focus_edit = true;
+            }
+            return;
+        }
+
+        let mut text = RichText::new(format!("{:02X}", b));
+        if frozen.contains_key(&adr) {
+            text = text.underline();
+        }
+        if self.highlight_regions {
+            if let Some((_, color)) = region(core, adr) {
+                text = text.color(color);
+            }
+        }
+
+        if ui.selectable_label(selected, text).clicked() {
+            self.selected = Some(adr);
+            self.edit_value = format!("{:02X}", b);
+            self.focus_edit = true;
+        }
+    }
+
+    fn render_row(
+        &mut self,
+        offset: usize,
+        ui: &mut Ui,
+        core: &mut impl Debuggable,
+        frozen: &mut BTreeMap<usize, u8>,
+    ) {
+        let mut char_str = String::with_capacity(MemoryView::BYTES_PER_ROW);
+
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 4.0;
+            ui.label(format!("{:04X}", offset));
+
+            for i in 0..MemoryView::BYTES_PER_ROW {
+                let b = core.peek(offset + i);
+                self.render_cell(offset + i, ui, core, frozen);
+                char_str.push(match b {
+                    32..=126 => b as char,
+                    _ => '.',
+                });
+            }
+
+            ui.label(char_str);
+        });
+    }
+
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        core: &mut impl Debuggable,
+        frozen: &mut BTreeMap<usize, u8>,
+    ) {
+        ui.scope(|ui| {
+            let text_style = egui::TextStyle::Monospace;
+            let row_height = MemoryView::ROW_HEIGHT;
+            let num_rows = self.mem_size / MemoryView::BYTES_PER_ROW;
+
+            ui.style_mut().override_text_style = Some(text_style);
+
+            let mut scroll_area = ScrollArea::vertical().auto_shrink([false; 2]);
+            if let Some(adr) = self.scroll_to.take() {
+                let row = adr / MemoryView::BYTES_PER_ROW;
+                scroll_area = scroll_area.vertical_scroll_offset(row as f32 * row_height);
+            }
+
+            scroll_area.show_rows(ui, row_height, num_rows, |ui, row_range| {
+                for row in row_range {
+                    self.render_row(row * MemoryView::BYTES_PER_ROW, ui, core, frozen);
+                }
+            })
+        });
+    }
+}
+
+pub struct MemoryWindow {
+    mem_view: MemoryView,
+    goto_input: String,
+    search_input: String,
+    search_ascii: bool,
+    search_status: String,
+
+    // Range and file of memory dumps. Files are only loaded while
+    // paused, so that the running program doesn't overwrite them
+    // half-way.
+    dump_range: String,
+    dump_file: String,
+    dump_status: String,
+
+    // Show the memory from the selected address as tiles, text or words
+    interpret: bool,
+    interpret_view: InterpretView,
+}
+
+impl MemoryWindow {
+    pub fn new() -> Self {
+        MemoryWindow {
+            mem_view: MemoryView::new(0x10000),
+            goto_input: "".to_string(),
+            search_input: "".to_string(),
+            search_ascii: false,
+            search_status: "".to_string(),
+            dump_range: "C000-DFFF".to_string(),
+            dump_file: "memory.bin".to_string(),
+            dump_status: "".to_string(),
+            interpret: false,
+            interpret_view: InterpretView::new(),
+        }
+    }
+
+    fn render_goto(&mut self, ui: &mut Ui, core: &impl Debuggable) {
+        ui.horizontal(|ui| {
+            ui.label("Go to:");
+            let resp = ui.add(TextEdit::singleline(&mut self.goto_input).desired_width(48.0));
+            let enter = resp.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            let adr = usize::from_str_radix(self.goto_input.trim_start_matches("0x"), 16);
+
+            match adr {
+                Ok(adr) if adr < self.mem_view.mem_size => {
+                    if ui.button("Go").clicked() || enter {
+                        self.mem_view.select(adr, core);
+                    }
+                }
+                _ => {
+                    ui.add_enabled(false, egui::Button::new("Go"));
+                }
+            }
+
+            ui.label(format!(
+                "ROM bank: {:02X} RAM bank: {:02X}",
+                core.bank(0x4000),
+                core.bank(0xA000)
+            ));
+        });
+    }
+
+    fn render_search(&mut self, ui: &mut Ui, core: &impl Debuggable) {
+        ui.horizontal(|ui| {
+            ui.label("Find:");
+            let resp = ui.add(TextEdit::singleline(&mut self.search_input).desired_width(120.0));
+            let enter = resp.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            ui.checkbox(&mut self.search_ascii, "ASCII");
+
+            match parse_pattern(&self.search_input, self.search_ascii) {
+                Some(pattern) => {
+                    if ui.button("Find next").clicked() || enter {
+                        match self.mem_view.find_next(&pattern, core) {
+                            Some(adr) => {
+                                self.mem_view.select(adr, core);
+                                self.search_status = format!("Found at {:04X}", adr);
+                            }
+                            None => self.search_status = "Not found".to_string(),
+                        }
+                    }
+                }
+                None => {
+                    ui.add_enabled(false, egui::Button::new("Find next"));
+                }
+            }
+
+            ui.label(&self.search_status);
+        });
+    }
+
+    fn render_dump(&mut self, ui: &mut Ui, core: &mut impl Debuggable, paused: bool) {
+        ui.horizontal(|ui| {
+            ui.label("Dump:");
+            ui.add(TextEdit::singleline(&mut self.dump_range).desired_width(80.0))
+                .on_hover_text("Address range, such as C000-DFFF, or region, such as VRAM");
+            ui.add(TextEdit::singleline(&mut self.dump_file).desired_width(120.0));
+
+            let range = parse_range(&self.dump_range, core);
+            if ui
+                .add_enabled(range.is_ok(), egui::Button::new("Save"))
+                .clicked()
+            {
+                if let Ok((start, end)) = range {
+                    self.dump_status = match dump_memory(core, start, end, &self.dump_file) {
+                        Ok(count) => format!("Saved {} bytes", count),
+                        Err(e) => e,
+                    };
+                }
+            }
+            if ui
+                .add_enabled(range.is_ok() && paused, egui::Button::new("Load"))
+                .on_disabled_hover_text("Pause to load memory")
+                .clicked()
+            {
+                if let Ok((start, _)) = range {
+                    self.dump_status = match load_memory(core, start, &self.dump_file) {
+                        Ok(count) => format!("Loaded {} bytes", count),
+                        Err(e) => e,
+                    };
+                }
+            }
+
+            ui.label(&self.dump_status);
+        });
+    }
+
+    fn render_selection(
+        &mut self,
+        ui: &mut Ui,
+        core: &impl Debuggable,
+        frozen: &mut BTreeMap<usize, u8>,
+    ) {
+        ui.horizontal(|ui| match self.mem_view.selected {
+            Some(adr) => {
+                let region_name = match region(core, adr) {
+                    Some((name, _)) => name,
+                    None => "-",
+                };
+                let bank = Some(core.bank(adr)).filter(|_| is_banked(adr));
+                let address = format_address(bank, adr);
+                ui.label(format!("Selected: {} ({})", address, region_name));
+                ui.checkbox(&mut self.interpret, "Interpret");
+
+                let mut is_frozen = frozen.contains_key(&adr);
+                let checkbox = Checkbox::new(&mut is_frozen, "Freeze");
+                if ui.add_enabled(core.is_writable(adr), checkbox).changed() {
+                    if is_frozen {
+                        frozen.insert(adr, core.peek(adr));
+                    } else {
+                        frozen.remove(&adr);
+                    }
+                }
+            }
+            None => {
+                ui.label("Selected: -");
+            }
+        });
+    }
+
+    fn render_frozen(&mut self, ui: &mut Ui, frozen: &mut BTreeMap<usize, u8>) {
+        if frozen.is_empty() {
+            return;
+        }
+
+        let mut unfreeze = None;
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Frozen:");
+            for (adr, value) in frozen.iter() {
+                if ui
+                    .button(format!("{:04X}={:02X} ✖", adr, value))
+                    .on_hover_text("Unfreeze")
+                    .clicked()
+                {
+                    unfreeze = Some(*adr);
+                }
+            }
+        });
+
+        if let Some(adr) = unfreeze {
+            frozen.remove(&adr);
+        }
+    }
+
+    fn render_legend(&mut self, ui: &mut Ui, core: &impl Debuggable) {
+        ui.horizontal_wrapped(|ui| {
+            ui.checkbox(&mut self.mem_view.highlight_regions, "Highlight regions");
+            if self.mem_view.highlight_regions {
+                for (n, region) in core.memory_regions().iter().enumerate() {
+                    ui.label(RichText::new(region.name).color(region_color(n)));
+                }
+            }
+        });
+    }
+
+    pub fn render(
+        &mut self,
+        ctx: &Context,
+        core: &mut impl Debuggable,
+        frozen: &mut BTreeMap<usize, u8>,
+        paused: bool,
+        open: &mut bool,
+    ) {
+        self.mem_view.mem_size = core.memory_size();
+
+        window_layout::show(
+            ctx,
+            "Memory",
+            egui::Window::new("Memory").open(open).resizable(true),
+            |ui| {
+                self.render_goto(ui, core);
+                self.render_search(ui, core);
+                self.render_dump(ui, core, paused);
+                self.render_selection(ui, core, frozen);
+                self.render_frozen(ui, frozen);
+                self.render_legend(ui, core);
+                if let (true, Some(adr)) = (self.interpret, self.mem_view.selected) {
+                    ui.separator();
+                    self.interpret_view.render(ui, core, adr);
+                }
+                ui.separator();
+                self.mem_view.render(ui, core, frozen);
+            },
+        );
+    }
+}
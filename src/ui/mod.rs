@@ -1,8 +1,23 @@
 pub mod app;
 pub mod audio_player;
+pub mod audio_settings_window;
+pub mod audio_sink;
 pub mod breakpoints_window;
+pub mod core_thread;
+#[cfg(feature = "audio")]
+pub mod cpal_sink;
+pub mod debug_window;
 pub mod gameboy;
+pub mod interpret_view;
+pub mod memory_window;
 pub mod pixbuf;
 pub mod render_stats;
+pub mod screen;
+#[cfg(feature = "sdl2-audio")]
+pub mod sdl_sink;
 pub mod serial_window;
+pub mod states_window;
+pub mod theme;
+pub mod trace_window;
 pub mod utils;
+pub mod window_layout;
@@ -60,6 +60,23 @@ impl PixBuf {
         self.texture_id = Some(texture_id);
     }
 
+    // Save the content of the buffer as a PNG image
+    pub fn save_png(&self, filename: &str) -> Result<(), std::io::Error> {
+        use png::HasParameters;
+        use std::fs::File;
+        use std::io::BufWriter;
+
+        let file = File::create(filename)?;
+        let mut w = BufWriter::new(file);
+
+        let mut encoder = png::Encoder::new(&mut w, self.width as u32, self.height as u32);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.buf)?;
+
+        Ok(())
+    }
+
     pub fn prepare(&mut self, queue: &wgpu::Queue) {
         if self.dirty {
             if let Some(ref txt) = self.texture {
@@ -1,10 +1,41 @@
 // RenderStats is to a large extent copied from the FrameHistory type in
 // the egui demo app.
 
-use egui::util::History;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use egui::{pos2, util::History, vec2, Color32, Sense, Shape, Stroke, Ui};
+
+use crate::gameboy::timing::SUBSYSTEM_NAMES;
+
+use super::app::TARGET_FPS;
+
+// Number of frames shown in the frame time graph
+const GRAPH_FRAMES: usize = 120;
 
 pub struct RenderStats {
     frame_times: History<f32>,
+
+    // Times of the last GRAPH_FRAMES frames, oldest first
+    recent: VecDeque<f32>,
+}
+
+// Counters of how emulated frames and audio reach the host
+#[derive(Default)]
+pub struct PlaybackStats {
+    // Samples in the audio output buffer and its size, after the
+    // audio of the latest frame was queued. None without audio output.
+    pub audio_fill: Option<(usize, usize)>,
+
+    // Samples that did not fit in the audio output buffer
+    pub dropped_samples: usize,
+
+    // Frames skipped because emulation fell behind
+    pub late_frames: usize,
+
+    // Frames emulated but never shown, because several completed
+    // during a single UI frame
+    pub unshown_frames: usize,
 }
 
 impl RenderStats {
@@ -14,9 +45,13 @@ impl RenderStats {
             *latest = previous_frame_time;
         }
         self.frame_times.add(now, previous_frame_time);
+
+        if self.recent.len() == GRAPH_FRAMES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(previous_frame_time);
     }
 
-    #[allow(dead_code)]
     pub fn mean_frame_time(&self) -> f32 {
         self.frame_times.average().unwrap_or_default()
     }
@@ -32,6 +67,140 @@ impl Default for RenderStats {
         let max_len = (max_age * 300.0).round() as usize;
         Self {
             frame_times: History::new(0..max_len, max_age),
+            recent: VecDeque::with_capacity(GRAPH_FRAMES),
+        }
+    }
+}
+
+// Show frame rates, emulation speed and host time spent per frame.
+// `emu_stats` should have one entry per emulated frame, with the time
+// it took to emulate it. The per-subsystem times are only available
+// with the "timing" feature.
+pub fn render_performance(
+    ui: &mut Ui,
+    ui_stats: &RenderStats,
+    emu_stats: &RenderStats,
+    subsystem_times: Option<[Duration; 4]>,
+) {
+    let ms = |seconds: f64| seconds * 1000.0;
+    let speed = emu_stats.fps() as f64 / TARGET_FPS * 100.0;
+
+    ui.label(format!("UI FPS: {:.1}", ui_stats.fps()));
+    ui.label(format!(
+        "Emulator FPS: {:.1} ({:.0}% speed)",
+        emu_stats.fps(),
+        speed
+    ));
+    ui.label(format!(
+        "UI: {:.2} ms/frame",
+        ms(ui_stats.mean_frame_time() as f64)
+    ));
+    ui.label(format!(
+        "Emulation: {:.2} ms/frame",
+        ms(emu_stats.mean_frame_time() as f64)
+    ));
+
+    match subsystem_times {
+        Some(times) => {
+            for (name, time) in SUBSYSTEM_NAMES.iter().zip(times.iter()) {
+                ui.label(format!("  {}: {:.2} ms", name, ms(time.as_secs_f64())));
+            }
+        }
+        None => {
+            ui.label("Build with the \"timing\" feature for per-subsystem times");
+        }
+    }
+}
+
+// Graph of the recent frame times of the UI and of emulation. The
+// dashed line is the time available per frame at full speed, halfway
+// up the graph.
+fn render_frame_time_graph(ui: &mut Ui, ui_stats: &RenderStats, emu_stats: &RenderStats) {
+    let size = vec2(GRAPH_FRAMES as f32 * 2.0, 60.0);
+    let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+    let budget = 1.0 / TARGET_FPS as f32;
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    painter.add(Shape::dashed_line(
+        &[rect.left_center(), rect.right_center()],
+        Stroke::new(1.0, Color32::GRAY),
+        4.0,
+        4.0,
+    ));
+
+    for (stats, color) in [
+        (ui_stats, Color32::LIGHT_BLUE),
+        (emu_stats, Color32::YELLOW),
+    ] {
+        let points = stats
+            .recent
+            .iter()
+            .enumerate()
+            .map(|(n, time)| {
+                let y = (time / budget * 0.5).min(1.0);
+                pos2(
+                    rect.left() + n as f32 * 2.0,
+                    rect.bottom() - y * rect.height(),
+                )
+            })
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, color)));
+    }
+}
+
+// Overlay with the frame time graph, emulation speed, the fill level
+// of the audio buffer and counters of dropped frames and samples
+pub fn render_stats_overlay(
+    ui: &mut Ui,
+    ui_stats: &RenderStats,
+    emu_stats: &RenderStats,
+    playback: &PlaybackStats,
+) {
+    let speed = emu_stats.fps() as f64 / TARGET_FPS * 100.0;
+    ui.label(format!(
+        "{:.0}% speed, {:.1} FPS, UI {:.1} FPS",
+        speed,
+        emu_stats.fps(),
+        ui_stats.fps()
+    ));
+
+    render_frame_time_graph(ui, ui_stats, emu_stats);
+    ui.horizontal(|ui| {
+        ui.colored_label(Color32::LIGHT_BLUE, "UI");
+        ui.colored_label(Color32::YELLOW, "Emulation");
+    });
+
+    match playback.audio_fill {
+        Some((len, capacity)) => {
+            let fill = len as f32 / capacity.max(1) as f32;
+            ui.add(
+                egui::ProgressBar::new(fill)
+                    .desired_width(GRAPH_FRAMES as f32 * 2.0)
+                    .text(format!("Audio buffer {:.0}%", fill * 100.0)),
+            );
+        }
+        None => {
+            ui.label("No audio output");
+        }
+    }
+
+    ui.label(format!(
+        "Dropped: {} late frames, {} unshown frames, {} samples",
+        playback.late_frames, playback.unshown_frames, playback.dropped_samples
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_frame_times() {
+        let mut stats = RenderStats::default();
+        for n in 0..GRAPH_FRAMES + 10 {
+            stats.on_new_frame(n as f64 / 60.0, Some(n as f32));
         }
+        assert_eq!(stats.recent.len(), GRAPH_FRAMES);
+        assert_eq!(stats.recent.front(), Some(&10.0));
     }
 }
@@ -0,0 +1,186 @@
+// Presentation of the emulated screen: scaling and filters
+//
+// Filters are applied on the CPU when the screen texture is updated.
+// The framebuffer is scaled up by FILTER_SCALE, and each pixel is
+// turned into a block where some rows and columns are darkened to
+// mimic the look of the display.
+
+use egui::{vec2, Color32, FontId, Rect, Ui, Vec2};
+use serde::{Deserialize, Serialize};
+
+// Scale factor of the screen texture when a filter is enabled
+pub const FILTER_SCALE: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleMode {
+    // Largest integer multiple of the screen size that fits
+    Integer,
+
+    // As large as possible while keeping the aspect ratio
+    Fit,
+
+    // Fill all available space
+    Stretch,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 3] = [ScaleMode::Integer, ScaleMode::Fit, ScaleMode::Stretch];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScaleMode::Integer => "Integer",
+            ScaleMode::Fit => "Fit",
+            ScaleMode::Stretch => "Stretch",
+        }
+    }
+
+    // Size of the screen when shown in an area of size `available`
+    pub fn screen_size(&self, width: usize, height: usize, available: Vec2) -> Vec2 {
+        let (w, h) = (width as f32, height as f32);
+        match self {
+            ScaleMode::Integer => {
+                let scale = (available.x / w).min(available.y / h).floor().max(1.0);
+                vec2(w * scale, h * scale)
+            }
+            ScaleMode::Fit => {
+                let scale = (available.x / w).min(available.y / h);
+                vec2(w * scale, h * scale)
+            }
+            ScaleMode::Stretch => available,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenFilter {
+    None,
+
+    // Dark lines between pixels, like the DMG LCD
+    LcdGrid,
+
+    // Dark scanlines, like a CRT
+    Crt,
+}
+
+impl ScreenFilter {
+    pub const ALL: [ScreenFilter; 3] =
+        [ScreenFilter::None, ScreenFilter::LcdGrid, ScreenFilter::Crt];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScreenFilter::None => "None",
+            ScreenFilter::LcdGrid => "LCD grid",
+            ScreenFilter::Crt => "CRT",
+        }
+    }
+
+    // Scale factor of the texture produced by the filter
+    pub fn scale(&self) -> usize {
+        match self {
+            ScreenFilter::None => 1,
+            _ => FILTER_SCALE,
+        }
+    }
+
+    // Brightness (0-256) of the sub-pixel at (x, y) of a pixel block
+    fn brightness(&self, x: usize, y: usize) -> u32 {
+        let last = FILTER_SCALE - 1;
+        match self {
+            ScreenFilter::None => 256,
+            ScreenFilter::LcdGrid => {
+                if x == last || y == last {
+                    192
+                } else {
+                    256
+                }
+            }
+            ScreenFilter::Crt => match y {
+                0 => 224,
+                y if y == last => 112,
+                _ => 256,
+            },
+        }
+    }
+
+    // Apply the filter to an RGBA image. The result is written
+    // to `dst`, which is resized as needed.
+    pub fn apply(&self, src: &[u8], width: usize, height: usize, dst: &mut Vec<u8>) {
+        let scale = self.scale();
+        let dst_width = width * scale;
+        dst.resize(dst_width * height * scale * 4, 0);
+
+        for y in 0..height * scale {
+            for x in 0..dst_width {
+                let s = ((y / scale) * width + x / scale) * 4;
+                let d = (y * dst_width + x) * 4;
+                let brightness = self.brightness(x % scale, y % scale);
+                for c in 0..3 {
+                    dst[d + c] = ((src[s + c] as u32 * brightness) >> 8) as u8;
+                }
+                dst[d + 3] = src[s + 3];
+            }
+        }
+    }
+}
+
+// Darken the pixels of an RGBA image from pixel `start` on, to show
+// the part of the frame that has not been drawn yet
+pub fn dim_from(rgba: &mut [u8], start: usize) {
+    for px in rgba.chunks_exact_mut(4).skip(start) {
+        for c in px[0..3].iter_mut() {
+            *c /= 3;
+        }
+    }
+}
+
+// Draw the buttons of the controller along the bottom of the screen,
+// highlighted when pressed. `scale` is the size of a screen pixel.
+pub fn render_input_display(ui: &mut Ui, screen_rect: Rect, scale: f32, input: &[(&str, bool)]) {
+    let painter = ui.painter();
+    let padding = vec2(2.0, 1.0) * scale;
+    let mut pos = screen_rect.left_bottom() + vec2(2.0, -2.0) * scale;
+    for (name, pressed) in input {
+        let (fill, color) = match pressed {
+            true => (Color32::WHITE, Color32::BLACK),
+            false => (Color32::from_black_alpha(128), Color32::GRAY),
+        };
+        let galley =
+            painter.layout_no_wrap(name.to_string(), FontId::monospace(7.0 * scale), color);
+        let size = galley.size() + padding * 2.0;
+        let rect = Rect::from_min_size(pos - vec2(0.0, size.y), size);
+        painter.rect_filled(rect, scale, fill);
+        painter.galley(rect.min + padding, galley);
+        pos.x += size.x + scale;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_scaling() {
+        let size = ScaleMode::Integer.screen_size(160, 144, vec2(500.0, 1000.0));
+        assert_eq!(size, vec2(480.0, 432.0));
+
+        // Never smaller than 1x
+        let size = ScaleMode::Integer.screen_size(160, 144, vec2(100.0, 100.0));
+        assert_eq!(size, vec2(160.0, 144.0));
+    }
+
+    #[test]
+    fn test_dim_from() {
+        let mut rgba = [0x90, 0x90, 0x90, 0xFF].repeat(3);
+        dim_from(&mut rgba, 1);
+        assert_eq!(rgba[0..4], [0x90, 0x90, 0x90, 0xFF]);
+        assert_eq!(rgba[8..12], [0x30, 0x30, 0x30, 0xFF]);
+    }
+
+    #[test]
+    fn test_fit_keeps_aspect_ratio() {
+        let size = ScaleMode::Fit.screen_size(160, 144, vec2(1000.0, 288.0));
+        assert_eq!(size, vec2(320.0, 288.0));
+    }
+}
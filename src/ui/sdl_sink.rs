@@ -0,0 +1,144 @@
+// Audio output with SDL2, for systems where cpal fails to start
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ringbuf::Consumer;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+use crate::config::AudioConfig;
+use crate::ui::app::AUDIO_SAMPLE_RATE;
+use crate::ui::audio_sink::AudioSink;
+
+thread_local! {
+    // SDL can only be initialized once at a time, so the audio
+    // subsystem is kept for listing devices while playing
+    static AUDIO: RefCell<Option<AudioSubsystem>> = const { RefCell::new(None) };
+}
+
+fn audio_subsystem() -> Result<AudioSubsystem, String> {
+    AUDIO.with(|audio| {
+        let mut audio = audio.borrow_mut();
+        if audio.is_none() {
+            *audio = Some(sdl2::init()?.audio()?);
+        }
+        Ok(audio.as_ref().unwrap().clone())
+    })
+}
+
+// Names of the available output devices
+pub fn device_names() -> Vec<String> {
+    let audio = match audio_subsystem() {
+        Ok(audio) => audio,
+        Err(e) => {
+            println!("Failed to list audio devices: {}", e);
+            return Vec::new();
+        }
+    };
+    let count = audio.num_audio_playback_devices().unwrap_or(0);
+    (0..count)
+        .filter_map(|i| audio.audio_playback_device_name(i).ok())
+        .collect()
+}
+
+struct Callback {
+    consumer: Consumer<i16>,
+    sample_rate: u32,
+
+    // Latency in microseconds, as in CpalSink
+    latency: Arc<AtomicU32>,
+}
+
+impl AudioCallback for Callback {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        for sample in out.iter_mut() {
+            *sample = self.consumer.pop().unwrap_or(0);
+        }
+
+        // SDL doesn't report the delay of the device, so only the
+        // buffered samples are counted
+        let queued = out.len() + self.consumer.len();
+        let buffered = Duration::from_secs_f64(queued as f64 / self.sample_rate as f64);
+        self.latency
+            .store(buffered.as_micros() as u32, Ordering::Relaxed);
+    }
+}
+
+pub struct SdlSink {
+    // Plays until dropped
+    _device: AudioDevice<Callback>,
+
+    device_name: Option<String>,
+    sample_rate: u32,
+    latency: Arc<AtomicU32>,
+}
+
+impl SdlSink {
+    // Start playing on the configured device. The samples are mono,
+    // and SDL converts them to the format of the device.
+    pub fn open(config: &AudioConfig, consumer: Consumer<i16>) -> Result<Self, String> {
+        let audio = audio_subsystem()?;
+
+        let mut device_name = config.device.clone();
+        if let Some(ref name) = device_name {
+            if !device_names().contains(name) {
+                println!(
+                    "Audio device not found: {}. Using the default device.",
+                    name
+                );
+                device_name = None;
+            }
+        }
+
+        // SDL wants a power of two
+        let desired = AudioSpecDesired {
+            freq: Some(AUDIO_SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: config
+                .buffer_size
+                .map(|frames| frames.clamp(64, 0x8000).next_power_of_two() as u16),
+        };
+
+        let latency = Arc::new(AtomicU32::new(0));
+        let measured = latency.clone();
+        let device = audio.open_playback(device_name.as_deref(), &desired, |spec| {
+            println!("Selected audio config: {:?}", spec);
+            Callback {
+                consumer,
+                sample_rate: spec.freq as u32,
+                latency: measured,
+            }
+        })?;
+        let sample_rate = device.spec().freq as u32;
+        device.resume();
+
+        Ok(SdlSink {
+            _device: device,
+            device_name: Some(device_name.unwrap_or_else(|| "default device".to_string())),
+            sample_rate,
+            latency,
+        })
+    }
+}
+
+impl AudioSink for SdlSink {
+    fn device_name(&self) -> Option<String> {
+        self.device_name.clone()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        match self.latency.load(Ordering::Relaxed) {
+            0 => None,
+            us => Some(Duration::from_micros(us as u64)),
+        }
+    }
+}
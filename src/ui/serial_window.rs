@@ -1,5 +1,7 @@
 use egui::Context;
 
+use crate::ui::window_layout;
+
 pub struct SerialWindow {
     pub output: String,
 }
@@ -16,9 +18,13 @@ impl SerialWindow {
     }
 
     pub fn render(&mut self, ctx: &Context, open: &mut bool) {
-        egui::Window::new("Serial Transfer")
-            .open(open)
-            .resizable(true)
-            .show(ctx, |ui| ui.label(&self.output));
+        window_layout::show(
+            ctx,
+            "Serial Transfer",
+            egui::Window::new("Serial Transfer")
+                .open(open)
+                .resizable(true),
+            |ui| ui.label(&self.output),
+        );
     }
 }
@@ -0,0 +1,110 @@
+use egui::{ColorImage, Context, TextureHandle};
+
+use crate::palette::DmgPalette;
+use crate::savestate::{StateSlots, Thumbnail, STATE_SLOTS};
+use crate::ui::window_layout;
+
+// Requested from the savestates window
+pub enum SlotAction {
+    Save(usize),
+    Load(usize),
+}
+
+// Lists the savestate slots of the current game, with a thumbnail of
+// the screen of each saved state
+pub struct StatesWindow {
+    // Thumbnails of the slots, read when the window is opened and
+    // after a state has been saved. None for empty slots.
+    thumbnails: Option<Vec<Option<TextureHandle>>>,
+}
+
+impl Default for StatesWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn thumbnail_image(thumbnail: &Thumbnail, palette: &DmgPalette) -> ColorImage {
+    let mut rgba = Vec::with_capacity(thumbnail.pixels.len() * 4);
+    for px in thumbnail.pixels.iter() {
+        let (r, g, b) = palette.color(*px);
+        rgba.extend_from_slice(&[r, g, b, 0xFF]);
+    }
+    ColorImage::from_rgba_unmultiplied([thumbnail.width, thumbnail.height], &rgba)
+}
+
+impl StatesWindow {
+    pub fn new() -> Self {
+        StatesWindow { thumbnails: None }
+    }
+
+    // Read the thumbnails again the next time the window is shown
+    pub fn refresh(&mut self) {
+        self.thumbnails = None;
+    }
+
+    pub fn render(
+        &mut self,
+        ctx: &Context,
+        slots: &StateSlots,
+        palette: &DmgPalette,
+        open: &mut bool,
+    ) -> Option<SlotAction> {
+        let thumbnails = self.thumbnails.get_or_insert_with(|| {
+            slots
+                .thumbnails()
+                .iter()
+                .enumerate()
+                .map(|(i, thumb)| {
+                    thumb.as_ref().map(|thumb| {
+                        ctx.load_texture(
+                            format!("state_slot_{}", i),
+                            thumbnail_image(thumb, palette),
+                        )
+                    })
+                })
+                .collect()
+        });
+
+        let mut action = None;
+        window_layout::show(
+            ctx,
+            "Savestates",
+            egui::Window::new("Savestates").open(open).resizable(true),
+            |ui| {
+                ui.label(format!("Saved in {}", slots.dir().display()));
+                ui.label("F1-F10 loads a slot, and Shift+F1-F10 saves to it");
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("states_grid_id")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for slot in 1..=STATE_SLOTS {
+                                ui.label(format!("F{}", slot));
+                                match thumbnails[slot - 1] {
+                                    Some(ref texture) => ui.image(texture, texture.size_vec2()),
+                                    None => ui.label("Empty"),
+                                };
+                                ui.vertical(|ui| {
+                                    if ui.button("Save").clicked() {
+                                        action = Some(SlotAction::Save(slot));
+                                    }
+                                    let load = ui.add_enabled(
+                                        thumbnails[slot - 1].is_some(),
+                                        egui::Button::new("Load"),
+                                    );
+                                    if load.clicked() {
+                                        action = Some(SlotAction::Load(slot));
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                });
+            },
+        );
+
+        action
+    }
+}
@@ -0,0 +1,51 @@
+// Theme of the debug and tooling UI
+
+use egui::{Context, Visuals};
+use serde::{Deserialize, Serialize};
+
+// Default size of body text in egui
+pub const DEFAULT_FONT_SIZE: f32 = 14.0;
+
+pub const MIN_FONT_SIZE: f32 = 8.0;
+pub const MAX_FONT_SIZE: f32 = 32.0;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 2] = [Theme::Dark, Theme::Light];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+
+    fn visuals(&self) -> Visuals {
+        match self {
+            Theme::Dark => Visuals::dark(),
+            Theme::Light => Visuals::light(),
+        }
+    }
+}
+
+// Apply theme and font size to the context. All text styles are
+// scaled by the same factor, so that the relative sizes are kept.
+pub fn apply_theme(ctx: &Context, theme: Theme, font_size: f32) {
+    let scale = font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE) / DEFAULT_FONT_SIZE;
+
+    let mut style = (*ctx.style()).clone();
+    let default_style = egui::Style::default();
+    for (text_style, font_id) in style.text_styles.iter_mut() {
+        if let Some(default_font) = default_style.text_styles.get(text_style) {
+            font_id.size = default_font.size * scale;
+        }
+    }
+    style.visuals = theme.visuals();
+    ctx.set_style(style);
+}
@@ -0,0 +1,204 @@
+use egui::{Context, Ui};
+
+use crate::debug::{Debug, TraceFilter};
+
+use super::utils::timestamped_filename;
+use super::window_layout;
+
+#[derive(PartialEq)]
+enum TraceView {
+    // Instructions captured by "trace to breakpoint"
+    Captured,
+
+    // The most recent instructions, from the ring buffer
+    Recent,
+}
+
+// Shows traces of executed instructions
+pub struct TraceWindow {
+    view: TraceView,
+    lines: Vec<String>,
+
+    // Filter of the ring buffer trace, as entered by the user
+    filter_from: String,
+    filter_to: String,
+    filter_opcode: String,
+}
+
+fn save(lines: impl Iterator<Item = impl AsRef<str>>) {
+    let filename = timestamped_filename("trace", "txt");
+    let mut content = String::new();
+    for line in lines {
+        content.push_str(line.as_ref());
+        content.push('\n');
+    }
+    match std::fs::write(&filename, content) {
+        Ok(_) => println!("Saved trace to {}", filename),
+        Err(e) => println!("Failed to save trace: {}", e),
+    }
+}
+
+fn render_lines(ui: &mut Ui, count: usize, line: impl Fn(usize) -> String) {
+    ui.scope(|ui| {
+        ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .stick_to_bottom()
+            .show_rows(ui, row_height, count, |ui, range| {
+                for n in range {
+                    ui.label(line(n));
+                }
+            });
+    });
+}
+
+// Parse a hexadecimal value. An empty string gives `default`.
+fn parse_hex(s: &str, default: usize) -> Result<usize, String> {
+    let s = s.trim().trim_start_matches("0x").trim_start_matches('$');
+    if s.is_empty() {
+        return Ok(default);
+    }
+    usize::from_str_radix(s, 16).map_err(|_| format!("invalid hex value: {}", s))
+}
+
+impl Default for TraceWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceWindow {
+    pub fn new() -> Self {
+        TraceWindow {
+            view: TraceView::Captured,
+            lines: Vec::new(),
+            filter_from: String::new(),
+            filter_to: String::new(),
+            filter_opcode: String::new(),
+        }
+    }
+
+    fn parse_filter(&self) -> Result<TraceFilter, String> {
+        let from = parse_hex(&self.filter_from, 0)?;
+        let to = parse_hex(&self.filter_to, 0xFFFF)?;
+        let opcode = match self.filter_opcode.trim() {
+            "" => None,
+            s => Some(parse_hex(s, 0)? as u8),
+        };
+        Ok(TraceFilter { from, to, opcode })
+    }
+
+    fn render_captured(&mut self, ui: &mut Ui, debug: &mut Debug) {
+        ui.horizontal(|ui| {
+            if debug.is_tracing() {
+                ui.label("Tracing until next break...");
+            } else if ui.button("Trace to breakpoint").clicked() {
+                debug.trace_to_breakpoint();
+            }
+
+            if ui
+                .add_enabled(!self.lines.is_empty(), egui::Button::new("Save"))
+                .clicked()
+            {
+                save(self.lines.iter());
+            }
+
+            ui.label(format!("{} instructions", self.lines.len()));
+        });
+
+        ui.separator();
+
+        let lines = &self.lines;
+        render_lines(ui, lines.len(), |n| lines[n].clone());
+    }
+
+    fn render_recent(&mut self, ui: &mut Ui, debug: &mut Debug) {
+        ui.horizontal(|ui| {
+            let mut enabled = debug.ring_trace().is_some();
+            if ui.checkbox(&mut enabled, "Record").changed() {
+                debug.enable_ring_trace(enabled);
+            }
+
+            ui.label("Keep");
+            ui.add(
+                egui::DragValue::new(&mut debug.ring_trace_size)
+                    .clamp_range(1..=10_000_000)
+                    .speed(1000),
+            );
+            ui.label("instructions");
+
+            if ui.button("Clear").clicked() {
+                debug.clear_ring_trace();
+            }
+
+            if let Some(ring) = debug.ring_trace() {
+                if ui
+                    .add_enabled(!ring.is_empty(), egui::Button::new("Export"))
+                    .clicked()
+                {
+                    save(ring.iter());
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Address from");
+            ui.add(egui::TextEdit::singleline(&mut self.filter_from).desired_width(40.0));
+            ui.label("to");
+            ui.add(egui::TextEdit::singleline(&mut self.filter_to).desired_width(40.0));
+            ui.label("Opcode");
+            ui.add(egui::TextEdit::singleline(&mut self.filter_opcode).desired_width(24.0));
+
+            if ui.button("Apply filter").clicked() {
+                match self.parse_filter() {
+                    Ok(filter) => debug.ring_trace_filter = filter,
+                    Err(e) => println!("Invalid trace filter: {}", e),
+                }
+            }
+        });
+
+        ui.separator();
+
+        match debug.ring_trace() {
+            Some(ring) => {
+                ui.label(format!("{} instructions", ring.len()));
+                render_lines(ui, ring.len(), |n| ring[n].clone());
+            }
+            None => {
+                ui.label("Enable recording to keep a trace of the most recent instructions");
+            }
+        }
+    }
+
+    pub fn render(&mut self, ctx: &Context, debug: &mut Debug, open: &mut bool) {
+        // Open the window as soon as a trace has been captured
+        if let Some(lines) = debug.take_captured_trace() {
+            self.lines = lines;
+            self.view = TraceView::Captured;
+            *open = true;
+        }
+
+        window_layout::show(
+            ctx,
+            "Trace",
+            egui::Window::new("Trace")
+                .open(open)
+                .resizable(true)
+                .default_size(egui::vec2(700.0, 400.0)),
+            |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.view, TraceView::Captured, "To breakpoint");
+                    ui.selectable_value(&mut self.view, TraceView::Recent, "Recent");
+                });
+
+                ui.separator();
+
+                match self.view {
+                    TraceView::Captured => self.render_captured(ui, debug),
+                    TraceView::Recent => self.render_recent(ui, debug),
+                }
+            },
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use egui::{Color32, Pos2, Rect, Stroke, Ui};
+use egui::{emath, pos2, vec2, Color32, Pos2, Rect, Sense, Shape, Stroke, Ui};
 
 use crate::gameboy::ppu::{PPU, TILE_HEIGHT, TILE_WIDTH};
 
@@ -37,7 +37,17 @@ pub fn render_grid(ui: &Ui, r: Rect, columns: usize, rows: usize, color: Option<
     }
 }
 
-pub fn render_tile(ppu: &PPU, adr: usize, buf: &mut PixBuf, x: usize, y: usize) {
+// Render a tile into `buf`. If `palette` is given, color indices are
+// mapped through it to DMG shades (0 = lightest). Otherwise the raw
+// color index is shown, with 0 as black.
+pub fn render_tile(
+    ppu: &PPU,
+    adr: usize,
+    buf: &mut PixBuf,
+    x: usize,
+    y: usize,
+    palette: Option<[u8; 4]>,
+) {
     let top_left_offs = buf.get_offset(x, y);
     let stride = buf.get_stride();
 
@@ -48,10 +58,70 @@ pub fn render_tile(ppu: &PPU, adr: usize, buf: &mut PixBuf, x: usize, y: usize)
         for col in 0..TILE_WIDTH {
             let v = ((lo >> (7 - col)) & 1) | (((hi >> (7 - col)) & 1) << 1);
             let dst = row_offs + col * PIXEL_SIZE;
-            buf.buf[dst + 0] = v * 40;
-            buf.buf[dst + 1] = v * 40;
-            buf.buf[dst + 2] = v * 40;
+            let c = match palette {
+                Some(p) => 255 - (p[v as usize] & 3) * 85,
+                None => v * 40,
+            };
+            buf.buf[dst + 0] = c;
+            buf.buf[dst + 1] = c;
+            buf.buf[dst + 2] = c;
             buf.buf[dst + 3] = 255;
         }
     }
 }
+
+// Render a row of swatches showing the shades of a DMG palette
+pub fn render_palette(ui: &mut Ui, label: &str, palette: [u8; 4]) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for (i, shade) in palette.iter().enumerate() {
+            let c = 255 - (shade & 3) * 85;
+            let (rect, resp) =
+                ui.allocate_exact_size(egui::Vec2::new(16.0, 16.0), egui::Sense::hover());
+            ui.painter().rect_filled(rect, 0.0, Color32::from_gray(c));
+            ui.painter()
+                .rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::from_rgb(120, 120, 80)));
+            resp.on_hover_text(format!("Color {}: shade {}", i, shade));
+        }
+    });
+}
+
+// Draw audio samples as a waveform, stretched to the available width
+pub fn render_waveform(ui: &mut Ui, samples: &[i16]) {
+    let height = ui.spacing().slider_width / 2.0;
+    let size = vec2(ui.available_size_before_wrap().x, height);
+    let (rect, _) = ui.allocate_at_least(size, Sense::hover());
+
+    ui.painter()
+        .rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+    if samples.len() < 2 {
+        return;
+    }
+
+    let wave_rect = Rect::from_x_y_ranges(
+        0.0..=(samples.len() - 1) as f32,
+        i16::MAX as f32..=i16::MIN as f32,
+    );
+    let to_screen = emath::RectTransform::from_to(wave_rect, rect);
+    let points = samples
+        .iter()
+        .enumerate()
+        .map(|(n, s)| to_screen.transform_pos_clamped(pos2(n as f32, *s as f32)))
+        .collect();
+
+    ui.painter().add(Shape::line(
+        points,
+        Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+}
+
+// Filename with a timestamp, for exported images
+pub fn timestamped_filename(prefix: &str, ext: &str) -> String {
+    format!(
+        "{}-{}.{}",
+        prefix,
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        ext
+    )
+}
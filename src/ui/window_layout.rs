@@ -0,0 +1,95 @@
+// Positions and sizes of the egui windows
+//
+// egui remembers where windows are while the application runs, but
+// not between sessions. Windows shown with `show` get their position
+// and size from the saved layout the first time they're shown, and
+// their current position and size are recorded in the layout, which
+// is saved in the config file on exit.
+//
+// The layout is kept in the egui memory, so that every window can
+// reach it through the context.
+
+use std::collections::BTreeMap;
+
+use egui::{Context, Id, InnerResponse, Pos2, Ui, Vec2, Window};
+use serde::{Deserialize, Serialize};
+
+// Position of the window, and size of its content. The size isn't
+// known for windows that scroll their content.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct WindowRect {
+    pub pos: [f32; 2],
+    pub size: Option<[f32; 2]>,
+}
+
+// Windows by title
+pub type WindowLayouts = BTreeMap<String, WindowRect>;
+
+fn layouts_id() -> Id {
+    Id::new("window_layouts")
+}
+
+// Use a saved layout. Only affects windows that haven't been shown.
+pub fn restore(ctx: &Context, layouts: &WindowLayouts) {
+    ctx.memory().data.insert_temp(layouts_id(), layouts.clone());
+}
+
+// The current layout of all windows that have been shown
+pub fn current(ctx: &Context) -> WindowLayouts {
+    ctx.memory()
+        .data
+        .get_temp::<WindowLayouts>(layouts_id())
+        .unwrap_or_default()
+}
+
+// Show a window titled `title`, placed as in the saved layout
+pub fn show<R>(
+    ctx: &Context,
+    title: &str,
+    window: Window,
+    add_contents: impl FnOnce(&mut Ui) -> R,
+) -> Option<InnerResponse<Option<R>>> {
+    let saved = ctx
+        .memory()
+        .data
+        .get_temp_mut_or_default::<WindowLayouts>(layouts_id())
+        .get(title)
+        .copied();
+    let window = match saved {
+        Some(WindowRect { pos, size }) => {
+            let window = window.default_pos(Pos2::new(pos[0], pos[1]));
+            match size {
+                Some(size) => window.default_size(Vec2::new(size[0], size[1])),
+                None => window,
+            }
+        }
+        None => window,
+    };
+
+    let mut size = None;
+    let response = window.show(ctx, |ui| {
+        size = Some(ui.max_rect().size());
+        add_contents(ui)
+    });
+
+    // Collapsed windows keep their saved size
+    if let Some(ref response) = response {
+        let pos = response.response.rect.min;
+        let size = match size {
+            Some(size) if size.is_finite() => Some([size.x, size.y]),
+            _ => saved.and_then(|rect| rect.size),
+        };
+        ctx.memory()
+            .data
+            .get_temp_mut_or_default::<WindowLayouts>(layouts_id())
+            .insert(
+                title.to_string(),
+                WindowRect {
+                    pos: [pos.x, pos.y],
+                    size,
+                },
+            );
+    }
+
+    response
+}
@@ -29,3 +29,18 @@ impl<T> VecExt<T> for Vec<T> {
         }
     }
 }
+
+// Parse a string of hexadecimal digit pairs, such as "C3FF01". Works on
+// bytes, so that any input, including non-ASCII, is rejected rather
+// than split inside a character.
+pub fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    let digits = s.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    let digit = |d: u8| (d as char).to_digit(16);
+    digits
+        .chunks(2)
+        .map(|pair| Some((digit(pair[0])? * 16 + digit(pair[1])?) as u8))
+        .collect()
+}
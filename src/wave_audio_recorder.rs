@@ -1,61 +1,99 @@
+#[cfg(feature = "recording")]
 use std::{fs::File, io::BufWriter};
 
-use crate::ui::audio_player::AudioRecorder;
+// Receives audio samples while recording. Samples are in the range
+// -1.0 to 1.0, at the output sample rate of the emulator.
+pub trait AudioRecorder: Send {
+    fn mono(&mut self, sample: f32);
+    fn stereo(&mut self, left: f32, right: f32);
 
-pub struct WaveAudioRecorder {
-    pub mono_writer: Option<hound::WavWriter<BufWriter<File>>>,
-    pub gen1_writer: Option<hound::WavWriter<BufWriter<File>>>,
-    pub gen2_writer: Option<hound::WavWriter<BufWriter<File>>>,
+    // Output of a single sound channel (0-3), before mixing
+    fn channel(&mut self, n: usize, sample: f32);
+
+    fn flush(&mut self);
 }
 
-impl AudioRecorder for WaveAudioRecorder {
-    fn mono(&mut self, sample: f32) {
-        if let Some(ref mut wr) = self.mono_writer {
-            match wr.write_sample(sample) {
-                Ok(_) => {}
-                Err(e) => println!("Failed to write sample: {:?}", e),
-            }
+#[cfg(feature = "recording")]
+type WavWriter = hound::WavWriter<BufWriter<File>>;
+
+#[cfg(feature = "recording")]
+fn write_sample(writer: &mut Option<WavWriter>, sample: f32) {
+    if let Some(ref mut wr) = writer {
+        match wr.write_sample(sample) {
+            Ok(_) => {}
+            Err(e) => println!("Failed to write sample: {:?}", e),
         }
     }
+}
 
-    fn gen1(&mut self, sample: f32) {
-        if let Some(ref mut wr) = self.gen1_writer {
-            match wr.write_sample(sample) {
-                Ok(_) => {}
-                Err(e) => println!("Failed to write sample: {:?}", e),
-            }
+#[cfg(feature = "recording")]
+fn flush_writer(writer: &mut Option<WavWriter>) {
+    if let Some(ref mut wr) = writer {
+        match wr.flush() {
+            Ok(_) => {}
+            Err(e) => println!("Failed to flush samples: {:?}", e),
         }
     }
+}
+
+#[cfg(feature = "recording")]
+pub struct WaveAudioRecorder {
+    pub mono_writer: Option<WavWriter>,
+    pub stereo_writer: Option<WavWriter>,
+    pub channel_writers: [Option<WavWriter>; 4],
+}
 
-    fn gen2(&mut self, sample: f32) {
-        if let Some(ref mut wr) = self.gen2_writer {
-            match wr.write_sample(sample) {
-                Ok(_) => {}
-                Err(e) => println!("Failed to write sample: {:?}", e),
+#[cfg(feature = "recording")]
+impl WaveAudioRecorder {
+    // Create a recorder that writes the stereo output to `<basename>.wav`.
+    // If `stems` is true, each sound channel is also written to its own
+    // file: `<basename>-ch1.wav` to `<basename>-ch4.wav`.
+    pub fn create(basename: &str, sample_rate: u32, stems: bool) -> Result<Self, hound::Error> {
+        let spec = |channels| hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+
+        let stereo_writer = hound::WavWriter::create(format!("{}.wav", basename), spec(2))?;
+
+        let mut channel_writers = [None, None, None, None];
+        if stems {
+            for (n, writer) in channel_writers.iter_mut().enumerate() {
+                let filename = format!("{}-ch{}.wav", basename, n + 1);
+                *writer = Some(hound::WavWriter::create(filename, spec(1))?);
             }
         }
+
+        Ok(WaveAudioRecorder {
+            mono_writer: None,
+            stereo_writer: Some(stereo_writer),
+            channel_writers,
+        })
     }
+}
 
-    fn flush(&mut self) {
-        if let Some(ref mut wr) = self.mono_writer {
-            match wr.flush() {
-                Ok(_) => {}
-                Err(e) => println!("Failed to flush samples: {:?}", e),
-            }
-        }
+#[cfg(feature = "recording")]
+impl AudioRecorder for WaveAudioRecorder {
+    fn mono(&mut self, sample: f32) {
+        write_sample(&mut self.mono_writer, sample);
+    }
 
-        if let Some(ref mut wr) = self.gen1_writer {
-            match wr.flush() {
-                Ok(_) => {}
-                Err(e) => println!("Failed to flush samples: {:?}", e),
-            }
-        }
+    fn stereo(&mut self, left: f32, right: f32) {
+        write_sample(&mut self.stereo_writer, left);
+        write_sample(&mut self.stereo_writer, right);
+    }
 
-        if let Some(ref mut wr) = self.gen2_writer {
-            match wr.flush() {
-                Ok(_) => {}
-                Err(e) => println!("Failed to flush samples: {:?}", e),
-            }
+    fn channel(&mut self, n: usize, sample: f32) {
+        write_sample(&mut self.channel_writers[n], sample);
+    }
+
+    fn flush(&mut self) {
+        flush_writer(&mut self.mono_writer);
+        flush_writer(&mut self.stereo_writer);
+        for writer in self.channel_writers.iter_mut() {
+            flush_writer(writer);
         }
     }
 }